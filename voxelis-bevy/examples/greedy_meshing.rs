@@ -452,7 +452,8 @@ impl World {
 
         let settings = WorldSettings::default();
 
-        let chunk = VoxChunk::with_position(CHUNK_SIZE, settings.general.max_depth, 0, 0, 0);
+        let chunk =
+            VoxChunk::with_position(Vec3::splat(CHUNK_SIZE), settings.general.max_depth, 0, 0, 0);
         let batch = Batch::<i32>::new(settings.general.max_depth);
 
         let mut world = Self {
@@ -479,7 +480,8 @@ impl World {
         let _span = tracy_client::span!("World::regenerate_chunks");
 
         self.chunk.clear(&mut self.interner);
-        self.chunk = VoxChunk::with_position(CHUNK_SIZE, settings.general.max_depth, 0, 0, 0);
+        self.chunk =
+            VoxChunk::with_position(Vec3::splat(CHUNK_SIZE), settings.general.max_depth, 0, 0, 0);
     }
 
     pub fn generate_mesh(&mut self, meshes: &mut ResMut<Assets<Mesh>>, lod: Lod, first_time: bool) {
@@ -59,7 +59,7 @@ impl World {
     pub fn new() -> Self {
         let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024 * 256);
 
-        let mut chunk = VoxChunk::with_position(CHUNK_SIZE, MAX_DEPTH, 0, 0, 0);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(CHUNK_SIZE), MAX_DEPTH, 0, 0, 0);
 
         let mut batch = chunk.create_batch();
 
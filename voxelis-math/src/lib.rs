@@ -211,6 +211,120 @@ pub fn point_in_quad(point: DVec3, quad: (DVec3, DVec3, DVec3, DVec3)) -> bool {
     point_in_or_on_triangle(point, (a, b, c)) || point_in_or_on_triangle(point, (a, c, d))
 }
 
+pub fn ray_triangle_intersection(
+    origin: DVec3,
+    direction: DVec3,
+    triangle: (DVec3, DVec3, DVec3),
+) -> Option<f64> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("ray_triangle_intersection");
+
+    let (a, b, c) = triangle;
+    let edge1 = b - a;
+    let edge2 = c - a;
+
+    // Moller-Trumbore, `t` is the distance along `direction` in its own units
+    let p = direction.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < 1e-12 {
+        // Ray is parallel to the triangle plane
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inv_det;
+    if t < 0.0 {
+        // Triangle is behind the ray origin
+        return None;
+    }
+
+    Some(t)
+}
+
+/// Conservative variant of `triangle_cube_intersection`: the triangle's plane and edge tests
+/// are widened by the cube half-diagonal around its center, so every cube the closed triangle
+/// touches passes, including ones it only clips by a rounding error. Cubes close to, but not
+/// touching the triangle may pass as well.
+pub fn triangle_cube_intersection_conservative(
+    triangle: (DVec3, DVec3, DVec3),
+    cube: (DVec3, DVec3),
+) -> bool {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("triangle_cube_intersection_conservative");
+
+    let (cube_min, cube_max) = cube;
+
+    let center = (cube_min + cube_max) * 0.5;
+    let half_diagonal = (cube_max - cube_min).length() * 0.5;
+
+    // relative slack, so rounding never pushes a touching cube out
+    let epsilon = half_diagonal * 1e-6;
+
+    point_triangle_distance(center, triangle) <= half_diagonal + epsilon
+}
+
+/// Distance from `point` to the closest point of the closed triangle. Degenerate triangles
+/// are measured as their edges.
+pub fn point_triangle_distance(point: DVec3, triangle: (DVec3, DVec3, DVec3)) -> f64 {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("point_triangle_distance");
+
+    let (a, b, c) = triangle;
+    let ab = b - a;
+    let ac = c - a;
+
+    let normal = ab.cross(ac);
+    let area = normal.length();
+
+    if area < 1e-12 * ab.length_squared().max(ac.length_squared()).max(1e-300) {
+        return point_segment_distance(point, (a, b))
+            .min(point_segment_distance(point, (b, c)))
+            .min(point_segment_distance(point, (c, a)));
+    }
+
+    // Inside the prism over the triangle the plane distance is the answer
+    let inside_ab = ab.cross(point - a).dot(normal) >= 0.0;
+    let inside_bc = (c - b).cross(point - b).dot(normal) >= 0.0;
+    let inside_ca = (a - c).cross(point - c).dot(normal) >= 0.0;
+
+    if inside_ab && inside_bc && inside_ca {
+        return (point - a).dot(normal).abs() / area;
+    }
+
+    point_segment_distance(point, (a, b))
+        .min(point_segment_distance(point, (b, c)))
+        .min(point_segment_distance(point, (c, a)))
+}
+
+pub fn point_segment_distance(point: DVec3, segment: (DVec3, DVec3)) -> f64 {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("point_segment_distance");
+
+    let (a, b) = segment;
+    let ab = b - a;
+
+    let length_squared = ab.length_squared();
+    if length_squared == 0.0 {
+        return (point - a).length();
+    }
+
+    let t = ((point - a).dot(ab) / length_squared).clamp(0.0, 1.0);
+
+    (point - (a + ab * t)).length()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -812,4 +926,127 @@ mod tests {
             assert!(!triangle_cube_intersection(triangle, cube));
         }
     }
+
+    mod test_triangle_cube_intersection_conservative {
+        use super::*;
+
+        const CUBE: (DVec3, DVec3) = (DVec3::ZERO, DVec3::ONE);
+
+        #[test]
+        fn test_passes_whenever_exact_test_passes() {
+            let triangles = [
+                (
+                    DVec3::new(-1.0, 0.5, -1.0),
+                    DVec3::new(2.0, 0.5, -1.0),
+                    DVec3::new(-1.0, 0.5, 2.0),
+                ),
+                (
+                    DVec3::new(0.9, 0.9, 0.9),
+                    DVec3::new(3.0, 1.0, 1.0),
+                    DVec3::new(1.0, 3.0, 1.0),
+                ),
+                (
+                    DVec3::new(-2.0, 1.0, 0.0),
+                    DVec3::new(2.0, 1.0, 0.0),
+                    DVec3::new(0.0, 1.0, 2.0),
+                ),
+            ];
+
+            for triangle in triangles {
+                assert!(triangle_cube_intersection(triangle, CUBE));
+                assert!(triangle_cube_intersection_conservative(triangle, CUBE));
+            }
+        }
+
+        #[test]
+        fn test_near_axis_aligned_triangle() {
+            // just below the top face, by far more than the exact test tolerates
+            let triangle = (
+                DVec3::new(-1.0, -1e-4, -1.0),
+                DVec3::new(2.0, 1e-4, -1.0),
+                DVec3::new(-1.0, -1e-4, 2.0),
+            );
+            let cube = (DVec3::new(0.0, 0.1, 0.0), DVec3::new(1.0, 1.1, 1.0));
+
+            assert!(triangle_cube_intersection_conservative(triangle, cube));
+        }
+
+        #[test]
+        fn test_degenerate_triangle() {
+            // collapsed into a segment crossing the cube
+            let triangle = (
+                DVec3::new(-1.0, 0.5, 0.5),
+                DVec3::new(2.0, 0.5, 0.5),
+                DVec3::new(0.5, 0.5, 0.5),
+            );
+
+            assert!(triangle_cube_intersection_conservative(triangle, CUBE));
+        }
+
+        #[test]
+        fn test_far_triangle() {
+            let triangle = (
+                DVec3::new(3.0, 3.0, 3.0),
+                DVec3::new(4.0, 3.0, 3.0),
+                DVec3::new(3.0, 4.0, 3.0),
+            );
+
+            assert!(!triangle_cube_intersection_conservative(triangle, CUBE));
+        }
+
+        #[test]
+        fn test_point_triangle_distance() {
+            let triangle = (DVec3::ZERO, DVec3::X * 2.0, DVec3::Y * 2.0);
+
+            assert!(
+                (point_triangle_distance(DVec3::new(0.5, 0.5, 3.0), triangle) - 3.0).abs() < 1e-12
+            );
+            assert!(
+                (point_triangle_distance(DVec3::new(-1.0, 0.5, 0.0), triangle) - 1.0).abs() < 1e-12
+            );
+            assert!(
+                (point_triangle_distance(DVec3::new(2.0, 2.0, 0.0), triangle) - 2.0f64.sqrt())
+                    .abs()
+                    < 1e-12
+            );
+            assert_eq!(
+                point_triangle_distance(DVec3::new(0.5, 0.5, 0.0), triangle),
+                0.0
+            );
+        }
+    }
+
+    mod test_ray_triangle_intersection {
+        use super::*;
+
+        const TRIANGLE: (DVec3, DVec3, DVec3) = (
+            DVec3::new(2.0, 0.0, 0.0),
+            DVec3::new(2.0, 1.0, 0.0),
+            DVec3::new(2.0, 0.0, 1.0),
+        );
+
+        #[test]
+        fn test_ray_hitting_triangle() {
+            let t = ray_triangle_intersection(DVec3::new(0.0, 0.25, 0.25), DVec3::X, TRIANGLE);
+            assert_eq!(t, Some(2.0));
+        }
+
+        #[test]
+        fn test_ray_missing_triangle() {
+            let origin = DVec3::new(0.0, 0.75, 0.75);
+            assert_eq!(ray_triangle_intersection(origin, DVec3::X, TRIANGLE), None);
+        }
+
+        #[test]
+        fn test_triangle_behind_ray() {
+            let origin = DVec3::new(3.0, 0.25, 0.25);
+            assert_eq!(ray_triangle_intersection(origin, DVec3::X, TRIANGLE), None);
+        }
+
+        #[test]
+        fn test_ray_parallel_to_triangle() {
+            let origin = DVec3::new(2.0, -1.0, 0.25);
+            assert_eq!(ray_triangle_intersection(origin, DVec3::Y, TRIANGLE), None);
+        }
+    }
 }
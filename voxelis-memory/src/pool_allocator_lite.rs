@@ -80,6 +80,60 @@ impl<T> PoolAllocatorLite<T> {
         }
     }
 
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Grows the pool to `new_capacity` blocks. Blocks keep their indices and values, the
+    /// added blocks are zeroed like the initial allocation.
+    pub fn grow(&mut self, new_capacity: usize) {
+        assert!(
+            new_capacity >= self.capacity,
+            "New capacity must not be smaller than the current capacity"
+        );
+        assert!(
+            new_capacity < u32::MAX as usize,
+            "Capacity must be less than u32::MAX"
+        );
+
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("PoolAllocatorLite::grow");
+
+        let block_size = Self::block_size();
+        let actual_size = block_size * new_capacity;
+
+        let layout = Layout::from_size_align(actual_size, Self::align()).expect("Invalid layout");
+
+        let memory = unsafe {
+            let ptr = std::alloc::realloc(self.memory as *mut u8, self.layout, actual_size);
+
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+
+            let ptr = ptr as *mut T;
+            ptr.add(self.capacity)
+                .write_bytes(0, new_capacity - self.capacity);
+
+            ptr
+        };
+
+        debug_assert!(
+            memory.align_offset(Self::align()) == 0,
+            "Memory not properly aligned"
+        );
+
+        self.memory = memory;
+        self.layout = layout;
+        self.capacity = new_capacity;
+
+        #[cfg(feature = "memory_stats")]
+        {
+            self.stats.memory_budget = actual_size;
+        }
+    }
+
     #[inline(always)]
     pub fn get(&self, index: u32) -> &T {
         debug_assert!(
@@ -215,6 +269,24 @@ mod tests {
         let _id3 = allocator.allocate(22, None); // Should panic
     }
 
+    #[test]
+    fn test_pool_allocator_grow() {
+        let mut allocator: PoolAllocatorLite<u64> = PoolAllocatorLite::new(2);
+
+        let id1 = allocator.allocate(42, None);
+        let id2 = allocator.allocate(24, None);
+
+        allocator.grow(4);
+        assert_eq!(allocator.capacity(), 4);
+
+        let id3 = allocator.allocate(7, None);
+        assert_eq!(id3, 2);
+        assert_eq!(*allocator.get(id1), 42);
+        assert_eq!(*allocator.get(id2), 24);
+        assert_eq!(*allocator.get(id3), 7);
+        assert_eq!(*allocator.get(3), 0);
+    }
+
     #[repr(align(16))]
     struct Aligned16;
 
@@ -1,46 +1,35 @@
 use std::{
     sync::{
-        Arc,
+        Arc, Mutex,
         atomic::{AtomicBool, AtomicUsize, Ordering},
     },
-    time::{Duration, Instant},
+    time::Instant,
 };
 
-#[cfg(feature = "memory_stats")]
-use std::{fmt::Write, sync::Mutex};
+#[cfg(feature = "progress_bar")]
+use std::time::Duration;
 
 use crossbeam::channel::{Receiver, Sender, bounded};
-use glam::{DVec3, IVec3};
-#[cfg(feature = "memory_stats")]
-use indicatif::ProgressState;
+use glam::{DVec3, IVec3, Vec3};
+#[cfg(feature = "progress_bar")]
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
-use voxelis_math::triangle_cube_intersection;
+use voxelis_math::{
+    ray_triangle_intersection, triangle_cube_intersection, triangle_cube_intersection_conservative,
+};
 
 use voxelis::{
-    Batch, Lod, MaxDepth,
+    Batch, Lod, MaxDepth, VoxInterner,
     io::Obj,
-    spatial::{VoxOpsBatch, VoxOpsConfig, VoxOpsState, VoxOpsWrite},
+    spatial::{VoxOpsBatch, VoxOpsBulkWrite, VoxOpsConfig, VoxOpsWrite},
+    utils::common::world_voxel_to_chunk,
     world::VoxModel,
 };
 
-#[cfg(feature = "memory_stats")]
-use voxelis::interner::InternerStats;
-
-#[cfg(feature = "memory_stats")]
-const PROGRESS_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({eta_precise:.0} {stats})";
-
-#[cfg(not(feature = "memory_stats"))]
-const PROGRESS_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({eta_precise:.0})";
-
-fn convert_voxel_world_to_chunk_position(
-    voxel_world_position: IVec3,
-    chunk_world_size: f32,
-) -> IVec3 {
-    voxel_world_position.as_vec3().floor().as_ivec3() * (chunk_world_size as i32)
-}
+#[cfg(feature = "progress_bar")]
+const PROGRESS_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({eta_precise:.0} {msg})";
 
 pub struct ByteSize(pub usize);
 
@@ -58,15 +47,165 @@ impl std::fmt::Display for ByteSize {
     }
 }
 
+/// Sampling settings used when testing voxels against triangles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelizeConfig {
+    /// Number of sub-voxel samples along each axis, `1..=4`. With a single sample a voxel is set
+    /// whenever a triangle touches it, which also catches triangles that only graze a corner.
+    pub samples_per_axis: u32,
+    /// Minimum coverage a voxel needs to be set when `samples_per_axis > 1`. Coverage is the
+    /// number of sub-voxel cells touched by the surface, relative to the `samples_per_axis²`
+    /// cells a flat surface crossing the whole voxel touches, clamped to `1.0`.
+    pub coverage_threshold: f32,
+    /// Fills chunks that no triangle touches and that lie inside the mesh, so closed meshes
+    /// come out solid at chunk granularity. Those chunks skip the triangle tests and are set
+    /// with a single uniform fill. Chunks crossed by the surface still only get surface voxels.
+    /// Only meaningful for closed meshes.
+    pub fill_interior: bool,
+    /// Widens the voxel test by the voxel half-diagonal, so every voxel the triangle touches is
+    /// set, even when it only clips the voxel by a rounding error, e.g. near axis-aligned faces.
+    /// The surface can come out up to one voxel thicker. Sub-voxel samples keep the exact test.
+    pub conservative_rasterization: bool,
+}
+
+impl Default for VoxelizeConfig {
+    fn default() -> Self {
+        Self {
+            samples_per_axis: 1,
+            coverage_threshold: 0.0,
+            fill_interior: false,
+            conservative_rasterization: false,
+        }
+    }
+}
+
+// Model and mesh settings shared by every chunk of a voxelization pass.
+#[derive(Clone, Copy)]
+struct ChunkContext {
+    depth: MaxDepth,
+    chunk_world_size: DVec3,
+    voxel_size: DVec3,
+    voxels_per_axis: usize,
+    mesh_min: DVec3,
+    config: VoxelizeConfig,
+}
+
+/// Progress of [`Voxelizer::voxelize_mesh`], see [`Voxelizer::set_progress_callback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxelizeProgress {
+    /// Chunks voxelized so far, including the ones the mesh turned out not to touch.
+    pub processed_chunks: usize,
+    /// Chunks crossed by the surface plus the interior chunks filled afterwards.
+    pub total_chunks: usize,
+    /// Bytes taken by the interner's live nodes.
+    pub used_memory: usize,
+    /// Bytes the interner's node pool is currently sized for.
+    pub memory_budget: usize,
+}
+
+impl VoxelizeProgress {
+    fn new(processed_chunks: usize, total_chunks: usize, interner: &VoxInterner<i32>) -> Self {
+        Self {
+            processed_chunks,
+            total_chunks,
+            used_memory: interner.live_node_count() * VoxInterner::<i32>::node_size(),
+            memory_budget: interner.memory_budget(),
+        }
+    }
+}
+
+/// Returns a progress callback drawing a progress bar with the interner memory use on the
+/// terminal. A new bar is started whenever a voxelization reports its first progress.
+#[cfg(feature = "progress_bar")]
+pub fn terminal_progress_bar() -> impl FnMut(VoxelizeProgress) + Send + 'static {
+    let mut bar: Option<ProgressBar> = None;
+
+    move |progress| {
+        if progress.processed_chunks == 0 || bar.is_none() {
+            let new_bar = ProgressBar::new(progress.total_chunks as u64);
+            new_bar.set_style(
+                ProgressStyle::with_template(PROGRESS_TEMPLATE)
+                    .unwrap()
+                    .progress_chars("#>-"),
+            );
+            new_bar.enable_steady_tick(Duration::from_millis(16));
+            bar = Some(new_bar);
+        }
+
+        let bar = bar.as_ref().unwrap();
+        bar.set_length(progress.total_chunks as u64);
+        bar.set_position(progress.processed_chunks as u64);
+        bar.set_message(format!(
+            "{} / {}",
+            ByteSize(progress.used_memory),
+            ByteSize(progress.memory_budget)
+        ));
+
+        if progress.processed_chunks >= progress.total_chunks {
+            bar.finish();
+        }
+    }
+}
+
+/// How far the interner may grow past the requested memory budget, so an underestimated
+/// budget slows a long voxelization down instead of aborting it.
+pub const MAX_MEMORY_BUDGET_GROWTH: usize = 4;
+
+/// Returns how far an interner started with `memory_budget` may grow during a voxelization,
+/// [`MAX_MEMORY_BUDGET_GROWTH`] times the budget, capped at what `u32` node ids can address.
+pub fn max_memory_budget(memory_budget: usize) -> usize {
+    memory_budget
+        .saturating_mul(MAX_MEMORY_BUDGET_GROWTH)
+        .min((u32::MAX as usize - 1) * VoxInterner::<i32>::node_size())
+        .max(memory_budget)
+}
+
+fn growable_interner(memory_budget: usize) -> VoxInterner<i32> {
+    VoxInterner::with_growable_budget(memory_budget, max_memory_budget(memory_budget))
+}
+
+/// Returns about how many voxels of `voxel_size` the surface of `mesh` touches, the area of its
+/// triangles over the area of a voxel face.
+pub fn estimate_surface_voxels(mesh: &Obj, voxel_size: f64) -> usize {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("estimate_surface_voxels");
+
+    let area = mesh
+        .faces
+        .iter()
+        .map(|face| {
+            let v1 = mesh.vertices[(face.x - 1) as usize];
+            let v2 = mesh.vertices[(face.y - 1) as usize];
+            let v3 = mesh.vertices[(face.z - 1) as usize];
+
+            (v2 - v1).cross(v3 - v1).length() * 0.5
+        })
+        .sum::<f64>();
+
+    (area / (voxel_size * voxel_size)).ceil() as usize
+}
+
+/// Returns a memory budget for voxelizing `mesh` into voxels of `voxel_size`, see
+/// [`estimate_surface_voxels`] and [`VoxInterner::memory_budget_for_voxels`].
+pub fn estimate_memory_budget(mesh: &Obj, voxel_size: f64) -> usize {
+    VoxInterner::<i32>::memory_budget_for_voxels(estimate_surface_voxels(mesh, voxel_size))
+}
+
+type ProgressCallback = Box<dyn FnMut(VoxelizeProgress) + Send>;
+
 pub struct Voxelizer {
     pub mesh: Obj,
     pub model: VoxModel<i32>,
+    pub config: VoxelizeConfig,
+    // Behind a lock only so the voxelizer stays `Sync` for the worker threads, the callback
+    // itself is only ever called through `&mut self`
+    progress_callback: Option<Mutex<ProgressCallback>>,
 }
 
 impl Voxelizer {
     pub fn empty(
         max_depth: MaxDepth,
-        chunk_world_size: f32,
+        chunk_world_size: Vec3,
         mesh: Obj,
         memory_budget: usize,
     ) -> Self {
@@ -75,13 +214,34 @@ impl Voxelizer {
 
         Self {
             mesh,
-            model: VoxModel::empty(max_depth, chunk_world_size, memory_budget),
+            model: VoxModel::with_interner(
+                max_depth,
+                chunk_world_size,
+                IVec3::ZERO,
+                growable_interner(memory_budget),
+            ),
+            config: VoxelizeConfig::default(),
+            progress_callback: None,
+        }
+    }
+
+    /// Creates a voxelizer writing into an existing `model`, e.g. one created with
+    /// [`voxelis::world::VoxModelBuilder`].
+    pub fn with_model(model: VoxModel<i32>, mesh: Obj) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::with_model");
+
+        Self {
+            mesh,
+            model,
+            config: VoxelizeConfig::default(),
+            progress_callback: None,
         }
     }
 
     pub fn new(
         max_depth: MaxDepth,
-        chunk_world_size: f32,
+        chunk_world_size: Vec3,
         mesh: Obj,
         memory_budget: usize,
     ) -> Self {
@@ -96,15 +256,34 @@ impl Voxelizer {
 
         Self {
             mesh,
-            model: VoxModel::with_dimensions(
+            model: VoxModel::with_interner(
                 max_depth,
                 chunk_world_size,
                 world_bounds,
-                memory_budget,
+                growable_interner(memory_budget),
             ),
+            config: VoxelizeConfig::default(),
+            progress_callback: None,
         }
     }
 
+    /// Sets the callback [`Voxelizer::voxelize_mesh`] reports its progress to, once before
+    /// the first chunk and after every chunk applied to the model.
+    ///
+    /// The callback runs on the thread applying the batches, never on the parallel workers.
+    /// Without one, no progress is reported, see [`terminal_progress_bar`] for a ready made one.
+    pub fn set_progress_callback(
+        &mut self,
+        callback: impl FnMut(VoxelizeProgress) + Send + 'static,
+    ) {
+        self.progress_callback = Some(Mutex::new(Box::new(callback)));
+    }
+
+    /// Removes the progress callback.
+    pub fn clear_progress_callback(&mut self) {
+        self.progress_callback = None;
+    }
+
     pub fn clear(&mut self) {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("Voxelizer::clear");
@@ -112,25 +291,51 @@ impl Voxelizer {
         self.model.clear();
     }
 
-    pub fn build_face_to_chunk_map(&mut self) -> FxHashMap<IVec3, Vec<IVec3>> {
+    pub fn build_face_to_chunk_map(&self) -> FxHashMap<IVec3, Vec<IVec3>> {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("Voxelizer::build_face_to_chunk_map");
 
-        let mut chunk_face_map: FxHashMap<IVec3, Vec<IVec3>> = FxHashMap::default();
+        self.build_face_index_to_chunk_map()
+            .into_iter()
+            .map(|(chunk_position, face_indices)| {
+                let faces = face_indices
+                    .into_iter()
+                    .map(|face_idx| self.mesh.faces[face_idx])
+                    .collect();
+
+                (chunk_position, faces)
+            })
+            .collect()
+    }
+
+    // Same as `build_face_to_chunk_map`, with faces given by their index in the mesh, so
+    // faces sharing their vertices stay apart.
+    fn build_face_index_to_chunk_map(&self) -> FxHashMap<IVec3, Vec<usize>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::build_face_index_to_chunk_map");
+
+        let mut chunk_face_map: FxHashMap<IVec3, Vec<usize>> = FxHashMap::default();
 
         let mesh_min = self.mesh.aabb.0;
 
         let voxels_per_axis = self.model.voxels_per_axis(Lod::new(0));
-        let voxel_size: f64 = self.model.chunk_world_size as f64 / voxels_per_axis as f64;
-        let inv_voxel_size: f64 = 1.0 / voxel_size;
+        let voxel_size = self.model.chunk_world_size.as_dvec3() / voxels_per_axis as f64;
+        let inv_voxel_size = 1.0 / voxel_size;
 
-        for face in &self.mesh.faces {
+        // conservative tests reach up to a half-diagonal past the triangle
+        let margin = if self.config.conservative_rasterization {
+            DVec3::splat(voxel_size.length() * 0.5)
+        } else {
+            DVec3::ZERO
+        };
+
+        for (face_idx, face) in self.mesh.faces.iter().enumerate() {
             let v1 = self.mesh.vertices[(face.x - 1) as usize] - mesh_min;
             let v2 = self.mesh.vertices[(face.y - 1) as usize] - mesh_min;
             let v3 = self.mesh.vertices[(face.z - 1) as usize] - mesh_min;
 
-            let min = v1.min(v2).min(v3);
-            let max = v1.max(v2).max(v3);
+            let min = (v1.min(v2).min(v3) - margin).max(DVec3::ZERO);
+            let max = v1.max(v2).max(v3) + margin;
 
             let world_min_voxel = (min * inv_voxel_size).floor().as_ivec3();
             let world_max_voxel = (max * inv_voxel_size).ceil().as_ivec3();
@@ -147,7 +352,7 @@ impl Voxelizer {
                         chunk_face_map
                             .entry(chunk_position)
                             .or_default()
-                            .push(*face);
+                            .push(face_idx);
                     }
                 }
             }
@@ -156,42 +361,143 @@ impl Voxelizer {
         chunk_face_map
     }
 
+    /// Returns the chunks inside the mesh bounds that no face touches and that lie inside the
+    /// mesh, using the face-to-chunk mapping from [`Voxelizer::build_face_to_chunk_map`].
+    pub fn find_interior_chunks(
+        &self,
+        chunk_face_map: &FxHashMap<IVec3, Vec<IVec3>>,
+    ) -> FxHashSet<IVec3> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::find_interior_chunks");
+
+        let chunk_world_size = self.model.chunk_world_size.as_dvec3();
+        let mesh_min = self.mesh.aabb.0;
+        let max_chunk = (self.mesh.size / chunk_world_size).floor().as_ivec3();
+
+        let mut candidates = Vec::new();
+        for y in 0..=max_chunk.y {
+            for z in 0..=max_chunk.z {
+                for x in 0..=max_chunk.x {
+                    let chunk_position = IVec3::new(x, y, z);
+                    if !chunk_face_map.contains_key(&chunk_position) {
+                        candidates.push(chunk_position);
+                    }
+                }
+            }
+        }
+
+        candidates
+            .into_par_iter()
+            .filter(|chunk_position| {
+                // No triangle crosses the chunk, so it is either fully inside or fully outside.
+                // Cast a ray along +X, off-center so it does not run along mesh edges, the faces
+                // it can hit are all mapped to the chunks further along the same row.
+                let origin = (chunk_position.as_dvec3() + DVec3::new(0.5, 0.5123, 0.4871))
+                    * chunk_world_size;
+
+                let mut faces = FxHashSet::default();
+                for x in chunk_position.x + 1..=max_chunk.x {
+                    let row_position = IVec3::new(x, chunk_position.y, chunk_position.z);
+                    if let Some(row_faces) = chunk_face_map.get(&row_position) {
+                        faces.extend(row_faces.iter().copied());
+                    }
+                }
+
+                let crossings = faces
+                    .iter()
+                    .filter(|face| {
+                        let v1 = self.mesh.vertices[(face.x - 1) as usize] - mesh_min;
+                        let v2 = self.mesh.vertices[(face.y - 1) as usize] - mesh_min;
+                        let v3 = self.mesh.vertices[(face.z - 1) as usize] - mesh_min;
+
+                        ray_triangle_intersection(origin, DVec3::X, (v1, v2, v3)).is_some()
+                    })
+                    .count();
+
+                crossings % 2 == 1
+            })
+            .collect()
+    }
+
+    fn chunk_context(&self) -> ChunkContext {
+        let lod = Lod::new(0);
+
+        let voxels_per_axis = self.model.voxels_per_axis(lod) as usize;
+        let chunk_world_size = self.model.chunk_world_size.as_dvec3();
+
+        ChunkContext {
+            depth: self.model.max_depth(lod),
+            chunk_world_size,
+            voxel_size: chunk_world_size / voxels_per_axis as f64,
+            voxels_per_axis,
+            mesh_min: self.mesh.aabb.0,
+            config: self.config,
+        }
+    }
+
+    // `face_values` holds the value of each of `faces`, without it every voxel is set to `1`.
     fn voxelize_chunk(
+        context: ChunkContext,
         chunk_position: IVec3,
-        depth: MaxDepth,
-        chunk_world_size: f64,
-        voxel_size: f64,
-        voxels_per_axis: usize,
-        mesh_min: DVec3,
         faces: &[IVec3],
         vertices: &[DVec3],
+        face_values: Option<&[i32]>,
     ) -> Option<Batch<i32>> {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("Voxelizer::voxelize_chunk");
 
-        let epsilon = voxel_size * 1e-7;
+        let ChunkContext {
+            depth,
+            chunk_world_size,
+            voxel_size,
+            voxels_per_axis,
+            mesh_min,
+            config,
+        } = context;
+
+        let epsilon = voxel_size.min_element() * 1e-7;
         let splat = DVec3::splat(epsilon);
 
+        // voxels a conservative test can pass lie up to a half-diagonal past the triangle
+        let margin = if config.conservative_rasterization {
+            DVec3::splat(voxel_size.length() * 0.5)
+        } else {
+            DVec3::ZERO
+        };
+
         let mut batch = Batch::new(depth);
 
+        let samples = config.samples_per_axis as usize;
+        assert!(
+            (1..=4).contains(&samples),
+            "samples_per_axis must be in 1..=4, got {samples}"
+        );
+        let sample_size = voxel_size / samples as f64;
+
+        // bit per sub-voxel cell, merged across all faces touching the voxel, and the value of
+        // the first face touching it
+        let mut coverage: FxHashMap<IVec3, (u64, i32)> = FxHashMap::default();
+
         let chunk_world_position = chunk_position.as_dvec3() * chunk_world_size;
 
         // Compute the chunk's world bounding box
         let chunk_world_min = chunk_world_position;
-        let chunk_world_max = chunk_world_min + DVec3::splat(chunk_world_size);
+        let chunk_world_max = chunk_world_min + chunk_world_size;
 
-        for face in faces.iter() {
+        for (face_idx, face) in faces.iter().enumerate() {
             let v1 = vertices[(face.x - 1) as usize] - mesh_min;
             let v2 = vertices[(face.y - 1) as usize] - mesh_min;
             let v3 = vertices[(face.z - 1) as usize] - mesh_min;
 
+            let value = face_values.map_or(1, |face_values| face_values[face_idx]);
+
             // Compute the face's bounding box in world coordinates
             let face_min = v1.min(v2).min(v3);
             let face_max = v1.max(v2).max(v3);
 
             // Compute the overlapping region between the face and the chunk
-            let overlap_min = face_min.max(chunk_world_min) - splat;
-            let overlap_max = face_max.min(chunk_world_max) + splat;
+            let overlap_min = (face_min - margin).max(chunk_world_min) - splat;
+            let overlap_max = (face_max + margin).min(chunk_world_max) + splat;
 
             // Check if there is any overlap
             if overlap_min.x >= overlap_max.x
@@ -226,21 +532,60 @@ impl Voxelizer {
 
                         // Expand voxel bounds slightly by epsilon (if needed)
                         let world_min_position = world_voxel_position - splat;
-                        let world_max_position =
-                            world_voxel_position + DVec3::splat(voxel_size) + splat;
+                        let world_max_position = world_voxel_position + voxel_size + splat;
 
                         // Perform the intersection test
-                        if triangle_cube_intersection(
-                            (v1, v2, v3),
-                            (world_min_position, world_max_position),
-                        ) {
-                            batch.just_set(IVec3::new(x, y, z), 1);
+                        let intersects = if config.conservative_rasterization {
+                            triangle_cube_intersection_conservative(
+                                (v1, v2, v3),
+                                (world_min_position, world_max_position),
+                            )
+                        } else {
+                            triangle_cube_intersection(
+                                (v1, v2, v3),
+                                (world_min_position, world_max_position),
+                            )
+                        };
+
+                        if !intersects {
+                            continue;
+                        }
+
+                        if samples == 1 {
+                            batch.just_set(IVec3::new(x, y, z), value);
+                            continue;
                         }
+
+                        let mut mask = 0u64;
+                        for sy in 0..samples {
+                            for sz in 0..samples {
+                                for sx in 0..samples {
+                                    let sample_min = world_voxel_position
+                                        + DVec3::new(sx as f64, sy as f64, sz as f64) * sample_size;
+
+                                    if triangle_cube_intersection(
+                                        (v1, v2, v3),
+                                        (sample_min - splat, sample_min + sample_size + splat),
+                                    ) {
+                                        mask |= 1 << ((sy * samples + sz) * samples + sx);
+                                    }
+                                }
+                            }
+                        }
+
+                        coverage.entry(IVec3::new(x, y, z)).or_insert((0, value)).0 |= mask;
                     }
                 }
             }
         }
 
+        let full_coverage = (samples * samples) as f32;
+        for (position, (mask, value)) in coverage {
+            if (mask.count_ones() as f32 / full_coverage).min(1.0) > config.coverage_threshold {
+                batch.just_set(position, value);
+            }
+        }
+
         if batch.has_patches() {
             Some(batch)
         } else {
@@ -252,21 +597,40 @@ impl Voxelizer {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("Voxelizer::voxelize_mesh");
 
-        let (tx, rx): (Sender<(IVec3, Batch<i32>)>, Receiver<(IVec3, Batch<i32>)>) = bounded(1024);
+        self.voxelize_mesh_until(chunk_face_map, Arc::new(AtomicBool::new(false)));
+    }
 
-        let lod = Lod::new(0);
+    // Voxelizes the mesh until `stop_signal` is set, returns `false` if it was stopped early.
+    // Batches are applied whole, so every chunk is either fully voxelized or left untouched.
+    fn voxelize_mesh_until(
+        &mut self,
+        chunk_face_map: FxHashMap<IVec3, Vec<IVec3>>,
+        stop_signal: Arc<AtomicBool>,
+    ) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::voxelize_mesh_until");
 
-        let depth = self.model.max_depth(lod);
-        let voxels_per_axis = self.model.voxels_per_axis(lod) as usize;
-        let voxel_size = self.model.chunk_world_size as f64 / voxels_per_axis as f64;
-        let chunk_world_size = self.model.chunk_world_size as f64;
-        let mesh_min = self.mesh.aabb.0;
+        let (tx, rx): (Sender<(IVec3, Batch<i32>)>, Receiver<(IVec3, Batch<i32>)>) = bounded(1024);
+
+        let context = self.chunk_context();
+        let depth = context.depth;
         let vertices = self.mesh.vertices.clone();
 
         let chunk_positions = chunk_face_map.keys().cloned().collect::<Vec<_>>();
 
-        let chunks_to_process = chunk_positions.len();
-        println!(" Chunks to process: {chunks_to_process}");
+        let interior_chunks = if context.config.fill_interior {
+            self.find_interior_chunks(&chunk_face_map)
+        } else {
+            FxHashSet::default()
+        };
+
+        let chunks_to_process = chunk_positions.len() + interior_chunks.len();
+        println!(
+            " Chunks to process: {chunks_to_process}, interior: {}",
+            interior_chunks.len()
+        );
+
+        self.model.reserve_chunks(chunks_to_process);
 
         let early_quit_no_faces = Arc::new(AtomicUsize::new(0));
         let early_quit_empty_faces = Arc::new(AtomicUsize::new(0));
@@ -278,7 +642,6 @@ impl Voxelizer {
         let early_quit_empty_batch_clone = early_quit_empty_batch.clone();
         let processed_chunks_clone = processed_chunks.clone();
 
-        let stop_signal: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
         let stop_signal_clone = stop_signal.clone();
 
         let handle = std::thread::spawn(move || {
@@ -299,24 +662,19 @@ impl Voxelizer {
                     return;
                 }
 
-                let Some(batch) = Self::voxelize_chunk(
-                    *chunk_position,
-                    depth,
-                    chunk_world_size,
-                    voxel_size,
-                    voxels_per_axis,
-                    mesh_min,
-                    faces,
-                    &vertices,
-                ) else {
+                let Some(batch) =
+                    Self::voxelize_chunk(context, *chunk_position, faces, &vertices, None)
+                else {
                     early_quit_empty_batch_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
                     return;
                 };
 
                 if batch.has_patches() {
                     processed_chunks_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                    tx.send((*chunk_position, batch))
-                        .expect("Failed to send batch to main thread");
+
+                    // The receiver is only dropped once stopped, so a failed send is ignored and
+                    // the rest of the chunks are skipped by the stop signal check
+                    let _ = tx.send((*chunk_position, batch));
                 }
             });
 
@@ -328,47 +686,73 @@ impl Voxelizer {
 
         println!("Applying batches to chunks");
 
-        let bar = ProgressBar::new(chunks_to_process as u64);
-
-        #[cfg(feature = "memory_stats")]
-        let total_memory = interner.stats().requested_budget;
-        #[cfg(feature = "memory_stats")]
-        let interner_stats = Arc::new(Mutex::new(InternerStats::default()));
-        #[cfg(feature = "memory_stats")]
-        let interner_stats_clone = interner_stats.clone();
-
-        let style = ProgressStyle::with_template(PROGRESS_TEMPLATE).unwrap();
-
-        #[cfg(feature = "memory_stats")]
-        let style = style.with_key("stats", move |_state: &ProgressState, w: &mut dyn Write| {
-            write!(w, "{}", {
-                let stats = interner_stats_clone.lock().unwrap();
-                let used_memory = stats.alive_nodes * stats.node_size;
+        let mut report = |processed_chunks: usize, interner: &VoxInterner<i32>| {
+            if let Some(Ok(callback)) = self.progress_callback.as_mut().map(Mutex::get_mut) {
+                callback(VoxelizeProgress::new(
+                    processed_chunks,
+                    chunks_to_process,
+                    interner,
+                ));
+            }
+        };
 
-                format!("{} / {}", ByteSize(used_memory), ByteSize(total_memory),)
-            })
-            .unwrap()
-        });
+        report(0, &interner);
 
-        bar.set_style(style.progress_chars("#>-"));
+        // chunks the workers skipped count as processed too
+        let skipped_chunks = || {
+            early_quit_no_faces.load(Ordering::Relaxed)
+                + early_quit_empty_faces.load(Ordering::Relaxed)
+                + early_quit_empty_batch.load(Ordering::Relaxed)
+        };
 
-        bar.enable_steady_tick(Duration::from_millis(16));
+        let mut applied_chunks = 0;
+        let mut reported_chunks = 0;
 
         for (chunk_position, batch) in rx.iter() {
+            if stop_signal.load(Ordering::Relaxed) {
+                break;
+            }
+
             self.model
                 .get_or_create_chunk(chunk_position)
                 .apply_batch(&mut interner, &batch);
 
-            #[cfg(feature = "memory_stats")]
-            {
-                let stats = interner.stats();
-                interner_stats.lock().unwrap().clone_from(&stats);
+            applied_chunks += 1;
+            reported_chunks = applied_chunks + skipped_chunks();
+            report(reported_chunks, &interner);
+        }
+
+        // unblocks workers waiting on a full channel after a stop
+        drop(rx);
+
+        let mut stopped = stop_signal.load(Ordering::Relaxed);
+
+        if !stopped {
+            // the channel closes once every worker is done, so only chunks skipped after the
+            // last batch are left to report
+            let surface_chunks = applied_chunks + skipped_chunks();
+            if surface_chunks > reported_chunks {
+                report(surface_chunks, &interner);
             }
 
-            bar.inc(1);
+            let mut fill_batch = Batch::new(depth);
+            fill_batch.just_fill(1);
+
+            for (index, chunk_position) in interior_chunks.into_iter().enumerate() {
+                if stop_signal.load(Ordering::Relaxed) {
+                    stopped = true;
+                    break;
+                }
+
+                self.model
+                    .get_or_create_chunk(chunk_position)
+                    .apply_batch(&mut interner, &fill_batch);
+
+                report(surface_chunks + index + 1, &interner);
+            }
         }
 
-        bar.finish();
+        handle.join().unwrap();
 
         println!(
             "Early quits: no faces: {}, empty faces: {}, empty batch: {}",
@@ -381,7 +765,134 @@ impl Voxelizer {
             processed_chunks.load(std::sync::atomic::Ordering::SeqCst)
         );
 
-        handle.join().unwrap();
+        !stopped
+    }
+
+    /// Voxelizes the mesh in the background and yields per-chunk batches as they are produced,
+    /// without touching `self.model`. Dropping the iterator stops the remaining work.
+    pub fn voxelize_streaming(&self) -> impl Iterator<Item = (IVec3, Batch<i32>)> + use<> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::voxelize_streaming");
+
+        // Small buffer so the producer never runs far ahead of the consumer
+        let (tx, rx) = bounded::<(IVec3, Batch<i32>)>(64);
+
+        let context = self.chunk_context();
+        let depth = context.depth;
+        let vertices = self.mesh.vertices.clone();
+
+        let chunk_face_map = self.build_face_to_chunk_map();
+        let interior_chunks = if context.config.fill_interior {
+            self.find_interior_chunks(&chunk_face_map)
+        } else {
+            FxHashSet::default()
+        };
+
+        let stop_signal = AtomicBool::new(false);
+
+        std::thread::spawn(move || {
+            for chunk_position in interior_chunks {
+                let mut batch = Batch::new(depth);
+                batch.just_fill(1);
+
+                if tx.send((chunk_position, batch)).is_err() {
+                    return;
+                }
+            }
+
+            chunk_face_map
+                .par_iter()
+                .for_each(|(chunk_position, faces)| {
+                    if stop_signal.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    let Some(batch) =
+                        Self::voxelize_chunk(context, *chunk_position, faces, &vertices, None)
+                    else {
+                        return;
+                    };
+
+                    // The receiver is gone, nobody wants the rest of the chunks
+                    if tx.send((*chunk_position, batch)).is_err() {
+                        stop_signal.store(true, Ordering::Relaxed);
+                    }
+                });
+        });
+
+        rx.into_iter()
+    }
+
+    /// Re-voxelizes only the chunks overlapping `world_aabb`, given in mesh coordinates.
+    /// Returns the positions of the chunks that were rebuilt.
+    pub fn revoxelize_region(&mut self, world_aabb: (DVec3, DVec3)) -> Vec<IVec3> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::revoxelize_region");
+
+        let context = self.chunk_context();
+        let depth = context.depth;
+
+        let min_chunk = ((world_aabb.0 - context.mesh_min) / context.chunk_world_size)
+            .floor()
+            .as_ivec3();
+        let max_chunk = ((world_aabb.1 - context.mesh_min) / context.chunk_world_size)
+            .floor()
+            .as_ivec3();
+
+        let mut chunk_face_map = self.build_face_to_chunk_map();
+        let interior_chunks = if self.config.fill_interior {
+            self.find_interior_chunks(&chunk_face_map)
+        } else {
+            FxHashSet::default()
+        };
+
+        let mut chunk_positions = Vec::new();
+        for y in min_chunk.y..=max_chunk.y {
+            for z in min_chunk.z..=max_chunk.z {
+                for x in min_chunk.x..=max_chunk.x {
+                    chunk_positions.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+
+        let chunk_faces = chunk_positions
+            .iter()
+            .map(|chunk_position| chunk_face_map.remove(chunk_position).unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let batches = chunk_positions
+            .par_iter()
+            .zip(chunk_faces.par_iter())
+            .map(|(chunk_position, faces)| {
+                if interior_chunks.contains(chunk_position) {
+                    let mut batch = Batch::new(depth);
+                    batch.just_fill(1);
+                    return Some(batch);
+                }
+
+                Self::voxelize_chunk(context, *chunk_position, faces, &self.mesh.vertices, None)
+            })
+            .collect::<Vec<_>>();
+
+        let interner = self.model.get_interner();
+        let mut interner = interner.write();
+
+        for (chunk_position, batch) in chunk_positions.iter().zip(batches) {
+            match batch {
+                Some(batch) => {
+                    let chunk = self.model.get_or_create_chunk(*chunk_position);
+                    chunk.clear(&mut interner);
+                    chunk.apply_batch(&mut interner, &batch);
+                }
+                None => {
+                    if let Some(mut chunk) = self.model.chunks.remove(chunk_position) {
+                        chunk.clear(&mut interner);
+                    }
+                }
+            }
+        }
+
+        chunk_positions
     }
 
     pub fn simple_voxelize(&mut self) {
@@ -402,11 +913,9 @@ impl Voxelizer {
         for face in self.mesh.faces.iter() {
             for vertex_index in [face.x, face.y, face.z] {
                 let vertex = self.mesh.vertices[(vertex_index - 1) as usize] - mesh_min;
-                let voxel = (vertex * inv_voxel_size).floor().as_ivec3();
-                let local_voxel = voxel % voxels_per_axis as i32;
+                let voxel = (vertex * inv_voxel_size).floor().as_i64vec3();
 
-                let chunk_position =
-                    convert_voxel_world_to_chunk_position(voxel, self.model.chunk_world_size);
+                let (chunk_position, local_voxel) = world_voxel_to_chunk(voxel, voxels_per_axis);
                 let chunk = &mut self.model.get_or_create_chunk(chunk_position);
 
                 chunk.set(&mut interner, local_voxel, 1);
@@ -420,6 +929,19 @@ impl Voxelizer {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("Voxelizer::voxelize");
 
+        self.voxelize_cancellable(Arc::new(AtomicBool::new(false)));
+    }
+
+    /// Voxelizes the mesh like [`Voxelizer::voxelize`], stopping as soon as `cancel` is set.
+    ///
+    /// Returns `false` if the voxelization was cancelled. Chunks are only ever written whole,
+    /// so the model is left with the chunks finished so far, each matching what a full run
+    /// would have produced, and none of the interior chunks filled afterwards if cancelled
+    /// before them.
+    pub fn voxelize_cancellable(&mut self, cancel: Arc<AtomicBool>) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::voxelize_cancellable");
+
         println!("Voxelize started");
 
         let face_to_chunk_map_time = Instant::now();
@@ -435,17 +957,10 @@ impl Voxelizer {
 
         println!("Voxelizing mesh");
 
-        self.voxelize_mesh(chunk_face_map);
+        let finished = self.voxelize_mesh_until(chunk_face_map, cancel);
 
         let voxelize_time = voxelize_time.elapsed();
 
-        let empty_chunks = self
-            .model
-            .chunks
-            .iter()
-            .filter(|(_, chunk)| chunk.is_empty())
-            .count();
-
         let total = face_to_chunk_map_time + voxelize_time;
 
         #[cfg(feature = "memory_stats")]
@@ -455,8 +970,900 @@ impl Voxelizer {
         }
 
         println!(
-            "Done, {} chunks, empty: {empty_chunks}, face-to-chunk: {face_to_chunk_map_time:?}, voxelized: {voxelize_time:?}, total: {total:?}",
-            self.model.chunks.len(),
+            "{}, {}, face-to-chunk: {face_to_chunk_map_time:?}, voxelized: {voxelize_time:?}, total: {total:?}",
+            if finished { "Done" } else { "Cancelled" },
+            self.model.summary(),
+        );
+
+        finished
+    }
+
+    /// Voxelizes the mesh as a solid, with voxel values taken from the material group of each
+    /// face through `material_map`. Groups missing from the map use `1`.
+    ///
+    /// Interior voxels are found with a parity test along `+X` for every voxel row, so the mesh
+    /// must be closed. They take the value of the face the row entered the mesh through, surface
+    /// voxels keep the value of the face they touch.
+    pub fn voxelize_solid(&mut self, material_map: &FxHashMap<usize, i32>) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::voxelize_solid");
+
+        let now = Instant::now();
+
+        let context = self.chunk_context();
+
+        // indexed like the mesh faces, identical faces may belong to different groups
+        let face_values = (0..self.mesh.faces.len())
+            .map(|face_idx| {
+                let material = self.mesh.face_materials.get(face_idx).copied().unwrap_or(0);
+                material_map.get(&material).copied().unwrap_or(1)
+            })
+            .collect::<Vec<_>>();
+
+        let chunk_face_map = self.build_face_index_to_chunk_map();
+
+        let interior = self.find_interior_voxels(&chunk_face_map, &face_values);
+
+        let surface = chunk_face_map
+            .par_iter()
+            .filter_map(|(chunk_position, face_indices)| {
+                let faces = face_indices
+                    .iter()
+                    .map(|&face_idx| self.mesh.faces[face_idx])
+                    .collect::<Vec<_>>();
+                let values = face_indices
+                    .iter()
+                    .map(|&face_idx| face_values[face_idx])
+                    .collect::<Vec<_>>();
+
+                Self::voxelize_chunk(
+                    context,
+                    *chunk_position,
+                    &faces,
+                    &self.mesh.vertices,
+                    Some(&values),
+                )
+                .map(|batch| (*chunk_position, batch))
+            })
+            .collect::<Vec<_>>();
+
+        let interner = self.model.get_interner();
+        let mut interner = interner.write();
+
+        // surface voxels go last, so they override the interior runs they bound
+        for (chunk_position, batch) in interior.iter().chain(surface.iter()) {
+            self.model
+                .get_or_create_chunk(*chunk_position)
+                .apply_batch(&mut interner, batch);
+        }
+
+        drop(interner);
+
+        println!(
+            "Solid voxelize took: {:?}, {}",
+            now.elapsed(),
+            self.model.summary()
         );
     }
+
+    // Casts a ray along +X through every voxel row of the mesh bounds and fills the voxels
+    // between each entering and leaving hit with the value of the entering face.
+    fn find_interior_voxels(
+        &self,
+        chunk_face_map: &FxHashMap<IVec3, Vec<usize>>,
+        face_values: &[i32],
+    ) -> Vec<(IVec3, Batch<i32>)> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Voxelizer::find_interior_voxels");
+
+        let lod = Lod::new(0);
+
+        let depth = self.model.max_depth(lod);
+        let voxels_per_axis = self.model.voxels_per_axis(lod) as i32;
+        let chunk_world_size = self.model.chunk_world_size.as_dvec3();
+        let voxel_size = chunk_world_size / voxels_per_axis as f64;
+        let mesh_min = self.mesh.aabb.0;
+        let max_chunk = (self.mesh.size / chunk_world_size).floor().as_ivec3();
+
+        let mut chunk_rows = Vec::new();
+        for y in 0..=max_chunk.y {
+            for z in 0..=max_chunk.z {
+                chunk_rows.push((y, z));
+            }
+        }
+
+        chunk_rows
+            .into_par_iter()
+            .flat_map_iter(|(chunk_y, chunk_z)| {
+                // every face crossing the row is mapped to one of its chunks, duplicated faces
+                // are counted once with the value of the last one, as on the surface
+                let mut faces: FxHashMap<IVec3, usize> = FxHashMap::default();
+                for x in 0..=max_chunk.x {
+                    if let Some(row_faces) = chunk_face_map.get(&IVec3::new(x, chunk_y, chunk_z)) {
+                        for &face_idx in row_faces {
+                            let last = faces.entry(self.mesh.faces[face_idx]).or_insert(face_idx);
+                            *last = (*last).max(face_idx);
+                        }
+                    }
+                }
+
+                let triangles = faces
+                    .iter()
+                    .map(|(face, &face_idx)| {
+                        let v1 = self.mesh.vertices[(face.x - 1) as usize] - mesh_min;
+                        let v2 = self.mesh.vertices[(face.y - 1) as usize] - mesh_min;
+                        let v3 = self.mesh.vertices[(face.z - 1) as usize] - mesh_min;
+                        ((v1, v2, v3), face_values[face_idx])
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut batches: FxHashMap<IVec3, Batch<i32>> = FxHashMap::default();
+                let mut hits = Vec::new();
+
+                for local_y in 0..voxels_per_axis {
+                    for local_z in 0..voxels_per_axis {
+                        let y = chunk_y * voxels_per_axis + local_y;
+                        let z = chunk_z * voxels_per_axis + local_z;
+
+                        // off-center, so the ray does not run along mesh edges
+                        let origin = DVec3::new(
+                            -voxel_size.x,
+                            (y as f64 + 0.5123) * voxel_size.y,
+                            (z as f64 + 0.4871) * voxel_size.z,
+                        );
+
+                        hits.clear();
+                        hits.extend(triangles.iter().filter_map(|(triangle, value)| {
+                            ray_triangle_intersection(origin, DVec3::X, *triangle)
+                                .map(|t| (origin.x + t, *value))
+                        }));
+                        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                        for span in hits.chunks_exact(2) {
+                            let (enter, value) = span[0];
+                            let exit = span[1].0;
+
+                            // voxels whose center lies between the two hits
+                            let first = (enter / voxel_size.x - 0.5).ceil() as i32;
+                            let last = (exit / voxel_size.x - 0.5).floor() as i32;
+
+                            for x in first.max(0)..=last {
+                                let chunk_position =
+                                    IVec3::new(x.div_euclid(voxels_per_axis), chunk_y, chunk_z);
+                                let local_position =
+                                    IVec3::new(x.rem_euclid(voxels_per_axis), local_y, local_z);
+
+                                batches
+                                    .entry(chunk_position)
+                                    .or_insert_with(|| Batch::new(depth))
+                                    .just_set(local_position, value);
+                            }
+                        }
+                    }
+                }
+
+                batches
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use voxelis::{
+        io::export::export_model_to_obj,
+        spatial::{VoxOpsChunkConfig, VoxOpsRead},
+        utils::common::to_vec,
+    };
+
+    use super::*;
+
+    fn make_mesh() -> Obj {
+        // Two triangles spanning a tilted quad, crossing several chunks
+        let vertices = vec![
+            DVec3::new(0.0, 0.0, 0.0),
+            DVec3::new(20.0, 4.0, 0.0),
+            DVec3::new(20.0, 12.0, 20.0),
+            DVec3::new(0.0, 8.0, 20.0),
+        ];
+        let faces = vec![IVec3::new(1, 2, 3), IVec3::new(1, 3, 4)];
+
+        let aabb = (DVec3::ZERO, DVec3::new(20.0, 12.0, 20.0));
+        let size = aabb.1 - aabb.0;
+
+        Obj {
+            vertices,
+            faces,
+            face_materials: Vec::new(),
+            materials: Vec::new(),
+            aabb,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_voxelize_streaming_matches_voxelize() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut expected = Voxelizer::empty(max_depth, Vec3::splat(8.0), make_mesh(), 1024 * 1024);
+        expected.voxelize();
+
+        let mut streamed = Voxelizer::empty(max_depth, Vec3::splat(8.0), make_mesh(), 1024 * 1024);
+        let batches = streamed.voxelize_streaming().collect::<Vec<_>>();
+        assert!(streamed.model.chunks.is_empty());
+
+        {
+            let interner = streamed.model.get_interner();
+            let mut interner = interner.write();
+
+            for (chunk_position, batch) in batches {
+                streamed
+                    .model
+                    .get_or_create_chunk(chunk_position)
+                    .apply_batch(&mut interner, &batch);
+            }
+        }
+
+        assert!(!expected.model.chunks.is_empty());
+        assert_eq!(expected.model.chunks.len(), streamed.model.chunks.len());
+
+        let expected_interner = expected.model.get_interner();
+        let expected_interner = expected_interner.read();
+        let streamed_interner = streamed.model.get_interner();
+        let streamed_interner = streamed_interner.read();
+
+        for (chunk_position, chunk) in &expected.model.chunks {
+            let other = &streamed.model.chunks[chunk_position];
+
+            assert_eq!(
+                to_vec(&expected_interner, &chunk.get_root_id(), max_depth),
+                to_vec(&streamed_interner, &other.get_root_id(), max_depth),
+                "Chunk {chunk_position} differs"
+            );
+        }
+    }
+
+    #[test]
+    fn test_simple_voxelize_splits_chunks() {
+        let mut voxelizer =
+            Voxelizer::empty(MaxDepth::new(3), Vec3::splat(1.0), make_mesh(), 1024 * 1024);
+        voxelizer.simple_voxelize();
+
+        // vertex (20, 4, 0) lands in voxel (160, 32, 0), chunk (20, 4, 0) local (0, 0, 0)
+        let interner = voxelizer.model.get_interner();
+        let interner = interner.read();
+        let chunk = &voxelizer.model.chunks[&IVec3::new(20, 4, 0)];
+        assert_eq!(chunk.get(&interner, IVec3::ZERO), Some(1));
+    }
+
+    fn assert_same_chunks(a: &VoxModel<i32>, b: &VoxModel<i32>) {
+        let a_interner = a.get_interner();
+        let a_interner = a_interner.read();
+        let b_interner = b.get_interner();
+        let b_interner = b_interner.read();
+
+        assert_eq!(a.chunks.len(), b.chunks.len());
+
+        for (chunk_position, chunk) in &a.chunks {
+            let other = &b.chunks[chunk_position];
+
+            assert_eq!(
+                to_vec(&a_interner, &chunk.get_root_id(), a.max_depth),
+                to_vec(&b_interner, &other.get_root_id(), b.max_depth),
+                "Chunk {chunk_position} differs"
+            );
+        }
+    }
+
+    #[test]
+    fn test_revoxelize_region_matches_full_voxelize() {
+        let max_depth = MaxDepth::new(3);
+
+        // one triangle near the origin, one in the far corner
+        let make_mesh = |corner: DVec3| Obj {
+            vertices: vec![
+                DVec3::new(0.0, 0.0, 0.0),
+                DVec3::new(6.0, 0.0, 0.0),
+                DVec3::new(0.0, 6.0, 6.0),
+                DVec3::new(17.0, 9.0, 17.0),
+                corner,
+                DVec3::new(17.0, 12.0, 23.0),
+            ],
+            faces: vec![IVec3::new(1, 2, 3), IVec3::new(4, 5, 6)],
+            face_materials: Vec::new(),
+            materials: Vec::new(),
+            aabb: (DVec3::ZERO, DVec3::new(23.0, 12.0, 23.0)),
+            size: DVec3::new(23.0, 12.0, 23.0),
+        };
+
+        let mut voxelizer = Voxelizer::empty(
+            max_depth,
+            Vec3::splat(8.0),
+            make_mesh(DVec3::new(23.0, 10.0, 17.0)),
+            1024 * 1024,
+        );
+        voxelizer.voxelize();
+
+        let roots_before = voxelizer
+            .model
+            .chunks
+            .iter()
+            .map(|(position, chunk)| (*position, chunk.get_root_id()))
+            .collect::<Vec<_>>();
+
+        let edited_corner = DVec3::new(23.0, 11.0, 20.0);
+        voxelizer.mesh.vertices[4] = edited_corner;
+
+        let region = (DVec3::new(16.0, 8.0, 16.0), DVec3::new(23.0, 15.0, 23.0));
+        let touched = voxelizer.revoxelize_region(region);
+
+        assert_eq!(touched, vec![IVec3::new(2, 1, 2)]);
+
+        for (position, root_id) in roots_before {
+            if !touched.contains(&position) {
+                assert_eq!(voxelizer.model.chunks[&position].get_root_id(), root_id);
+            }
+        }
+
+        let mut expected = Voxelizer::empty(
+            max_depth,
+            Vec3::splat(8.0),
+            make_mesh(edited_corner),
+            1024 * 1024,
+        );
+        expected.voxelize();
+
+        assert_same_chunks(&expected.model, &voxelizer.model);
+    }
+
+    #[test]
+    fn test_summary_after_voxelize() {
+        let mut voxelizer =
+            Voxelizer::empty(MaxDepth::new(3), Vec3::splat(8.0), make_mesh(), 1024 * 1024);
+        voxelizer.voxelize();
+
+        let interner = voxelizer.model.get_interner();
+        let voxels = voxelizer
+            .model
+            .chunks
+            .values()
+            .map(|chunk| {
+                to_vec(
+                    &interner.read(),
+                    &chunk.get_root_id(),
+                    voxelizer.model.max_depth,
+                )
+                .iter()
+                .filter(|value| **value != 0)
+                .count()
+            })
+            .sum::<usize>();
+
+        let summary = voxelizer.model.summary();
+        let chunks = voxelizer.model.chunks.len();
+
+        assert!(
+            summary.starts_with(&format!(
+                "chunks: {chunks} (non-empty: {chunks}), voxels: {voxels}, depth: 3, chunk size: 8m"
+            )),
+            "{summary}"
+        );
+    }
+
+    #[test]
+    fn test_obj_negative_indices_match_positive() {
+        let positive = "v 0 0 0\nv 20 4 0\nv 20 12 20\nv 0 8 20\nf 1 2 3\nf 1 3 4\n";
+        // relative indices count back from the vertices read so far
+        let negative = "v 0 0 0\nv 20 4 0\nv 20 12 20\nf -3 -2 -1\nv 0 8 20\nf -4 -2 -1\n";
+
+        let write = |name: &str, contents: &str| {
+            let path =
+                std::env::temp_dir().join(format!("voxelis_obj_{name}_{}.obj", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let positive_path = write("positive", positive);
+        let negative_path = write("negative", negative);
+
+        let positive_obj = Obj::parse(&positive_path).unwrap();
+        let negative_obj = Obj::parse(&negative_path).unwrap();
+
+        std::fs::remove_file(positive_path).unwrap();
+        std::fs::remove_file(negative_path).unwrap();
+
+        assert_eq!(positive_obj.faces, negative_obj.faces);
+
+        let max_depth = MaxDepth::new(3);
+
+        let mut expected = Voxelizer::empty(max_depth, Vec3::splat(8.0), positive_obj, 1024 * 1024);
+        expected.voxelize();
+
+        let mut voxelizer =
+            Voxelizer::empty(max_depth, Vec3::splat(8.0), negative_obj, 1024 * 1024);
+        voxelizer.voxelize();
+
+        assert!(!expected.model.chunks.is_empty());
+        assert_same_chunks(&expected.model, &voxelizer.model);
+    }
+
+    #[test]
+    fn test_multi_sample_drops_grazed_voxels() {
+        // flat triangle whose tip pokes 0.05 voxels into the next column
+        let vertices = vec![
+            DVec3::new(0.0, 3.6, 0.0),
+            DVec3::new(4.05, 3.6, 2.5),
+            DVec3::new(0.0, 3.6, 5.0),
+        ];
+        let make_mesh = || Obj {
+            vertices: vertices.clone(),
+            faces: vec![IVec3::new(1, 2, 3)],
+            face_materials: Vec::new(),
+            materials: Vec::new(),
+            aabb: (DVec3::ZERO, DVec3::new(8.0, 8.0, 8.0)),
+            size: DVec3::splat(8.0),
+        };
+
+        let max_depth = MaxDepth::new(3);
+        let occupied = |voxelizer: &Voxelizer| {
+            let interner = voxelizer.model.get_interner();
+            let interner = interner.read();
+            let chunk = &voxelizer.model.chunks[&IVec3::ZERO];
+
+            to_vec(&interner, &chunk.get_root_id(), max_depth)
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| **value != 0)
+                .map(|(index, _)| {
+                    IVec3::new(index as i32 % 8, index as i32 / 64, index as i32 / 8 % 8)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut single = Voxelizer::empty(max_depth, Vec3::splat(8.0), make_mesh(), 1024 * 1024);
+        single.voxelize();
+        let single = occupied(&single);
+
+        let mut sampled = Voxelizer::empty(max_depth, Vec3::splat(8.0), make_mesh(), 1024 * 1024);
+        sampled.config = VoxelizeConfig {
+            samples_per_axis: 4,
+            coverage_threshold: 0.25,
+            ..Default::default()
+        };
+        sampled.voxelize();
+        let sampled = occupied(&sampled);
+
+        let tip = IVec3::new(4, 3, 2);
+        assert!(single.contains(&tip));
+        assert!(!sampled.contains(&tip));
+
+        assert!(sampled.contains(&IVec3::new(1, 3, 2)));
+        assert!(sampled.len() < single.len());
+        assert!(sampled.iter().all(|position| single.contains(position)));
+    }
+
+    #[test]
+    fn test_conservative_rasterization_superset() {
+        // single quad slanted inside the row of voxels at y = 3, just above its floor
+        let make_mesh = || Obj {
+            vertices: vec![
+                DVec3::new(0.0, 3.1, 0.0),
+                DVec3::new(8.0, 3.2, 0.0),
+                DVec3::new(8.0, 3.2, 8.0),
+                DVec3::new(0.0, 3.1, 8.0),
+            ],
+            faces: vec![IVec3::new(1, 2, 3), IVec3::new(1, 3, 4)],
+            face_materials: Vec::new(),
+            materials: Vec::new(),
+            aabb: (DVec3::ZERO, DVec3::new(8.0, 8.0, 8.0)),
+            size: DVec3::splat(8.0),
+        };
+
+        let max_depth = MaxDepth::new(3);
+        let occupied = |config: VoxelizeConfig| {
+            let mut voxelizer =
+                Voxelizer::empty(max_depth, Vec3::splat(8.0), make_mesh(), 1024 * 1024);
+            voxelizer.config = config;
+            voxelizer.voxelize();
+
+            let interner = voxelizer.model.get_interner();
+            let interner = interner.read();
+            let chunk = &voxelizer.model.chunks[&IVec3::ZERO];
+
+            to_vec(&interner, &chunk.get_root_id(), max_depth)
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| **value != 0)
+                .map(|(index, _)| {
+                    IVec3::new(index as i32 % 8, index as i32 / 64, index as i32 / 8 % 8)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let normal = occupied(VoxelizeConfig::default());
+        let conservative = occupied(VoxelizeConfig {
+            conservative_rasterization: true,
+            ..Default::default()
+        });
+
+        assert_eq!(normal.len(), 64);
+        assert!(normal.iter().all(|position| position.y == 3));
+
+        // the centers of the row below are within a half-diagonal of the quad
+        assert!(conservative.contains(&IVec3::new(5, 2, 4)));
+        assert!(!conservative.contains(&IVec3::new(5, 4, 4)));
+
+        assert!(conservative.len() > normal.len());
+        assert!(
+            normal
+                .iter()
+                .all(|position| conservative.contains(position))
+        );
+    }
+
+    fn make_box() -> Obj {
+        // Closed box spanning 5 chunks per axis, so the inner 3x3x3 chunks touch no face
+        make_box_with_size(DVec3::splat(36.0))
+    }
+
+    fn make_box_with_size(size: DVec3) -> Obj {
+        let vertices = (0..8)
+            .map(|i| {
+                DVec3::new(
+                    (i & 1) as f64 * size.x,
+                    ((i >> 1) & 1) as f64 * size.y,
+                    ((i >> 2) & 1) as f64 * size.z,
+                )
+            })
+            .collect::<Vec<_>>();
+        let quads = [
+            [1, 3, 7, 5],
+            [2, 6, 8, 4],
+            [1, 5, 6, 2],
+            [3, 4, 8, 7],
+            [1, 2, 4, 3],
+            [5, 7, 8, 6],
+        ];
+        let faces = quads
+            .iter()
+            .flat_map(|[a, b, c, d]| [IVec3::new(*a, *b, *c), IVec3::new(*a, *c, *d)])
+            .collect::<Vec<_>>();
+        let aabb = (DVec3::ZERO, size);
+        Obj {
+            vertices,
+            faces,
+            face_materials: Vec::new(),
+            materials: Vec::new(),
+            aabb,
+            size: aabb.1 - aabb.0,
+        }
+    }
+
+    #[test]
+    fn test_voxelize_solid_materials() {
+        let max_depth = MaxDepth::new(3);
+
+        // the -X side of the box is group 1, the rest group 0
+        let mut mesh = make_box();
+        mesh.face_materials = vec![1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let material_map = FxHashMap::from_iter([(0, 20), (1, 10)]);
+
+        let mut voxelizer = Voxelizer::empty(max_depth, Vec3::splat(8.0), mesh, 1024 * 1024);
+        voxelizer.voxelize_solid(&material_map);
+
+        let interner = voxelizer.model.get_interner();
+        let interner = interner.read();
+
+        let voxel = |position: IVec3| {
+            let (chunk_position, local_position) = world_voxel_to_chunk(
+                position.as_i64vec3(),
+                voxelizer.model.voxels_per_axis(Lod::new(0)),
+            );
+            voxelizer
+                .model
+                .chunks
+                .get(&chunk_position)
+                .and_then(|chunk| chunk.get(&interner, local_position))
+        };
+
+        // rows enter the box through the -X side
+        assert_eq!(voxel(IVec3::splat(18)), Some(10));
+        assert_eq!(voxel(IVec3::new(30, 5, 33)), Some(10));
+
+        // surface voxels carry the material of their face
+        assert_eq!(voxel(IVec3::new(0, 18, 18)), Some(10));
+        assert_eq!(voxel(IVec3::new(18, 0, 18)), Some(20));
+        assert_eq!(voxel(IVec3::new(36, 18, 18)), Some(20));
+        assert_eq!(voxel(IVec3::new(18, 36, 18)), Some(20));
+
+        assert_eq!(voxel(IVec3::new(37, 18, 18)), None);
+
+        // the surface touches voxels 0..=36 on every axis, all of them are set
+        let occupied = voxelizer
+            .model
+            .chunks
+            .values()
+            .map(|chunk| {
+                to_vec(&interner, &chunk.get_root_id(), max_depth)
+                    .iter()
+                    .filter(|value| **value != 0)
+                    .count()
+            })
+            .sum::<usize>();
+        assert_eq!(occupied, 37 * 37 * 37);
+    }
+
+    #[test]
+    fn test_voxelize_solid_duplicated_faces() {
+        let max_depth = MaxDepth::new(3);
+
+        // the -X side of the box is listed twice, first in group 1, then in group 2
+        let mut mesh = make_box();
+        mesh.faces.insert(2, mesh.faces[0]);
+        mesh.faces.insert(3, mesh.faces[1]);
+        mesh.face_materials = vec![1, 1, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let material_map = FxHashMap::from_iter([(0, 20), (1, 10), (2, 30)]);
+
+        let mut voxelizer = Voxelizer::empty(max_depth, Vec3::splat(8.0), mesh, 1024 * 1024);
+        voxelizer.voxelize_solid(&material_map);
+
+        let interner = voxelizer.model.get_interner();
+        let interner = interner.read();
+
+        let voxel = |position: IVec3| {
+            let (chunk_position, local_position) = world_voxel_to_chunk(
+                position.as_i64vec3(),
+                voxelizer.model.voxels_per_axis(Lod::new(0)),
+            );
+            voxelizer
+                .model
+                .chunks
+                .get(&chunk_position)
+                .and_then(|chunk| chunk.get(&interner, local_position))
+        };
+
+        // the later copy wins on the surface and the box is still filled through it
+        assert_eq!(voxel(IVec3::new(0, 18, 18)), Some(30));
+        assert_eq!(voxel(IVec3::splat(18)), Some(30));
+        assert_eq!(voxel(IVec3::new(36, 18, 18)), Some(20));
+    }
+
+    #[test]
+    fn test_fill_interior_chunks() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut surface = Voxelizer::empty(max_depth, Vec3::splat(8.0), make_box(), 1024 * 1024);
+        surface.voxelize();
+        assert!(!surface.model.chunks.contains_key(&IVec3::splat(2)));
+
+        let mut solid = Voxelizer::empty(max_depth, Vec3::splat(8.0), make_box(), 1024 * 1024);
+        solid.config.fill_interior = true;
+
+        let interior = solid.find_interior_chunks(&solid.build_face_to_chunk_map());
+        assert_eq!(interior.len(), 27);
+        assert!(
+            interior
+                .iter()
+                .all(|p| p.cmpge(IVec3::ONE).all() && p.cmple(IVec3::splat(3)).all())
+        );
+
+        solid.voxelize();
+        assert_eq!(
+            solid.model.chunks.len(),
+            surface.model.chunks.len() + interior.len()
+        );
+
+        let interner = solid.model.get_interner();
+        let interner = interner.read();
+
+        for chunk_position in interior {
+            let chunk = &solid.model.chunks[&chunk_position];
+
+            // filled in one step, the whole chunk collapses into a single leaf
+            assert!(chunk.get_root_id().is_leaf());
+            assert!(
+                to_vec(&interner, &chunk.get_root_id(), max_depth)
+                    .iter()
+                    .all(|value| *value == 1)
+            );
+        }
+
+        // surface chunks are unchanged
+        let surface_interner = surface.model.get_interner();
+        let surface_interner = surface_interner.read();
+        for (chunk_position, chunk) in surface.model.chunks.iter() {
+            assert_eq!(
+                to_vec(
+                    &interner,
+                    &solid.model.chunks[chunk_position].get_root_id(),
+                    max_depth
+                ),
+                to_vec(&surface_interner, &chunk.get_root_id(), max_depth)
+            );
+        }
+    }
+
+    #[test]
+    fn test_progress_callback() {
+        let mut voxelizer =
+            Voxelizer::empty(MaxDepth::new(3), Vec3::splat(8.0), make_box(), 1024 * 1024);
+        voxelizer.config.fill_interior = true;
+
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        voxelizer.set_progress_callback(move |progress| {
+            reports_clone.lock().unwrap().push(progress);
+        });
+
+        voxelizer.voxelize();
+
+        let reports = reports.lock().unwrap();
+
+        // every chunk of the 5x5x5 box, the 27 inner ones filled as interior
+        assert!(reports.iter().all(|progress| progress.total_chunks == 125));
+        assert_eq!(reports.first().unwrap().processed_chunks, 0);
+        assert_eq!(reports.last().unwrap().processed_chunks, 125);
+        assert!(
+            reports
+                .windows(2)
+                .all(|pair| pair[0].processed_chunks < pair[1].processed_chunks)
+        );
+
+        let last = reports.last().unwrap();
+        assert!(last.used_memory > reports[0].used_memory);
+        assert!(last.used_memory <= last.memory_budget);
+
+        // the callback only has to be `Send`, a `Cell` is enough to count the reports
+        let mut voxelizer =
+            Voxelizer::empty(MaxDepth::new(3), Vec3::splat(8.0), make_box(), 1024 * 1024);
+        voxelizer.config.fill_interior = true;
+
+        let count = std::cell::Cell::new(0);
+        let (tx, rx) = std::sync::mpsc::channel();
+        voxelizer.set_progress_callback(move |_| {
+            count.set(count.get() + 1);
+            tx.send(count.get()).unwrap();
+        });
+
+        voxelizer.voxelize();
+
+        assert!(rx.try_iter().last().is_some_and(|count| count > 1));
+    }
+
+    #[test]
+    fn test_voxelize_cancellable_stops_after_first_chunk() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut full = Voxelizer::empty(max_depth, Vec3::splat(4.0), make_box(), 1024 * 1024);
+        full.config.fill_interior = true;
+        assert!(full.voxelize_cancellable(Arc::new(AtomicBool::new(false))));
+
+        let mut cancelled = Voxelizer::empty(max_depth, Vec3::splat(4.0), make_box(), 1024 * 1024);
+        cancelled.config.fill_interior = true;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel_clone = cancel.clone();
+        cancelled.set_progress_callback(move |progress| {
+            if progress.processed_chunks > 0 {
+                cancel_clone.store(true, Ordering::Relaxed);
+            }
+        });
+
+        assert!(!cancelled.voxelize_cancellable(cancel.clone()));
+
+        // batches still in flight are dropped, so only the first chunk is written
+        assert_eq!(cancelled.model.chunks.len(), 1);
+        assert!(full.model.chunks.len() > 100);
+
+        let interner = cancelled.model.get_interner();
+        let interner = interner.read();
+        let full_interner = full.model.get_interner();
+        let full_interner = full_interner.read();
+
+        for (chunk_position, chunk) in &cancelled.model.chunks {
+            assert_eq!(
+                to_vec(&interner, &chunk.get_root_id(), max_depth),
+                to_vec(
+                    &full_interner,
+                    &full.model.chunks[chunk_position].get_root_id(),
+                    max_depth
+                ),
+                "Chunk {chunk_position} differs"
+            );
+        }
+        drop(interner);
+
+        // an already set flag stops before any chunk
+        cancelled.clear();
+        assert!(!cancelled.voxelize_cancellable(cancel));
+        assert!(cancelled.model.chunks.is_empty());
+    }
+
+    #[test]
+    fn test_voxelize_anisotropic_chunks_export_obj() {
+        let max_depth = MaxDepth::new(3);
+
+        // a long thin bar, with chunks stretched along it, 2 x 0.25 x 0.25 world units per voxel
+        let chunk_world_size = Vec3::new(16.0, 2.0, 2.0);
+        let mut voxelizer = Voxelizer::empty(
+            max_depth,
+            chunk_world_size,
+            make_box_with_size(DVec3::new(32.0, 2.0, 2.0)),
+            1024 * 1024,
+        );
+        voxelizer.voxelize_solid(&FxHashMap::default());
+
+        // the surface touches voxels 0..=16 along the bar and 0..=8 across it
+        let voxels = voxelizer
+            .model
+            .total_voxel_count(&voxelizer.model.get_interner().read());
+        assert_eq!(voxels, 17 * 9 * 9);
+        assert!(voxelizer.model.chunks.contains_key(&IVec3::new(2, 1, 1)));
+
+        let path = std::env::temp_dir().join(format!("voxelis_bar_{}.obj", std::process::id()));
+        export_model_to_obj("bar".to_string(), &path, &voxelizer.model, Lod::new(0));
+        let obj = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let vertices = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|line| {
+                let coords = line
+                    .split(' ')
+                    .map(|coord| coord.parse::<f32>().unwrap())
+                    .collect::<Vec<_>>();
+                Vec3::from_slice(&coords)
+            })
+            .collect::<Vec<_>>();
+        assert!(!vertices.is_empty());
+
+        let min = vertices.iter().fold(Vec3::MAX, |a, v| a.min(*v));
+        let max = vertices.iter().fold(Vec3::MIN, |a, v| a.max(*v));
+        assert_eq!(min, Vec3::ZERO);
+        assert_eq!(max, Vec3::new(34.0, 2.25, 2.25));
+
+        // every vertex is a voxel corner of the stretched grid
+        let voxel_size = voxelizer.model.voxel_size(Lod::new(0));
+        assert_eq!(voxel_size, Vec3::new(2.0, 0.25, 0.25));
+        for vertex in &vertices {
+            let corner = *vertex / voxel_size;
+            assert_eq!(corner, corner.round(), "{vertex} is not on a voxel corner");
+        }
+
+        let interner = voxelizer.model.get_interner();
+        let mut interner = interner.write();
+        for chunk in voxelizer.model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_estimated_memory_budget_fits_without_growing() {
+        let max_depth = MaxDepth::new(3);
+        let chunk_world_size = Vec3::splat(2.0);
+
+        let mesh = make_mesh();
+        let voxel_size = chunk_world_size.x as f64 / (1 << max_depth.max()) as f64;
+        let memory_budget = estimate_memory_budget(&mesh, voxel_size);
+        assert!(memory_budget > VoxInterner::<i32>::memory_budget_for_voxels(0));
+
+        let mut voxelizer = Voxelizer::empty(max_depth, chunk_world_size, mesh, memory_budget);
+        let pool_capacity = voxelizer.model.get_interner().read().pool_capacity();
+        assert_eq!(
+            pool_capacity,
+            memory_budget / VoxInterner::<i32>::node_size()
+        );
+
+        voxelizer.voxelize();
+        assert!(!voxelizer.model.chunks.is_empty());
+
+        let interner = voxelizer.model.get_interner();
+        let interner = interner.read();
+        assert_eq!(interner.pool_capacity(), pool_capacity);
+        assert!(interner.live_node_count() < pool_capacity);
+    }
 }
@@ -0,0 +1,53 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use glam::IVec3;
+use rand::Rng;
+
+use voxelis::{MaxDepth, utils::common::morton_encode_many_scalar};
+
+#[cfg(feature = "simd")]
+use voxelis::utils::common::morton_encode_many_simd;
+
+const POSITIONS: usize = 1_000_000;
+
+fn benchmark_morton(c: &mut Criterion) {
+    let mut group = c.benchmark_group("morton_encode_many");
+    group.throughput(Throughput::Elements(POSITIONS as u64));
+
+    let mut rng = rand::rng();
+
+    for depth in [3, 6] {
+        let size = 1 << depth;
+        let positions = (0..POSITIONS)
+            .map(|_| {
+                IVec3::new(
+                    rng.random_range(0..size),
+                    rng.random_range(0..size),
+                    rng.random_range(0..size),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut codes = vec![0u32; POSITIONS];
+
+        group.bench_with_input(BenchmarkId::new("scalar", depth), &depth, |b, &depth| {
+            b.iter(|| {
+                morton_encode_many_scalar(black_box(&positions), MaxDepth::new(depth), &mut codes);
+                black_box(&codes);
+            });
+        });
+
+        #[cfg(feature = "simd")]
+        group.bench_with_input(BenchmarkId::new("simd", depth), &depth, |b, &depth| {
+            b.iter(|| {
+                morton_encode_many_simd(black_box(&positions), MaxDepth::new(depth), &mut codes);
+                black_box(&codes);
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_morton);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use glam::{IVec3, Vec3};
+
+use voxelis::{MaxDepth, world::VoxModel};
+
+fn create_chunks(model: &mut VoxModel<i32>, side: i32) {
+    for y in 0..side {
+        for z in 0..side {
+            for x in 0..side {
+                black_box(model.get_or_create_chunk(IVec3::new(x, y, z)));
+            }
+        }
+    }
+}
+
+fn benchmark_voxmodel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("voxmodel_get_or_create_chunk");
+
+    for side in [16, 32, 64] {
+        let count = (side * side * side) as usize;
+
+        group.bench_with_input(BenchmarkId::new("no_reserve", count), &side, |b, &side| {
+            b.iter(|| {
+                let mut model =
+                    VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(1.0), 1024 * 1024);
+                create_chunks(&mut model, side);
+
+                #[cfg(feature = "tracy")]
+                tracy_client::frame_mark();
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("reserve", count), &side, |b, &side| {
+            b.iter(|| {
+                let mut model =
+                    VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(1.0), 1024 * 1024);
+                model.reserve_chunks(count);
+                create_chunks(&mut model, side);
+
+                #[cfg(feature = "tracy")]
+                tracy_client::frame_mark();
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_voxmodel);
+criterion_main!(benches);
@@ -1508,6 +1508,30 @@ fn benchmark_voxtree(c: &mut Criterion) {
         group.finish();
     }
 
+    {
+        // Hot `set` loop for measuring the cost of internal consistency checks,
+        // compare runs with and without `--features strict`.
+        let mut group = c.benchmark_group("voxtree_set_hot_loop");
+
+        for &(size, depth) in depths.iter() {
+            group.bench_with_input(size.to_string(), &depth, |b, &depth| {
+                let mut tree = VoxTree::new(depth);
+                let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024 * 185);
+
+                b.iter(|| {
+                    fill_sum!(size, tree, interner);
+
+                    tree.clear(&mut interner);
+
+                    #[cfg(feature = "tracy")]
+                    tracy_client::frame_mark();
+                });
+            });
+        }
+
+        group.finish();
+    }
+
     {
         let mut group = c.benchmark_group("voxtree_get_empty");
 
@@ -1593,6 +1617,55 @@ fn benchmark_voxtree(c: &mut Criterion) {
         group.finish();
     }
 
+    {
+        let mut group = c.benchmark_group("voxtree_get_many_sphere");
+
+        for &(size, depth) in depths.iter() {
+            let mut tree = VoxTree::new(depth);
+            let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024 * 14);
+
+            generate_test_sphere(&mut tree, &mut interner, size, 1);
+
+            // 3x3x3 neighborhoods of every fourth voxel, like mesh neighbor sampling
+            let size = size as i32;
+            let mut positions = Vec::new();
+            for cy in (1..size - 1).step_by(4) {
+                for cz in (1..size - 1).step_by(4) {
+                    for cx in (1..size - 1).step_by(4) {
+                        for y in -1..=1 {
+                            for z in -1..=1 {
+                                for x in -1..=1 {
+                                    positions.push(IVec3::new(cx + x, cy + y, cz + z));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            let mut out = vec![None; positions.len()];
+
+            let bench_id = BenchmarkId::new(size.to_string(), "get");
+            group.bench_with_input(bench_id, &positions, |b, positions| {
+                b.iter(|| {
+                    for position in positions.iter() {
+                        let _ = black_box(tree.get(&interner, black_box(*position)));
+                    }
+                });
+            });
+
+            let bench_id = BenchmarkId::new(size.to_string(), "get_many");
+            group.bench_with_input(bench_id, &positions, |b, positions| {
+                b.iter(|| {
+                    tree.get_many(&interner, black_box(positions), &mut out);
+                    black_box(&out);
+                });
+            });
+        }
+
+        group.finish();
+    }
+
     {
         let mut group = c.benchmark_group("voxtree_get_full_uniform");
 
@@ -1852,7 +1925,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_sphere");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -1887,7 +1960,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_terrain_surface");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -1921,7 +1994,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_terrain_full");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -1955,7 +2028,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_terrain_full_3_mats");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -1989,7 +2062,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_corners_all");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2019,7 +2092,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_corners_all_unique");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2049,7 +2122,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_corners_min");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2083,7 +2156,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_corners_max");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2117,7 +2190,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_checkerboard");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2147,7 +2220,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_sparse_fill");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2177,7 +2250,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_hollow_cube");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2207,7 +2280,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_diagonal");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2237,7 +2310,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_perlin3d");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -2272,7 +2345,7 @@ fn benchmark_voxtree(c: &mut Criterion) {
         let mut group = c.benchmark_group("voxtree_mesh_uniform");
 
         for &(size, depth) in depths.iter() {
-            let mut chunk = VoxChunk::with_position(1.28, depth, 0, 0, 0);
+            let mut chunk = VoxChunk::with_position(Vec3::splat(1.28), depth, 0, 0, 0);
 
             let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
@@ -22,20 +22,46 @@
 //! ```
 
 use glam::IVec3;
+use rustc_hash::FxHashMap;
 
 use crate::{
     Lod, MaxDepth, VoxInterner, VoxelTrait,
     interner::MAX_CHILDREN,
     spatial::{VoxOpsBulkWrite, VoxOpsConfig, VoxOpsWrite},
-    utils::common::encode_child_index_path,
+    utils::common::{decode_child_index_path, encode_child_index_path, world_voxel_to_chunk},
 };
 
+// Splits world voxel edits into chunk and local positions, keeping their order
+fn for_each_world_edit<T: VoxelTrait>(
+    chunk_voxels_per_axis: u32,
+    edits: &[(IVec3, T)],
+    mut f: impl FnMut(IVec3, IVec3, &(IVec3, T)),
+) {
+    assert!(
+        chunk_voxels_per_axis.is_power_of_two(),
+        "Voxels per axis must be a power of two"
+    );
+
+    for edit in edits.iter() {
+        let (chunk_position, local_position) =
+            world_voxel_to_chunk(edit.0.as_i64vec3(), chunk_voxels_per_axis);
+
+        f(chunk_position, local_position, edit);
+    }
+}
+
+// Position of the voxel at `index` of the node at `path_index`
+#[inline(always)]
+fn path_position(path_index: usize, index: usize) -> IVec3 {
+    decode_child_index_path(((path_index << 3) | index) as u32)
+}
+
 /// Accumulates per-node voxel modifications, enabling efficient bulk updates for an octree.
 ///
 /// # Type parameters
 ///
 /// * `T` - The voxel type implementing [`VoxelTrait`].
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Batch<T: VoxelTrait> {
     masks: Vec<(u8, u8)>,
     values: Vec<[T; MAX_CHILDREN]>,
@@ -80,6 +106,100 @@ impl<T: VoxelTrait> Batch<T> {
         }
     }
 
+    /// Creates a [`Batch`] for a single chunk from edits given in world voxel coordinates.
+    /// Returns the batch with the edits inside the chunk, and the edits that fall outside it.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_position` - Position of the chunk, in chunks.
+    /// * `chunk_voxels_per_axis` - Number of voxels per chunk axis, must be a power of two.
+    /// * `edits` - World voxel positions and values; `T::default()` clears the voxel.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::Batch;
+    ///
+    /// let edits = [(IVec3::new(7, 0, 0), 1), (IVec3::new(8, 0, 0), 2)];
+    /// let (batch, leftover) = Batch::<u8>::from_world_edits(IVec3::ZERO, 8, &edits);
+    ///
+    /// assert_eq!(batch.size(), 1);
+    /// assert_eq!(leftover, vec![(IVec3::new(8, 0, 0), 2)]);
+    /// ```
+    #[must_use]
+    pub fn from_world_edits(
+        chunk_position: IVec3,
+        chunk_voxels_per_axis: u32,
+        edits: &[(IVec3, T)],
+    ) -> (Self, Vec<(IVec3, T)>) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::from_world_edits");
+
+        let mut batch = Self::new(MaxDepth::new(chunk_voxels_per_axis.trailing_zeros() as u8));
+        let mut leftover = Vec::new();
+
+        for_each_world_edit(
+            chunk_voxels_per_axis,
+            edits,
+            |chunk, local_position, edit| {
+                if chunk == chunk_position {
+                    batch.just_set(local_position, edit.1);
+                } else {
+                    leftover.push(*edit);
+                }
+            },
+        );
+
+        (batch, leftover)
+    }
+
+    /// Groups edits given in world voxel coordinates into one [`Batch`] per touched chunk,
+    /// in a single pass. Later edits of the same voxel win.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_voxels_per_axis` - Number of voxels per chunk axis, must be a power of two.
+    /// * `edits` - World voxel positions and values; `T::default()` clears the voxel.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::Batch;
+    ///
+    /// let edits = [(IVec3::new(7, 0, 0), 1), (IVec3::new(8, 0, 0), 2), (IVec3::new(9, 0, 0), 3)];
+    /// let batches = Batch::<u8>::group_world_edits(8, &edits);
+    ///
+    /// assert_eq!(batches.len(), 2);
+    /// assert!(batches.contains_key(&IVec3::ZERO));
+    /// assert!(batches.contains_key(&IVec3::new(1, 0, 0)));
+    /// ```
+    #[must_use]
+    pub fn group_world_edits(
+        chunk_voxels_per_axis: u32,
+        edits: &[(IVec3, T)],
+    ) -> FxHashMap<IVec3, Self> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::group_world_edits");
+
+        let max_depth = MaxDepth::new(chunk_voxels_per_axis.trailing_zeros() as u8);
+        let mut batches: FxHashMap<IVec3, Self> = FxHashMap::default();
+
+        for_each_world_edit(
+            chunk_voxels_per_axis,
+            edits,
+            |chunk_position, local_position, edit| {
+                batches
+                    .entry(chunk_position)
+                    .or_insert_with(|| Self::new(max_depth))
+                    .just_set(local_position, edit.1);
+            },
+        );
+
+        batches
+    }
+
     #[must_use]
     #[inline(always)]
     /// Returns the internal vector of (`set_mask`, `clear_mask`) pairs per node.
@@ -131,6 +251,63 @@ impl<T: VoxelTrait> Batch<T> {
         self.has_patches
     }
 
+    /// Iterates over the recorded set operations, the positions and values the batch will
+    /// write, e.g. to preview a brush stroke before applying it.
+    ///
+    /// Operations are yielded in Morton order. A pending fill, see [`Batch::to_fill`], is not
+    /// enumerated, it covers every voxel without a recorded operation.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::{Batch, MaxDepth};
+    ///
+    /// let mut batch = Batch::<u8>::new(MaxDepth::new(3));
+    /// batch.just_set(IVec3::new(1, 2, 3), 5);
+    /// batch.just_set(IVec3::new(4, 4, 4), 0);
+    ///
+    /// assert_eq!(batch.iter_set().collect::<Vec<_>>(), vec![(IVec3::new(1, 2, 3), 5)]);
+    /// assert_eq!(batch.iter_clear().collect::<Vec<_>>(), vec![IVec3::new(4, 4, 4)]);
+    /// ```
+    pub fn iter_set(&self) -> impl Iterator<Item = (IVec3, T)> + '_ {
+        self.iter_mask(|(set_mask, _)| set_mask)
+            .map(|(path_index, index)| {
+                (
+                    path_position(path_index, index),
+                    self.values[path_index][index],
+                )
+            })
+    }
+
+    /// Iterates over the positions of the recorded clear operations, in Morton order. Like
+    /// [`Batch::iter_set`], a pending fill is not enumerated.
+    pub fn iter_clear(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.iter_mask(|(_, clear_mask)| clear_mask)
+            .map(|(path_index, index)| path_position(path_index, index))
+    }
+
+    // Path index and child index of every bit set in the mask picked by `select`
+    fn iter_mask(&self, select: fn((u8, u8)) -> u8) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.masks
+            .iter()
+            .enumerate()
+            .flat_map(move |(path_index, masks)| {
+                let mut bits = select(*masks);
+
+                std::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+
+                    let index = bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+
+                    Some((path_index, index))
+                })
+            })
+    }
+
     /// Records a voxel set or clear operation at the specified 3D position.
     /// Returns `true` indicating that the state has changed.
     ///
@@ -159,7 +336,7 @@ impl<T: VoxelTrait> Batch<T> {
 
         let (set_mask, clear_mask) = &mut self.masks[path_index];
 
-        if voxel != T::default() {
+        if !voxel.voxel_eq(&T::default()) {
             *set_mask |= bit;
             *clear_mask &= !bit;
         } else {
@@ -174,6 +351,350 @@ impl<T: VoxelTrait> Batch<T> {
         true
     }
 
+    /// Records every voxel within `radius` of `center` as set to `value`, using the squared
+    /// distance test `dx² + dy² + dz² <= radius²`.
+    ///
+    /// Stamping uses [`Batch::just_set`] semantics, so it updates the set and clear masks like
+    /// individual sets, and `T::default()` clears the sphere instead. Voxels outside
+    /// `[0, voxels_per_axis)` are skipped, the sphere can extend past the batch bounds.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::{Batch, MaxDepth};
+    ///
+    /// let mut batch = Batch::<u8>::new(MaxDepth::new(3));
+    /// // only the octant inside the batch is recorded
+    /// batch.stamp_sphere(IVec3::ZERO, 2, 1);
+    /// assert!(batch.has_patches());
+    /// ```
+    pub fn stamp_sphere(&mut self, center: IVec3, radius: i32, value: T) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::stamp_sphere");
+
+        self.stamp_sphere_hollow(center, radius, -1, value);
+    }
+
+    /// Records a spherical shell around `center`: voxels with a squared distance above
+    /// `inner_radius²` and at most `radius²`. A negative `inner_radius` stamps a full sphere.
+    ///
+    /// Clipped to the batch bounds and recorded with [`Batch::just_set`] semantics, like
+    /// [`Batch::stamp_sphere`].
+    pub fn stamp_sphere_hollow(&mut self, center: IVec3, radius: i32, inner_radius: i32, value: T) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::stamp_sphere_hollow");
+
+        if radius < 0 {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let inner_radius_squared = if inner_radius < 0 {
+            -1
+        } else {
+            inner_radius * inner_radius
+        };
+
+        let Some((min, max)) = self.clip(center - radius, center + radius) else {
+            return;
+        };
+
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    let position = IVec3::new(x, y, z);
+                    let distance_squared = (position - center).length_squared();
+
+                    if distance_squared <= radius_squared && distance_squared > inner_radius_squared
+                    {
+                        self.just_set(position, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records every voxel of the box spanning `min..=max` as set to `value`.
+    ///
+    /// Clipped to the batch bounds and recorded with [`Batch::just_set`] semantics, like
+    /// [`Batch::stamp_sphere`]. Corners given in any order describe the same box.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::{Batch, MaxDepth};
+    ///
+    /// let mut batch = Batch::<u8>::new(MaxDepth::new(2));
+    /// batch.stamp_box(IVec3::new(-5, 0, 0), IVec3::new(1, 0, 0), 1);
+    /// assert_eq!(batch.values()[0][0..2], [1, 1]);
+    /// ```
+    pub fn stamp_box(&mut self, min: IVec3, max: IVec3, value: T) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::stamp_box");
+
+        let Some((min, max)) = self.clip(min.min(max), min.max(max)) else {
+            return;
+        };
+
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    self.just_set(IVec3::new(x, y, z), value);
+                }
+            }
+        }
+    }
+
+    /// Records a cylinder of `height` voxels starting at `base` and running along `axis`, with
+    /// every voxel within `radius` of the axis set to `value`, e.g. for pillars or pipes.
+    ///
+    /// The radial check is the squared distance test of [`Batch::stamp_sphere`], taken on the
+    /// two axes across the cylinder. Clipped to the batch bounds and recorded with
+    /// [`Batch::just_set`] semantics.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not one of the six cardinal directions, e.g. `IVec3::NEG_Y`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::{Batch, MaxDepth};
+    ///
+    /// let mut batch = Batch::<u8>::new(MaxDepth::new(3));
+    /// // a pillar hanging down from the top of the batch
+    /// batch.stamp_cylinder(IVec3::new(4, 7, 4), IVec3::NEG_Y, 2, 8, 1);
+    /// assert!(batch.has_patches());
+    /// ```
+    pub fn stamp_cylinder(&mut self, base: IVec3, axis: IVec3, radius: i32, height: i32, value: T) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::stamp_cylinder");
+
+        self.stamp_along_axis(base, axis, radius, height, false, value);
+    }
+
+    /// Records a capsule: the cylinder of [`Batch::stamp_cylinder`] with both ends rounded by
+    /// a half sphere of `radius`, centered on the first and last voxel of the axis.
+    ///
+    /// A `height` of `1` stamps a sphere like [`Batch::stamp_sphere`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not one of the six cardinal directions.
+    pub fn stamp_capsule(&mut self, base: IVec3, axis: IVec3, radius: i32, height: i32, value: T) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::stamp_capsule");
+
+        self.stamp_along_axis(base, axis, radius, height, true, value);
+    }
+
+    // Stamps the voxels within `radius` of the `height` voxels starting at `base` along `axis`,
+    // measured across the axis only unless the ends are `rounded`
+    fn stamp_along_axis(
+        &mut self,
+        base: IVec3,
+        axis: IVec3,
+        radius: i32,
+        height: i32,
+        rounded: bool,
+        value: T,
+    ) {
+        assert!(
+            axis.abs().element_sum() == 1,
+            "Axis must be a cardinal direction: {axis}"
+        );
+
+        if radius < 0 || height <= 0 {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let axis_index = (0..3).find(|&i| axis[i] != 0).unwrap_or_default();
+
+        let end = base + axis * (height - 1);
+        let Some((min, max)) = self.clip(base.min(end) - radius, base.max(end) + radius) else {
+            return;
+        };
+
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    let position = IVec3::new(x, y, z);
+
+                    let mut offset = position - base;
+                    let along = offset[axis_index] * axis[axis_index];
+                    offset[axis_index] = 0;
+
+                    // distance past the nearest end of the axis, zero alongside it
+                    let beyond = along - along.clamp(0, height - 1);
+                    let distance_squared = if beyond == 0 {
+                        offset.length_squared()
+                    } else if rounded {
+                        offset.length_squared() + beyond * beyond
+                    } else {
+                        continue;
+                    };
+
+                    if distance_squared <= radius_squared {
+                        self.just_set(position, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a copy of the batch with every recorded set or clear moved by `offset`, e.g. to
+    /// paste a region copied by [`crate::spatial::VoxTree::extract_region`] somewhere else.
+    ///
+    /// Operations moved outside of the batch bounds are dropped, a pending fill is kept.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::{Batch, MaxDepth};
+    ///
+    /// let mut batch = Batch::<u8>::new(MaxDepth::new(2));
+    /// batch.stamp_box(IVec3::ZERO, IVec3::new(1, 0, 0), 1);
+    ///
+    /// let moved = batch.translated(IVec3::new(2, 0, 0));
+    /// assert_eq!(moved.values()[1][0..2], [1, 1]);
+    /// assert_eq!(batch.translated(IVec3::new(4, 0, 0)).size(), 0);
+    /// ```
+    #[must_use]
+    pub fn translated(&self, offset: IVec3) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::translated");
+
+        let mut translated = Self::new(self.max_depth);
+        translated.to_fill = self.to_fill;
+
+        let size = IVec3::splat(1 << self.max_depth.max());
+
+        for (path_index, (set_mask, clear_mask)) in self.masks.iter().enumerate() {
+            let mut bits = set_mask | clear_mask;
+            while bits != 0 {
+                let index = bits.trailing_zeros() as usize;
+                bits &= !(1 << index);
+
+                let position = decode_child_index_path(((path_index << 3) | index) as u32) + offset;
+                if position.cmpge(IVec3::ZERO).all() && position.cmplt(size).all() {
+                    translated.just_set(position, self.values[path_index][index]);
+                }
+            }
+        }
+
+        translated
+    }
+
+    /// Returns the edits that turn the voxels written by this batch into the ones written by
+    /// `other`, e.g. for undo and redo or to send only the changed voxels over the network.
+    ///
+    /// Both batches are compared by the voxels they produce on an empty tree: a recorded set
+    /// or clear wins, other voxels take the fill value, or stay empty without one. When the
+    /// fills differ the diff carries the fill of `other`, or an empty fill, followed by the
+    /// recorded operations of `other` that differ from it. Otherwise it only records the
+    /// voxels that differ, with their values from `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batches were created for different depths.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::{Batch, MaxDepth};
+    ///
+    /// let mut before = Batch::<u8>::new(MaxDepth::new(3));
+    /// before.stamp_box(IVec3::ZERO, IVec3::splat(3), 1);
+    ///
+    /// let mut after = before.clone();
+    /// after.just_set(IVec3::new(1, 1, 1), 2);
+    /// after.just_set(IVec3::new(5, 5, 5), 3);
+    ///
+    /// let diff = before.diff(&after);
+    /// assert_eq!(diff.size(), 2);
+    /// assert_eq!(diff.to_fill(), None);
+    /// ```
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Batch::diff");
+
+        assert_eq!(
+            self.max_depth.max(),
+            other.max_depth.max(),
+            "Batches must have the same depth"
+        );
+
+        let mut diff = Self::new(self.max_depth);
+
+        let fill = self.to_fill.unwrap_or_default();
+        let other_fill = other.to_fill.unwrap_or_default();
+        let refill = !fill.voxel_eq(&other_fill);
+
+        if refill {
+            diff.to_fill = Some(other_fill);
+        }
+
+        // voxel written at `index` of the node at `path_index`
+        let voxel = |batch: &Self, fill: T, path_index: usize, index: usize| {
+            let (set_mask, clear_mask) = batch.masks[path_index];
+
+            if (set_mask | clear_mask) & (1 << index) != 0 {
+                batch.values[path_index][index]
+            } else {
+                fill
+            }
+        };
+
+        for path_index in 0..self.masks.len() {
+            let (set_mask, clear_mask) = self.masks[path_index];
+            let (other_set_mask, other_clear_mask) = other.masks[path_index];
+
+            // after a refill only the operations of `other` can differ from it
+            let touched = if refill {
+                other_set_mask | other_clear_mask
+            } else {
+                set_mask | clear_mask | other_set_mask | other_clear_mask
+            };
+
+            let mut bits = touched;
+            while bits != 0 {
+                let index = bits.trailing_zeros() as usize;
+                bits &= !(1 << index);
+
+                let before = if refill {
+                    other_fill
+                } else {
+                    voxel(self, fill, path_index, index)
+                };
+                let after = voxel(other, other_fill, path_index, index);
+
+                if !before.voxel_eq(&after) {
+                    let position = decode_child_index_path(((path_index << 3) | index) as u32);
+                    diff.just_set(position, after);
+                }
+            }
+        }
+
+        diff
+    }
+
+    // Clamps the inclusive box `min..=max` to the batch bounds, `None` if nothing is left.
+    fn clip(&self, min: IVec3, max: IVec3) -> Option<(IVec3, IVec3)> {
+        let last = IVec3::splat((1 << self.max_depth.max()) - 1);
+
+        let min = min.max(IVec3::ZERO);
+        let max = max.min(last);
+
+        min.cmple(max).all().then_some((min, max))
+    }
+
     /// Clears existing operations and sets a uniform fill value for the batch.
     pub fn just_fill(&mut self, value: T) {
         #[cfg(feature = "tracy")]
@@ -238,3 +759,272 @@ impl<T: VoxelTrait> VoxOpsConfig for Batch<T> {
         1 << self.max_depth.for_lod(lod).max()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::common::to_vec;
+
+    use super::*;
+
+    #[test]
+    fn test_from_world_edits_partitions_at_chunk_boundaries() {
+        let edits = [
+            (IVec3::new(0, 0, 0), 1),
+            (IVec3::new(7, 7, 7), 2),
+            (IVec3::new(8, 0, 0), 3),
+            (IVec3::new(-1, 0, 0), 4),
+            (IVec3::new(0, 0, 8), 0),
+        ];
+
+        let (batch, leftover) = Batch::<i32>::from_world_edits(IVec3::ZERO, 8, &edits);
+        assert_eq!(batch.max_depth(Lod::new(0)).max(), 3);
+        assert_eq!(batch.size(), 2);
+        assert_eq!(leftover, edits[2..].to_vec());
+
+        let (batch, leftover) = Batch::<i32>::from_world_edits(IVec3::new(1, 0, 0), 8, &edits);
+        assert_eq!(batch.size(), 1);
+        assert_eq!(leftover.len(), 4);
+
+        let path = encode_child_index_path(&IVec3::ZERO) as usize;
+        assert_eq!(batch.masks()[path >> 3], (1, 0));
+        assert_eq!(batch.values()[path >> 3][0], 3);
+
+        // clears are kept as clears, even for a chunk on the negative side
+        let (batch, leftover) = Batch::<i32>::from_world_edits(IVec3::new(-1, 0, 0), 8, &edits);
+        assert_eq!(batch.size(), 1);
+        assert_eq!(leftover.len(), 4);
+
+        let (batch, _) = Batch::<i32>::from_world_edits(IVec3::new(0, 0, 1), 8, &edits);
+        assert_eq!(batch.masks()[0], (0, 1));
+    }
+
+    #[test]
+    fn test_iter_set_and_clear_follow_masks() {
+        let size = 8;
+
+        let mut batch = Batch::<i32>::new(MaxDepth::new(3));
+        batch.stamp_sphere(IVec3::new(3, 4, 4), 2, 6);
+        batch.just_set(IVec3::new(3, 4, 4), 0);
+        batch.just_set(IVec3::ZERO, 0);
+
+        let set = batch.iter_set().collect::<Vec<_>>();
+        let cleared = batch.iter_clear().collect::<Vec<_>>();
+
+        let mut morton_order = set.clone();
+        morton_order.sort_by_key(|(position, _)| encode_child_index_path(position));
+        assert_eq!(set, morton_order);
+
+        let mut set_count = 0;
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let position = IVec3::new(x, y, z);
+                    let path = encode_child_index_path(&position) as usize;
+                    let (set_mask, clear_mask) = batch.masks()[path >> 3];
+                    let bit = 1 << (path & 7);
+
+                    let in_set = set.iter().any(|(p, _)| *p == position);
+                    assert_eq!(in_set, set_mask & bit != 0, "{position}");
+                    assert_eq!(cleared.contains(&position), clear_mask & bit != 0);
+                    set_count += usize::from(in_set);
+                }
+            }
+        }
+
+        // 33 voxels within the radius, minus the cleared center
+        assert_eq!(set_count, 32);
+        assert!(set.iter().all(|(_, value)| *value == 6));
+        assert_eq!(cleared, vec![IVec3::ZERO, IVec3::new(3, 4, 4)]);
+
+        // a fill is not enumerated
+        batch.just_fill(3);
+        assert_eq!(batch.iter_set().count(), 0);
+        assert_eq!(batch.iter_clear().count(), 0);
+    }
+
+    #[test]
+    fn test_stamp_clips_to_bounds() {
+        let max_depth = MaxDepth::new(3);
+        let size = 8;
+
+        let mut batch = Batch::<i32>::new(max_depth);
+        batch.stamp_sphere(IVec3::new(1, 6, 3), 4, 2);
+
+        let mut expected = Batch::<i32>::new(max_depth);
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let position = IVec3::new(x, y, z);
+                    if (position - IVec3::new(1, 6, 3)).length_squared() <= 16 {
+                        expected.just_set(position, 2);
+                    }
+                }
+            }
+        }
+        assert_eq!(batch.masks(), expected.masks());
+        assert_eq!(batch.values(), expected.values());
+
+        // a shell leaves the inside untouched
+        let mut shell = Batch::<i32>::new(max_depth);
+        shell.stamp_sphere_hollow(IVec3::splat(4), 3, 2, 1);
+        let is_set = |batch: &Batch<i32>, position: IVec3| {
+            let full_path = encode_child_index_path(&position);
+            batch.masks()[(full_path >> 3) as usize].0 & (1 << (full_path & 0b111)) != 0
+        };
+        assert!(!is_set(&shell, IVec3::splat(4)));
+        assert!(!is_set(&shell, IVec3::new(6, 4, 4)));
+        assert!(is_set(&shell, IVec3::new(7, 4, 4)));
+        assert!(is_set(&shell, IVec3::new(4, 1, 4)));
+
+        // stamping the default value records clears
+        let mut boxed = Batch::<i32>::new(max_depth);
+        boxed.stamp_box(IVec3::new(6, -3, 9), IVec3::new(10, 1, 5), 0);
+        let set_count = boxed
+            .masks()
+            .iter()
+            .map(|(set, _)| set.count_ones())
+            .sum::<u32>();
+        let clear_count = boxed
+            .masks()
+            .iter()
+            .map(|(_, clear)| clear.count_ones())
+            .sum::<u32>();
+        assert_eq!(set_count, 0);
+        assert_eq!(clear_count, 2 * 2 * 3);
+
+        // entirely outside records nothing
+        let mut outside = Batch::<i32>::new(max_depth);
+        outside.stamp_box(IVec3::splat(8), IVec3::splat(12), 1);
+        outside.stamp_sphere(IVec3::splat(-5), 3, 1);
+        assert!(!outside.has_patches());
+    }
+
+    #[test]
+    fn test_stamp_cylinder_and_capsule_volumes() {
+        use std::f64::consts::PI;
+
+        let max_depth = MaxDepth::new(5);
+        let (radius, height) = (6, 12);
+
+        let set_count = |batch: &Batch<i32>| {
+            batch
+                .masks()
+                .iter()
+                .map(|(set, _)| set.count_ones())
+                .sum::<u32>() as f64
+        };
+
+        let cylinder_volume = PI * (radius * radius * height) as f64;
+        // a cylinder of `height - 1` between the centers of the two half spheres
+        let capsule_volume = PI * (radius * radius * (height - 1)) as f64
+            + 4.0 / 3.0 * PI * (radius * radius * radius) as f64;
+
+        let mut counts = Vec::new();
+
+        for axis in [
+            IVec3::X,
+            IVec3::Y,
+            IVec3::Z,
+            IVec3::NEG_X,
+            IVec3::NEG_Y,
+            IVec3::NEG_Z,
+        ] {
+            let base = IVec3::splat(16) - axis * height / 2;
+
+            let mut cylinder = Batch::<i32>::new(max_depth);
+            cylinder.stamp_cylinder(base, axis, radius, height, 1);
+            let cylinder_count = set_count(&cylinder);
+            assert!((cylinder_count - cylinder_volume).abs() / cylinder_volume < 0.05);
+
+            let mut capsule = Batch::<i32>::new(max_depth);
+            capsule.stamp_capsule(base, axis, radius, height, 1);
+            let capsule_count = set_count(&capsule);
+            assert!((capsule_count - capsule_volume).abs() / capsule_volume < 0.05);
+
+            counts.push((cylinder_count, capsule_count));
+        }
+
+        // the same shape along every axis
+        assert!(counts.iter().all(|count| *count == counts[0]));
+
+        // a capsule of one voxel is a sphere
+        let mut capsule = Batch::<i32>::new(max_depth);
+        capsule.stamp_capsule(IVec3::splat(16), IVec3::Z, 4, 1, 1);
+        let mut sphere = Batch::<i32>::new(max_depth);
+        sphere.stamp_sphere(IVec3::splat(16), 4, 1);
+        assert_eq!(capsule.masks(), sphere.masks());
+
+        // clipped to the bounds, the cylinder starts at the base and only covers `height` voxels
+        let mut clipped = Batch::<i32>::new(max_depth);
+        clipped.stamp_cylinder(IVec3::new(0, 2, 0), IVec3::Y, 3, 4, 1);
+        let quarter = (0..=3)
+            .flat_map(|x| (0..=3).map(move |z| x * x + z * z))
+            .filter(|&distance_squared| distance_squared <= 9)
+            .count();
+        assert_eq!(set_count(&clipped), (quarter * 4) as f64);
+    }
+
+    #[test]
+    fn test_diff_reproduces_other_batch() {
+        use crate::spatial::{VoxOpsBatch, VoxTree};
+
+        let max_depth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // voxels after applying `batches` in order to a tree with a voxel set outside of them
+        let mut apply = |batches: &[&Batch<i32>]| {
+            let mut tree = VoxTree::new(max_depth);
+            for batch in batches {
+                tree.apply_batch(&mut interner, batch);
+            }
+            let voxels = to_vec(&interner, &tree.get_root_id(), max_depth);
+            tree.clear(&mut interner);
+            voxels
+        };
+
+        let mut original = Batch::<i32>::new(max_depth);
+        original.stamp_sphere(IVec3::splat(4), 3, 1);
+        original.stamp_box(IVec3::new(0, 0, 6), IVec3::new(2, 1, 7), 0);
+
+        let mut modified = original.clone();
+        modified.just_set(IVec3::splat(4), 2);
+        modified.just_set(IVec3::new(4, 4, 7), 0);
+        modified.just_set(IVec3::new(7, 0, 0), 3);
+        modified.just_set(IVec3::new(0, 0, 6), 5);
+        // set to the value it already has
+        modified.just_set(IVec3::new(4, 4, 5), 1);
+
+        let diff = original.diff(&modified);
+        assert_eq!(diff.to_fill(), None);
+        assert_eq!(diff.size(), 4);
+        assert_eq!(apply(&[&original, &diff]), apply(&[&modified]));
+        assert!(!original.diff(&original).has_patches());
+
+        // a fill added to the modified batch replaces everything but its own patches
+        let mut filled = Batch::<i32>::new(max_depth);
+        filled.just_fill(7);
+        filled.just_set(IVec3::new(1, 2, 3), 0);
+        filled.just_set(IVec3::new(3, 2, 1), 8);
+
+        let diff = original.diff(&filled);
+        assert_eq!(diff.to_fill(), Some(7));
+        assert_eq!(diff.size(), 2);
+        assert_eq!(apply(&[&original, &diff]), apply(&[&filled]));
+
+        // and removing it clears everything that is not recorded
+        let diff = filled.diff(&original);
+        assert_eq!(diff.to_fill(), Some(0));
+        assert_eq!(apply(&[&filled, &diff]), apply(&[&original]));
+
+        // the same fill only records the cells that differ
+        let mut refilled = filled.clone();
+        refilled.just_set(IVec3::new(1, 2, 3), 7);
+        let diff = filled.diff(&refilled);
+        assert_eq!(diff.to_fill(), None);
+        assert_eq!(diff.size(), 1);
+        assert_eq!(apply(&[&filled, &diff]), apply(&[&refilled]));
+
+        assert!(interner.patterns_empty());
+    }
+}
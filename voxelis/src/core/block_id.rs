@@ -93,6 +93,7 @@ const INDEX_MASK: u64 = 0xFFFF_FFFF;
 /// ```
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockId(u64);
 
 impl From<BlockId> for u64 {
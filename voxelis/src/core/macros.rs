@@ -0,0 +1,26 @@
+//! Module `core::macros`
+//!
+//! Internal consistency assertions for the hot paths.
+//!
+//! These are checked in debug builds and in builds with the `strict` feature enabled,
+//! and compiled out of release builds otherwise. User-facing validation (like position
+//! bounds) does not use them.
+
+/// Asserts an internal invariant, see the [module docs](self).
+macro_rules! strict_assert {
+    ($($arg:tt)*) => {
+        #[cfg(any(debug_assertions, feature = "strict"))]
+        assert!($($arg)*);
+    };
+}
+
+/// Asserts two expressions are not equal, see the [module docs](self).
+macro_rules! strict_assert_ne {
+    ($($arg:tt)*) => {
+        #[cfg(any(debug_assertions, feature = "strict"))]
+        assert_ne!($($arg)*);
+    };
+}
+
+pub(crate) use strict_assert;
+pub(crate) use strict_assert_ne;
@@ -31,6 +31,11 @@ use super::Lod;
 /// ```
 #[repr(transparent)]
 #[derive(Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "u8", try_from = "u8")
+)]
 pub struct MaxDepth(u8);
 
 impl From<MaxDepth> for u8 {
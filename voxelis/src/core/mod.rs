@@ -1,7 +1,11 @@
 mod batch;
 mod block_id;
 mod lod;
+mod macros;
 mod max_depth;
+mod occupied;
+mod octant_transform;
+mod orientation;
 mod traversal_depth;
 mod voxel;
 
@@ -9,5 +13,10 @@ pub use batch::Batch;
 pub use block_id::BlockId;
 pub use lod::Lod;
 pub use max_depth::MaxDepth;
+pub use occupied::Occupied;
+pub use octant_transform::OctantTransform;
+pub use orientation::Orientation;
 pub use traversal_depth::TraversalDepth;
 pub use voxel::VoxelTrait;
+
+pub(crate) use macros::{strict_assert, strict_assert_ne};
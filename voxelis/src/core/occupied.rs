@@ -0,0 +1,165 @@
+//! Module `core::occupied`
+//!
+//! Defines the [`Occupied`] voxel type, for worlds that only track whether a voxel is set.
+//!
+//! # Usage
+//!
+//! [`Occupied`] has a single non-default state, so every set voxel shares the same leaf and
+//! deduplication is maximal. This keeps occupancy-only data, like collision maps, as small as
+//! possible.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use voxelis::Occupied;
+//!
+//! assert!(Occupied::SET.is_set());
+//! assert_eq!(Occupied::default(), Occupied::EMPTY);
+//! ```
+
+use super::{VoxelTrait, voxel::ByteConversion};
+
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Occupied(bool);
+
+impl Occupied {
+    /// Empty voxel, the default state.
+    pub const EMPTY: Self = Self(false);
+
+    /// Set voxel, the only non-default state.
+    pub const SET: Self = Self(true);
+
+    /// Returns `true` if the voxel is set.
+    #[must_use]
+    #[inline(always)]
+    pub const fn is_set(&self) -> bool {
+        self.0
+    }
+}
+
+impl From<bool> for Occupied {
+    #[inline(always)]
+    fn from(value: bool) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Occupied> for bool {
+    #[inline(always)]
+    fn from(value: Occupied) -> Self {
+        value.0
+    }
+}
+
+/// Display implementation for [`Occupied`], `1` for set and `0` for empty voxels
+impl std::fmt::Display for Occupied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", u8::from(self.0))
+    }
+}
+
+impl ByteConversion for Occupied {
+    type ByteArray = [u8; 1];
+
+    #[inline(always)]
+    fn to_be_bytes(&self) -> Self::ByteArray {
+        [self.0 as u8]
+    }
+
+    #[inline(always)]
+    fn to_le_bytes(&self) -> Self::ByteArray {
+        [self.0 as u8]
+    }
+
+    #[inline(always)]
+    fn from_be_bytes(bytes: Self::ByteArray) -> Self {
+        Self(bytes[0] != 0)
+    }
+
+    #[inline(always)]
+    fn from_le_bytes(bytes: Self::ByteArray) -> Self {
+        Self(bytes[0] != 0)
+    }
+}
+
+impl VoxelTrait for Occupied {
+    #[inline(always)]
+    fn material_id(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, Vec3};
+
+    use crate::{
+        Lod, MaxDepth, VoxInterner,
+        spatial::{VoxOpsMesh, VoxOpsRead, VoxOpsWrite},
+        utils::mesh::MeshData,
+        world::VoxChunk,
+    };
+
+    use super::*;
+
+    fn set_sphere<T: VoxelTrait>(
+        interner: &mut VoxInterner<T>,
+        chunk: &mut VoxChunk<T>,
+        value: impl Fn(IVec3) -> T,
+    ) {
+        let center = IVec3::splat(8);
+
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let position = IVec3::new(x, y, z);
+                    if (position - center).length_squared() <= 36 {
+                        chunk.set(interner, position, value(position));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_round_trip() {
+        for value in [Occupied::EMPTY, Occupied::SET] {
+            assert_eq!(Occupied::from_be_bytes(value.to_be_bytes()), value);
+            assert_eq!(Occupied::from_le_bytes(value.to_le_bytes()), value);
+        }
+    }
+
+    #[test]
+    fn test_occupied_sphere() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(4);
+
+        let mut interner = VoxInterner::<Occupied>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(16.0), MAX_DEPTH, 0, 0, 0);
+        set_sphere(&mut interner, &mut chunk, |_| Occupied::SET);
+
+        // every set voxel shares a single leaf
+        assert_eq!(interner.leaf_patterns().len(), 1);
+        assert_eq!(chunk.get(&interner, IVec3::splat(8)), Some(Occupied::SET));
+        assert_eq!(chunk.get(&interner, IVec3::ZERO), None);
+
+        let mut materials = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut material_chunk = VoxChunk::with_position(Vec3::splat(16.0), MAX_DEPTH, 0, 0, 0);
+        set_sphere(&mut materials, &mut material_chunk, |_| 1);
+
+        let mut mesh_data = MeshData::default();
+        chunk.generate_greedy_mesh_arrays(&interner, &mut mesh_data, Vec3::ZERO, Lod::new(0));
+
+        let mut material_mesh_data = MeshData::default();
+        material_chunk.generate_greedy_mesh_arrays(
+            &materials,
+            &mut material_mesh_data,
+            Vec3::ZERO,
+            Lod::new(0),
+        );
+
+        assert!(!mesh_data.indices.is_empty());
+        assert_eq!(mesh_data.vertices, material_mesh_data.vertices);
+        assert_eq!(mesh_data.indices, material_mesh_data.indices);
+    }
+}
@@ -0,0 +1,254 @@
+//! Module `core::octant_transform`
+//!
+//! Defines the [`OctantTransform`] struct, one of the 48 axis permutations with optional sign
+//! flips of a voxel grid, i.e. the 24 axis-aligned rotations and their mirror images.
+//!
+//! # Usage
+//!
+//! A transform moves every voxel to another position of the same cube, so it can be applied to
+//! an octree by swapping child slots at every level, see [`crate::spatial::VoxTree::transform`].
+//! Rotations can be created from an [`Orientation`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use glam::IVec3;
+//! use voxelis::{OctantTransform, Orientation};
+//!
+//! let mirror = OctantTransform::new([0, 1, 2], [true, false, false]);
+//! assert_eq!(mirror.transform_in_cube(IVec3::new(1, 2, 3), 4), IVec3::new(2, 2, 3));
+//! assert!(mirror.is_mirror());
+//!
+//! let rotation = OctantTransform::from(Orientation::from_quarter_turns(0, 1, 0));
+//! assert!(!rotation.is_mirror());
+//! assert_eq!(OctantTransform::all().len(), OctantTransform::COUNT);
+//! ```
+
+use glam::IVec3;
+
+use super::Orientation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OctantTransform {
+    // input axis read by each output axis
+    permutation: [u8; 3],
+    // bit per output axis, reversed along that axis when set
+    flips: u8,
+}
+
+impl Default for OctantTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<Orientation> for OctantTransform {
+    fn from(orientation: Orientation) -> Self {
+        let mut permutation = [0; 3];
+        let mut flips = [false; 3];
+
+        for (input, axis) in [IVec3::X, IVec3::Y, IVec3::Z].into_iter().enumerate() {
+            let image = orientation.rotate(axis);
+            let output = (0..3).find(|&i| image[i] != 0).unwrap_or_default();
+
+            permutation[output] = input;
+            flips[output] = image[output] < 0;
+        }
+
+        Self::new(permutation, flips)
+    }
+}
+
+impl OctantTransform {
+    /// Number of distinct axis permutations with sign flips.
+    pub const COUNT: usize = 48;
+
+    /// Transform that leaves every position unchanged.
+    pub const IDENTITY: Self = Self {
+        permutation: [0, 1, 2],
+        flips: 0,
+    };
+
+    /// Creates a transform where output axis `i` takes the coordinate of input axis
+    /// `permutation[i]`, reversed when `flips[i]` is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `permutation` is not a permutation of `[0, 1, 2]`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::OctantTransform;
+    ///
+    /// let swap = OctantTransform::new([2, 1, 0], [false; 3]);
+    /// assert_eq!(swap.transform_in_cube(IVec3::new(1, 2, 3), 4), IVec3::new(3, 2, 1));
+    /// ```
+    #[must_use]
+    pub fn new(permutation: [usize; 3], flips: [bool; 3]) -> Self {
+        let mut seen = [false; 3];
+        for &axis in &permutation {
+            assert!(
+                axis < 3 && !seen[axis],
+                "Invalid axis permutation: {permutation:?}"
+            );
+            seen[axis] = true;
+        }
+
+        Self {
+            permutation: permutation.map(|axis| axis as u8),
+            flips: flips[0] as u8 | ((flips[1] as u8) << 1) | ((flips[2] as u8) << 2),
+        }
+    }
+
+    /// Returns all 48 transforms, starting with [`OctantTransform::IDENTITY`].
+    #[must_use]
+    pub fn all() -> [Self; Self::COUNT] {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("OctantTransform::all");
+
+        const PERMUTATIONS: [[u8; 3]; 6] = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+
+        let mut transforms = [Self::IDENTITY; Self::COUNT];
+
+        for (i, transform) in transforms.iter_mut().enumerate() {
+            *transform = Self {
+                permutation: PERMUTATIONS[i / 8],
+                flips: (i % 8) as u8,
+            };
+        }
+
+        transforms
+    }
+
+    /// Returns `true` if the transform mirrors the grid, i.e. it is not a rotation.
+    #[must_use]
+    pub fn is_mirror(&self) -> bool {
+        // a single swap of two axes and every flip each mirror the grid once
+        let odd_permutation = matches!(self.permutation, [0, 2, 1] | [1, 0, 2] | [2, 1, 0]);
+
+        odd_permutation ^ (self.flips.count_ones() % 2 == 1)
+    }
+
+    /// Transforms a position inside a cube of `size` voxels per axis, so the result stays
+    /// inside the same `[0, size)` cube.
+    #[must_use]
+    #[inline(always)]
+    pub fn transform_in_cube(&self, position: IVec3, size: i32) -> IVec3 {
+        IVec3::from_array(std::array::from_fn(|axis| {
+            let value = position[self.permutation[axis] as usize];
+
+            if self.flips & (1 << axis) != 0 {
+                size - 1 - value
+            } else {
+                value
+            }
+        }))
+    }
+
+    /// Returns the child slot a child at `index` moves to, see
+    /// [`crate::utils::common::octant_index`] for the child order.
+    ///
+    /// The same mapping applies at every level of an octree.
+    #[must_use]
+    #[inline(always)]
+    pub const fn child_index(&self, index: usize) -> usize {
+        let mut result = 0;
+        let mut axis = 0;
+
+        while axis < 3 {
+            let bit = ((index >> self.permutation[axis]) ^ (self.flips as usize >> axis)) & 1;
+            result |= bit << axis;
+            axis += 1;
+        }
+
+        result
+    }
+
+    /// Returns the transform that undoes this one.
+    #[must_use]
+    pub fn inverse(&self) -> Self {
+        let mut permutation = [0; 3];
+        let mut flips = 0;
+
+        for (output, &input) in self.permutation.iter().enumerate() {
+            permutation[input as usize] = output as u8;
+            flips |= ((self.flips >> output) & 1) << input;
+        }
+
+        Self { permutation, flips }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_all_distinct() {
+        let all = OctantTransform::all();
+        let unique = all.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), OctantTransform::COUNT);
+        assert_eq!(all[0], OctantTransform::IDENTITY);
+        assert_eq!(all.iter().filter(|t| t.is_mirror()).count(), 24);
+    }
+
+    #[test]
+    fn test_from_orientation_matches_rotate_in_cube() {
+        const SIZE: i32 = 4;
+
+        for orientation in Orientation::all() {
+            let transform = OctantTransform::from(orientation);
+            assert!(!transform.is_mirror());
+
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    for x in 0..SIZE {
+                        let position = IVec3::new(x, y, z);
+                        assert_eq!(
+                            transform.transform_in_cube(position, SIZE),
+                            orientation.rotate_in_cube(position, SIZE)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_child_index_matches_transform_in_cube() {
+        for transform in OctantTransform::all() {
+            for index in 0..8 {
+                let position = IVec3::new(
+                    index as i32 & 1,
+                    (index as i32 >> 1) & 1,
+                    (index as i32 >> 2) & 1,
+                );
+                let moved = transform.transform_in_cube(position, 2);
+                let expected = (moved.x | (moved.y << 1) | (moved.z << 2)) as usize;
+
+                assert_eq!(transform.child_index(index), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        let position = IVec3::new(0, 1, 3);
+
+        for transform in OctantTransform::all() {
+            let moved = transform.transform_in_cube(position, 4);
+            assert_eq!(transform.inverse().transform_in_cube(moved, 4), position);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Module `core::orientation`
+//!
+//! Defines the [`Orientation`] struct, one of the 24 axis-aligned rotations of a voxel grid.
+//!
+//! # Usage
+//!
+//! Orientations are used to rotate prefabs and batches in 90° steps, which keeps every voxel
+//! on the grid. An orientation is stored as the images of the `X`, `Y` and `Z` unit axes.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use glam::IVec3;
+//! use voxelis::Orientation;
+//!
+//! let orientation = Orientation::from_quarter_turns(0, 1, 0);
+//! assert_eq!(orientation.rotate(IVec3::X), IVec3::NEG_Z);
+//! assert_eq!(Orientation::all().len(), Orientation::COUNT);
+//! ```
+
+use glam::{IVec3, Quat, Vec3};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Orientation {
+    x_axis: IVec3,
+    y_axis: IVec3,
+    z_axis: IVec3,
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Orientation {
+    /// Number of distinct axis-aligned orientations.
+    pub const COUNT: usize = 24;
+
+    /// Orientation that leaves every position unchanged.
+    pub const IDENTITY: Self = Self {
+        x_axis: IVec3::X,
+        y_axis: IVec3::Y,
+        z_axis: IVec3::Z,
+    };
+
+    /// Returns all 24 axis-aligned orientations, starting with [`Orientation::IDENTITY`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use voxelis::Orientation;
+    ///
+    /// let all = Orientation::all();
+    /// assert_eq!(all[0], Orientation::IDENTITY);
+    /// ```
+    #[must_use]
+    pub fn all() -> [Self; Self::COUNT] {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Orientation::all");
+
+        const AXES: [IVec3; 6] = [
+            IVec3::X,
+            IVec3::Y,
+            IVec3::Z,
+            IVec3::NEG_X,
+            IVec3::NEG_Y,
+            IVec3::NEG_Z,
+        ];
+
+        let mut orientations = [Self::IDENTITY; Self::COUNT];
+        let mut count = 0;
+
+        for x_axis in AXES {
+            for y_axis in AXES {
+                if x_axis.dot(y_axis) != 0 {
+                    continue;
+                }
+
+                // right-handed basis, so the rotation has no mirroring
+                orientations[count] = Self {
+                    x_axis,
+                    y_axis,
+                    z_axis: x_axis.cross(y_axis),
+                };
+                count += 1;
+            }
+        }
+
+        orientations
+    }
+
+    /// Creates an orientation from quarter turns around the `X`, `Y` and `Z` axes,
+    /// applied in that order.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::Orientation;
+    ///
+    /// let orientation = Orientation::from_quarter_turns(0, 0, 1);
+    /// assert_eq!(orientation.rotate(IVec3::X), IVec3::Y);
+    /// ```
+    #[must_use]
+    pub fn from_quarter_turns(x: i32, y: i32, z: i32) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Orientation::from_quarter_turns");
+
+        let quarter = std::f32::consts::FRAC_PI_2;
+        let rotation = Quat::from_rotation_z(z as f32 * quarter)
+            * Quat::from_rotation_y(y as f32 * quarter)
+            * Quat::from_rotation_x(x as f32 * quarter);
+
+        Self::from_quat(rotation).expect("Quarter turns are always axis-aligned")
+    }
+
+    /// Snaps a rotation to an axis-aligned orientation.
+    /// Returns `None` if the rotation is not a multiple of 90° around the principal axes.
+    #[must_use]
+    pub fn from_quat(rotation: Quat) -> Option<Self> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("Orientation::from_quat");
+
+        const EPSILON: f32 = 1e-3;
+
+        let snap = |axis: Vec3| -> Option<IVec3> {
+            let rotated = rotation * axis;
+            let snapped = rotated.round();
+
+            if (rotated - snapped).abs().max_element() > EPSILON
+                || snapped.abs().element_sum() != 1.0
+            {
+                None
+            } else {
+                Some(snapped.as_ivec3())
+            }
+        };
+
+        Some(Self {
+            x_axis: snap(Vec3::X)?,
+            y_axis: snap(Vec3::Y)?,
+            z_axis: snap(Vec3::Z)?,
+        })
+    }
+
+    /// Rotates a position around the origin.
+    #[must_use]
+    #[inline(always)]
+    pub fn rotate(&self, position: IVec3) -> IVec3 {
+        self.x_axis * position.x + self.y_axis * position.y + self.z_axis * position.z
+    }
+
+    /// Rotates a position inside a cube of `size` voxels per axis, so the result stays
+    /// inside the same `[0, size)` cube.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use glam::IVec3;
+    /// use voxelis::Orientation;
+    ///
+    /// let orientation = Orientation::from_quarter_turns(0, 0, 1);
+    /// assert_eq!(orientation.rotate_in_cube(IVec3::new(1, 0, 0), 4), IVec3::new(3, 1, 0));
+    /// ```
+    #[must_use]
+    #[inline(always)]
+    pub fn rotate_in_cube(&self, position: IVec3, size: i32) -> IVec3 {
+        let corner = self.rotate(IVec3::splat(size - 1));
+        let offset = (-corner).max(IVec3::ZERO);
+
+        self.rotate(position) + offset
+    }
+
+    /// Returns the orientation that undoes this one.
+    #[must_use]
+    pub fn inverse(&self) -> Self {
+        // inverse of an orthonormal matrix is its transpose
+        Self {
+            x_axis: IVec3::new(self.x_axis.x, self.y_axis.x, self.z_axis.x),
+            y_axis: IVec3::new(self.x_axis.y, self.y_axis.y, self.z_axis.y),
+            z_axis: IVec3::new(self.x_axis.z, self.y_axis.z, self.z_axis.z),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_all_distinct() {
+        let all = Orientation::all();
+        let unique = all.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), Orientation::COUNT);
+    }
+
+    #[test]
+    fn test_identity() {
+        let position = IVec3::new(1, 2, 3);
+        assert_eq!(Orientation::IDENTITY.rotate(position), position);
+        assert_eq!(Orientation::default(), Orientation::IDENTITY);
+    }
+
+    #[test]
+    fn test_quarter_turns() {
+        let orientation = Orientation::from_quarter_turns(0, 1, 0);
+        assert_eq!(orientation.rotate(IVec3::X), IVec3::NEG_Z);
+        assert_eq!(orientation.rotate(IVec3::Y), IVec3::Y);
+
+        let full_turn = Orientation::from_quarter_turns(4, 0, 0);
+        assert_eq!(full_turn, Orientation::IDENTITY);
+    }
+
+    #[test]
+    fn test_from_quat_rejects_non_axis_aligned() {
+        assert!(Orientation::from_quat(Quat::from_rotation_y(0.3)).is_none());
+    }
+
+    #[test]
+    fn test_rotate_in_cube_stays_inside() {
+        const SIZE: i32 = 4;
+
+        for orientation in Orientation::all() {
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    for x in 0..SIZE {
+                        let position = IVec3::new(x, y, z);
+                        let rotated = orientation.rotate_in_cube(position, SIZE);
+
+                        assert!(rotated.cmpge(IVec3::ZERO).all());
+                        assert!(rotated.cmplt(IVec3::splat(SIZE)).all());
+                        assert_eq!(
+                            orientation.inverse().rotate(orientation.rotate(position)),
+                            position
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -40,6 +40,17 @@ pub trait VoxelTrait:
         calc_average(children)
     }
 
+    /// Returns `true` if both values should be treated as the same voxel.
+    ///
+    /// Every value comparison made when writing voxels goes through this method: no-change
+    /// detection, deciding whether a value clears a voxel, collapsing uniform octants and
+    /// leaf deduplication. Float-backed types can override it to accept near-equal values
+    /// or to treat `NaN` as empty; values that compare equal here must also hash equally.
+    #[inline(always)]
+    fn voxel_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+
     fn material_id(&self) -> usize;
 }
 
@@ -104,7 +115,7 @@ where
     for &c in children {
         let mut i = 0;
         while i < unique {
-            if values[i] == c {
+            if values[i].voxel_eq(&c) {
                 counts[i] += 1;
                 break;
             }
@@ -128,8 +139,8 @@ where
 
     for i in 1..unique {
         let cnt = counts[i];
-        let is_default_i = values[i] == default;
-        let is_default_max = values[max_i] == default;
+        let is_default_i = values[i].voxel_eq(&default);
+        let is_default_max = values[max_i].voxel_eq(&default);
 
         if cnt > max_cnt || (cnt == max_cnt && is_default_max && !is_default_i) {
             max_cnt = cnt;
@@ -139,3 +150,127 @@ where
 
     values[max_i]
 }
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hasher;
+
+    use glam::IVec3;
+
+    use crate::{
+        MaxDepth, VoxInterner,
+        spatial::{VoxOpsBulkWrite, VoxOpsRead, VoxOpsState, VoxOpsWrite, VoxTree},
+    };
+
+    use super::*;
+
+    // Densities closer than `STEP` are the same voxel, `NaN` and near-zero are empty
+    #[derive(Debug, Default, Clone, Copy)]
+    struct Density(f32);
+
+    impl Density {
+        const STEP: f32 = 1e-3;
+
+        fn key(&self) -> i64 {
+            if self.0.is_nan() {
+                0
+            } else {
+                (self.0 / Self::STEP).round() as i64
+            }
+        }
+    }
+
+    impl PartialEq for Density {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.to_bits() == other.0.to_bits()
+        }
+    }
+
+    impl Eq for Density {}
+
+    impl PartialOrd for Density {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Density {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.0.total_cmp(&other.0)
+        }
+    }
+
+    impl Hash for Density {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            state.write_i64(self.key());
+        }
+    }
+
+    impl Display for Density {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl ByteConversion for Density {
+        type ByteArray = [u8; 4];
+
+        fn to_be_bytes(&self) -> Self::ByteArray {
+            self.0.to_be_bytes()
+        }
+
+        fn to_le_bytes(&self) -> Self::ByteArray {
+            self.0.to_le_bytes()
+        }
+
+        fn from_be_bytes(bytes: Self::ByteArray) -> Self {
+            Self(f32::from_be_bytes(bytes))
+        }
+
+        fn from_le_bytes(bytes: Self::ByteArray) -> Self {
+            Self(f32::from_le_bytes(bytes))
+        }
+    }
+
+    impl VoxelTrait for Density {
+        fn voxel_eq(&self, other: &Self) -> bool {
+            self.key() == other.key()
+        }
+
+        fn material_id(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn test_tolerant_voxel_eq() {
+        let mut interner = VoxInterner::<Density>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(2));
+
+        assert!(tree.set(&mut interner, IVec3::ZERO, Density(1.0)));
+        assert!(!tree.set(&mut interner, IVec3::ZERO, Density(1.0 + 1e-5)));
+        assert_eq!(tree.get(&interner, IVec3::ZERO), Some(Density(1.0)));
+
+        // near-equal values share one leaf, so the octant collapses
+        for i in 1..8 {
+            let position = IVec3::new(i & 1, (i >> 1) & 1, (i >> 2) & 1);
+            tree.set(&mut interner, position, Density(1.0 + i as f32 * 1e-5));
+        }
+        assert_eq!(interner.leaf_patterns().len(), 1);
+        assert!(interner.get_child_id(&tree.get_root_id(), 0).is_leaf());
+
+        assert_eq!(
+            Density::average(&[Density(2.0), Density(1.0), Density(1.0 + 1e-5)]),
+            Density(1.0)
+        );
+
+        // NaN clears the voxel instead of being stored
+        assert!(!tree.set(&mut interner, IVec3::splat(3), Density(f32::NAN)));
+        assert!(tree.set(&mut interner, IVec3::ZERO, Density(f32::NAN)));
+        assert_eq!(tree.get(&interner, IVec3::ZERO), None);
+        assert_eq!(tree.get(&interner, IVec3::X), Some(Density(1.0)));
+
+        tree.clear(&mut interner);
+        assert!(tree.is_empty());
+    }
+}
@@ -52,7 +52,10 @@ pub fn compute_empty_branch_hash() -> u64 {
 
 #[inline(always)]
 pub fn compute_leaf_hash_for_value<T: VoxelTrait>(value: &T) -> u64 {
-    debug_assert!(*value != T::default(), "Leaf value should not be default");
+    debug_assert!(
+        !value.voxel_eq(&T::default()),
+        "Leaf value should not be default"
+    );
 
     let mut hasher = FxHasher::default();
 
@@ -15,7 +15,11 @@ macro_rules! get_next_index_macro {
             }
 
             index
-        } else if $self.next_index < $self.capacity as u32 {
+        } else {
+            if $self.next_index as usize >= $self.capacity {
+                $crate::grow_node_pools_macro!($self);
+            }
+
             let index = $self.next_index;
 
             #[cfg(feature = "debug_trace_ref_counts")]
@@ -34,8 +38,31 @@ macro_rules! get_next_index_macro {
             }
 
             index
-        } else {
-            panic!("Out of memory");
+        }
+    }};
+}
+
+// Doubles the node pools, capped at the maximum capacity. Only touches the pools, so it can
+// run while a patterns entry is borrowed.
+#[macro_export]
+macro_rules! grow_node_pools_macro {
+    ($self:expr) => {{
+        assert!($self.capacity < $self.max_capacity, "Out of memory");
+
+        let new_capacity = $self.capacity.saturating_mul(2).min($self.max_capacity);
+
+        $self.ref_counts.grow(new_capacity);
+        $self.generations.grow(new_capacity);
+        $self.children.grow(new_capacity);
+        $self.values.grow(new_capacity);
+        $self.hashes.grow(new_capacity);
+
+        $self.capacity = new_capacity;
+
+        #[cfg(feature = "memory_stats")]
+        {
+            $self.stats.nodes_capacity = new_capacity;
+            $self.stats.actual_budget = new_capacity * Self::node_size();
         }
     }};
 }
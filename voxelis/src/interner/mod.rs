@@ -1,5 +1,6 @@
 use std::collections::{HashMap, hash_map::Entry};
 
+use rustc_hash::FxHashMap;
 use voxelis_memory::PoolAllocatorLite;
 
 use crate::{BlockId, VoxelTrait, get_next_index_macro};
@@ -13,7 +14,7 @@ mod stats;
 pub use consts::*;
 pub use hash::PatternsHashmap;
 #[cfg(feature = "memory_stats")]
-pub use stats::InternerStats;
+pub use stats::{BatchStats, InternerStats};
 
 use hash::{
     IdentityHasherBuilder, compute_branch_hash_for_children, compute_empty_branch_hash,
@@ -32,6 +33,7 @@ pub struct VoxInterner<T> {
     values: PoolAllocatorLite<T>,
     hashes: PoolAllocatorLite<u64>,
     capacity: usize,
+    max_capacity: usize,
     empty_branch_id: BlockId,
     empty_branch_hash: u64,
     dec_ref_rec_stack: Vec<BlockId>,
@@ -42,6 +44,10 @@ pub struct VoxInterner<T> {
 impl<T: VoxelTrait> VoxInterner<T> {
     const INITIAL_CAPACITY: usize = 16384; // 43ms
 
+    /// Nodes [`VoxInterner::memory_budget_for_voxels`] adds to every estimate, so small models
+    /// don't start from a pool they outgrow after a few edits.
+    pub const MIN_EXPECTED_NODES: usize = 4096;
+
     pub fn with_memory_budget(requested_budget: usize) -> Self {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("VoxInterner::with_memory_budget");
@@ -126,6 +132,7 @@ impl<T: VoxelTrait> VoxInterner<T> {
             leaf_cache_hits: 0,
             leaf_cache_misses: 0,
             collapsed_branches: 0,
+            cloned_subtrees: 0,
             leaf_nodes: 0,
             branch_nodes: 1,
             max_alive_nodes: 0,
@@ -146,6 +153,7 @@ impl<T: VoxelTrait> VoxInterner<T> {
             hashes,
             patterns: [branch_patterns, leafs_patterns],
             capacity: nodes_capacity,
+            max_capacity: nodes_capacity,
             empty_branch_id,
             empty_branch_hash,
             dec_ref_rec_stack,
@@ -154,6 +162,68 @@ impl<T: VoxelTrait> VoxInterner<T> {
         }
     }
 
+    /// Creates an interner sized for `initial_budget` that grows its node pool when it runs
+    /// out of nodes instead of panicking, doubling it each time up to `max_budget`.
+    ///
+    /// Existing [`BlockId`]s stay valid across growth. Running out of nodes at `max_budget`
+    /// still panics.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_budget` is smaller than `initial_budget`, or either is too small or too
+    /// large for a node pool, like [`VoxInterner::with_memory_budget`].
+    pub fn with_growable_budget(initial_budget: usize, max_budget: usize) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxInterner::with_growable_budget");
+
+        assert!(
+            max_budget >= initial_budget,
+            "Maximum budget must not be smaller than the initial budget"
+        );
+
+        let max_capacity = max_budget / Self::node_size();
+        assert!(
+            max_capacity < u32::MAX as usize,
+            "Maximum budget is too large"
+        );
+
+        let mut interner = Self::with_memory_budget(initial_budget);
+        interner.max_capacity = max_capacity;
+
+        interner
+    }
+
+    /// Creates an interner sized for a model of about `count` voxels, using the estimate of
+    /// [`VoxInterner::memory_budget_for_voxels`].
+    ///
+    /// The estimate assumes the voxels form a surface, like a voxelized mesh. Callers knowing
+    /// better, e.g. for solid or noisy models, can scale the estimate and pass it to
+    /// [`VoxInterner::with_memory_budget`] or [`VoxInterner::with_growable_budget`] instead.
+    pub fn for_expected_voxels(count: usize) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxInterner::for_expected_voxels");
+
+        Self::with_memory_budget(Self::memory_budget_for_voxels(count))
+    }
+
+    /// Returns the memory budget of a node pool expected to hold a surface of `count` voxels.
+    ///
+    /// Without any sharing, a surface keeps about a quarter of the nodes at every level above
+    /// its voxels, so about a third of `count` branches. Identical subtrees, like flat or
+    /// regularly curved patches, are interned once, which cuts that by another 3 to 5 times on
+    /// typical meshes. The estimate takes a quarter of `count`, leaving headroom for the nodes
+    /// copied while editing, plus [`VoxInterner::MIN_EXPECTED_NODES`].
+    pub const fn memory_budget_for_voxels(count: usize) -> usize {
+        let nodes = count / 4 + Self::MIN_EXPECTED_NODES;
+        let nodes = if nodes < u32::MAX as usize - 1 {
+            nodes
+        } else {
+            u32::MAX as usize - 1
+        };
+
+        nodes * Self::node_size()
+    }
+
     #[inline(always)]
     pub const fn node_size() -> usize {
         PoolAllocatorLite::<u32>::block_size() + // ref_count
@@ -163,6 +233,36 @@ impl<T: VoxelTrait> VoxInterner<T> {
         PoolAllocatorLite::<u64>::block_size() // hash
     }
 
+    /// Memory budget the node pool is currently sized for, rounded down to whole nodes.
+    #[inline(always)]
+    pub fn memory_budget(&self) -> usize {
+        self.capacity * Self::node_size()
+    }
+
+    /// Number of nodes currently allocated, including the reserved empty branch.
+    ///
+    /// Always available and `O(1)`, unlike `stats()` which needs the `memory_stats` feature.
+    /// Together with [`VoxInterner::pool_capacity`] it gives the pool utilization, e.g. to log
+    /// memory pressure every frame.
+    #[inline(always)]
+    pub fn live_node_count(&self) -> usize {
+        self.next_index as usize - self.free_indices.len()
+    }
+
+    /// Number of nodes the pool can currently hold, set by the memory budget. Only changes
+    /// for interners created with [`VoxInterner::with_growable_budget`].
+    #[inline(always)]
+    pub fn pool_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of nodes the pool can grow to, equal to [`VoxInterner::pool_capacity`] unless
+    /// the interner was created with [`VoxInterner::with_growable_budget`].
+    #[inline(always)]
+    pub fn max_pool_capacity(&self) -> usize {
+        self.max_capacity
+    }
+
     #[inline(always)]
     pub fn get_value(&self, block_id: &BlockId) -> &T {
         debug_assert!(
@@ -582,22 +682,7 @@ impl<T: VoxelTrait> VoxInterner<T> {
         *self.children.get_mut(block_index) = EMPTY_CHILD;
         *self.hashes.get_mut(block_index) = 0;
         *self.ref_counts.get_mut(block_index) = 0;
-        let generation = self.generations.get_mut(block_index);
-        *generation += 1;
-
-        if *generation >= BlockId::MAX_GENERATION {
-            *generation = 0;
-
-            #[cfg(feature = "memory_stats")]
-            {
-                self.stats.generations_overflows += 1;
-            }
-        }
-
-        #[cfg(feature = "memory_stats")]
-        {
-            self.stats.max_generation = self.stats.max_generation.max(*generation as usize);
-        }
+        self.bump_generation(block_index);
 
         debug_assert!(
             !self.free_indices.contains(&block_index),
@@ -624,8 +709,258 @@ impl<T: VoxelTrait> VoxInterner<T> {
         println!("  Node recycled");
     }
 
+    // Invalidates every id still pointing at the slot
+    fn bump_generation(&mut self, index: u32) {
+        let generation = self.generations.get_mut(index);
+        *generation += 1;
+
+        if *generation >= BlockId::MAX_GENERATION {
+            *generation = 0;
+
+            #[cfg(feature = "memory_stats")]
+            {
+                self.stats.generations_overflows += 1;
+            }
+        }
+
+        #[cfg(feature = "memory_stats")]
+        {
+            self.stats.max_generation = self.stats.max_generation.max(*generation as usize);
+        }
+    }
+
+    /// Frees every node not reachable from `roots` and moves the live nodes to the front of
+    /// the pool, so it is contiguous again.
+    ///
+    /// `roots` must list every reference held outside the interner, once per holder, e.g.
+    /// the root of every tree sharing this interner. Reference counts are rebuilt from them,
+    /// which also reclaims nodes kept alive by unbalanced references.
+    ///
+    /// Returns the new id of every live node, keyed by its old id. Live nodes may move, so
+    /// ids held outside the interner must be replaced, e.g. with `VoxTree::remap_root`.
+    pub fn compact(&mut self, roots: &[BlockId]) -> FxHashMap<BlockId, BlockId> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxInterner::compact");
+
+        let end = self.next_index;
+
+        // mark in post-order, so children get their new ids before their parents
+        let mut visited = vec![false; end as usize];
+        let mut order = Vec::new();
+        let mut stack = Vec::with_capacity(PREALLOCATED_STACK_SIZE);
+
+        for root in roots.iter().filter(|root| !root.is_empty()) {
+            debug_assert!(self.is_valid_block_id(root), "Invalid root id: {root:?}");
+
+            stack.push((*root, false));
+
+            while let Some((node_id, expanded)) = stack.pop() {
+                if expanded {
+                    order.push(node_id);
+                    continue;
+                }
+
+                if visited[node_id.index() as usize] {
+                    continue;
+                }
+                visited[node_id.index() as usize] = true;
+
+                stack.push((node_id, true));
+
+                if node_id.is_branch() {
+                    for child_id in self.children.get(node_id.index()).iter() {
+                        if !child_id.is_empty() && !visited[child_id.index() as usize] {
+                            stack.push((*child_id, false));
+                        }
+                    }
+                }
+            }
+        }
+
+        // nodes created outside of the patterns, e.g. by `create_branch`, are not interned again
+        let live = order
+            .iter()
+            .map(|node_id| {
+                let index = node_id.index();
+                let patterns = &self.patterns[node_id.is_leaf() as usize];
+                let interned = patterns.get(self.hashes.get(index)) == Some(node_id);
+
+                (
+                    *node_id,
+                    *self.children.get(index),
+                    *self.values.get(index),
+                    interned,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        #[cfg(feature = "memory_stats")]
+        let freed = (end as usize - 1 - self.free_indices.len()) - live.len();
+
+        for index in 1..end {
+            let kept = live
+                .get(index as usize - 1)
+                .is_some_and(|(node_id, ..)| node_id.index() == index);
+
+            *self.values.get_mut(index) = T::default();
+            *self.children.get_mut(index) = EMPTY_CHILD;
+            *self.hashes.get_mut(index) = 0;
+            *self.ref_counts.get_mut(index) = 0;
+
+            if !kept {
+                self.bump_generation(index);
+            }
+        }
+
+        self.free_indices.clear();
+        self.next_index = live.len() as u32 + 1;
+
+        for patterns in self.patterns.iter_mut() {
+            patterns.clear();
+        }
+        self.patterns[PATTERNS_TYPE_BRANCH].insert(self.empty_branch_hash, self.empty_branch_id);
+
+        let mut remap = FxHashMap::with_capacity_and_hasher(live.len(), Default::default());
+
+        #[cfg(feature = "memory_stats")]
+        let mut interned_nodes = 0;
+
+        for (new_index, (old_id, children, value, interned)) in live.iter().enumerate() {
+            let index = new_index as u32 + 1;
+            let generation = *self.generations.get(index);
+
+            let (new_id, children) = if old_id.is_leaf() {
+                (BlockId::new_leaf(index, generation), *children)
+            } else {
+                let children = children.map(|child_id| {
+                    if child_id.is_empty() {
+                        child_id
+                    } else {
+                        remap[&child_id]
+                    }
+                });
+
+                (
+                    BlockId::new_branch(index, generation, old_id.types(), old_id.mask()),
+                    children,
+                )
+            };
+
+            for child_id in children.iter().filter(|child_id| !child_id.is_empty()) {
+                *self.ref_counts.get_mut(child_id.index()) += 1;
+            }
+
+            // branch hashes are built from the ids of their children, so they change too
+            if *interned {
+                let hash = if new_id.is_leaf() {
+                    compute_leaf_hash_for_value(value)
+                } else {
+                    compute_branch_hash_for_children(&children, new_id.types(), new_id.mask())
+                };
+
+                *self.hashes.get_mut(index) = hash;
+                self.patterns[new_id.is_leaf() as usize].insert(hash, new_id);
+
+                #[cfg(feature = "memory_stats")]
+                {
+                    interned_nodes += 1;
+                }
+            }
+
+            *self.children.get_mut(index) = children;
+            *self.values.get_mut(index) = *value;
+
+            remap.insert(*old_id, new_id);
+        }
+
+        for root in roots.iter().filter(|root| !root.is_empty()) {
+            *self.ref_counts.get_mut(remap[root].index()) += 1;
+        }
+
+        #[cfg(feature = "memory_stats")]
+        {
+            let leaf_nodes = live
+                .iter()
+                .filter(|(node_id, ..)| node_id.is_leaf())
+                .count();
+
+            self.stats.total_deallocations += freed;
+            self.stats.recycled_nodes = 0;
+            self.stats.allocated_nodes = live.len() + 1;
+            self.stats.alive_nodes = live.len() + 1;
+            self.stats.leaf_nodes = leaf_nodes;
+            self.stats.branch_nodes = live.len() - leaf_nodes + 1;
+            self.stats.patterns = interned_nodes + 1;
+        }
+
+        remap
+    }
+
+    /// Imports the nodes reachable from `roots` in `other` and returns the ids of the roots in
+    /// this interner, in the same order, e.g. to combine chunks built on separate threads
+    /// without going through a file.
+    ///
+    /// Nodes are interned like newly built ones, so subtrees equal to nodes already in this
+    /// interner, or shared between the roots, are stored once. Every returned root holds one
+    /// reference, owned by the caller, like the ids returned by
+    /// [`VoxInterner::get_or_create_branch`]. `other` is left untouched.
+    pub fn merge_from(&mut self, other: &VoxInterner<T>, roots: &[BlockId]) -> Vec<BlockId> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxInterner::merge_from");
+
+        let mut imported = FxHashMap::default();
+
+        roots
+            .iter()
+            .map(|root| {
+                if root.is_empty() {
+                    BlockId::EMPTY
+                } else {
+                    self.import_node(other, root, &mut imported)
+                }
+            })
+            .collect()
+    }
+
+    // Returns the id of `node_id` of `other` in this interner with one reference owned by the
+    // caller. `imported` maps nodes already imported, each of them held by a parent or root.
+    fn import_node(
+        &mut self,
+        other: &VoxInterner<T>,
+        node_id: &BlockId,
+        imported: &mut FxHashMap<BlockId, BlockId>,
+    ) -> BlockId {
+        if let Some(block_id) = imported.get(node_id) {
+            let block_id = *block_id;
+            self.inc_ref(&block_id);
+            return block_id;
+        }
+
+        let block_id = if node_id.is_leaf() {
+            self.get_or_create_leaf(*other.get_value(node_id))
+        } else {
+            // the references of the children are handed over to the branch
+            let children = other.get_children(node_id).map(|child_id| {
+                if child_id.is_empty() {
+                    child_id
+                } else {
+                    self.import_node(other, &child_id, imported)
+                }
+            });
+
+            self.get_or_create_branch(children, node_id.types(), node_id.mask())
+        };
+
+        imported.insert(*node_id, block_id);
+
+        block_id
+    }
+
     pub fn get_or_create_leaf(&mut self, value: T) -> BlockId {
-        debug_assert_ne!(value, T::default(), "Leaf value should not be default");
+        debug_assert!(
+            !value.voxel_eq(&T::default()),
+            "Leaf value should not be default"
+        );
 
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("VoxInterner::get_or_create_leaf");
@@ -646,9 +981,8 @@ impl<T: VoxelTrait> VoxInterner<T> {
                         self.get_ref(&existing_id)
                     );
 
-                    debug_assert_eq!(
-                        self.values.get(existing_id.index()),
-                        &value,
+                    debug_assert!(
+                        self.values.get(existing_id.index()).voxel_eq(&value),
                         "Value mismatch for existing leaf node"
                     );
 
@@ -833,6 +1167,11 @@ impl<T: VoxelTrait> VoxInterner<T> {
         self.stats.collapsed_branches += 1;
     }
 
+    #[cfg(feature = "memory_stats")]
+    pub fn bump_cloned_subtrees(&mut self, count: usize) {
+        self.stats.cloned_subtrees += count;
+    }
+
     pub fn create_empty_branch(&mut self) -> BlockId {
         // Get new index
         let index = get_next_index_macro!(self);
@@ -939,7 +1278,10 @@ impl<T: VoxelTrait> VoxInterner<T> {
     }
 
     pub fn deserialize_leaf(&mut self, index: u32, value: T) -> BlockId {
-        debug_assert_ne!(value, T::default(), "Leaf value should not be default");
+        debug_assert!(
+            !value.voxel_eq(&T::default()),
+            "Leaf value should not be default"
+        );
 
         // Compute hash for the new node
         let hash = compute_leaf_hash_for_value(&value);
@@ -960,6 +1302,12 @@ impl<T: VoxelTrait> VoxInterner<T> {
         // Cache the new node
         self.patterns[PATTERNS_TYPE_LEAF].insert(hash, block_id);
 
+        #[cfg(feature = "memory_stats")]
+        {
+            self.stats.leaf_nodes += 1;
+            self.stats.patterns += 1;
+        }
+
         block_id
     }
 
@@ -989,6 +1337,12 @@ impl<T: VoxelTrait> VoxInterner<T> {
         // Cache the new node
         self.patterns[PATTERNS_TYPE_BRANCH].insert(hash, block_id);
 
+        #[cfg(feature = "memory_stats")]
+        {
+            self.stats.branch_nodes += 1;
+            self.stats.patterns += 1;
+        }
+
         self.inc_all_child_refs(&children);
     }
 
@@ -1023,6 +1377,15 @@ impl<T: VoxelTrait> VoxInterner<T> {
         self.capacity
     }
 
+    /// Indices the next new nodes get, in order, as long as no node is freed in between.
+    pub fn upcoming_indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.free_indices
+            .iter()
+            .rev()
+            .copied()
+            .chain(self.next_index..)
+    }
+
     pub fn patterns_empty(&self) -> bool {
         self.patterns[PATTERNS_TYPE_BRANCH].len() == 1
             && self.patterns[PATTERNS_TYPE_LEAF].is_empty()
@@ -1165,6 +1528,265 @@ impl<T: VoxelTrait> VoxInterner<T> {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{
+        MaxDepth,
+        spatial::{VoxOpsBulkWrite, VoxOpsRead, VoxOpsWrite, VoxTree},
+        utils::common::to_vec,
+    };
+
+    use super::*;
+
+    fn fill_tree(tree: &mut VoxTree<i32>, interner: &mut VoxInterner<i32>) {
+        for y in 0..8 {
+            for z in 0..8 {
+                for x in 0..8 {
+                    tree.set(interner, IVec3::new(x, y, z), (x + y * z) % 5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_live_node_count() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        // only the empty branch sentinel
+        assert_eq!(interner.live_node_count(), 1);
+        assert_eq!(
+            interner.pool_capacity(),
+            1024 * 1024 / VoxInterner::<i32>::node_size()
+        );
+
+        fill_tree(&mut tree, &mut interner);
+        let live = interner.live_node_count();
+        assert!(live > 1);
+
+        #[cfg(feature = "memory_stats")]
+        assert_eq!(live, interner.stats().alive_nodes);
+
+        // recycled slots stop counting
+        tree.set(&mut interner, IVec3::ZERO, 42);
+        tree.clear(&mut interner);
+        assert_eq!(interner.live_node_count(), 1);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_for_expected_voxels() {
+        let node_size = VoxInterner::<i32>::node_size();
+        let min_nodes = VoxInterner::<i32>::MIN_EXPECTED_NODES;
+
+        assert_eq!(
+            VoxInterner::<i32>::memory_budget_for_voxels(0),
+            min_nodes * node_size
+        );
+        assert_eq!(
+            VoxInterner::<i32>::memory_budget_for_voxels(usize::MAX),
+            (u32::MAX as usize - 1) * node_size
+        );
+
+        let interner = VoxInterner::<i32>::for_expected_voxels(400_000);
+        assert_eq!(interner.pool_capacity(), 100_000 + min_nodes);
+        assert_eq!(interner.max_pool_capacity(), interner.pool_capacity());
+    }
+
+    #[test]
+    fn test_growable_budget_keeps_block_ids() {
+        let node_size = VoxInterner::<i32>::node_size();
+        let mut interner = VoxInterner::<i32>::with_growable_budget(16 * node_size, 1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        assert_eq!(interner.pool_capacity(), 16);
+        assert_eq!(interner.max_pool_capacity(), 1024 * 1024 / node_size);
+
+        let value = |position: IVec3| 1 + position.x + position.y * 8 + position.z * 64;
+        let positions = (0..8)
+            .flat_map(|y| (0..8).flat_map(move |z| (0..8).map(move |x| IVec3::new(x, y, z))))
+            .collect::<Vec<_>>();
+
+        // set until the first growth, so some voxels are set before and some after it
+        let mut set = 0;
+        while interner.pool_capacity() == 16 {
+            tree.set(&mut interner, positions[set], value(positions[set]));
+            set += 1;
+        }
+        let root_before_growth = tree.get_root_id();
+        assert!(interner.is_valid_block_id(&root_before_growth));
+
+        for position in &positions[set..] {
+            tree.set(&mut interner, *position, value(*position));
+        }
+        assert!(interner.pool_capacity() > 32);
+
+        for position in &positions {
+            assert_eq!(tree.get(&interner, *position), Some(value(*position)));
+        }
+
+        tree.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Out of memory")]
+    fn test_growable_budget_out_of_memory() {
+        let node_size = VoxInterner::<i32>::node_size();
+        let mut interner = VoxInterner::<i32>::with_growable_budget(4 * node_size, 8 * node_size);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        fill_tree(&mut tree, &mut interner);
+    }
+
+    #[test]
+    fn test_merge_from_shares_common_subtrees() {
+        let max_depth = MaxDepth::new(3);
+
+        // both trees hold the same filled half and a voxel of their own
+        let build = |interner: &mut VoxInterner<i32>, position: IVec3, value: i32| {
+            let mut tree = VoxTree::new(max_depth);
+            tree.fill_region(interner, IVec3::ZERO, IVec3::new(3, 7, 7), 1);
+            tree.set(interner, position, value);
+            tree
+        };
+
+        let mut interner_a = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree_a = build(&mut interner_a, IVec3::new(6, 6, 6), 2);
+        let mut interner_b = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree_b = build(&mut interner_b, IVec3::new(7, 0, 0), 3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let roots_a = interner.merge_from(&interner_a, &[tree_a.get_root_id(), BlockId::EMPTY]);
+        let roots_b = interner.merge_from(&interner_b, &[tree_b.get_root_id()]);
+
+        assert_eq!(roots_a[1], BlockId::EMPTY);
+
+        // the empty branch sentinel is counted by every interner
+        let merged_nodes = interner.live_node_count() - 1;
+        let source_nodes = interner_a.live_node_count() - 1 + interner_b.live_node_count() - 1;
+        assert!(merged_nodes < source_nodes);
+
+        let mut merged = Vec::new();
+        for (root, (source, source_interner)) in [roots_a[0], roots_b[0]]
+            .into_iter()
+            .zip([(&tree_a, &interner_a), (&tree_b, &interner_b)])
+        {
+            assert_eq!(
+                to_vec(&interner, &root, max_depth),
+                to_vec(source_interner, &source.get_root_id(), max_depth)
+            );
+
+            // hand the reference of the merge over to the tree
+            let mut tree = VoxTree::new(max_depth);
+            tree.set_root_id(&mut interner, root);
+            interner.dec_ref(&root);
+            merged.push(tree);
+        }
+
+        assert_eq!(interner.get_ref(&merged[0].get_root_id()), 1);
+
+        // merging a tree already present shares its root
+        let again = interner.merge_from(&interner_b, &[tree_b.get_root_id()]);
+        assert_eq!(again[0], merged[1].get_root_id());
+        interner.dec_ref(&again[0]);
+
+        for tree in merged.iter_mut() {
+            tree.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
+
+        tree_a.clear(&mut interner_a);
+        tree_b.clear(&mut interner_b);
+        assert!(interner_a.patterns_empty());
+        assert!(interner_b.patterns_empty());
+    }
+
+    #[test]
+    fn test_compact_frees_unreachable_nodes() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        fill_tree(&mut tree, &mut interner);
+
+        // unbalanced reference, the subtree outlives the tree
+        let leaked_id = interner.get_children_ref(&tree.get_root_id())[0];
+        interner.inc_ref(&leaked_id);
+
+        tree.clear(&mut interner);
+        assert!(!interner.patterns_empty());
+
+        let remap = interner.compact(&[]);
+
+        assert!(remap.is_empty());
+        assert!(interner.patterns_empty());
+        assert!(!interner.is_valid_block_id(&leaked_id));
+
+        // the empty branch sentinel is always alive
+        #[cfg(feature = "memory_stats")]
+        {
+            let stats = interner.stats();
+            assert_eq!(stats.alive_nodes, 1);
+            assert_eq!(stats.leaf_nodes, 0);
+            assert_eq!(stats.branch_nodes, 1);
+            assert_eq!(stats.patterns, 1);
+        }
+    }
+
+    #[test]
+    fn test_compact_keeps_live_roots() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+        let mut garbage = VoxTree::new(MaxDepth::new(3));
+
+        // interleave the allocations, so the live nodes are scattered across the pool
+        for i in 0..8 {
+            garbage.set(&mut interner, IVec3::new(i, i, i), 100 + i);
+            tree.set(&mut interner, IVec3::new(i, 0, 7 - i), 1 + i % 3);
+        }
+        fill_tree(&mut garbage, &mut interner);
+        interner.inc_ref(&garbage.get_root_id());
+        garbage.clear(&mut interner);
+
+        let expected = to_vec(&interner, &tree.get_root_id(), MaxDepth::new(3));
+
+        let remap = interner.compact(&[tree.get_root_id()]);
+        tree.remap_root(&remap);
+
+        assert!(interner.is_valid_block_id(&tree.get_root_id()));
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        assert_eq!(
+            to_vec(&interner, &tree.get_root_id(), MaxDepth::new(3)),
+            expected
+        );
+
+        // live nodes fill the front of the pool
+        let mut indices = remap.values().map(|id| id.index()).collect::<Vec<_>>();
+        indices.sort_unstable();
+        assert_eq!(indices, (1..=remap.len() as u32).collect::<Vec<_>>());
+
+        // patterns are rebuilt, so equal subtrees are shared again
+        let mut copy = VoxTree::new(MaxDepth::new(3));
+        for i in 0..8 {
+            copy.set(&mut interner, IVec3::new(i, 0, 7 - i), 1 + i % 3);
+        }
+        assert_eq!(copy.get_root_id(), tree.get_root_id());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 2);
+
+        tree.set(&mut interner, IVec3::ZERO, 42);
+        assert_eq!(
+            to_vec(&interner, &copy.get_root_id(), MaxDepth::new(3)),
+            expected
+        );
+
+        tree.clear(&mut interner);
+        copy.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
@@ -17,6 +17,7 @@ pub struct InternerStats {
     pub leaf_cache_hits: usize,
     pub leaf_cache_misses: usize,
     pub collapsed_branches: usize,
+    pub cloned_subtrees: usize,
     pub leaf_nodes: usize,
     pub branch_nodes: usize,
     pub max_alive_nodes: usize,
@@ -26,3 +27,30 @@ pub struct InternerStats {
     pub max_generation: usize,
     pub generations_overflows: usize,
 }
+
+/// Interner activity caused by a single edit, e.g. one `apply_batch`.
+///
+/// Built from two [`InternerStats`] snapshots taken before and after the edit.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BatchStats {
+    /// Branches replaced by a single leaf because all 8 children were the same leaf.
+    pub collapsed_branches: usize,
+    /// Nodes that did not exist in the interner yet.
+    pub created_nodes: usize,
+    /// Nodes found already interned and shared instead of created.
+    pub deduped_nodes: usize,
+    /// Existing children reused, or split leaves copied, while integrating new branches.
+    pub cloned_subtrees: usize,
+}
+
+impl BatchStats {
+    /// Returns the difference between `before` and `after`.
+    pub fn between(before: &InternerStats, after: &InternerStats) -> Self {
+        Self {
+            collapsed_branches: after.collapsed_branches - before.collapsed_branches,
+            created_nodes: after.total_cache_misses - before.total_cache_misses,
+            deduped_nodes: after.total_cache_hits - before.total_cache_hits,
+            cloned_subtrees: after.cloned_subtrees - before.cloned_subtrees,
+        }
+    }
+}
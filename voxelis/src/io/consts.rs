@@ -1,6 +1,21 @@
-pub const VTM_VERSION: u16 = 0x0100;
+pub const VTM_VERSION: u16 = 0x0104;
 pub const VTM_MAGIC: [u8; 12] = *b"VoxTreeModel";
 pub const VTC_MAGIC: [u8; 12] = *b"VoxTreeChunk";
 
+// Every VTM version from the oldest readable one up to VTM_VERSION can be imported. Each of
+// the following is the first version with the named change to the layout.
+pub const VTM_MIN_VERSION: u16 = 0x0100;
+pub const VTM_VERSION_CHUNK_INDEX: u16 = 0x0101;
+pub const VTM_VERSION_OWNERS: u16 = 0x0102;
+pub const VTM_VERSION_CHUNK_SIZE_XYZ: u16 = 0x0103;
+pub const VTM_VERSION_PALETTE: u16 = 0x0104;
+
+pub const VTR_VERSION: u16 = 0x0102;
+pub const VTR_MAGIC: [u8; 12] = *b"VoxTreeRegn\0";
+
+// Same as for VTM, every VTR version from VTR_MIN_VERSION up to VTR_VERSION can be read.
+pub const VTR_MIN_VERSION: u16 = 0x0101;
+pub const VTR_VERSION_OWNERS: u16 = 0x0102;
+
 pub const RESERVED_1: u32 = 0;
 pub const RESERVED_2: u32 = 0;
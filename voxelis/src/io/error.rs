@@ -0,0 +1,68 @@
+use glam::IVec3;
+
+#[derive(Debug)]
+pub enum VtmError {
+    Io(std::io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    InvalidFlags(u16),
+    ChecksumMismatch,
+    ChunkOutOfBounds {
+        position: IVec3,
+        offset: u32,
+        length: u32,
+    },
+    ChunkChecksumMismatch(IVec3),
+    /// Malformed node, or a reference to a node index that is not in the node table.
+    InvalidNode(u32),
+    /// Stored voxel values don't have the size of the value type they are read as.
+    ValueSizeMismatch {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl std::fmt::Display for VtmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::InvalidMagic => write!(f, "not a VTM file"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported VTM version: {version:#06X}")
+            }
+            Self::InvalidFlags(flags) => write!(f, "invalid VTM flags: {flags:#06X}"),
+            Self::ChecksumMismatch => write!(f, "node data checksum mismatch"),
+            Self::ChunkOutOfBounds {
+                position,
+                offset,
+                length,
+            } => write!(
+                f,
+                "chunk {position} record out of bounds (offset: {offset}, length: {length})"
+            ),
+            Self::ChunkChecksumMismatch(position) => {
+                write!(f, "chunk {position} checksum mismatch")
+            }
+            Self::InvalidNode(index) => write!(f, "invalid node {index}"),
+            Self::ValueSizeMismatch { expected, found } => write!(
+                f,
+                "voxel value size mismatch (expected: {expected} bytes, found: {found} bytes)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VtmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VtmError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
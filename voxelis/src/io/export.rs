@@ -1,13 +1,24 @@
 use std::{io::Write, path::Path};
 
 use byteorder::{BigEndian, WriteBytesExt};
+use glam::{IVec3, Vec3};
 use md5::{Digest, Md5};
+use rustc_hash::FxHashMap;
 
 use crate::{
-    Lod, VoxelTrait,
-    spatial::{VoxOpsConfig, VoxOpsMesh, VoxOpsSpatial3D, VoxOpsState},
-    utils::mesh::MeshData,
-    world::VoxModel,
+    Lod, VoxInterner, VoxelTrait,
+    spatial::{VoxOpsChunkConfig, VoxOpsConfig, VoxOpsMesh, VoxOpsState},
+    utils::{common::count_occupied_voxels, mesh::MeshData},
+    world::{VoxChunk, VoxModel},
+};
+
+#[cfg(feature = "gltf")]
+use gltf::json::{self, validation::Checked::Valid, validation::USize64};
+
+#[cfg(feature = "gltf")]
+use crate::{
+    spatial::VoxOpsSpatial3D,
+    utils::{common::to_vec, mesh::generate_greedy_mesh_arrays_dense_by_value},
 };
 
 use super::{
@@ -15,21 +26,47 @@ use super::{
     consts::{RESERVED_1, RESERVED_2, VTM_MAGIC, VTM_VERSION},
 };
 
+/// Options of [`export_model_to_obj_with_options`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ObjExportOptions {
+    /// Shares the vertices at the same position within a chunk between the quads meeting
+    /// there, instead of writing four vertices for every quad. Normals are shared too and
+    /// referenced per face corner, so faces stay flat shaded.
+    ///
+    /// Quad corners lie on the voxel grid, so only vertices with bitwise equal positions are
+    /// welded and distinct corners are never merged.
+    pub weld_vertices: bool,
+}
+
+/// Exports the model to OBJ with [`ObjExportOptions::default`], see
+/// [`export_model_to_obj_with_options`].
 pub fn export_model_to_obj<T: VoxelTrait, P: AsRef<Path>>(
     name: String,
     path: &P,
     model: &VoxModel<T>,
     lod: Lod,
+) {
+    export_model_to_obj_with_options(name, path, model, lod, ObjExportOptions::default());
+}
+
+/// Exports the model to OBJ as a single object named `name`, greedy meshed at `lod`.
+pub fn export_model_to_obj_with_options<T: VoxelTrait, P: AsRef<Path>>(
+    name: String,
+    path: &P,
+    model: &VoxModel<T>,
+    lod: Lod,
+    options: ObjExportOptions,
 ) {
     #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("export_model_to_obj");
+    let _span = tracy_client::span!("export_model_to_obj_with_options");
 
     let mut mesh_data = MeshData::default();
+    let mut welded = WeldedMesh::default();
 
     let interner = model.get_interner();
     let interner = interner.read();
 
-    for (_, chunk) in model.chunks.iter() {
+    for (position, chunk) in model.chunks.iter() {
         if chunk.is_empty() {
             continue;
         }
@@ -37,11 +74,92 @@ pub fn export_model_to_obj<T: VoxelTrait, P: AsRef<Path>>(
         chunk.generate_greedy_mesh_arrays(
             &interner,
             &mut mesh_data,
-            chunk.world_position_3d(),
+            model.chunk_mesh_offset(*position),
             lod,
         );
+
+        if options.weld_vertices {
+            welded.append(&mesh_data);
+            mesh_data.clear();
+        }
+    }
+
+    if options.weld_vertices {
+        write_welded_obj(&name, path, &welded);
+    } else {
+        write_obj(&name, path, &mesh_data);
+    }
+}
+
+// Positions and normals of meshes welded by their exact value
+#[derive(Default)]
+struct WeldedMesh {
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    normal_ids: FxHashMap<[u32; 3], u32>,
+    // zero based vertex and normal index of every triangle corner
+    corners: Vec<(u32, u32)>,
+}
+
+impl WeldedMesh {
+    // Appends a mesh, welding its vertices with each other but not with those of the meshes
+    // appended before it
+    fn append(&mut self, mesh_data: &MeshData) {
+        let key = |vector: &Vec3| vector.to_array().map(f32::to_bits);
+
+        let mut vertex_ids: FxHashMap<[u32; 3], u32> = FxHashMap::default();
+        let mut remap = Vec::with_capacity(mesh_data.vertices.len());
+
+        for (vertex, normal) in mesh_data.vertices.iter().zip(&mesh_data.normals) {
+            let vertex_id = *vertex_ids.entry(key(vertex)).or_insert_with(|| {
+                self.vertices.push(*vertex);
+                self.vertices.len() as u32 - 1
+            });
+            let normal_id = *self.normal_ids.entry(key(normal)).or_insert_with(|| {
+                self.normals.push(*normal);
+                self.normals.len() as u32 - 1
+            });
+
+            remap.push((vertex_id, normal_id));
+        }
+
+        self.corners
+            .extend(mesh_data.indices.iter().map(|&index| remap[index as usize]));
     }
+}
+
+/// Exports a single chunk to OBJ, with vertices offset to the chunk's place in the world.
+///
+/// Meant for inspecting one chunk in isolation, e.g. when debugging the mesher.
+pub fn export_chunk_to_obj<T: VoxelTrait, P: AsRef<Path>>(
+    path: &P,
+    chunk: &VoxChunk<T>,
+    interner: &VoxInterner<T>,
+    chunk_position: IVec3,
+    chunk_world_size: Vec3,
+    lod: Lod,
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("export_chunk_to_obj");
+
+    let mut mesh_data = MeshData::default();
 
+    chunk.generate_greedy_mesh_arrays(
+        interner,
+        &mut mesh_data,
+        chunk_position.as_vec3() * chunk_world_size,
+        lod,
+    );
+
+    let name = format!(
+        "chunk_{}_{}_{}",
+        chunk_position.x, chunk_position.y, chunk_position.z
+    );
+
+    write_obj(&name, path, &mesh_data);
+}
+
+fn write_obj<P: AsRef<Path>>(name: &str, path: &P, mesh_data: &MeshData) {
     let obj_file = std::fs::File::create(path).unwrap();
     let mut writer = std::io::BufWriter::new(obj_file);
 
@@ -71,6 +189,268 @@ pub fn export_model_to_obj<T: VoxelTrait, P: AsRef<Path>>(
     }
 }
 
+fn write_welded_obj<P: AsRef<Path>>(name: &str, path: &P, mesh: &WeldedMesh) {
+    let obj_file = std::fs::File::create(path).unwrap();
+    let mut writer = std::io::BufWriter::new(obj_file);
+
+    writer.write_all(format!("o {name}\n").as_bytes()).unwrap();
+
+    for vertex in mesh.vertices.iter() {
+        writer
+            .write_fmt(format_args!("v {} {} {}\n", vertex.x, vertex.y, vertex.z))
+            .unwrap();
+    }
+
+    for normal in mesh.normals.iter() {
+        writer
+            .write_fmt(format_args!("vn {} {} {}\n", normal.x, normal.y, normal.z))
+            .unwrap();
+    }
+
+    for corners in mesh.corners.chunks(3) {
+        writer.write_all(b"f").unwrap();
+        for (vertex, normal) in corners {
+            writer
+                .write_fmt(format_args!(" {}//{}", vertex + 1, normal + 1))
+                .unwrap();
+        }
+        writer.write_all(b"\n").unwrap();
+    }
+}
+
+/// Exports a model to a binary glTF (`.glb`) file, greedy meshed at `lod`.
+///
+/// Every distinct voxel value gets its own material, with the base color of that value in
+/// the palette of the model, or white for values missing from it. `color_override` replaces
+/// the palette, e.g. to export a heat map of the values. All chunks are primitives of a single mesh, one per chunk and
+/// material, so each primitive stays far below the 32-bit index limit. The mesh is placed
+/// under one node named `name`.
+#[cfg(feature = "gltf")]
+pub fn export_model_to_gltf<T: VoxelTrait, P: AsRef<Path>>(
+    name: String,
+    path: &P,
+    model: &VoxModel<T>,
+    lod: Lod,
+    color_override: Option<&dyn Fn(T) -> [f32; 4]>,
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("export_model_to_gltf");
+
+    let interner = model.get_interner();
+    let interner = interner.read();
+
+    let palette = |value: T| model.palette.color(value).unwrap_or([1.0; 4]);
+    let color = color_override.unwrap_or(&palette);
+
+    let mut root = json::Root::default();
+    let mut bin = Vec::new();
+
+    let buffer = root.push(json::Buffer {
+        byte_length: USize64(0),
+        extensions: Default::default(),
+        extras: Default::default(),
+        name: None,
+        uri: None,
+    });
+
+    let mut materials: FxHashMap<T, json::Index<json::Material>> = FxHashMap::default();
+    let mut primitives = Vec::new();
+
+    // sorted, so the same model always exports the same file
+    let mut chunks = model.chunks.values().collect::<Vec<_>>();
+    chunks.sort_by_key(|chunk| {
+        let position = chunk.position_3d();
+        (position.x, position.y, position.z)
+    });
+
+    for chunk in chunks {
+        if chunk.is_empty() {
+            continue;
+        }
+
+        let voxels = to_vec(&interner, &chunk.get_root_id(), chunk.max_depth(lod));
+        let meshes = generate_greedy_mesh_arrays_dense_by_value(
+            &voxels,
+            glam::UVec3::splat(chunk.voxels_per_axis(lod)),
+            model.chunk_mesh_offset(chunk.position_3d()),
+            chunk.voxel_size(lod),
+        );
+
+        for (value, mesh_data) in meshes {
+            let material = *materials.entry(value).or_insert_with(|| {
+                root.push(json::Material {
+                    name: Some(format!("voxel_{value}")),
+                    pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+                        base_color_factor: json::material::PbrBaseColorFactor(color(value)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            });
+
+            primitives.push(gltf_primitive(
+                &mut root, &mut bin, buffer, &mesh_data, material,
+            ));
+        }
+    }
+
+    root.buffers[buffer.value()].byte_length = USize64::from(bin.len());
+
+    let mesh = (!primitives.is_empty()).then(|| {
+        root.push(json::Mesh {
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: Some(name.clone()),
+            primitives,
+            weights: None,
+        })
+    });
+
+    let node = root.push(json::Node {
+        mesh,
+        name: Some(name),
+        ..Default::default()
+    });
+
+    let scene = root.push(json::Scene {
+        extensions: Default::default(),
+        extras: Default::default(),
+        name: None,
+        nodes: vec![node],
+    });
+    root.scene = Some(scene);
+
+    let json = root.to_vec().unwrap();
+    let glb = gltf::binary::Glb {
+        header: gltf::binary::Header {
+            magic: *b"glTF",
+            version: 2,
+            // recomputed by the writer
+            length: 0,
+        },
+        json: json.into(),
+        bin: (!bin.is_empty()).then_some(bin.into()),
+    };
+
+    let glb_file = std::fs::File::create(path).unwrap();
+    glb.to_writer(std::io::BufWriter::new(glb_file)).unwrap();
+}
+
+// Appends the positions, normals and indices of a mesh to `bin` and returns the primitive
+// referencing them
+#[cfg(feature = "gltf")]
+fn gltf_primitive(
+    root: &mut json::Root,
+    bin: &mut Vec<u8>,
+    buffer: json::Index<json::Buffer>,
+    mesh_data: &MeshData,
+    material: json::Index<json::Material>,
+) -> json::mesh::Primitive {
+    let mut push_accessor =
+        |bytes: Vec<u8>,
+         count: usize,
+         type_: json::accessor::Type,
+         component_type: json::accessor::ComponentType,
+         target: json::buffer::Target,
+         bounds: Option<(json::Value, json::Value)>| {
+            let view = root.push(json::buffer::View {
+                buffer,
+                byte_length: USize64::from(bytes.len()),
+                byte_offset: Some(USize64::from(bin.len())),
+                byte_stride: None,
+                extensions: Default::default(),
+                extras: Default::default(),
+                name: None,
+                target: Some(Valid(target)),
+            });
+            // every component is 4 bytes, so views stay aligned without padding
+            bin.extend(bytes);
+
+            let (min, max) = bounds.unzip();
+
+            root.push(json::Accessor {
+                buffer_view: Some(view),
+                byte_offset: None,
+                count: USize64::from(count),
+                component_type: Valid(json::accessor::GenericComponentType(component_type)),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(type_),
+                min,
+                max,
+                name: None,
+                normalized: false,
+                sparse: None,
+            })
+        };
+
+    let vec3_bytes = |vectors: &[glam::Vec3]| {
+        vectors
+            .iter()
+            .flat_map(|vector| vector.to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect::<Vec<_>>()
+    };
+
+    // glTF requires the bounds of the positions
+    let min = mesh_data
+        .vertices
+        .iter()
+        .fold(glam::Vec3::MAX, |a, v| a.min(*v));
+    let max = mesh_data
+        .vertices
+        .iter()
+        .fold(glam::Vec3::MIN, |a, v| a.max(*v));
+
+    let positions = push_accessor(
+        vec3_bytes(&mesh_data.vertices),
+        mesh_data.vertices.len(),
+        json::accessor::Type::Vec3,
+        json::accessor::ComponentType::F32,
+        json::buffer::Target::ArrayBuffer,
+        Some((
+            json::Value::from(min.to_array().to_vec()),
+            json::Value::from(max.to_array().to_vec()),
+        )),
+    );
+
+    let normals = push_accessor(
+        vec3_bytes(&mesh_data.normals),
+        mesh_data.normals.len(),
+        json::accessor::Type::Vec3,
+        json::accessor::ComponentType::F32,
+        json::buffer::Target::ArrayBuffer,
+        None,
+    );
+
+    let indices = push_accessor(
+        mesh_data
+            .indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect(),
+        mesh_data.indices.len(),
+        json::accessor::Type::Scalar,
+        json::accessor::ComponentType::U32,
+        json::buffer::Target::ElementArrayBuffer,
+        None,
+    );
+
+    json::mesh::Primitive {
+        attributes: [
+            (Valid(json::mesh::Semantic::Positions), positions),
+            (Valid(json::mesh::Semantic::Normals), normals),
+        ]
+        .into(),
+        extensions: Default::default(),
+        extras: Default::default(),
+        indices: Some(indices),
+        material: Some(material),
+        mode: Valid(json::mesh::Mode::Triangles),
+        targets: None,
+    }
+}
+
 pub struct ByteSize(pub usize);
 
 impl std::fmt::Display for ByteSize {
@@ -87,31 +467,43 @@ impl std::fmt::Display for ByteSize {
     }
 }
 
+/// Exports the model to a VTM file with [`Flags::DEFAULT`], see [`export_model_to_vtm_with_flags`].
 pub fn export_model_to_vtm<T: VoxelTrait, P: AsRef<Path>>(
     name: String,
     path: &P,
     model: &VoxModel<T>,
+) {
+    export_model_to_vtm_with_flags(name, path, model, Flags::DEFAULT);
+}
+
+/// Exports the model to a VTM file with the given `flags` stored in the header.
+///
+/// With [`Flags::COMPRESSED`] the node data is zstd compressed. The header, the chunk index and
+/// the palette of the model are always written uncompressed, so they can be read without
+/// inflating the node data.
+pub fn export_model_to_vtm_with_flags<T: VoxelTrait, P: AsRef<Path>>(
+    name: String,
+    path: &P,
+    model: &VoxModel<T>,
+    flags: Flags,
 ) {
     #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("export_model_to_vtm");
+    let _span = tracy_client::span!("export_model_to_vtm_with_flags");
 
     print!("Exporting VTM model to {}", path.as_ref().display(),);
 
     let mut vox_file = std::fs::File::create(path).unwrap();
     let mut writer = std::io::BufWriter::new(&mut vox_file);
 
-    let flags = Flags::DEFAULT;
-    // let flags = Flags::NONE;
-
     let max_depth = model.max_depth(Lod::new(0));
 
     writer.write_all(&VTM_MAGIC).unwrap();
     writer.write_u16::<BigEndian>(VTM_VERSION).unwrap();
     writer.write_u16::<BigEndian>(flags.bits()).unwrap();
     writer.write_u8(max_depth.max()).unwrap();
-    writer
-        .write_f32::<BigEndian>(model.chunk_world_size)
-        .unwrap();
+    for size in model.chunk_world_size.to_array() {
+        writer.write_f32::<BigEndian>(size).unwrap();
+    }
     writer.write_u32::<BigEndian>(RESERVED_1).unwrap();
     writer.write_u32::<BigEndian>(RESERVED_2).unwrap();
 
@@ -123,8 +515,57 @@ pub fn export_model_to_vtm<T: VoxelTrait, P: AsRef<Path>>(
     writer.write_u8(name.len().try_into().unwrap()).unwrap();
     writer.write_all(name.as_bytes()).unwrap();
 
+    let value_size = std::mem::size_of::<T::ByteArray>();
+    writer.write_u8(value_size.try_into().unwrap()).unwrap();
+
     let mut data = Vec::new();
-    model.serialize(&mut data);
+    let chunk_ranges = model.serialize(&mut data);
+
+    // Chunk index, lets readers validate the file without deserializing nodes
+    {
+        let interner = model.get_interner();
+        let interner = interner.read();
+
+        writer
+            .write_u32::<BigEndian>(chunk_ranges.len().try_into().unwrap())
+            .unwrap();
+
+        for (position, range) in chunk_ranges {
+            let root_id = model.chunks[&position].get_root_id();
+            let voxels = count_occupied_voxels(&interner, &root_id, max_depth);
+            let crc = crc32fast::hash(&data[range.clone()]);
+
+            writer.write_i32::<BigEndian>(position.x).unwrap();
+            writer.write_i32::<BigEndian>(position.y).unwrap();
+            writer.write_i32::<BigEndian>(position.z).unwrap();
+            writer
+                .write_u32::<BigEndian>(range.start.try_into().unwrap())
+                .unwrap();
+            writer
+                .write_u32::<BigEndian>(range.len().try_into().unwrap())
+                .unwrap();
+            writer.write_u32::<BigEndian>(crc).unwrap();
+            writer.write_u64::<BigEndian>(voxels).unwrap();
+        }
+    }
+
+    // Palette, values are stored in `value_size` bytes like the nodes
+    writer
+        .write_u32::<BigEndian>(model.palette.len().try_into().unwrap())
+        .unwrap();
+
+    for (value, entry) in model.palette.iter() {
+        value.write_as_be(&mut writer).unwrap();
+        for channel in entry.color {
+            writer.write_f32::<BigEndian>(channel).unwrap();
+        }
+        writer.write_u32::<BigEndian>(entry.flags).unwrap();
+        // names have no length limit in memory, so the length takes the full u32
+        writer
+            .write_u32::<BigEndian>(entry.name.len().try_into().unwrap())
+            .unwrap();
+        writer.write_all(entry.name.as_bytes()).unwrap();
+    }
 
     let mut md5_hasher = Md5::new();
     md5_hasher.update(&data);
@@ -143,9 +584,247 @@ pub fn export_model_to_vtm<T: VoxelTrait, P: AsRef<Path>>(
     writer
         .write_u32::<BigEndian>(data.len().try_into().unwrap())
         .unwrap();
+    writer
+        .write_u32::<BigEndian>(crc32fast::hash(&data))
+        .unwrap();
     writer.write_all(&data).unwrap();
+    writer.flush().unwrap();
 
     let file_len = writer.get_ref().metadata().unwrap().len();
 
     println!(" ({})", ByteSize(file_len as usize));
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::{
+        MaxDepth,
+        spatial::{VoxOpsBulkWrite, VoxOpsWrite},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_export_chunk_to_obj() {
+        let path = std::env::temp_dir().join(format!("voxelis_chunk_{}.obj", std::process::id()));
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MaxDepth::new(3), 1, 0, 2);
+
+        // a 2x1x1 bar, greedy meshing merges it into a box of 6 quads
+        chunk.set(&mut interner, IVec3::new(0, 0, 0), 1);
+        chunk.set(&mut interner, IVec3::new(1, 0, 0), 1);
+
+        export_chunk_to_obj(
+            &path,
+            &chunk,
+            &interner,
+            IVec3::new(1, 0, 2),
+            Vec3::splat(8.0),
+            Lod::new(0),
+        );
+
+        let obj = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(obj.starts_with("o chunk_1_0_2\n"));
+
+        let vertices = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|line| {
+                let xyz = line
+                    .split_whitespace()
+                    .map(|value| value.parse::<f32>().unwrap())
+                    .collect::<Vec<_>>();
+                Vec3::new(xyz[0], xyz[1], xyz[2])
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(vertices.len(), 6 * 4);
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("vn ")).count(),
+            6 * 4
+        );
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            6 * 2
+        );
+
+        let min = vertices.iter().fold(Vec3::MAX, |a, v| a.min(*v));
+        let max = vertices.iter().fold(Vec3::MIN, |a, v| a.max(*v));
+        assert_eq!(min, Vec3::new(8.0, 0.0, 16.0));
+        assert_eq!(max, Vec3::new(10.0, 1.0, 17.0));
+
+        chunk.clear(&mut interner);
+    }
+
+    #[test]
+    fn test_export_model_to_obj_welds_vertices() {
+        let path = |name: &str| {
+            std::env::temp_dir().join(format!("voxelis_{name}_{}.obj", std::process::id()))
+        };
+
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+
+            // a flat 4x1x4 plane of alternating strips, meshed as 18 quads
+            let chunk = model.get_or_create_chunk(IVec3::ZERO);
+            for x in 0..4 {
+                for z in 0..4 {
+                    chunk.set(&mut interner, IVec3::new(x, 0, z), 1 + x % 2);
+                }
+            }
+        }
+
+        let export = |name: &str, weld_vertices: bool| {
+            let path = path(name);
+            export_model_to_obj_with_options(
+                "plane".to_string(),
+                &path,
+                &model,
+                Lod::new(0),
+                ObjExportOptions { weld_vertices },
+            );
+
+            let obj = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            obj
+        };
+        let count =
+            |obj: &str, prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+
+        let plain = export("plain", false);
+        let welded = export("welded", true);
+
+        assert_eq!(count(&plain, "v "), 18 * 4);
+        assert_eq!(count(&plain, "f "), 18 * 2);
+
+        // the corners of the strips on the top and bottom side, 5 along x and 2 along z
+        assert_eq!(count(&welded, "v "), 2 * 5 * 2);
+        assert_eq!(count(&welded, "vn "), 6);
+        assert_eq!(count(&welded, "f "), 18 * 2);
+
+        // every corner keeps its position and normal
+        let parse = |obj: &str, prefix: &str| {
+            obj.lines()
+                .filter_map(|line| line.strip_prefix(prefix))
+                .map(|line| {
+                    let xyz = line
+                        .split_whitespace()
+                        .map(|value| value.parse::<f32>().unwrap())
+                        .collect::<Vec<_>>();
+                    Vec3::new(xyz[0], xyz[1], xyz[2])
+                })
+                .collect::<Vec<_>>()
+        };
+        let (plain_vertices, plain_normals) = (parse(&plain, "v "), parse(&plain, "vn "));
+        let (welded_vertices, welded_normals) = (parse(&welded, "v "), parse(&welded, "vn "));
+
+        let plain_corners = plain
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .flat_map(|line| line.split_whitespace())
+            .map(|index| index.parse::<usize>().unwrap() - 1)
+            .map(|index| (plain_vertices[index], plain_normals[index]));
+        let welded_corners = welded
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .flat_map(|line| line.split_whitespace())
+            .map(|corner| {
+                let (vertex, normal) = corner.split_once("//").unwrap();
+                (
+                    welded_vertices[vertex.parse::<usize>().unwrap() - 1],
+                    welded_normals[normal.parse::<usize>().unwrap() - 1],
+                )
+            });
+        assert!(plain_corners.eq(welded_corners));
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[cfg(feature = "gltf")]
+    #[test]
+    fn test_export_model_to_gltf() {
+        let path = std::env::temp_dir().join(format!("voxelis_model_{}.glb", std::process::id()));
+
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+
+            // a 2x1x1 bar in one chunk, two touching voxels of different values in another
+            let chunk = model.get_or_create_chunk(IVec3::ZERO);
+            chunk.set(&mut interner, IVec3::new(0, 0, 0), 1);
+            chunk.set(&mut interner, IVec3::new(1, 0, 0), 1);
+
+            let chunk = model.get_or_create_chunk(IVec3::new(1, 0, 0));
+            chunk.set(&mut interner, IVec3::new(0, 0, 0), 1);
+            chunk.set(&mut interner, IVec3::new(0, 1, 0), 2);
+
+            model.get_or_create_chunk(IVec3::new(0, 1, 0));
+        }
+
+        let heat = |value: i32| [value as f32 / 2.0, 0.0, 0.0, 1.0];
+        export_model_to_gltf("model".to_string(), &path, &model, Lod::new(0), Some(&heat));
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let gltf = gltf::Gltf::from_slice(&bytes).unwrap();
+
+        assert_eq!(gltf.nodes().count(), 1);
+        assert_eq!(gltf.nodes().next().unwrap().name(), Some("model"));
+
+        let colors = gltf
+            .materials()
+            .map(|material| material.pbr_metallic_roughness().base_color_factor())
+            .collect::<Vec<_>>();
+        assert_eq!(colors, vec![[0.5, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0]]);
+
+        // one primitive per chunk and material
+        let mesh = gltf.meshes().next().unwrap();
+        assert_eq!(mesh.primitives().count(), 3);
+
+        // 6 quads of the bar, 5 quads of each voxel, the face they share is hidden
+        let triangles = mesh
+            .primitives()
+            .map(|primitive| primitive.indices().unwrap().count() / 3)
+            .sum::<usize>();
+        assert_eq!(triangles, (6 + 5 + 5) * 2);
+
+        // without an override the palette of the model is used, white for missing values
+        model.palette.insert(
+            2,
+            crate::world::PaletteEntry::new("stone", [0.5, 0.5, 0.5, 1.0]),
+        );
+        export_model_to_gltf("model".to_string(), &path, &model, Lod::new(0), None);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let gltf = gltf::Gltf::from_slice(&bytes).unwrap();
+        let colors = gltf
+            .materials()
+            .map(|material| material.pbr_metallic_roughness().base_color_factor())
+            .collect::<Vec<_>>();
+        assert_eq!(colors, vec![[1.0; 4], [0.5, 0.5, 0.5, 1.0]]);
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+}
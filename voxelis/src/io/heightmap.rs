@@ -0,0 +1,347 @@
+use std::{
+    io::{BufRead, BufReader, Seek},
+    path::Path,
+};
+
+use glam::{IVec3, Vec3};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Batch, Lod, MaxDepth,
+    spatial::{VoxOpsBatch, VoxOpsConfig},
+    world::VoxModel,
+};
+
+#[derive(Debug)]
+pub enum HeightmapError {
+    Io(std::io::Error),
+    Decode(png::DecodingError),
+    /// Only grayscale images, optionally with alpha, hold heights.
+    UnsupportedFormat(png::ColorType),
+}
+
+impl std::fmt::Display for HeightmapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::Decode(err) => write!(f, "PNG decoding error: {err}"),
+            Self::UnsupportedFormat(color_type) => {
+                write!(f, "unsupported heightmap color type: {color_type:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeightmapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for HeightmapError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for HeightmapError {
+    fn from(err: png::DecodingError) -> Self {
+        Self::Decode(err)
+    }
+}
+
+/// Imports a grayscale PNG heightmap as voxel terrain, see [`read_heightmap`].
+pub fn import_heightmap<P: AsRef<Path>>(
+    path: &P,
+    max_depth: MaxDepth,
+    chunk_world_size: Vec3,
+    max_height: u32,
+    value: i32,
+    fill_below: bool,
+    memory_budget: usize,
+) -> Result<VoxModel<i32>, HeightmapError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("import_heightmap");
+
+    let file = std::fs::File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    read_heightmap(
+        &mut reader,
+        max_depth,
+        chunk_world_size,
+        max_height,
+        value,
+        fill_below,
+        memory_budget,
+    )
+}
+
+/// Reads a grayscale PNG heightmap, 8 or 16 bits per sample, into a new model.
+///
+/// Pixel `(x, row)` becomes the column at `(x, row)` on the `XZ` plane, with the surface
+/// voxel at `y = round(sample / sample_max * max_height)`, so black pixels still get a
+/// surface at `y = 0`. With `fill_below` the whole column down to `y = 0` is set to `value`,
+/// otherwise only the surface voxel. Alpha is ignored.
+pub fn read_heightmap<R: BufRead + Seek>(
+    reader: &mut R,
+    max_depth: MaxDepth,
+    chunk_world_size: Vec3,
+    max_height: u32,
+    value: i32,
+    fill_below: bool,
+    memory_budget: usize,
+) -> Result<VoxModel<i32>, HeightmapError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("read_heightmap");
+
+    let mut decoder = png::Decoder::new(reader);
+    // low bit depths are widened to 8 bits, 16 bit samples are kept
+    decoder.set_transformations(png::Transformations::EXPAND);
+
+    let mut png_reader = decoder.read_info()?;
+
+    let (color_type, bit_depth) = png_reader.output_color_type();
+    let channels = match color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        _ => return Err(HeightmapError::UnsupportedFormat(color_type)),
+    };
+    let sample_bytes = if bit_depth == png::BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+
+    let mut pixels = vec![0; png_reader.output_buffer_size().unwrap_or_default()];
+    let info = png_reader.next_frame(&mut pixels)?;
+
+    let sample_max = (1u64 << (8 * sample_bytes)) - 1;
+
+    let mut model = VoxModel::empty(max_depth, chunk_world_size, memory_budget);
+
+    let voxels_per_axis = model.voxels_per_axis(Lod::new(0)) as i32;
+    let mut batches: FxHashMap<IVec3, Batch<i32>> = FxHashMap::default();
+
+    for row in 0..info.height as usize {
+        let line = &pixels[row * info.line_size..];
+
+        for x in 0..info.width as usize {
+            let offset = x * channels * sample_bytes;
+            let sample = if sample_bytes == 2 {
+                u16::from_be_bytes([line[offset], line[offset + 1]]) as u64
+            } else {
+                line[offset] as u64
+            };
+
+            let height = ((sample * max_height as u64 + sample_max / 2) / sample_max) as i32;
+
+            let top = IVec3::new(x as i32, height, row as i32);
+            let top_chunk = top.div_euclid(IVec3::splat(voxels_per_axis));
+            let bottom_chunk_y = if fill_below { 0 } else { top_chunk.y };
+
+            for chunk_y in bottom_chunk_y..=top_chunk.y {
+                let chunk_position = IVec3::new(top_chunk.x, chunk_y, top_chunk.z);
+                let local_top = top - chunk_position * voxels_per_axis;
+
+                let batch = batches
+                    .entry(chunk_position)
+                    .or_insert_with(|| Batch::new(max_depth));
+
+                if fill_below {
+                    // clipped to the chunk, so only the part of the column inside it is set
+                    let local_bottom =
+                        IVec3::new(local_top.x, -chunk_y * voxels_per_axis, local_top.z);
+                    batch.stamp_box(local_bottom, local_top, value);
+                } else {
+                    batch.just_set(local_top, value);
+                }
+            }
+        }
+    }
+
+    let interner = model.get_interner();
+    let mut interner = interner.write();
+
+    for (chunk_position, batch) in &batches {
+        model
+            .get_or_create_chunk(*chunk_position)
+            .apply_batch(&mut interner, batch);
+    }
+
+    drop(interner);
+
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::spatial::VoxOpsBulkWrite;
+
+    use super::*;
+
+    fn encode_png(width: u32, height: u32, bit_depth: png::BitDepth, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(bit_depth);
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+
+        bytes
+    }
+
+    // number of set voxels in every column, by row
+    fn column_heights(model: &VoxModel<i32>, width: i32, depth: i32) -> Vec<Vec<i32>> {
+        let interner = model.interner.read();
+        let lod = Lod::new(0);
+
+        (0..depth)
+            .map(|z| {
+                (0..width)
+                    .map(|x| {
+                        (0..64)
+                            .filter(|&y| {
+                                model
+                                    .get_world_voxel(&interner, IVec3::new(x, y, z), lod)
+                                    .is_some()
+                            })
+                            .count() as i32
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn clear_model(model: &mut VoxModel<i32>) {
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_gradient_heightmap_16_bit() {
+        // 12 columns rising left to right, a second row twice as high
+        let width = 12;
+        let samples = (0..width)
+            .map(|x| (x * 65535 / 22) as u16)
+            .chain((0..width).map(|x| (x * 65535 / 11) as u16))
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<_>>();
+        let png = encode_png(width as u32, 2, png::BitDepth::Sixteen, &samples);
+
+        let mut filled = read_heightmap(
+            &mut Cursor::new(&png),
+            MaxDepth::new(3),
+            Vec3::ONE,
+            22,
+            5,
+            true,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        // columns cross chunk borders on both the x and y axis
+        assert!(filled.chunks.contains_key(&IVec3::new(1, 2, 0)));
+
+        let heights = column_heights(&filled, width, 2);
+        assert_eq!(heights[0], (0..width).map(|x| x + 1).collect::<Vec<_>>());
+        assert_eq!(
+            heights[1],
+            (0..width).map(|x| 2 * x + 1).collect::<Vec<_>>()
+        );
+
+        let interner = filled.interner.read();
+        let top = filled.get_world_voxel(&interner, IVec3::new(11, 22, 1), Lod::new(0));
+        assert_eq!(top, Some(5));
+        drop(interner);
+
+        clear_model(&mut filled);
+
+        let mut surface = read_heightmap(
+            &mut Cursor::new(&png),
+            MaxDepth::new(3),
+            Vec3::ONE,
+            22,
+            5,
+            false,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        assert_eq!(column_heights(&surface, width, 2), vec![vec![1; 12]; 2]);
+
+        let interner = surface.interner.read();
+        let lod = Lod::new(0);
+        for x in 0..width {
+            let surface_y = 2 * x;
+            assert_eq!(
+                surface.get_world_voxel(&interner, IVec3::new(x, surface_y, 1), lod),
+                Some(5)
+            );
+        }
+        drop(interner);
+
+        clear_model(&mut surface);
+    }
+
+    #[test]
+    fn test_heightmap_8_bit_scales_to_max_height() {
+        let png = encode_png(3, 1, png::BitDepth::Eight, &[0, 128, 255]);
+
+        let mut model = read_heightmap(
+            &mut Cursor::new(&png),
+            MaxDepth::new(4),
+            Vec3::ONE,
+            10,
+            1,
+            true,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        assert_eq!(column_heights(&model, 3, 1), vec![vec![1, 6, 11]]);
+
+        clear_model(&mut model);
+    }
+
+    #[test]
+    fn test_rgb_heightmap_is_rejected() {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 1, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1, 2, 3]).unwrap();
+        writer.finish().unwrap();
+
+        let result = read_heightmap(
+            &mut Cursor::new(&bytes),
+            MaxDepth::new(3),
+            Vec3::ONE,
+            8,
+            1,
+            true,
+            1024 * 1024,
+        );
+
+        assert!(matches!(
+            result,
+            Err(HeightmapError::UnsupportedFormat(png::ColorType::Rgb))
+        ));
+    }
+}
@@ -1,98 +1,1185 @@
-use std::{io::Read, path::Path};
+use std::{
+    io::{BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
 
 use byteorder::{BigEndian, ReadBytesExt};
-use glam::IVec3;
+use glam::{IVec3, Vec3};
 use md5::{Digest, Md5};
+use parking_lot::RwLock;
 
-use crate::{MaxDepth, VoxelTrait, world::VoxModel};
+use crate::{
+    MaxDepth, VoxInterner, VoxelTrait,
+    spatial::VoxOpsSpatial3D,
+    world::{
+        Palette, PaletteEntry, VoxChunk, VoxModel,
+        voxchunk::{BranchPatterns, LeafPatterns, deserialize_chunk},
+        voxmodel::deserialize_patterns,
+    },
+};
 
 use super::{
-    Flags,
-    consts::{VTM_MAGIC, VTM_VERSION},
+    Flags, VtmError,
+    consts::{
+        VTC_MAGIC, VTM_MAGIC, VTM_MIN_VERSION, VTM_VERSION, VTM_VERSION_CHUNK_INDEX,
+        VTM_VERSION_CHUNK_SIZE_XYZ, VTM_VERSION_PALETTE,
+    },
 };
 
+/// Metadata of a VTM file, as returned by [`verify_vtm`].
+#[derive(Debug, Clone)]
+pub struct VtmInfo {
+    pub name: String,
+    pub version: u16,
+    pub flags: Flags,
+    pub max_depth: MaxDepth,
+    pub chunk_world_size: Vec3,
+    pub world_bounds: IVec3,
+    /// Size in bytes of a single stored voxel value.
+    ///
+    /// Files older than [`VTM_VERSION_CHUNK_INDEX`] don't store it, nor a chunk index, so
+    /// this and the chunk totals are `0` for them.
+    pub value_size: u8,
+    pub chunk_count: usize,
+    pub total_voxels: u64,
+}
+
+struct VtmChunkEntry {
+    position: IVec3,
+    offset: u32,
+    length: u32,
+    crc: u32,
+    voxels: u64,
+}
+
+// Palette entry with its voxel value still encoded, the header is read before the value type
+// is known
+struct VtmPaletteEntry {
+    value: Vec<u8>,
+    entry: PaletteEntry,
+}
+
+fn read_header<R: Read>(
+    reader: &mut R,
+) -> Result<(VtmInfo, Vec<VtmChunkEntry>, Vec<VtmPaletteEntry>), VtmError> {
+    let mut magic = [0u8; VTM_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != VTM_MAGIC {
+        return Err(VtmError::InvalidMagic);
+    }
+
+    let version = reader.read_u16::<BigEndian>()?;
+    if !(VTM_MIN_VERSION..=VTM_VERSION).contains(&version) {
+        return Err(VtmError::UnsupportedVersion(version));
+    }
+
+    let flags = reader.read_u16::<BigEndian>()?;
+    let flags = Flags::from_bits(flags).ok_or(VtmError::InvalidFlags(flags))?;
+
+    let lod_level = reader.read_u8()?;
+    let chunk_world_size = if version >= VTM_VERSION_CHUNK_SIZE_XYZ {
+        Vec3::new(
+            reader.read_f32::<BigEndian>()?,
+            reader.read_f32::<BigEndian>()?,
+            reader.read_f32::<BigEndian>()?,
+        )
+    } else {
+        Vec3::splat(reader.read_f32::<BigEndian>()?)
+    };
+
+    let _reserved_1 = reader.read_u32::<BigEndian>()?;
+    let _reserved_2 = reader.read_u32::<BigEndian>()?;
+
+    let world_bounds_x = reader.read_i32::<BigEndian>()?;
+    let world_bounds_y = reader.read_i32::<BigEndian>()?;
+    let world_bounds_z = reader.read_i32::<BigEndian>()?;
+    let world_bounds = IVec3::new(world_bounds_x, world_bounds_y, world_bounds_z);
+
+    let name_len = reader.read_u8()?;
+    let mut name = vec![0u8; name_len as usize];
+    reader.read_exact(&mut name)?;
+    let name = String::from_utf8_lossy(&name).into_owned();
+
+    let has_chunk_index = version >= VTM_VERSION_CHUNK_INDEX;
+
+    let value_size = if has_chunk_index {
+        reader.read_u8()?
+    } else {
+        0
+    };
+
+    let chunk_count = if has_chunk_index {
+        reader.read_u32::<BigEndian>()? as usize
+    } else {
+        0
+    };
+    let mut chunks = Vec::with_capacity(chunk_count);
+
+    for _ in 0..chunk_count {
+        let x = reader.read_i32::<BigEndian>()?;
+        let y = reader.read_i32::<BigEndian>()?;
+        let z = reader.read_i32::<BigEndian>()?;
+
+        chunks.push(VtmChunkEntry {
+            position: IVec3::new(x, y, z),
+            offset: reader.read_u32::<BigEndian>()?,
+            length: reader.read_u32::<BigEndian>()?,
+            crc: reader.read_u32::<BigEndian>()?,
+            voxels: reader.read_u64::<BigEndian>()?,
+        });
+    }
+
+    let palette_len = if version >= VTM_VERSION_PALETTE {
+        reader.read_u32::<BigEndian>()? as usize
+    } else {
+        0
+    };
+    let mut palette = Vec::with_capacity(palette_len);
+
+    for _ in 0..palette_len {
+        let mut value = vec![0u8; value_size as usize];
+        reader.read_exact(&mut value)?;
+
+        let mut color = [0.0; 4];
+        for channel in &mut color {
+            *channel = reader.read_f32::<BigEndian>()?;
+        }
+        let flags = reader.read_u32::<BigEndian>()?;
+
+        // read through `take`, so a corrupted length fails at the end of the data instead of
+        // allocating the whole length upfront
+        let name_len = reader.read_u32::<BigEndian>()?;
+        let mut name = Vec::new();
+        reader
+            .by_ref()
+            .take(name_len.into())
+            .read_to_end(&mut name)?;
+        if name.len() != name_len as usize {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let name = String::from_utf8(name)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        palette.push(VtmPaletteEntry {
+            value,
+            entry: PaletteEntry { color, name, flags },
+        });
+    }
+
+    let info = VtmInfo {
+        name,
+        version,
+        flags,
+        max_depth: MaxDepth::new(lod_level),
+        chunk_world_size,
+        world_bounds,
+        value_size,
+        chunk_count,
+        total_voxels: chunks.iter().map(|chunk| chunk.voxels).sum(),
+    };
+
+    Ok((info, chunks, palette))
+}
+
+// Decodes the palette values, which need the value size of the file to match `T`
+fn decode_palette<T: VoxelTrait>(
+    info: &VtmInfo,
+    entries: Vec<VtmPaletteEntry>,
+) -> Result<Palette<T>, VtmError> {
+    let mut palette = Palette::new();
+
+    if entries.is_empty() {
+        return Ok(palette);
+    }
+
+    let expected = std::mem::size_of::<T::ByteArray>();
+    if info.value_size as usize != expected {
+        return Err(VtmError::ValueSizeMismatch {
+            expected,
+            found: info.value_size as usize,
+        });
+    }
+
+    for VtmPaletteEntry { value, entry } in entries {
+        palette.insert(T::read_from_be(&mut value.as_slice())?, entry);
+    }
+
+    Ok(palette)
+}
+
+// Reads the node data following the header, checking its checksums and decompressing it
+// if needed.
+fn read_node_data<R: Read>(reader: &mut R, info: &VtmInfo) -> Result<Vec<u8>, VtmError> {
+    let mut md5_hash = [0u8; 16];
+    reader.read_exact(&mut md5_hash)?;
+
+    let data_size = reader.read_u32::<BigEndian>()?;
+    let data_crc = if info.version >= VTM_VERSION_CHUNK_INDEX {
+        Some(reader.read_u32::<BigEndian>()?)
+    } else {
+        None
+    };
+    let mut data = vec![0u8; data_size as usize];
+    reader.read_exact(&mut data)?;
+
+    if data_crc.is_some_and(|crc| crc32fast::hash(&data) != crc) {
+        return Err(VtmError::ChecksumMismatch);
+    }
+
+    let data = if info.flags.contains(Flags::COMPRESSED) {
+        let mut decoder = zstd::stream::Decoder::new(&data[..])?;
+        let mut data = Vec::new();
+        std::io::copy(&mut decoder, &mut data)?;
+
+        data
+    } else {
+        data
+    };
+
+    if Md5::digest(&data).as_slice() != md5_hash {
+        return Err(VtmError::ChecksumMismatch);
+    }
+
+    Ok(data)
+}
+
+// Returns the record of `chunk` if it lies inside `data` and matches its checksum.
+fn chunk_record<'a>(data: &'a [u8], chunk: &VtmChunkEntry) -> Result<&'a [u8], VtmError> {
+    let start = chunk.offset as usize;
+    let end = start + chunk.length as usize;
+
+    let Some(record) = data.get(start..end) else {
+        return Err(VtmError::ChunkOutOfBounds {
+            position: chunk.position,
+            offset: chunk.offset,
+            length: chunk.length,
+        });
+    };
+
+    if crc32fast::hash(record) != chunk.crc || !record.starts_with(&VTC_MAGIC) {
+        return Err(VtmError::ChunkChecksumMismatch(chunk.position));
+    }
+
+    Ok(record)
+}
+
+/// Checks the integrity of a VTM file without building the model.
+///
+/// Validates the header, the stored checksums of the node data and of every chunk record,
+/// and that every chunk index entry points inside the node data.
+pub fn verify_vtm<P: AsRef<Path>>(path: &P) -> Result<VtmInfo, VtmError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("verify_vtm");
+
+    let mut vox_file = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(&mut vox_file);
+
+    let (info, chunks, _) = read_header(&mut reader)?;
+
+    let data = read_node_data(&mut reader, &info)?;
+
+    for chunk in chunks.iter() {
+        chunk_record(&data, chunk)?;
+    }
+
+    Ok(info)
+}
+
+/// Imports a whole VTM file into a new model with the given memory budget.
+///
+/// Files from [`VTM_MIN_VERSION`] up to [`VTM_VERSION`] are decoded according to the layout
+/// of their version, any other version is rejected with [`VtmError::UnsupportedVersion`].
+/// Node data compressed with [`Flags::COMPRESSED`] is inflated transparently. With
+/// `target_chunk_world_size` the chunks are rescaled to that world size. The palette is
+/// loaded into [`VoxModel::palette`], it stays empty for files older than
+/// [`VTM_VERSION_PALETTE`].
 pub fn import_model_from_vtm<T: VoxelTrait, P: AsRef<Path>>(
     path: &P,
     memory_budget: usize,
-    target_chunk_world_size: Option<f32>,
-) -> VoxModel<T> {
+    target_chunk_world_size: Option<Vec3>,
+) -> Result<VoxModel<T>, VtmError> {
     #[cfg(feature = "tracy")]
     let _span = tracy_client::span!("import_model_from_vtm");
 
-    let mut vox_file = std::fs::File::open(path).unwrap();
+    let mut vox_file = std::fs::File::open(path)?;
     let mut reader = std::io::BufReader::new(&mut vox_file);
 
-    let mut magic = [0u8; VTM_MAGIC.len()];
-    reader.read_exact(&mut magic).unwrap();
-    assert_eq!(magic, VTM_MAGIC);
+    let (info, _, palette) = read_header(&mut reader)?;
+    let palette = decode_palette(&info, palette)?;
 
-    let version = reader.read_u16::<BigEndian>().unwrap();
-    assert_eq!(version, VTM_VERSION);
+    println!("Version: {:#06X}", info.version);
 
-    let flags = reader.read_u16::<BigEndian>().unwrap();
-    let flags = Flags::from_bits(flags).unwrap();
+    let flags = info.flags;
     println!("Flags: {flags:?}");
 
-    let lod_level = reader.read_u8().unwrap();
+    let lod_level = info.max_depth.max();
     println!("LOD Level: {lod_level}");
 
-    let chunk_world_size = reader.read_f32::<BigEndian>().unwrap();
+    let chunk_world_size = info.chunk_world_size;
     println!("Chunk Size: {chunk_world_size}m");
     println!(
         "Voxel Size: {}cm",
         chunk_world_size / (1 << lod_level) as f32 * 100.0
     );
 
-    let _reserved_1 = reader.read_u32::<BigEndian>().unwrap();
-    let _reserved_2 = reader.read_u32::<BigEndian>().unwrap();
+    let world_bounds = info.world_bounds;
+    println!("World bounds: {world_bounds:?}");
 
-    let world_bounds_x = reader.read_i32::<BigEndian>().unwrap();
-    let world_bounds_y = reader.read_i32::<BigEndian>().unwrap();
-    let world_bounds_z = reader.read_i32::<BigEndian>().unwrap();
-    let world_bounds = IVec3::new(world_bounds_x, world_bounds_y, world_bounds_z);
+    println!("Name: {:?}", info.name);
 
-    println!("World bounds: {world_bounds:?}");
+    let data = read_node_data(&mut reader, &info)?;
 
-    let name_len = reader.read_u8().unwrap();
-    let mut name = vec![0u8; name_len as usize];
-    reader.read_exact(&mut name).unwrap();
+    println!("Data: {:?}", data.len());
 
-    println!("Name: {:?}", std::str::from_utf8(&name).unwrap());
+    let chunk_world_size = target_chunk_world_size.unwrap_or(chunk_world_size);
 
-    let mut md5_hash = [0u8; 16];
-    reader.read_exact(&mut md5_hash).unwrap();
+    let mut model = VoxModel::empty(MaxDepth::new(lod_level), chunk_world_size, memory_budget);
+    model.world_bounds = world_bounds;
+    model.palette = palette;
+    model.deserialize(&data, info.version)?;
 
-    println!("MD5 Hash: {md5_hash:0X?}");
+    Ok(model)
+}
 
-    let data_size = reader.read_u32::<BigEndian>().unwrap();
-    let mut data = vec![0u8; data_size as usize];
-    reader.read_exact(&mut data).unwrap();
+/// Loads a VTM file one chunk at a time, so a loading screen can pump chunks between frames
+/// and report progress.
+///
+/// The header and node data are read and verified up front, see [`VtmReader::new`], chunks
+/// are then decoded on demand by [`VtmReader::load_next_chunk`]. A chunk that fails to load
+/// is skipped, so loading can resume with the next one.
+pub struct VtmReader<T: VoxelTrait> {
+    info: VtmInfo,
+    chunks: Vec<VtmChunkEntry>,
+    data: Vec<u8>,
+    leaf_patterns: LeafPatterns<T>,
+    branch_patterns: BranchPatterns<T>,
+    model: VoxModel<T>,
+    next_chunk: usize,
+}
 
-    println!("Data: {data_size:?}");
+impl<T: VoxelTrait> VtmReader<T> {
+    /// Reads the header and node data from `reader` and interns the node tables into a new
+    /// model with the given memory budget. No chunks are loaded yet.
+    ///
+    /// Files older than [`VTM_VERSION_CHUNK_INDEX`] have no chunk index and are rejected
+    /// with [`VtmError::UnsupportedVersion`], [`import_model_from_vtm`] still reads them.
+    pub fn new<R: Read>(mut reader: R, memory_budget: usize) -> Result<Self, VtmError> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VtmReader::new");
 
-    let data = if flags.contains(Flags::COMPRESSED) {
-        let mut decoder = zstd::stream::Decoder::new(&data[..]).unwrap();
-        let mut data = Vec::new();
-        std::io::copy(&mut decoder, &mut data).unwrap();
+        let (info, chunks, palette) = read_header(&mut reader)?;
+        // chunks are located through the index
+        if info.version < VTM_VERSION_CHUNK_INDEX {
+            return Err(VtmError::UnsupportedVersion(info.version));
+        }
 
-        data
+        let palette = decode_palette(&info, palette)?;
+        let data = read_node_data(&mut reader, &info)?;
+
+        let mut model = VoxModel::empty(info.max_depth, info.chunk_world_size, memory_budget);
+        model.world_bounds = info.world_bounds;
+        model.palette = palette;
+        model.reserve_chunks(chunks.len());
+
+        let (leaf_patterns, branch_patterns) = {
+            let mut interner = model.interner.write();
+            deserialize_patterns(&mut interner, &mut BufReader::new(data.as_slice()))?
+        };
+
+        Ok(Self {
+            info,
+            chunks,
+            data,
+            leaf_patterns,
+            branch_patterns,
+            model,
+            next_chunk: 0,
+        })
+    }
+
+    /// Returns the metadata read from the header.
+    pub fn info(&self) -> &VtmInfo {
+        &self.info
+    }
+
+    /// Returns the number of chunks processed so far, including the ones that failed to load.
+    pub fn chunks_read(&self) -> usize {
+        self.next_chunk
+    }
+
+    /// Returns the total number of chunks in the file.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns the interner of the model being loaded, e.g. to mesh chunks as they arrive.
+    pub fn get_interner(&self) -> Arc<RwLock<VoxInterner<T>>> {
+        self.model.get_interner()
+    }
+
+    /// Loads the next chunk into the model and returns it.
+    ///
+    /// Returns `None` once every chunk has been processed. An error only affects the chunk
+    /// it is returned for, the next call moves on to the following chunk.
+    pub fn load_next_chunk(&mut self) -> Option<Result<(IVec3, &VoxChunk<T>), VtmError>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VtmReader::load_next_chunk");
+
+        let entry = self.chunks.get(self.next_chunk)?;
+        self.next_chunk += 1;
+
+        let chunk = chunk_record(&self.data, entry).and_then(|record| {
+            let mut interner = self.model.interner.write();
+            deserialize_chunk(
+                &mut interner,
+                &self.leaf_patterns,
+                &self.branch_patterns,
+                &mut BufReader::new(record),
+                self.model.chunk_world_size,
+                self.model.max_depth,
+                self.info.version,
+            )
+        });
+
+        Some(chunk.map(|chunk| {
+            let position = chunk.position_3d();
+            self.model.chunks.insert(position, chunk);
+
+            (position, &self.model.chunks[&position])
+        }))
+    }
+
+    /// Returns the model with every chunk loaded so far.
+    pub fn into_model(self) -> VoxModel<T> {
+        self.model
+    }
+}
+
+// Counts the bytes read, so the stream can skip to the offsets of the chunk index
+struct CountingReader<R: Read> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+/// Chunks of a VTM file decoded one at a time while reading the file, as returned by
+/// [`import_model_from_vtm_streaming`].
+///
+/// Only the node tables and the current chunk record are held in memory. Every chunk holds a
+/// reference to its root in the shared interner, see [`VtmChunkStream::get_interner`], so
+/// nodes are still deduplicated across chunks. Dropped chunks have to be cleared with that
+/// interner to release their nodes.
+pub struct VtmChunkStream<T: VoxelTrait> {
+    info: VtmInfo,
+    chunks: std::vec::IntoIter<VtmChunkEntry>,
+    reader: CountingReader<BufReader<Box<dyn Read>>>,
+    record: Vec<u8>,
+    leaf_patterns: LeafPatterns<T>,
+    branch_patterns: BranchPatterns<T>,
+    interner: Arc<RwLock<VoxInterner<T>>>,
+    palette: Palette<T>,
+    chunks_read: usize,
+    done: bool,
+}
+
+impl<T: VoxelTrait> VtmChunkStream<T> {
+    /// Returns the metadata read from the header.
+    pub fn info(&self) -> &VtmInfo {
+        &self.info
+    }
+
+    /// Returns the number of chunks processed so far, including the ones that failed to load.
+    pub fn chunks_read(&self) -> usize {
+        self.chunks_read
+    }
+
+    /// Returns the total number of chunks stored in the file.
+    pub fn chunk_count(&self) -> usize {
+        self.info.chunk_count
+    }
+
+    /// Returns the interner shared by all yielded chunks.
+    pub fn get_interner(&self) -> Arc<RwLock<VoxInterner<T>>> {
+        self.interner.clone()
+    }
+
+    /// Returns the palette stored in the file, empty for files older than
+    /// [`VTM_VERSION_PALETTE`].
+    pub fn palette(&self) -> &Palette<T> {
+        &self.palette
+    }
+
+    fn read_chunk(&mut self, chunk: &VtmChunkEntry) -> Result<(IVec3, VoxChunk<T>), VtmError> {
+        let out_of_bounds = || VtmError::ChunkOutOfBounds {
+            position: chunk.position,
+            offset: chunk.offset,
+            length: chunk.length,
+        };
+
+        // records are stored in index order, so only ever skip forward
+        let skip = (chunk.offset as u64)
+            .checked_sub(self.reader.position)
+            .ok_or_else(out_of_bounds)?;
+        let skipped = std::io::copy(&mut (&mut self.reader).take(skip), &mut std::io::sink())
+            .inspect_err(|_| self.done = true)?;
+        if skipped != skip {
+            self.done = true;
+            return Err(out_of_bounds());
+        }
+
+        self.record.resize(chunk.length as usize, 0);
+        if let Err(err) = self.reader.read_exact(&mut self.record) {
+            // a truncated file can't be resumed, there is nothing left to read
+            self.done = true;
+            return Err(err.into());
+        }
+
+        if crc32fast::hash(&self.record) != chunk.crc || !self.record.starts_with(&VTC_MAGIC) {
+            return Err(VtmError::ChunkChecksumMismatch(chunk.position));
+        }
+
+        let mut interner = self.interner.write();
+        let voxel_chunk = deserialize_chunk(
+            &mut interner,
+            &self.leaf_patterns,
+            &self.branch_patterns,
+            &mut self.record.as_slice(),
+            self.info.chunk_world_size,
+            self.info.max_depth,
+            self.info.version,
+        )?;
+
+        Ok((voxel_chunk.position_3d(), voxel_chunk))
+    }
+}
+
+impl<T: VoxelTrait> Iterator for VtmChunkStream<T> {
+    type Item = Result<(IVec3, VoxChunk<T>), VtmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VtmChunkStream::next");
+
+        if self.done {
+            return None;
+        }
+
+        let chunk = self.chunks.next()?;
+        self.chunks_read += 1;
+
+        Some(self.read_chunk(&chunk))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, Some(self.chunks.len()))
+        }
+    }
+}
+
+/// Opens a VTM file for importing one chunk at a time, without loading the whole model.
+///
+/// Reads the header and the node tables up front, the chunks are then decoded as the
+/// returned stream is advanced. Chunks can be filtered or processed before the next one is
+/// read. A chunk failing its checksum yields an `Err` and the stream moves on, a truncated
+/// file yields an `Err` for the chunk it cuts off and ends the stream.
+///
+/// Unlike [`verify_vtm`], the checksums of the whole node data are not validated, since that
+/// would require reading it twice.
+///
+/// Like [`VtmReader::new`], files without a chunk index are rejected.
+pub fn import_model_from_vtm_streaming<T: VoxelTrait, P: AsRef<Path>>(
+    path: &P,
+    memory_budget: usize,
+) -> Result<VtmChunkStream<T>, VtmError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("import_model_from_vtm_streaming");
+
+    let file = std::fs::File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    let (info, mut chunks, palette) = read_header(&mut reader)?;
+    // chunks are located through the index
+    if info.version < VTM_VERSION_CHUNK_INDEX {
+        return Err(VtmError::UnsupportedVersion(info.version));
+    }
+
+    let palette = decode_palette(&info, palette)?;
+
+    let mut md5_hash = [0u8; 16];
+    reader.read_exact(&mut md5_hash)?;
+    let data_size = reader.read_u32::<BigEndian>()?;
+    let _data_crc = reader.read_u32::<BigEndian>()?;
+
+    let data = reader.take(data_size as u64);
+    let data: Box<dyn Read> = if info.flags.contains(Flags::COMPRESSED) {
+        Box::new(zstd::stream::Decoder::new(data)?)
     } else {
-        data
+        Box::new(data)
     };
 
-    let mut md5_hasher = Md5::new();
-    md5_hasher.update(&data);
-    let md5_hash_calculated = md5_hasher.finalize();
+    let mut reader = CountingReader {
+        inner: BufReader::new(data),
+        position: 0,
+    };
 
-    assert_eq!(md5_hash, md5_hash_calculated.as_slice());
+    let interner = Arc::new(RwLock::new(VoxInterner::with_memory_budget(memory_budget)));
+    let (leaf_patterns, branch_patterns) =
+        deserialize_patterns(&mut interner.write(), &mut reader)?;
 
-    println!("MD5 Hash calculated: {md5_hash_calculated:0X?}");
+    chunks.sort_by_key(|chunk| chunk.offset);
 
-    let chunk_world_size = target_chunk_world_size.unwrap_or(chunk_world_size);
+    Ok(VtmChunkStream {
+        info,
+        chunks: chunks.into_iter(),
+        reader,
+        record: Vec::new(),
+        leaf_patterns,
+        branch_patterns,
+        interner,
+        palette,
+        chunks_read: 0,
+        done: false,
+    })
+}
 
-    let mut model = VoxModel::empty(MaxDepth::new(lod_level), chunk_world_size, memory_budget);
-    model.world_bounds = world_bounds;
-    model.deserialize(&data);
+#[cfg(test)]
+mod tests {
+    use crate::{
+        io::export::{export_model_to_vtm, export_model_to_vtm_with_flags},
+        spatial::{VoxOpsBulkWrite, VoxOpsRead, VoxOpsWrite},
+        utils::common::to_vec,
+    };
+
+    use super::*;
+
+    fn build_model() -> VoxModel<i32> {
+        let mut model = VoxModel::empty(MaxDepth::new(3), Vec3::splat(1.0), 1024 * 1024);
+
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+
+            for (chunk_position, value) in [(IVec3::ZERO, 1), (IVec3::new(1, 0, -1), 2)] {
+                let chunk = model.get_or_create_chunk(chunk_position);
+                for i in 0..8 {
+                    chunk.set(&mut interner, IVec3::new(i, i, 7 - i), value);
+                }
+            }
+        }
+
+        model
+    }
+
+    #[test]
+    fn test_verify_vtm() {
+        let path = std::env::temp_dir().join(format!("voxelis_verify_{}.vtm", std::process::id()));
+
+        let model = build_model();
+        export_model_to_vtm("verify".to_string(), &path, &model);
+
+        let info = verify_vtm(&path).unwrap();
+        assert_eq!(info.name, "verify");
+        assert_eq!(info.max_depth.max(), 3);
+        assert_eq!(info.value_size, 4);
+        assert_eq!(info.chunk_count, 2);
+        assert_eq!(info.total_voxels, 16);
+
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+        let interner = imported.get_interner();
+        let interner = interner.read();
+        assert_eq!(
+            imported.chunks[&IVec3::new(1, 0, -1)].get(&interner, IVec3::new(2, 2, 5)),
+            Some(2)
+        );
+
+        // flip a byte in the middle of the node data
+        let mut bytes = std::fs::read(&path).unwrap();
+        let mut cursor = std::io::Cursor::new(&bytes);
+        read_header(&mut cursor).unwrap();
+        cursor.set_position(cursor.position() + 16);
+        let data_size = cursor.read_u32::<BigEndian>().unwrap() as usize;
+        let data_start = cursor.position() as usize + 4;
+        bytes[data_start + data_size / 2] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let result = verify_vtm(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(VtmError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn test_owners_survive_vtm_round_trip() {
+        let path = std::env::temp_dir().join(format!("voxelis_owners_{}.vtm", std::process::id()));
+
+        let mut model = build_model();
+        {
+            let interner = model.get_interner();
+            let interner = interner.read();
+
+            let chunk = model.chunks.get_mut(&IVec3::new(1, 0, -1)).unwrap();
+            assert!(chunk.set_owner(&interner, IVec3::new(2, 2, 5), 7));
+            assert!(chunk.set_owner(&interner, IVec3::new(7, 7, 0), u32::MAX));
+        }
+
+        export_model_to_vtm("owners".to_string(), &path, &model);
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (position, chunk) in model.chunks.iter() {
+            assert_eq!(imported.chunks[position].owners(), chunk.owners());
+        }
+
+        let chunk = &imported.chunks[&IVec3::new(1, 0, -1)];
+        assert_eq!(chunk.owner(IVec3::new(2, 2, 5)), Some(7));
+        assert_eq!(chunk.owner(IVec3::new(7, 7, 0)), Some(u32::MAX));
+        assert!(imported.chunks[&IVec3::ZERO].owners().is_empty());
+    }
+
+    #[test]
+    fn test_palette_survives_vtm_round_trip() {
+        let path = std::env::temp_dir().join(format!("voxelis_palette_{}.vtm", std::process::id()));
+
+        let mut model = build_model();
+
+        export_model_to_vtm("palette".to_string(), &path, &model);
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+        assert!(imported.palette.is_empty());
+
+        model
+            .palette
+            .insert(1, PaletteEntry::new("grass", [0.2, 0.8, 0.1, 1.0]));
+        model.palette.insert(
+            -3,
+            PaletteEntry {
+                color: [0.0, 0.4, 1.0, 0.5],
+                name: "water".to_string(),
+                flags: 0b101,
+            },
+        );
+        // names past 255 bytes
+        model.palette.insert(
+            7,
+            PaletteEntry::new("lava ".repeat(100), [1.0, 0.3, 0.0, 1.0]),
+        );
+
+        export_model_to_vtm("palette".to_string(), &path, &model);
+
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+        assert_eq!(imported.palette, model.palette);
+        assert_eq!(imported.palette.get(-3).unwrap().flags, 0b101);
+
+        let file = std::fs::File::open(&path).unwrap();
+        let reader = VtmReader::<i32>::new(std::io::BufReader::new(file), 1024 * 1024).unwrap();
+        assert_eq!(reader.into_model().palette, model.palette);
+
+        let stream = import_model_from_vtm_streaming::<i32, _>(&path, 1024 * 1024).unwrap();
+        assert_eq!(stream.palette(), &model.palette);
+
+        // palette values can't be read as a type of another size
+        let result = import_model_from_vtm::<u8, _>(&path, 1024 * 1024, None);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(VtmError::ValueSizeMismatch {
+                expected: 1,
+                found: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_palette_name_must_be_utf8() {
+        let path = std::env::temp_dir().join(format!("voxelis_utf8_{}.vtm", std::process::id()));
+
+        let mut model = build_model();
+        model
+            .palette
+            .insert(1, PaletteEntry::new("grass", [0.2, 0.8, 0.1, 1.0]));
+
+        export_model_to_vtm("utf8".to_string(), &path, &model);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let offset = bytes
+            .windows(5)
+            .position(|window| window == b"grass")
+            .unwrap();
+        bytes[offset] = 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let result = import_model_from_vtm::<i32, _>(&path, 1024 * 1024, None);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(VtmError::Io(err)) if err.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[test]
+    fn test_verify_vtm_rejects_bad_magic() {
+        let path = std::env::temp_dir().join(format!("voxelis_magic_{}.vtm", std::process::id()));
+        std::fs::write(&path, b"NotAVoxTreeModel").unwrap();
+
+        let result = verify_vtm(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(VtmError::InvalidMagic)));
+    }
+
+    #[test]
+    fn test_vtm_reader_matches_import() {
+        let path = std::env::temp_dir().join(format!("voxelis_reader_{}.vtm", std::process::id()));
+
+        let mut model = build_model();
+        {
+            let interner = model.get_interner();
+            let interner = interner.read();
+
+            let chunk = model.chunks.get_mut(&IVec3::ZERO).unwrap();
+            assert!(chunk.set_owner(&interner, IVec3::new(3, 3, 4), 5));
+        }
+
+        export_model_to_vtm("reader".to_string(), &path, &model);
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mut reader = VtmReader::<i32>::new(std::io::BufReader::new(file), 1024 * 1024).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(reader.info().name, "reader");
+        assert_eq!(reader.chunk_count(), 2);
+
+        let mut positions = Vec::new();
+        while let Some(result) = reader.load_next_chunk() {
+            let (position, chunk) = result.unwrap();
+            assert_eq!(chunk.position_3d(), position);
+            positions.push(position);
+            assert_eq!(reader.chunks_read(), positions.len());
+        }
+        assert!(reader.load_next_chunk().is_none());
+
+        let loaded = reader.into_model();
+        assert_eq!(loaded.chunks.len(), imported.chunks.len());
+        assert_eq!(loaded.world_bounds, imported.world_bounds);
+
+        let loaded_interner = loaded.get_interner();
+        let loaded_interner = loaded_interner.read();
+        let imported_interner = imported.get_interner();
+        let imported_interner = imported_interner.read();
+
+        for (position, chunk) in imported.chunks.iter() {
+            assert!(positions.contains(position));
+
+            let loaded_chunk = &loaded.chunks[position];
+            assert_eq!(loaded_chunk.owners(), chunk.owners());
+            assert_eq!(
+                to_vec(
+                    &loaded_interner,
+                    &loaded_chunk.get_root_id(),
+                    loaded.max_depth
+                ),
+                to_vec(&imported_interner, &chunk.get_root_id(), imported.max_depth)
+            );
+        }
+    }
+
+    #[test]
+    fn test_streaming_import_matches_import() {
+        let path = std::env::temp_dir().join(format!("voxelis_stream_{}.vtm", std::process::id()));
+
+        let model = build_model();
+        export_model_to_vtm("stream".to_string(), &path, &model);
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+
+        let stream = import_model_from_vtm_streaming::<i32, _>(&path, 1024 * 1024).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(stream.info().name, "stream");
+        assert_eq!(stream.chunk_count(), 2);
+
+        let interner = stream.get_interner();
+        let mut chunks = stream.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks.len(), imported.chunks.len());
+
+        let imported_interner = imported.get_interner();
+        let imported_interner = imported_interner.read();
+
+        {
+            let interner = interner.read();
+            for (position, chunk) in chunks.iter() {
+                assert_eq!(chunk.position_3d(), *position);
+
+                let imported_chunk = &imported.chunks[position];
+                assert_eq!(chunk.owners(), imported_chunk.owners());
+                assert_eq!(
+                    to_vec(&interner, &chunk.get_root_id(), imported.max_depth),
+                    to_vec(
+                        &imported_interner,
+                        &imported_chunk.get_root_id(),
+                        imported.max_depth
+                    )
+                );
+            }
+        }
+
+        let mut interner = interner.write();
+        for (_, chunk) in chunks.iter_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_streaming_import_truncated_file() {
+        let path =
+            std::env::temp_dir().join(format!("voxelis_truncated_{}.vtm", std::process::id()));
+
+        let model = build_model();
+        export_model_to_vtm("truncated".to_string(), &path, &model);
+
+        // rewrite the file uncompressed, so the cut lands inside a chunk record
+        let bytes = std::fs::read(&path).unwrap();
+        let mut cursor = std::io::Cursor::new(&bytes);
+        let (info, mut chunks, _) = read_header(&mut cursor).unwrap();
+        let header_size = cursor.position() as usize;
+        let data = read_node_data(&mut cursor, &info).unwrap();
+
+        let mut uncompressed = bytes[..header_size].to_vec();
+        let flags_offset = VTM_MAGIC.len() + 2;
+        uncompressed[flags_offset..flags_offset + 2]
+            .copy_from_slice(&Flags::NONE.bits().to_be_bytes());
+        uncompressed.extend_from_slice(&Md5::digest(&data));
+        uncompressed.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        uncompressed.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+        uncompressed.extend_from_slice(&data);
+
+        chunks.sort_by_key(|chunk| chunk.offset);
+        let last = chunks.last().unwrap();
+        let cut = uncompressed.len() - data.len() + (last.offset + last.length / 2) as usize;
+        std::fs::write(&path, &uncompressed[..cut]).unwrap();
+
+        let mut stream = import_model_from_vtm_streaming::<i32, _>(&path, 1024 * 1024).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (position, mut chunk) = stream.next().unwrap().unwrap();
+        assert_eq!(position, chunks[0].position);
+        assert!(matches!(stream.next(), Some(Err(VtmError::Io(_)))));
+        assert!(stream.next().is_none());
+        assert_eq!(stream.chunks_read(), 2);
+
+        chunk.clear(&mut stream.get_interner().write());
+    }
+
+    #[test]
+    fn test_import_rejects_unknown_version() {
+        let path = std::env::temp_dir().join(format!("voxelis_version_{}.vtm", std::process::id()));
+
+        let model = build_model();
+        export_model_to_vtm("version".to_string(), &path, &model);
+
+        // the version follows the magic
+        let mut bytes = std::fs::read(&path).unwrap();
+        let version_offset = VTM_MAGIC.len();
+        bytes[version_offset..version_offset + 2].copy_from_slice(&0x0200u16.to_be_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        let result = import_model_from_vtm::<i32, _>(&path, 1024 * 1024, None);
+        let verified = verify_vtm(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let err = result.err().unwrap();
+        assert!(matches!(err, VtmError::UnsupportedVersion(0x0200)));
+        assert!(err.to_string().contains("version"), "{err}");
+        assert!(matches!(
+            verified,
+            Err(VtmError::UnsupportedVersion(0x0200))
+        ));
+    }
+
+    #[test]
+    fn test_import_version_1_0() {
+        let path = std::env::temp_dir().join(format!("voxelis_v1_{}.vtm", std::process::id()));
+
+        let model = build_model();
+
+        // 0x0100 records end with the root, drop the empty owners of every record
+        let mut data = Vec::new();
+        let chunk_ranges = model.serialize(&mut data);
+        let mut ends = chunk_ranges
+            .iter()
+            .map(|(_, range)| range.end - 1)
+            .collect::<Vec<_>>();
+        ends.sort_unstable();
+        for end in ends.into_iter().rev() {
+            assert_eq!(data.remove(end), 0);
+        }
+
+        // no value size, chunk index or node data checksum, a single chunk size
+        let mut bytes = VTM_MAGIC.to_vec();
+        bytes.extend_from_slice(&VTM_MIN_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&Flags::NONE.bits().to_be_bytes());
+        bytes.push(model.max_depth.max());
+        bytes.extend_from_slice(&2.0f32.to_be_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        for coord in model.world_bounds.to_array() {
+            bytes.extend_from_slice(&coord.to_be_bytes());
+        }
+        bytes.push(2);
+        bytes.extend_from_slice(b"v1");
+        bytes.extend_from_slice(&Md5::digest(&data));
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&data);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let info = verify_vtm(&path).unwrap();
+        let imported: VoxModel<i32> = import_model_from_vtm(&path, 1024 * 1024, None).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        let reader = VtmReader::<i32>::new(std::io::BufReader::new(file), 1024 * 1024);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(info.version, VTM_MIN_VERSION);
+        assert_eq!(info.name, "v1");
+        assert_eq!(info.chunk_count, 0);
+        assert!(matches!(
+            reader.err(),
+            Some(VtmError::UnsupportedVersion(VTM_MIN_VERSION))
+        ));
+
+        assert_eq!(imported.chunk_world_size, Vec3::splat(2.0));
+        assert_eq!(imported.chunks.len(), model.chunks.len());
+
+        let interner = model.get_interner();
+        let interner = interner.read();
+        let imported_interner = imported.get_interner();
+        let imported_interner = imported_interner.read();
+
+        for (position, chunk) in model.chunks.iter() {
+            let imported_chunk = &imported.chunks[position];
+            assert!(imported_chunk.owners().is_empty());
+            assert_eq!(
+                to_vec(
+                    &imported_interner,
+                    &imported_chunk.get_root_id(),
+                    imported.max_depth
+                ),
+                to_vec(&interner, &chunk.get_root_id(), model.max_depth)
+            );
+        }
+    }
+
+    #[test]
+    fn test_compressed_vtm_round_trip() {
+        let compressed_path =
+            std::env::temp_dir().join(format!("voxelis_zstd_{}.vtm", std::process::id()));
+        let uncompressed_path =
+            std::env::temp_dir().join(format!("voxelis_raw_{}.vtm", std::process::id()));
+
+        // rolling hills over 4x4 chunks, stone below a layer of grass
+        let mut model = VoxModel::empty(MaxDepth::new(4), Vec3::splat(1.0), 1024 * 1024);
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+
+            for z in 0..64 {
+                for x in 0..64 {
+                    let height = 8.0 + 3.0 * (x as f32 * 0.2).sin() + 3.0 * (z as f32 * 0.15).cos();
+                    let height = height as i32;
+
+                    let chunk = model.get_or_create_chunk(IVec3::new(x / 16, 0, z / 16));
+                    for y in 0..=height {
+                        let value = if y == height { 2 } else { 1 };
+                        chunk.set(&mut interner, IVec3::new(x % 16, y, z % 16), value);
+                    }
+                }
+            }
+        }
+
+        export_model_to_vtm_with_flags(
+            "zstd".to_string(),
+            &compressed_path,
+            &model,
+            Flags::COMPRESSED,
+        );
+        export_model_to_vtm_with_flags("raw".to_string(), &uncompressed_path, &model, Flags::NONE);
+
+        let compressed_len = std::fs::metadata(&compressed_path).unwrap().len();
+        let uncompressed_len = std::fs::metadata(&uncompressed_path).unwrap().len();
+        assert!(
+            compressed_len < uncompressed_len,
+            "{compressed_len} >= {uncompressed_len}"
+        );
+
+        // the header is stored as is, as is the node data once inflated
+        let read_file = |path: &std::path::Path| {
+            let bytes = std::fs::read(path).unwrap();
+            let mut cursor = std::io::Cursor::new(&bytes);
+            let (info, chunks, _) = read_header(&mut cursor).unwrap();
+            let data = read_node_data(&mut cursor, &info).unwrap();
+
+            let index = chunks
+                .iter()
+                .map(|chunk| (chunk.position, chunk.offset, chunk.length, chunk.crc))
+                .collect::<Vec<_>>();
+
+            (info, index, data)
+        };
+        let (compressed_info, compressed_index, compressed_data) = read_file(&compressed_path);
+        let (uncompressed_info, uncompressed_index, uncompressed_data) =
+            read_file(&uncompressed_path);
+
+        assert_eq!(compressed_info.flags, Flags::COMPRESSED);
+        assert_eq!(uncompressed_info.flags, Flags::NONE);
+        assert_eq!(compressed_info.total_voxels, uncompressed_info.total_voxels);
+        assert_eq!(compressed_index, uncompressed_index);
+        assert_eq!(compressed_data, uncompressed_data);
+
+        let imported: VoxModel<i32> =
+            import_model_from_vtm(&compressed_path, 1024 * 1024, None).unwrap();
+        let raw: VoxModel<i32> =
+            import_model_from_vtm(&uncompressed_path, 1024 * 1024, None).unwrap();
+        std::fs::remove_file(&compressed_path).unwrap();
+        std::fs::remove_file(&uncompressed_path).unwrap();
+
+        assert_eq!(imported.chunks.len(), model.chunks.len());
+        assert_eq!(raw.chunks.len(), model.chunks.len());
+
+        let interner = model.get_interner();
+        let interner = interner.read();
+        let imported_interner = imported.get_interner();
+        let imported_interner = imported_interner.read();
+        let raw_interner = raw.get_interner();
+        let raw_interner = raw_interner.read();
+
+        for (position, chunk) in model.chunks.iter() {
+            let expected = to_vec(&interner, &chunk.get_root_id(), model.max_depth);
+
+            let imported_chunk = &imported.chunks[position];
+            assert_eq!(
+                to_vec(
+                    &imported_interner,
+                    &imported_chunk.get_root_id(),
+                    imported.max_depth
+                ),
+                expected
+            );
 
-    model
+            let raw_chunk = &raw.chunks[position];
+            assert_eq!(
+                to_vec(&raw_interner, &raw_chunk.get_root_id(), raw.max_depth),
+                expected
+            );
+        }
+    }
 }
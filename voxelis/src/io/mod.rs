@@ -1,16 +1,37 @@
 pub mod obj_reader;
 
-pub use obj_reader::Obj;
+pub use obj_reader::{Obj, ObjError};
 
 #[cfg(feature = "vtm")]
 pub mod consts;
 #[cfg(feature = "vtm")]
+pub mod error;
+#[cfg(feature = "vtm")]
 pub mod flags;
 #[cfg(feature = "vtm")]
 pub mod varint;
 #[cfg(feature = "vtm")]
+pub use error::VtmError;
+#[cfg(feature = "vtm")]
 pub use flags::Flags;
 #[cfg(feature = "vtm")]
 pub mod export;
 #[cfg(feature = "vtm")]
 pub mod import;
+#[cfg(feature = "vtm")]
+pub mod region;
+
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(feature = "serde")]
+pub use serialization::{ChunkSeed, SerializeChunk};
+
+#[cfg(feature = "heightmap")]
+pub mod heightmap;
+#[cfg(feature = "heightmap")]
+pub use heightmap::HeightmapError;
+
+#[cfg(feature = "vox")]
+pub mod vox;
+#[cfg(feature = "vox")]
+pub use vox::VoxError;
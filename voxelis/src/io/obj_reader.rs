@@ -6,25 +6,89 @@ use std::{
 
 use glam::{DVec3, IVec3};
 
+#[derive(Debug)]
+pub enum ObjError {
+    /// The file can't be opened or read, e.g. because it doesn't exist.
+    Io(std::io::Error),
+    /// A `v` line without three numeric coordinates.
+    InvalidVertex { line: usize },
+    /// A `f` line with fewer than three vertex references, or one that is not a number.
+    InvalidFace { line: usize },
+    /// A face refers to a vertex that was not defined before it. `index` is the reference as
+    /// written, negative for a relative one.
+    FaceIndexOutOfRange {
+        line: usize,
+        index: i32,
+        vertex_count: usize,
+    },
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::InvalidVertex { line } => write!(f, "line {line}: invalid vertex"),
+            Self::InvalidFace { line } => write!(f, "line {line}: invalid face"),
+            Self::FaceIndexOutOfRange {
+                line,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "line {line}: face refers to vertex {index}, but only {vertex_count} are defined"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ObjError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
 pub struct Obj {
     pub vertices: Vec<DVec3>,
     pub faces: Vec<IVec3>,
+    /// Material group of every face, an index into `materials`. May be empty, all faces then
+    /// belong to group `0`.
+    pub face_materials: Vec<usize>,
+    /// Names of the `usemtl` groups in order of first use. Faces before the first `usemtl`
+    /// form a group with an empty name.
+    pub materials: Vec<String>,
     pub aabb: (DVec3, DVec3),
     pub size: DVec3,
 }
 
 impl Obj {
-    pub fn parse<P: AsRef<Path>>(path: &P) -> Self {
+    /// Reads the vertices and triangles of an OBJ file, polygons contribute their first three
+    /// vertices.
+    ///
+    /// Faces may only refer to vertices defined above them, errors report the 1-based line
+    /// they were found on.
+    pub fn parse<P: AsRef<Path>>(path: &P) -> Result<Self, ObjError> {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("Obj::parse");
 
         println!("Parsing obj file: {}", path.as_ref().display());
 
-        let file = File::open(path).unwrap();
+        let file = File::open(path)?;
         let reader = BufReader::new(file);
 
         let mut vertices = Vec::new();
         let mut faces = Vec::new();
+        let mut face_materials = Vec::new();
+        let mut materials: Vec<String> = Vec::new();
+        let mut material = None;
 
         let mut min_x = f64::MAX;
         let mut min_y = f64::MAX;
@@ -34,14 +98,27 @@ impl Obj {
         let mut max_y = f64::MIN;
         let mut max_z = f64::MIN;
 
-        for line in reader.lines() {
-            let line = line.unwrap();
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_number = line_index + 1;
+
             let tokens: Vec<&str> = line.split_whitespace().collect();
-            match tokens[0] {
+            let Some(keyword) = tokens.first() else {
+                continue;
+            };
+
+            match *keyword {
                 "v" => {
-                    let x: f64 = tokens[1].parse().unwrap();
-                    let y: f64 = tokens[2].parse().unwrap();
-                    let z: f64 = tokens[3].parse().unwrap();
+                    let coordinate = |index: usize| {
+                        tokens
+                            .get(index)
+                            .and_then(|token| token.parse::<f64>().ok())
+                            .ok_or(ObjError::InvalidVertex { line: line_number })
+                    };
+
+                    let x = coordinate(1)?;
+                    let y = coordinate(2)?;
+                    let z = coordinate(3)?;
 
                     let vertex = DVec3::new(x, y, z);
 
@@ -55,13 +132,39 @@ impl Obj {
                     vertices.push(vertex);
                 }
                 "f" => {
-                    let v1: i32 = tokens[1].parse().unwrap();
-                    let v2: i32 = tokens[2].parse().unwrap();
-                    let v3: i32 = tokens[3].parse().unwrap();
+                    let vertex_count = vertices.len();
+
+                    let vertex_index = |index: usize| {
+                        let token = tokens
+                            .get(index)
+                            .ok_or(ObjError::InvalidFace { line: line_number })?;
+                        let reference = token
+                            .split('/')
+                            .next()
+                            .and_then(|reference| reference.parse::<i32>().ok())
+                            .ok_or(ObjError::InvalidFace { line: line_number })?;
 
-                    let face = IVec3::new(v1, v2, v3);
+                        let resolved = parse_face_index(reference, vertex_count as i32);
+                        if resolved < 1 || resolved as usize > vertex_count {
+                            return Err(ObjError::FaceIndexOutOfRange {
+                                line: line_number,
+                                index: reference,
+                                vertex_count,
+                            });
+                        }
+
+                        Ok(resolved)
+                    };
+
+                    let face = IVec3::new(vertex_index(1)?, vertex_index(2)?, vertex_index(3)?);
 
                     faces.push(face);
+                    face_materials
+                        .push(*material.get_or_insert_with(|| material_index(&mut materials, "")));
+                }
+                "usemtl" => {
+                    let name = tokens.get(1).copied().unwrap_or_default();
+                    material = Some(material_index(&mut materials, name));
                 }
                 _ => {}
             }
@@ -76,14 +179,151 @@ impl Obj {
         println!("Parsed obj file: {}", path.as_ref().display());
         println!("Vertices: {}", vertices.len());
         println!("Faces: {}", faces.len());
+        println!("Materials: {}", materials.len());
         println!("Size: {size:?}");
         println!("AABB: {:?}, {:?}", aabb.0, aabb.1);
 
-        Self {
+        Ok(Self {
             vertices,
             faces,
+            face_materials,
+            materials,
             aabb,
             size,
+        })
+    }
+}
+
+// Resolves the vertex part of a face reference to a 1-based index. Negative indices are
+// relative to the vertices read so far (`-1` is the last one).
+fn parse_face_index(index: i32, vertex_count: i32) -> i32 {
+    if index < 0 {
+        vertex_count + index + 1
+    } else {
+        index
+    }
+}
+
+// Returns the index of the material group `name`, adding it if it is new
+fn material_index(materials: &mut Vec<String>, name: &str) -> usize {
+    match materials.iter().position(|material| material == name) {
+        Some(index) => index,
+        None => {
+            materials.push(name.to_string());
+            materials.len() - 1
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(name: &str, contents: &str) -> Result<Obj, ObjError> {
+        let path = std::env::temp_dir().join(format!("voxelis_{name}_{}.obj", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+
+        let obj = Obj::parse(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        obj
+    }
+
+    #[test]
+    fn test_parse_face_index() {
+        assert_eq!(parse_face_index(3, 10), 3);
+        assert_eq!(parse_face_index(-1, 10), 10);
+        assert_eq!(parse_face_index(-10, 10), 1);
+        assert_eq!(parse_face_index(-2, 4), 3);
+
+        let obj = parse_str(
+            "face_refs",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nv 1 1 0\nf -2/-2/-1 7//2 1/1\n",
+        );
+        assert!(matches!(
+            obj,
+            Err(ObjError::FaceIndexOutOfRange {
+                line: 6,
+                index: 7,
+                vertex_count: 4
+            })
+        ));
+
+        let obj = parse_str(
+            "face_refs_ok",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -2/-2/-1 3//2 1/1\n",
+        );
+        assert_eq!(obj.unwrap().faces, vec![IVec3::new(2, 3, 1)]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let missing = std::env::temp_dir().join("voxelis_missing_file.obj");
+        assert!(matches!(
+            Obj::parse(&missing),
+            Err(ObjError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound
+        ));
+
+        // cut off in the middle of a vertex
+        let truncated = parse_str("truncated", "v 0 0 0\nv 1 0 0\nv 0 1");
+        assert!(matches!(
+            truncated,
+            Err(ObjError::InvalidVertex { line: 3 })
+        ));
+
+        let obj = parse_str("bad_vertex", "v 0 0 zero\n");
+        assert!(matches!(obj, Err(ObjError::InvalidVertex { line: 1 })));
+
+        let obj = parse_str("short_face", "v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert!(matches!(obj, Err(ObjError::InvalidFace { line: 3 })));
+
+        // faces may only refer to vertices above them
+        let obj = parse_str("bad_index", "v 0 0 0\nv 1 0 0\nf 1 2 3\nv 0 1 0\n");
+        let err = obj.err().unwrap();
+        assert!(matches!(
+            err,
+            ObjError::FaceIndexOutOfRange {
+                line: 3,
+                index: 3,
+                vertex_count: 2
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "line 3: face refers to vertex 3, but only 2 are defined"
+        );
+
+        let obj = parse_str("zero_index", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n");
+        assert!(matches!(
+            obj,
+            Err(ObjError::FaceIndexOutOfRange {
+                line: 4,
+                index: 0,
+                ..
+            })
+        ));
+
+        let obj = parse_str("relative_index", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -4 1 2\n");
+        assert!(matches!(
+            obj,
+            Err(ObjError::FaceIndexOutOfRange {
+                line: 4,
+                index: -4,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_material_groups() {
+        let obj = parse_str(
+            "mtl",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl stone\nf 1 2 3\n\
+             usemtl grass\nf 1 2 3\nusemtl stone\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        assert_eq!(obj.materials, vec!["", "stone", "grass"]);
+        assert_eq!(obj.face_materials, vec![0, 1, 2, 1]);
+    }
+}
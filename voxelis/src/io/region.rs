@@ -0,0 +1,303 @@
+use std::io::{BufReader, Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use glam::{IVec3, Vec3};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    BlockId, Lod, MaxDepth, VoxInterner, VoxelTrait,
+    interner::EMPTY_CHILD,
+    spatial::{VoxOpsChunkConfig, VoxOpsConfig, VoxOpsSpatial3D},
+    utils::common::collect_nodes,
+    world::{
+        VoxChunk,
+        voxchunk::{read_owners, write_owners},
+    },
+};
+
+use super::{
+    VtmError,
+    consts::{VTR_MAGIC, VTR_MIN_VERSION, VTR_VERSION, VTR_VERSION_OWNERS},
+    varint::{decode_varint_u32_from_reader, encode_varint_u32},
+};
+
+const NODE_LEAF: u8 = 0;
+const NODE_BRANCH: u8 = 1;
+
+/// Returns the file name of the region at `region_coord`, e.g. `r.0.-1.2.vtr`.
+pub fn region_file_name(region_coord: IVec3) -> String {
+    format!(
+        "r.{}.{}.{}.vtr",
+        region_coord.x, region_coord.y, region_coord.z
+    )
+}
+
+/// Returns the coordinates of the region holding the chunk at `chunk_position`.
+pub fn chunk_to_region(chunk_position: IVec3, region_chunk_size: u32) -> IVec3 {
+    chunk_position.div_euclid(IVec3::splat(region_chunk_size as i32))
+}
+
+/// Writes a self-contained region file holding `chunks`.
+///
+/// Layout:
+/// - magic, version, region coordinates
+/// - node table in post-order, so children always precede their parents
+/// - chunk index: position, max depth, chunk size, 1-based root node (`0` for empty chunks)
+///   and the voxel owners of the chunk
+pub fn write_region<T: VoxelTrait, W: Write>(
+    writer: &mut W,
+    region_coord: IVec3,
+    chunks: &[&VoxChunk<T>],
+    interner: &VoxInterner<T>,
+) -> Result<(), VtmError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("write_region");
+
+    let mut node_ids = FxHashMap::default();
+    let mut nodes = Vec::new();
+
+    let roots = chunks
+        .iter()
+        .map(|chunk| collect_nodes(interner, chunk.get_root_id(), &mut node_ids, &mut nodes))
+        .collect::<Vec<_>>();
+
+    writer.write_all(&VTR_MAGIC)?;
+    writer.write_u16::<BigEndian>(VTR_VERSION)?;
+    writer.write_i32::<BigEndian>(region_coord.x)?;
+    writer.write_i32::<BigEndian>(region_coord.y)?;
+    writer.write_i32::<BigEndian>(region_coord.z)?;
+
+    writer.write_u32::<BigEndian>(nodes.len() as u32)?;
+    for node_id in nodes.iter() {
+        if node_id.is_leaf() {
+            writer.write_u8(NODE_LEAF)?;
+            interner.get_value(node_id).write_as_be(writer)?;
+        } else {
+            writer.write_u8(NODE_BRANCH)?;
+            writer.write_u8(node_id.mask())?;
+            for child_id in interner.get_children_ref(node_id).iter() {
+                if !child_id.is_empty() {
+                    writer.write_all(&encode_varint_u32(node_ids[child_id]))?;
+                }
+            }
+        }
+    }
+
+    writer.write_u32::<BigEndian>(chunks.len() as u32)?;
+    for (chunk, root) in chunks.iter().zip(roots) {
+        let position = chunk.position_3d();
+        writer.write_i32::<BigEndian>(position.x)?;
+        writer.write_i32::<BigEndian>(position.y)?;
+        writer.write_i32::<BigEndian>(position.z)?;
+        writer.write_u8(chunk.max_depth(Lod::new(0)).max())?;
+        for size in chunk.chunk_size().to_array() {
+            writer.write_f32::<BigEndian>(size)?;
+        }
+        writer.write_u32::<BigEndian>(root)?;
+        write_owners(chunk.owners(), writer)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a region file written by [`write_region`], interning its nodes into `interner`.
+///
+/// Files from [`VTR_MIN_VERSION`] on are accepted, chunks of files older than
+/// [`VTR_VERSION_OWNERS`] come without owners. Returns the region coordinates and its chunks, each holding a reference to its root.
+pub fn read_region<T: VoxelTrait, R: Read>(
+    reader: &mut R,
+    interner: &mut VoxInterner<T>,
+) -> Result<(IVec3, Vec<VoxChunk<T>>), VtmError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("read_region");
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut reader = BufReader::new(data.as_slice());
+
+    let mut magic = [0u8; VTR_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != VTR_MAGIC {
+        return Err(VtmError::InvalidMagic);
+    }
+
+    let version = reader.read_u16::<BigEndian>()?;
+    if !(VTR_MIN_VERSION..=VTR_VERSION).contains(&version) {
+        return Err(VtmError::UnsupportedVersion(version));
+    }
+
+    let x = reader.read_i32::<BigEndian>()?;
+    let y = reader.read_i32::<BigEndian>()?;
+    let z = reader.read_i32::<BigEndian>()?;
+    let region_coord = IVec3::new(x, y, z);
+
+    let node_count = reader.read_u32::<BigEndian>()?;
+    let mut nodes: Vec<BlockId> = Vec::with_capacity(node_count as usize);
+
+    let result = read_nodes(&mut reader, interner, node_count, &mut nodes)
+        .and_then(|_| read_chunks(&mut reader, interner, &nodes, version));
+
+    // chunks hold their own references now, drop the ones owned by the node table
+    for node_id in nodes.iter().rev() {
+        interner.dec_ref_recursive(node_id);
+    }
+
+    result.map(|chunks| (region_coord, chunks))
+}
+
+fn read_nodes<T: VoxelTrait>(
+    reader: &mut BufReader<&[u8]>,
+    interner: &mut VoxInterner<T>,
+    node_count: u32,
+    nodes: &mut Vec<BlockId>,
+) -> Result<(), VtmError> {
+    for _ in 0..node_count {
+        let node_id = match reader.read_u8()? {
+            NODE_LEAF => {
+                let value = T::read_from_be(reader)?;
+                if value.voxel_eq(&T::default()) {
+                    return Err(VtmError::InvalidNode(nodes.len() as u32 + 1));
+                }
+
+                interner.get_or_create_leaf(value)
+            }
+            NODE_BRANCH => {
+                let mask = reader.read_u8()?;
+                if mask == 0 {
+                    return Err(VtmError::InvalidNode(nodes.len() as u32 + 1));
+                }
+
+                let mut children = EMPTY_CHILD;
+                let mut types = 0u8;
+
+                for (child_idx, child) in children.iter_mut().enumerate() {
+                    if mask & (1 << child_idx) == 0 {
+                        continue;
+                    }
+
+                    let index = decode_varint_u32_from_reader(reader)
+                        .ok_or_else(|| VtmError::Io(std::io::ErrorKind::UnexpectedEof.into()))?;
+
+                    // post-order, so a child must already be in the table
+                    if index == 0 || index as usize > nodes.len() {
+                        return Err(VtmError::InvalidNode(index));
+                    }
+
+                    *child = nodes[index as usize - 1];
+                    if child.is_leaf() {
+                        types |= 1 << child_idx;
+                    }
+                }
+
+                for child in children.iter().filter(|child| !child.is_empty()) {
+                    interner.inc_ref(child);
+                }
+
+                interner.get_or_create_branch(children, types, mask)
+            }
+            _ => return Err(VtmError::InvalidNode(nodes.len() as u32 + 1)),
+        };
+
+        nodes.push(node_id);
+    }
+
+    Ok(())
+}
+
+fn read_chunks<T: VoxelTrait>(
+    reader: &mut BufReader<&[u8]>,
+    interner: &mut VoxInterner<T>,
+    nodes: &[BlockId],
+    version: u16,
+) -> Result<Vec<VoxChunk<T>>, VtmError> {
+    let chunk_count = reader.read_u32::<BigEndian>()?;
+
+    let mut entries = Vec::with_capacity(chunk_count as usize);
+
+    for _ in 0..chunk_count {
+        let x = reader.read_i32::<BigEndian>()?;
+        let y = reader.read_i32::<BigEndian>()?;
+        let z = reader.read_i32::<BigEndian>()?;
+        let max_depth = MaxDepth::new(reader.read_u8()?);
+        let chunk_size = Vec3::new(
+            reader.read_f32::<BigEndian>()?,
+            reader.read_f32::<BigEndian>()?,
+            reader.read_f32::<BigEndian>()?,
+        );
+        let root = reader.read_u32::<BigEndian>()?;
+
+        if root as usize > nodes.len() {
+            return Err(VtmError::InvalidNode(root));
+        }
+
+        let owners = if version >= VTR_VERSION_OWNERS {
+            read_owners(reader)?
+        } else {
+            FxHashMap::default()
+        };
+
+        entries.push((IVec3::new(x, y, z), max_depth, chunk_size, root, owners));
+    }
+
+    // validate the whole index first, so no chunk holds references on error
+    let chunks = entries
+        .into_iter()
+        .map(|(position, max_depth, chunk_size, root, owners)| {
+            let mut chunk =
+                VoxChunk::with_position(chunk_size, max_depth, position.x, position.y, position.z);
+            if root != 0 {
+                chunk.set_root_id(interner, nodes[root as usize - 1]);
+            }
+            chunk.set_owners(owners);
+            chunk
+        })
+        .collect();
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spatial::{VoxOpsBulkWrite, VoxOpsRead, VoxOpsWrite};
+
+    use super::*;
+
+    const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+    fn write_single_chunk(interner: &mut VoxInterner<i32>) -> Vec<u8> {
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 1, 2, 3);
+        chunk.set(interner, IVec3::new(4, 5, 6), 3);
+
+        let mut data = Vec::new();
+        write_region(&mut data, IVec3::ZERO, &[&chunk], interner).unwrap();
+        chunk.clear(interner);
+
+        data
+    }
+
+    #[test]
+    fn test_read_region_without_owners() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut data = write_single_chunk(&mut interner);
+
+        // the previous version ends every chunk entry with its root, there is no owner count
+        let version_offset = VTR_MAGIC.len();
+        data[version_offset..version_offset + 2].copy_from_slice(&VTR_MIN_VERSION.to_be_bytes());
+        assert_eq!(data.pop(), Some(0));
+
+        let (_, chunks) = read_region(&mut data.as_slice(), &mut interner).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].position_3d(), IVec3::new(1, 2, 3));
+        assert_eq!(chunks[0].get(&interner, IVec3::new(4, 5, 6)), Some(3));
+        assert!(chunks[0].owners().is_empty());
+
+        let mut data = write_single_chunk(&mut interner);
+        data[version_offset..version_offset + 2].copy_from_slice(&(VTR_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            read_region(&mut data.as_slice(), &mut interner),
+            Err(VtmError::UnsupportedVersion(_))
+        ));
+    }
+}
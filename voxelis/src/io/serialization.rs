@@ -0,0 +1,510 @@
+use glam::{IVec3, Vec3};
+use rustc_hash::FxHashMap;
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, DeserializeSeed},
+};
+
+use crate::{
+    BlockId, Lod, MaxDepth, VoxInterner, VoxelTrait,
+    interner::EMPTY_CHILD,
+    spatial::{VoxOpsChunkConfig, VoxOpsConfig, VoxOpsSpatial3D},
+    utils::common::collect_nodes,
+    world::VoxChunk,
+};
+
+#[cfg(feature = "vtm")]
+use crate::world::VoxModel;
+
+// Node of the flattened DAG, children are 1-based indices of earlier nodes in the table
+#[derive(Serialize, Deserialize)]
+enum Node<T> {
+    Leaf(T),
+    Branch { mask: u8, children: Vec<u32> },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkData {
+    position: [i32; 3],
+    max_depth: MaxDepth,
+    chunk_size: [f32; 3],
+    // 1-based root node, `0` for empty chunks
+    root: u32,
+    owners: Vec<([i32; 3], u32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkRecord<T> {
+    nodes: Vec<Node<T>>,
+    chunk: ChunkData,
+}
+
+#[cfg(feature = "vtm")]
+#[derive(Serialize, Deserialize)]
+struct ModelRecord<T> {
+    max_depth: MaxDepth,
+    chunk_world_size: [f32; 3],
+    world_bounds: [i32; 3],
+    origin: [i32; 3],
+    memory_budget: usize,
+    nodes: Vec<Node<T>>,
+    chunks: Vec<ChunkData>,
+}
+
+/// Serializes a [`VoxChunk`] together with the part of the DAG it uses.
+///
+/// Chunks only hold a root id, so the nodes are read from `interner`. They are written as
+/// a flat table, so shared subtrees are stored once.
+pub struct SerializeChunk<'a, T: VoxelTrait> {
+    chunk: &'a VoxChunk<T>,
+    interner: &'a VoxInterner<T>,
+}
+
+impl<'a, T: VoxelTrait> SerializeChunk<'a, T> {
+    pub fn new(chunk: &'a VoxChunk<T>, interner: &'a VoxInterner<T>) -> Self {
+        Self { chunk, interner }
+    }
+}
+
+impl<T: VoxelTrait + Serialize> Serialize for SerializeChunk<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("SerializeChunk::serialize");
+
+        let (nodes, roots) = build_node_table(self.interner, &[self.chunk]);
+
+        ChunkRecord {
+            nodes,
+            chunk: chunk_data(self.chunk, roots[0]),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes a chunk written by [`SerializeChunk`], interning its nodes into `interner`.
+///
+/// Nodes already in `interner` are shared, the returned chunk holds a reference to its root.
+///
+/// ```rust
+/// # use serde::de::DeserializeSeed;
+/// use voxelis::{
+///     MaxDepth, VoxInterner,
+///     io::{ChunkSeed, SerializeChunk},
+///     spatial::{VoxOpsRead, VoxOpsWrite},
+///     world::VoxChunk,
+/// };
+///
+/// let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+/// let mut chunk = VoxChunk::with_position(glam::Vec3::splat(1.0), MaxDepth::new(3), 0, 0, 0);
+/// chunk.set(&mut interner, glam::IVec3::new(1, 2, 3), 7);
+///
+/// let json = serde_json::to_string(&SerializeChunk::new(&chunk, &interner)).unwrap();
+///
+/// let mut other = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+/// let mut deserializer = serde_json::Deserializer::from_str(&json);
+/// let copy = ChunkSeed::new(&mut other).deserialize(&mut deserializer).unwrap();
+///
+/// assert_eq!(copy.get(&other, glam::IVec3::new(1, 2, 3)), Some(7));
+/// ```
+pub struct ChunkSeed<'a, T: VoxelTrait> {
+    interner: &'a mut VoxInterner<T>,
+}
+
+impl<'a, T: VoxelTrait> ChunkSeed<'a, T> {
+    pub fn new(interner: &'a mut VoxInterner<T>) -> Self {
+        Self { interner }
+    }
+}
+
+impl<'de, T: VoxelTrait + Deserialize<'de>> DeserializeSeed<'de> for ChunkSeed<'_, T> {
+    type Value = VoxChunk<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("ChunkSeed::deserialize");
+
+        let record = ChunkRecord::<T>::deserialize(deserializer)?;
+
+        let mut chunks = intern_chunks(self.interner, &record.nodes, &[record.chunk])?;
+
+        Ok(chunks.remove(0))
+    }
+}
+
+#[cfg(feature = "vtm")]
+impl<T: VoxelTrait + Serialize> Serialize for VoxModel<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::serialize");
+
+        let interner = self.interner.read();
+
+        // sorted, so equal models serialize to equal bytes
+        let mut chunks = self.chunks.values().collect::<Vec<_>>();
+        chunks.sort_by_key(|chunk| {
+            let position = chunk.position_3d();
+            (position.x, position.y, position.z)
+        });
+
+        let (nodes, roots) = build_node_table(&interner, &chunks);
+
+        ModelRecord {
+            max_depth: self.max_depth,
+            chunk_world_size: self.chunk_world_size.to_array(),
+            world_bounds: self.world_bounds.to_array(),
+            origin: self.origin().to_array(),
+            memory_budget: interner.memory_budget(),
+            nodes,
+            chunks: chunks
+                .iter()
+                .zip(roots)
+                .map(|(chunk, root)| chunk_data(chunk, root))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "vtm")]
+impl<'de, T: VoxelTrait + Deserialize<'de>> Deserialize<'de> for VoxModel<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::deserialize");
+
+        let record = ModelRecord::<T>::deserialize(deserializer)?;
+
+        let mut model = VoxModel::empty(
+            record.max_depth,
+            Vec3::from_array(record.chunk_world_size),
+            record.memory_budget,
+        );
+        model.world_bounds = IVec3::from_array(record.world_bounds);
+        model.set_origin(IVec3::from_array(record.origin));
+
+        let chunks = {
+            let mut interner = model.interner.write();
+            intern_chunks(&mut interner, &record.nodes, &record.chunks)?
+        };
+
+        model.chunks = chunks
+            .into_iter()
+            .map(|chunk| (chunk.position_3d(), chunk))
+            .collect();
+
+        Ok(model)
+    }
+}
+
+// Flattens the nodes of every chunk into a single post-order table, returns it with the
+// 1-based root of each chunk
+fn build_node_table<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    chunks: &[&VoxChunk<T>],
+) -> (Vec<Node<T>>, Vec<u32>) {
+    let mut node_ids = FxHashMap::default();
+    let mut node_order = Vec::new();
+
+    let roots = chunks
+        .iter()
+        .map(|chunk| {
+            collect_nodes(
+                interner,
+                chunk.get_root_id(),
+                &mut node_ids,
+                &mut node_order,
+            )
+        })
+        .collect();
+
+    let nodes = node_order
+        .iter()
+        .map(|node_id| {
+            if node_id.is_leaf() {
+                Node::Leaf(*interner.get_value(node_id))
+            } else {
+                Node::Branch {
+                    mask: node_id.mask(),
+                    children: interner
+                        .get_children_ref(node_id)
+                        .iter()
+                        .filter(|child_id| !child_id.is_empty())
+                        .map(|child_id| node_ids[child_id])
+                        .collect(),
+                }
+            }
+        })
+        .collect();
+
+    (nodes, roots)
+}
+
+fn chunk_data<T: VoxelTrait>(chunk: &VoxChunk<T>, root: u32) -> ChunkData {
+    let mut owners = chunk
+        .owners()
+        .iter()
+        .map(|(position, owner)| (position.to_array(), *owner))
+        .collect::<Vec<_>>();
+    owners.sort_unstable();
+
+    ChunkData {
+        position: chunk.position_3d().to_array(),
+        max_depth: chunk.max_depth(Lod::new(0)),
+        chunk_size: chunk.chunk_size().to_array(),
+        root,
+        owners,
+    }
+}
+
+// Interns the node table and builds the chunks, each holding a reference to its root
+fn intern_chunks<T: VoxelTrait, E: de::Error>(
+    interner: &mut VoxInterner<T>,
+    nodes: &[Node<T>],
+    chunks: &[ChunkData],
+) -> Result<Vec<VoxChunk<T>>, E> {
+    let mut node_ids: Vec<BlockId> = Vec::with_capacity(nodes.len());
+
+    let result = intern_nodes(interner, nodes, &mut node_ids)
+        .and_then(|_| build_chunks(interner, &node_ids, chunks));
+
+    // chunks hold their own references now, drop the ones owned by the node table
+    for node_id in node_ids.iter().rev() {
+        interner.dec_ref_recursive(node_id);
+    }
+
+    result
+}
+
+fn intern_nodes<T: VoxelTrait, E: de::Error>(
+    interner: &mut VoxInterner<T>,
+    nodes: &[Node<T>],
+    node_ids: &mut Vec<BlockId>,
+) -> Result<(), E> {
+    for node in nodes.iter() {
+        let node_id = match node {
+            Node::Leaf(value) => {
+                if value.voxel_eq(&T::default()) {
+                    return Err(E::custom(format!("empty leaf node {}", node_ids.len() + 1)));
+                }
+
+                interner.get_or_create_leaf(*value)
+            }
+            Node::Branch { mask, children } => {
+                if *mask == 0 || mask.count_ones() as usize != children.len() {
+                    return Err(E::custom(format!(
+                        "invalid branch node {}",
+                        node_ids.len() + 1
+                    )));
+                }
+
+                let mut branch_children = EMPTY_CHILD;
+                let mut types = 0u8;
+
+                let child_indices = (0..branch_children.len()).filter(|i| mask & (1 << i) != 0);
+
+                for (child_idx, index) in child_indices.zip(children.iter()) {
+                    // post-order, so a child must already be in the table
+                    if *index == 0 || *index as usize > node_ids.len() {
+                        return Err(E::custom(format!("invalid child node {index}")));
+                    }
+
+                    let child = node_ids[*index as usize - 1];
+                    branch_children[child_idx] = child;
+                    if child.is_leaf() {
+                        types |= 1 << child_idx;
+                    }
+                }
+
+                for child in branch_children.iter().filter(|child| !child.is_empty()) {
+                    interner.inc_ref(child);
+                }
+
+                interner.get_or_create_branch(branch_children, types, *mask)
+            }
+        };
+
+        node_ids.push(node_id);
+    }
+
+    Ok(())
+}
+
+fn build_chunks<T: VoxelTrait, E: de::Error>(
+    interner: &mut VoxInterner<T>,
+    node_ids: &[BlockId],
+    chunks: &[ChunkData],
+) -> Result<Vec<VoxChunk<T>>, E> {
+    // validate every chunk first, so no chunk holds references on error
+    for data in chunks.iter() {
+        if data.root as usize > node_ids.len() {
+            return Err(E::custom(format!("invalid root node {}", data.root)));
+        }
+    }
+
+    let mut result = Vec::with_capacity(chunks.len());
+
+    for data in chunks.iter() {
+        let [x, y, z] = data.position;
+        let mut chunk =
+            VoxChunk::with_position(Vec3::from_array(data.chunk_size), data.max_depth, x, y, z);
+
+        if data.root != 0 {
+            chunk.set_root_id(interner, node_ids[data.root as usize - 1]);
+        }
+
+        for (position, owner) in data.owners.iter() {
+            chunk.set_owner(interner, IVec3::from_array(*position), *owner);
+        }
+
+        result.push(chunk);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::{
+        spatial::{VoxOpsBatch, VoxOpsBulkWrite},
+        utils::{common::to_vec, shapes::generate_terrain_batch},
+    };
+
+    use super::*;
+
+    fn build_terrain_chunk(interner: &mut VoxInterner<i32>) -> VoxChunk<i32> {
+        let mut chunk = VoxChunk::with_position(Vec3::splat(1.0), MaxDepth::new(4), 1, 0, 2);
+
+        let mut batch = chunk.create_batch();
+        generate_terrain_batch(&mut batch, 1.0 / 16.0, 25.0, Vec3::ZERO, false);
+        chunk.apply_batch(interner, &batch);
+        chunk.set_owner(interner, IVec3::ZERO, 3);
+
+        chunk
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = build_terrain_chunk(&mut interner);
+
+        let json = serde_json::to_string(&SerializeChunk::new(&chunk, &interner)).unwrap();
+
+        let mut other = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let mut copy = ChunkSeed::new(&mut other)
+            .deserialize(&mut deserializer)
+            .unwrap();
+
+        assert_eq!(copy.position_3d(), chunk.position_3d());
+        assert_eq!(copy.max_depth(Lod::new(0)).max(), 4);
+        assert_eq!(copy.owners(), chunk.owners());
+        assert_eq!(
+            to_vec(&other, &copy.get_root_id(), MaxDepth::new(4)),
+            to_vec(&interner, &chunk.get_root_id(), MaxDepth::new(4))
+        );
+
+        // the node table only holds references through the chunk
+        assert_eq!(other.get_ref(&copy.get_root_id()), 1);
+        copy.clear(&mut other);
+        assert!(other.patterns_empty());
+
+        chunk.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_chunk_deserialize_shares_nodes() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = build_terrain_chunk(&mut interner);
+
+        let json = serde_json::to_string(&SerializeChunk::new(&chunk, &interner)).unwrap();
+
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let mut copy = ChunkSeed::new(&mut interner)
+            .deserialize(&mut deserializer)
+            .unwrap();
+
+        assert_eq!(copy.get_root_id(), chunk.get_root_id());
+        assert_eq!(interner.get_ref(&chunk.get_root_id()), 2);
+
+        copy.clear(&mut interner);
+        chunk.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_chunk_deserialize_rejects_invalid_nodes() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // child referencing a node later in the table
+        let json = r#"{"nodes":[{"Branch":{"mask":1,"children":[2]}},{"Leaf":1}],
+            "chunk":{"position":[0,0,0],"max_depth":3,"chunk_size":[1.0,1.0,1.0],"root":1,"owners":[]}}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let result = ChunkSeed::new(&mut interner).deserialize(&mut deserializer);
+        assert!(result.is_err());
+
+        // root outside of the table
+        let json = r#"{"nodes":[{"Leaf":1}],
+            "chunk":{"position":[0,0,0],"max_depth":3,"chunk_size":[1.0,1.0,1.0],"root":2,"owners":[]}}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let result = ChunkSeed::new(&mut interner).deserialize(&mut deserializer);
+        assert!(result.is_err());
+
+        // max depth outside of the allowed range
+        let json = r#"{"nodes":[],
+            "chunk":{"position":[0,0,0],"max_depth":200,"chunk_size":[1.0,1.0,1.0],"root":0,"owners":[]}}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let result = ChunkSeed::new(&mut interner).deserialize(&mut deserializer);
+        assert!(result.is_err());
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "vtm")]
+    fn test_model_round_trip() {
+        let mut model = VoxModel::empty(MaxDepth::new(4), Vec3::splat(1.0), 1024 * 1024);
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+            for x in 0..3 {
+                let chunk = model.get_or_create_chunk(IVec3::new(x, 0, 0));
+                let mut batch = chunk.create_batch();
+                generate_terrain_batch(&mut batch, 1.0 / 16.0, 25.0, Vec3::ZERO, false);
+                chunk.apply_batch(&mut interner, &batch);
+            }
+        }
+        model.set_origin(IVec3::new(1, 0, 0));
+
+        let json = serde_json::to_string(&model).unwrap();
+        let copy: VoxModel<i32> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(copy.chunks.len(), 3);
+        assert_eq!(copy.origin(), model.origin());
+        assert_eq!(copy.world_bounds, model.world_bounds);
+
+        let interner = model.interner.read();
+        let copy_interner = copy.interner.read();
+        assert_eq!(copy_interner.memory_budget(), interner.memory_budget());
+
+        // equal chunks still share a single root
+        let root_id = copy.chunks[&IVec3::ZERO].get_root_id();
+        for (position, chunk) in copy.chunks.iter() {
+            assert_eq!(chunk.get_root_id(), root_id);
+            assert_eq!(
+                to_vec(&copy_interner, &chunk.get_root_id(), MaxDepth::new(4)),
+                to_vec(
+                    &interner,
+                    &model.chunks[position].get_root_id(),
+                    MaxDepth::new(4)
+                )
+            );
+        }
+        assert_eq!(copy_interner.get_ref(&root_id), 3);
+
+        // same content, same bytes
+        drop(copy_interner);
+        assert_eq!(serde_json::to_string(&copy).unwrap(), json);
+    }
+}
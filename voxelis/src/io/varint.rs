@@ -1,4 +1,4 @@
-use std::io::BufReader;
+use std::io::Read;
 
 use byteorder::ReadBytesExt;
 
@@ -53,7 +53,7 @@ pub fn decode_varint_u32(iter: &mut std::slice::Iter<u8>) -> Option<u32> {
     Some(result)
 }
 
-pub fn decode_varint_u32_from_reader(reader: &mut BufReader<&[u8]>) -> Option<u32> {
+pub fn decode_varint_u32_from_reader<R: Read>(reader: &mut R) -> Option<u32> {
     let mut result = 0u32;
     let mut shift = 0;
 
@@ -0,0 +1,335 @@
+use std::{
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use glam::{IVec3, Vec3};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Batch, MaxDepth, VoxInterner,
+    spatial::{VoxOpsBatch, VoxOpsSpatial3D},
+    world::VoxChunk,
+};
+
+const VOX_MAGIC: [u8; 4] = *b"VOX ";
+const VOX_VERSIONS: [u32; 2] = [150, 200];
+
+const CHUNK_MAIN: [u8; 4] = *b"MAIN";
+const CHUNK_SIZE: [u8; 4] = *b"SIZE";
+const CHUNK_XYZI: [u8; 4] = *b"XYZI";
+const CHUNK_RGBA: [u8; 4] = *b"RGBA";
+
+/// RGBA colors of a .vox palette, indexed by the voxel value. Entry `0` is the empty voxel.
+pub type VoxPalette = [[u8; 4]; 256];
+
+/// Chunks of an imported .vox model, keyed by their position on the chunk grid.
+pub type VoxChunks = Vec<(IVec3, VoxChunk<i32>)>;
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(std::io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    /// Chunk with a missing or malformed content, e.g. a voxel outside its model size.
+    InvalidChunk([u8; 4]),
+    /// The file holds no `SIZE` and `XYZI` pair.
+    MissingModel,
+}
+
+impl std::fmt::Display for VoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::InvalidMagic => write!(f, "not a MagicaVoxel file"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported .vox version: {version}"),
+            Self::InvalidChunk(id) => {
+                write!(f, "invalid .vox chunk: {}", String::from_utf8_lossy(id))
+            }
+            Self::MissingModel => write!(f, "no model in .vox file"),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VoxError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Imports a MagicaVoxel .vox file, see [`read_vox`].
+pub fn import_vox<P: AsRef<Path>>(
+    path: &P,
+    interner: &mut VoxInterner<i32>,
+    max_depth: MaxDepth,
+    chunk_world_size: Vec3,
+) -> Result<(VoxChunks, Option<VoxPalette>), VoxError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("import_vox");
+
+    let file = std::fs::File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    read_vox(&mut reader, interner, max_depth, chunk_world_size)
+}
+
+/// Reads a MagicaVoxel .vox file, interning its voxels into `interner`.
+///
+/// Voxel values are the palette indices (`1..=255`), the colors are returned separately,
+/// or `None` if the file uses the default MagicaVoxel palette. The model is split into
+/// chunks of `max_depth`, sorted by position, each holding a reference to its root.
+///
+/// MagicaVoxel is `Z`-up, so `(x, y, z)` maps to `(x, z, size.y - 1 - y)`. Only the first
+/// model is imported, the scene graph and materials are ignored.
+pub fn read_vox<R: Read>(
+    reader: &mut R,
+    interner: &mut VoxInterner<i32>,
+    max_depth: MaxDepth,
+    chunk_world_size: Vec3,
+) -> Result<(VoxChunks, Option<VoxPalette>), VoxError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("read_vox");
+
+    let mut magic = [0u8; VOX_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != VOX_MAGIC {
+        return Err(VoxError::InvalidMagic);
+    }
+
+    let version = reader.read_u32::<LittleEndian>()?;
+    if !VOX_VERSIONS.contains(&version) {
+        return Err(VoxError::UnsupportedVersion(version));
+    }
+
+    let (id, _, children_size) = read_chunk(reader)?;
+    if id != CHUNK_MAIN {
+        return Err(VoxError::InvalidChunk(id));
+    }
+
+    let mut children = Vec::new();
+    reader
+        .take(children_size as u64)
+        .read_to_end(&mut children)?;
+    let mut children = children.as_slice();
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = None;
+
+    while !children.is_empty() {
+        let (id, content, _) = read_chunk(&mut children)?;
+        let mut content = content.as_slice();
+
+        match id {
+            CHUNK_SIZE if size.is_none() => {
+                let x = content.read_u32::<LittleEndian>()?;
+                let y = content.read_u32::<LittleEndian>()?;
+                let z = content.read_u32::<LittleEndian>()?;
+                size = Some(IVec3::new(x as i32, y as i32, z as i32));
+            }
+            // voxels of the first model, always directly after its size
+            CHUNK_XYZI if voxels.is_none() => {
+                let count = content.read_u32::<LittleEndian>()? as usize;
+                if content.len() < count * 4 {
+                    return Err(VoxError::InvalidChunk(id));
+                }
+                voxels = Some(content[..count * 4].to_vec());
+            }
+            CHUNK_RGBA => {
+                // color `i` of the chunk belongs to the palette index `i + 1`
+                let mut colors = [[0u8; 4]; 256];
+                for color in colors.iter_mut().skip(1) {
+                    content.read_exact(color)?;
+                }
+                palette = Some(colors);
+            }
+            _ => {}
+        }
+    }
+
+    let (Some(size), Some(voxels)) = (size, voxels) else {
+        return Err(VoxError::MissingModel);
+    };
+
+    let voxels_per_axis = 1 << max_depth.max();
+    let mut batches: FxHashMap<IVec3, Batch<i32>> = FxHashMap::default();
+
+    for voxel in voxels.chunks_exact(4) {
+        let (x, y, z, index) = (voxel[0] as i32, voxel[1] as i32, voxel[2] as i32, voxel[3]);
+
+        if x >= size.x || y >= size.y || z >= size.z {
+            return Err(VoxError::InvalidChunk(CHUNK_XYZI));
+        }
+
+        if index == 0 {
+            continue;
+        }
+
+        let position = IVec3::new(x, z, size.y - 1 - y);
+        let chunk_position = position.div_euclid(IVec3::splat(voxels_per_axis));
+
+        batches
+            .entry(chunk_position)
+            .or_insert_with(|| Batch::new(max_depth))
+            .just_set(
+                position.rem_euclid(IVec3::splat(voxels_per_axis)),
+                index as i32,
+            );
+    }
+
+    let mut chunks = batches
+        .into_iter()
+        .map(|(position, batch)| {
+            let mut chunk = VoxChunk::with_position(
+                chunk_world_size,
+                max_depth,
+                position.x,
+                position.y,
+                position.z,
+            );
+            chunk.apply_batch(interner, &batch);
+            (chunk.position_3d(), chunk)
+        })
+        .collect::<Vec<_>>();
+
+    chunks.sort_by_key(|(position, _)| (position.x, position.y, position.z));
+
+    Ok((chunks, palette))
+}
+
+// Reads a chunk header and its content, returns the id, content and children size
+fn read_chunk<R: Read>(reader: &mut R) -> Result<([u8; 4], Vec<u8>, u32), VoxError> {
+    let mut id = [0u8; 4];
+    reader.read_exact(&mut id)?;
+
+    let content_size = reader.read_u32::<LittleEndian>()?;
+    let children_size = reader.read_u32::<LittleEndian>()?;
+
+    let mut content = Vec::new();
+    reader.take(content_size as u64).read_to_end(&mut content)?;
+    if content.len() != content_size as usize {
+        return Err(VoxError::InvalidChunk(id));
+    }
+
+    Ok((id, content, children_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spatial::{VoxOpsBulkWrite, VoxOpsRead};
+
+    use super::*;
+
+    fn vox_chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    fn build_vox(voxels: &[[u8; 4]]) -> Vec<u8> {
+        let size = [20u32, 3, 2]
+            .iter()
+            .flat_map(|axis| axis.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut xyzi = (voxels.len() as u32).to_le_bytes().to_vec();
+        xyzi.extend(voxels.iter().flatten());
+
+        let rgba = (1..=256u32)
+            .flat_map(|i| [i as u8, 0, 0, 255])
+            .collect::<Vec<_>>();
+
+        let mut children = vox_chunk(&CHUNK_SIZE, &size, &[]);
+        children.extend(vox_chunk(&CHUNK_XYZI, &xyzi, &[]));
+        children.extend(vox_chunk(b"LAYR", &[0; 8], &[]));
+        children.extend(vox_chunk(&CHUNK_RGBA, &rgba, &[]));
+
+        let mut bytes = VOX_MAGIC.to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend(vox_chunk(&CHUNK_MAIN, &[], &children));
+        bytes
+    }
+
+    #[test]
+    fn test_read_vox_splits_into_chunks() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let bytes = build_vox(&[[0, 0, 0, 1], [19, 2, 1, 5], [10, 1, 0, 200]]);
+        let (mut chunks, palette) = read_vox(
+            &mut bytes.as_slice(),
+            &mut interner,
+            MaxDepth::new(3),
+            Vec3::ONE,
+        )
+        .unwrap();
+
+        let positions = chunks
+            .iter()
+            .map(|(position, _)| *position)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![IVec3::ZERO, IVec3::new(1, 0, 0), IVec3::new(2, 0, 0)]
+        );
+
+        assert_eq!(chunks[0].1.get(&interner, IVec3::new(0, 0, 2)), Some(1));
+        assert_eq!(chunks[1].1.get(&interner, IVec3::new(2, 0, 1)), Some(200));
+        assert_eq!(chunks[2].1.get(&interner, IVec3::new(3, 1, 0)), Some(5));
+
+        let palette = palette.unwrap();
+        assert_eq!(palette[0], [0; 4]);
+        assert_eq!(palette[5], [5, 0, 0, 255]);
+        assert_eq!(palette[255], [255, 0, 0, 255]);
+
+        for (_, chunk) in chunks.iter_mut() {
+            chunk.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_read_vox_rejects_invalid_files() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let result = read_vox(
+            &mut b"NotAVoxFile".as_slice(),
+            &mut interner,
+            MaxDepth::new(3),
+            Vec3::ONE,
+        );
+        assert!(matches!(result, Err(VoxError::InvalidMagic)));
+
+        // voxel outside of the model size
+        let bytes = build_vox(&[[20, 0, 0, 1]]);
+        let result = read_vox(
+            &mut bytes.as_slice(),
+            &mut interner,
+            MaxDepth::new(3),
+            Vec3::ONE,
+        );
+        assert!(matches!(result, Err(VoxError::InvalidChunk(CHUNK_XYZI))));
+
+        // truncated in the middle of the voxels
+        let bytes = build_vox(&[[0, 0, 0, 1]]);
+        let result = read_vox(
+            &mut &bytes[..bytes.len() / 3],
+            &mut interner,
+            MaxDepth::new(3),
+            Vec3::ONE,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -14,5 +14,8 @@ pub mod spatial;
 pub mod utils;
 pub mod world;
 
-pub use core::{Batch, BlockId, Lod, MaxDepth, TraversalDepth, VoxelTrait};
+pub use core::{
+    Batch, BlockId, Lod, MaxDepth, Occupied, OctantTransform, Orientation, TraversalDepth,
+    VoxelTrait,
+};
 pub use interner::VoxInterner;
@@ -8,4 +8,4 @@ pub use voxops::{
     VoxOpsChunkWorldContainer, VoxOpsConfig, VoxOpsConvertPositions, VoxOpsDirty, VoxOpsMesh,
     VoxOpsRead, VoxOpsSpatial, VoxOpsSpatial2D, VoxOpsSpatial3D, VoxOpsState, VoxOpsWrite,
 };
-pub use voxtree::VoxTree;
+pub use voxtree::{ApplyState, ComponentInfo, OutOfBounds, RayHit, VoxTree};
@@ -140,11 +140,18 @@ pub trait VoxOpsChunkConfig {
     /// Returns the chunk dimensions in chunks.
     fn chunk_dimensions(&self) -> UVec3;
 
-    /// Returns the chunk size in world units.
-    fn chunk_size(&self) -> f32;
-
-    /// Returns the voxel size in world units for the given level of detail.
-    fn voxel_size(&self, lod: Lod) -> f32;
+    /// Returns the chunk size in world units along each axis.
+    ///
+    /// Chunks may be boxes rather than cubes, while their octrees stay cubic in voxels.
+    fn chunk_size(&self) -> Vec3;
+
+    /// Returns the voxel size in world units along each axis for the given level of detail.
+    fn voxel_size(&self, lod: Lod) -> Vec3;
+
+    /// Returns the mesh vertex offset of the chunk at the given position.
+    fn chunk_mesh_offset(&self, chunk_position: IVec3) -> Vec3 {
+        chunk_position.as_vec3() * self.chunk_size()
+    }
 }
 
 /// Trait for local chunk container operations.
@@ -1,18 +1,30 @@
 use std::marker::PhantomData;
 
-use glam::IVec3;
+use glam::{IVec3, Vec3};
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
-    Batch, BlockId, Lod, MaxDepth, TraversalDepth, VoxInterner, VoxelTrait, child_index_macro,
-    child_index_macro_2,
-    interner::{EMPTY_CHILD, MAX_ALLOWED_DEPTH, MAX_CHILDREN},
-    utils::common::get_at_depth,
+    Batch, BlockId, Lod, MaxDepth, OctantTransform, TraversalDepth, VoxInterner, VoxelTrait,
+    child_index_macro, child_index_macro_2,
+    core::{strict_assert, strict_assert_ne},
+    interner::{Children, EMPTY_CHILD, MAX_ALLOWED_DEPTH, MAX_CHILDREN},
+    utils::common::{count_occupied_voxels, get_at_depth, morton_child_index, morton_encode_many},
 };
 
 use super::{
     VoxOpsBatch, VoxOpsBulkWrite, VoxOpsConfig, VoxOpsDirty, VoxOpsRead, VoxOpsState, VoxOpsWrite,
 };
 
+// Offsets of the six face neighbors of a voxel, the structuring element of `dilate` and `erode`
+const FACE_OFFSETS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
 /// Lookup table for fast sibling scanning in octree traversal using Morton-encoded paths.
 ///
 /// `PATH_MASKS[max_depth][level]` provides a bitmask indicating which sibling nodes
@@ -104,6 +116,82 @@ const PATH_MASKS: [[u32; MAX_ALLOWED_DEPTH - 1]; MAX_ALLOWED_DEPTH] = [
     ],
 ];
 
+/// Progress of a batch applied in time slices with [`VoxTree::apply_batch_budgeted`].
+///
+/// Start with [`ApplyState::default`] and pass the returned state back to the next call
+/// until [`ApplyState::is_done`] returns `true`. The state keeps references to the nodes
+/// built so far, dropping it before it is done leaves them in the interner.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyState {
+    filled: bool,
+    walk: Option<BatchWalk>,
+    changed: bool,
+    done: bool,
+}
+
+impl ApplyState {
+    /// Returns `true` once every octant path of the batch has been applied.
+    #[must_use]
+    pub const fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns `true` if any of the slices applied so far changed the tree.
+    #[must_use]
+    pub const fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+/// First solid voxel hit by a ray, as returned by [`VoxTree::raycast`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit<T: VoxelTrait> {
+    /// Position of the voxel that was hit.
+    pub position: IVec3,
+    /// Value of the voxel that was hit.
+    pub value: T,
+    /// Normal of the face the ray entered the voxel through, `IVec3::ZERO` if the ray
+    /// started inside it.
+    pub normal: IVec3,
+    /// Distance from the ray origin to the hit.
+    pub t: f32,
+}
+
+/// 6-connected group of set voxels, as returned by [`VoxTree::connected_components`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentInfo {
+    /// Index of the component, in the order their first voxel is visited by
+    /// [`VoxTree::iter_voxels`].
+    pub label: u32,
+    /// Number of voxels in the component.
+    pub voxel_count: u64,
+    /// Minimum corner of the component's bounding box, inclusive.
+    pub min: IVec3,
+    /// Maximum corner of the component's bounding box, inclusive.
+    pub max: IVec3,
+}
+
+/// Position outside of a tree, as returned by [`VoxTree::try_set`] and [`VoxTree::try_get`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds {
+    /// The rejected position.
+    pub position: IVec3,
+    /// Voxels along each axis of the tree, valid coordinates are `0..voxels_per_axis`.
+    pub voxels_per_axis: i32,
+}
+
+impl std::fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "position {} is outside of the tree, coordinates must be in 0..{}",
+            self.position, self.voxels_per_axis
+        )
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
 /// VoxTree - a high performance, SVO DAG (Sparse Voxel Octree Directed Acyclic Graph) structure.
 pub struct VoxTree<T: VoxelTrait> {
     max_depth: MaxDepth,
@@ -125,6 +213,81 @@ impl<T: VoxelTrait> VoxTree<T> {
         }
     }
 
+    /// Builds a tree from a dense grid of `2^max_depth` voxels per axis, in the layout of
+    /// [`crate::utils::common::to_vec`]: `x` fastest, then `z`, then `y`. Default values are
+    /// empty voxels.
+    ///
+    /// The tree is built bottom-up, every 2x2x2 block is merged into its parent as soon as its
+    /// children are known, so uniform blocks collapse into single leaves on the way up
+    /// rather than being set voxel by voxel and collapsed afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` doesn't hold exactly one value per voxel.
+    pub fn from_dense(interner: &mut VoxInterner<T>, data: &[T], max_depth: MaxDepth) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::from_dense");
+
+        let size = 1usize << max_depth.max();
+        assert_eq!(
+            data.len(),
+            size * size * size,
+            "Dense data must hold {size}^3 voxels"
+        );
+
+        Self {
+            max_depth,
+            root_id: dense_nodes(interner, data, size, IVec3::ZERO, size),
+            dirty: true,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Like [`VoxOpsWrite::set`], but returns an error for a position outside of the tree
+    /// instead of panicking, e.g. for positions coming from user input.
+    pub fn try_set(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        position: IVec3,
+        voxel: T,
+    ) -> Result<bool, OutOfBounds> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::try_set");
+
+        self.check_bounds(position)?;
+
+        Ok(self.set(interner, position, voxel))
+    }
+
+    /// Like [`VoxOpsRead::get`], but returns an error for a position outside of the tree
+    /// instead of panicking.
+    pub fn try_get(
+        &self,
+        interner: &VoxInterner<T>,
+        position: IVec3,
+    ) -> Result<Option<T>, OutOfBounds> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::try_get");
+
+        self.check_bounds(position)?;
+
+        Ok(self.get(interner, position))
+    }
+
+    fn check_bounds(&self, position: IVec3) -> Result<(), OutOfBounds> {
+        let voxels_per_axis = 1 << self.max_depth.max();
+
+        if position.cmplt(IVec3::ZERO).any() || position.cmpge(IVec3::splat(voxels_per_axis)).any()
+        {
+            return Err(OutOfBounds {
+                position,
+                voxels_per_axis,
+            });
+        }
+
+        Ok(())
+    }
+
     pub fn get_root_id(&self) -> BlockId {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("VoxTree::get_root_id");
@@ -139,1232 +302,3687 @@ impl<T: VoxelTrait> VoxTree<T> {
         self.root_id = root_id;
         interner.inc_ref(&self.root_id);
     }
-}
-
-impl<T: VoxelTrait> VoxOpsRead<T> for VoxTree<T> {
-    fn get(&self, interner: &VoxInterner<T>, position: IVec3) -> Option<T> {
-        assert!(position.x >= 0 && position.x < (1 << self.max_depth.max()));
-        assert!(position.y >= 0 && position.y < (1 << self.max_depth.max()));
-        assert!(position.z >= 0 && position.z < (1 << self.max_depth.max()));
 
+    /// Replaces the root with its new id after [`VoxInterner::compact`].
+    ///
+    /// The root must have been passed to `compact`, which already holds its reference.
+    pub fn remap_root(&mut self, remap: &FxHashMap<BlockId, BlockId>) {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxTree::get");
+        let _span = tracy_client::span!("VoxTree::remap_root");
 
-        get_at_depth(
-            interner,
-            self.root_id,
-            &position,
-            &TraversalDepth::new(0, self.max_depth.max()),
-        )
+        if let Some(root_id) = remap.get(&self.root_id) {
+            self.root_id = *root_id;
+        }
     }
-}
-
-impl<T: VoxelTrait> VoxOpsWrite<T> for VoxTree<T> {
-    fn set(&mut self, interner: &mut VoxInterner<T>, position: IVec3, voxel: T) -> bool {
-        assert!(position.x >= 0 && position.x < (1 << self.max_depth.max()));
-        assert!(position.y >= 0 && position.y < (1 << self.max_depth.max()));
-        assert!(position.z >= 0 && position.z < (1 << self.max_depth.max()));
 
+    /// Applies `batch` in steps of at most `max_paths` octant paths, resuming from `state`.
+    ///
+    /// Large batches can be spread across frames this way, the final tree is the same as
+    /// with a single [`VoxOpsBatch::apply_batch`]. Every call picks up the descent where the
+    /// previous one stopped, first building the nodes of the touched paths, then merging
+    /// them level by level up to the root, which replaces the old one in the last call. The
+    /// tree and the batch must not change between calls.
+    pub fn apply_batch_budgeted(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        batch: &Batch<T>,
+        state: ApplyState,
+        max_paths: usize,
+    ) -> ApplyState {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxTree::set");
+        let _span = tracy_client::span!("VoxTree::apply_batch_budgeted");
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        {
-            println!("\n");
-            println!("set position: {position:?} voxel: {voxel}");
+        assert!(max_paths > 0, "Budget must allow at least one path");
+
+        let mut state = state;
+
+        if state.done {
+            return state;
         }
 
-        let new_root_id = if !self.root_id.is_empty() {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            {
-                println!("Some(root) set position: {position:?} voxel: {voxel}");
-                interner.dump_node(self.root_id, 0, "  ");
+        if !state.filled {
+            // the fill replaces the whole tree, so it has to land before any patches
+            if let Some(value) = batch.to_fill() {
+                let mut fill = Batch::new(self.max_depth);
+                fill.just_fill(value);
+                state.changed |= self.apply_batch(interner, &fill);
             }
+            state.filled = true;
 
-            // Existing root - modify
-            set_at_root(
-                interner,
-                &self.root_id,
-                &position,
-                self.max_depth.max(),
-                voxel,
-            )
-        } else if voxel != T::default() {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            {
-                println!("None set position: {position:?} voxel: {voxel}");
-                interner.dump_node(self.root_id, 0, "  ");
+            if !batch.has_patches() {
+                state.done = true;
+                return state;
             }
+        }
 
-            set_at_root(
-                interner,
-                &self.root_id,
-                &position,
-                self.max_depth.max(),
-                voxel,
-            )
-        } else {
-            return false;
+        let walk = state.walk.get_or_insert_with(|| {
+            let depth = TraversalDepth::new(0, self.max_depth.max());
+            BatchWalk::new(self.root_id, &depth, batch)
+        });
+
+        let mut budget = max_paths;
+
+        if !walk.prepare(interner, batch, &mut budget) {
+            return state;
+        }
+
+        let Some(new_root_id) = walk.merge(interner, &mut budget) else {
+            return state;
         };
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!("\n setting new_root");
+        state.walk = None;
+        state.changed |= self.replace_root(interner, new_root_id);
+        state.done = true;
 
-        if new_root_id != BlockId::INVALID {
-            if !self.root_id.is_empty() {
-                assert_ne!(new_root_id, self.root_id);
+        state
+    }
 
-                #[cfg(feature = "debug_trace_ref_counts")]
-                {
-                    println!("existing_root_id:");
-                    interner.dump_node(self.root_id, 0, "  ");
-                    println!("new_root_id:");
-                    interner.dump_node(new_root_id, 0, "  ");
-                    println!(
-                        "  existing_root_id: {:?} new_root: {new_root_id:?}",
-                        self.root_id,
-                    );
-                }
+    // Swaps in the root built by a batch, `BlockId::INVALID` when the batch changed nothing
+    fn replace_root(&mut self, interner: &mut VoxInterner<T>, new_root_id: BlockId) -> bool {
+        if new_root_id == BlockId::INVALID {
+            return false;
+        }
 
-                interner.dec_ref_recursive(&self.root_id);
+        if !self.root_id.is_empty() {
+            strict_assert_ne!(new_root_id, self.root_id);
 
-                #[cfg(feature = "debug_trace_ref_counts")]
-                {
-                    println!("new_root after recycling existing_root position: {position:?}");
-                    interner.dump_node(new_root_id, 0, "  ");
-                }
-            } else {
-                #[cfg(feature = "debug_trace_ref_counts")]
-                {
-                    println!("new_root_id:");
-                    interner.dump_node(new_root_id, 0, "  ");
-                    println!("  new_root: {new_root_id:?}");
-                }
-            }
+            interner.dec_ref_recursive(&self.root_id);
+        }
 
-            assert!(
-                interner.is_valid_block_id(&new_root_id),
-                "Invalid new root id: {new_root_id:?}"
-            );
+        strict_assert!(
+            new_root_id.is_empty() || interner.is_valid_block_id(&new_root_id),
+            "Invalid new root id: {new_root_id:?}"
+        );
 
-            self.root_id = new_root_id;
-            self.dirty = true;
+        self.root_id = new_root_id;
+        self.dirty = true;
 
-            true
-        } else {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!("new_root is None");
+        true
+    }
 
-            false
-        }
+    /// Adds the voxels of `other` to this tree, voxels set in both keep the value of `self`.
+    ///
+    /// Both trees have to live in `interner`. Subtrees shared by both, or covered by only one
+    /// of them, are reused without being visited. Returns `true` if the tree changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the trees have different max depths.
+    pub fn union(&mut self, interner: &mut VoxInterner<T>, other: &VoxTree<T>) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::union");
+
+        self.combine(interner, other, CsgOp::Union)
     }
-}
 
-impl<T: VoxelTrait> VoxOpsBulkWrite<T> for VoxTree<T> {
-    fn fill(&mut self, interner: &mut VoxInterner<T>, value: T) {
+    /// Keeps only the voxels set in both trees, with the values of `self`.
+    ///
+    /// See [`VoxTree::union`] for the requirements. Returns `true` if the tree changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the trees have different max depths.
+    pub fn intersect(&mut self, interner: &mut VoxInterner<T>, other: &VoxTree<T>) -> bool {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxTree::fill");
+        let _span = tracy_client::span!("VoxTree::intersect");
 
-        if value != T::default() {
-            if !self.root_id.is_empty() {
-                interner.dec_ref_recursive(&self.root_id);
-            }
-            self.root_id = interner.get_or_create_leaf(value);
-            self.dirty = true;
-        } else {
-            self.clear(interner);
-        }
+        self.combine(interner, other, CsgOp::Intersect)
     }
 
-    fn clear(&mut self, interner: &mut VoxInterner<T>) {
+    /// Removes every voxel set in `other` from this tree, e.g. to carve caves into terrain.
+    ///
+    /// See [`VoxTree::union`] for the requirements. Returns `true` if the tree changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the trees have different max depths.
+    pub fn difference(&mut self, interner: &mut VoxInterner<T>, other: &VoxTree<T>) -> bool {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxTree::clear");
+        let _span = tracy_client::span!("VoxTree::difference");
 
-        if !self.root_id.is_empty() {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!("clear root_id: {:?}", self.root_id);
+        self.combine(interner, other, CsgOp::Difference)
+    }
 
-            interner.dec_ref_recursive(&self.root_id);
+    fn combine(&mut self, interner: &mut VoxInterner<T>, other: &VoxTree<T>, op: CsgOp) -> bool {
+        assert_eq!(
+            self.max_depth.max(),
+            other.max_depth.max(),
+            "CSG operands must have the same max depth"
+        );
 
-            self.root_id = BlockId::EMPTY;
-            self.dirty = true;
+        let new_root_id = combine_nodes(interner, self.root_id, other.root_id, op);
+
+        if new_root_id == self.root_id {
+            // the result holds its own reference, the tree already has one
+            if !new_root_id.is_empty() {
+                interner.dec_ref(&new_root_id);
+            }
+
+            return false;
         }
-    }
-}
 
-impl<T: VoxelTrait> VoxOpsBatch<T> for VoxTree<T> {
-    fn create_batch(&self) -> Batch<T> {
-        #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxTree::create_batch");
+        if !self.root_id.is_empty() {
+            interner.dec_ref_recursive(&self.root_id);
+        }
 
-        Batch::new(self.max_depth)
+        self.root_id = new_root_id;
+        self.dirty = true;
+
+        true
     }
 
-    fn apply_batch(&mut self, interner: &mut VoxInterner<T>, batch: &Batch<T>) -> bool {
+    /// Sets every voxel of the box spanning `min..=max` to `value`, clearing it when `value`
+    /// is the default.
+    ///
+    /// Octants fully inside the box become a single leaf and octants outside of it are kept
+    /// as they are, so only the octants crossing the box surface are descended into. The box
+    /// is clipped to the tree and its corners can be given in any order. Returns `true` if
+    /// the tree changed.
+    pub fn fill_region(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        min: IVec3,
+        max: IVec3,
+        value: T,
+    ) -> bool {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxTree::apply_batch");
+        let _span = tracy_client::span!("VoxTree::fill_region");
 
-        let new_root_id = set_batch_at_root(interner, &self.root_id, self.max_depth.max(), batch);
+        let size = 1 << self.max_depth.max();
+        let (min, max) = (min.min(max), min.max(max));
 
-        if new_root_id != BlockId::INVALID {
-            if !self.root_id.is_empty() {
-                assert_ne!(new_root_id, self.root_id);
+        let min = min.max(IVec3::ZERO);
+        let max = max.min(IVec3::splat(size - 1));
 
-                interner.dec_ref_recursive(&self.root_id);
-            }
+        if min.cmpgt(max).any() {
+            return false;
+        }
 
-            assert!(
-                interner.is_valid_block_id(&new_root_id),
-                "Invalid new root id: {new_root_id:?}"
-            );
+        let leaf_id = if value.voxel_eq(&T::default()) {
+            BlockId::EMPTY
+        } else {
+            interner.get_or_create_leaf(value)
+        };
 
-            self.root_id = new_root_id;
-            self.dirty = true;
+        let new_root_id =
+            fill_region_nodes(interner, self.root_id, IVec3::ZERO, size, min, max, leaf_id);
 
-            true
-        } else {
-            false
+        // drop the reference `get_or_create_leaf` handed out, the new nodes hold their own
+        if !leaf_id.is_empty() {
+            interner.dec_ref(&leaf_id);
         }
-    }
-}
 
-impl<T: VoxelTrait> VoxOpsConfig for VoxTree<T> {
-    #[inline(always)]
-    fn max_depth(&self, lod: Lod) -> MaxDepth {
-        self.max_depth.for_lod(lod)
-    }
+        if new_root_id == self.root_id {
+            if !new_root_id.is_empty() {
+                interner.dec_ref(&new_root_id);
+            }
 
-    #[inline(always)]
-    fn voxels_per_axis(&self, lod: Lod) -> u32 {
-        1 << self.max_depth.for_lod(lod).max()
-    }
-}
+            return false;
+        }
 
-impl<T: VoxelTrait> VoxOpsState for VoxTree<T> {
-    #[inline(always)]
-    fn is_empty(&self) -> bool {
-        self.root_id.is_empty()
-    }
+        if !self.root_id.is_empty() {
+            interner.dec_ref_recursive(&self.root_id);
+        }
 
-    #[inline(always)]
-    fn is_leaf(&self) -> bool {
-        self.root_id.is_leaf()
-    }
-}
+        self.root_id = new_root_id;
+        self.dirty = true;
 
-impl<T: VoxelTrait> VoxOpsDirty for VoxTree<T> {
-    #[inline(always)]
-    fn is_dirty(&self) -> bool {
-        self.dirty
+        true
     }
 
-    #[inline(always)]
-    fn mark_dirty(&mut self) {
-        self.dirty = true;
-    }
+    /// Copies every set voxel of the box spanning `min..=max` into a new [`Batch`], at the
+    /// same positions, e.g. the copy half of a copy and paste brush.
+    ///
+    /// Only the octants overlapping the box are descended into, and a leaf found on the way
+    /// is copied for the part of the box it covers. The box is clipped to the tree and its
+    /// corners can be given in any order. Move the copy with [`Batch::translated`] to paste
+    /// it elsewhere.
+    pub fn extract_region(&self, interner: &VoxInterner<T>, min: IVec3, max: IVec3) -> Batch<T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::extract_region");
 
-    #[inline(always)]
-    fn clear_dirty(&mut self) {
-        self.dirty = false;
+        let mut batch = Batch::new(self.max_depth);
+
+        let size = 1 << self.max_depth.max();
+        let (min, max) = (min.min(max), min.max(max));
+
+        let min = min.max(IVec3::ZERO);
+        let max = max.min(IVec3::splat(size - 1));
+
+        if min.cmple(max).all() {
+            extract_region_nodes(
+                interner,
+                self.root_id,
+                IVec3::ZERO,
+                size,
+                min,
+                max,
+                &mut batch,
+            );
+        }
+
+        batch
     }
-}
 
-#[inline(always)]
-fn set_at_root<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    node_id: &BlockId,
-    position: &IVec3,
-    max_depth: u8,
-    voxel: T,
-) -> BlockId {
-    assert!(*node_id != BlockId::INVALID);
+    /// Returns a copy of the tree rotated or mirrored by `transform`, e.g. to place a prefab in
+    /// all 24 orientations without voxelizing it again.
+    ///
+    /// Children are moved to their transformed slots at every level, every distinct node is
+    /// rewritten once and shared subtrees stay shared. The tree itself is left unchanged.
+    pub fn transform(&self, interner: &mut VoxInterner<T>, transform: OctantTransform) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::transform");
 
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("set_at_root");
+        let mut transformed = FxHashMap::default();
 
-    let depth = TraversalDepth::new(0, max_depth);
-    if voxel != T::default() {
-        set_at_depth_iterative(interner, node_id, position, &depth, voxel)
-    } else {
-        remove_at_depth(interner, node_id, position, &depth)
+        Self {
+            max_depth: self.max_depth,
+            root_id: transform_nodes(interner, self.root_id, transform, &mut transformed),
+            dirty: true,
+            _marker: PhantomData,
+        }
     }
-}
 
-fn set_at_depth_iterative<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    initial_node_id: &BlockId,
-    position: &IVec3,
-    initial_depth: &TraversalDepth,
-    voxel: T,
-) -> BlockId {
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!(
-        "set_at_depth_iterative initial_node: {initial_node_id:?} position: {position:?} voxel: {voxel}"
-    );
+    /// Replaces every voxel value with `f(value)` and keeps the geometry, e.g. to remap
+    /// material ids after merging models from different sources.
+    ///
+    /// Every distinct node is rewritten once, so `f` runs once per distinct leaf rather than
+    /// per voxel. Leaves that map to the same value are shared again and eight equal leaves
+    /// collapse into their parent. Voxels mapped to `T::default()` are removed, collapsing
+    /// the branches left empty. Returns `true` if the tree changed.
+    pub fn map_values(&mut self, interner: &mut VoxInterner<T>, f: impl Fn(T) -> T) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::map_values");
 
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("set_at_depth_iterative");
+        let mut mapped = FxHashMap::default();
+        let new_root_id = map_values_nodes(interner, self.root_id, &f, &mut mapped);
 
-    let mut stack = [const { (BlockId::INVALID, BlockId::INVALID, u8::MAX) }; MAX_ALLOWED_DEPTH];
-    let mut current_node_id = *initial_node_id;
+        if new_root_id == self.root_id {
+            // the result holds its own reference, the tree already has one
+            if !new_root_id.is_empty() {
+                interner.dec_ref(&new_root_id);
+            }
 
-    let max_depth = initial_depth.max() as usize;
-    let initial_depth = initial_depth.current() as usize;
-    let mut current_depth = initial_depth;
+            return false;
+        }
 
-    // Phase 1: descend down the tree and build the chain
-    #[cfg(feature = "debug_trace_ref_counts")]
-    {
-        println!(" Phase 1 - Find the spot");
-        interner.dump_node(current_node_id, 0, "  ");
-    }
+        if !self.root_id.is_empty() {
+            interner.dec_ref_recursive(&self.root_id);
+        }
 
-    let mut leaf_node_id = BlockId::EMPTY;
-    while current_depth < max_depth {
-        #[cfg(feature = "debug_trace_ref_counts")]
-        let current_node_ref_count = interner.get_ref(&current_node_id);
+        self.root_id = new_root_id;
+        self.dirty = true;
 
-        let index = child_index_macro_2!(position, current_depth, max_depth);
+        true
+    }
 
-        if current_node_id.is_branch() {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                "  depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {current_node_ref_count} [1]"
-            );
+    /// Returns a copy of the tree at half the resolution, one level shallower, with every
+    /// 2x2x2 block of voxels merged into one voxel by majority vote.
+    ///
+    /// A block with four or more empty voxels becomes empty, otherwise it takes the most
+    /// common value among its set voxels, ties going to the one first in child order. Leaves
+    /// covering more than a block are kept as they are, and every distinct subtree is
+    /// downsampled once. Unlike reading the tree at a coarser [`Lod`], this builds a new tree
+    /// in `interner`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has a maximum depth of zero.
+    pub fn downsample(&self, interner: &mut VoxInterner<T>) -> VoxTree<T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::downsample");
 
-            stack[current_depth] = (current_node_id, leaf_node_id, index as u8);
+        let max_depth = self.max_depth.max();
+        assert!(max_depth > 0, "A tree without levels can't be downsampled");
 
-            current_node_id = interner.get_child_id(&current_node_id, index);
-        } else {
-            if interner.get_value(&current_node_id) == &voxel {
-                return BlockId::INVALID; // Nothing changed
-            }
+        let mut downsampled = FxHashMap::default();
+        let root_id = downsample_nodes(interner, self.root_id, max_depth, &mut downsampled);
 
-            // Split leaf node
-            leaf_node_id = current_node_id;
-            current_node_id = BlockId::EMPTY;
+        VoxTree {
+            max_depth: MaxDepth::new(max_depth - 1),
+            root_id,
+            dirty: true,
+            _marker: PhantomData,
+        }
+    }
 
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                "  depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {current_node_ref_count} [2]"
-            );
+    /// Casts a ray and returns the first non-empty voxel it hits within `max_dist`.
+    ///
+    /// The ray is given in voxel units of the tree, with voxel `(x, y, z)` covering
+    /// `[x, x + 1)` on each axis, and `dir` does not need to be normalized. Empty octants are
+    /// skipped as a whole, so crossing a large empty region costs a descent per octant
+    /// instead of a step per voxel. A ray starting inside a solid voxel hits it at `t == 0`.
+    pub fn raycast(
+        &self,
+        interner: &VoxInterner<T>,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+    ) -> Option<RayHit<T>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::raycast");
 
-            stack[current_depth] = (current_node_id, leaf_node_id, index as u8);
+        if self.root_id.is_empty() || max_dist < 0.0 || !origin.is_finite() {
+            return None;
         }
 
-        current_depth += 1;
-    }
+        let max_depth = self.max_depth.max();
+        let size = 1i32 << max_depth;
+
+        let dir = dir.normalize_or_zero();
+        // axes with a zero component are never divided by
+        let sign = |value: f32| (value > 0.0) as i32 - (value < 0.0) as i32;
+        let step = IVec3::new(sign(dir.x), sign(dir.y), sign(dir.z));
+
+        // clip the ray against the tree bounds, axes the ray is parallel to only have to
+        // contain the origin
+        let mut t = 0.0f32;
+        let mut t_exit = max_dist;
+        let mut entry_axis = None;
+        for axis in 0..3 {
+            if step[axis] == 0 {
+                if origin[axis] < 0.0 || origin[axis] >= size as f32 {
+                    return None;
+                }
+                continue;
+            }
 
-    // Phase 2: create a leaf at the correct depth
-    #[cfg(feature = "debug_trace_ref_counts")]
-    {
-        println!(" Phase 2 - Create leaf");
-        println!(
-            "  depth: {current_depth}/{max_depth} current: {current_node_id:?} ref_count: {} is_valid: {}",
-            interner.get_ref(&current_node_id),
-            interner.is_valid_block_id(&current_node_id)
-        );
-    }
+            let t0 = (0.0 - origin[axis]) / dir[axis];
+            let t1 = (size as f32 - origin[axis]) / dir[axis];
+            let (t_near, t_far) = (t0.min(t1), t0.max(t1));
 
-    if current_node_id.is_leaf() && interner.get_value(&current_node_id) == &voxel {
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!("  voxel already exists, no change required");
-        return BlockId::INVALID; // Nothing changed
-    }
+            if t_near > t {
+                t = t_near;
+                entry_axis = Some(axis);
+            }
+            t_exit = t_exit.min(t_far);
+        }
 
-    current_node_id = interner.get_or_create_leaf(voxel);
+        if t > t_exit {
+            return None;
+        }
 
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!(
-        "   current: {current_node_id:?} ref_count: {}",
-        interner.get_ref(&current_node_id)
-    );
+        let point = origin + dir * t;
+        let mut cell = point
+            .floor()
+            .as_ivec3()
+            .clamp(IVec3::ZERO, IVec3::splat(size - 1));
+        let mut normal = IVec3::ZERO;
+        if let Some(axis) = entry_axis {
+            cell[axis] = if step[axis] > 0 { 0 } else { size - 1 };
+            normal[axis] = -step[axis];
+        }
 
-    // Phase 3: propagate upwards and create new branch nodes
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!(" Phase 3 - Propagate upwards");
-    while current_depth > initial_depth {
-        current_depth -= 1;
+        loop {
+            // descend to the node holding `cell`, stopping at the first leaf or empty child
+            let mut node_id = self.root_id;
+            let mut depth = 0;
+            let mut empty = false;
 
-        let (parent_node_id, leaf_node_id, parent_node_index) = stack[current_depth];
+            while node_id.is_branch() && depth < max_depth {
+                let index = child_index_macro_2!(cell, depth, max_depth);
+                depth += 1;
 
-        if !parent_node_id.is_empty() {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                "  depth: {current_depth}/{max_depth} i: {parent_node_index:2x} parent: {parent_node_id:?} current: {current_node_id:?} leaf: {leaf_node_id:?} [B]"
-            );
+                if node_id.mask() & (1 << index) == 0 {
+                    empty = true;
+                    break;
+                }
 
-            #[cfg(feature = "debug_trace_ref_counts")]
-            for (child_idx, child) in interner.get_children(&parent_node_id).iter().enumerate() {
-                if child_idx == parent_node_index as usize {
-                    println!(
-                        "   child[{child_idx}]: {child:?} [{}] => {current_node_id:?} [{}]",
-                        interner.get_ref(child),
-                        interner.get_ref(&current_node_id),
-                    );
-                } else {
-                    println!(
-                        "   child[{child_idx}]: {child:?} [{}]",
-                        interner.get_ref(child)
-                    );
+                node_id = interner.get_child_id(&node_id, index);
+            }
+
+            if !empty {
+                let value = *interner.get_value(&node_id);
+                if !value.voxel_eq(&T::default()) {
+                    return Some(RayHit {
+                        position: cell,
+                        value,
+                        normal,
+                        t,
+                    });
                 }
             }
 
-            let types =
-                parent_node_id.types() | ((current_node_id.is_leaf() as u8) << parent_node_index);
+            // leave the empty octant of `cell` at this depth in one step
+            let octant_size = 1i32 << (max_depth - depth);
+            let octant_min = cell & IVec3::splat(!(octant_size - 1));
+
+            let mut t_next = f32::INFINITY;
+            let mut exit_axis = 0;
+            for axis in 0..3 {
+                let boundary = match step[axis] {
+                    0 => continue,
+                    1 => octant_min[axis] + octant_size,
+                    _ => octant_min[axis],
+                };
+
+                let t_axis = (boundary as f32 - origin[axis]) / dir[axis];
+                if t_axis < t_next {
+                    t_next = t_axis;
+                    exit_axis = axis;
+                }
+            }
 
-            let mut branch = interner.get_children(&parent_node_id);
-            branch[parent_node_index as usize] = current_node_id;
+            if !t_next.is_finite() || t_next > t_exit {
+                return None;
+            }
 
-            if !(types == 0xFF && branch.iter().all(|item| item == &current_node_id)) {
-                let mask = parent_node_id.mask() | (1 << parent_node_index);
+            let point = origin + dir * t_next;
+            let octant_max = octant_min + IVec3::splat(octant_size - 1);
 
-                interner.inc_child_refs(&branch, parent_node_index as usize);
+            cell = point.floor().as_ivec3().clamp(octant_min, octant_max);
+            cell[exit_axis] = if step[exit_axis] > 0 {
+                octant_max[exit_axis] + 1
+            } else {
+                octant_min[exit_axis] - 1
+            };
 
-                current_node_id = interner.get_or_create_branch(branch, types, mask);
+            if cell[exit_axis] < 0 || cell[exit_axis] >= size {
+                return None;
             }
-        } else if leaf_node_id.is_empty() {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                "  depth: {current_depth}/{max_depth} i: {parent_node_index:2x} parent: {parent_node_id:?} current: {current_node_id:?} leaf: {leaf_node_id:?} [E]"
-            );
-
-            let mut children = EMPTY_CHILD;
-            children[parent_node_index as usize] = current_node_id;
-            let types = (current_node_id.is_leaf() as u8) << parent_node_index;
-            let mask = 1 << parent_node_index;
-            current_node_id = interner.get_or_create_branch(children, types, mask);
-        } else {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                "  depth: {current_depth}/{max_depth} i: {parent_node_index:2x} parent: {parent_node_id:?} current: {current_node_id:?} leaf: {leaf_node_id:?} [ESL]"
-            );
 
-            let mut children = [leaf_node_id; MAX_CHILDREN];
-            interner.inc_ref_by(&leaf_node_id, 7);
-            children[parent_node_index as usize] = current_node_id;
-            let types = !(1 << parent_node_index)
-                | ((current_node_id.is_leaf() as u8) << parent_node_index);
-            let mask = 0xFF;
-            current_node_id = interner.get_or_create_branch(children, types, mask);
+            normal = IVec3::ZERO;
+            normal[exit_axis] = -step[exit_axis];
+            t = t.max(t_next);
         }
     }
 
-    #[cfg(feature = "debug_trace_ref_counts")]
-    {
-        println!(" Phase 4 - Finalize");
-        println!("  new_root: {current_node_id:?}");
-        interner.dump_node(current_node_id, 0, "  ");
+    /// Returns an iterator over the non-empty voxels and their positions, depth-first.
+    ///
+    /// Empty octants are never visited, and a leaf above max depth yields every voxel of the
+    /// octant it covers. Unlike [`crate::utils::common::to_vec`] nothing is allocated per
+    /// voxel, the traversal stack is allocated once up front.
+    pub fn iter_voxels<'a>(
+        &self,
+        interner: &'a VoxInterner<T>,
+    ) -> impl Iterator<Item = (IVec3, T)> + use<'a, T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::iter_voxels");
+
+        VoxelIter::new(interner, self.root_id, self.max_depth.max())
     }
 
-    current_node_id
-}
+    /// Returns the number of set voxels, without visiting them one by one.
+    ///
+    /// A leaf counts for the whole cube it covers. The count of a subtree shared by several
+    /// parents is computed once and added for every place it appears.
+    pub fn voxel_count(&self, interner: &VoxInterner<T>) -> u64 {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::voxel_count");
 
-fn remove_at_depth<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    node_id: &BlockId,
-    position: &IVec3,
-    depth: &TraversalDepth,
-) -> BlockId {
-    assert!(*node_id != BlockId::INVALID);
+        count_occupied_voxels(interner, &self.root_id, self.max_depth)
+    }
 
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("remove_at_depth");
+    /// Returns the inclusive corners of the smallest box holding every set voxel, `None` if the
+    /// tree is empty, e.g. to center a camera on a model or crop it.
+    ///
+    /// Only non-empty branches are descended into, a leaf counts for the whole cube it covers
+    /// and subtrees already inside the bounds found so far are skipped.
+    pub fn occupied_bounds(&self, interner: &VoxInterner<T>) -> Option<(IVec3, IVec3)> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::occupied_bounds");
 
-    if node_id.is_branch() {
-        remove_at_depth_branch(interner, node_id, position, depth)
-    } else {
-        remove_at_depth_leaf(interner, node_id, position, depth)
+        let mut bounds = None;
+
+        occupied_bounds_nodes(
+            interner,
+            self.root_id,
+            IVec3::ZERO,
+            1 << self.max_depth.max(),
+            &mut bounds,
+        );
+
+        bounds
     }
-}
 
-fn remove_at_depth_branch<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    node_id: &BlockId,
-    position: &IVec3,
-    depth: &TraversalDepth,
-) -> BlockId {
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!("remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}");
+    /// Returns the node covering `position` at `depth.current()` levels below the root and
+    /// whether it is a leaf, `None` if that part of the tree is empty.
+    ///
+    /// Stops early at a leaf above the requested depth, as the leaf covers the cell. Shared
+    /// subtrees have the same [`BlockId`], e.g. to color them alike in a debug overlay.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth.max()` is not the maximum depth of the tree.
+    pub fn node_at(
+        &self,
+        interner: &VoxInterner<T>,
+        position: IVec3,
+        depth: TraversalDepth,
+    ) -> Option<(BlockId, bool)> {
+        assert!(position.x >= 0 && position.x < (1 << self.max_depth.max()));
+        assert!(position.y >= 0 && position.y < (1 << self.max_depth.max()));
+        assert!(position.z >= 0 && position.z < (1 << self.max_depth.max()));
+        assert_eq!(
+            depth.max(),
+            self.max_depth.max(),
+            "Traversal depth doesn't match the tree depth"
+        );
 
-    assert!(interner.is_valid_block_id(node_id));
-    assert!(depth.current() < depth.max(), "Branch node at max depth");
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::node_at");
 
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("remove_at_depth_branch");
+        let max_depth = depth.max();
+        let mut node_id = self.root_id;
 
-    let index = child_index_macro!(position, depth);
+        for current in 0..depth.current() {
+            if node_id.is_empty() || !node_id.is_branch() {
+                break;
+            }
 
-    if node_id.has_child(index as u8) {
-        let mut branch = interner.get_children(node_id);
-        let child_id = branch[index];
+            let index = child_index_macro_2!(position, current, max_depth);
+            node_id = interner.get_child_id(&node_id, index);
+        }
 
-        let new_child_id = remove_at_depth(interner, &child_id, position, &depth.increment());
+        if node_id.is_empty() {
+            None
+        } else {
+            Some((node_id, !node_id.is_branch()))
+        }
+    }
 
-        if new_child_id != BlockId::INVALID {
-            assert!(interner.is_valid_block_id(&new_child_id));
+    /// Returns the groups of set voxels connected through their faces, regardless of value.
+    ///
+    /// Only set voxels are visited and kept, together with a union-find over them, so memory
+    /// grows with the number of set voxels rather than the volume of the tree.
+    pub fn connected_components(&self, interner: &VoxInterner<T>) -> Vec<ComponentInfo> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::connected_components");
 
-            let is_empty = new_child_id.is_empty();
+        self.label_components(interner).1
+    }
 
-            // let current_mask = node_id.mask();
+    /// Clears every component with fewer than `min_voxels` voxels, e.g. floating specks
+    /// left over from a scan. Returns the number of components removed.
+    pub fn remove_components_smaller_than(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        min_voxels: u64,
+    ) -> usize {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::remove_components_smaller_than");
 
-            let mask = if is_empty {
-                node_id.mask() & !(1 << index)
-            } else {
-                node_id.mask()
-            };
+        let (voxels, components) = self.label_components(interner);
 
-            // Check if all children are empty
-            if mask == 0 {
-                // All children are empty, remove this branch node
-                BlockId::EMPTY
-            } else {
-                // Return the updated branch node
-                let is_branch = new_child_id.is_branch();
-                assert!(is_branch, "Removing voxel should never produce a leaf node");
+        let removed = components
+            .iter()
+            .filter(|component| component.voxel_count < min_voxels)
+            .count();
 
-                // let current_types = node_id.types();
-                let types = node_id.types() & !(1 << index);
-
-                // println!(
-                //     "before types: {current_types:08b} mask: {current_mask:08b}"
-                // );
-                // println!(" after types: {types:08b} mask: {mask:08b}");
-
-                branch[index] = new_child_id;
-
-                #[cfg(feature = "debug_trace_ref_counts")]
-                println!(
-                    ".. [new branch] remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}"
-                );
-
-                interner.inc_child_refs(&branch, index);
-
-                interner.get_or_create_branch(branch, types, mask)
+        if removed > 0 {
+            for (position, label) in voxels {
+                if components[label as usize].voxel_count < min_voxels {
+                    self.set(interner, position, T::default());
+                }
             }
-        } else {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                ".. [invalid new_child_id] remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}"
-            );
-            BlockId::INVALID
         }
-    } else {
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!(
-            ".. [no child] remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}"
-        );
 
-        BlockId::INVALID
+        removed
     }
-}
 
-fn remove_at_depth_leaf<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    node_id: &BlockId,
-    position: &IVec3,
-    depth: &TraversalDepth,
-) -> BlockId {
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!("remove_at_depth_leaf node_id: {node_id:?} position: {position:?} depth: {depth:?}");
+    /// Grows the set voxels by one voxel through each face, `iterations` times, e.g. to close
+    /// small holes. A new voxel takes the value of its first set neighbor along `+X`, `-X`,
+    /// `+Y`, `-Y`, `+Z`, `-Z`, and nothing grows past the bounds of the tree.
+    ///
+    /// Each iteration is applied as one batch. Returns `true` if the tree changed.
+    pub fn dilate(&mut self, interner: &mut VoxInterner<T>, iterations: u32) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::dilate");
 
-    assert!(interner.is_valid_block_id(node_id));
+        let voxels_per_axis = IVec3::splat(1 << self.max_depth.max());
+        let in_bounds = |position: IVec3| {
+            position.cmpge(IVec3::ZERO).all() && position.cmplt(voxels_per_axis).all()
+        };
 
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("remove_at_depth_leaf");
+        let mut voxels = self.iter_voxels(interner).collect::<FxHashMap<_, _>>();
+        let mut changed = false;
 
-    if depth.current() == depth.max() {
-        // At max depth, just remove the leaf
-        BlockId::EMPTY
-    } else {
-        // Remove the voxel in the appropriate child, splitting the leaf always results in a new branch
-        let new_node_id = remove_at_depth_leaf(interner, node_id, position, &depth.increment());
+        for _ in 0..iterations {
+            let mut grown: FxHashMap<IVec3, T> = FxHashMap::default();
 
-        assert!(interner.is_valid_block_id(&new_node_id));
+            for position in voxels.keys() {
+                for offset in FACE_OFFSETS {
+                    let candidate = *position + offset;
 
-        // Convert leaf to branch
-        let index = child_index_macro!(position, depth);
-        let mut children = [*node_id; MAX_CHILDREN];
-        children[index] = new_node_id;
+                    if !in_bounds(candidate)
+                        || voxels.contains_key(&candidate)
+                        || grown.contains_key(&candidate)
+                    {
+                        continue;
+                    }
 
-        let is_leaf = new_node_id.is_leaf();
-        let is_empty = new_node_id.is_empty();
-        let types = !(1 << index) | ((is_leaf as u8) << index);
-        let mask = !(1 << index) | ((!is_empty as u8) << index);
+                    // at least `position` is set, so a value is always found
+                    let value = FACE_OFFSETS
+                        .iter()
+                        .find_map(|offset| voxels.get(&(candidate + *offset)))
+                        .copied()
+                        .unwrap();
+                    grown.insert(candidate, value);
+                }
+            }
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!("incrementing refs for node_id: {node_id:?}");
+            if grown.is_empty() {
+                break;
+            }
 
-        // println!("types: {types:b} mask: {mask:b}");
+            let mut batch = Batch::new(self.max_depth);
+            for (position, value) in &grown {
+                batch.just_set(*position, *value);
+            }
+            self.apply_batch(interner, &batch);
 
-        interner.inc_ref_by(node_id, 7);
+            voxels.extend(grown);
+            changed = true;
+        }
 
-        // Create new branch node
-        interner.get_or_create_branch(children, types, mask)
+        changed
     }
-}
-
-#[inline(always)]
-fn set_batch_at_root<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    node_id: &BlockId,
-    max_depth: u8,
-    batch: &Batch<T>,
-) -> BlockId {
-    assert!(*node_id != BlockId::INVALID);
-
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("set_batch_at_root");
-
-    let depth = TraversalDepth::new(0, max_depth);
 
-    set_batch_at_depth_iterative(interner, node_id, &depth, batch)
-}
+    /// Shrinks the set voxels by one voxel, `iterations` times, clearing every voxel with an
+    /// empty face neighbor. Outside the tree counts as empty, so its boundary erodes too.
+    ///
+    /// Eroding and then dilating as often, an opening, removes specks and parts thinner than
+    /// the erosion while keeping larger shapes. Each iteration is applied as one batch.
+    /// Returns `true` if the tree changed.
+    pub fn erode(&mut self, interner: &mut VoxInterner<T>, iterations: u32) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::erode");
+
+        let mut voxels = self
+            .iter_voxels(interner)
+            .map(|(position, _)| position)
+            .collect::<FxHashSet<_>>();
+        let mut changed = false;
+
+        for _ in 0..iterations {
+            let removed = voxels
+                .iter()
+                .filter(|position| {
+                    FACE_OFFSETS
+                        .iter()
+                        .any(|offset| !voxels.contains(&(**position + *offset)))
+                })
+                .copied()
+                .collect::<Vec<_>>();
+
+            if removed.is_empty() {
+                break;
+            }
 
-fn set_batch_at_depth_iterative<T: VoxelTrait>(
-    interner: &mut VoxInterner<T>,
-    initial_node_id: &BlockId,
-    initial_depth: &TraversalDepth,
-    batch: &Batch<T>,
-) -> BlockId {
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!(
-        "set_batch_at_depth_iterative initial node: {initial_node_id:?} depth: {initial_depth:?} batch size: {}",
-        batch.size()
-    );
+            let mut batch = Batch::new(self.max_depth);
+            for position in &removed {
+                batch.just_set(*position, T::default());
+                voxels.remove(position);
+            }
+            self.apply_batch(interner, &batch);
 
-    #[cfg(feature = "tracy")]
-    let _span = tracy_client::span!("set_batch_at_depth_iterative");
+            changed = true;
+        }
 
-    // Phase 0: handle fill
-    #[cfg(feature = "debug_trace_ref_counts")]
-    println!(" Phase 0 - Handle fill",);
-    let initial_node_id = if let Some(voxel) = batch.to_fill() {
-        let node_id = interner.get_or_create_leaf(voxel);
+        changed
+    }
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        {
-            let ref_count = interner.get_ref(&node_id);
-            println!("  current: {node_id:?} value: {voxel:?} ref_count: {ref_count}");
+    // Set voxels with the label of their component, and the components
+    fn label_components(
+        &self,
+        interner: &VoxInterner<T>,
+    ) -> (Vec<(IVec3, u32)>, Vec<ComponentInfo>) {
+        let positions = self
+            .iter_voxels(interner)
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+
+        let indices = positions
+            .iter()
+            .enumerate()
+            .map(|(index, position)| (*position, index as u32))
+            .collect::<FxHashMap<_, _>>();
+
+        let mut sets = DisjointSet::new(positions.len());
+
+        // every face is shared with the neighbour on the negative side, so checking the
+        // positive side of each voxel visits every face once
+        for (index, position) in positions.iter().enumerate() {
+            for offset in [IVec3::X, IVec3::Y, IVec3::Z] {
+                if let Some(neighbour) = indices.get(&(*position + offset)) {
+                    sets.union(index as u32, *neighbour);
+                }
+            }
         }
 
-        node_id
-    } else {
-        *initial_node_id
-    };
+        drop(indices);
+
+        let mut labels = vec![u32::MAX; positions.len()];
+        let mut components: Vec<ComponentInfo> = Vec::new();
+
+        let voxels = positions
+            .into_iter()
+            .enumerate()
+            .map(|(index, position)| {
+                let root = sets.find(index as u32) as usize;
+
+                if labels[root] == u32::MAX {
+                    labels[root] = components.len() as u32;
+                    components.push(ComponentInfo {
+                        label: labels[root],
+                        voxel_count: 0,
+                        min: position,
+                        max: position,
+                    });
+                }
 
-    if !batch.has_patches() {
-        return initial_node_id;
-    }
+                let component = &mut components[labels[root] as usize];
+                component.voxel_count += 1;
+                component.min = component.min.min(position);
+                component.max = component.max.max(position);
 
-    let max_depth = initial_depth.max() as usize;
-    let initial_depth = initial_depth.current() as usize;
+                (position, labels[root])
+            })
+            .collect();
 
-    // Phase 1: prepare the chain & build dangling branches
-    #[cfg(feature = "debug_trace_ref_counts")]
-    {
-        println!(" Phase 1 - Prepare the chain & build dangling branches",);
-        interner.dump_node(initial_node_id, 0, "  ");
+        (voxels, components)
     }
 
-    let data_len = batch.masks().len();
+    /// Reads the voxels at `positions` into `out`, with the same result as calling
+    /// [`VoxOpsRead::get`] for every position.
+    ///
+    /// Each read resumes from the deepest node it shares with the previous position instead
+    /// of the root, so spatially clustered queries, e.g. the 3x3x3 neighborhood of a voxel,
+    /// only descend the few levels they differ in. To get the most reuse the positions are
+    /// read sorted by their Morton code, see [`morton_encode_many`], and the values are
+    /// written back to `out` in the given order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `positions` and `out` have different lengths, or a position is outside the
+    /// tree.
+    pub fn get_many(&self, interner: &VoxInterner<T>, positions: &[IVec3], out: &mut [Option<T>]) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::get_many");
 
-    let mut current_level_data = vec![BlockId::INVALID; data_len];
-    let mut next_level_data = vec![BlockId::INVALID; data_len];
+        assert_eq!(
+            positions.len(),
+            out.len(),
+            "Output must have a slot for every position"
+        );
 
-    let mut paths = Vec::with_capacity(data_len);
-    let mut next_paths = Vec::with_capacity(data_len);
+        let max_depth = self.max_depth.max() as usize;
+        let voxels_per_axis = 1 << max_depth;
+        let default_t = T::default();
 
-    for (path_index, (set_mask, _clear_mask)) in batch.masks().iter().enumerate() {
-        if *set_mask == 0 {
-            continue;
+        for position in positions {
+            assert!(
+                position.cmpge(IVec3::ZERO).all()
+                    && position.cmplt(IVec3::splat(voxels_per_axis)).all(),
+                "Position {position} outside of the tree"
+            );
         }
 
-        let path = path_index << 3;
+        // child indices of every position, and the order visiting them along the Morton curve
+        let mut codes = vec![0u32; positions.len()];
+        morton_encode_many(positions, self.max_depth, &mut codes);
 
-        let mut current_node_id = initial_node_id;
+        let mut order = (0..positions.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&index| codes[index]);
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!("  path: 0x{path:08X} {path:09b}");
-
-        let mut leaf_node_id = BlockId::EMPTY;
+        // nodes of the last descent, `path[depth]` is the node at `depth`
+        let mut path = [BlockId::EMPTY; MAX_ALLOWED_DEPTH + 1];
+        let mut path_len = 1;
+        path[0] = self.root_id;
 
-        for current_depth in initial_depth..(max_depth - 1) {
-            if current_node_id.is_branch() {
-                let index = (path >> ((max_depth - current_depth - 1) * 3)) & 0b111;
+        let mut previous = 0u32;
 
-                #[cfg(feature = "debug_trace_ref_counts")]
-                println!(
-                    "   depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {} [1a]",
-                    interner.get_ref(&current_node_id)
-                );
+        for index in order {
+            let code = codes[index];
 
-                current_node_id = interner.get_child_id(&current_node_id, index);
+            // every level takes three bits of the code, root first, so the highest bit that
+            // differs from the previous code is where the descents split
+            let diff = previous ^ code;
+            let shared = if diff == 0 {
+                max_depth
             } else {
-                // Split leaf node
-                leaf_node_id = current_node_id;
-                current_node_id = BlockId::EMPTY;
-
-                #[cfg(feature = "debug_trace_ref_counts")]
-                {
-                    let index = (path >> ((max_depth - current_depth - 1) * 3)) & 0b111;
+                max_depth - (31 - diff.leading_zeros() as usize) / 3 - 1
+            };
+            previous = code;
 
-                    println!(
-                        "   depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {} [2]",
-                        interner.get_ref(&current_node_id)
-                    );
-                }
-            }
+            let mut depth = shared.min(path_len - 1);
+            let mut node_id = path[depth];
 
-            if current_node_id.is_empty() {
-                break;
+            while !node_id.is_empty() && node_id.is_branch() && depth < max_depth {
+                let child = morton_child_index(code, depth, max_depth);
+                node_id = interner.get_child_id(&node_id, child);
+                depth += 1;
+                path[depth] = node_id;
             }
-        }
-
-        let values = &batch.values()[path_index];
+            path_len = depth + 1;
 
-        let all_same = *set_mask == 0xFF && values.iter().all(|v| *v == values[0]);
-
-        if !all_same {
-            let (mut children, mut types, mut mask) = if !current_node_id.is_empty() {
-                (
-                    interner.get_children(&current_node_id),
-                    current_node_id.types(),
-                    current_node_id.mask(),
-                )
-            } else if leaf_node_id.is_leaf() {
-                let data_len = set_mask.count_ones() as usize;
-                interner.inc_ref_by(&leaf_node_id, (MAX_CHILDREN - data_len) as u32);
-
-                ([leaf_node_id; MAX_CHILDREN], 0xFF, 0xFF)
+            out[index] = if node_id.is_empty() {
+                None
             } else {
-                (EMPTY_CHILD, 0, 0)
+                let value = *interner.get_value(&node_id);
+                (value != default_t).then_some(value)
             };
+        }
+    }
 
-            let mut modified_childs: u8 = 0;
-
-            let mut set_mask_bits = *set_mask;
-            while set_mask_bits != 0 {
-                let idx = set_mask_bits.trailing_zeros() as usize;
-                set_mask_bits &= !(1 << idx);
-
-                let value = &values[idx];
-
-                if !children[idx].is_empty() && interner.get_value(&children[idx]) == value {
-                    // No change needed
-                    continue;
-                }
-
-                children[idx] = interner.get_or_create_leaf(*value);
-
-                types |= 1 << idx;
-                mask |= 1 << idx;
-                modified_childs |= 1 << idx;
-            }
-
-            if modified_childs == 0 {
-                // No changes made
-                continue;
-            }
+    /// Returns `true` if both trees have the same depth and hold the same voxels.
+    ///
+    /// The interner deduplicates nodes, so trees with the same content almost always share
+    /// their root and this is a single comparison of [`VoxTree::get_root_id`]. Otherwise the
+    /// nodes are compared, with shared subtrees again settled by their ids, which also
+    /// covers a leaf standing for a branch of equal children. Trees of different depths are
+    /// never equal.
+    pub fn content_eq(&self, other: &Self, interner: &VoxInterner<T>) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::content_eq");
 
-            if leaf_node_id.is_empty() {
-                let mut non_modified_childs_bits = !modified_childs;
-                while non_modified_childs_bits != 0 {
-                    let idx = non_modified_childs_bits.trailing_zeros() as usize;
-                    non_modified_childs_bits &= !(1 << idx);
+        if self.max_depth.max() != other.max_depth.max() {
+            return false;
+        }
 
-                    if !children[idx].is_empty() {
-                        // If the child was not modified, we need to increment its ref count
-                        interner.inc_ref_by(&children[idx], 1);
-                    }
-                }
-            }
+        if self.root_id == other.root_id {
+            return true;
+        }
 
-            let branch_id = interner.get_or_create_branch(children, types, mask);
+        nodes_content_eq(interner, self.root_id, other.root_id)
+    }
+}
 
-            current_level_data[path_index] = branch_id;
-            paths.push(path);
-        } else {
-            #[cfg(feature = "memory_stats")]
-            interner.bump_collapsed_branches();
+impl<T: VoxelTrait> VoxOpsRead<T> for VoxTree<T> {
+    fn get(&self, interner: &VoxInterner<T>, position: IVec3) -> Option<T> {
+        assert!(position.x >= 0 && position.x < (1 << self.max_depth.max()));
+        assert!(position.y >= 0 && position.y < (1 << self.max_depth.max()));
+        assert!(position.z >= 0 && position.z < (1 << self.max_depth.max()));
 
-            let first_value = values[set_mask.trailing_zeros() as usize];
-            let leaf_id = interner.get_or_create_leaf(first_value);
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::get");
 
-            current_level_data[path_index] = leaf_id;
-            paths.push(path);
-        };
+        get_at_depth(
+            interner,
+            self.root_id,
+            &position,
+            &TraversalDepth::new(0, self.max_depth.max()),
+        )
     }
+}
 
-    // Phase 2: Integrate dangling branches
-    #[cfg(feature = "debug_trace_ref_counts")]
-    {
-        println!(" Phase 2 - Integrate dangling branches");
-    }
+impl<T: VoxelTrait> VoxOpsWrite<T> for VoxTree<T> {
+    fn set(&mut self, interner: &mut VoxInterner<T>, position: IVec3, voxel: T) -> bool {
+        assert!(position.x >= 0 && position.x < (1 << self.max_depth.max()));
+        assert!(position.y >= 0 && position.y < (1 << self.max_depth.max()));
+        assert!(position.z >= 0 && position.z < (1 << self.max_depth.max()));
+
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::set");
 
-    if paths.is_empty() {
         #[cfg(feature = "debug_trace_ref_counts")]
         {
-            println!("  No paths to process");
+            println!("\n");
+            println!("set position: {position:?} voxel: {voxel}");
         }
-        return BlockId::INVALID;
-    }
 
-    let mut target_depth = max_depth - 1;
+        let new_root_id = if !self.root_id.is_empty() {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            {
+                println!("Some(root) set position: {position:?} voxel: {voxel}");
+                interner.dump_node(self.root_id, 0, "  ");
+            }
 
-    'main: while let Some(mut path) = paths.pop() {
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!(
-            "starting with path: {path:08X} {path:09b} target_depth: {target_depth} paths: {}",
-            paths.len(),
-        );
+            // Existing root - modify
+            set_at_root(
+                interner,
+                &self.root_id,
+                &position,
+                self.max_depth.max(),
+                voxel,
+            )
+        } else if !voxel.voxel_eq(&T::default()) {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            {
+                println!("None set position: {position:?} voxel: {voxel}");
+                interner.dump_node(self.root_id, 0, "  ");
+            }
 
-        let path_mask_depth = if target_depth > 1 {
-            target_depth - 2
+            set_at_root(
+                interner,
+                &self.root_id,
+                &position,
+                self.max_depth.max(),
+                voxel,
+            )
         } else {
-            0
+            return false;
         };
 
-        let path_mask = PATH_MASKS[max_depth][path_mask_depth] as usize;
+        #[cfg(feature = "debug_trace_ref_counts")]
+        println!("\n setting new_root");
 
-        let mut equivalent_id = initial_node_id;
-        let mut leaf_id = BlockId::EMPTY;
+        if new_root_id != BlockId::INVALID {
+            if !self.root_id.is_empty() {
+                strict_assert_ne!(new_root_id, self.root_id);
 
-        // Find equivalent node in the current tree
-        for current_depth in 0..(target_depth - 1) {
-            if equivalent_id.is_leaf() {
-                leaf_id = equivalent_id;
-                equivalent_id = BlockId::EMPTY;
-                break;
-            } else {
-                let index = (path >> ((max_depth - current_depth - 1) * 3)) & 0b111;
-                equivalent_id = interner.get_child_id(&equivalent_id, index);
-            }
+                #[cfg(feature = "debug_trace_ref_counts")]
+                {
+                    println!("existing_root_id:");
+                    interner.dump_node(self.root_id, 0, "  ");
+                    println!("new_root_id:");
+                    interner.dump_node(new_root_id, 0, "  ");
+                    println!(
+                        "  existing_root_id: {:?} new_root: {new_root_id:?}",
+                        self.root_id,
+                    );
+                }
 
-            if equivalent_id.is_empty() {
-                break;
-            }
-        }
+                interner.dec_ref_recursive(&self.root_id);
 
-        if equivalent_id.is_leaf() {
-            leaf_id = equivalent_id;
-            equivalent_id = BlockId::EMPTY;
-        };
-
-        let mut children = EMPTY_CHILD;
-        let mut types = 0;
-        let mut mask = 0;
-        let mut has_next_sibling = true;
-
-        while has_next_sibling {
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(" path: {path:08X} {path:09b}");
-
-            let target_index = (path >> ((max_depth - target_depth) * 3)) & 0b111;
-            let next_path = paths.last();
-
-            #[cfg(feature = "debug_trace_ref_counts")]
-            if let Some(next_path) = next_path {
-                println!("  next path: {next_path:08X} {next_path:09b}");
-            }
-
-            has_next_sibling = if target_depth == 1 {
-                next_path.is_some()
-            } else if let Some(next_path) = next_path {
-                (path & path_mask) == (*next_path & path_mask)
+                #[cfg(feature = "debug_trace_ref_counts")]
+                {
+                    println!("new_root after recycling existing_root position: {position:?}");
+                    interner.dump_node(new_root_id, 0, "  ");
+                }
             } else {
-                false
-            };
-
-            let current_path_index = path >> 3;
-            let current_level_id = current_level_data[current_path_index];
-            children[target_index] = current_level_id;
-            current_level_data[current_path_index] = BlockId::INVALID;
-
-            types |= (current_level_id.is_leaf() as u8) << target_index;
-            mask |= 1 << target_index;
+                #[cfg(feature = "debug_trace_ref_counts")]
+                {
+                    println!("new_root_id:");
+                    interner.dump_node(new_root_id, 0, "  ");
+                    println!("  new_root: {new_root_id:?}");
+                }
+            }
 
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!(
-                "  new_path: {:08X} {:09b}",
-                path & path_mask,
-                path & path_mask
+            strict_assert!(
+                interner.is_valid_block_id(&new_root_id),
+                "Invalid new root id: {new_root_id:?}"
             );
 
-            if has_next_sibling && !paths.is_empty() {
-                path = paths.pop().expect("No path found");
-            }
+            self.root_id = new_root_id;
+            self.dirty = true;
 
+            true
+        } else {
             #[cfg(feature = "debug_trace_ref_counts")]
-            println!("     has_more_paths: {}", !paths.is_empty());
+            println!("new_root is None");
+
+            false
         }
+    }
+}
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        {
-            println!(
-                "     types: {types:08b} mask: {mask:08b} current_path: {:09b}",
-                path & path_mask
-            );
-            for (child_idx, child) in children.iter().enumerate() {
-                println!("     child[{child_idx}]: {child:?}");
+impl<T: VoxelTrait> VoxOpsBulkWrite<T> for VoxTree<T> {
+    fn fill(&mut self, interner: &mut VoxInterner<T>, value: T) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::fill");
+
+        if !value.voxel_eq(&T::default()) {
+            if !self.root_id.is_empty() {
+                interner.dec_ref_recursive(&self.root_id);
             }
+            self.root_id = interner.get_or_create_leaf(value);
+            self.dirty = true;
+        } else {
+            self.clear(interner);
         }
+    }
 
-        let existing_mask = equivalent_id.mask();
-        let inv_mask = !mask;
-        let cloned_nodes = existing_mask & inv_mask;
+    fn clear(&mut self, interner: &mut VoxInterner<T>) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::clear");
 
-        if mask != 0xFF {
-            if cloned_nodes != 0 {
-                let existing_children = interner.get_children_ref(&equivalent_id);
+        if !self.root_id.is_empty() {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!("clear root_id: {:?}", self.root_id);
 
-                let mut cloned_nodes_bits = cloned_nodes;
+            interner.dec_ref_recursive(&self.root_id);
 
-                while cloned_nodes_bits != 0 {
-                    let idx = cloned_nodes_bits.trailing_zeros() as usize;
-                    cloned_nodes_bits &= !(1 << idx);
+            self.root_id = BlockId::EMPTY;
+            self.dirty = true;
+        }
+    }
+}
 
-                    children[idx] = existing_children[idx];
+impl<T: VoxelTrait> VoxOpsBatch<T> for VoxTree<T> {
+    fn create_batch(&self) -> Batch<T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::create_batch");
 
-                    types |= (children[idx].is_leaf() as u8) << idx;
-                    mask |= 1 << idx;
-                }
-            } else if !leaf_id.is_empty() {
-                let leafs_to_clone = inv_mask.count_ones();
+        Batch::new(self.max_depth)
+    }
 
-                let mut leafs_to_clone_bits = inv_mask;
+    fn apply_batch(&mut self, interner: &mut VoxInterner<T>, batch: &Batch<T>) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxTree::apply_batch");
 
-                types |= inv_mask;
-                mask |= inv_mask;
+        let mut changed = false;
 
-                while leafs_to_clone_bits != 0 {
-                    let idx = leafs_to_clone_bits.trailing_zeros() as usize;
-                    children[idx] = leaf_id;
-                    leafs_to_clone_bits &= !(1 << idx);
-                }
+        // Fill first, so the patches below are applied on top of a root owned by the tree
+        if let Some(value) = batch.to_fill() {
+            let already_filled = if self.root_id.is_leaf() {
+                interner.get_value(&self.root_id).voxel_eq(&value)
+            } else {
+                self.root_id.is_empty() && value.voxel_eq(&T::default())
+            };
 
-                interner.inc_ref_by(&leaf_id, leafs_to_clone);
+            if !already_filled {
+                self.fill(interner, value);
+                changed = true;
             }
         }
 
-        let all_same = types == 0xFF && children.iter().all(|item| item == &children[0]);
+        let new_root_id = set_batch_at_root(interner, &self.root_id, self.max_depth.max(), batch);
 
-        let new_node_id = if !all_same {
-            let mut cloned_nodes_bits = cloned_nodes;
-            while cloned_nodes_bits != 0 {
-                let idx = cloned_nodes_bits.trailing_zeros() as usize;
-                cloned_nodes_bits &= !(1 << idx);
+        changed |= self.replace_root(interner, new_root_id);
 
-                interner.inc_ref(&children[idx]);
-            }
+        changed
+    }
+}
 
-            interner.get_or_create_branch(children, types, mask)
-        } else {
-            #[cfg(feature = "memory_stats")]
-            interner.bump_collapsed_branches();
+impl<T: VoxelTrait> VoxOpsConfig for VoxTree<T> {
+    #[inline(always)]
+    fn max_depth(&self, lod: Lod) -> MaxDepth {
+        self.max_depth.for_lod(lod)
+    }
 
-            let dec_ref = if cloned_nodes != 0 {
-                cloned_nodes.count_ones().min(7)
-            } else {
-                7
-            };
+    #[inline(always)]
+    fn voxels_per_axis(&self, lod: Lod) -> u32 {
+        1 << self.max_depth.for_lod(lod).max()
+    }
+}
 
-            interner.dec_ref_by(&children[0], dec_ref);
+impl<T: VoxelTrait> VoxOpsState for VoxTree<T> {
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        self.root_id.is_empty()
+    }
 
-            children[0]
-        };
+    #[inline(always)]
+    fn is_leaf(&self) -> bool {
+        self.root_id.is_leaf()
+    }
+}
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!(
-            "     new_node_id: {new_node_id:?} ref_count: {}",
-            interner.get_ref(&new_node_id)
-        );
+impl<T: VoxelTrait> VoxOpsDirty for VoxTree<T> {
+    #[inline(always)]
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
 
-        let next_path = path & path_mask;
-        next_paths.push(next_path);
-        next_level_data[next_path >> 3] = new_node_id;
+    #[inline(always)]
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
 
-        #[cfg(feature = "debug_trace_ref_counts")]
-        println!(
-            "     has_more_paths: {} target_depth: {target_depth}",
-            !paths.is_empty(),
-        );
+    #[inline(always)]
+    fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+}
 
-        if paths.is_empty() {
-            std::mem::swap(&mut current_level_data, &mut next_level_data);
-            std::mem::swap(&mut paths, &mut next_paths);
-            next_paths.clear();
+// Depth-first walk over the non-empty voxels of a tree, see [`VoxTree::iter_voxels`]
+struct VoxelIter<'a, T: VoxelTrait> {
+    interner: &'a VoxInterner<T>,
+    max_depth: u8,
+    // nodes left to visit, with the minimum corner and depth of their octant
+    stack: Vec<(BlockId, IVec3, u8)>,
+    // leaf being expanded: minimum corner, side, value and index of the next voxel
+    leaf: Option<(IVec3, i32, T, i32)>,
+}
 
-            #[cfg(feature = "debug_trace_ref_counts")]
-            println!("  paths: {paths:#?}");
+impl<'a, T: VoxelTrait> VoxelIter<'a, T> {
+    fn new(interner: &'a VoxInterner<T>, root_id: BlockId, max_depth: u8) -> Self {
+        // every level leaves at most 7 siblings behind
+        let mut stack = Vec::with_capacity(max_depth as usize * 7 + 1);
+        if !root_id.is_empty() {
+            stack.push((root_id, IVec3::ZERO, 0));
+        }
 
-            target_depth -= 1;
-            if target_depth == 0 {
-                break 'main;
-            }
+        Self {
+            interner,
+            max_depth,
+            stack,
+            leaf: None,
         }
     }
+}
 
-    let final_node_id = current_level_data[paths[0] >> 3];
+impl<T: VoxelTrait> Iterator for VoxelIter<'_, T> {
+    type Item = (IVec3, T);
 
-    #[cfg(feature = "debug_trace_ref_counts")]
-    {
-        println!(" Phase 3 - Finalize");
-        println!("  new_root: {final_node_id:?}");
-        interner.dump_node(final_node_id, 0, "  ");
-    }
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((min, side, value, index)) = &mut self.leaf {
+                if *index < *side * *side * *side {
+                    let x = *index % *side;
+                    let z = (*index / *side) % *side;
+                    let y = *index / (*side * *side);
+                    *index += 1;
 
-    final_node_id
-}
+                    return Some((*min + IVec3::new(x, y, z), *value));
+                }
 
-#[cfg(test)]
-mod tests {
-    use rand::Rng;
+                self.leaf = None;
+            }
 
-    use crate::utils::common::child_index;
+            let (node_id, min, depth) = self.stack.pop()?;
 
-    use super::*;
+            if !node_id.is_branch() || depth >= self.max_depth {
+                let value = *self.interner.get_value(&node_id);
+                if depth >= self.max_depth {
+                    return Some((min, value));
+                }
 
-    #[test]
-    fn test_create() {
-        let tree = VoxTree::<u8>::new(MaxDepth::new(3));
-        assert!(tree.is_empty());
-        assert_eq!(tree.max_depth(Lod::new(0)).max(), 3);
-        assert_eq!(tree.voxels_per_axis(Lod::new(0)), 8);
-    }
+                self.leaf = Some((min, 1 << (self.max_depth - depth), value, 0));
+                continue;
+            }
 
-    #[test]
-    fn test_child_index() {
-        for max_depth in 0..(MAX_ALLOWED_DEPTH as u8) {
-            let voxels_per_axis = 1 << max_depth;
-            for depth in 0..max_depth {
-                for y in 0..voxels_per_axis {
-                    for z in 0..voxels_per_axis {
-                        for x in 0..voxels_per_axis {
-                            let position = IVec3::new(x, y, z);
-                            let result =
-                                child_index(&position, &TraversalDepth::new(depth, max_depth));
-                            assert!(result < 8);
-                        }
-                    }
+            let half_side = 1 << (self.max_depth - depth - 1);
+            let mask = node_id.mask();
+            let children = self.interner.get_children_ref(&node_id);
+
+            // pushed in reverse, so the first child is visited first
+            for idx in (0..MAX_CHILDREN).rev() {
+                if mask & (1 << idx) == 0 {
+                    continue;
                 }
+
+                let offset = IVec3::new(
+                    (idx & 1) as i32,
+                    ((idx >> 1) & 1) as i32,
+                    ((idx >> 2) & 1) as i32,
+                ) * half_side;
+
+                self.stack.push((children[idx], min + offset, depth + 1));
             }
         }
     }
+}
 
-    #[test]
-    fn test_set_and_get() {
-        let mut interner = VoxInterner::with_memory_budget(1024 * 2);
+// Union-find over `0..len`, with path halving and union by size
+struct DisjointSet {
+    parents: Vec<u32>,
+    sizes: Vec<u32>,
+}
 
-        let mut tree = VoxTree::new(MaxDepth::new(3));
-        let position = IVec3::new(0, 0, 0);
+impl DisjointSet {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len as u32).collect(),
+            sizes: vec![1; len],
+        }
+    }
 
-        // Test setting and getting a value
-        assert!(tree.set(&mut interner, position, 42));
-        assert_eq!(tree.get(&interner, position), Some(42));
+    fn find(&mut self, mut index: u32) -> u32 {
+        while self.parents[index as usize] != index {
+            let parent = self.parents[index as usize];
+            self.parents[index as usize] = self.parents[parent as usize];
+            index = parent;
+        }
 
-        // Test overwriting a value
-        assert!(tree.set(&mut interner, position, 24));
-        assert_eq!(tree.get(&interner, position), Some(24));
+        index
+    }
 
-        // Test getting from an empty position
-        assert_eq!(tree.get(&interner, IVec3::new(1, 1, 1)), None);
+    fn union(&mut self, a: u32, b: u32) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
 
-        // Test setting at max depth
-        let max_pos = IVec3::new(7, 7, 7); // 2^3 - 1
-        assert!(tree.set(&mut interner, max_pos, 99));
-        assert_eq!(tree.get(&interner, max_pos), Some(99));
+        if self.sizes[a as usize] < self.sizes[b as usize] {
+            std::mem::swap(&mut a, &mut b);
+        }
 
-        tree.clear(&mut interner);
+        self.parents[b as usize] = a;
+        self.sizes[a as usize] += self.sizes[b as usize];
+    }
+}
 
-        let positions = [
-            IVec3::new(0, 0, 0),
-            IVec3::new(0, 0, 1),
-            IVec3::new(0, 1, 0),
-            IVec3::new(0, 1, 1),
-            IVec3::new(1, 0, 0),
-            IVec3::new(1, 0, 1),
-            IVec3::new(1, 1, 0),
-            IVec3::new(1, 1, 1),
-        ];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CsgOp {
+    Union,
+    Intersect,
+    Difference,
+}
 
-        for (i, &pos) in positions.iter().enumerate() {
-            tree.set(&mut interner, pos, (i + 1) as u8);
+// Combines two nodes at the same depth and returns the resulting node with a reference
+// owned by the caller. A leaf covers its whole octant, so when only one side is a leaf it is
+// used as every child of itself.
+fn combine_nodes<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    a: BlockId,
+    b: BlockId,
+    op: CsgOp,
+) -> BlockId {
+    let reuse = |interner: &mut VoxInterner<T>, node_id: BlockId| {
+        if !node_id.is_empty() {
+            interner.inc_ref(&node_id);
         }
+        node_id
+    };
 
-        for (i, &pos) in positions.iter().enumerate() {
-            assert_eq!(tree.get(&interner, pos).unwrap(), (i + 1) as u8);
+    match op {
+        CsgOp::Union => {
+            if a == b || b.is_empty() || a.is_leaf() {
+                return reuse(interner, a);
+            }
+            if a.is_empty() {
+                return reuse(interner, b);
+            }
+        }
+        CsgOp::Intersect => {
+            if a.is_empty() || b.is_empty() {
+                return BlockId::EMPTY;
+            }
+            if a == b || b.is_leaf() {
+                return reuse(interner, a);
+            }
+        }
+        CsgOp::Difference => {
+            if a.is_empty() || a == b || b.is_leaf() {
+                return BlockId::EMPTY;
+            }
+            if b.is_empty() {
+                return reuse(interner, a);
+            }
         }
     }
 
-    #[test]
-    fn test_is_empty() {
-        let mut interner = VoxInterner::with_memory_budget(1024);
+    // at least one side is a branch, so nodes below max depth are combined here
+    let child = |interner: &VoxInterner<T>, node_id: BlockId, idx: usize| {
+        if node_id.is_branch() {
+            interner.get_child_id(&node_id, idx)
+        } else {
+            node_id
+        }
+    };
 
-        let mut tree = VoxTree::new(MaxDepth::new(3));
-        assert!(tree.is_empty());
+    let mut children = EMPTY_CHILD;
 
-        // Setting a value makes it non-empty
-        assert!(tree.set(&mut interner, IVec3::new(0, 0, 0), 1));
-        assert!(!tree.is_empty());
+    for (idx, result) in children.iter_mut().enumerate() {
+        let child_a = child(interner, a, idx);
+        let child_b = child(interner, b, idx);
 
-        // Clearing makes it empty again
-        tree.clear(&mut interner);
-        assert!(tree.is_empty());
+        *result = combine_nodes(interner, child_a, child_b, op);
     }
 
-    #[test]
-    fn test_clear() {
-        let mut interner = VoxInterner::with_memory_budget(1024 * 2);
+    branch_from_children(interner, children)
+}
 
-        let mut tree = VoxTree::new(MaxDepth::new(3));
+// Builds the node at `node_min` with side `size` from a dense grid of `data_size` voxels per
+// axis, laid out like `to_vec`, returning it with a reference owned by the caller
+fn dense_nodes<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    data: &[T],
+    data_size: usize,
+    node_min: IVec3,
+    size: usize,
+) -> BlockId {
+    if size == 1 {
+        let index = (node_min.y as usize * data_size + node_min.z as usize) * data_size
+            + node_min.x as usize;
+        let value = data[index];
+
+        return if value.voxel_eq(&T::default()) {
+            BlockId::EMPTY
+        } else {
+            interner.get_or_create_leaf(value)
+        };
+    }
+
+    let half = size / 2;
+    let mut children = EMPTY_CHILD;
+
+    for (idx, child) in children.iter_mut().enumerate() {
+        let offset = IVec3::new(idx as i32 & 1, (idx as i32 >> 1) & 1, (idx as i32 >> 2) & 1);
+
+        *child = dense_nodes(
+            interner,
+            data,
+            data_size,
+            node_min + offset * half as i32,
+            half,
+        );
+    }
+
+    branch_from_children(interner, children)
+}
+
+// Interns a branch from children owning a reference each and returns it with a reference
+// owned by the caller. Empty children give an empty node, eight equal leaves collapse into
+// that leaf.
+fn branch_from_children<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    children: Children,
+) -> BlockId {
+    let mut types = 0u8;
+    let mut mask = 0u8;
+
+    for (idx, child_id) in children.iter().enumerate() {
+        if !child_id.is_empty() {
+            mask |= 1 << idx;
+            if child_id.is_leaf() {
+                types |= 1 << idx;
+            }
+        }
+    }
+
+    if mask == 0 {
+        return BlockId::EMPTY;
+    }
+
+    if types == 0xFF && children.iter().all(|item| item == &children[0]) {
+        #[cfg(feature = "memory_stats")]
+        interner.bump_collapsed_branches();
+
+        interner.dec_ref_by(&children[0], 7);
+
+        return children[0];
+    }
+
+    interner.get_or_create_branch(children, types, mask)
+}
+
+// Sets every voxel of the node at `node_min` with side `size` that lies in `min..=max` to
+// `leaf_id`, returning the new node with a reference owned by the caller. Nodes outside of
+// the box are reused and nodes inside it replaced as a whole, so only nodes crossing the
+// box surface are visited.
+fn fill_region_nodes<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: BlockId,
+    node_min: IVec3,
+    size: i32,
+    min: IVec3,
+    max: IVec3,
+    leaf_id: BlockId,
+) -> BlockId {
+    let node_max = node_min + IVec3::splat(size - 1);
+
+    let result_id = if node_max.cmplt(min).any() || node_min.cmpgt(max).any() {
+        node_id
+    } else if node_min.cmpge(min).all() && node_max.cmple(max).all() {
+        leaf_id
+    } else {
+        // partially covered, so `size` is at least 2
+        let half = size / 2;
+        let mut children = EMPTY_CHILD;
+
+        for (idx, result) in children.iter_mut().enumerate() {
+            let child_id = if !node_id.is_empty() && node_id.is_branch() {
+                interner.get_child_id(&node_id, idx)
+            } else {
+                // a leaf or empty node looks the same in all of its children
+                node_id
+            };
+            let offset = IVec3::new(idx as i32 & 1, (idx as i32 >> 1) & 1, (idx as i32 >> 2) & 1);
+
+            *result = fill_region_nodes(
+                interner,
+                child_id,
+                node_min + offset * half,
+                half,
+                min,
+                max,
+                leaf_id,
+            );
+        }
+
+        return branch_from_children(interner, children);
+    };
+
+    if !result_id.is_empty() {
+        interner.inc_ref(&result_id);
+    }
+
+    result_id
+}
+
+// Compares the voxels of two nodes at the same depth. A leaf or empty node holds the same
+// voxel in all of its children, so it is compared with every child of the other node
+fn nodes_content_eq<T: VoxelTrait>(interner: &VoxInterner<T>, a: BlockId, b: BlockId) -> bool {
+    if a == b {
+        return true;
+    }
+
+    let a_branch = !a.is_empty() && a.is_branch();
+    let b_branch = !b.is_empty() && b.is_branch();
+
+    match (a_branch, b_branch) {
+        (true, true) => interner
+            .get_children_ref(&a)
+            .iter()
+            .zip(interner.get_children_ref(&b))
+            .all(|(a_child, b_child)| nodes_content_eq(interner, *a_child, *b_child)),
+        (true, false) => interner
+            .get_children_ref(&a)
+            .iter()
+            .all(|a_child| nodes_content_eq(interner, *a_child, b)),
+        (false, true) => interner
+            .get_children_ref(&b)
+            .iter()
+            .all(|b_child| nodes_content_eq(interner, a, *b_child)),
+        (false, false) => {
+            let default_t = T::default();
+            let value = |id: BlockId| {
+                if id.is_empty() {
+                    default_t
+                } else {
+                    *interner.get_value(&id)
+                }
+            };
+
+            value(a).voxel_eq(&value(b))
+        }
+    }
+}
+
+// Grows `bounds` to hold the set voxels of `node_id`, covering the cube at `node_min` with
+// `size` voxels per axis
+fn occupied_bounds_nodes<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    node_min: IVec3,
+    size: i32,
+    bounds: &mut Option<(IVec3, IVec3)>,
+) {
+    if node_id.is_empty() {
+        return;
+    }
+
+    let node_max = node_min + IVec3::splat(size - 1);
+
+    if let Some((min, max)) = *bounds {
+        // nothing inside can grow the bounds
+        if node_min.cmpge(min).all() && node_max.cmple(max).all() {
+            return;
+        }
+    }
+
+    if node_id.is_leaf() {
+        *bounds = Some(match *bounds {
+            Some((min, max)) => (min.min(node_min), max.max(node_max)),
+            None => (node_min, node_max),
+        });
+
+        return;
+    }
+
+    let half = size / 2;
+
+    for (idx, child_id) in interner.get_children_ref(&node_id).iter().enumerate() {
+        let offset = IVec3::new(idx as i32 & 1, (idx as i32 >> 1) & 1, (idx as i32 >> 2) & 1);
+
+        occupied_bounds_nodes(interner, *child_id, node_min + offset * half, half, bounds);
+    }
+}
+
+// Records the set voxels of `node_id`, covering the cube at `node_min` with `size` voxels per
+// axis, that lie inside `min..=max` into `batch`
+fn extract_region_nodes<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    node_min: IVec3,
+    size: i32,
+    min: IVec3,
+    max: IVec3,
+    batch: &mut Batch<T>,
+) {
+    let node_max = node_min + IVec3::splat(size - 1);
+
+    if node_id.is_empty() || node_max.cmplt(min).any() || node_min.cmpgt(max).any() {
+        return;
+    }
+
+    if node_id.is_branch() {
+        let half = size / 2;
+
+        for (idx, child_id) in interner.get_children_ref(&node_id).iter().enumerate() {
+            let offset = IVec3::new(idx as i32 & 1, (idx as i32 >> 1) & 1, (idx as i32 >> 2) & 1);
+
+            extract_region_nodes(
+                interner,
+                *child_id,
+                node_min + offset * half,
+                half,
+                min,
+                max,
+                batch,
+            );
+        }
+
+        return;
+    }
+
+    let value = *interner.get_value(&node_id);
+    if value.voxel_eq(&T::default()) {
+        return;
+    }
+
+    let (from, to) = (node_min.max(min), node_max.min(max));
+
+    for y in from.y..=to.y {
+        for z in from.z..=to.z {
+            for x in from.x..=to.x {
+                batch.just_set(IVec3::new(x, y, z), value);
+            }
+        }
+    }
+}
+
+// Returns the transformed node holding a reference for the caller, `transformed` caches the
+// nodes rewritten so far
+fn transform_nodes<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: BlockId,
+    transform: OctantTransform,
+    transformed: &mut FxHashMap<BlockId, BlockId>,
+) -> BlockId {
+    if node_id.is_empty() {
+        return node_id;
+    }
+
+    if node_id.is_leaf() {
+        // uniform nodes look the same in every orientation
+        interner.inc_ref(&node_id);
+        return node_id;
+    }
+
+    if let Some(new_id) = transformed.get(&node_id) {
+        interner.inc_ref(new_id);
+        return *new_id;
+    }
+
+    let mut children = EMPTY_CHILD;
+    let mut types = 0u8;
+    let mut mask = 0u8;
+
+    for idx in 0..MAX_CHILDREN {
+        let child_id = interner.get_child_id(&node_id, idx);
+        let new_idx = transform.child_index(idx);
+
+        children[new_idx] = transform_nodes(interner, child_id, transform, transformed);
+
+        if !children[new_idx].is_empty() {
+            mask |= 1 << new_idx;
+            if children[new_idx].is_leaf() {
+                types |= 1 << new_idx;
+            }
+        }
+    }
+
+    let new_id = interner.get_or_create_branch(children, types, mask);
+    transformed.insert(node_id, new_id);
+
+    new_id
+}
+
+// Returns the node with every value mapped by `f` with a reference owned by the caller,
+// `mapped` caches the nodes rewritten so far
+fn map_values_nodes<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: BlockId,
+    f: &impl Fn(T) -> T,
+    mapped: &mut FxHashMap<BlockId, BlockId>,
+) -> BlockId {
+    if node_id.is_empty() {
+        return node_id;
+    }
+
+    if let Some(new_id) = mapped.get(&node_id) {
+        if !new_id.is_empty() {
+            interner.inc_ref(new_id);
+        }
+        return *new_id;
+    }
+
+    let new_id = if node_id.is_leaf() {
+        let value = f(*interner.get_value(&node_id));
+
+        if value.voxel_eq(&T::default()) {
+            BlockId::EMPTY
+        } else {
+            interner.get_or_create_leaf(value)
+        }
+    } else {
+        let mut children = EMPTY_CHILD;
+
+        for (idx, child) in children.iter_mut().enumerate() {
+            let child_id = interner.get_child_id(&node_id, idx);
+            *child = map_values_nodes(interner, child_id, f, mapped);
+        }
+
+        branch_from_children(interner, children)
+    };
+
+    mapped.insert(node_id, new_id);
+
+    new_id
+}
+
+// Returns `node_id`, `levels` above the voxels, with its 2x2x2 blocks of voxels merged by
+// majority vote, with a reference owned by the caller. `downsampled` maps the nodes already
+// merged, keyed by node and level.
+fn downsample_nodes<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: BlockId,
+    levels: u8,
+    downsampled: &mut FxHashMap<(BlockId, u8), BlockId>,
+) -> BlockId {
+    if node_id.is_empty() {
+        return node_id;
+    }
+
+    if node_id.is_leaf() {
+        // a leaf at any level covers at least a whole block
+        interner.inc_ref(&node_id);
+        return node_id;
+    }
+
+    if let Some(new_id) = downsampled.get(&(node_id, levels)) {
+        if !new_id.is_empty() {
+            interner.inc_ref(new_id);
+        }
+        return *new_id;
+    }
+
+    let new_id = if levels == 1 {
+        let children = interner.get_children(&node_id);
+
+        let mut votes: [(BlockId, u8); MAX_CHILDREN] = [(BlockId::EMPTY, 0); MAX_CHILDREN];
+        let mut unique = 0;
+        let mut empty = 0;
+
+        for child_id in children.iter() {
+            if child_id.is_empty() {
+                empty += 1;
+                continue;
+            }
+
+            // equal voxels are the same leaf
+            match votes[..unique].iter_mut().find(|(id, _)| id == child_id) {
+                Some((_, count)) => *count += 1,
+                None => {
+                    votes[unique] = (*child_id, 1);
+                    unique += 1;
+                }
+            }
+        }
+
+        let winner = votes[..unique]
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(id, _)| *id);
+
+        match winner {
+            Some(leaf_id) if empty < 4 => {
+                interner.inc_ref(&leaf_id);
+                leaf_id
+            }
+            _ => BlockId::EMPTY,
+        }
+    } else {
+        let mut children = EMPTY_CHILD;
+
+        for (idx, child) in children.iter_mut().enumerate() {
+            let child_id = interner.get_child_id(&node_id, idx);
+            *child = downsample_nodes(interner, child_id, levels - 1, downsampled);
+        }
+
+        branch_from_children(interner, children)
+    };
+
+    downsampled.insert((node_id, levels), new_id);
+
+    new_id
+}
+
+#[inline(always)]
+fn set_at_root<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: &BlockId,
+    position: &IVec3,
+    max_depth: u8,
+    voxel: T,
+) -> BlockId {
+    strict_assert!(*node_id != BlockId::INVALID);
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("set_at_root");
+
+    let depth = TraversalDepth::new(0, max_depth);
+    if !voxel.voxel_eq(&T::default()) {
+        set_at_depth_iterative(interner, node_id, position, &depth, voxel)
+    } else {
+        remove_at_depth(interner, node_id, position, &depth)
+    }
+}
+
+fn set_at_depth_iterative<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    initial_node_id: &BlockId,
+    position: &IVec3,
+    initial_depth: &TraversalDepth,
+    voxel: T,
+) -> BlockId {
+    #[cfg(feature = "debug_trace_ref_counts")]
+    println!(
+        "set_at_depth_iterative initial_node: {initial_node_id:?} position: {position:?} voxel: {voxel}"
+    );
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("set_at_depth_iterative");
+
+    let mut stack = [const { (BlockId::INVALID, BlockId::INVALID, u8::MAX) }; MAX_ALLOWED_DEPTH];
+    let mut current_node_id = *initial_node_id;
+
+    let max_depth = initial_depth.max() as usize;
+    let initial_depth = initial_depth.current() as usize;
+    let mut current_depth = initial_depth;
+
+    // Phase 1: descend down the tree and build the chain
+    #[cfg(feature = "debug_trace_ref_counts")]
+    {
+        println!(" Phase 1 - Find the spot");
+        interner.dump_node(current_node_id, 0, "  ");
+    }
+
+    let mut leaf_node_id = BlockId::EMPTY;
+    while current_depth < max_depth {
+        #[cfg(feature = "debug_trace_ref_counts")]
+        let current_node_ref_count = interner.get_ref(&current_node_id);
+
+        let index = child_index_macro_2!(position, current_depth, max_depth);
+
+        if current_node_id.is_branch() {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "  depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {current_node_ref_count} [1]"
+            );
+
+            stack[current_depth] = (current_node_id, leaf_node_id, index as u8);
+
+            current_node_id = interner.get_child_id(&current_node_id, index);
+        } else {
+            if interner.get_value(&current_node_id).voxel_eq(&voxel) {
+                return BlockId::INVALID; // Nothing changed
+            }
+
+            // Split leaf node
+            leaf_node_id = current_node_id;
+            current_node_id = BlockId::EMPTY;
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "  depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {current_node_ref_count} [2]"
+            );
+
+            stack[current_depth] = (current_node_id, leaf_node_id, index as u8);
+        }
+
+        current_depth += 1;
+    }
+
+    // Phase 2: create a leaf at the correct depth
+    #[cfg(feature = "debug_trace_ref_counts")]
+    {
+        println!(" Phase 2 - Create leaf");
+        println!(
+            "  depth: {current_depth}/{max_depth} current: {current_node_id:?} ref_count: {} is_valid: {}",
+            interner.get_ref(&current_node_id),
+            interner.is_valid_block_id(&current_node_id)
+        );
+    }
+
+    if current_node_id.is_leaf() && interner.get_value(&current_node_id).voxel_eq(&voxel) {
+        #[cfg(feature = "debug_trace_ref_counts")]
+        println!("  voxel already exists, no change required");
+        return BlockId::INVALID; // Nothing changed
+    }
+
+    current_node_id = interner.get_or_create_leaf(voxel);
+
+    #[cfg(feature = "debug_trace_ref_counts")]
+    println!(
+        "   current: {current_node_id:?} ref_count: {}",
+        interner.get_ref(&current_node_id)
+    );
+
+    // Phase 3: propagate upwards and create new branch nodes
+    #[cfg(feature = "debug_trace_ref_counts")]
+    println!(" Phase 3 - Propagate upwards");
+    while current_depth > initial_depth {
+        current_depth -= 1;
+
+        let (parent_node_id, leaf_node_id, parent_node_index) = stack[current_depth];
+
+        if !parent_node_id.is_empty() {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "  depth: {current_depth}/{max_depth} i: {parent_node_index:2x} parent: {parent_node_id:?} current: {current_node_id:?} leaf: {leaf_node_id:?} [B]"
+            );
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            for (child_idx, child) in interner.get_children(&parent_node_id).iter().enumerate() {
+                if child_idx == parent_node_index as usize {
+                    println!(
+                        "   child[{child_idx}]: {child:?} [{}] => {current_node_id:?} [{}]",
+                        interner.get_ref(child),
+                        interner.get_ref(&current_node_id),
+                    );
+                } else {
+                    println!(
+                        "   child[{child_idx}]: {child:?} [{}]",
+                        interner.get_ref(child)
+                    );
+                }
+            }
+
+            let types =
+                parent_node_id.types() | ((current_node_id.is_leaf() as u8) << parent_node_index);
+
+            let mut branch = interner.get_children(&parent_node_id);
+            branch[parent_node_index as usize] = current_node_id;
+
+            if !(types == 0xFF && branch.iter().all(|item| item == &current_node_id)) {
+                let mask = parent_node_id.mask() | (1 << parent_node_index);
+
+                interner.inc_child_refs(&branch, parent_node_index as usize);
+
+                current_node_id = interner.get_or_create_branch(branch, types, mask);
+            }
+        } else if leaf_node_id.is_empty() {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "  depth: {current_depth}/{max_depth} i: {parent_node_index:2x} parent: {parent_node_id:?} current: {current_node_id:?} leaf: {leaf_node_id:?} [E]"
+            );
+
+            let mut children = EMPTY_CHILD;
+            children[parent_node_index as usize] = current_node_id;
+            let types = (current_node_id.is_leaf() as u8) << parent_node_index;
+            let mask = 1 << parent_node_index;
+            current_node_id = interner.get_or_create_branch(children, types, mask);
+        } else {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "  depth: {current_depth}/{max_depth} i: {parent_node_index:2x} parent: {parent_node_id:?} current: {current_node_id:?} leaf: {leaf_node_id:?} [ESL]"
+            );
+
+            let mut children = [leaf_node_id; MAX_CHILDREN];
+            interner.inc_ref_by(&leaf_node_id, 7);
+            children[parent_node_index as usize] = current_node_id;
+            let types = !(1 << parent_node_index)
+                | ((current_node_id.is_leaf() as u8) << parent_node_index);
+            let mask = 0xFF;
+            current_node_id = interner.get_or_create_branch(children, types, mask);
+        }
+    }
+
+    #[cfg(feature = "debug_trace_ref_counts")]
+    {
+        println!(" Phase 4 - Finalize");
+        println!("  new_root: {current_node_id:?}");
+        interner.dump_node(current_node_id, 0, "  ");
+    }
+
+    current_node_id
+}
+
+fn remove_at_depth<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: &BlockId,
+    position: &IVec3,
+    depth: &TraversalDepth,
+) -> BlockId {
+    strict_assert!(*node_id != BlockId::INVALID);
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("remove_at_depth");
+
+    if node_id.is_branch() {
+        remove_at_depth_branch(interner, node_id, position, depth)
+    } else {
+        remove_at_depth_leaf(interner, node_id, position, depth)
+    }
+}
+
+fn remove_at_depth_branch<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: &BlockId,
+    position: &IVec3,
+    depth: &TraversalDepth,
+) -> BlockId {
+    #[cfg(feature = "debug_trace_ref_counts")]
+    println!("remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}");
+
+    strict_assert!(interner.is_valid_block_id(node_id));
+    strict_assert!(depth.current() < depth.max(), "Branch node at max depth");
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("remove_at_depth_branch");
+
+    let index = child_index_macro!(position, depth);
+
+    if node_id.has_child(index as u8) {
+        let mut branch = interner.get_children(node_id);
+        let child_id = branch[index];
+
+        let new_child_id = remove_at_depth(interner, &child_id, position, &depth.increment());
+
+        if new_child_id != BlockId::INVALID {
+            strict_assert!(interner.is_valid_block_id(&new_child_id));
+
+            let is_empty = new_child_id.is_empty();
+
+            // let current_mask = node_id.mask();
+
+            let mask = if is_empty {
+                node_id.mask() & !(1 << index)
+            } else {
+                node_id.mask()
+            };
+
+            // Check if all children are empty
+            if mask == 0 {
+                // All children are empty, remove this branch node
+                BlockId::EMPTY
+            } else {
+                // Return the updated branch node
+                strict_assert!(
+                    new_child_id.is_branch(),
+                    "Removing voxel should never produce a leaf node"
+                );
+
+                // let current_types = node_id.types();
+                let types = node_id.types() & !(1 << index);
+
+                // println!(
+                //     "before types: {current_types:08b} mask: {current_mask:08b}"
+                // );
+                // println!(" after types: {types:08b} mask: {mask:08b}");
+
+                branch[index] = new_child_id;
+
+                #[cfg(feature = "debug_trace_ref_counts")]
+                println!(
+                    ".. [new branch] remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}"
+                );
+
+                interner.inc_child_refs(&branch, index);
+
+                interner.get_or_create_branch(branch, types, mask)
+            }
+        } else {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                ".. [invalid new_child_id] remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}"
+            );
+            BlockId::INVALID
+        }
+    } else {
+        #[cfg(feature = "debug_trace_ref_counts")]
+        println!(
+            ".. [no child] remove_at_depth_branch node_id: {node_id:?} position: {position:?} depth: {depth:?}"
+        );
+
+        BlockId::INVALID
+    }
+}
+
+fn remove_at_depth_leaf<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: &BlockId,
+    position: &IVec3,
+    depth: &TraversalDepth,
+) -> BlockId {
+    #[cfg(feature = "debug_trace_ref_counts")]
+    println!("remove_at_depth_leaf node_id: {node_id:?} position: {position:?} depth: {depth:?}");
+
+    strict_assert!(interner.is_valid_block_id(node_id));
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("remove_at_depth_leaf");
+
+    if depth.current() == depth.max() {
+        // At max depth, just remove the leaf
+        BlockId::EMPTY
+    } else {
+        // Remove the voxel in the appropriate child, splitting the leaf always results in a new branch
+        let new_node_id = remove_at_depth_leaf(interner, node_id, position, &depth.increment());
+
+        strict_assert!(interner.is_valid_block_id(&new_node_id));
+
+        // Convert leaf to branch
+        let index = child_index_macro!(position, depth);
+        let mut children = [*node_id; MAX_CHILDREN];
+        children[index] = new_node_id;
+
+        let is_leaf = new_node_id.is_leaf();
+        let is_empty = new_node_id.is_empty();
+        let types = !(1 << index) | ((is_leaf as u8) << index);
+        let mask = !(1 << index) | ((!is_empty as u8) << index);
+
+        #[cfg(feature = "debug_trace_ref_counts")]
+        println!("incrementing refs for node_id: {node_id:?}");
+
+        // println!("types: {types:b} mask: {mask:b}");
+
+        interner.inc_ref_by(node_id, 7);
+
+        // Create new branch node
+        interner.get_or_create_branch(children, types, mask)
+    }
+}
+
+#[inline(always)]
+fn set_batch_at_root<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    node_id: &BlockId,
+    max_depth: u8,
+    batch: &Batch<T>,
+) -> BlockId {
+    strict_assert!(*node_id != BlockId::INVALID);
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("set_batch_at_root");
+
+    let depth = TraversalDepth::new(0, max_depth);
+
+    set_batch_at_depth_iterative(interner, node_id, &depth, batch)
+}
+
+fn set_batch_at_depth_iterative<T: VoxelTrait>(
+    interner: &mut VoxInterner<T>,
+    initial_node_id: &BlockId,
+    initial_depth: &TraversalDepth,
+    batch: &Batch<T>,
+) -> BlockId {
+    #[cfg(feature = "debug_trace_ref_counts")]
+    println!(
+        "set_batch_at_depth_iterative initial node: {initial_node_id:?} depth: {initial_depth:?} batch size: {}",
+        batch.size()
+    );
+
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("set_batch_at_depth_iterative");
+
+    // The fill is handled by the caller, only patches are applied here
+    if !batch.has_patches() {
+        return BlockId::INVALID;
+    }
+
+    let mut walk = BatchWalk::new(*initial_node_id, initial_depth, batch);
+    let mut budget = usize::MAX;
+
+    walk.prepare(interner, batch, &mut budget);
+    walk.merge(interner, &mut budget)
+        .expect("Unlimited budget finishes the walk")
+}
+
+// State of `set_batch_at_depth_iterative` between its steps, so a batch can also be
+// applied a few paths at a time, see `VoxTree::apply_batch_budgeted`. The tree the walk
+// started from must not change until it is done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BatchWalk {
+    initial_node_id: BlockId,
+    initial_depth: usize,
+    max_depth: usize,
+    // Phase 1 resumes at this octant path of the batch
+    next_path_index: usize,
+    current_level_data: Vec<BlockId>,
+    next_level_data: Vec<BlockId>,
+    paths: Vec<usize>,
+    next_paths: Vec<usize>,
+    // Phase 2 merges the paths into their parents at this depth, up to the root
+    target_depth: usize,
+}
+
+impl BatchWalk {
+    fn new<T: VoxelTrait>(
+        initial_node_id: BlockId,
+        initial_depth: &TraversalDepth,
+        batch: &Batch<T>,
+    ) -> Self {
+        let data_len = batch.masks().len();
+        let max_depth = initial_depth.max() as usize;
+
+        Self {
+            initial_node_id,
+            initial_depth: initial_depth.current() as usize,
+            max_depth,
+            next_path_index: 0,
+            current_level_data: vec![BlockId::INVALID; data_len],
+            next_level_data: vec![BlockId::INVALID; data_len],
+            paths: Vec::with_capacity(data_len),
+            next_paths: Vec::with_capacity(data_len),
+            target_depth: max_depth - 1,
+        }
+    }
+
+    // Phase 1, builds the octant nodes of at most `budget` paths touched by `batch`, taking
+    // them from the budget. Returns `true` once every path is built.
+    fn prepare<T: VoxelTrait>(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        batch: &Batch<T>,
+        budget: &mut usize,
+    ) -> bool {
+        #[cfg(feature = "debug_trace_ref_counts")]
+        {
+            println!(" Phase 1 - Prepare the chain & build dangling branches",);
+            interner.dump_node(self.initial_node_id, 0, "  ");
+        }
+
+        let initial_node_id = self.initial_node_id;
+        let initial_depth = self.initial_depth;
+        let max_depth = self.max_depth;
+
+        let current_level_data = &mut self.current_level_data;
+        let paths = &mut self.paths;
+
+        let masks = batch.masks();
+
+        while self.next_path_index < masks.len() {
+            let path_index = self.next_path_index;
+            let (set_mask, clear_mask) = &masks[path_index];
+
+            if *set_mask == 0 && *clear_mask == 0 {
+                self.next_path_index += 1;
+                continue;
+            }
+
+            if *budget == 0 {
+                return false;
+            }
+
+            *budget -= 1;
+            self.next_path_index += 1;
+
+            let path = path_index << 3;
+
+            let mut current_node_id = initial_node_id;
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!("  path: 0x{path:08X} {path:09b}");
+
+            let mut leaf_node_id = BlockId::EMPTY;
+
+            for current_depth in initial_depth..(max_depth - 1) {
+                if current_node_id.is_branch() {
+                    let index = (path >> ((max_depth - current_depth - 1) * 3)) & 0b111;
+
+                    #[cfg(feature = "debug_trace_ref_counts")]
+                    println!(
+                        "   depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {} [1a]",
+                        interner.get_ref(&current_node_id)
+                    );
+
+                    current_node_id = interner.get_child_id(&current_node_id, index);
+                } else {
+                    // Split leaf node
+                    leaf_node_id = current_node_id;
+                    current_node_id = BlockId::EMPTY;
+
+                    #[cfg(feature = "debug_trace_ref_counts")]
+                    {
+                        let index = (path >> ((max_depth - current_depth - 1) * 3)) & 0b111;
+
+                        println!(
+                            "   depth: {current_depth}/{max_depth} i: {index:2x} current: {current_node_id:?} leaf: {leaf_node_id:?} ref_count: {} [2]",
+                            interner.get_ref(&current_node_id)
+                        );
+                    }
+                }
+
+                if current_node_id.is_empty() {
+                    break;
+                }
+            }
+
+            // The octant itself can be a collapsed leaf, split it as well
+            if current_node_id.is_leaf() {
+                leaf_node_id = current_node_id;
+                current_node_id = BlockId::EMPTY;
+            }
+
+            let values = &batch.values()[path_index];
+
+            let all_same = *set_mask == 0xFF && values.iter().all(|v| v.voxel_eq(&values[0]));
+
+            if !all_same {
+                let (mut children, mut types, mut mask) = if !current_node_id.is_empty() {
+                    (
+                        interner.get_children(&current_node_id),
+                        current_node_id.types(),
+                        current_node_id.mask(),
+                    )
+                } else if leaf_node_id.is_leaf() {
+                    ([leaf_node_id; MAX_CHILDREN], 0xFF, 0xFF)
+                } else {
+                    (EMPTY_CHILD, 0, 0)
+                };
+
+                let mut modified_childs: u8 = 0;
+
+                let mut set_mask_bits = *set_mask;
+                while set_mask_bits != 0 {
+                    let idx = set_mask_bits.trailing_zeros() as usize;
+                    set_mask_bits &= !(1 << idx);
+
+                    let value = &values[idx];
+
+                    if !children[idx].is_empty()
+                        && interner.get_value(&children[idx]).voxel_eq(value)
+                    {
+                        // No change needed
+                        continue;
+                    }
+
+                    children[idx] = interner.get_or_create_leaf(*value);
+
+                    types |= 1 << idx;
+                    mask |= 1 << idx;
+                    modified_childs |= 1 << idx;
+                }
+
+                let mut clear_mask_bits = *clear_mask;
+                while clear_mask_bits != 0 {
+                    let idx = clear_mask_bits.trailing_zeros() as usize;
+                    clear_mask_bits &= !(1 << idx);
+
+                    if children[idx].is_empty() {
+                        // Already empty
+                        continue;
+                    }
+
+                    children[idx] = BlockId::EMPTY;
+
+                    types &= !(1 << idx);
+                    mask &= !(1 << idx);
+                    modified_childs |= 1 << idx;
+                }
+
+                if modified_childs == 0 {
+                    // No changes made
+                    continue;
+                }
+
+                if leaf_node_id.is_leaf() {
+                    // Children set to the split leaf's own value keep pointing at it as well
+                    let data_len = modified_childs.count_ones() as usize;
+                    interner.inc_ref_by(&leaf_node_id, (MAX_CHILDREN - data_len) as u32);
+                } else {
+                    let mut non_modified_childs_bits = !modified_childs;
+                    while non_modified_childs_bits != 0 {
+                        let idx = non_modified_childs_bits.trailing_zeros() as usize;
+                        non_modified_childs_bits &= !(1 << idx);
+
+                        if !children[idx].is_empty() {
+                            // If the child was not modified, we need to increment its ref count
+                            interner.inc_ref_by(&children[idx], 1);
+                        }
+                    }
+                }
+
+                // Clearing every child leaves nothing to build
+                let branch_id = if mask != 0 {
+                    interner.get_or_create_branch(children, types, mask)
+                } else {
+                    BlockId::EMPTY
+                };
+
+                current_level_data[path_index] = branch_id;
+                paths.push(path);
+            } else {
+                #[cfg(feature = "memory_stats")]
+                interner.bump_collapsed_branches();
+
+                let first_value = values[set_mask.trailing_zeros() as usize];
+                let leaf_id = interner.get_or_create_leaf(first_value);
+
+                current_level_data[path_index] = leaf_id;
+                paths.push(path);
+            };
+        }
+
+        true
+    }
+
+    // Phase 2, merges at most `budget` built paths into their parents, level by level up to
+    // the root, taking them from the budget. Returns the new root once the walk is done,
+    // `BlockId::INVALID` if nothing changed.
+    fn merge<T: VoxelTrait>(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        budget: &mut usize,
+    ) -> Option<BlockId> {
+        #[cfg(feature = "debug_trace_ref_counts")]
+        {
+            println!(" Phase 2 - Integrate dangling branches");
+        }
+
+        if self.paths.is_empty() {
+            #[cfg(feature = "debug_trace_ref_counts")]
+            {
+                println!("  No paths to process");
+            }
+            return Some(BlockId::INVALID);
+        }
+
+        let initial_node_id = self.initial_node_id;
+        let max_depth = self.max_depth;
+
+        let mut current_level_data = std::mem::take(&mut self.current_level_data);
+        let mut next_level_data = std::mem::take(&mut self.next_level_data);
+        let mut paths = std::mem::take(&mut self.paths);
+        let mut next_paths = std::mem::take(&mut self.next_paths);
+        let mut target_depth = self.target_depth;
+
+        'main: while let Some(mut path) = paths.pop() {
+            if *budget == 0 {
+                paths.push(path);
+
+                self.current_level_data = current_level_data;
+                self.next_level_data = next_level_data;
+                self.paths = paths;
+                self.next_paths = next_paths;
+                self.target_depth = target_depth;
+
+                return None;
+            }
+
+            *budget -= 1;
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "starting with path: {path:08X} {path:09b} target_depth: {target_depth} paths: {}",
+                paths.len(),
+            );
+
+            let path_mask_depth = if target_depth > 1 {
+                target_depth - 2
+            } else {
+                0
+            };
+
+            let path_mask = PATH_MASKS[max_depth][path_mask_depth] as usize;
+
+            let mut equivalent_id = initial_node_id;
+            let mut leaf_id = BlockId::EMPTY;
+
+            // Find equivalent node in the current tree
+            for current_depth in 0..(target_depth - 1) {
+                if equivalent_id.is_leaf() {
+                    leaf_id = equivalent_id;
+                    equivalent_id = BlockId::EMPTY;
+                    break;
+                } else {
+                    let index = (path >> ((max_depth - current_depth - 1) * 3)) & 0b111;
+                    equivalent_id = interner.get_child_id(&equivalent_id, index);
+                }
+
+                if equivalent_id.is_empty() {
+                    break;
+                }
+            }
+
+            if equivalent_id.is_leaf() {
+                leaf_id = equivalent_id;
+                equivalent_id = BlockId::EMPTY;
+            };
+
+            let mut children = EMPTY_CHILD;
+            let mut types = 0;
+            let mut mask = 0;
+            let mut touched: u8 = 0;
+            let mut has_next_sibling = true;
+
+            while has_next_sibling {
+                #[cfg(feature = "debug_trace_ref_counts")]
+                println!(" path: {path:08X} {path:09b}");
+
+                let target_index = (path >> ((max_depth - target_depth) * 3)) & 0b111;
+                let next_path = paths.last();
+
+                #[cfg(feature = "debug_trace_ref_counts")]
+                if let Some(next_path) = next_path {
+                    println!("  next path: {next_path:08X} {next_path:09b}");
+                }
+
+                has_next_sibling = if target_depth == 1 {
+                    next_path.is_some()
+                } else if let Some(next_path) = next_path {
+                    (path & path_mask) == (*next_path & path_mask)
+                } else {
+                    false
+                };
+
+                let current_path_index = path >> 3;
+                let current_level_id = current_level_data[current_path_index];
+                children[target_index] = current_level_id;
+                current_level_data[current_path_index] = BlockId::INVALID;
+
+                // A cleared octant is touched, but doesn't count as a child anymore
+                types |= (current_level_id.is_leaf() as u8) << target_index;
+                mask |= (!current_level_id.is_empty() as u8) << target_index;
+                touched |= 1 << target_index;
+
+                #[cfg(feature = "debug_trace_ref_counts")]
+                println!(
+                    "  new_path: {:08X} {:09b}",
+                    path & path_mask,
+                    path & path_mask
+                );
+
+                if has_next_sibling && !paths.is_empty() {
+                    path = paths.pop().expect("No path found");
+                    *budget = budget.saturating_sub(1);
+                }
+
+                #[cfg(feature = "debug_trace_ref_counts")]
+                println!("     has_more_paths: {}", !paths.is_empty());
+            }
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            {
+                println!(
+                    "     types: {types:08b} mask: {mask:08b} current_path: {:09b}",
+                    path & path_mask
+                );
+                for (child_idx, child) in children.iter().enumerate() {
+                    println!("     child[{child_idx}]: {child:?}");
+                }
+            }
+
+            let existing_mask = equivalent_id.mask();
+            let inv_mask = !touched;
+            let cloned_nodes = existing_mask & inv_mask;
+
+            if touched != 0xFF {
+                if cloned_nodes != 0 {
+                    #[cfg(feature = "memory_stats")]
+                    interner.bump_cloned_subtrees(cloned_nodes.count_ones() as usize);
+
+                    let existing_children = interner.get_children_ref(&equivalent_id);
+
+                    let mut cloned_nodes_bits = cloned_nodes;
+
+                    while cloned_nodes_bits != 0 {
+                        let idx = cloned_nodes_bits.trailing_zeros() as usize;
+                        cloned_nodes_bits &= !(1 << idx);
+
+                        children[idx] = existing_children[idx];
+
+                        types |= (children[idx].is_leaf() as u8) << idx;
+                        mask |= 1 << idx;
+                    }
+                } else if !leaf_id.is_empty() {
+                    let leafs_to_clone = inv_mask.count_ones();
+
+                    let mut leafs_to_clone_bits = inv_mask;
+
+                    types |= inv_mask;
+                    mask |= inv_mask;
+
+                    while leafs_to_clone_bits != 0 {
+                        let idx = leafs_to_clone_bits.trailing_zeros() as usize;
+                        children[idx] = leaf_id;
+                        leafs_to_clone_bits &= !(1 << idx);
+                    }
+
+                    interner.inc_ref_by(&leaf_id, leafs_to_clone);
+
+                    #[cfg(feature = "memory_stats")]
+                    interner.bump_cloned_subtrees(leafs_to_clone as usize);
+                }
+            }
+
+            let all_same = types == 0xFF && children.iter().all(|item| item == &children[0]);
+
+            let new_node_id = if mask == 0 {
+                BlockId::EMPTY
+            } else if !all_same {
+                let mut cloned_nodes_bits = cloned_nodes;
+                while cloned_nodes_bits != 0 {
+                    let idx = cloned_nodes_bits.trailing_zeros() as usize;
+                    cloned_nodes_bits &= !(1 << idx);
+
+                    interner.inc_ref(&children[idx]);
+                }
+
+                interner.get_or_create_branch(children, types, mask)
+            } else {
+                #[cfg(feature = "memory_stats")]
+                interner.bump_collapsed_branches();
+
+                let dec_ref = if cloned_nodes != 0 {
+                    cloned_nodes.count_ones().min(7)
+                } else {
+                    7
+                };
+
+                interner.dec_ref_by(&children[0], dec_ref);
+
+                children[0]
+            };
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "     new_node_id: {new_node_id:?} ref_count: {}",
+                interner.get_ref(&new_node_id)
+            );
+
+            let next_path = path & path_mask;
+            next_paths.push(next_path);
+            next_level_data[next_path >> 3] = new_node_id;
+
+            #[cfg(feature = "debug_trace_ref_counts")]
+            println!(
+                "     has_more_paths: {} target_depth: {target_depth}",
+                !paths.is_empty(),
+            );
+
+            if paths.is_empty() {
+                std::mem::swap(&mut current_level_data, &mut next_level_data);
+                std::mem::swap(&mut paths, &mut next_paths);
+                next_paths.clear();
+
+                #[cfg(feature = "debug_trace_ref_counts")]
+                println!("  paths: {paths:#?}");
+
+                target_depth -= 1;
+                if target_depth == 0 {
+                    break 'main;
+                }
+            }
+        }
+
+        let final_node_id = current_level_data[paths[0] >> 3];
+
+        #[cfg(feature = "debug_trace_ref_counts")]
+        {
+            println!(" Phase 3 - Finalize");
+            println!("  new_root: {final_node_id:?}");
+            interner.dump_node(final_node_id, 0, "  ");
+        }
+
+        Some(final_node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{
+        Orientation,
+        utils::common::{child_index, collect_nodes, decode_child_index_path, to_vec},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_create() {
+        let tree = VoxTree::<u8>::new(MaxDepth::new(3));
+        assert!(tree.is_empty());
+        assert_eq!(tree.max_depth(Lod::new(0)).max(), 3);
+        assert_eq!(tree.voxels_per_axis(Lod::new(0)), 8);
+    }
+
+    #[test]
+    fn test_child_index() {
+        for max_depth in 0..(MAX_ALLOWED_DEPTH as u8) {
+            let voxels_per_axis = 1 << max_depth;
+            for depth in 0..max_depth {
+                for y in 0..voxels_per_axis {
+                    for z in 0..voxels_per_axis {
+                        for x in 0..voxels_per_axis {
+                            let position = IVec3::new(x, y, z);
+                            let result =
+                                child_index(&position, &TraversalDepth::new(depth, max_depth));
+                            assert!(result < 8);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let mut interner = VoxInterner::with_memory_budget(1024 * 2);
+
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+        let position = IVec3::new(0, 0, 0);
+
+        // Test setting and getting a value
+        assert!(tree.set(&mut interner, position, 42));
+        assert_eq!(tree.get(&interner, position), Some(42));
+
+        // Test overwriting a value
+        assert!(tree.set(&mut interner, position, 24));
+        assert_eq!(tree.get(&interner, position), Some(24));
+
+        // Test getting from an empty position
+        assert_eq!(tree.get(&interner, IVec3::new(1, 1, 1)), None);
+
+        // Test setting at max depth
+        let max_pos = IVec3::new(7, 7, 7); // 2^3 - 1
+        assert!(tree.set(&mut interner, max_pos, 99));
+        assert_eq!(tree.get(&interner, max_pos), Some(99));
+
+        tree.clear(&mut interner);
+
+        let positions = [
+            IVec3::new(0, 0, 0),
+            IVec3::new(0, 0, 1),
+            IVec3::new(0, 1, 0),
+            IVec3::new(0, 1, 1),
+            IVec3::new(1, 0, 0),
+            IVec3::new(1, 0, 1),
+            IVec3::new(1, 1, 0),
+            IVec3::new(1, 1, 1),
+        ];
+
+        for (i, &pos) in positions.iter().enumerate() {
+            tree.set(&mut interner, pos, (i + 1) as u8);
+        }
+
+        for (i, &pos) in positions.iter().enumerate() {
+            assert_eq!(tree.get(&interner, pos).unwrap(), (i + 1) as u8);
+        }
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let mut interner = VoxInterner::with_memory_budget(1024);
+
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+        assert!(tree.is_empty());
+
+        // Setting a value makes it non-empty
+        assert!(tree.set(&mut interner, IVec3::new(0, 0, 0), 1));
+        assert!(!tree.is_empty());
+
+        // Clearing makes it empty again
+        tree.clear(&mut interner);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut interner = VoxInterner::with_memory_budget(1024 * 2);
+
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        let positions = [
+            IVec3::new(0, 0, 0),
+            IVec3::new(0, 0, 1),
+            IVec3::new(0, 1, 0),
+            IVec3::new(0, 1, 1),
+            IVec3::new(1, 0, 0),
+            IVec3::new(1, 0, 1),
+            IVec3::new(1, 1, 0),
+            IVec3::new(1, 1, 1),
+        ];
+
+        for (i, &pos) in positions.iter().enumerate() {
+            tree.set(&mut interner, pos, (i + 1) as u8);
+        }
+
+        tree.clear(&mut interner);
+        assert!(tree.is_empty());
+
+        for &pos in positions.iter() {
+            assert!(tree.get(&interner, pos).is_none());
+        }
+    }
+
+    #[test]
+    fn test_no_default_leaf_nodes() {
+        let mut interner = VoxInterner::with_memory_budget(1024);
+
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        // Set a value and then set it back to default
+        let position = IVec3::new(0, 0, 0);
+        assert!(tree.set(&mut interner, position, 42));
+        assert_eq!(tree.get(&interner, position), Some(42));
+        assert!(!tree.is_empty());
+
+        // 0 is default for u8
+        assert!(tree.set(&mut interner, position, 0));
+        // The node should be removed when set to default
+        assert_eq!(tree.get(&interner, position), None);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_dirty_flag() {
+        let mut interner = VoxInterner::with_memory_budget(1024);
+
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+        assert!(!tree.is_dirty());
+
+        // Setting a value should make it dirty
+        assert!(tree.set(&mut interner, IVec3::new(0, 0, 0), 1));
+        assert!(tree.is_dirty());
+
+        // Clearing the dirty flag
+        tree.clear_dirty();
+        assert!(!tree.is_dirty());
+
+        // Clearing the voxtree should make it dirty again
+        tree.clear(&mut interner);
+        assert!(tree.is_dirty());
+    }
+
+    #[test]
+    fn test_shared_interner_uniqueness() {
+        let mut interner = VoxInterner::with_memory_budget(1024);
+
+        let mut tree1 = VoxTree::new(MaxDepth::new(3));
+        let mut tree2 = VoxTree::new(MaxDepth::new(3));
+
+        // Both trees should be empty initially
+        assert!(tree1.is_empty());
+        assert!(tree2.is_empty());
+
+        // Setting in one tree should not affect the other
+        assert!(tree1.set(&mut interner, IVec3::new(0, 0, 0), 42));
+        assert_eq!(tree1.get(&interner, IVec3::new(0, 0, 0)), Some(42));
+        assert_eq!(tree2.get(&interner, IVec3::new(0, 0, 0)), None);
+
+        // But they should share the same interner for efficiency
+        assert!(tree2.set(&mut interner, IVec3::new(0, 0, 0), 24));
+        assert_eq!(tree2.get(&interner, IVec3::new(0, 0, 0)), Some(24));
+        assert_ne!(tree1.get_root_id(), tree2.get_root_id());
+    }
+
+    #[test]
+    fn test_shared_interner_deduplication() {
+        let mut interner = VoxInterner::with_memory_budget(1024);
+
+        let mut tree1 = VoxTree::new(MaxDepth::new(3));
+        let mut tree2 = VoxTree::new(MaxDepth::new(3));
+
+        // Both trees should be empty initially
+        assert!(tree1.is_empty());
+        assert!(tree2.is_empty());
+
+        // Setting same value in both trees should result in the same root id (deduplication)
+        assert!(tree1.set(&mut interner, IVec3::new(0, 0, 0), 42));
+        assert!(tree2.set(&mut interner, IVec3::new(0, 0, 0), 42));
+        assert_eq!(tree1.get_root_id(), tree2.get_root_id());
+    }
+
+    #[test]
+    fn test_set_behaviour() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+
+        let position = IVec3::new(0, 0, 0);
+        assert!(tree.set(&mut interner, position, TEST_VALUE));
+        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+
+        // Test overwriting a value
+        assert!(tree.set(&mut interner, position, TEST_VALUE + 1));
+        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE + 1));
+
+        // Test setting same value
+        assert!(!tree.set(&mut interner, position, TEST_VALUE + 1));
+        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE + 1));
+    }
+
+    #[test]
+    fn test_batch_double_apply() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+
+        let position = IVec3::new(0, 0, 0);
+
+        let mut batch = tree.create_batch();
+
+        batch.set(&mut interner, position, TEST_VALUE);
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+        assert!(!tree.apply_batch(&mut interner, &batch));
+    }
+
+    #[test]
+    fn test_batch_on_collapsed_octant() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+
+        // a uniform 2x2x2 octant collapses into a single leaf
+        for y in 2..4 {
+            for z in 2..4 {
+                for x in 2..4 {
+                    tree.set(&mut interner, IVec3::new(x, y, z), 1);
+                }
+            }
+        }
+
+        let mut batch = tree.create_batch();
+        batch.set(&mut interner, IVec3::new(3, 3, 3), 2);
+        assert!(tree.apply_batch(&mut interner, &batch));
+
+        assert_eq!(tree.get(&interner, IVec3::new(3, 3, 3)), Some(2));
+        assert_eq!(tree.get(&interner, IVec3::new(2, 2, 2)), Some(1));
+        assert_eq!(tree.get(&interner, IVec3::new(1, 1, 1)), None);
+
+        tree.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_batch_fill_with_fill_valued_patches() {
+        const FILL_VALUE: i32 = 1;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        let value_at = |x: i32, y: i32, z: i32| (x + y * 3 + z * 7) % 4 + 1;
+
+        // some patches keep the fill value, the split fill leaf must stay referenced by them
+        let mut batch = tree.create_batch();
+        batch.fill(&mut interner, FILL_VALUE);
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    batch.set(&mut interner, IVec3::new(x, y, z), value_at(x, y, z));
+                }
+            }
+        }
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    assert_eq!(
+                        tree.get(&interner, IVec3::new(x, y, z)),
+                        Some(value_at(x, y, z))
+                    );
+                }
+            }
+        }
+
+        tree.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[cfg(feature = "memory_stats")]
+    #[test]
+    fn test_apply_batch_stats() {
+        use crate::interner::BatchStats;
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
+
+        // every voxel set to the same value, each level collapses into a single leaf
+        let mut batch = tree.create_batch();
+        for y in 0..8 {
+            for z in 0..8 {
+                for x in 0..8 {
+                    batch.set(&mut interner, IVec3::new(x, y, z), 1);
+                }
+            }
+        }
+
+        let before = interner.stats();
+        tree.apply_batch(&mut interner, &batch);
+        let stats = BatchStats::between(&before, &interner.stats());
+
+        assert!(tree.is_leaf());
+        assert_eq!(stats.collapsed_branches, 64 + 8 + 1);
+        assert_eq!(stats.created_nodes, 1);
+        assert_eq!(stats.deduped_nodes, 63);
+        assert_eq!(stats.cloned_subtrees, 0);
+
+        // a single edit splits the leaf, its untouched siblings are copies of it
+        let mut batch = tree.create_batch();
+        batch.set(&mut interner, IVec3::ZERO, 2);
+
+        let before = interner.stats();
+        tree.apply_batch(&mut interner, &batch);
+        let stats = BatchStats::between(&before, &interner.stats());
+
+        assert_eq!(stats.collapsed_branches, 0);
+        assert_eq!(stats.cloned_subtrees, 7 + 7);
+        assert!(stats.created_nodes > 0);
+
+        tree.clear(&mut interner);
+    }
+
+    #[test]
+    fn test_apply_batch_budgeted() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut expected = VoxTree::new(MAX_DEPTH);
+        let mut budgeted = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = expected.voxels_per_axis(Lod::new(0)) as i32;
+
+        let mut batch = expected.create_batch();
+        batch.fill(&mut interner, 1);
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let value = (x + y * 3 + z * 7) % 5;
+                    batch.set(&mut interner, IVec3::new(x, y, z), value);
+                }
+            }
+        }
+
+        assert!(expected.apply_batch(&mut interner, &batch));
+
+        let mut state = ApplyState::default();
+        let mut calls = 0;
+        while !state.is_done() {
+            let root_id = budgeted.get_root_id();
+            state = budgeted.apply_batch_budgeted(&mut interner, &batch, state, 100);
+            calls += 1;
+
+            // the root is only replaced by the last call, after the fill of the first one
+            if calls > 1 && !state.is_done() {
+                assert_eq!(budgeted.get_root_id(), root_id);
+            }
+        }
+
+        // every touched path is built once and merged once, each call handles up to 100 of them
+        let paths = batch
+            .masks()
+            .iter()
+            .filter(|masks| **masks != (0, 0))
+            .count();
+        assert!(state.changed());
+        assert!(calls > 2 * paths / 100);
+        assert!(calls <= 3 * paths.div_ceil(100) + 1);
+        assert_eq!(budgeted.get_root_id(), expected.get_root_id());
+
+        // resuming a finished state is a no-op
+        let root_id = budgeted.get_root_id();
+        let state = budgeted.apply_batch_budgeted(&mut interner, &batch, state, 100);
+        assert!(state.is_done());
+        assert_eq!(budgeted.get_root_id(), root_id);
+    }
+
+    #[test]
+    fn test_batch_clear() {
+        const FILL_VALUE: i32 = 1;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(4);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        tree.fill(&mut interner, FILL_VALUE);
+
+        let mut batch = tree.create_batch();
+        for i in 0..voxels_per_axis {
+            batch.set(&mut interner, IVec3::new(i, i, i), 0);
+        }
+        batch.set(&mut interner, IVec3::new(3, 1, 2), 2);
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let expected = if x == y && y == z {
+                        None
+                    } else if (x, y, z) == (3, 1, 2) {
+                        Some(2)
+                    } else {
+                        Some(FILL_VALUE)
+                    };
+                    assert_eq!(tree.get(&interner, IVec3::new(x, y, z)), expected);
+                }
+            }
+        }
+
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+
+        let mut batch = tree.create_batch();
+        batch.set(&mut interner, IVec3::new(0, 0, 0), 0);
+        assert!(!tree.apply_batch(&mut interner, &batch));
+    }
+
+    #[test]
+    fn test_batch_clear_whole_tree() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        tree.set(&mut interner, IVec3::new(0, 0, 0), 1);
+        tree.set(&mut interner, IVec3::new(1, 0, 0), 2);
+        tree.set(&mut interner, IVec3::new(7, 7, 7), 3);
+
+        // Clearing a whole octant drops it from its parent
+        let mut batch = tree.create_batch();
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    batch.set(&mut interner, IVec3::new(x, y, z), 0);
+                }
+            }
+        }
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+        assert_eq!(tree.get(&interner, IVec3::new(0, 0, 0)), None);
+        assert_eq!(tree.get(&interner, IVec3::new(1, 0, 0)), None);
+        assert_eq!(tree.get(&interner, IVec3::new(7, 7, 7)), Some(3));
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+
+        let mut batch = tree.create_batch();
+        for z in 0..voxels_per_axis {
+            for y in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    batch.set(&mut interner, IVec3::new(x, y, z), 0);
+                }
+            }
+        }
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+        assert!(tree.is_empty());
+        assert!(!tree.apply_batch(&mut interner, &batch));
+    }
+
+    #[test]
+    fn test_batch_clear_matches_set() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(4);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut batched = VoxTree::new(MAX_DEPTH);
+        let mut expected = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = batched.voxels_per_axis(Lod::new(0)) as i32;
+
+        batched.fill(&mut interner, 1);
+        expected.fill(&mut interner, 1);
+
+        let mut rng = rand::rng();
+
+        for _ in 0..8 {
+            let mut batch = batched.create_batch();
+
+            for _ in 0..200 {
+                let position = IVec3::new(
+                    rng.random_range(0..voxels_per_axis),
+                    rng.random_range(0..voxels_per_axis),
+                    rng.random_range(0..voxels_per_axis),
+                );
+                let value = if rng.random_bool(0.7) {
+                    0
+                } else {
+                    rng.random_range(1..=3)
+                };
+
+                batch.set(&mut interner, position, value);
+            }
+
+            batched.apply_batch(&mut interner, &batch);
+
+            for (position, value) in batch.iter_set() {
+                expected.set(&mut interner, position, value);
+            }
+            for position in batch.iter_clear() {
+                expected.set(&mut interner, position, 0);
+            }
+
+            assert!(batched.content_eq(&expected, &interner));
+        }
+    }
+
+    #[test]
+    fn test_patterns_set_expand_shared_leaf() {
+        const START_VALUE: u8 = 1;
+        const END_VALUE: u8 = 6;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        for value in START_VALUE..END_VALUE {
+            tree.fill(&mut interner, value * 10);
+            tree.set(&mut interner, IVec3::new(0, 0, 0), value);
+
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        let position = IVec3::new(x, y, z);
+                        let value = if x == 0 && y == 0 && z == 0 {
+                            value
+                        } else {
+                            value * 10
+                        };
+                        assert_eq!(tree.get(&interner, position), Some(value));
+                    }
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
+
+    #[test]
+    fn test_patterns_batch_expand_shared_leaf() {
+        const FILL_VALUE: u8 = 1;
+        const TEST_VALUE: u8 = 2;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        let mut branch = tree.create_batch();
+
+        branch.fill(&mut interner, FILL_VALUE);
+        branch.set(&mut interner, IVec3::new(0, 0, 0), TEST_VALUE);
+
+        assert!(tree.apply_batch(&mut interner, &branch));
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    let value = if x == 0 && y == 0 && z == 0 {
+                        TEST_VALUE
+                    } else {
+                        FILL_VALUE
+                    };
+                    assert_eq!(tree.get(&interner, position), Some(value));
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
+
+    #[test]
+    fn test_patterns_set_checkerboard() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        // Create a checkerboard pattern
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    if (x + y + z) % 2 == 0 {
+                        let position = IVec3::new(x, y, z);
+                        let value = 2;
+                        assert!(tree.set(&mut interner, position, value));
+                        assert_eq!(tree.get(&interner, position), Some(value));
+                    }
+                }
+            }
+        }
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    let value = if (x + y + z) % 2 == 0 { Some(2) } else { None };
+                    assert_eq!(tree.get(&interner, position), value);
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
+
+    #[test]
+    fn test_patterns_batch_checkerboard() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        let mut batch = tree.create_batch();
+
+        // Create a checkerboard pattern
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    let value = if (x + y + z) % 2 == 0 { 2 } else { 1 };
+                    assert!(batch.set(&mut interner, position, value));
+                }
+            }
+        }
 
-        let positions = [
-            IVec3::new(0, 0, 0),
-            IVec3::new(0, 0, 1),
-            IVec3::new(0, 1, 0),
-            IVec3::new(0, 1, 1),
-            IVec3::new(1, 0, 0),
-            IVec3::new(1, 0, 1),
-            IVec3::new(1, 1, 0),
-            IVec3::new(1, 1, 1),
-        ];
+        assert!(tree.apply_batch(&mut interner, &batch));
 
-        for (i, &pos) in positions.iter().enumerate() {
-            tree.set(&mut interner, pos, (i + 1) as u8);
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    let value = if (x + y + z) % 2 == 0 { 2 } else { 1 };
+                    assert_eq!(tree.get(&interner, position), Some(value));
+                }
+            }
         }
 
-        tree.clear(&mut interner);
-        assert!(tree.is_empty());
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
 
-        for &pos in positions.iter() {
-            assert!(tree.get(&interner, pos).is_none());
+    #[test]
+    fn test_patterns_set_solid_fill_one_by_one() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert!(tree.set(&mut interner, position, TEST_VALUE));
+                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                }
+            }
+        }
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                }
+            }
         }
+        assert!(!tree.is_empty());
+        assert!(tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_no_default_leaf_nodes() {
-        let mut interner = VoxInterner::with_memory_budget(1024);
+    fn test_patterns_batch_solid_fill_one_by_one() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
 
-        let mut tree = VoxTree::new(MaxDepth::new(3));
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        // Set a value and then set it back to default
-        let position = IVec3::new(0, 0, 0);
-        assert!(tree.set(&mut interner, position, 42));
-        assert_eq!(tree.get(&interner, position), Some(42));
+        let mut batch = tree.create_batch();
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert!(batch.set(&mut interner, position, TEST_VALUE));
+                }
+            }
+        }
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                }
+            }
+        }
         assert!(!tree.is_empty());
+        assert!(tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
 
-        // 0 is default for u8
-        assert!(tree.set(&mut interner, position, 0));
-        // The node should be removed when set to default
-        assert_eq!(tree.get(&interner, position), None);
-        assert!(tree.is_empty());
+    #[test]
+    fn test_patterns_set_solid_fill_half_one_by_one() {
+        const START_VALUE: u8 = 1;
+        const END_VALUE: u8 = 6;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let half_voxels_per_axis = voxels_per_axis / 2;
+
+        for value in START_VALUE..END_VALUE {
+            for y in 0..half_voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        let position = IVec3::new(x, y, z);
+                        assert!(tree.set(&mut interner, position, value));
+                        assert_eq!(tree.get(&interner, position), Some(value));
+                    }
+                }
+            }
+
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        let position = IVec3::new(x, y, z);
+                        assert_eq!(
+                            tree.get(&interner, position),
+                            if y < half_voxels_per_axis {
+                                Some(value)
+                            } else {
+                                None
+                            }
+                        );
+                    }
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_dirty_flag() {
-        let mut interner = VoxInterner::with_memory_budget(1024);
+    fn test_patterns_batch_solid_fill_half_one_by_one() {
+        const START_VALUE: u8 = 1;
+        const END_VALUE: u8 = 6;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
 
-        let mut tree = VoxTree::new(MaxDepth::new(3));
-        assert!(!tree.is_dirty());
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let half_voxels_per_axis = voxels_per_axis / 2;
 
-        // Setting a value should make it dirty
-        assert!(tree.set(&mut interner, IVec3::new(0, 0, 0), 1));
-        assert!(tree.is_dirty());
+        for value in START_VALUE..END_VALUE {
+            let mut batch = tree.create_batch();
 
-        // Clearing the dirty flag
-        tree.clear_dirty();
-        assert!(!tree.is_dirty());
+            for y in 0..half_voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        let position = IVec3::new(x, y, z);
+                        assert!(batch.set(&mut interner, position, value));
+                    }
+                }
+            }
 
-        // Clearing the voxtree should make it dirty again
-        tree.clear(&mut interner);
-        assert!(tree.is_dirty());
+            assert!(tree.apply_batch(&mut interner, &batch));
+
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        let position = IVec3::new(x, y, z);
+                        assert_eq!(
+                            tree.get(&interner, position),
+                            if y < half_voxels_per_axis {
+                                Some(value)
+                            } else {
+                                None
+                            }
+                        );
+                    }
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
+
+    #[test]
+    fn test_patterns_set_solid_fill_fill_op() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        tree.fill(&mut interner, TEST_VALUE);
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
+
+    #[test]
+    fn test_patterns_batch_solid_fill_fill_op() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
+
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+
+        let mut batch = tree.create_batch();
+
+        batch.fill(&mut interner, TEST_VALUE);
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_shared_interner_uniqueness() {
-        let mut interner = VoxInterner::with_memory_budget(1024);
+    fn test_patterns_set_sparse_fill() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
 
-        let mut tree1 = VoxTree::new(MaxDepth::new(3));
-        let mut tree2 = VoxTree::new(MaxDepth::new(3));
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        // Both trees should be empty initially
-        assert!(tree1.is_empty());
-        assert!(tree2.is_empty());
+        for y in (0..voxels_per_axis).step_by(4) {
+            for z in (0..voxels_per_axis).step_by(4) {
+                for x in (0..voxels_per_axis).step_by(4) {
+                    let position = IVec3::new(x, y, z);
+                    assert!(tree.set(&mut interner, position, TEST_VALUE));
+                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                }
+            }
+        }
 
-        // Setting in one tree should not affect the other
-        assert!(tree1.set(&mut interner, IVec3::new(0, 0, 0), 42));
-        assert_eq!(tree1.get(&interner, IVec3::new(0, 0, 0)), Some(42));
-        assert_eq!(tree2.get(&interner, IVec3::new(0, 0, 0)), None);
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
 
-        // But they should share the same interner for efficiency
-        assert!(tree2.set(&mut interner, IVec3::new(0, 0, 0), 24));
-        assert_eq!(tree2.get(&interner, IVec3::new(0, 0, 0)), Some(24));
-        assert_ne!(tree1.get_root_id(), tree2.get_root_id());
+                    if x % 4 == 0 && y % 4 == 0 && z % 4 == 0 {
+                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    } else {
+                        assert_eq!(tree.get(&interner, position), None);
+                    }
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_shared_interner_deduplication() {
-        let mut interner = VoxInterner::with_memory_budget(1024);
+    fn test_patterns_batch_sparse_fill() {
+        const TEST_VALUE: u8 = 3;
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+        const MEMORY_BUDGET: usize = 1024 * 1024;
 
-        let mut tree1 = VoxTree::new(MaxDepth::new(3));
-        let mut tree2 = VoxTree::new(MaxDepth::new(3));
+        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
+        let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        // Both trees should be empty initially
-        assert!(tree1.is_empty());
-        assert!(tree2.is_empty());
+        let mut batch = tree.create_batch();
 
-        // Setting same value in both trees should result in the same root id (deduplication)
-        assert!(tree1.set(&mut interner, IVec3::new(0, 0, 0), 42));
-        assert!(tree2.set(&mut interner, IVec3::new(0, 0, 0), 42));
-        assert_eq!(tree1.get_root_id(), tree2.get_root_id());
+        for y in (0..voxels_per_axis).step_by(4) {
+            for z in (0..voxels_per_axis).step_by(4) {
+                for x in (0..voxels_per_axis).step_by(4) {
+                    let position = IVec3::new(x, y, z);
+                    assert!(batch.set(&mut interner, position, TEST_VALUE));
+                }
+            }
+        }
+
+        assert!(tree.apply_batch(&mut interner, &batch));
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+
+                    if x % 4 == 0 && y % 4 == 0 && z % 4 == 0 {
+                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    } else {
+                        assert_eq!(tree.get(&interner, position), None);
+                    }
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_set_behaviour() {
-        const TEST_VALUE: u8 = 3;
+    fn test_patterns_set_gradient_fill() {
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
         let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
         let mut tree = VoxTree::new(MAX_DEPTH);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        let position = IVec3::new(0, 0, 0);
-        assert!(tree.set(&mut interner, position, TEST_VALUE));
-        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+        for x in 0..voxels_per_axis {
+            let value = (x % 256) as u8;
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(tree.set(&mut interner, position, value), value > 0);
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if value > 0 { Some(value) } else { None }
+                    );
+                }
+            }
+        }
 
-        // Test overwriting a value
-        assert!(tree.set(&mut interner, position, TEST_VALUE + 1));
-        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE + 1));
+        for x in 0..voxels_per_axis {
+            let value = (x % 256) as u8;
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if value > 0 { Some(value) } else { None }
+                    );
+                }
+            }
+        }
 
-        // Test setting same value
-        assert!(!tree.set(&mut interner, position, TEST_VALUE + 1));
-        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE + 1));
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_batch_double_apply() {
-        const TEST_VALUE: u8 = 3;
+    fn test_patterns_batch_gradient_fill() {
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
         let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
         let mut tree = VoxTree::new(MAX_DEPTH);
-
-        let position = IVec3::new(0, 0, 0);
+        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
         let mut batch = tree.create_batch();
 
-        batch.set(&mut interner, position, TEST_VALUE);
+        for x in 0..voxels_per_axis {
+            let value = (x % 256) as u8;
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert!(batch.set(&mut interner, position, value));
+                }
+            }
+        }
 
         assert!(tree.apply_batch(&mut interner, &batch));
-        assert!(!tree.apply_batch(&mut interner, &batch));
+
+        for x in 0..voxels_per_axis {
+            let value = (x % 256) as u8;
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if value > 0 { Some(value) } else { None }
+                    );
+                }
+            }
+        }
+
+        assert!(!tree.is_empty());
+        assert!(!tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_patterns_set_expand_shared_leaf() {
-        const START_VALUE: u8 = 1;
-        const END_VALUE: u8 = 6;
+    fn test_patterns_set_hollow_cube() {
+        const TEST_VALUE: u8 = 3;
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
@@ -1372,20 +3990,42 @@ mod tests {
         let mut tree = VoxTree::new(MAX_DEPTH);
         let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        for value in START_VALUE..END_VALUE {
-            tree.fill(&mut interner, value * 10);
-            tree.set(&mut interner, IVec3::new(0, 0, 0), value);
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let is_edge = x == 0
+                        || x == voxels_per_axis - 1
+                        || y == 0
+                        || y == voxels_per_axis - 1
+                        || z == 0
+                        || z == voxels_per_axis - 1;
 
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    for x in 0..voxels_per_axis {
-                        let position = IVec3::new(x, y, z);
-                        let value = if x == 0 && y == 0 && z == 0 {
-                            value
-                        } else {
-                            value * 10
-                        };
-                        assert_eq!(tree.get(&interner, position), Some(value));
+                    let position = IVec3::new(x, y, z);
+                    if is_edge {
+                        assert!(tree.set(&mut interner, position, TEST_VALUE));
+                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    } else {
+                        assert_eq!(tree.get(&interner, position), None);
+                    }
+                }
+            }
+        }
+
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let is_edge = x == 0
+                        || x == voxels_per_axis - 1
+                        || y == 0
+                        || y == voxels_per_axis - 1
+                        || z == 0
+                        || z == voxels_per_axis - 1;
+
+                    let position = IVec3::new(x, y, z);
+                    if is_edge {
+                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    } else {
+                        assert_eq!(tree.get(&interner, position), None);
                     }
                 }
             }
@@ -1397,9 +4037,8 @@ mod tests {
     }
 
     #[test]
-    fn test_patterns_batch_expand_shared_leaf() {
-        const FILL_VALUE: u8 = 1;
-        const TEST_VALUE: u8 = 2;
+    fn test_patterns_batch_hollow_cube() {
+        const TEST_VALUE: u8 = 3;
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
@@ -1407,23 +4046,44 @@ mod tests {
         let mut tree = VoxTree::new(MAX_DEPTH);
         let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        let mut branch = tree.create_batch();
+        let mut batch = tree.create_batch();
 
-        branch.fill(&mut interner, FILL_VALUE);
-        branch.set(&mut interner, IVec3::new(0, 0, 0), TEST_VALUE);
+        for y in 0..voxels_per_axis {
+            for z in 0..voxels_per_axis {
+                for x in 0..voxels_per_axis {
+                    let is_edge = x == 0
+                        || x == voxels_per_axis - 1
+                        || y == 0
+                        || y == voxels_per_axis - 1
+                        || z == 0
+                        || z == voxels_per_axis - 1;
 
-        assert!(tree.apply_batch(&mut interner, &branch));
+                    let position = IVec3::new(x, y, z);
+                    if is_edge {
+                        assert!(batch.set(&mut interner, position, TEST_VALUE));
+                    }
+                }
+            }
+        }
+
+        assert!(tree.apply_batch(&mut interner, &batch));
 
         for y in 0..voxels_per_axis {
             for z in 0..voxels_per_axis {
                 for x in 0..voxels_per_axis {
+                    let is_edge = x == 0
+                        || x == voxels_per_axis - 1
+                        || y == 0
+                        || y == voxels_per_axis - 1
+                        || z == 0
+                        || z == voxels_per_axis - 1;
+
                     let position = IVec3::new(x, y, z);
-                    let value = if x == 0 && y == 0 && z == 0 {
-                        TEST_VALUE
+                    if is_edge {
+                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
                     } else {
-                        FILL_VALUE
-                    };
-                    assert_eq!(tree.get(&interner, position), Some(value));
+                        assert_eq!(tree.get(&interner, position), None);
+                    }
                 }
             }
         }
@@ -1434,7 +4094,8 @@ mod tests {
     }
 
     #[test]
-    fn test_patterns_set_checkerboard() {
+    fn test_patterns_set_diagonal() {
+        const TEST_VALUE: u8 = 3;
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
@@ -1442,26 +4103,24 @@ mod tests {
         let mut tree = VoxTree::new(MAX_DEPTH);
         let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
 
-        // Create a checkerboard pattern
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    if (x + y + z) % 2 == 0 {
-                        let position = IVec3::new(x, y, z);
-                        let value = 2;
-                        assert!(tree.set(&mut interner, position, value));
-                        assert_eq!(tree.get(&interner, position), Some(value));
-                    }
-                }
-            }
+        for i in 0..voxels_per_axis {
+            let position = IVec3::new(i, i, i);
+            assert!(tree.set(&mut interner, position, TEST_VALUE));
+            assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
         }
 
         for y in 0..voxels_per_axis {
             for z in 0..voxels_per_axis {
                 for x in 0..voxels_per_axis {
                     let position = IVec3::new(x, y, z);
-                    let value = if (x + y + z) % 2 == 0 { Some(2) } else { None };
-                    assert_eq!(tree.get(&interner, position), value);
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if x == y && x == z {
+                            Some(TEST_VALUE)
+                        } else {
+                            None
+                        }
+                    );
                 }
             }
         }
@@ -1472,7 +4131,8 @@ mod tests {
     }
 
     #[test]
-    fn test_patterns_batch_checkerboard() {
+    fn test_patterns_batch_diagonal() {
+        const TEST_VALUE: u8 = 3;
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
@@ -1482,15 +4142,9 @@ mod tests {
 
         let mut batch = tree.create_batch();
 
-        // Create a checkerboard pattern
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    let value = if (x + y + z) % 2 == 0 { 2 } else { 1 };
-                    assert!(batch.set(&mut interner, position, value));
-                }
-            }
+        for i in 0..voxels_per_axis {
+            let position = IVec3::new(i, i, i);
+            assert!(batch.set(&mut interner, position, TEST_VALUE));
         }
 
         assert!(tree.apply_batch(&mut interner, &batch));
@@ -1499,8 +4153,14 @@ mod tests {
             for z in 0..voxels_per_axis {
                 for x in 0..voxels_per_axis {
                     let position = IVec3::new(x, y, z);
-                    let value = if (x + y + z) % 2 == 0 { 2 } else { 1 };
-                    assert_eq!(tree.get(&interner, position), Some(value));
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if x == y && x == z {
+                            Some(TEST_VALUE)
+                        } else {
+                            None
+                        }
+                    );
                 }
             }
         }
@@ -1511,57 +4171,81 @@ mod tests {
     }
 
     #[test]
-    fn test_patterns_set_solid_fill_one_by_one() {
-        const TEST_VALUE: u8 = 3;
+    fn test_patterns_set_random_noise() {
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
         let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
         let mut tree = VoxTree::new(MAX_DEPTH);
+
         let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let size = 1 << (3 * MAX_DEPTH.max());
+        let mut data = vec![0; size as usize];
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert!(tree.set(&mut interner, position, TEST_VALUE));
-                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
-                }
-            }
+        let mut rng = rand::rng();
+
+        for _ in 0..1000 {
+            let x = rng.random_range(0..voxels_per_axis);
+            let y = rng.random_range(0..voxels_per_axis);
+            let z = rng.random_range(0..voxels_per_axis);
+            let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
+            let value = rng.random_range(1..=255) as u8;
+            data[index as usize] = value;
+            let position = IVec3::new(x, y, z);
+
+            assert!(tree.set(&mut interner, position, value));
+            assert_eq!(tree.get(&interner, position), Some(value));
         }
 
         for y in 0..voxels_per_axis {
             for z in 0..voxels_per_axis {
                 for x in 0..voxels_per_axis {
+                    let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
+                    let expected_value = data[index as usize];
                     let position = IVec3::new(x, y, z);
-                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if expected_value != 0 {
+                            Some(expected_value)
+                        } else {
+                            None
+                        }
+                    );
                 }
             }
         }
+
         assert!(!tree.is_empty());
-        assert!(tree.is_leaf());
+        assert!(!tree.is_leaf());
         assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_patterns_batch_solid_fill_one_by_one() {
-        const TEST_VALUE: u8 = 3;
+    fn test_patterns_batch_random_noise() {
         const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
         let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
         let mut tree = VoxTree::new(MAX_DEPTH);
+
         let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let size = 1 << (3 * MAX_DEPTH.max());
+        let mut data = vec![0; size as usize];
 
         let mut batch = tree.create_batch();
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert!(batch.set(&mut interner, position, TEST_VALUE));
-                }
-            }
+        let mut rng = rand::rng();
+
+        for _ in 0..1000 {
+            let x = rng.random_range(0..voxels_per_axis);
+            let y = rng.random_range(0..voxels_per_axis);
+            let z = rng.random_range(0..voxels_per_axis);
+            let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
+            let value = rng.random_range(1..=255) as u8;
+            data[index as usize] = value;
+            let position = IVec3::new(x, y, z);
+
+            assert!(batch.set(&mut interner, position, value));
         }
 
         assert!(tree.apply_batch(&mut interner, &batch));
@@ -1569,635 +4253,1159 @@ mod tests {
         for y in 0..voxels_per_axis {
             for z in 0..voxels_per_axis {
                 for x in 0..voxels_per_axis {
+                    let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
+                    let expected_value = data[index as usize];
                     let position = IVec3::new(x, y, z);
-                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    assert_eq!(
+                        tree.get(&interner, position),
+                        if expected_value != 0 {
+                            Some(expected_value)
+                        } else {
+                            None
+                        }
+                    );
                 }
             }
         }
+
         assert!(!tree.is_empty());
-        assert!(tree.is_leaf());
+        assert!(!tree.is_leaf());
         assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
     }
 
     #[test]
-    fn test_patterns_set_solid_fill_half_one_by_one() {
-        const START_VALUE: u8 = 1;
-        const END_VALUE: u8 = 6;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+    fn test_max_depth_zero() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(0);
         const MEMORY_BUDGET: usize = 1024 * 1024;
 
         let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
         let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
-        let half_voxels_per_axis = voxels_per_axis / 2;
 
-        for value in START_VALUE..END_VALUE {
-            for y in 0..half_voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    for x in 0..voxels_per_axis {
-                        let position = IVec3::new(x, y, z);
-                        assert!(tree.set(&mut interner, position, value));
-                        assert_eq!(tree.get(&interner, position), Some(value));
-                    }
-                }
-            }
+        let position = IVec3::new(0, 0, 0);
+        assert!(tree.set(&mut interner, position, 1));
+        assert_eq!(tree.get(&interner, position), Some(1));
 
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    for x in 0..voxels_per_axis {
-                        let position = IVec3::new(x, y, z);
-                        assert_eq!(
-                            tree.get(&interner, position),
-                            if y < half_voxels_per_axis {
-                                Some(value)
-                            } else {
-                                None
-                            }
-                        );
+        assert!(!tree.is_empty());
+        assert!(tree.is_leaf());
+        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    }
+
+    fn build_csg_tree(
+        interner: &mut VoxInterner<i32>,
+        max_depth: MaxDepth,
+        voxel: impl Fn(IVec3) -> i32,
+    ) -> VoxTree<i32> {
+        let mut tree = VoxTree::new(max_depth);
+        let size = 1 << max_depth.max();
+
+        let mut batch = tree.create_batch();
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let position = IVec3::new(x, y, z);
+                    let value = voxel(position);
+                    if value != 0 {
+                        batch.set(interner, position, value);
                     }
                 }
             }
         }
+        tree.apply_batch(interner, &batch);
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        tree
     }
 
     #[test]
-    fn test_patterns_batch_solid_fill_half_one_by_one() {
-        const START_VALUE: u8 = 1;
-        const END_VALUE: u8 = 6;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
-
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
-        let half_voxels_per_axis = voxels_per_axis / 2;
+    fn test_csg_matches_per_voxel() {
+        let max_depth = MaxDepth::new(4);
+        let size = 1 << max_depth.max();
 
-        for value in START_VALUE..END_VALUE {
-            let mut batch = tree.create_batch();
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
-            for y in 0..half_voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    for x in 0..voxels_per_axis {
-                        let position = IVec3::new(x, y, z);
-                        assert!(batch.set(&mut interner, position, value));
-                    }
-                }
+        // solid lower half, which collapses into large leaves, and a ball crossing it
+        let mut terrain = build_csg_tree(&mut interner, max_depth, |p| (p.y < 8) as i32);
+        let mut ball = build_csg_tree(&mut interner, max_depth, |p| {
+            if (p - IVec3::new(9, 8, 6)).length_squared() < 25 {
+                2 + p.x % 2
+            } else {
+                0
             }
+        });
 
-            assert!(tree.apply_batch(&mut interner, &batch));
+        type Expected = fn(Option<i32>, Option<i32>) -> Option<i32>;
 
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    for x in 0..voxels_per_axis {
+        let ops: [(Expected, CsgOp); 3] = [
+            (|a, b| a.or(b), CsgOp::Union),
+            (|a, b| b.and(a), CsgOp::Intersect),
+            (|a, b| if b.is_some() { None } else { a }, CsgOp::Difference),
+        ];
+
+        for (expected, op) in ops {
+            let mut result = VoxTree::new(max_depth);
+            result.set_root_id(&mut interner, terrain.get_root_id());
+
+            assert!(result.combine(&mut interner, &ball, op), "{op:?}");
+            assert!(result.is_dirty());
+
+            for y in 0..size {
+                for z in 0..size {
+                    for x in 0..size {
                         let position = IVec3::new(x, y, z);
                         assert_eq!(
-                            tree.get(&interner, position),
-                            if y < half_voxels_per_axis {
-                                Some(value)
-                            } else {
-                                None
-                            }
+                            result.get(&interner, position),
+                            expected(
+                                terrain.get(&interner, position),
+                                ball.get(&interner, position)
+                            ),
+                            "{op:?} at {position:?}"
                         );
                     }
                 }
             }
+
+            result.clear(&mut interner);
         }
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        // the operands are untouched
+        assert_eq!(terrain.get(&interner, IVec3::new(9, 3, 6)), Some(1));
+        assert_eq!(ball.get(&interner, IVec3::new(9, 8, 6)), Some(3));
+
+        terrain.clear(&mut interner);
+        ball.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_set_solid_fill_fill_op() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_csg_shared_subtrees() {
+        let max_depth = MaxDepth::new(3);
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
-        tree.fill(&mut interner, TEST_VALUE);
+        let mut a = build_csg_tree(&mut interner, max_depth, |p| (p.x + p.y + p.z) % 3);
+        let mut b = VoxTree::new(max_depth);
+        b.set_root_id(&mut interner, a.get_root_id());
+
+        // same root on both sides, nothing to combine
+        let root_id = a.get_root_id();
+        let ref_count = interner.get_ref(&root_id);
+        assert!(!a.union(&mut interner, &b));
+        assert!(!a.intersect(&mut interner, &b));
+        assert_eq!(a.get_root_id(), root_id);
+        assert_eq!(interner.get_ref(&root_id), ref_count);
+
+        let empty = VoxTree::new(max_depth);
+        assert!(!a.union(&mut interner, &empty));
+        assert!(!a.difference(&mut interner, &empty));
+
+        // removing a tree from itself drops every node
+        assert!(a.difference(&mut interner, &b));
+        assert!(a.is_empty());
+
+        b.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "same max depth")]
+    fn test_csg_rejects_different_depths() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let mut a = VoxTree::new(MaxDepth::new(3));
+        let b = VoxTree::new(MaxDepth::new(4));
+        a.union(&mut interner, &b);
+    }
+
+    #[test]
+    fn test_fill_region_matches_nested_loops() {
+        let max_depth = MaxDepth::new(4);
+        let size = 1 << max_depth.max();
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let noise = |p: IVec3| i32::from((p.x * 7 + p.y * 3 + p.z * 5) % 4 == 0) * 2;
+
+        let boxes = [
+            (IVec3::new(1, 2, 3), IVec3::new(12, 9, 14), 1),
+            // corners swapped and partly outside of the tree
+            (IVec3::new(20, 7, 5), IVec3::new(-4, 0, 8), 3),
+            // a single voxel, a clear and a box covering whole octants
+            (IVec3::splat(5), IVec3::splat(5), 4),
+            (IVec3::new(0, 4, 0), IVec3::new(15, 11, 6), 0),
+            (IVec3::ZERO, IVec3::splat(7), 5),
+        ];
+
+        let mut filled = build_csg_tree(&mut interner, max_depth, noise);
+        let mut expected = build_csg_tree(&mut interner, max_depth, noise);
+
+        for (min, max, value) in boxes {
+            assert!(filled.fill_region(&mut interner, min, max, value));
+
+            let (lo, hi) = (min.min(max), min.max(max));
+            for y in lo.y.max(0)..=hi.y.min(size - 1) {
+                for z in lo.z.max(0)..=hi.z.min(size - 1) {
+                    for x in lo.x.max(0)..=hi.x.min(size - 1) {
+                        expected.set(&mut interner, IVec3::new(x, y, z), value);
+                    }
+                }
+            }
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+            for y in 0..size {
+                for z in 0..size {
+                    for x in 0..size {
+                        let position = IVec3::new(x, y, z);
+                        assert_eq!(
+                            filled.get(&interner, position),
+                            expected.get(&interner, position),
+                            "{position} after filling {min}..={max}"
+                        );
+                    }
                 }
             }
         }
 
-        assert!(!tree.is_empty());
-        assert!(tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        // filling the same box again or outside of the tree changes nothing
+        assert!(!filled.fill_region(&mut interner, IVec3::ZERO, IVec3::splat(7), 5));
+        assert!(!filled.fill_region(&mut interner, IVec3::splat(16), IVec3::splat(20), 5));
+
+        filled.clear(&mut interner);
+        expected.clear(&mut interner);
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_batch_solid_fill_fill_op() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_fill_region_collapses_full_box() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(6));
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        // a full box only touches the root
+        assert!(tree.fill_region(&mut interner, IVec3::ZERO, IVec3::splat(63), 9));
+        assert!(tree.get_root_id().is_leaf());
+        assert_eq!(interner.live_node_count(), 2);
 
-        let mut batch = tree.create_batch();
+        // an inset box only descends along its surface
+        tree.clear(&mut interner);
+        assert!(tree.fill_region(&mut interner, IVec3::ONE, IVec3::splat(62), 9));
+        assert_eq!(tree.voxel_count(&interner), 62 * 62 * 62);
+        assert!(interner.live_node_count() < 64 * 64);
 
-        batch.fill(&mut interner, TEST_VALUE);
+        tree.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
 
-        assert!(tree.apply_batch(&mut interner, &batch));
+    #[test]
+    fn test_extract_region_copy_paste() {
+        let max_depth = MaxDepth::new(5);
+        let size = 1 << max_depth.max();
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // a two colored sphere next to an octant that is left behind
+        let center = IVec3::splat(8);
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| {
+            if (p - center).length_squared() <= 25 {
+                1 + i32::from(p.y > 8)
+            } else if p.cmpge(IVec3::splat(16)).all() && p.cmplt(IVec3::splat(24)).all() {
+                3
+            } else {
+                0
+            }
+        });
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
+        let region_min = IVec3::splat(3);
+        let region_max = IVec3::splat(13);
+        // corners in any order
+        let clipboard = tree.extract_region(&interner, region_max, region_min);
+
+        let offset = IVec3::new(14, 2, 10);
+        let mut pasted = VoxTree::new(max_depth);
+        pasted.apply_batch(&mut interner, &clipboard.translated(offset));
+
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
                     let position = IVec3::new(x, y, z);
-                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    let source = position - offset;
+
+                    let expected =
+                        if source.cmpge(region_min).all() && source.cmple(region_max).all() {
+                            tree.get(&interner, source)
+                        } else {
+                            None
+                        };
+                    assert_eq!(pasted.get(&interner, position), expected, "{position}");
                 }
             }
         }
 
-        assert!(!tree.is_empty());
-        assert!(tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
-    }
+        // a region larger than the tree is clamped, a full copy includes the collapsed box
+        let mut copy = VoxTree::new(max_depth);
+        let everything = tree.extract_region(&interner, IVec3::splat(-10), IVec3::splat(100));
+        copy.apply_batch(&mut interner, &everything);
+        assert_eq!(
+            to_vec(&interner, &copy.get_root_id(), max_depth),
+            to_vec(&interner, &tree.get_root_id(), max_depth)
+        );
 
-    #[test]
-    fn test_patterns_set_sparse_fill() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+        let outside = tree.extract_region(&interner, IVec3::splat(size), IVec3::splat(size + 4));
+        assert!(!outside.has_patches());
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        tree.clear(&mut interner);
+        pasted.clear(&mut interner);
+        copy.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
 
-        for y in (0..voxels_per_axis).step_by(4) {
-            for z in (0..voxels_per_axis).step_by(4) {
-                for x in (0..voxels_per_axis).step_by(4) {
+    #[test]
+    fn test_map_values_collapses_checkerboard() {
+        let max_depth = MaxDepth::new(4);
+        let size = 1 << max_depth.max();
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // a checkerboard of 1 and 2 with a corner of 3 and the last voxel left empty
+        let last = IVec3::splat(size - 1);
+        let voxel = |p: IVec3| {
+            if p == last {
+                0
+            } else if p.cmplt(IVec3::splat(2)).all() {
+                3
+            } else {
+                1 + (p.x + p.y + p.z) % 2
+            }
+        };
+        let mut tree = build_csg_tree(&mut interner, max_depth, voxel);
+        let voxel_count = tree.voxel_count(&interner);
+
+        // only the corner changes
+        assert!(tree.map_values(&mut interner, |v| if v == 3 { 7 } else { v }));
+        assert_eq!(tree.get(&interner, IVec3::ZERO), Some(7));
+        assert_eq!(tree.get(&interner, IVec3::new(2, 0, 0)), Some(1));
+        assert_eq!(tree.get(&interner, IVec3::new(3, 0, 0)), Some(2));
+        assert_eq!(tree.voxel_count(&interner), voxel_count);
+
+        assert!(!tree.map_values(&mut interner, |v| v));
+
+        // the whole checkerboard becomes one value, every octant but the one holding the
+        // empty voxel collapses into a leaf
+        assert!(tree.map_values(&mut interner, |_| 5));
+        assert_eq!(tree.voxel_count(&interner), voxel_count);
+
+        let root_id = tree.get_root_id();
+        assert!(root_id.is_branch());
+        let children = interner.get_children(&root_id);
+        assert!(children[..7].iter().all(|child| child.is_leaf()));
+        assert!(children[7].is_branch());
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
                     let position = IVec3::new(x, y, z);
-                    assert!(tree.set(&mut interner, position, TEST_VALUE));
-                    assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+                    let expected = (position != last).then_some(5);
+                    assert_eq!(tree.get(&interner, position), expected);
                 }
             }
         }
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
+        // mapping to the default removes the voxels
+        let mut board = build_csg_tree(&mut interner, max_depth, voxel);
+        assert!(board.map_values(&mut interner, |v| if v == 2 { 0 } else { v }));
+        assert_eq!(board.get(&interner, IVec3::new(3, 0, 0)), None);
+        assert_eq!(board.get(&interner, IVec3::new(2, 0, 0)), Some(1));
+        assert!(board.map_values(&mut interner, |_| 0));
+        assert!(board.is_empty());
 
-                    if x % 4 == 0 && y % 4 == 0 && z % 4 == 0 {
-                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
-                    } else {
-                        assert_eq!(tree.get(&interner, position), None);
-                    }
-                }
-            }
-        }
+        tree.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+    #[test]
+    fn test_voxel_count_hollow_cube() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let mut tree = VoxTree::new(max_depth);
+        assert_eq!(tree.voxel_count(&interner), 0);
+
+        tree.fill(&mut interner, 1);
+        assert_eq!(tree.voxel_count(&interner), 8 * 8 * 8);
+        tree.clear(&mut interner);
+
+        // an 8^3 shell around a 6^3 hole
+        let mut shell = build_csg_tree(&mut interner, max_depth, |p| {
+            i32::from(p.min_element() == 0 || p.max_element() == 7)
+        });
+        assert_eq!(shell.voxel_count(&interner), 8 * 8 * 8 - 6 * 6 * 6);
+
+        shell.set(&mut interner, IVec3::splat(3), 2);
+        assert_eq!(shell.voxel_count(&interner), 8 * 8 * 8 - 6 * 6 * 6 + 1);
+
+        shell.clear(&mut interner);
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_batch_sparse_fill() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_dilate_and_erode() {
+        let max_depth = MaxDepth::new(3);
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
-        let mut batch = tree.create_batch();
+        let sorted_voxels = |tree: &VoxTree<i32>, interner: &VoxInterner<i32>| {
+            let mut voxels = tree.iter_voxels(interner).collect::<Vec<_>>();
+            voxels.sort_by_key(|(p, _)| (p.x, p.y, p.z));
+            voxels
+        };
 
-        for y in (0..voxels_per_axis).step_by(4) {
-            for z in (0..voxels_per_axis).step_by(4) {
-                for x in (0..voxels_per_axis).step_by(4) {
-                    let position = IVec3::new(x, y, z);
-                    assert!(batch.set(&mut interner, position, TEST_VALUE));
-                }
-            }
-        }
+        // a single voxel grows into a plus of 7, then an octahedron of 25
+        let mut tree = VoxTree::new(max_depth);
+        tree.set(&mut interner, IVec3::splat(4), 3);
+        assert!(tree.dilate(&mut interner, 1));
+
+        let mut plus = FACE_OFFSETS
+            .iter()
+            .map(|offset| (IVec3::splat(4) + *offset, 3))
+            .chain([(IVec3::splat(4), 3)])
+            .collect::<Vec<_>>();
+        plus.sort_by_key(|(p, _)| (p.x, p.y, p.z));
+        assert_eq!(sorted_voxels(&tree, &interner), plus);
+
+        // eroding the plus leaves its center
+        assert!(tree.erode(&mut interner, 1));
+        assert_eq!(sorted_voxels(&tree, &interner), vec![(IVec3::splat(4), 3)]);
+
+        assert!(tree.dilate(&mut interner, 2));
+        assert_eq!(tree.voxel_count(&interner), 25);
+        assert!(
+            tree.iter_voxels(&interner)
+                .all(|(p, _)| (p - IVec3::splat(4)).abs().element_sum() <= 2)
+        );
 
-        assert!(tree.apply_batch(&mut interner, &batch));
+        // nothing grows past the bounds
+        tree.clear(&mut interner);
+        tree.set(&mut interner, IVec3::ZERO, 1);
+        tree.dilate(&mut interner, 1);
+        assert_eq!(tree.voxel_count(&interner), 4);
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
+        // new voxels take the value of their first set neighbor along +X, -X, +Y, ...
+        tree.clear(&mut interner);
+        tree.set(&mut interner, IVec3::new(3, 4, 4), 1);
+        tree.set(&mut interner, IVec3::new(5, 4, 4), 2);
+        tree.dilate(&mut interner, 1);
+        assert_eq!(tree.get(&interner, IVec3::splat(4)), Some(2));
+        assert_eq!(tree.get(&interner, IVec3::new(2, 4, 4)), Some(1));
 
-                    if x % 4 == 0 && y % 4 == 0 && z % 4 == 0 {
-                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
-                    } else {
-                        assert_eq!(tree.get(&interner, position), None);
+        // the boundary of the tree erodes as well
+        tree.clear(&mut interner);
+        tree.fill_region(&mut interner, IVec3::ZERO, IVec3::splat(7), 1);
+        assert!(tree.erode(&mut interner, 1));
+        assert_eq!(tree.voxel_count(&interner), 6 * 6 * 6);
+        assert_eq!(
+            tree.occupied_bounds(&interner),
+            Some((IVec3::ONE, IVec3::splat(6)))
+        );
+
+        // an opening removes a speck but keeps the block next to it
+        tree.clear(&mut interner);
+        tree.fill_region(&mut interner, IVec3::ZERO, IVec3::splat(4), 1);
+        tree.set(&mut interner, IVec3::splat(7), 2);
+        tree.erode(&mut interner, 1);
+        tree.dilate(&mut interner, 1);
+        assert_eq!(tree.get(&interner, IVec3::splat(7)), None);
+        assert_eq!(tree.get(&interner, IVec3::splat(2)), Some(1));
+
+        // empty trees and zero iterations don't change anything
+        tree.clear(&mut interner);
+        assert!(!tree.dilate(&mut interner, 3));
+        assert!(!tree.erode(&mut interner, 3));
+        tree.set(&mut interner, IVec3::ONE, 1);
+        assert!(!tree.dilate(&mut interner, 0));
+
+        tree.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_downsample_by_majority() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // every coarse voxel by vote over its 2x2x2 block, ties to the lowest child index
+        let expected = |tree: &VoxTree<i32>, interner: &VoxInterner<i32>| {
+            let voxels = to_vec(interner, &tree.get_root_id(), max_depth);
+            let mut coarse = vec![0; 64];
+            for y in 0..4 {
+                for z in 0..4 {
+                    for x in 0..4 {
+                        let block = (0..8)
+                            .map(|i| {
+                                let p = IVec3::new(x, y, z) * 2 + decode_child_index_path(i);
+                                voxels[(p.y * 64 + p.z * 8 + p.x) as usize]
+                            })
+                            .collect::<Vec<_>>();
+                        let count = |value: i32| block.iter().filter(|&&v| v == value).count();
+                        if count(0) < 4 {
+                            let best = block.iter().map(|&v| count(v) * (v != 0) as usize).max();
+                            let value = *block
+                                .iter()
+                                .find(|&&v| v != 0 && Some(count(v)) == best)
+                                .unwrap();
+                            coarse[(y * 16 + z * 4 + x) as usize] = value;
+                        }
                     }
                 }
             }
+            coarse
+        };
+
+        let trees = [
+            // a solid region stays solid
+            build_csg_tree(&mut interner, max_depth, |p| i32::from(p.max_element() < 6)),
+            // a thin diagonal only fills two voxels of every block, so it vanishes
+            build_csg_tree(&mut interner, max_depth, |p| {
+                i32::from(p.x == p.y && p.y == p.z)
+            }),
+            // thick enough diagonal slabs keep their majority value
+            build_csg_tree(&mut interner, max_depth, |p| match (p.x + p.y + p.z) % 4 {
+                0 => 0,
+                1 => 1,
+                _ => 2,
+            }),
+            VoxTree::new(max_depth),
+        ];
+
+        for mut tree in trees {
+            let mut downsampled = tree.downsample(&mut interner);
+            assert_eq!(downsampled.max_depth(Lod::new(0)).max(), 2);
+            assert_eq!(
+                to_vec(&interner, &downsampled.get_root_id(), MaxDepth::new(2)),
+                expected(&tree, &interner)
+            );
+
+            downsampled.clear(&mut interner);
+            tree.clear(&mut interner);
         }
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        let mut solid = build_csg_tree(&mut interner, max_depth, |p| i32::from(p.x < 4));
+        let mut downsampled = solid.downsample(&mut interner);
+        assert_eq!(downsampled.voxel_count(&interner), 32);
+        assert!(downsampled.get(&interner, IVec3::new(1, 3, 3)).is_some());
+        assert_eq!(downsampled.get(&interner, IVec3::new(2, 0, 0)), None);
+
+        let mut diagonal = build_csg_tree(&mut interner, max_depth, |p| {
+            i32::from(p.x == p.y && p.y == p.z)
+        });
+        let mut diagonal_downsampled = diagonal.downsample(&mut interner);
+        assert!(diagonal_downsampled.is_empty());
+
+        let mut empty = VoxTree::<i32>::new(max_depth);
+        assert!(empty.downsample(&mut interner).is_empty());
+
+        for tree in [
+            &mut solid,
+            &mut downsampled,
+            &mut diagonal,
+            &mut diagonal_downsampled,
+            &mut empty,
+        ] {
+            tree.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_set_gradient_fill() {
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_try_set_and_try_get_reject_out_of_bounds() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(MaxDepth::new(3));
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let negative = IVec3::new(2, -1, 0);
+        let error = tree.try_set(&mut interner, negative, 1).unwrap_err();
+        assert_eq!(error.position, negative);
+        assert_eq!(error.voxels_per_axis, 8);
+
+        let over = IVec3::new(0, 3, 8);
+        assert_eq!(
+            tree.try_set(&mut interner, over, 1),
+            Err(OutOfBounds {
+                position: over,
+                voxels_per_axis: 8,
+            })
+        );
+        assert_eq!(tree.try_get(&interner, over).unwrap_err().position, over);
+        assert!(tree.get_root_id().is_empty());
 
-        for x in 0..voxels_per_axis {
-            let value = (x % 256) as u8;
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(tree.set(&mut interner, position, value), value > 0);
-                    assert_eq!(
-                        tree.get(&interner, position),
-                        if value > 0 { Some(value) } else { None }
-                    );
-                }
+        let corner = IVec3::splat(7);
+        assert_eq!(tree.try_set(&mut interner, corner, 1), Ok(true));
+        assert_eq!(tree.try_set(&mut interner, corner, 1), Ok(false));
+        assert_eq!(tree.try_get(&interner, corner), Ok(Some(1)));
+        assert_eq!(tree.try_get(&interner, IVec3::ZERO), Ok(None));
+
+        tree.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_node_at() {
+        let max_depth = MaxDepth::new(3);
+        let depth = |current| TraversalDepth::new(current, max_depth.max());
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // the lower half in x is filled, the upper half holds a single voxel
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| i32::from(p.x < 4));
+        tree.set(&mut interner, IVec3::new(5, 6, 1), 2);
+
+        let (root, is_leaf) = tree.node_at(&interner, IVec3::ZERO, depth(0)).unwrap();
+        assert_eq!(root, tree.get_root_id());
+        assert!(!is_leaf);
+
+        // the filled octants are one shared leaf, also returned for deeper requests
+        let (filled, is_leaf) = tree.node_at(&interner, IVec3::ZERO, depth(1)).unwrap();
+        assert!(is_leaf);
+        for current in 1..=3 {
+            for position in [IVec3::new(0, 7, 0), IVec3::new(3, 0, 7)] {
+                assert_eq!(
+                    tree.node_at(&interner, position, depth(current)),
+                    Some((filled, true))
+                );
             }
         }
 
-        for x in 0..voxels_per_axis {
-            let value = (x % 256) as u8;
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(
-                        tree.get(&interner, position),
-                        if value > 0 { Some(value) } else { None }
-                    );
-                }
-            }
+        // the path down to the single voxel is made of branches
+        let position = IVec3::new(5, 6, 1);
+        for current in 1..3 {
+            let (_, is_leaf) = tree.node_at(&interner, position, depth(current)).unwrap();
+            assert!(!is_leaf);
         }
+        let (voxel, is_leaf) = tree.node_at(&interner, position, depth(3)).unwrap();
+        assert!(is_leaf);
+        assert_eq!(*interner.get_value(&voxel), 2);
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        assert_eq!(tree.node_at(&interner, IVec3::new(7, 0, 0), depth(1)), None);
+        assert_eq!(tree.node_at(&interner, IVec3::new(4, 6, 1), depth(3)), None);
+
+        tree.clear(&mut interner);
+        assert_eq!(tree.node_at(&interner, IVec3::ZERO, depth(0)), None);
+
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_batch_gradient_fill() {
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_occupied_bounds() {
+        let max_depth = MaxDepth::new(4);
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
-        let mut batch = tree.create_batch();
+        let mut tree = VoxTree::new(max_depth);
+        assert_eq!(tree.occupied_bounds(&interner), None);
 
-        for x in 0..voxels_per_axis {
-            let value = (x % 256) as u8;
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert!(batch.set(&mut interner, position, value));
-                }
-            }
-        }
+        let position = IVec3::new(3, 11, 6);
+        tree.set(&mut interner, position, 1);
+        assert_eq!(tree.occupied_bounds(&interner), Some((position, position)));
 
-        assert!(tree.apply_batch(&mut interner, &batch));
+        // a collapsed 4^3 octant counts for all of its voxels
+        tree.fill_region(&mut interner, IVec3::new(8, 4, 0), IVec3::new(11, 7, 3), 2);
+        assert_eq!(
+            tree.occupied_bounds(&interner),
+            Some((IVec3::new(3, 4, 0), IVec3::new(11, 11, 6)))
+        );
 
-        for x in 0..voxels_per_axis {
-            let value = (x % 256) as u8;
-            for y in 0..voxels_per_axis {
-                for z in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(
-                        tree.get(&interner, position),
-                        if value > 0 { Some(value) } else { None }
-                    );
-                }
-            }
-        }
+        tree.clear(&mut interner);
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        // a 16^3 shell around a 14^3 hole
+        let mut shell = build_csg_tree(&mut interner, max_depth, |p| {
+            i32::from(p.min_element() == 0 || p.max_element() == 15)
+        });
+        assert_eq!(
+            shell.occupied_bounds(&interner),
+            Some((IVec3::ZERO, IVec3::splat(15)))
+        );
+
+        shell.fill(&mut interner, 1);
+        assert_eq!(
+            shell.occupied_bounds(&interner),
+            Some((IVec3::ZERO, IVec3::splat(15)))
+        );
+
+        shell.clear(&mut interner);
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_set_hollow_cube() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
-
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+    fn test_connected_components() {
+        let max_depth = MaxDepth::new(4);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| {
+            let block = p.cmpge(IVec3::ONE).all() && p.cmple(IVec3::splat(4)).all();
+            // crosses octant borders, so its voxels are not visited in a row
+            let bar = p.y == 14 && p.z == 0;
+            i32::from(block || bar)
+        });
+        // other values still connect, diagonal neighbours don't
+        tree.set(&mut interner, IVec3::new(5, 1, 1), 2);
+        tree.set(&mut interner, IVec3::new(6, 2, 2), 1);
+        tree.set(&mut interner, IVec3::new(10, 10, 10), 1);
+        tree.set(&mut interner, IVec3::new(10, 11, 10), 3);
+        tree.set(&mut interner, IVec3::splat(15), 1);
+
+        let components = tree.connected_components(&interner);
+
+        let mut labels = components.iter().map(|c| c.label).collect::<Vec<_>>();
+        labels.sort_unstable();
+        assert_eq!(labels, (0..5).collect::<Vec<_>>());
+
+        let mut found = components
+            .iter()
+            .map(|c| (c.voxel_count, c.min, c.max))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(count, min, _)| (*count, min.to_array()));
+        assert_eq!(
+            found,
+            vec![
+                (1, IVec3::new(6, 2, 2), IVec3::new(6, 2, 2)),
+                (1, IVec3::splat(15), IVec3::splat(15)),
+                (2, IVec3::splat(10), IVec3::new(10, 11, 10)),
+                (16, IVec3::new(0, 14, 0), IVec3::new(15, 14, 0)),
+                (65, IVec3::ONE, IVec3::new(5, 4, 4)),
+            ]
+        );
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let is_edge = x == 0
-                        || x == voxels_per_axis - 1
-                        || y == 0
-                        || y == voxels_per_axis - 1
-                        || z == 0
-                        || z == voxels_per_axis - 1;
+        assert_eq!(tree.remove_components_smaller_than(&mut interner, 3), 3);
+        assert_eq!(tree.voxel_count(&interner), 65 + 16);
+        assert_eq!(tree.get(&interner, IVec3::new(6, 2, 2)), None);
+        assert_eq!(tree.get(&interner, IVec3::new(10, 11, 10)), None);
+        assert_eq!(tree.get(&interner, IVec3::new(5, 1, 1)), Some(2));
+        assert_eq!(tree.connected_components(&interner).len(), 2);
 
-                    let position = IVec3::new(x, y, z);
-                    if is_edge {
-                        assert!(tree.set(&mut interner, position, TEST_VALUE));
-                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
-                    } else {
-                        assert_eq!(tree.get(&interner, position), None);
-                    }
-                }
-            }
-        }
+        assert_eq!(tree.remove_components_smaller_than(&mut interner, 3), 0);
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let is_edge = x == 0
-                        || x == voxels_per_axis - 1
-                        || y == 0
-                        || y == voxels_per_axis - 1
-                        || z == 0
-                        || z == voxels_per_axis - 1;
+        tree.clear(&mut interner);
+        assert!(tree.connected_components(&interner).is_empty());
+        assert!(interner.patterns_empty());
+    }
 
+    #[test]
+    fn test_content_eq() {
+        let max_depth = MaxDepth::new(4);
+        let size = 1 << max_depth.max();
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let mut stamped = VoxTree::new(max_depth);
+        let mut batch = Batch::new(max_depth);
+        batch.stamp_sphere(IVec3::splat(8), 6, 3);
+        stamped.apply_batch(&mut interner, &batch);
+
+        // the same voxels set one by one, in the opposite order
+        let mut set = VoxTree::new(max_depth);
+        for y in (0..size).rev() {
+            for z in (0..size).rev() {
+                for x in (0..size).rev() {
                     let position = IVec3::new(x, y, z);
-                    if is_edge {
-                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
-                    } else {
-                        assert_eq!(tree.get(&interner, position), None);
+                    if let Some(value) = stamped.get(&interner, position) {
+                        set.set(&mut interner, position, value);
                     }
                 }
             }
         }
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        // deduplicated down to the root
+        assert_eq!(stamped.get_root_id(), set.get_root_id());
+        assert!(stamped.content_eq(&set, &interner));
+
+        set.set(&mut interner, IVec3::splat(8), 4);
+        assert_ne!(stamped.get_root_id(), set.get_root_id());
+        assert!(!stamped.content_eq(&set, &interner));
+        assert!(!set.content_eq(&stamped, &interner));
+
+        // no voxels, but a different depth
+        let empty = VoxTree::new(max_depth);
+        let shallow = VoxTree::new(MaxDepth::new(3));
+        assert!(empty.content_eq(&VoxTree::new(max_depth), &interner));
+        assert!(!empty.content_eq(&shallow, &interner));
+        assert!(!empty.content_eq(&stamped, &interner));
+
+        stamped.clear(&mut interner);
+        set.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_batch_hollow_cube() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
-
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+    fn test_from_dense_matches_set() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
 
-        let mut batch = tree.create_batch();
+        for depth in 0..=4 {
+            let max_depth = MaxDepth::new(depth);
+            let size = 1 << depth;
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let is_edge = x == 0
-                        || x == voxels_per_axis - 1
-                        || y == 0
-                        || y == voxels_per_axis - 1
-                        || z == 0
-                        || z == voxels_per_axis - 1;
+            // a solid octant that collapses, a sparse pattern and empty space
+            let voxel = |p: IVec3| {
+                if p.cmplt(IVec3::splat(size / 2)).all() && depth > 1 {
+                    7
+                } else {
+                    i32::from((p.x + 2 * p.y + 3 * p.z) % 5 == 0) * (1 + p.x % 3)
+                }
+            };
 
-                    let position = IVec3::new(x, y, z);
-                    if is_edge {
-                        assert!(batch.set(&mut interner, position, TEST_VALUE));
+            let mut data = Vec::with_capacity((size * size * size) as usize);
+            let mut set = VoxTree::new(max_depth);
+            for y in 0..size {
+                for z in 0..size {
+                    for x in 0..size {
+                        let position = IVec3::new(x, y, z);
+                        data.push(voxel(position));
+                        set.set(&mut interner, position, voxel(position));
                     }
                 }
             }
+
+            let mut dense = VoxTree::from_dense(&mut interner, &data, max_depth);
+            assert!(dense.is_dirty());
+            assert!(dense.content_eq(&set, &interner), "depth {depth}");
+            assert_eq!(to_vec(&interner, &dense.get_root_id(), max_depth), data);
+
+            dense.clear(&mut interner);
+            set.clear(&mut interner);
         }
 
-        assert!(tree.apply_batch(&mut interner, &batch));
+        // a uniform grid is a single leaf
+        let max_depth = MaxDepth::new(3);
+        let mut solid = VoxTree::from_dense(&mut interner, &[4; 512], max_depth);
+        assert!(solid.get_root_id().is_leaf());
+        solid.clear(&mut interner);
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let is_edge = x == 0
-                        || x == voxels_per_axis - 1
-                        || y == 0
-                        || y == voxels_per_axis - 1
-                        || z == 0
-                        || z == voxels_per_axis - 1;
+        let empty = VoxTree::from_dense(&mut interner, &[0; 512], max_depth);
+        assert!(empty.get_root_id().is_empty());
 
-                    let position = IVec3::new(x, y, z);
-                    if is_edge {
-                        assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
-                    } else {
-                        assert_eq!(tree.get(&interner, position), None);
-                    }
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_get_many_matches_get() {
+        let max_depth = MaxDepth::new(4);
+        let size = 1 << max_depth.max();
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // a collapsed solid octant, a sparse pattern and empty space
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| {
+            if p.cmplt(IVec3::splat(8)).all() {
+                7
+            } else {
+                i32::from((p.x + 2 * p.y + 3 * p.z) % 5 == 0) * (1 + p.x % 3)
+            }
+        });
+
+        let mut rng = rand::rng();
+        let mut positions = (0..500)
+            .map(|_| {
+                IVec3::new(
+                    rng.random_range(0..size),
+                    rng.random_range(0..size),
+                    rng.random_range(0..size),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // a neighborhood across the octant borders, and repeated positions
+        for y in 7..10 {
+            for z in 7..10 {
+                for x in 7..10 {
+                    positions.push(IVec3::new(x, y, z));
                 }
             }
         }
+        positions.push(positions[3]);
+        positions.push(IVec3::ZERO);
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        let mut out = vec![None; positions.len()];
+        tree.get_many(&interner, &positions, &mut out);
+
+        for (position, value) in positions.iter().zip(out) {
+            assert_eq!(
+                value,
+                tree.get(&interner, *position),
+                "Mismatch at {position}"
+            );
+        }
+
+        tree.clear(&mut interner);
+
+        let mut out = vec![Some(1); positions.len()];
+        tree.get_many(&interner, &positions, &mut out);
+        assert!(out.iter().all(Option::is_none));
+
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_set_diagonal() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_transform_rotates_l_shape() {
+        const SIZE: i32 = 8;
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let max_depth = MaxDepth::new(3);
 
-        for i in 0..voxels_per_axis {
-            let position = IVec3::new(i, i, i);
-            assert!(tree.set(&mut interner, position, TEST_VALUE));
-            assert_eq!(tree.get(&interner, position), Some(TEST_VALUE));
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // asymmetric L, long leg along X, short leg along Z, one voxel thick in Y
+        let l_shape = |p: IVec3| {
+            let on_leg = (p.z == 1 && (1..7).contains(&p.x)) || (p.x == 1 && (1..4).contains(&p.z));
+            if p.y == 2 && on_leg { p.x + p.z + 1 } else { 0 }
+        };
+
+        let mut tree = VoxTree::new(max_depth);
+        for y in 0..SIZE {
+            for z in 0..SIZE {
+                for x in 0..SIZE {
+                    let position = IVec3::new(x, y, z);
+                    if l_shape(position) != 0 {
+                        tree.set(&mut interner, position, l_shape(position));
+                    }
+                }
+            }
         }
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
+        // 90° around Y takes X to -Z and Z to X
+        let transform = OctantTransform::from(Orientation::from_quarter_turns(0, 1, 0));
+        let mut rotated = tree.transform(&mut interner, transform);
+
+        for y in 0..SIZE {
+            for z in 0..SIZE {
+                for x in 0..SIZE {
                     let position = IVec3::new(x, y, z);
+                    let reference = l_shape(IVec3::new(SIZE - 1 - z, y, x));
+
                     assert_eq!(
-                        tree.get(&interner, position),
-                        if x == y && x == z {
-                            Some(TEST_VALUE)
-                        } else {
-                            None
-                        }
+                        rotated.get(&interner, position).unwrap_or_default(),
+                        reference,
+                        "Mismatch at {position:?}"
                     );
                 }
             }
         }
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        // the source tree is left unchanged
+        assert_eq!(tree.get(&interner, IVec3::new(6, 2, 1)), Some(8));
+
+        rotated.clear(&mut interner);
+        tree.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_batch_diagonal() {
-        const TEST_VALUE: u8 = 3;
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_transform_shares_subtrees() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // the same octant repeated in every child of the root
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| {
+            i32::from(p.x % 4 == 0 && p.y % 4 < 2) * (1 + p.z % 4)
+        });
+        let distinct_nodes = |interner: &VoxInterner<i32>, root_id: BlockId| {
+            let mut nodes = Vec::new();
+            collect_nodes(interner, root_id, &mut FxHashMap::default(), &mut nodes);
+            nodes.len()
+        };
+        let nodes = distinct_nodes(&interner, tree.get_root_id());
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
+        for transform in OctantTransform::all() {
+            let mut transformed = tree.transform(&mut interner, transform);
+            let root_id = transformed.get_root_id();
 
-        let mut batch = tree.create_batch();
+            // the shared octant is rewritten once and stays shared by every child
+            let children = interner.get_children(&root_id);
+            assert!(children.iter().all(|child| *child == children[0]));
+            assert_eq!(distinct_nodes(&interner, root_id), nodes);
 
-        for i in 0..voxels_per_axis {
-            let position = IVec3::new(i, i, i);
-            assert!(batch.set(&mut interner, position, TEST_VALUE));
+            // undoing the transform interns back to the very same nodes
+            let mut restored = transformed.transform(&mut interner, transform.inverse());
+            assert_eq!(restored.get_root_id(), tree.get_root_id());
+
+            restored.clear(&mut interner);
+            transformed.clear(&mut interner);
         }
 
-        assert!(tree.apply_batch(&mut interner, &batch));
+        let mut identity = tree.transform(&mut interner, OctantTransform::IDENTITY);
+        assert_eq!(identity.get_root_id(), tree.get_root_id());
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(
-                        tree.get(&interner, position),
-                        if x == y && x == z {
-                            Some(TEST_VALUE)
-                        } else {
-                            None
-                        }
-                    );
-                }
-            }
-        }
+        identity.clear(&mut interner);
+        tree.clear(&mut interner);
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        assert!(interner.patterns_empty());
     }
 
     #[test]
-    fn test_patterns_set_random_noise() {
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+    fn test_raycast_axis_aligned() {
+        let max_depth = MaxDepth::new(4);
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(max_depth);
 
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
-        let size = 1 << (3 * MAX_DEPTH.max());
-        let mut data = vec![0; size as usize];
+        assert_eq!(tree.raycast(&interner, Vec3::ZERO, Vec3::X, 100.0), None);
 
-        let mut rng = rand::rng();
+        tree.set(&mut interner, IVec3::new(12, 3, 9), 5);
 
-        for _ in 0..1000 {
-            let x = rng.random_range(0..voxels_per_axis);
-            let y = rng.random_range(0..voxels_per_axis);
-            let z = rng.random_range(0..voxels_per_axis);
-            let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
-            let value = rng.random_range(1..=255) as u8;
-            data[index as usize] = value;
-            let position = IVec3::new(x, y, z);
+        // parallel to two axis planes, so only x is ever divided by
+        let origin = Vec3::new(-2.0, 3.5, 9.5);
+        let hit = tree.raycast(&interner, origin, Vec3::X, 100.0).unwrap();
+        assert_eq!(hit.position, IVec3::new(12, 3, 9));
+        assert_eq!(hit.value, 5);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.t - 14.0).abs() < 1e-5);
 
-            assert!(tree.set(&mut interner, position, value));
-            assert_eq!(tree.get(&interner, position), Some(value));
-        }
+        assert_eq!(tree.raycast(&interner, origin, Vec3::X, 13.0), None);
+        assert_eq!(tree.raycast(&interner, origin, Vec3::NEG_X, 100.0), None);
+        assert_eq!(tree.raycast(&interner, origin, Vec3::ZERO, 100.0), None);
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
-                    let expected_value = data[index as usize];
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(
-                        tree.get(&interner, position),
-                        if expected_value != 0 {
-                            Some(expected_value)
-                        } else {
-                            None
-                        }
-                    );
+        // starting inside the voxel
+        let inside = Vec3::new(12.25, 3.5, 9.75);
+        let hit = tree.raycast(&interner, inside, Vec3::Y, 100.0).unwrap();
+        assert_eq!(hit.position, IVec3::new(12, 3, 9));
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.t, 0.0);
+
+        // solid lower half, collapsed into leaves covering whole octants
+        tree.fill(&mut interner, 0);
+        let mut batch = tree.create_batch();
+        for y in 0..8 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    batch.set(&mut interner, IVec3::new(x, y, z), 1);
                 }
             }
         }
+        tree.apply_batch(&mut interner, &batch);
+        assert!(
+            interner
+                .get_children_ref(&tree.get_root_id())
+                .iter()
+                .all(|id| id.is_empty() || id.is_leaf())
+        );
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        let hit = tree
+            .raycast(&interner, Vec3::new(5.5, 20.2, 3.3), Vec3::NEG_Y, 100.0)
+            .unwrap();
+        assert_eq!(hit.position, IVec3::new(5, 7, 3));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!((hit.t - 12.2).abs() < 1e-4);
+
+        tree.clear(&mut interner);
     }
 
     #[test]
-    fn test_patterns_batch_random_noise() {
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
-
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
+    fn test_raycast_matches_marching() {
+        let max_depth = MaxDepth::new(4);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| {
+            let ball = (p - IVec3::new(10, 6, 9)).length_squared() < 10;
+            let pillar = p.x == 3 && p.z == 12;
+            (ball as i32) * 2 + pillar as i32
+        });
+
+        // fine fixed steps, with origins and directions away from voxel edges
+        let march = |origin: Vec3, dir: Vec3, max_dist: f32| {
+            let dir = dir.normalize();
+            (0..(max_dist / 1e-3) as i32).find_map(|i| {
+                let point = origin + dir * (i as f32 * 1e-3);
+                let cell = point.floor().as_ivec3();
+                if cell.cmplt(IVec3::ZERO).any() || cell.cmpge(IVec3::splat(16)).any() {
+                    return None;
+                }
+                tree.get(&interner, cell)
+                    .map(|value| (cell, value, i as f32 * 1e-3))
+            })
+        };
 
-        let voxels_per_axis = tree.voxels_per_axis(Lod::new(0)) as i32;
-        let size = 1 << (3 * MAX_DEPTH.max());
-        let mut data = vec![0; size as usize];
+        let rays = [
+            (Vec3::new(-3.1, 7.3, -2.7), Vec3::new(1.0, -0.1, 0.9)),
+            (Vec3::new(20.3, 20.1, 20.4), Vec3::new(-1.0, -1.1, -0.9)),
+            (Vec3::new(3.4, 15.6, 12.3), Vec3::new(0.01, -1.0, 0.02)),
+            (Vec3::new(0.3, 1.2, 0.7), Vec3::new(0.7, 0.3, 0.9)),
+            (Vec3::new(15.7, 0.4, 0.6), Vec3::new(-0.8, 0.45, 0.75)),
+            (Vec3::new(9.6, 30.2, 8.7), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(-5.3, 2.2, 14.9), Vec3::new(1.0, 0.0, -0.3)),
+        ];
 
-        let mut batch = tree.create_batch();
+        let mut hits = 0;
+        for (origin, dir) in rays {
+            let expected = march(origin, dir, 60.0);
+            let hit = tree.raycast(&interner, origin, dir, 60.0);
 
-        let mut rng = rand::rng();
+            assert_eq!(
+                hit.map(|hit| (hit.position, hit.value)),
+                expected.map(|(position, value, _)| (position, value)),
+                "ray {origin:?} {dir:?}"
+            );
 
-        for _ in 0..1000 {
-            let x = rng.random_range(0..voxels_per_axis);
-            let y = rng.random_range(0..voxels_per_axis);
-            let z = rng.random_range(0..voxels_per_axis);
-            let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
-            let value = rng.random_range(1..=255) as u8;
-            data[index as usize] = value;
-            let position = IVec3::new(x, y, z);
+            if let (Some(hit), Some((_, _, t))) = (hit, expected) {
+                assert!((hit.t - t).abs() < 2e-3, "ray {origin:?} {dir:?}");
 
-            assert!(batch.set(&mut interner, position, value));
+                // the normal points back towards the ray
+                assert!(hit.normal.as_vec3().dot(dir) <= 0.0);
+                hits += 1;
+            }
         }
+        assert!(hits >= 4);
 
-        assert!(tree.apply_batch(&mut interner, &batch));
+        tree.clear(&mut interner);
+    }
 
-        for y in 0..voxels_per_axis {
-            for z in 0..voxels_per_axis {
-                for x in 0..voxels_per_axis {
-                    let index = z * voxels_per_axis * voxels_per_axis + y * voxels_per_axis + x;
-                    let expected_value = data[index as usize];
-                    let position = IVec3::new(x, y, z);
-                    assert_eq!(
-                        tree.get(&interner, position),
-                        if expected_value != 0 {
-                            Some(expected_value)
-                        } else {
-                            None
-                        }
-                    );
+    #[test]
+    fn test_iter_voxels_matches_to_vec() {
+        let max_depth = MaxDepth::new(4);
+        let size = 1 << max_depth.max();
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // a collapsed 8x8x8 octant next to scattered single voxels
+        let mut tree = build_csg_tree(&mut interner, max_depth, |p| {
+            if p.cmplt(IVec3::splat(8)).all() {
+                4
+            } else {
+                ((p.x * 7 + p.y * 3 + p.z) % 11 == 0) as i32 * (1 + p.y)
+            }
+        });
+        assert!(interner.get_child_id(&tree.get_root_id(), 0).is_leaf());
+
+        let data = crate::utils::common::to_vec(&interner, &tree.get_root_id(), max_depth);
+        let mut expected = Vec::new();
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let value = data[((y * size + z) * size + x) as usize];
+                    if value != 0 {
+                        expected.push((IVec3::new(x, y, z), value));
+                    }
                 }
             }
         }
 
-        assert!(!tree.is_empty());
-        assert!(!tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
-    }
+        let mut voxels = tree.iter_voxels(&interner).collect::<Vec<_>>();
+        assert_eq!(voxels.len(), expected.len());
 
-    #[test]
-    fn test_max_depth_zero() {
-        const MAX_DEPTH: MaxDepth = MaxDepth::new(0);
-        const MEMORY_BUDGET: usize = 1024 * 1024;
+        // depth-first, the collapsed first octant comes out first
+        assert!(voxels[..512].iter().all(|(_, value)| *value == 4));
 
-        let mut interner = VoxInterner::with_memory_budget(MEMORY_BUDGET);
-        let mut tree = VoxTree::new(MAX_DEPTH);
+        voxels.sort_by_key(|(position, _)| (position.y, position.z, position.x));
+        assert_eq!(voxels, expected);
 
-        let position = IVec3::new(0, 0, 0);
-        assert!(tree.set(&mut interner, position, 1));
-        assert_eq!(tree.get(&interner, position), Some(1));
+        tree.clear(&mut interner);
+        assert_eq!(tree.iter_voxels(&interner).count(), 0);
 
-        assert!(!tree.is_empty());
-        assert!(tree.is_leaf());
-        assert_eq!(interner.get_ref(&tree.get_root_id()), 1);
+        // a root leaf covers the whole tree
+        tree.fill(&mut interner, 9);
+        assert_eq!(
+            tree.iter_voxels(&interner).count(),
+            (size * size * size) as usize
+        );
+
+        tree.clear(&mut interner);
     }
 }
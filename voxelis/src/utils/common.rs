@@ -1,7 +1,33 @@
-use glam::IVec3;
+use glam::{I64Vec3, IVec3};
+use rustc_hash::FxHashMap;
 
 use crate::{BlockId, MaxDepth, TraversalDepth, VoxInterner, VoxelTrait};
 
+/// Returns the index of the child octant selected by the `x`, `y` and `z` bits of a position.
+///
+/// This is the child order of every branch, at every depth. The bit of each axis sets one
+/// bit of the index, `x` bit 0, `y` bit 1 and `z` bit 2, so a bit value of `1` picks the
+/// upper half along that axis. At depth `d` of a tree with `max_depth` levels the bits are
+/// taken at `max_depth - d - 1`, see [`child_index`]. Code that walks the DAG outside of the
+/// crate, e.g. GPU shaders, has to use the same mapping.
+///
+/// # Examples
+///
+/// ```rust
+/// use voxelis::utils::common::octant_index;
+///
+/// assert_eq!(octant_index(0, 0, 0), 0);
+/// assert_eq!(octant_index(1, 0, 0), 1);
+/// assert_eq!(octant_index(0, 1, 0), 2);
+/// assert_eq!(octant_index(0, 0, 1), 4);
+/// assert_eq!(octant_index(1, 1, 1), 7);
+/// ```
+#[must_use]
+#[inline(always)]
+pub const fn octant_index(x_bit: usize, y_bit: usize, z_bit: usize) -> usize {
+    (x_bit & 1) | ((y_bit & 1) << 1) | ((z_bit & 1) << 2)
+}
+
 #[inline(always)]
 pub const fn child_index(position: &IVec3, depth: &TraversalDepth) -> usize {
     let shift = depth.max() - depth.current() - 1;
@@ -54,6 +80,139 @@ pub const fn encode_child_index_path(position: &IVec3) -> u32 {
     x | (y << 1) | (z << 2)
 }
 
+#[inline(always)]
+pub const fn decode_child_index_path(path: u32) -> IVec3 {
+    const MASK_10_BITS: u32 = 0x000003FF; // Mask for lower 10 bits
+    const MASK_1: u32 = 0x30000FF;
+    const MASK_2: u32 = 0x300F00F;
+    const MASK_3: u32 = 0x30C30C3;
+    const MASK_4: u32 = 0x9249249;
+
+    // Compact every third bit back together, inverse of `encode_child_index_path`
+    const fn compact(v: u32) -> u32 {
+        let v = v & MASK_4;
+        let v = (v | (v >> 2)) & MASK_3;
+        let v = (v | (v >> 4)) & MASK_2;
+        let v = (v | (v >> 8)) & MASK_1;
+        (v | (v >> 16)) & MASK_10_BITS
+    }
+
+    IVec3::new(
+        compact(path) as i32,
+        compact(path >> 1) as i32,
+        compact(path >> 2) as i32,
+    )
+}
+
+/// Returns the child index at depth `current` of a tree with `max` levels, taken from the
+/// Morton code of a position, see [`morton_encode_many`].
+#[inline(always)]
+pub const fn morton_child_index(code: u32, current: usize, max: usize) -> usize {
+    ((code >> (3 * (max - current - 1))) & 7) as usize
+}
+
+/// Writes the Morton code of every position in `positions` to `out`, the code
+/// [`encode_child_index_path`] gives for the lowest `depth.max()` bits of each coordinate.
+///
+/// The code holds the child index of every level, root first, so a traversal encodes a
+/// position once and takes each index with [`morton_child_index`] instead of shifting the
+/// three coordinates at every depth. With the `simd` feature the codes are computed eight
+/// at a time, with `morton_encode_many_simd`, otherwise by
+/// [`morton_encode_many_scalar`]. Both give the same codes.
+///
+/// # Panics
+///
+/// Panics if `positions` and `out` have different lengths.
+#[inline]
+pub fn morton_encode_many(positions: &[IVec3], depth: MaxDepth, out: &mut [u32]) {
+    #[cfg(feature = "simd")]
+    morton_encode_many_simd(positions, depth, out);
+
+    #[cfg(not(feature = "simd"))]
+    morton_encode_many_scalar(positions, depth, out);
+}
+
+/// Scalar path of [`morton_encode_many`], one position at a time.
+pub fn morton_encode_many_scalar(positions: &[IVec3], depth: MaxDepth, out: &mut [u32]) {
+    assert_eq!(
+        positions.len(),
+        out.len(),
+        "Output must have a slot for every position"
+    );
+
+    let mask = (1 << depth.max()) - 1;
+
+    for (position, out) in positions.iter().zip(out.iter_mut()) {
+        *out = encode_child_index_path(&(*position & IVec3::splat(mask)));
+    }
+}
+
+/// Vectorized path of [`morton_encode_many`], spreading the bits of eight positions at once
+/// with the same masks as [`encode_child_index_path`]. The positions left over after the last
+/// group of eight take the scalar path.
+#[cfg(feature = "simd")]
+pub fn morton_encode_many_simd(positions: &[IVec3], depth: MaxDepth, out: &mut [u32]) {
+    use wide::u32x8;
+
+    assert_eq!(
+        positions.len(),
+        out.len(),
+        "Output must have a slot for every position"
+    );
+
+    #[inline(always)]
+    fn spread(v: u32x8) -> u32x8 {
+        let v = (v | (v << 16u32)) & u32x8::splat(0x30000FF);
+        let v = (v | (v << 8u32)) & u32x8::splat(0x300F00F);
+        let v = (v | (v << 4u32)) & u32x8::splat(0x30C30C3);
+        (v | (v << 2u32)) & u32x8::splat(0x9249249)
+    }
+
+    let mask = u32x8::splat((1 << depth.max()) - 1);
+
+    let mut position_groups = positions.chunks_exact(8);
+    let mut out_groups = out.chunks_exact_mut(8);
+
+    for (group, out) in (&mut position_groups).zip(&mut out_groups) {
+        let axis = |axis: usize| u32x8::new(std::array::from_fn(|i| group[i][axis] as u32)) & mask;
+
+        let code = spread(axis(0)) | (spread(axis(1)) << 1u32) | (spread(axis(2)) << 2u32);
+        out.copy_from_slice(&code.to_array());
+    }
+
+    morton_encode_many_scalar(
+        position_groups.remainder(),
+        depth,
+        out_groups.into_remainder(),
+    );
+}
+
+// Chunk keys stay `IVec3`, but world-space voxel positions can be `voxels_per_axis` times
+// larger, so conversions between the two are done in `i64` to avoid overflow
+
+/// Splits a world-space voxel position into a chunk position and a position local to that chunk.
+#[inline(always)]
+pub fn world_voxel_to_chunk(world_voxel: I64Vec3, voxels_per_axis: u32) -> (IVec3, IVec3) {
+    let voxels_per_axis = I64Vec3::splat(voxels_per_axis as i64);
+
+    let chunk_position = world_voxel.div_euclid(voxels_per_axis);
+    let local_position = world_voxel.rem_euclid(voxels_per_axis);
+
+    assert!(
+        chunk_position.cmpge(I64Vec3::splat(i32::MIN as i64)).all()
+            && chunk_position.cmple(I64Vec3::splat(i32::MAX as i64)).all(),
+        "Chunk position {chunk_position} out of range"
+    );
+
+    (chunk_position.as_ivec3(), local_position.as_ivec3())
+}
+
+/// Returns the world-space voxel position of a chunk's origin.
+#[inline(always)]
+pub fn chunk_to_world_voxel(chunk_position: IVec3, voxels_per_axis: u32) -> I64Vec3 {
+    chunk_position.as_i64vec3() * voxels_per_axis as i64
+}
+
 #[macro_export]
 macro_rules! encode_child_index_path_macro {
     ($position:expr) => {{
@@ -211,6 +370,289 @@ pub fn to_vec<T: VoxelTrait>(
     data
 }
 
+/// Counts occupied voxels without visiting them one by one - a leaf covers a whole
+/// cube, and shared branches are counted once per depth they appear at.
+pub fn count_occupied_voxels<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+) -> u64 {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("count_occupied_voxels");
+
+    let mut cache = FxHashMap::default();
+
+    count_occupied_voxels_rec(interner, *root_id, 0, max_depth.max() as u32, &mut cache)
+}
+
+fn count_occupied_voxels_rec<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    depth: u32,
+    max_depth: u32,
+    cache: &mut FxHashMap<(BlockId, u32), u64>,
+) -> u64 {
+    if node_id.is_empty() {
+        return 0;
+    }
+
+    if !node_id.is_branch() || depth >= max_depth {
+        if interner.get_value(&node_id).voxel_eq(&T::default()) {
+            return 0;
+        }
+
+        let cube_side = 1u64 << (max_depth - depth);
+        return cube_side * cube_side * cube_side;
+    }
+
+    if let Some(count) = cache.get(&(node_id, depth)) {
+        return *count;
+    }
+
+    let count = interner
+        .get_children_ref(&node_id)
+        .iter()
+        .map(|child_id| count_occupied_voxels_rec(interner, *child_id, depth + 1, max_depth, cache))
+        .sum();
+
+    cache.insert((node_id, depth), count);
+
+    count
+}
+
+/// Adds the number of voxels at `max_depth` holding each set value below `node_id`, found at
+/// `depth`, to `counts`. A leaf above max depth counts for the whole cube it covers.
+pub fn count_voxel_values<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    depth: u32,
+    max_depth: u32,
+    counts: &mut FxHashMap<T, u64>,
+) {
+    if node_id.is_empty() {
+        return;
+    }
+
+    if !node_id.is_branch() || depth >= max_depth {
+        let value = *interner.get_value(&node_id);
+        if !value.voxel_eq(&T::default()) {
+            let cube_side = 1u64 << (max_depth - depth);
+            *counts.entry(value).or_default() += cube_side * cube_side * cube_side;
+        }
+        return;
+    }
+
+    for child_id in interner.get_children_ref(&node_id) {
+        count_voxel_values(interner, *child_id, depth + 1, max_depth, counts);
+    }
+}
+
+/// Checks whether the volume has no occupied voxel when sampled at `max_depth`. Coarse levels
+/// store the majority value of their children, so sparse detail can vanish at a low depth.
+pub fn is_empty_at_depth<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+) -> bool {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("is_empty_at_depth");
+
+    is_empty_at_depth_rec(interner, *root_id, 0, max_depth.max() as u32)
+}
+
+fn is_empty_at_depth_rec<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    depth: u32,
+    max_depth: u32,
+) -> bool {
+    if node_id.is_empty() {
+        return true;
+    }
+
+    if !node_id.is_branch() || depth >= max_depth {
+        return interner.get_value(&node_id).voxel_eq(&T::default());
+    }
+
+    interner
+        .get_children_ref(&node_id)
+        .iter()
+        .all(|child_id| is_empty_at_depth_rec(interner, *child_id, depth + 1, max_depth))
+}
+
+/// Counts voxel faces that touch an empty voxel or the edge of the volume, which is what
+/// the naive mesher emits one quad for. Works on the DAG directly: every occupied voxel
+/// has six faces, minus two for each pair of occupied neighbours, and pairs across child
+/// boundaries are found by walking only the touching halves of both children.
+pub fn count_exposed_faces<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+) -> u64 {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("count_exposed_faces");
+
+    count_exposed_faces_per_axis(interner, root_id, max_depth)
+        .iter()
+        .sum()
+}
+
+/// Same as `count_exposed_faces`, split by the axis the faces are perpendicular to
+/// (0 = X, 1 = Y, 2 = Z), e.g. to weigh them by the face area of non-cubic voxels.
+pub fn count_exposed_faces_per_axis<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+) -> [u64; 3] {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("count_exposed_faces_per_axis");
+
+    let max_depth = max_depth.max() as u32;
+
+    let mut occupied_cache = FxHashMap::default();
+    let occupied = count_occupied_voxels_rec(interner, *root_id, 0, max_depth, &mut occupied_cache);
+
+    let pairs = FaceCounter::new(interner, max_depth).inner_pairs(*root_id, 0);
+
+    pairs.map(|pairs| 2 * occupied - 2 * pairs)
+}
+
+/// Counts pairs of occupied voxels facing each other across the boundary of two neighbouring
+/// volumes of the same depth, with `upper` placed after `lower` along `axis` (0 = X, 1 = Y, 2 = Z).
+/// Each pair hides one face on both sides of the seam.
+pub fn count_touching_faces<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    lower_root_id: &BlockId,
+    upper_root_id: &BlockId,
+    axis: usize,
+    max_depth: MaxDepth,
+) -> u64 {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("count_touching_faces");
+
+    assert!(axis < 3, "Axis must be 0, 1 or 2");
+
+    FaceCounter::new(interner, max_depth.max() as u32).touching_pairs(
+        *lower_root_id,
+        *upper_root_id,
+        axis,
+        0,
+    )
+}
+
+struct FaceCounter<'a, T: VoxelTrait> {
+    interner: &'a VoxInterner<T>,
+    max_depth: u32,
+    inner_cache: FxHashMap<(BlockId, u32), [u64; 3]>,
+    touching_cache: FxHashMap<(BlockId, BlockId, usize, u32), u64>,
+}
+
+impl<'a, T: VoxelTrait> FaceCounter<'a, T> {
+    fn new(interner: &'a VoxInterner<T>, max_depth: u32) -> Self {
+        Self {
+            interner,
+            max_depth,
+            inner_cache: FxHashMap::default(),
+            touching_cache: FxHashMap::default(),
+        }
+    }
+
+    #[inline(always)]
+    fn is_solid(&self, node_id: BlockId, depth: u32) -> Option<bool> {
+        if node_id.is_empty() {
+            return Some(false);
+        }
+
+        if !node_id.is_branch() || depth >= self.max_depth {
+            return Some(!self.interner.get_value(&node_id).voxel_eq(&T::default()));
+        }
+
+        None
+    }
+
+    #[inline(always)]
+    fn child(&self, node_id: BlockId, depth: u32, index: usize) -> BlockId {
+        // a uniform node is its own child
+        match self.is_solid(node_id, depth) {
+            Some(_) => node_id,
+            None => self.interner.get_children_ref(&node_id)[index],
+        }
+    }
+
+    // Number of pairs of occupied voxels sharing a face inside the node, by axis.
+    fn inner_pairs(&mut self, node_id: BlockId, depth: u32) -> [u64; 3] {
+        if let Some(solid) = self.is_solid(node_id, depth) {
+            if !solid {
+                return [0; 3];
+            }
+
+            let side = 1u64 << (self.max_depth - depth);
+            return [side * side * (side - 1); 3];
+        }
+
+        if let Some(pairs) = self.inner_cache.get(&(node_id, depth)) {
+            return *pairs;
+        }
+
+        let childs = *self.interner.get_children_ref(&node_id);
+
+        let mut pairs = [0; 3];
+        for (index, child_id) in childs.iter().enumerate() {
+            let child_pairs = self.inner_pairs(*child_id, depth + 1);
+
+            for axis in 0..3 {
+                pairs[axis] += child_pairs[axis];
+
+                let bit = 1 << axis;
+                if index & bit == 0 {
+                    pairs[axis] +=
+                        self.touching_pairs(*child_id, childs[index | bit], axis, depth + 1);
+                }
+            }
+        }
+
+        self.inner_cache.insert((node_id, depth), pairs);
+
+        pairs
+    }
+
+    // Number of pairs of occupied voxels across the `+axis` side of `lower` and
+    // the `-axis` side of `upper`, two equally sized neighbouring nodes.
+    fn touching_pairs(&mut self, lower: BlockId, upper: BlockId, axis: usize, depth: u32) -> u64 {
+        let lower_solid = self.is_solid(lower, depth);
+        let upper_solid = self.is_solid(upper, depth);
+
+        if lower_solid == Some(false) || upper_solid == Some(false) {
+            return 0;
+        }
+
+        if lower_solid.is_some() && upper_solid.is_some() {
+            let side = 1u64 << (self.max_depth - depth);
+            return side * side;
+        }
+
+        let key = (lower, upper, axis, depth);
+        if let Some(pairs) = self.touching_cache.get(&key) {
+            return *pairs;
+        }
+
+        let bit = 1 << axis;
+        let mut pairs = 0;
+        for index in (0..8).filter(|index| index & bit == 0) {
+            pairs += self.touching_pairs(
+                self.child(lower, depth, index | bit),
+                self.child(upper, depth, index),
+                axis,
+                depth + 1,
+            );
+        }
+
+        self.touching_cache.insert(key, pairs);
+
+        pairs
+    }
+}
+
 #[inline(always)]
 fn fill_sub_volume<T: VoxelTrait>(
     data: &mut [T],
@@ -245,6 +687,35 @@ fn fill_sub_volume<T: VoxelTrait>(
     }
 }
 
+/// Appends the nodes reachable from `node_id` in post-order and returns its 1-based
+/// index in the table, shared nodes are collected once.
+pub fn collect_nodes<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    node_ids: &mut FxHashMap<BlockId, u32>,
+    nodes: &mut Vec<BlockId>,
+) -> u32 {
+    if node_id.is_empty() {
+        return 0;
+    }
+
+    if let Some(index) = node_ids.get(&node_id) {
+        return *index;
+    }
+
+    if node_id.is_branch() {
+        for child_id in interner.get_children_ref(&node_id).iter() {
+            collect_nodes(interner, *child_id, node_ids, nodes);
+        }
+    }
+
+    nodes.push(node_id);
+    let index = nodes.len() as u32;
+    node_ids.insert(node_id, index);
+
+    index
+}
+
 pub fn dump_structure<T: VoxelTrait>(
     interner: &VoxInterner<T>,
     root_id: BlockId,
@@ -331,3 +802,141 @@ fn collect_stats<T: VoxelTrait>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_octant_index_matches_traversal() {
+        for max_depth in 1..6u8 {
+            for depth in 0..max_depth {
+                let shift = max_depth - depth - 1;
+
+                for index in 0..8 {
+                    let (x_bit, y_bit, z_bit) = (index & 1, (index >> 1) & 1, (index >> 2) & 1);
+                    let position = IVec3::new(
+                        (x_bit << shift) as i32,
+                        (y_bit << shift) as i32,
+                        (z_bit << shift) as i32,
+                    );
+
+                    let traversal_depth = TraversalDepth::new(depth, max_depth);
+                    assert_eq!(octant_index(x_bit, y_bit, z_bit), index);
+                    assert_eq!(child_index(&position, &traversal_depth), index);
+                    assert_eq!(crate::child_index_macro!(position, traversal_depth), index);
+                    assert_eq!(
+                        crate::child_index_macro_2!(position, depth as usize, max_depth as usize),
+                        index
+                    );
+                    assert_eq!(
+                        child_index2(&position, depth as usize, max_depth as usize),
+                        index
+                    );
+                }
+            }
+        }
+    }
+
+    fn assert_morton_matches_traversal(encode: fn(&[IVec3], MaxDepth, &mut [u32])) {
+        use rand::Rng;
+
+        let mut rng = rand::rng();
+
+        for max_depth in 0..=6u8 {
+            let size = 1 << max_depth;
+            // not a multiple of eight, so some positions are left over after the groups
+            let positions = (0..1003)
+                .map(|_| {
+                    IVec3::new(
+                        rng.random_range(0..size),
+                        rng.random_range(0..size),
+                        rng.random_range(0..size),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let mut codes = vec![u32::MAX; positions.len()];
+            encode(&positions, MaxDepth::new(max_depth), &mut codes);
+
+            for (position, code) in positions.iter().zip(&codes) {
+                assert_eq!(*code, encode_child_index_path(position));
+
+                for depth in 0..max_depth as usize {
+                    assert_eq!(
+                        morton_child_index(*code, depth, max_depth as usize),
+                        crate::child_index_macro_2!(position, depth, max_depth as usize),
+                        "{position} at depth {depth} of {max_depth}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_morton_encode_many_matches_child_index() {
+        assert_morton_matches_traversal(morton_encode_many_scalar);
+        assert_morton_matches_traversal(morton_encode_many);
+
+        // bits above the depth are dropped
+        let mut codes = [0; 1];
+        morton_encode_many(&[IVec3::new(9, 10, 11)], MaxDepth::new(3), &mut codes);
+        assert_eq!(codes[0], encode_child_index_path(&IVec3::new(1, 2, 3)));
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_morton_encode_many_simd_matches_child_index() {
+        assert_morton_matches_traversal(morton_encode_many_simd);
+    }
+
+    #[test]
+    fn test_decode_child_index_path() {
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let position = IVec3::new(x, y, z);
+                    let path = encode_child_index_path(&position);
+                    assert_eq!(decode_child_index_path(path), position);
+                }
+            }
+        }
+
+        let position = IVec3::new(1023, 511, 7);
+        assert_eq!(
+            decode_child_index_path(encode_child_index_path(&position)),
+            position
+        );
+    }
+
+    #[test]
+    fn test_world_voxel_to_chunk() {
+        assert_eq!(
+            world_voxel_to_chunk(I64Vec3::new(17, -1, 0), 16),
+            (IVec3::new(1, -1, 0), IVec3::new(1, 15, 0))
+        );
+
+        // past i32::MAX in voxels, but the chunk still fits in i32
+        let world_voxel = I64Vec3::new(i32::MAX as i64 + 5, i32::MIN as i64 - 1, 0);
+        let (chunk_position, local_position) = world_voxel_to_chunk(world_voxel, 16);
+        assert_eq!(chunk_position, IVec3::new(134_217_728, -134_217_729, 0));
+        assert_eq!(local_position, IVec3::new(4, 15, 0));
+        assert_eq!(
+            chunk_to_world_voxel(chunk_position, 16) + local_position.as_i64vec3(),
+            world_voxel
+        );
+    }
+
+    #[test]
+    fn test_chunk_to_world_voxel_extremes() {
+        let world_voxel = chunk_to_world_voxel(IVec3::new(i32::MAX, i32::MIN, 0), 64);
+        assert_eq!(
+            world_voxel,
+            I64Vec3::new(i32::MAX as i64 * 64, i32::MIN as i64 * 64, 0)
+        );
+        assert_eq!(
+            world_voxel_to_chunk(world_voxel + I64Vec3::splat(63), 64),
+            (IVec3::new(i32::MAX, i32::MIN, 0), IVec3::new(63, 63, 63))
+        );
+    }
+}
@@ -1,14 +1,15 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 #[cfg(feature = "trace_greedy_timings")]
 use std::time::{Duration, Instant};
 
 use glam::{IVec3, UVec2, UVec3, Vec3};
+use rustc_hash::FxHashMap;
 
 use crate::{
     BlockId, Lod, MaxDepth, TraversalDepth, VoxInterner, VoxelTrait,
-    spatial::{VoxOpsChunkConfig, VoxOpsChunkLocalContainer, VoxOpsConfig, VoxOpsState},
-    utils::common::get_at_depth,
+    spatial::{VoxOpsChunkConfig, VoxOpsChunkLocalContainer, VoxOpsConfig, VoxOpsMesh},
+    utils::common::{get_at_depth, to_vec},
     world::VoxChunk,
 };
 
@@ -47,6 +48,9 @@ pub const NORMAL_XZ_NEG: usize = 2;
 pub const NORMAL_XY_POS: usize = 5;
 pub const NORMAL_XY_NEG: usize = 4;
 
+/// Light factor of a face corner by the number of voxels occluding it.
+pub const AO_CURVE: [f32; 4] = [1.0, 2.0 / 3.0, 1.0 / 3.0, 0.0];
+
 pub const MAX_VOXELS_PER_AXIS: usize = 64;
 pub const PLANE_SIZE: usize = MAX_VOXELS_PER_AXIS * MAX_VOXELS_PER_AXIS;
 pub const PLANE_SIZE_ALL_AXES: usize = PLANE_SIZE * 3;
@@ -148,8 +152,260 @@ pub struct MeshData {
     pub vertices: Vec<Vec3>,
     pub normals: Vec<Vec3>,
     pub indices: Vec<u32>,
+    /// Per-vertex colors, only filled by meshers that emit a color channel.
+    pub colors: Vec<[f32; 4]>,
+    /// Per-vertex ambient occlusion, from `1.0` for an unoccluded corner down to `0.0`,
+    /// only filled when the mesher is asked to compute it.
+    pub ao: Vec<f32>,
+    /// Convention every mesher writing into this mesh emits positions, normals and winding in.
+    pub coordinate_system: CoordinateSystem,
+    /// How `indices` are to be drawn.
+    pub topology: MeshTopology,
+}
+
+impl MeshData {
+    pub fn with_coordinate_system(coordinate_system: CoordinateSystem) -> Self {
+        Self {
+            coordinate_system,
+            ..Default::default()
+        }
+    }
+}
+
+/// Greedy meshes memoized by the root node and level of detail of the chunk they were
+/// generated for.
+///
+/// Identical subtrees share a [`BlockId`], so chunks with the same root reuse one mesh, as
+/// does a chunk whose root didn't change since it was last meshed. Meshes are generated at
+/// the origin, callers place them at the chunk offset. Node generations keep a reused slot
+/// from hitting the mesh of the node it replaced.
+///
+/// A cache is meant for chunks of a single size, since the mesh depends on the voxel size.
+/// Once full, the least recently used mesh is evicted.
+pub struct MeshCache {
+    meshes: FxHashMap<(BlockId, Lod), (Arc<MeshData>, u64)>,
+    max_entries: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl MeshCache {
+    /// Creates a cache holding at most `max_entries` meshes.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            meshes: FxHashMap::default(),
+            max_entries: max_entries.max(1),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the greedy mesh of `chunk` at `lod`, generating it on a cache miss.
+    pub fn get_or_generate<T: VoxelTrait>(
+        &mut self,
+        chunk: &VoxChunk<T>,
+        interner: &VoxInterner<T>,
+        lod: Lod,
+    ) -> Arc<MeshData> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("MeshCache::get_or_generate");
+
+        self.tick += 1;
+
+        let key = (chunk.get_root_id(), lod);
+
+        if let Some((mesh_data, last_used)) = self.meshes.get_mut(&key) {
+            self.hits += 1;
+            *last_used = self.tick;
+            return mesh_data.clone();
+        }
+
+        self.misses += 1;
+
+        if self.meshes.len() >= self.max_entries {
+            self.evict_least_recently_used();
+        }
+
+        let mut mesh_data = MeshData::default();
+        chunk.generate_greedy_mesh_arrays(interner, &mut mesh_data, Vec3::ZERO, lod);
+
+        let mesh_data = Arc::new(mesh_data);
+        self.meshes.insert(key, (mesh_data.clone(), self.tick));
+
+        mesh_data
+    }
+
+    /// Returns the number of lookups served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns the number of lookups that had to generate a mesh.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Returns the number of cached meshes.
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Returns `true` if no mesh is cached.
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+
+    /// Drops all cached meshes, keeping the hit and miss counters.
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .meshes
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(key, _)| *key);
+
+        if let Some(key) = oldest {
+            self.meshes.remove(&key);
+        }
+    }
+}
+
+/// Primitives the indices of a [`MeshData`] describe.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum MeshTopology {
+    /// Every three indices form a triangle, emitted by the meshers.
+    #[default]
+    TriangleList,
+    /// Every two indices form a line, emitted by the debug wireframes, without normals.
+    LineList,
+}
+
+/// Coordinate convention of the engine a mesh is generated for.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum CoordinateSystem {
+    /// Right-handed, `Y` up, counter-clockwise front faces (Bevy, OpenGL).
+    /// This is the space voxelis works in, so vertices are emitted unchanged.
+    #[default]
+    RightHandedYUp,
+    /// Left-handed, `Y` up, clockwise front faces (Unity, Direct3D).
+    /// The `Z` axis of positions and normals is mirrored and the winding is reversed,
+    /// so the model keeps its layout, normals point outward and faces stay front-facing.
+    LeftHandedYUp,
+}
+
+impl CoordinateSystem {
+    /// Converts a position or a direction from voxelis space into this convention.
+    #[inline(always)]
+    pub fn convert(&self, vector: Vec3) -> Vec3 {
+        match self {
+            Self::RightHandedYUp => vector,
+            Self::LeftHandedYUp => Vec3::new(vector.x, vector.y, -vector.z),
+        }
+    }
+
+    /// Returns the indices of a triangle wound counter-clockwise seen from its front in
+    /// voxelis space, in this convention's winding.
+    #[inline(always)]
+    pub fn triangle_indices(&self, [a, b, c]: [u32; 3]) -> [u32; 3] {
+        match self {
+            Self::RightHandedYUp => [a, b, c],
+            Self::LeftHandedYUp => [a, c, b],
+        }
+    }
+
+    /// Returns the two triangles of a quad starting at `index`, in this convention's winding.
+    #[inline(always)]
+    pub fn quad_indices(&self, index: u32) -> [u32; 6] {
+        match self {
+            Self::RightHandedYUp => [index + 2, index + 1, index, index + 3, index, index + 1],
+            // mirroring one axis flips the winding, so swap it back to keep faces front-facing
+            Self::LeftHandedYUp => [index, index + 1, index + 2, index + 1, index, index + 3],
+        }
+    }
 }
 
+/// Normals the meshers emit, see [`MeshData::smooth_normals`].
+///
+/// Only [`generate_greedy_mesh_arrays_stride`] takes a mode, it meshes a whole model into one
+/// mesh, so smoothing afterwards also blends normals across chunk borders. The other meshers
+/// see a single chunk at a time, call [`MeshData::smooth_normals`] on their merged output
+/// instead.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum NormalMode {
+    /// Every face keeps its axis aligned normal, for a blocky look.
+    #[default]
+    Flat,
+    /// Normals are averaged over the faces meeting at a vertex, e.g. for rounded terrain.
+    Smooth,
+}
+
+/// How the value aware greedy mesher merges neighboring faces into quads, see
+/// [`generate_greedy_mesh_arrays_dense`].
+///
+/// [`generate_greedy_mesh_arrays`] has no mode, it meshes occupancy bits that carry no
+/// values and so always merges like [`MergeMode::MergeAllSolid`].
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum MergeMode {
+    /// Quads only merge across voxels of the same value.
+    #[default]
+    PerValue,
+    /// Quads merge across all solid voxels, values only show in the vertex colors.
+    MergeAllSolid,
+}
+
+/// Axis the greedy mesher merges faces along first, see [`generate_greedy_mesh_arrays`].
+///
+/// Quads grow along the primary axis before extending across it, so the axis decides which
+/// strips a shape is cut into. Planes which don't contain the axis keep their default order.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum SweepAxis {
+    /// Merges along x, and along z on the YZ planes.
+    #[default]
+    X,
+    Y,
+    Z,
+}
+
+impl SweepAxis {
+    pub const ALL: [SweepAxis; 3] = [SweepAxis::X, SweepAxis::Y, SweepAxis::Z];
+
+    // Slices merge along their columns first, the axis is swept first on the planes where it
+    // runs along the rows instead
+    const fn transposes(&self, plane: Plane) -> bool {
+        matches!(
+            (self, plane),
+            (SweepAxis::Y, Plane::YZ) | (SweepAxis::Y, Plane::XY) | (SweepAxis::Z, Plane::XZ)
+        )
+    }
+}
+
+/// Debug overlay written into the color channel of a mesh.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum DebugTint {
+    #[default]
+    None,
+    /// Tint faces by the octree depth of the node that emitted them.
+    ByDepth,
+    /// Tint faces by the position of the chunk that emitted them.
+    ByChunk,
+}
+
+pub const DEBUG_TINT_PALETTE: [[f32; 4]; 8] = [
+    [0.90, 0.10, 0.10, 1.0],
+    [0.95, 0.55, 0.10, 1.0],
+    [0.95, 0.90, 0.15, 1.0],
+    [0.20, 0.80, 0.20, 1.0],
+    [0.10, 0.80, 0.85, 1.0],
+    [0.15, 0.35, 0.95, 1.0],
+    [0.60, 0.20, 0.90, 1.0],
+    [0.95, 0.35, 0.75, 1.0],
+];
+
 #[cfg(feature = "trace_greedy_timings")]
 #[derive(Default, Debug)]
 pub struct GreedyTimings {
@@ -193,6 +449,56 @@ pub struct OccupancyDataBuilder {
 }
 
 impl MeshData {
+    /// Replaces every normal with the normalized sum of the normals of all vertices at the
+    /// same position, so faces meeting at an edge or corner shade smoothly.
+    ///
+    /// Quads don't share vertices, so vertices are welded by their exact position first. Only
+    /// the normals change. A corner lying on the edge of a larger quad has no vertex there to
+    /// weld with, and normals that cancel out, like both sides of a thin wall, are kept.
+    pub fn smooth_normals(&mut self) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("MeshData::smooth_normals");
+
+        let key = |vertex: &Vec3| vertex.to_array().map(f32::to_bits);
+
+        let mut sums: FxHashMap<[u32; 3], Vec3> = FxHashMap::default();
+        for (vertex, normal) in self.vertices.iter().zip(&self.normals) {
+            *sums.entry(key(vertex)).or_default() += *normal;
+        }
+
+        for (vertex, normal) in self.vertices.iter().zip(self.normals.iter_mut()) {
+            *normal = sums[&key(vertex)].try_normalize().unwrap_or(*normal);
+        }
+    }
+
+    /// Returns the number of vertices.
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Returns the number of triangles, `0` for a [`MeshTopology::LineList`].
+    pub fn triangle_count(&self) -> usize {
+        match self.topology {
+            MeshTopology::TriangleList => self.indices.len() / 3,
+            MeshTopology::LineList => 0,
+        }
+    }
+
+    /// Returns the number of bytes the mesh takes once uploaded as is: positions, normals,
+    /// colors and ambient occlusion as 32-bit floats and `u32` indices.
+    pub fn estimated_gpu_bytes(&self) -> usize {
+        size_of_val(self.vertices.as_slice())
+            + size_of_val(self.normals.as_slice())
+            + size_of_val(self.indices.as_slice())
+            + size_of_val(self.colors.as_slice())
+            + size_of_val(self.ao.as_slice())
+    }
+
+    /// Returns `true` if there is nothing to draw.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
     pub fn clear(&mut self) {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("MeshData::clear");
@@ -200,14 +506,46 @@ impl MeshData {
         self.vertices.clear();
         self.normals.clear();
         self.indices.clear();
+        self.colors.clear();
+        self.ao.clear();
+    }
+}
+
+impl DebugTint {
+    /// Returns the color for a face emitted at `depth` in the chunk at `chunk_position`,
+    /// or `None` when tinting is disabled.
+    pub fn color(&self, depth: u32, chunk_position: IVec3) -> Option<[f32; 4]> {
+        match self {
+            DebugTint::None => None,
+            DebugTint::ByDepth => {
+                Some(DEBUG_TINT_PALETTE[depth as usize % DEBUG_TINT_PALETTE.len()])
+            }
+            DebugTint::ByChunk => {
+                // checkered pattern, so neighboring chunks never share a color
+                let parity = chunk_position
+                    .x
+                    .wrapping_add(chunk_position.y)
+                    .wrapping_add(chunk_position.z)
+                    & 1;
+                let hash = chunk_position
+                    .x
+                    .wrapping_mul(73_856_093)
+                    .wrapping_add(chunk_position.y.wrapping_mul(19_349_663))
+                    .wrapping_add(chunk_position.z.wrapping_mul(83_492_791));
+                let index = ((hash as u32 as usize >> 1) % 4) * 2 + parity as usize;
+                Some(DEBUG_TINT_PALETTE[index])
+            }
+        }
     }
 }
 
 struct SliceData {
     global_offset: Vec3,
-    voxel_size: f32,
+    voxel_size: Vec3,
     min_row: usize,
     max_row: usize,
+    min_col: usize,
+    max_col: usize,
     plane: Plane,
     dir: Dir,
 }
@@ -603,12 +941,22 @@ pub fn generate_occupancy_masks<T: VoxelTrait>(
 // -   512 ( 8x 8x 8) chunks of  8x 8x 8 voxels
 // -  4096 (16x16x16) chunks of  4x 4x 4 voxels
 // - 32768 (32x32x32) chunks of  2x 2x 2 voxels
+//
+// With `ao` set, every vertex also gets the ambient occlusion of its corner, sampled from
+// the voxels in front of the face, and faces only merge when all their corners match.
+// Voxels outside of the occupancy data don't occlude.
+//
+// Faces merge along `sweep_axis` first, which changes how many quads a shape takes, see
+// `generate_greedy_mesh_arrays_best_axis`.
+#[allow(clippy::too_many_arguments)]
 pub fn generate_greedy_mesh_arrays(
     occupancy_data: &OccupancyData,
     mesh_data: &mut MeshData,
     max_depth: MaxDepth,
     offset: Vec3,
-    voxel_size: f32,
+    voxel_size: Vec3,
+    ao: bool,
+    sweep_axis: SweepAxis,
     #[cfg(feature = "trace_greedy_timings")] timings: &mut GreedyTimings,
 ) {
     #[cfg(feature = "tracy")]
@@ -817,6 +1165,8 @@ pub fn generate_greedy_mesh_arrays(
                     voxel_size,
                     min_row: dir_data.active_row.min,
                     max_row: dir_data.active_row.max,
+                    min_col: dir_data.active_col.min,
+                    max_col: dir_data.active_col.max,
                     plane: plane_data.plane,
                     dir: dir_data.dir,
                 };
@@ -851,12 +1201,19 @@ pub fn generate_greedy_mesh_arrays(
 
                     let faces_total = current_faces_left - faces_left;
 
+                    let faces_ao = ao.then(|| {
+                        let plane_masks = &occupancy_data.global[plane_data.offset..];
+                        slice_ao(&plane_masks[..PLANE_SIZE], &slice_data, slice, &faces)
+                    });
+
                     generate_greedy_faces_for_slice(
                         mesh_data,
                         &slice_data,
                         slice as f32,
                         faces_total,
                         &faces,
+                        faces_ao.as_ref(),
+                        sweep_axis.transposes(plane_data.plane),
                     );
 
                     if faces_left == 0 {
@@ -873,6 +1230,147 @@ pub fn generate_greedy_mesh_arrays(
     }
 }
 
+/// Meshes the occupancy data once per `SweepAxis` and appends the mesh with the fewest quads
+/// to `mesh_data`, returning the axis it was generated with. Ties go to the axis listed first
+/// in `SweepAxis::ALL`. Costs about three plain meshing passes, so it is meant for meshes that
+/// are baked once, like exports.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_greedy_mesh_arrays_best_axis(
+    occupancy_data: &OccupancyData,
+    mesh_data: &mut MeshData,
+    max_depth: MaxDepth,
+    offset: Vec3,
+    voxel_size: Vec3,
+    ao: bool,
+    #[cfg(feature = "trace_greedy_timings")] timings: &mut GreedyTimings,
+) -> SweepAxis {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("generate_greedy_mesh_arrays_best_axis");
+
+    let mut best: Option<(SweepAxis, MeshData)> = None;
+
+    for sweep_axis in SweepAxis::ALL {
+        let mut candidate = MeshData::with_coordinate_system(mesh_data.coordinate_system);
+
+        generate_greedy_mesh_arrays(
+            occupancy_data,
+            &mut candidate,
+            max_depth,
+            offset,
+            voxel_size,
+            ao,
+            sweep_axis,
+            #[cfg(feature = "trace_greedy_timings")]
+            timings,
+        );
+
+        // every quad takes the same number of indices
+        if best
+            .as_ref()
+            .is_none_or(|(_, best)| candidate.indices.len() < best.indices.len())
+        {
+            best = Some((sweep_axis, candidate));
+        }
+    }
+
+    let (sweep_axis, best) = best.expect("SweepAxis::ALL is not empty");
+
+    let base_index = mesh_data.vertices.len() as u32;
+
+    mesh_data.vertices.extend(best.vertices);
+    mesh_data.normals.extend(best.normals);
+    mesh_data.colors.extend(best.colors);
+    mesh_data.ao.extend(best.ao);
+    mesh_data
+        .indices
+        .extend(best.indices.iter().map(|&index| base_index + index));
+
+    sweep_axis
+}
+
+// Occlusion of the four corners of every face in a slice, packed two bits per corner, see
+// `corner_occlusion`.
+type SliceAo = [[u8; MAX_VOXELS_PER_AXIS]; MAX_VOXELS_PER_AXIS];
+
+// Returns the corner occlusion of the faces of `slice`, sampled in the layer of voxels the
+// faces look into. `plane_masks` holds the global occupancy of the slice's plane.
+fn slice_ao(
+    plane_masks: &[u64],
+    slice_data: &SliceData,
+    slice: usize,
+    faces: &[u64; MAX_VOXELS_PER_AXIS],
+) -> SliceAo {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("slice_ao");
+
+    let mut faces_ao = [[0u8; MAX_VOXELS_PER_AXIS]; MAX_VOXELS_PER_AXIS];
+
+    let layer = match slice_data.dir {
+        Dir::Pos => slice + 1,
+        Dir::Neg => slice.wrapping_sub(1),
+    };
+
+    // faces on the border look out of the occupancy data, nothing occludes them
+    if layer >= MAX_VOXELS_PER_AXIS {
+        return faces_ao;
+    }
+
+    let occupied = |row: usize, col: usize| -> u8 {
+        if row >= MAX_VOXELS_PER_AXIS || col >= MAX_VOXELS_PER_AXIS {
+            0
+        } else {
+            ((plane_masks[row * MAX_VOXELS_PER_AXIS + col] >> layer) & 1) as u8
+        }
+    };
+
+    for (row, row_faces) in faces.iter().enumerate() {
+        let mut remaining = *row_faces;
+
+        while remaining != 0 {
+            let col = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+
+            let mut packed = 0u8;
+
+            for corner in 0..4 {
+                // neighbors below zero wrap around and read as empty
+                let corner_row = if corner & 2 != 0 {
+                    row + 1
+                } else {
+                    row.wrapping_sub(1)
+                };
+                let corner_col = if corner & 1 != 0 {
+                    col + 1
+                } else {
+                    col.wrapping_sub(1)
+                };
+
+                let side_row = occupied(corner_row, col);
+                let side_col = occupied(row, corner_col);
+                let diagonal = occupied(corner_row, corner_col);
+
+                packed |= corner_occlusion(side_row, side_col, diagonal) << (corner * 2);
+            }
+
+            faces_ao[row][col] = packed;
+        }
+    }
+
+    faces_ao
+}
+
+// Classic vertex occlusion: two occupied sides hide the corner no matter the diagonal.
+#[inline(always)]
+const fn corner_occlusion(side_row: u8, side_col: u8, diagonal: u8) -> u8 {
+    if side_row == 1 && side_col == 1 {
+        3
+    } else {
+        side_row + side_col + diagonal
+    }
+}
+
+// Merges the faces of a slice into quads, along the columns first unless `transposed`, in
+// which case rows and columns swap roles while merging.
 #[inline(never)]
 fn generate_greedy_faces_for_slice(
     mesh_data: &mut MeshData,
@@ -880,27 +1378,119 @@ fn generate_greedy_faces_for_slice(
     slice: f32,
     faces_total: usize,
     faces: &[u64; MAX_VOXELS_PER_AXIS],
+    faces_ao: Option<&SliceAo>,
+    transposed: bool,
 ) {
     #[cfg(feature = "tracy")]
     let _span = tracy_client::span!("generate_greedy_faces_for_slice");
 
+    if transposed {
+        let faces = transpose_faces(faces);
+        // the packed corners are only compared while merging, the quads get them as sampled
+        let faces_ao = faces_ao.map(|faces_ao| {
+            let mut transposed = [[0u8; MAX_VOXELS_PER_AXIS]; MAX_VOXELS_PER_AXIS];
+            for (row, row_ao) in faces_ao.iter().enumerate() {
+                for (col, &corners) in row_ao.iter().enumerate() {
+                    transposed[col][row] = corners;
+                }
+            }
+            transposed
+        });
+
+        merge_slice_faces(
+            slice_data.min_col..slice_data.max_col,
+            faces_total,
+            &faces,
+            faces_ao.as_ref(),
+            |start_col, start_row, width, height, corners| {
+                add_slice_quad(
+                    mesh_data, slice_data, slice, start_row, start_col, height, width, corners,
+                );
+            },
+        );
+    } else {
+        merge_slice_faces(
+            slice_data.min_row..slice_data.max_row,
+            faces_total,
+            faces,
+            faces_ao,
+            |start_col, start_row, width, height, corners| {
+                add_slice_quad(
+                    mesh_data, slice_data, slice, start_col, start_row, width, height, corners,
+                );
+            },
+        );
+    }
+}
+
+// Returns the faces with rows and columns swapped.
+fn transpose_faces(faces: &[u64; MAX_VOXELS_PER_AXIS]) -> [u64; MAX_VOXELS_PER_AXIS] {
+    let mut transposed = [const { 0u64 }; MAX_VOXELS_PER_AXIS];
+
+    for (row, &row_faces) in faces.iter().enumerate() {
+        let mut remaining = row_faces;
+
+        while remaining != 0 {
+            let col = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+
+            transposed[col] |= 1 << row;
+        }
+    }
+
+    transposed
+}
+
+// Greedily covers the faces with rectangles, growing each one along its row first and then
+// over the following rows. `add_quad` gets the start column and row, width, height and
+// shared corner occlusion of every rectangle.
+#[inline(always)]
+fn merge_slice_faces(
+    rows: std::ops::Range<usize>,
+    faces_total: usize,
+    faces: &[u64; MAX_VOXELS_PER_AXIS],
+    faces_ao: Option<&SliceAo>,
+    mut add_quad: impl FnMut(usize, usize, usize, usize, Option<u8>),
+) {
     let mut faces_left = faces_total;
 
     let mut used = [const { 0u64 }; MAX_VOXELS_PER_AXIS];
 
-    'main: for start_row in slice_data.min_row..slice_data.max_row {
+    let max_row = rows.end;
+
+    'main: for start_row in rows {
         let mut available = faces[start_row] & !used[start_row];
 
         while available != 0 {
             let start_col = available.trailing_zeros() as usize;
-            let width_mask = find_contiguous_bits(available, start_col);
+            let mut width_mask = find_contiguous_bits(available, start_col);
+
+            let corners = faces_ao.map(|faces_ao| faces_ao[start_row][start_col]);
+
+            // corners of a quad are shared by all its faces, stop where their occlusion changes
+            if let (Some(faces_ao), Some(corners)) = (faces_ao, corners) {
+                let width = (start_col..MAX_VOXELS_PER_AXIS)
+                    .take_while(|&col| {
+                        (width_mask >> col) & 1 != 0 && faces_ao[start_row][col] == corners
+                    })
+                    .count();
+                width_mask =
+                    find_contiguous_bits(width_mask & low_bits(start_col + width), start_col);
+            }
+
             let width = width_mask.count_ones() as usize;
 
             let mut height = 1;
 
-            for row in start_row + 1..slice_data.max_row {
+            for row in start_row + 1..max_row {
                 let row_mask = faces[row] & !used[row];
-                if (row_mask & width_mask) == width_mask {
+                let same_ao = faces_ao.is_none_or(|faces_ao| {
+                    faces_ao[row][start_col..start_col + width]
+                        .iter()
+                        .all(|&face_ao| Some(face_ao) == corners)
+                });
+
+                if (row_mask & width_mask) == width_mask && same_ao {
                     height += 1;
                     used[row] |= width_mask;
                 } else {
@@ -908,43 +1498,7 @@ fn generate_greedy_faces_for_slice(
                 }
             }
 
-            let ijk_scale = [
-                slice_data.voxel_size * width as f32,
-                slice_data.voxel_size * height as f32,
-                slice_data.voxel_size,
-            ];
-            let ijk_offset = [
-                slice_data.voxel_size * start_col as f32,
-                slice_data.voxel_size * start_row as f32,
-                slice_data.voxel_size * slice,
-            ];
-
-            let (v_ids, ijk_ids, normal_id) = match (slice_data.plane, slice_data.dir) {
-                (Plane::YZ, Dir::Pos) => (VERTS_YZ_POS, IJK_YZ, NORMAL_YZ_POS),
-                (Plane::YZ, Dir::Neg) => (VERTS_YZ_NEG, IJK_YZ, NORMAL_YZ_NEG),
-                (Plane::XZ, Dir::Pos) => (VERTS_XZ_POS, IJK_XZ, NORMAL_XZ_POS),
-                (Plane::XZ, Dir::Neg) => (VERTS_XZ_NEG, IJK_XZ, NORMAL_XZ_NEG),
-                (Plane::XY, Dir::Pos) => (VERTS_XY_POS, IJK_XY, NORMAL_XY_POS),
-                (Plane::XY, Dir::Neg) => (VERTS_XY_NEG, IJK_XY, NORMAL_XY_NEG),
-            };
-
-            let scale = Vec3::new(
-                ijk_scale[ijk_ids[0]],
-                ijk_scale[ijk_ids[1]],
-                ijk_scale[ijk_ids[2]],
-            );
-            let offset = Vec3::new(
-                ijk_offset[ijk_ids[0]],
-                ijk_offset[ijk_ids[1]],
-                ijk_offset[ijk_ids[2]],
-            );
-
-            let v0 = CUBE_VERTS[v_ids[0]] * scale + offset + slice_data.global_offset;
-            let v1 = CUBE_VERTS[v_ids[1]] * scale + offset + slice_data.global_offset;
-            let v2 = CUBE_VERTS[v_ids[2]] * scale + offset + slice_data.global_offset;
-            let v3 = CUBE_VERTS[v_ids[3]] * scale + offset + slice_data.global_offset;
-
-            add_quad(mesh_data, [v0, v1, v2, v3], &CUBE_NORMALS[normal_id]);
+            add_quad(start_col, start_row, width, height, corners);
 
             used[start_row] |= width_mask;
             available &= !width_mask;
@@ -957,18 +1511,917 @@ fn generate_greedy_faces_for_slice(
     }
 }
 
+// Emits the quad covering `width` x `height` faces of a slice, starting at `start_col`
+// and `start_row`. `corners` is the packed corner occlusion shared by all the faces, if
+// ambient occlusion is computed.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+fn add_slice_quad(
+    mesh_data: &mut MeshData,
+    slice_data: &SliceData,
+    slice: f32,
+    start_col: usize,
+    start_row: usize,
+    width: usize,
+    height: usize,
+    corners: Option<u8>,
+) {
+    // in voxels, scaled to world units per axis once mapped to x, y and z
+    let ijk_scale = [width as f32, height as f32, 1.0];
+    let ijk_offset = [start_col as f32, start_row as f32, slice];
+
+    let (v_ids, ijk_ids, normal_id) = match (slice_data.plane, slice_data.dir) {
+        (Plane::YZ, Dir::Pos) => (VERTS_YZ_POS, IJK_YZ, NORMAL_YZ_POS),
+        (Plane::YZ, Dir::Neg) => (VERTS_YZ_NEG, IJK_YZ, NORMAL_YZ_NEG),
+        (Plane::XZ, Dir::Pos) => (VERTS_XZ_POS, IJK_XZ, NORMAL_XZ_POS),
+        (Plane::XZ, Dir::Neg) => (VERTS_XZ_NEG, IJK_XZ, NORMAL_XZ_NEG),
+        (Plane::XY, Dir::Pos) => (VERTS_XY_POS, IJK_XY, NORMAL_XY_POS),
+        (Plane::XY, Dir::Neg) => (VERTS_XY_NEG, IJK_XY, NORMAL_XY_NEG),
+    };
+
+    let scale = slice_data.voxel_size
+        * Vec3::new(
+            ijk_scale[ijk_ids[0]],
+            ijk_scale[ijk_ids[1]],
+            ijk_scale[ijk_ids[2]],
+        );
+    let offset = slice_data.voxel_size
+        * Vec3::new(
+            ijk_offset[ijk_ids[0]],
+            ijk_offset[ijk_ids[1]],
+            ijk_offset[ijk_ids[2]],
+        );
+
+    let v0 = CUBE_VERTS[v_ids[0]] * scale + offset + slice_data.global_offset;
+    let v1 = CUBE_VERTS[v_ids[1]] * scale + offset + slice_data.global_offset;
+    let v2 = CUBE_VERTS[v_ids[2]] * scale + offset + slice_data.global_offset;
+    let v3 = CUBE_VERTS[v_ids[3]] * scale + offset + slice_data.global_offset;
+
+    let quad = [v0, v1, v2, v3];
+    let normal = &CUBE_NORMALS[normal_id];
+
+    match corners {
+        Some(corners) => {
+            let ao = quad_vertex_corners(slice_data)
+                .map(|corner| AO_CURVE[((corners >> (corner * 2)) & 0b11) as usize]);
+
+            add_quad_with_ao(mesh_data, quad, normal, ao);
+        }
+        None => add_quad(mesh_data, quad, normal),
+    }
+}
+
+// Returns the corner of the quad every vertex emitted by `add_slice_quad` lies on, with bit 0
+// set at the last column and bit 1 at the last row
+fn quad_vertex_corners(slice_data: &SliceData) -> [usize; 4] {
+    let (v_ids, ijk_ids) = match (slice_data.plane, slice_data.dir) {
+        (Plane::YZ, Dir::Pos) => (VERTS_YZ_POS, IJK_YZ),
+        (Plane::YZ, Dir::Neg) => (VERTS_YZ_NEG, IJK_YZ),
+        (Plane::XZ, Dir::Pos) => (VERTS_XZ_POS, IJK_XZ),
+        (Plane::XZ, Dir::Neg) => (VERTS_XZ_NEG, IJK_XZ),
+        (Plane::XY, Dir::Pos) => (VERTS_XY_POS, IJK_XY),
+        (Plane::XY, Dir::Neg) => (VERTS_XY_NEG, IJK_XY),
+    };
+
+    v_ids.map(|v_id| {
+        // columns and rows of the vertex on the unit cube pick its corner
+        let vertex = CUBE_VERTS[v_id];
+        let mut corner = 0;
+        for axis in 0..3 {
+            match ijk_ids[axis] {
+                0 => corner |= vertex[axis] as usize,
+                1 => corner |= (vertex[axis] as usize) << 1,
+                _ => {}
+            }
+        }
+
+        corner
+    })
+}
+
+/// Greedy meshes a dense grid of `size` voxels, indexed as `(y * size.z + z) * size.x + x`.
+/// Unlike `generate_greedy_mesh_arrays` the grid is not limited to 64 voxels per axis, so
+/// faces merge across chunk borders. Voxels outside the grid are treated as empty.
+///
+/// `merge_mode` picks whether quads stop at value changes. With a non-empty `palette`, indexed
+/// by `VoxelTrait::material_id` and white for ids past its end, every vertex gets the color of
+/// the voxel at its corner of the quad, so merged quads blend between their corner values.
+/// Colors are kept in step with the vertices of a mesh meshed into more than once, vertices
+/// left without a color, with no palette or from an earlier uncolored pass, are white.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_greedy_mesh_arrays_dense<T: VoxelTrait>(
+    voxels: &[T],
+    size: UVec3,
+    mesh_data: &mut MeshData,
+    offset: Vec3,
+    voxel_size: Vec3,
+    merge_mode: MergeMode,
+    palette: &[[f32; 4]],
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("generate_greedy_mesh_arrays_dense");
+
+    let color = |value: T| {
+        palette
+            .get(value.material_id())
+            .copied()
+            .unwrap_or([1.0; 4])
+    };
+
+    greedy_mesh_dense(
+        voxels,
+        size,
+        std::slice::from_mut(mesh_data),
+        |_| 0,
+        offset,
+        voxel_size,
+        merge_mode,
+        (!palette.is_empty()).then_some(&color),
+    );
+}
+
+/// Same as `generate_greedy_mesh_arrays_dense`, but with a separate mesh for every distinct
+/// non-empty value, sorted by value, e.g. to give every value its own material.
+pub fn generate_greedy_mesh_arrays_dense_by_value<T: VoxelTrait>(
+    voxels: &[T],
+    size: UVec3,
+    offset: Vec3,
+    voxel_size: Vec3,
+) -> Vec<(T, MeshData)> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("generate_greedy_mesh_arrays_dense_by_value");
+
+    let default_t = T::default();
+
+    let mut values = voxels
+        .iter()
+        .filter(|value| **value != default_t)
+        .copied()
+        .collect::<Vec<_>>();
+    values.sort_unstable();
+    values.dedup();
+
+    let mut meshes = values
+        .iter()
+        .map(|_| MeshData::default())
+        .collect::<Vec<_>>();
+
+    greedy_mesh_dense(
+        voxels,
+        size,
+        &mut meshes,
+        |value| values.binary_search(&value).unwrap(),
+        offset,
+        voxel_size,
+        MergeMode::PerValue,
+        None,
+    );
+
+    values.into_iter().zip(meshes).collect()
+}
+
+// `mesh_index` picks the mesh of a value, a quad merged across values goes to the mesh of
+// its first face. With `color` set every vertex gets the color of the value at its corner.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn greedy_mesh_dense<T: VoxelTrait>(
+    voxels: &[T],
+    size: UVec3,
+    meshes: &mut [MeshData],
+    mesh_index: impl Fn(T) -> usize,
+    offset: Vec3,
+    voxel_size: Vec3,
+    merge_mode: MergeMode,
+    color: Option<&dyn Fn(T) -> [f32; 4]>,
+) {
+    assert_eq!(voxels.len(), size.element_product() as usize);
+
+    let size = size.as_ivec3();
+    let default_t = T::default();
+
+    let merges = |face: T, value: T| match merge_mode {
+        MergeMode::PerValue => face.voxel_eq(&value),
+        MergeMode::MergeAllSolid => !face.voxel_eq(&default_t),
+    };
+
+    let colored = color.is_some();
+    let color = color.unwrap_or(&|_| [1.0; 4]);
+
+    let voxel_at = |position: IVec3| -> T {
+        if position.cmplt(IVec3::ZERO).any() || position.cmpge(size).any() {
+            default_t
+        } else {
+            voxels[((position.y * size.z + position.z) * size.x + position.x) as usize]
+        }
+    };
+
+    // slice, row and column axes of every plane, matching `IJK_*`
+    const AXES: [(Plane, [usize; 3]); 3] = [
+        (Plane::YZ, [0, 1, 2]),
+        (Plane::XZ, [1, 2, 0]),
+        (Plane::XY, [2, 1, 0]),
+    ];
+
+    for (plane, [slice_axis, row_axis, col_axis]) in AXES {
+        let slices = size[slice_axis];
+        let rows = size[row_axis] as usize;
+        let cols = size[col_axis] as usize;
+
+        let mut faces = vec![default_t; rows * cols];
+
+        for dir in [Dir::Pos, Dir::Neg] {
+            let step = if dir == Dir::Pos { 1 } else { -1 };
+
+            let slice_data = SliceData {
+                global_offset: offset,
+                voxel_size,
+                min_row: 0,
+                max_row: rows,
+                min_col: 0,
+                max_col: cols,
+                plane,
+                dir,
+            };
+
+            for slice in 0..slices {
+                let mut faces_left = 0;
+
+                for row in 0..rows {
+                    for col in 0..cols {
+                        let mut position = IVec3::ZERO;
+                        position[slice_axis] = slice;
+                        position[row_axis] = row as i32;
+                        position[col_axis] = col as i32;
+
+                        let value = voxel_at(position);
+
+                        position[slice_axis] += step;
+                        let face = if !value.voxel_eq(&default_t)
+                            && voxel_at(position).voxel_eq(&default_t)
+                        {
+                            faces_left += 1;
+                            value
+                        } else {
+                            default_t
+                        };
+
+                        faces[row * cols + col] = face;
+                    }
+                }
+
+                for start_row in 0..rows {
+                    if faces_left == 0 {
+                        break;
+                    }
+
+                    let mut start_col = 0;
+
+                    while start_col < cols {
+                        let value = faces[start_row * cols + start_col];
+                        if value.voxel_eq(&default_t) {
+                            start_col += 1;
+                            continue;
+                        }
+
+                        let mut width = 1;
+                        while start_col + width < cols
+                            && merges(faces[start_row * cols + start_col + width], value)
+                        {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        while start_row + height < rows
+                            && faces[(start_row + height) * cols + start_col..][..width]
+                                .iter()
+                                .all(|face| merges(*face, value))
+                        {
+                            height += 1;
+                        }
+
+                        // first and last column of the first and last row
+                        let corner_values = [start_row, start_row + height - 1].map(|row| {
+                            [start_col, start_col + width - 1].map(|col| faces[row * cols + col])
+                        });
+
+                        for row in start_row..start_row + height {
+                            faces[row * cols + start_col..][..width].fill(default_t);
+                        }
+
+                        let mesh_data = &mut meshes[mesh_index(value)];
+                        let vertex_count = mesh_data.vertices.len();
+
+                        add_slice_quad(
+                            mesh_data,
+                            &slice_data,
+                            slice as f32,
+                            start_col,
+                            start_row,
+                            width,
+                            height,
+                            None,
+                        );
+
+                        if colored || !mesh_data.colors.is_empty() {
+                            mesh_data.colors.resize(vertex_count, [1.0; 4]);
+                            mesh_data.colors.extend(
+                                quad_vertex_corners(&slice_data)
+                                    .map(|corner| color(corner_values[corner >> 1][corner & 1])),
+                            );
+                        }
+
+                        faces_left -= width * height;
+                        start_col += width;
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[inline(always)]
 pub fn add_quad(mesh_data: &mut MeshData, quad: [Vec3; 4], normal: &Vec3) {
     #[cfg(feature = "tracy")]
     let _span = tracy_client::span!("add_quad");
 
     let index = mesh_data.vertices.len() as u32;
+    let coordinate_system = mesh_data.coordinate_system;
+    let normal = coordinate_system.convert(*normal);
 
-    mesh_data.vertices.extend(quad);
-    mesh_data.normals.extend([normal, normal, normal, normal]);
+    mesh_data
+        .vertices
+        .extend(quad.map(|vertex| coordinate_system.convert(vertex)));
+    mesh_data.normals.extend([normal; 4]);
     mesh_data
         .indices
-        .extend([index + 2, index + 1, index, index + 3, index, index + 1]);
+        .extend(coordinate_system.quad_indices(index));
+}
+
+#[inline(always)]
+pub fn add_quad_with_color(
+    mesh_data: &mut MeshData,
+    quad: [Vec3; 4],
+    normal: &Vec3,
+    color: [f32; 4],
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("add_quad_with_color");
+
+    add_quad(mesh_data, quad, normal);
+    mesh_data.colors.extend([color; 4]);
+}
+
+#[inline(always)]
+pub fn add_quad_with_ao(mesh_data: &mut MeshData, quad: [Vec3; 4], normal: &Vec3, ao: [f32; 4]) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("add_quad_with_ao");
+
+    add_quad(mesh_data, quad, normal);
+    mesh_data.ao.extend(ao);
+}
+
+/// Emits the six faces of every non-empty leaf node, so the octree subdivision is
+/// visible in the mesh. Colors are written only when `tint` is not `DebugTint::None`.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_debug_tint_mesh_arrays<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    mesh_data: &mut MeshData,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+    offset: Vec3,
+    voxel_size: Vec3,
+    chunk_position: IVec3,
+    tint: DebugTint,
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("generate_debug_tint_mesh_arrays");
+
+    if root_id.is_empty() {
+        return;
+    }
+
+    let default_t = T::default();
+    let max_depth = max_depth.max() as u32;
+
+    let mut stack: Vec<(BlockId, UVec3, u32)> = Vec::with_capacity(64);
+    stack.push((*root_id, UVec3::ZERO, 0));
+
+    while let Some((node_id, pos, depth)) = stack.pop() {
+        if node_id.is_branch() && (depth < max_depth) {
+            let child_cube_half_side = 1 << (max_depth - depth - 1);
+            let childs = interner.get_children_ref(&node_id);
+            for (i, child_id) in childs.iter().enumerate() {
+                if child_id.is_empty() {
+                    continue;
+                }
+
+                let i = i as u32;
+                let x = (i & 1) * child_cube_half_side;
+                let y = ((i & 2) >> 1) * child_cube_half_side;
+                let z = ((i & 4) >> 2) * child_cube_half_side;
+
+                stack.push((*child_id, pos + UVec3::new(x, y, z), depth + 1));
+            }
+        } else {
+            if *interner.get_value(&node_id) == default_t {
+                continue;
+            }
+
+            let side = (1u32 << (max_depth - depth)) as f32 * voxel_size;
+            let origin = pos.as_vec3() * voxel_size + offset;
+
+            let v = CUBE_VERTS.map(|v| v * side + origin);
+
+            let quads = [
+                ([v[0], v[2], v[3], v[1]], VEC_UP),
+                ([v[2], v[5], v[6], v[1]], VEC_RIGHT),
+                ([v[7], v[5], v[4], v[6]], VEC_DOWN),
+                ([v[0], v[7], v[4], v[3]], VEC_LEFT),
+                ([v[3], v[6], v[7], v[2]], VEC_BACK),
+                ([v[1], v[4], v[5], v[0]], VEC_FORWARD),
+            ];
+
+            match tint.color(depth, chunk_position) {
+                Some(color) => {
+                    for (quad, normal) in quads {
+                        add_quad_with_color(mesh_data, quad, &normal, color);
+                    }
+                }
+                None => {
+                    for (quad, normal) in quads {
+                        add_quad(mesh_data, quad, &normal);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Pairs of `CUBE_VERTS` joined by the twelve edges of a cube
+const CUBE_EDGES: [[u32; 2]; 12] = [
+    [0, 1],
+    [1, 2],
+    [2, 3],
+    [3, 0],
+    [4, 5],
+    [5, 6],
+    [6, 7],
+    [7, 4],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+/// Emits the twelve edges of the bounds of every non-empty branch node as lines, colored by
+/// depth, so it is visible where the octree subdivides and where it collapses into leaves.
+/// Shared subtrees are emitted at every position they appear at.
+pub fn generate_octree_wireframe_arrays<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    mesh_data: &mut MeshData,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+    offset: Vec3,
+    voxel_size: Vec3,
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("generate_octree_wireframe_arrays");
+
+    if root_id.is_empty() {
+        return;
+    }
+
+    let coordinate_system = mesh_data.coordinate_system;
+    let max_depth = max_depth.max() as u32;
+
+    let mut stack: Vec<(BlockId, UVec3, u32)> = Vec::with_capacity(64);
+    stack.push((*root_id, UVec3::ZERO, 0));
+
+    while let Some((node_id, pos, depth)) = stack.pop() {
+        if !node_id.is_branch() || depth >= max_depth {
+            continue;
+        }
+
+        let side = (1u32 << (max_depth - depth)) as f32 * voxel_size;
+        let origin = pos.as_vec3() * voxel_size + offset;
+
+        let index = mesh_data.vertices.len() as u32;
+        mesh_data
+            .vertices
+            .extend(CUBE_VERTS.map(|vertex| coordinate_system.convert(vertex * side + origin)));
+        mesh_data
+            .indices
+            .extend(CUBE_EDGES.iter().flatten().map(|corner| index + corner));
+        mesh_data
+            .colors
+            .extend([DEBUG_TINT_PALETTE[depth as usize % DEBUG_TINT_PALETTE.len()]; 8]);
+
+        let child_cube_half_side = 1 << (max_depth - depth - 1);
+        let childs = interner.get_children_ref(&node_id);
+        for (i, child_id) in childs.iter().enumerate() {
+            if child_id.is_empty() {
+                continue;
+            }
+
+            let i = i as u32;
+            let x = (i & 1) * child_cube_half_side;
+            let y = ((i & 2) >> 1) * child_cube_half_side;
+            let z = ((i & 4) >> 2) * child_cube_half_side;
+
+            stack.push((*child_id, pos + UVec3::new(x, y, z), depth + 1));
+        }
+    }
+}
+
+// Corners joined by the twelve edges of a marching cube, corner `i` sits at
+// `(i & 1, (i >> 1) & 1, (i >> 2) & 1)` like the children of a node. Edges along `x` come
+// first, then along `y` and `z`, each from its lower corner.
+const MARCHING_CUBES_EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+// Triangles for every combination of corners inside the surface, indexed by a mask with bit
+// `i` set for an inside corner `i`. Every triangle is three `MARCHING_CUBES_EDGES` its
+// vertices lie on, wound counter-clockwise seen from outside, and a row ends at `-1`.
+// Ambiguous faces always keep their two inside corners apart, so the cubes sharing a face
+// agree on where the surface crosses it and the surface has no cracks.
+#[rustfmt::skip]
+const MARCHING_CUBES_TRIANGLES: [[i8; 16]; 256] = [
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 9, 5, 8, 5, 1, 8, 1, 10, -1, -1, -1, -1, -1, -1, -1],
+    [1, 5, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 1, 5, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [9, 11, 1, 9, 1, 4, 9, 4, 8, -1, -1, -1, -1, -1, -1, -1],
+    [4, 5, 11, 4, 11, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [10, 8, 0, 10, 0, 5, 10, 5, 11, -1, -1, -1, -1, -1, -1, -1],
+    [11, 10, 4, 11, 4, 0, 11, 0, 9, -1, -1, -1, -1, -1, -1, -1],
+    [8, 9, 11, 8, 11, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 6, 0, 6, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 6, 0, 6, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 6, 1, 6, 2, 1, 2, 9, 1, 9, 5, -1, -1, -1, -1],
+    [1, 5, 11, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 6, 0, 6, 2, 1, 5, 11, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 9, 4, 9, 11, 4, 11, 1, -1, -1, -1, -1],
+    [2, 8, 6, 4, 5, 11, 4, 11, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 5, 11, 0, 11, 10, 0, 10, 6, 0, 6, 2, -1, -1, -1, -1],
+    [11, 10, 4, 11, 4, 0, 11, 0, 9, 2, 8, 6, -1, -1, -1, -1],
+    [11, 10, 6, 11, 6, 2, 11, 2, 9, -1, -1, -1, -1, -1, -1, -1],
+    [2, 7, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 2, 7, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 4, 8, 5, 8, 2, 5, 2, 7, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 2, 7, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 8, 2, 7, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, 1, 10, 4, -1, -1, -1, -1, -1, -1, -1],
+    [10, 8, 2, 10, 2, 7, 10, 7, 5, 10, 5, 1, -1, -1, -1, -1],
+    [1, 5, 11, 2, 7, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 1, 5, 11, 2, 7, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 11, 0, 11, 1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 4, 8, 1, 8, 2, 1, 2, 7, 1, 7, 11, -1, -1, -1, -1],
+    [2, 7, 9, 4, 5, 11, 4, 11, 10, -1, -1, -1, -1, -1, -1, -1],
+    [10, 8, 0, 10, 0, 5, 10, 5, 11, 2, 7, 9, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 11, 0, 11, 10, 0, 10, 4, -1, -1, -1, -1],
+    [10, 8, 2, 10, 2, 7, 10, 7, 11, -1, -1, -1, -1, -1, -1, -1],
+    [6, 7, 9, 6, 9, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 7, 9, 6, 9, 0, 6, 0, 4, -1, -1, -1, -1, -1, -1, -1],
+    [7, 5, 0, 7, 0, 8, 7, 8, 6, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 7, 4, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 6, 7, 9, 6, 9, 8, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 6, 0, 6, 7, 0, 7, 9, -1, -1, -1, -1],
+    [7, 5, 0, 7, 0, 8, 7, 8, 6, 1, 10, 4, -1, -1, -1, -1],
+    [7, 5, 1, 7, 1, 10, 7, 10, 6, -1, -1, -1, -1, -1, -1, -1],
+    [1, 5, 11, 6, 7, 9, 6, 9, 8, -1, -1, -1, -1, -1, -1, -1],
+    [6, 7, 9, 6, 9, 0, 6, 0, 4, 1, 5, 11, -1, -1, -1, -1],
+    [0, 8, 6, 0, 6, 7, 0, 7, 11, 0, 11, 1, -1, -1, -1, -1],
+    [6, 7, 11, 6, 11, 1, 6, 1, 4, -1, -1, -1, -1, -1, -1, -1],
+    [4, 5, 11, 4, 11, 10, 6, 7, 9, 6, 9, 8, -1, -1, -1, -1],
+    [0, 5, 11, 0, 11, 10, 0, 10, 6, 0, 6, 7, 0, 7, 9, -1],
+    [0, 8, 6, 0, 6, 7, 0, 7, 11, 0, 11, 10, 0, 10, 4, -1],
+    [6, 7, 11, 6, 11, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 6, 10, 4, 8, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 8, 1, 8, 0, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 3, 6, 1, 6, 4, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 8, 1, 8, 9, 1, 9, 5, -1, -1, -1, -1],
+    [1, 5, 11, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 1, 5, 11, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [9, 11, 1, 9, 1, 4, 9, 4, 8, 3, 6, 10, -1, -1, -1, -1],
+    [4, 5, 11, 4, 11, 3, 4, 3, 6, -1, -1, -1, -1, -1, -1, -1],
+    [0, 5, 11, 0, 11, 3, 0, 3, 6, 0, 6, 8, -1, -1, -1, -1],
+    [9, 11, 3, 9, 3, 6, 9, 6, 4, 9, 4, 0, -1, -1, -1, -1],
+    [9, 11, 3, 9, 3, 6, 9, 6, 8, -1, -1, -1, -1, -1, -1, -1],
+    [2, 8, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 0, 4, 2, 4, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 2, 8, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1, -1],
+    [2, 9, 5, 2, 5, 4, 2, 4, 10, 2, 10, 3, -1, -1, -1, -1],
+    [3, 2, 8, 3, 8, 4, 3, 4, 1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 3, 0, 3, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 3, 2, 8, 3, 8, 4, 3, 4, 1, -1, -1, -1, -1],
+    [3, 2, 9, 3, 9, 5, 3, 5, 1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 5, 11, 2, 8, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1, -1],
+    [2, 0, 4, 2, 4, 10, 2, 10, 3, 1, 5, 11, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, 2, 8, 10, 2, 10, 3, -1, -1, -1, -1],
+    [4, 10, 3, 4, 3, 2, 4, 2, 9, 4, 9, 11, 4, 11, 1, -1],
+    [2, 8, 4, 2, 4, 5, 2, 5, 11, 2, 11, 3, -1, -1, -1, -1],
+    [2, 0, 5, 2, 5, 11, 2, 11, 3, -1, -1, -1, -1, -1, -1, -1],
+    [4, 0, 9, 4, 9, 11, 4, 11, 3, 4, 3, 2, 4, 2, 8, -1],
+    [2, 9, 11, 2, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 7, 9, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 2, 7, 9, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [5, 4, 8, 5, 8, 2, 5, 2, 7, 3, 6, 10, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 4, 2, 7, 9, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 8, 1, 8, 0, 2, 7, 9, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, 1, 3, 6, 1, 6, 4, -1, -1, -1, -1],
+    [8, 2, 7, 8, 7, 5, 8, 5, 1, 8, 1, 3, 8, 3, 6, -1],
+    [1, 5, 11, 2, 7, 9, 3, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 1, 5, 11, 2, 7, 9, 3, 6, 10, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 11, 0, 11, 1, 3, 6, 10, -1, -1, -1, -1],
+    [1, 4, 8, 1, 8, 2, 1, 2, 7, 1, 7, 11, 3, 6, 10, -1],
+    [2, 7, 9, 4, 5, 11, 4, 11, 3, 4, 3, 6, -1, -1, -1, -1],
+    [0, 5, 11, 0, 11, 3, 0, 3, 6, 0, 6, 8, 2, 7, 9, -1],
+    [11, 3, 6, 11, 6, 4, 11, 4, 0, 11, 0, 2, 11, 2, 7, -1],
+    [11, 3, 6, 11, 6, 8, 11, 8, 2, 11, 2, 7, -1, -1, -1, -1],
+    [8, 10, 3, 8, 3, 7, 8, 7, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 10, 0, 10, 3, 0, 3, 7, 0, 7, 9, -1, -1, -1, -1],
+    [8, 10, 3, 8, 3, 7, 8, 7, 5, 8, 5, 0, -1, -1, -1, -1],
+    [5, 4, 10, 5, 10, 3, 5, 3, 7, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 7, 1, 7, 9, 1, 9, 8, 1, 8, 4, -1, -1, -1, -1],
+    [1, 3, 7, 1, 7, 9, 1, 9, 0, -1, -1, -1, -1, -1, -1, -1],
+    [8, 4, 1, 8, 1, 3, 8, 3, 7, 8, 7, 5, 8, 5, 0, -1],
+    [1, 3, 7, 1, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 5, 11, 8, 10, 3, 8, 3, 7, 8, 7, 9, -1, -1, -1, -1],
+    [0, 4, 10, 0, 10, 3, 0, 3, 7, 0, 7, 9, 1, 5, 11, -1],
+    [7, 11, 1, 7, 1, 0, 7, 0, 8, 7, 8, 10, 7, 10, 3, -1],
+    [4, 10, 3, 4, 3, 7, 4, 7, 11, 4, 11, 1, -1, -1, -1, -1],
+    [3, 7, 9, 3, 9, 8, 3, 8, 4, 3, 4, 5, 3, 5, 11, -1],
+    [0, 5, 11, 0, 11, 3, 0, 3, 7, 0, 7, 9, -1, -1, -1, -1],
+    [0, 8, 4, 3, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 7, 11, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 11, 7, 4, 8, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 8, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [8, 9, 5, 8, 5, 1, 8, 1, 10, 3, 11, 7, -1, -1, -1, -1],
+    [1, 5, 7, 1, 7, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 1, 5, 7, 1, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [1, 0, 9, 1, 9, 7, 1, 7, 3, -1, -1, -1, -1, -1, -1, -1],
+    [1, 4, 8, 1, 8, 9, 1, 9, 7, 1, 7, 3, -1, -1, -1, -1],
+    [5, 7, 3, 5, 3, 10, 5, 10, 4, -1, -1, -1, -1, -1, -1, -1],
+    [5, 7, 3, 5, 3, 10, 5, 10, 8, 5, 8, 0, -1, -1, -1, -1],
+    [0, 9, 7, 0, 7, 3, 0, 3, 10, 0, 10, 4, -1, -1, -1, -1],
+    [8, 9, 7, 8, 7, 3, 8, 3, 10, -1, -1, -1, -1, -1, -1, -1],
+    [2, 8, 6, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 6, 0, 6, 2, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 2, 8, 6, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 9, 4, 9, 5, 3, 11, 7, -1, -1, -1, -1],
+    [1, 10, 4, 2, 8, 6, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 6, 0, 6, 2, 3, 11, 7, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, 2, 8, 6, 3, 11, 7, -1, -1, -1, -1],
+    [1, 10, 6, 1, 6, 2, 1, 2, 9, 1, 9, 5, 3, 11, 7, -1],
+    [1, 5, 7, 1, 7, 3, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 6, 0, 6, 2, 1, 5, 7, 1, 7, 3, -1, -1, -1, -1],
+    [1, 0, 9, 1, 9, 7, 1, 7, 3, 2, 8, 6, -1, -1, -1, -1],
+    [9, 7, 3, 9, 3, 1, 9, 1, 4, 9, 4, 6, 9, 6, 2, -1],
+    [2, 8, 6, 5, 7, 3, 5, 3, 10, 5, 10, 4, -1, -1, -1, -1],
+    [10, 6, 2, 10, 2, 0, 10, 0, 5, 10, 5, 7, 10, 7, 3, -1],
+    [0, 9, 7, 0, 7, 3, 0, 3, 10, 0, 10, 4, 2, 8, 6, -1],
+    [9, 7, 3, 9, 3, 10, 9, 10, 6, 9, 6, 2, -1, -1, -1, -1],
+    [2, 3, 11, 2, 11, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 2, 3, 11, 2, 11, 9, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 11, 2, 11, 5, 2, 5, 0, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 11, 2, 11, 5, 2, 5, 4, 2, 4, 8, -1, -1, -1, -1],
+    [1, 10, 4, 2, 3, 11, 2, 11, 9, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 8, 2, 3, 11, 2, 11, 9, -1, -1, -1, -1],
+    [2, 3, 11, 2, 11, 5, 2, 5, 0, 1, 10, 4, -1, -1, -1, -1],
+    [5, 1, 10, 5, 10, 8, 5, 8, 2, 5, 2, 3, 5, 3, 11, -1],
+    [3, 1, 5, 3, 5, 9, 3, 9, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 3, 1, 5, 3, 5, 9, 3, 9, 2, -1, -1, -1, -1],
+    [0, 2, 3, 0, 3, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 1, 4, 3, 4, 8, 3, 8, 2, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 10, 2, 10, 4, 2, 4, 5, 2, 5, 9, -1, -1, -1, -1],
+    [5, 9, 2, 5, 2, 3, 5, 3, 10, 5, 10, 8, 5, 8, 0, -1],
+    [2, 3, 10, 2, 10, 4, 2, 4, 0, -1, -1, -1, -1, -1, -1, -1],
+    [2, 3, 10, 2, 10, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [9, 8, 6, 9, 6, 3, 9, 3, 11, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 3, 4, 3, 11, 4, 11, 9, 4, 9, 0, -1, -1, -1, -1],
+    [0, 8, 6, 0, 6, 3, 0, 3, 11, 0, 11, 5, -1, -1, -1, -1],
+    [4, 6, 3, 4, 3, 11, 4, 11, 5, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 9, 8, 6, 9, 6, 3, 9, 3, 11, -1, -1, -1, -1],
+    [6, 3, 11, 6, 11, 9, 6, 9, 0, 6, 0, 1, 6, 1, 10, -1],
+    [0, 8, 6, 0, 6, 3, 0, 3, 11, 0, 11, 5, 1, 10, 4, -1],
+    [6, 3, 11, 6, 11, 5, 6, 5, 1, 6, 1, 10, -1, -1, -1, -1],
+    [1, 5, 9, 1, 9, 8, 1, 8, 6, 1, 6, 3, -1, -1, -1, -1],
+    [9, 0, 4, 9, 4, 6, 9, 6, 3, 9, 3, 1, 9, 1, 5, -1],
+    [1, 0, 8, 1, 8, 6, 1, 6, 3, -1, -1, -1, -1, -1, -1, -1],
+    [1, 4, 6, 1, 6, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 10, 4, 3, 4, 5, 3, 5, 9, 3, 9, 8, 3, 8, 6, -1],
+    [0, 5, 9, 3, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 6, 0, 6, 3, 0, 3, 10, 0, 10, 4, -1, -1, -1, -1],
+    [3, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 10, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 6, 10, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 6, 10, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 9, 4, 9, 5, 6, 10, 11, 6, 11, 7, -1, -1, -1, -1],
+    [6, 4, 1, 6, 1, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 11, 0, 11, 7, 0, 7, 6, 0, 6, 8, -1, -1, -1, -1],
+    [0, 9, 5, 6, 4, 1, 6, 1, 11, 6, 11, 7, -1, -1, -1, -1],
+    [1, 11, 7, 1, 7, 6, 1, 6, 8, 1, 8, 9, 1, 9, 5, -1],
+    [7, 6, 10, 7, 10, 1, 7, 1, 5, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 7, 6, 10, 7, 10, 1, 7, 1, 5, -1, -1, -1, -1],
+    [0, 9, 7, 0, 7, 6, 0, 6, 10, 0, 10, 1, -1, -1, -1, -1],
+    [1, 4, 8, 1, 8, 9, 1, 9, 7, 1, 7, 6, 1, 6, 10, -1],
+    [4, 5, 7, 4, 7, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [7, 6, 8, 7, 8, 0, 7, 0, 5, -1, -1, -1, -1, -1, -1, -1],
+    [6, 4, 0, 6, 0, 9, 6, 9, 7, -1, -1, -1, -1, -1, -1, -1],
+    [6, 8, 9, 6, 9, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [10, 11, 7, 10, 7, 2, 10, 2, 8, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 10, 0, 10, 11, 0, 11, 7, 0, 7, 2, -1, -1, -1, -1],
+    [0, 9, 5, 10, 11, 7, 10, 7, 2, 10, 2, 8, -1, -1, -1, -1],
+    [2, 9, 5, 2, 5, 4, 2, 4, 10, 2, 10, 11, 2, 11, 7, -1],
+    [1, 11, 7, 1, 7, 2, 1, 2, 8, 1, 8, 4, -1, -1, -1, -1],
+    [0, 1, 11, 0, 11, 7, 0, 7, 2, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 11, 7, 1, 7, 2, 1, 2, 8, 1, 8, 4, -1],
+    [1, 11, 7, 1, 7, 2, 1, 2, 9, 1, 9, 5, -1, -1, -1, -1],
+    [5, 7, 2, 5, 2, 8, 5, 8, 10, 5, 10, 1, -1, -1, -1, -1],
+    [10, 1, 5, 10, 5, 7, 10, 7, 2, 10, 2, 0, 10, 0, 4, -1],
+    [7, 2, 8, 7, 8, 10, 7, 10, 1, 7, 1, 0, 7, 0, 9, -1],
+    [1, 4, 10, 2, 9, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 7, 2, 5, 2, 8, 5, 8, 4, -1, -1, -1, -1, -1, -1, -1],
+    [0, 5, 7, 0, 7, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [7, 2, 8, 7, 8, 4, 7, 4, 0, 7, 0, 9, -1, -1, -1, -1],
+    [2, 9, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [11, 9, 2, 11, 2, 6, 11, 6, 10, -1, -1, -1, -1, -1, -1, -1],
+    [0, 4, 8, 11, 9, 2, 11, 2, 6, 11, 6, 10, -1, -1, -1, -1],
+    [0, 2, 6, 0, 6, 10, 0, 10, 11, 0, 11, 5, -1, -1, -1, -1],
+    [2, 6, 10, 2, 10, 11, 2, 11, 5, 2, 5, 4, 2, 4, 8, -1],
+    [11, 9, 2, 11, 2, 6, 11, 6, 4, 11, 4, 1, -1, -1, -1, -1],
+    [6, 8, 0, 6, 0, 1, 6, 1, 11, 6, 11, 9, 6, 9, 2, -1],
+    [11, 5, 0, 11, 0, 2, 11, 2, 6, 11, 6, 4, 11, 4, 1, -1],
+    [1, 11, 5, 2, 6, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 5, 9, 1, 9, 2, 1, 2, 6, 1, 6, 10, -1, -1, -1, -1],
+    [0, 4, 8, 1, 5, 9, 1, 9, 2, 1, 2, 6, 1, 6, 10, -1],
+    [0, 2, 6, 0, 6, 10, 0, 10, 1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 4, 8, 1, 8, 2, 1, 2, 6, 1, 6, 10, -1, -1, -1, -1],
+    [4, 5, 9, 4, 9, 2, 4, 2, 6, -1, -1, -1, -1, -1, -1, -1],
+    [5, 9, 2, 5, 2, 6, 5, 6, 8, 5, 8, 0, -1, -1, -1, -1],
+    [0, 2, 6, 0, 6, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 6, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 10, 11, 8, 11, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [11, 9, 0, 11, 0, 4, 11, 4, 10, -1, -1, -1, -1, -1, -1, -1],
+    [10, 11, 5, 10, 5, 0, 10, 0, 8, -1, -1, -1, -1, -1, -1, -1],
+    [4, 10, 11, 4, 11, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [9, 8, 4, 9, 4, 1, 9, 1, 11, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 11, 0, 11, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 4, 1, 8, 1, 11, 8, 11, 5, 8, 5, 0, -1, -1, -1, -1],
+    [1, 11, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 10, 1, 8, 1, 5, 8, 5, 9, -1, -1, -1, -1, -1, -1, -1],
+    [10, 1, 5, 10, 5, 9, 10, 9, 0, 10, 0, 4, -1, -1, -1, -1],
+    [0, 8, 10, 0, 10, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 4, 10, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 5, 9, 4, 9, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 5, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+];
+
+/// Emits a smooth surface around the voxels with a value of at least `iso`, with marching
+/// cubes over a grid with a sample at every voxel center. Values are only ordered, so vertices
+/// sit halfway along the edges rather than interpolated, and normals follow the gradient of
+/// the occupancy, estimated per axis with central differences. Everything outside the chunk is
+/// outside, so the surface is closed at the chunk border. Triangles share their vertices.
+pub fn generate_marching_cubes_arrays<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    mesh_data: &mut MeshData,
+    root_id: &BlockId,
+    max_depth: MaxDepth,
+    offset: Vec3,
+    voxel_size: Vec3,
+    iso: T,
+) {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("generate_marching_cubes_arrays");
+
+    if root_id.is_empty() {
+        return;
+    }
+
+    let voxels = to_vec(interner, root_id, max_depth);
+    let size = 1i32 << max_depth.max();
+
+    // padded by two samples, so the gradient can be taken around the outermost cubes
+    let padded = size + 4;
+    let mut occupancy = vec![0.0f32; (padded * padded * padded) as usize];
+    for y in 0..size {
+        for z in 0..size {
+            for x in 0..size {
+                if voxels[(y * size * size + z * size + x) as usize] >= iso {
+                    let index = ((z + 2) * padded + y + 2) * padded + x + 2;
+                    occupancy[index as usize] = 1.0;
+                }
+            }
+        }
+    }
+
+    let sample = |p: IVec3| {
+        let p = p + IVec3::splat(2);
+        occupancy[((p.z * padded + p.y) * padded + p.x) as usize]
+    };
+    let gradient = |p: IVec3| {
+        Vec3::new(
+            sample(p + IVec3::X) - sample(p - IVec3::X),
+            sample(p + IVec3::Y) - sample(p - IVec3::Y),
+            sample(p + IVec3::Z) - sample(p - IVec3::Z),
+        )
+    };
+
+    let coordinate_system = mesh_data.coordinate_system;
+    let corners: [IVec3; 8] =
+        std::array::from_fn(|i| IVec3::new(i as i32 & 1, (i as i32 >> 1) & 1, (i as i32 >> 2) & 1));
+
+    // vertex of every crossed edge, keyed by its lower sample and axis
+    let mut edge_vertices: FxHashMap<(IVec3, usize), u32> = FxHashMap::default();
+
+    for z in -1..size {
+        for y in -1..size {
+            for x in -1..size {
+                let cube = IVec3::new(x, y, z);
+
+                let case = corners
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, corner)| sample(cube + **corner) > 0.0)
+                    .fold(0, |case, (i, _)| case | (1 << i));
+
+                for triangle in MARCHING_CUBES_TRIANGLES[case]
+                    .chunks_exact(3)
+                    .take_while(|triangle| triangle[0] >= 0)
+                {
+                    let indices = [0, 1, 2].map(|i| {
+                        let edge = triangle[i] as usize;
+                        let [a, b] = MARCHING_CUBES_EDGES[edge];
+                        let (lower, upper) = (cube + corners[a], cube + corners[b]);
+
+                        *edge_vertices.entry((lower, edge / 4)).or_insert_with(|| {
+                            let index = mesh_data.vertices.len() as u32;
+
+                            let center = (lower + upper).as_vec3() * 0.5 + 0.5;
+                            let outward = if sample(lower) > 0.0 {
+                                upper - lower
+                            } else {
+                                lower - upper
+                            };
+                            // the gradient cancels out across thin walls, fall back to the edge
+                            let normal = (-(gradient(lower) + gradient(upper)))
+                                .try_normalize()
+                                .unwrap_or(outward.as_vec3());
+
+                            mesh_data
+                                .vertices
+                                .push(coordinate_system.convert(center * voxel_size + offset));
+                            mesh_data.normals.push(coordinate_system.convert(normal));
+
+                            index
+                        })
+                    });
+
+                    mesh_data
+                        .indices
+                        .extend(coordinate_system.triangle_indices(indices));
+                }
+            }
+        }
+    }
 }
 
 #[inline(always)]
@@ -987,6 +2440,16 @@ const fn find_contiguous_bits(mask: u64, start: usize) -> u64 {
     ((1u64 << first_zero) - 1) << start
 }
 
+// Returns a mask of the `count` lowest bits.
+#[inline(always)]
+const fn low_bits(count: usize) -> u64 {
+    if count >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << count) - 1
+    }
+}
+
 pub fn generate_greedy_mesh_arrays_stride<
     T: VoxelTrait,
     C: VoxOpsChunkLocalContainer<T> + VoxOpsConfig + VoxOpsChunkConfig,
@@ -995,10 +2458,9 @@ pub fn generate_greedy_mesh_arrays_stride<
     store: &VoxInterner<T>,
     lod: Lod,
     mesh_data: &mut MeshData,
+    ao: bool,
+    normal_mode: NormalMode,
 ) {
-    let mut offset = Vec3::new(0.0, 0.0, 0.0);
-
-    let chunk_size = container.chunk_size();
     let voxels_per_axis = container.voxels_per_axis(lod);
     let max_depth = container.max_depth(lod);
     let voxel_size = container.voxel_size(lod);
@@ -1014,9 +2476,7 @@ pub fn generate_greedy_mesh_arrays_stride<
             for x in (0..chunks_size.x).step_by(stride as usize) {
                 let chunk_pos = IVec3::new(x, y, z);
 
-                offset.x = chunk_pos.x as f32 * chunk_size;
-                offset.y = chunk_pos.y as f32 * chunk_size;
-                offset.z = chunk_pos.z as f32 * chunk_size;
+                let offset = container.chunk_mesh_offset(chunk_pos);
 
                 let mut builder = OccupancyDataBuilder::default();
 
@@ -1037,7 +2497,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1065,7 +2525,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1093,7 +2553,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1121,7 +2581,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1149,7 +2609,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1177,7 +2637,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1204,7 +2664,7 @@ pub fn generate_greedy_mesh_arrays_stride<
                                 continue;
                             };
 
-                            if chunk.is_empty() {
+                            if chunk.is_empty_at_lod(store, lod) {
                                 continue;
                             }
 
@@ -1239,12 +2699,19 @@ pub fn generate_greedy_mesh_arrays_stride<
                     mesh_max_depth,
                     offset,
                     voxel_size,
+                    ao,
+                    SweepAxis::X,
                     #[cfg(feature = "trace_greedy_timings")]
                     &mut timings,
                 );
             }
         }
     }
+
+    // welded once the whole model is meshed, so normals also blend across chunk borders
+    if normal_mode == NormalMode::Smooth {
+        mesh_data.smooth_normals();
+    }
 }
 
 // debug version, to be removed later
@@ -1323,7 +2790,322 @@ pub fn chunk_generate_greedy_mesh_arrays_ext<T: VoxelTrait>(
         max_depth,
         offset,
         voxel_size,
+        false,
+        SweepAxis::X,
         #[cfg(feature = "trace_greedy_timings")]
         timings,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::spatial::{VoxOpsBulkWrite, VoxOpsWrite};
+
+    use super::*;
+
+    #[test]
+    fn test_mesh_cache_reuses_shared_roots() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut cache = MeshCache::new(2);
+
+        let mut chunks = (0..3)
+            .map(|x| VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, x, 0, 0))
+            .collect::<Vec<_>>();
+        for chunk in chunks.iter_mut() {
+            chunk.set(&mut interner, IVec3::new(1, 2, 3), 1);
+        }
+
+        // identical chunks dedup to one root, so only the first one is meshed
+        let first = cache.get_or_generate(&chunks[0], &interner, Lod::new(0));
+        let second = cache.get_or_generate(&chunks[1], &interner, Lod::new(0));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.indices.len(), 6 * 6);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // an edit changes the root
+        chunks[2].set(&mut interner, IVec3::new(5, 5, 5), 2);
+        let edited = cache.get_or_generate(&chunks[2], &interner, Lod::new(0));
+        assert_eq!(edited.indices.len(), 2 * 6 * 6);
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(cache.len(), 2);
+
+        // a new level of detail evicts the least recently used mesh
+        cache.get_or_generate(&chunks[2], &interner, Lod::new(1));
+        assert_eq!(cache.len(), 2);
+        cache.get_or_generate(&chunks[2], &interner, Lod::new(0));
+        assert_eq!((cache.hits(), cache.misses()), (2, 3));
+        cache.get_or_generate(&chunks[0], &interner, Lod::new(0));
+        assert_eq!((cache.hits(), cache.misses()), (2, 4));
+
+        cache.clear();
+        assert!(cache.is_empty());
+
+        for chunk in chunks.iter_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_mesh_data_counters() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        let mut mesh_data = MeshData::default();
+        assert!(mesh_data.is_empty());
+        assert_eq!(mesh_data.estimated_gpu_bytes(), 0);
+
+        // a 2x2x2 cube merges into one quad per side
+        for i in 0..8 {
+            let position = IVec3::new(i & 1, (i >> 1) & 1, i >> 2);
+            chunk.set(&mut interner, position, 1);
+        }
+        chunk.generate_greedy_mesh_arrays(&interner, &mut mesh_data, Vec3::ZERO, Lod::new(0));
+
+        assert!(!mesh_data.is_empty());
+        assert_eq!(mesh_data.vertex_count(), 24);
+        assert_eq!(mesh_data.triangle_count(), 12);
+        assert_eq!(mesh_data.estimated_gpu_bytes(), 24 * 12 + 24 * 12 + 36 * 4);
+
+        mesh_data.colors = vec![[1.0; 4]; 24];
+        mesh_data.ao = vec![1.0; 24];
+        assert_eq!(
+            mesh_data.estimated_gpu_bytes(),
+            24 * 12 + 24 * 12 + 36 * 4 + 24 * 16 + 24 * 4
+        );
+
+        let wireframe = chunk.generate_octree_wireframe(&interner, Lod::new(0));
+        assert!(!wireframe.is_empty());
+        assert_eq!(wireframe.triangle_count(), 0);
+
+        chunk.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_dense_merge_mode_on_two_color_plane() {
+        // a 4x4 plane, the first two columns of value 1 and the last two of value 2
+        let size = UVec3::new(4, 1, 4);
+        let voxels = (0..16).map(|i| 1 + (i % 4) / 2).collect::<Vec<u8>>();
+
+        let palette = [[0.0; 4], [1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]];
+
+        let top_quads = |merge_mode: MergeMode| {
+            let mut mesh_data = MeshData::default();
+            generate_greedy_mesh_arrays_dense(
+                &voxels,
+                size,
+                &mut mesh_data,
+                Vec3::ZERO,
+                Vec3::ONE,
+                merge_mode,
+                &palette,
+            );
+            assert_eq!(mesh_data.colors.len(), mesh_data.vertices.len());
+
+            let top = (0..mesh_data.vertices.len())
+                .filter(|&i| mesh_data.normals[i] == Vec3::Y)
+                .collect::<Vec<_>>();
+            let colors = top
+                .iter()
+                .map(|&i| (mesh_data.vertices[i].x, mesh_data.colors[i]))
+                .collect::<Vec<_>>();
+
+            (top.len() / 4, colors)
+        };
+
+        // every quad in the color of its value
+        let (quads, colors) = top_quads(MergeMode::PerValue);
+        assert_eq!(quads, 2);
+        for quad in colors.chunks(4) {
+            let min_x = quad.iter().map(|(x, _)| *x).fold(f32::MAX, f32::min);
+            let expected = palette[if min_x == 0.0 { 1 } else { 2 }];
+            assert!(quad.iter().all(|(_, color)| *color == expected));
+        }
+
+        // one quad, the vertices at either end take the color of their voxel
+        let (quads, colors) = top_quads(MergeMode::MergeAllSolid);
+        assert_eq!(quads, 1);
+        for (x, color) in colors {
+            assert_eq!(color, palette[if x == 0.0 { 1 } else { 2 }]);
+        }
+
+        // no palette, no colors
+        let mut mesh_data = MeshData::default();
+        generate_greedy_mesh_arrays_dense(
+            &voxels,
+            size,
+            &mut mesh_data,
+            Vec3::ZERO,
+            Vec3::ONE,
+            MergeMode::MergeAllSolid,
+            &[],
+        );
+        assert_eq!(mesh_data.vertices.len(), 6 * 4);
+        assert!(mesh_data.colors.is_empty());
+
+        // colors stay in step with the vertices when meshing into a colored mesh
+        let mut colored = MeshData::default();
+        generate_greedy_mesh_arrays_dense(
+            &voxels,
+            size,
+            &mut colored,
+            Vec3::ZERO,
+            Vec3::ONE,
+            MergeMode::MergeAllSolid,
+            &palette,
+        );
+        generate_greedy_mesh_arrays_dense(
+            &voxels,
+            size,
+            &mut colored,
+            Vec3::ZERO,
+            Vec3::ONE,
+            MergeMode::MergeAllSolid,
+            &[],
+        );
+        assert_eq!(colored.vertices.len(), 2 * 6 * 4);
+        assert_eq!(colored.colors.len(), colored.vertices.len());
+        assert!(
+            colored.colors[6 * 4..]
+                .iter()
+                .all(|color| *color == [1.0; 4])
+        );
+
+        // and uncolored vertices turn white once a palette is used
+        generate_greedy_mesh_arrays_dense(
+            &voxels,
+            size,
+            &mut mesh_data,
+            Vec3::ZERO,
+            Vec3::ONE,
+            MergeMode::MergeAllSolid,
+            &palette,
+        );
+        assert_eq!(mesh_data.colors.len(), mesh_data.vertices.len());
+        assert!(
+            mesh_data.colors[..6 * 4]
+                .iter()
+                .all(|color| *color == [1.0; 4])
+        );
+    }
+
+    #[test]
+    fn test_sweep_axis_changes_quad_count() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        // quads per sweep axis, then the quads and axis the best of mode picks
+        let quads = |chunk: &VoxChunk<i32>, interner: &VoxInterner<i32>| {
+            let mut builder = OccupancyDataBuilder::default();
+            generate_occupancy_masks(
+                interner,
+                &mut builder,
+                &chunk.get_root_id(),
+                MAX_DEPTH,
+                UVec3::ZERO,
+                #[cfg(feature = "trace_greedy_timings")]
+                &mut GreedyTimings::default(),
+            );
+            let occupancy_data = builder.build();
+
+            let meshes = SweepAxis::ALL.map(|sweep_axis| {
+                let mut mesh_data = MeshData::default();
+                generate_greedy_mesh_arrays(
+                    &occupancy_data,
+                    &mut mesh_data,
+                    MAX_DEPTH,
+                    Vec3::ZERO,
+                    Vec3::ONE,
+                    false,
+                    sweep_axis,
+                    #[cfg(feature = "trace_greedy_timings")]
+                    &mut GreedyTimings::default(),
+                );
+                mesh_data
+            });
+
+            // the same surface, only cut differently
+            let area = |mesh_data: &MeshData| {
+                mesh_data
+                    .indices
+                    .chunks(3)
+                    .map(|triangle| {
+                        let [a, b, c] = [0, 1, 2].map(|i| mesh_data.vertices[triangle[i] as usize]);
+                        (b - a).cross(c - a).length() / 2.0
+                    })
+                    .sum::<f32>()
+            };
+            assert!(
+                meshes
+                    .iter()
+                    .all(|mesh_data| area(mesh_data) == area(&meshes[0]))
+            );
+
+            // appended after what the mesh already holds
+            let mut best = MeshData::default();
+            best.vertices.extend(&meshes[0].vertices);
+            best.normals.extend(&meshes[0].normals);
+            best.indices.extend(&meshes[0].indices);
+            let best_axis = generate_greedy_mesh_arrays_best_axis(
+                &occupancy_data,
+                &mut best,
+                MAX_DEPTH,
+                Vec3::ZERO,
+                Vec3::ONE,
+                false,
+                #[cfg(feature = "trace_greedy_timings")]
+                &mut GreedyTimings::default(),
+            );
+            assert_eq!(
+                best.vertices[..meshes[0].vertices.len()],
+                meshes[0].vertices
+            );
+            assert!(
+                best.indices[meshes[0].indices.len()..]
+                    .iter()
+                    .all(|&index| index as usize >= meshes[0].vertices.len())
+            );
+
+            (
+                meshes
+                    .each_ref()
+                    .map(|mesh_data| mesh_data.triangle_count() / 2),
+                best.triangle_count() / 2 - meshes[0].triangle_count() / 2,
+                best_axis,
+            )
+        };
+
+        // a T standing in the XY plane, its front and back take three quads when merged along
+        // x first and two along y
+        for x in 0..3 {
+            chunk.set(&mut interner, IVec3::new(x, 2, 0), 1);
+        }
+        for y in 0..2 {
+            chunk.set(&mut interner, IVec3::new(1, y, 0), 1);
+        }
+
+        assert_eq!(quads(&chunk, &interner), ([14, 12, 14], 12, SweepAxis::Y));
+
+        // an L splits into the same two rectangles whichever way it is swept, ties keep x
+        chunk.clear(&mut interner);
+        for x in 0..3 {
+            chunk.set(&mut interner, IVec3::new(x, 0, 0), 1);
+        }
+        for y in 1..3 {
+            chunk.set(&mut interner, IVec3::new(0, y, 0), 1);
+        }
+
+        assert_eq!(quads(&chunk, &interner), ([10, 10, 10], 10, SweepAxis::X));
+
+        chunk.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
+    }
+}
@@ -99,31 +99,7 @@ pub fn generate_sphere_batch(batch: &mut Batch<i32>, center: IVec3, radius: i32,
     #[cfg(feature = "tracy")]
     let _span = tracy_client::span!("generate_sphere_batch");
 
-    let (cx, cy, cz) = (center.x, center.y, center.z);
-    let radius_squared = radius * radius;
-
-    let voxels_per_axis = batch.voxels_per_axis(Lod::new(0)) as i32;
-
-    let mut position = IVec3::ZERO;
-
-    for y in 0..voxels_per_axis {
-        position.y = y;
-        for z in 0..voxels_per_axis {
-            position.z = z;
-            for x in 0..voxels_per_axis {
-                let dx = x - cx;
-                let dy = y - cy;
-                let dz = z - cz;
-
-                let distance_squared = dx * dx + dy * dy + dz * dz;
-
-                if distance_squared <= radius_squared {
-                    position.x = x;
-                    batch.just_set(position, value);
-                }
-            }
-        }
-    }
+    batch.stamp_sphere(center, radius, value);
 }
 
 pub fn generate_checkerboard<T: VoxOpsConfig>(tree: &T) -> Batch<i32> {
@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+
+use glam::IVec3;
+
+use crate::{
+    Batch, Lod, VoxInterner, VoxelTrait,
+    spatial::{VoxOpsBatch, VoxOpsConfig, VoxOpsRead, VoxTree},
+    utils::common::decode_child_index_path,
+};
+
+// Batch applied to the tree and the batch restoring the voxels it touched
+struct Edit<T: VoxelTrait> {
+    batch: Batch<T>,
+    inverse: Batch<T>,
+}
+
+/// Undo and redo history of the batches applied to a [`VoxTree`].
+///
+/// Every applied batch is kept with its inverse, the previous values of the voxels it touched
+/// read from the tree right before it was applied. A batch with a fill touches every voxel, so
+/// its inverse clears the tree and sets all voxels the tree held. Only the last `depth` edits
+/// are kept, older ones are dropped as new ones are applied, and applying a new batch drops
+/// the edits that were undone.
+///
+/// Records hold voxel values rather than interner nodes, so they keep no references and stay
+/// valid however the shared nodes are released or reclaimed in the meantime. The history
+/// assumes the tree is only changed through it, edits made around it are not undone.
+///
+/// # Example
+///
+/// ```rust
+/// use glam::IVec3;
+/// use voxelis::{
+///     Batch, MaxDepth, VoxInterner,
+///     spatial::{VoxOpsRead, VoxTree},
+///     world::EditHistory,
+/// };
+///
+/// let mut interner = VoxInterner::<u8>::with_memory_budget(1024 * 1024);
+/// let mut tree = VoxTree::new(MaxDepth::new(3));
+/// let mut history = EditHistory::new(16);
+///
+/// let mut batch = Batch::new(MaxDepth::new(3));
+/// batch.just_set(IVec3::ONE, 1);
+/// history.apply(&mut tree, &mut interner, batch);
+///
+/// assert!(history.undo(&mut tree, &mut interner));
+/// assert_eq!(tree.get(&interner, IVec3::ONE), None);
+///
+/// assert!(history.redo(&mut tree, &mut interner));
+/// assert_eq!(tree.get(&interner, IVec3::ONE), Some(1));
+/// ```
+pub struct EditHistory<T: VoxelTrait> {
+    depth: usize,
+    // oldest edit first
+    undo: VecDeque<Edit<T>>,
+    // last undone edit last
+    redo: Vec<Edit<T>>,
+}
+
+impl<T: VoxelTrait> EditHistory<T> {
+    /// Creates an empty history keeping at most `depth` edits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is zero.
+    #[must_use]
+    pub fn new(depth: usize) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("EditHistory::new");
+
+        assert!(depth > 0, "History depth must be greater than zero");
+
+        Self {
+            depth,
+            undo: VecDeque::with_capacity(depth),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Returns the maximum number of edits kept.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the number of edits that can be undone.
+    #[must_use]
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Returns the number of undone edits that can be redone.
+    #[must_use]
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Applies `batch` to `tree` and records it, dropping the oldest edit once the history is
+    /// full and every undone edit.
+    ///
+    /// Returns `true` if the tree changed, a batch that changes nothing is not recorded.
+    pub fn apply(
+        &mut self,
+        tree: &mut VoxTree<T>,
+        interner: &mut VoxInterner<T>,
+        batch: Batch<T>,
+    ) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("EditHistory::apply");
+
+        let inverse = capture_inverse(tree, interner, &batch);
+
+        if !tree.apply_batch(interner, &batch) {
+            return false;
+        }
+
+        self.redo.clear();
+
+        if self.undo.len() == self.depth {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(Edit { batch, inverse });
+
+        true
+    }
+
+    /// Restores the voxels changed by the last edit, returns `false` if there is nothing to
+    /// undo.
+    pub fn undo(&mut self, tree: &mut VoxTree<T>, interner: &mut VoxInterner<T>) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("EditHistory::undo");
+
+        let Some(edit) = self.undo.pop_back() else {
+            return false;
+        };
+
+        tree.apply_batch(interner, &edit.inverse);
+        self.redo.push(edit);
+
+        true
+    }
+
+    /// Applies the last undone edit again, returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, tree: &mut VoxTree<T>, interner: &mut VoxInterner<T>) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("EditHistory::redo");
+
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+
+        // undo restored the voxels the inverse was read from, so it is still valid
+        tree.apply_batch(interner, &edit.batch);
+        self.undo.push_back(edit);
+
+        true
+    }
+
+    /// Drops every recorded edit.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("EditHistory::clear");
+
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+// Returns the batch restoring the voxels of `tree` touched by `batch`
+fn capture_inverse<T: VoxelTrait>(
+    tree: &VoxTree<T>,
+    interner: &VoxInterner<T>,
+    batch: &Batch<T>,
+) -> Batch<T> {
+    let max_depth = tree.max_depth(Lod::new(0));
+    let mut inverse = Batch::new(max_depth);
+
+    if batch.to_fill().is_some() {
+        let size = tree.voxels_per_axis(Lod::new(0)) as i32;
+        let previous = tree.extract_region(interner, IVec3::ZERO, IVec3::splat(size - 1));
+
+        inverse.just_fill(T::default());
+
+        for (path_index, (set_mask, _)) in previous.masks().iter().enumerate() {
+            let mut bits = *set_mask;
+            while bits != 0 {
+                let index = bits.trailing_zeros() as usize;
+                bits &= !(1 << index);
+
+                let position = decode_child_index_path(((path_index << 3) | index) as u32);
+                inverse.just_set(position, previous.values()[path_index][index]);
+            }
+        }
+
+        return inverse;
+    }
+
+    for (path_index, (set_mask, clear_mask)) in batch.masks().iter().enumerate() {
+        let mut bits = set_mask | clear_mask;
+        while bits != 0 {
+            let index = bits.trailing_zeros() as usize;
+            bits &= !(1 << index);
+
+            let position = decode_child_index_path(((path_index << 3) | index) as u32);
+            let previous = tree.get(interner, position).unwrap_or_default();
+            inverse.just_set(position, previous);
+        }
+    }
+
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MaxDepth, spatial::VoxOpsBulkWrite, utils::common::to_vec};
+
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let max_depth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut tree = VoxTree::new(max_depth);
+        let mut history = EditHistory::new(3);
+
+        let snapshot = |tree: &VoxTree<i32>, interner: &VoxInterner<i32>| {
+            to_vec(interner, &tree.get_root_id(), max_depth)
+        };
+
+        let mut states = vec![snapshot(&tree, &interner)];
+
+        let mut sphere = Batch::new(max_depth);
+        sphere.stamp_sphere(IVec3::splat(4), 3, 1);
+        assert!(history.apply(&mut tree, &mut interner, sphere));
+        states.push(snapshot(&tree, &interner));
+
+        let mut edit = Batch::new(max_depth);
+        edit.just_set(IVec3::splat(4), 2);
+        edit.just_set(IVec3::new(4, 4, 7), 0);
+        edit.just_set(IVec3::new(0, 0, 0), 3);
+        assert!(history.apply(&mut tree, &mut interner, edit));
+        states.push(snapshot(&tree, &interner));
+
+        let mut fill = Batch::new(max_depth);
+        fill.just_fill(5);
+        fill.just_set(IVec3::new(1, 2, 3), 0);
+        assert!(history.apply(&mut tree, &mut interner, fill));
+        states.push(snapshot(&tree, &interner));
+        assert_eq!(tree.get(&interner, IVec3::splat(4)), Some(5));
+
+        // nothing changes, nothing is recorded
+        let mut same = Batch::new(max_depth);
+        same.just_set(IVec3::ZERO, 5);
+        assert!(!history.apply(&mut tree, &mut interner, same));
+        assert_eq!(history.undo_len(), 3);
+
+        for expected in states[..3].iter().rev() {
+            assert!(history.undo(&mut tree, &mut interner));
+            assert_eq!(&snapshot(&tree, &interner), expected);
+        }
+        assert!(!history.undo(&mut tree, &mut interner));
+        assert_eq!(tree.get(&interner, IVec3::splat(4)), None);
+
+        for expected in &states[1..] {
+            assert!(history.redo(&mut tree, &mut interner));
+            assert_eq!(&snapshot(&tree, &interner), expected);
+        }
+        assert!(!history.redo(&mut tree, &mut interner));
+
+        // a new edit drops the undone ones and the oldest one beyond the depth
+        history.undo(&mut tree, &mut interner);
+        let mut point = Batch::new(max_depth);
+        point.just_set(IVec3::new(7, 7, 7), 9);
+        assert!(history.apply(&mut tree, &mut interner, point));
+        assert_eq!(history.redo_len(), 0);
+        assert_eq!(history.undo_len(), 3);
+        assert_eq!(tree.get(&interner, IVec3::new(7, 7, 7)), Some(9));
+        assert_eq!(tree.get(&interner, IVec3::splat(4)), Some(2));
+
+        let mut point = Batch::new(max_depth);
+        point.just_set(IVec3::new(7, 0, 7), 9);
+        assert!(history.apply(&mut tree, &mut interner, point));
+        assert_eq!(history.undo_len(), 3);
+
+        while history.undo(&mut tree, &mut interner) {}
+        assert_eq!(snapshot(&tree, &interner), states[1]);
+
+        history.clear();
+        tree.clear(&mut interner);
+
+        assert!(interner.patterns_empty());
+    }
+}
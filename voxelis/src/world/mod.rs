@@ -1,11 +1,18 @@
-mod voxchunk;
+mod edit_history;
+mod palette;
+pub(crate) mod voxchunk;
 mod voxworld;
 
+pub use edit_history::EditHistory;
+pub use palette::{MAX_COLOR_TABLE_LEN, Palette, PaletteEntry};
 pub use voxchunk::VoxChunk;
-pub use voxworld::VoxWorld;
+pub use voxworld::{VoxWorld, WorldRayHit};
 
 #[cfg(feature = "vtm")]
-mod voxmodel;
+pub(crate) mod voxmodel;
 
 #[cfg(feature = "vtm")]
-pub use voxmodel::VoxModel;
+pub use voxmodel::{
+    BuildError, CHUNK_FACE_NEIGHBORS, CHUNK_NEIGHBORS_26, DedupReport, MAX_UNIFIED_MESH_VOXELS,
+    RegionClass, UnifiedMeshTooLarge, VoxModel, VoxModelBuilder,
+};
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use crate::VoxelTrait;
+
+/// Most entries [`Palette::color_table`] returns, ids past it are left out.
+pub const MAX_COLOR_TABLE_LEN: usize = 1 << 16;
+
+/// How one voxel value is shown, see [`Palette`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteEntry {
+    /// RGBA color, each channel in `0.0..=1.0`.
+    pub color: [f32; 4],
+    pub name: String,
+    /// Application defined bits, e.g. to mark emissive or transparent materials. Voxelis
+    /// stores them but does not interpret them.
+    pub flags: u32,
+}
+
+impl PaletteEntry {
+    /// Creates an entry without flags.
+    pub fn new(name: impl Into<String>, color: [f32; 4]) -> Self {
+        Self {
+            color,
+            name: name.into(),
+            flags: 0,
+        }
+    }
+}
+
+/// Colors, names and flags of voxel values, kept apart from the values themselves.
+///
+/// Voxel values usually double as material ids, the palette says what they look like so
+/// meshers, exporters and viewers agree on it without hardcoding colors. Values without an
+/// entry are still valid voxels, they just have no color. Entries are kept sorted by value.
+///
+/// # Example
+///
+/// ```rust
+/// use voxelis::world::{Palette, PaletteEntry};
+///
+/// let mut palette = Palette::<u8>::new();
+/// palette.insert(1, PaletteEntry::new("stone", [0.5, 0.5, 0.5, 1.0]));
+///
+/// assert_eq!(palette.color(1), Some([0.5, 0.5, 0.5, 1.0]));
+/// assert_eq!(palette.color(2), None);
+/// assert_eq!(palette.color_table([0.0; 4])[1], [0.5, 0.5, 0.5, 1.0]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette<T: VoxelTrait> {
+    entries: BTreeMap<T, PaletteEntry>,
+}
+
+impl<T: VoxelTrait> Default for Palette<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: VoxelTrait> Palette<T> {
+    /// Creates an empty palette.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the entry of `value`, returning the entry it replaces.
+    pub fn insert(&mut self, value: T, entry: PaletteEntry) -> Option<PaletteEntry> {
+        self.entries.insert(value, entry)
+    }
+
+    /// Removes the entry of `value`, returning it.
+    pub fn remove(&mut self, value: T) -> Option<PaletteEntry> {
+        self.entries.remove(&value)
+    }
+
+    #[must_use]
+    pub fn get(&self, value: T) -> Option<&PaletteEntry> {
+        self.entries.get(&value)
+    }
+
+    #[must_use]
+    pub fn color(&self, value: T) -> Option<[f32; 4]> {
+        self.entries.get(&value).map(|entry| entry.color)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Iterates over the entries sorted by value.
+    pub fn iter(&self) -> impl Iterator<Item = (T, &PaletteEntry)> {
+        self.entries.iter().map(|(value, entry)| (*value, entry))
+    }
+
+    /// Returns the colors indexed by [`VoxelTrait::material_id`], the layout the greedy
+    /// dense mesher takes, with `default_color` for ids without an entry. The table ends with
+    /// the highest id in the palette, so it is meant for palettes of small ids.
+    ///
+    /// Entries with ids of [`MAX_COLOR_TABLE_LEN`] or more are left out, which includes
+    /// negative values, their ids wrap around. Look those up with [`Palette::color`].
+    #[must_use]
+    pub fn color_table(&self, default_color: [f32; 4]) -> Vec<[f32; 4]> {
+        let ids = || {
+            self.entries
+                .iter()
+                .map(|(value, entry)| (value.material_id(), entry))
+                .filter(|(id, _)| *id < MAX_COLOR_TABLE_LEN)
+        };
+
+        let len = ids().map(|(id, _)| id + 1).max().unwrap_or(0);
+
+        let mut table = vec![default_color; len];
+        for (id, entry) in ids() {
+            table[id] = entry.color;
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_color_table() {
+        let mut palette = Palette::<i32>::new();
+        assert!(palette.color_table([1.0; 4]).is_empty());
+
+        palette.insert(3, PaletteEntry::new("sand", [0.9, 0.8, 0.5, 1.0]));
+        palette.insert(1, PaletteEntry::new("dirt", [0.4, 0.3, 0.2, 1.0]));
+        let replaced = palette.insert(3, PaletteEntry::new("gold", [1.0, 0.8, 0.0, 1.0]));
+        assert_eq!(replaced.unwrap().name, "sand");
+
+        assert_eq!(palette.len(), 2);
+        assert_eq!(
+            palette.iter().map(|(value, _)| value).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(
+            palette.color_table([1.0; 4]),
+            vec![
+                [1.0; 4],
+                [0.4, 0.3, 0.2, 1.0],
+                [1.0; 4],
+                [1.0, 0.8, 0.0, 1.0]
+            ]
+        );
+
+        assert_eq!(palette.remove(1).unwrap().name, "dirt");
+        assert_eq!(palette.color(1), None);
+
+        // negative and huge ids stay out of the table
+        palette.insert(-1, PaletteEntry::new("void", [0.0, 0.0, 0.0, 1.0]));
+        palette.insert(i32::MAX, PaletteEntry::new("edge", [1.0, 0.0, 1.0, 1.0]));
+        assert_eq!(palette.color_table([1.0; 4]).len(), 4);
+        assert_eq!(palette.color(-1), Some([0.0, 0.0, 0.0, 1.0]));
+    }
+}
@@ -1,31 +1,37 @@
+use std::hash::{Hash, Hasher};
+
 #[cfg(feature = "vtm")]
-use std::io::{BufReader, Read, Write};
+use std::io::{Read, Write};
 
 #[cfg(feature = "vtm")]
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-#[cfg(feature = "vtm")]
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHasher};
 
 use glam::{IVec3, UVec3, Vec3};
 use wide::f32x8;
 
 #[cfg(feature = "vtm")]
 use crate::io::{
-    consts::VTC_MAGIC,
-    varint::{decode_varint_u32_from_reader, encode_varint},
+    VtmError,
+    consts::{VTC_MAGIC, VTM_VERSION_OWNERS},
+    varint::{decode_varint_u32_from_reader, encode_varint, encode_varint_u32},
 };
 
 use crate::{
     spatial::{
-        VoxOpsBatch, VoxOpsBulkWrite, VoxOpsChunkConfig, VoxOpsConfig, VoxOpsDirty, VoxOpsMesh,
-        VoxOpsRead, VoxOpsSpatial3D, VoxOpsState, VoxOpsWrite, VoxTree,
+        RayHit, VoxOpsBatch, VoxOpsBulkWrite, VoxOpsChunkConfig, VoxOpsConfig, VoxOpsDirty,
+        VoxOpsMesh, VoxOpsRead, VoxOpsSpatial3D, VoxOpsState, VoxOpsWrite, VoxTree,
     },
     utils::{
-        common::to_vec,
-        mesh::{self, MeshData, OccupancyDataBuilder},
+        common::{
+            child_index2, count_exposed_faces, count_voxel_values, is_empty_at_depth, to_vec,
+        },
+        mesh::{self, DebugTint, MeshData, MeshTopology, OccupancyDataBuilder},
     },
 };
 
+#[cfg(feature = "vtm")]
+use crate::utils::common::decode_child_index_path;
 #[cfg(feature = "trace_greedy_timings")]
 use crate::utils::mesh::GreedyTimings;
 
@@ -34,11 +40,14 @@ use crate::{Batch, BlockId, Lod, MaxDepth, VoxInterner, VoxelTrait};
 pub struct VoxChunk<T: VoxelTrait> {
     data: VoxTree<T>,
     position: IVec3,
-    chunk_size: f32,
+    chunk_size: Vec3,
+    // Owner ids of occupied voxels, kept apart from the voxel values. Entries are dropped
+    // whenever their voxel becomes empty.
+    owners: FxHashMap<IVec3, u32>,
 }
 
 impl<T: VoxelTrait> VoxChunk<T> {
-    pub fn with_position(chunk_size: f32, max_depth: MaxDepth, x: i32, y: i32, z: i32) -> Self {
+    pub fn with_position(chunk_size: Vec3, max_depth: MaxDepth, x: i32, y: i32, z: i32) -> Self {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("VoxChunk::with_position");
 
@@ -46,6 +55,7 @@ impl<T: VoxelTrait> VoxChunk<T> {
             data: VoxTree::new(max_depth),
             position: IVec3::new(x, y, z),
             chunk_size,
+            owners: FxHashMap::default(),
         }
     }
 
@@ -56,6 +66,346 @@ impl<T: VoxelTrait> VoxChunk<T> {
     pub fn get_root_id(&self) -> BlockId {
         self.data.get_root_id()
     }
+
+    /// Returns a hash of the chunk content, for finding changed chunks when saving or syncing.
+    ///
+    /// It hashes the root id and the depth, so it costs the same for any content. Identical
+    /// content is deduplicated to the same root, so chunks with equal voxels hash equal.
+    /// Root ids are only meaningful within one interner: hashes can only be compared between
+    /// chunks sharing an interner, and they change when [`VoxInterner::compact`] renumbers
+    /// the nodes. Owners are not part of the hash.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.data.get_root_id().hash(&mut hasher);
+        self.data.max_depth(Lod::new(0)).max().hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Associates an owner id with an occupied voxel, returns false if the voxel is empty.
+    pub fn set_owner(&mut self, interner: &VoxInterner<T>, position: IVec3, owner: u32) -> bool {
+        if self.data.get(interner, position).is_none() {
+            return false;
+        }
+
+        self.owners.insert(position, owner);
+
+        true
+    }
+
+    pub fn owner(&self, position: IVec3) -> Option<u32> {
+        self.owners.get(&position).copied()
+    }
+
+    pub fn clear_owner(&mut self, position: IVec3) -> Option<u32> {
+        self.owners.remove(&position)
+    }
+
+    pub fn owners(&self) -> &FxHashMap<IVec3, u32> {
+        &self.owners
+    }
+
+    // Drops owner entries of voxels that became empty.
+    fn retain_occupied_owners(&mut self, interner: &VoxInterner<T>) {
+        if self.owners.is_empty() {
+            return;
+        }
+
+        self.owners
+            .retain(|position, _| self.data.get(interner, *position).is_some());
+    }
+
+    // Owners belong to the previous content, so they are dropped.
+    #[cfg(any(feature = "vtm", feature = "serde"))]
+    pub(crate) fn set_root_id(&mut self, interner: &mut VoxInterner<T>, root_id: BlockId) {
+        self.owners.clear();
+        self.data.set_root_id(interner, root_id);
+    }
+
+    // Restores owners read along with the root, after `set_root_id`.
+    #[cfg(feature = "vtm")]
+    pub(crate) fn set_owners(&mut self, owners: FxHashMap<IVec3, u32>) {
+        self.owners = owners;
+    }
+
+    /// Replaces the root with its new id after `VoxInterner::compact`.
+    pub fn remap_root(&mut self, remap: &FxHashMap<BlockId, BlockId>) {
+        self.data.remap_root(remap);
+    }
+
+    pub fn generate_debug_mesh_arrays(
+        &self,
+        interner: &VoxInterner<T>,
+        mesh_data: &mut MeshData,
+        offset: Vec3,
+        lod: Lod,
+        tint: DebugTint,
+    ) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::generate_debug_mesh_arrays");
+
+        mesh::generate_debug_tint_mesh_arrays(
+            interner,
+            mesh_data,
+            &self.data.get_root_id(),
+            self.max_depth(lod),
+            offset,
+            self.voxel_size(lod),
+            self.position,
+            tint,
+        );
+    }
+
+    /// Line list of the bounds of every branch node at `lod`, colored by depth, in chunk local
+    /// coordinates.
+    pub fn generate_octree_wireframe(&self, interner: &VoxInterner<T>, lod: Lod) -> MeshData {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::generate_octree_wireframe");
+
+        let mut mesh_data = MeshData {
+            topology: MeshTopology::LineList,
+            ..Default::default()
+        };
+
+        mesh::generate_octree_wireframe_arrays(
+            interner,
+            &mut mesh_data,
+            &self.data.get_root_id(),
+            self.max_depth(lod),
+            Vec3::ZERO,
+            self.voxel_size(lod),
+        );
+
+        mesh_data
+    }
+
+    /// Smooth surface around the voxels with a value of at least `iso` at `lod`, in chunk local
+    /// coordinates, see `mesh::generate_marching_cubes_arrays`. Neighboring chunks are not
+    /// sampled yet, so the surface is closed at the chunk border.
+    pub fn generate_marching_cubes(&self, interner: &VoxInterner<T>, iso: T, lod: Lod) -> MeshData {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::generate_marching_cubes");
+
+        let mut mesh_data = MeshData::default();
+
+        mesh::generate_marching_cubes_arrays(
+            interner,
+            &mut mesh_data,
+            &self.data.get_root_id(),
+            self.max_depth(lod),
+            Vec3::ZERO,
+            self.voxel_size(lod),
+            iso,
+        );
+
+        mesh_data
+    }
+
+    /// True if the chunk has no visible voxel at `lod`, even though finer levels may hold detail.
+    pub fn is_empty_at_lod(&self, interner: &VoxInterner<T>, lod: Lod) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::is_empty_at_lod");
+
+        is_empty_at_depth(interner, &self.data.get_root_id(), self.max_depth(lod))
+    }
+
+    /// Most common set value of the cell at `coarse_pos` in the `lod` grid, weighted by the
+    /// number of full resolution voxels holding it, e.g. to pick the material of a coarse LOD
+    /// voxel. Returns `None` when more than half of the cell is empty, ties go to the smaller
+    /// value.
+    pub fn sample_majority(
+        &self,
+        interner: &VoxInterner<T>,
+        coarse_pos: IVec3,
+        lod: Lod,
+    ) -> Option<T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::sample_majority");
+
+        let max_depth = self.max_depth(Lod::new(0)).max() as usize;
+        let cell_depth = self.max_depth(lod).max() as usize;
+
+        assert!(
+            coarse_pos.cmpge(IVec3::ZERO).all()
+                && coarse_pos.cmplt(IVec3::splat(1 << cell_depth)).all(),
+            "Position {coarse_pos} outside of the chunk at {lod:?}"
+        );
+
+        let mut node_id = self.data.get_root_id();
+        let mut depth = 0;
+
+        while !node_id.is_empty() && node_id.is_branch() && depth < cell_depth {
+            let index = child_index2(&coarse_pos, depth, cell_depth);
+            node_id = interner.get_child_id(&node_id, index);
+            depth += 1;
+        }
+
+        let mut counts = FxHashMap::default();
+        count_voxel_values(
+            interner,
+            node_id,
+            cell_depth as u32,
+            max_depth as u32,
+            &mut counts,
+        );
+
+        let cell_volume = 1u64 << (3 * (max_depth - cell_depth));
+        let occupied: u64 = counts.values().sum();
+        if occupied * 2 < cell_volume {
+            return None;
+        }
+
+        counts
+            .into_iter()
+            .max_by_key(|&(value, count)| (count, std::cmp::Reverse(value)))
+            .map(|(value, _)| value)
+    }
+
+    /// Number of set voxels at full resolution, see `VoxTree::voxel_count`.
+    pub fn voxel_count(&self, interner: &VoxInterner<T>) -> u64 {
+        self.data.voxel_count(interner)
+    }
+
+    /// Encodes the voxels as a flat run-length stream, independent of the DAG layout, e.g. for
+    /// debugging or to hand a chunk to other tools.
+    ///
+    /// The stream is a sequence of `(value, run)` pairs covering all voxels of the chunk in
+    /// Morton order, see [`crate::utils::common::encode_child_index_path`]. The value is
+    /// written as the big endian bytes of `T`, empty voxels as `T::default()`, followed by the
+    /// run length as a varint. Neighboring runs always hold different values.
+    ///
+    /// Position, size and owners of the chunk are not part of the stream.
+    #[cfg(feature = "vtm")]
+    pub fn to_rle(&self, interner: &VoxInterner<T>) -> Vec<u8> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::to_rle");
+
+        let mut runs = Vec::new();
+        collect_rle_runs(
+            interner,
+            self.data.get_root_id(),
+            self.max_depth(Lod::new(0)).max(),
+            &mut runs,
+        );
+
+        let mut bytes = Vec::new();
+        for (value, run) in runs {
+            value.write_as_be(&mut bytes).unwrap();
+            bytes.extend(encode_varint_u32(run));
+        }
+
+        bytes
+    }
+
+    /// Decodes a stream written by [`VoxChunk::to_rle`] into a new chunk at the origin.
+    ///
+    /// Fails if the stream is cut short, covers more voxels than a chunk of `max_depth` holds
+    /// or has bytes left after the last voxel.
+    #[cfg(feature = "vtm")]
+    pub fn from_rle(
+        bytes: &[u8],
+        interner: &mut VoxInterner<T>,
+        chunk_size: Vec3,
+        max_depth: MaxDepth,
+    ) -> Result<Self, VtmError> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::from_rle");
+
+        let invalid = |message: &str| {
+            VtmError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ))
+        };
+
+        let total = 1u64 << (3 * max_depth.max() as u32);
+
+        let mut reader = bytes;
+        let mut batch = Batch::new(max_depth);
+        let mut start = 0u64;
+
+        while start < total {
+            let value = T::read_from_be(&mut reader)?;
+            let run = decode_varint_u32_from_reader(&mut reader)
+                .ok_or_else(|| VtmError::Io(std::io::ErrorKind::UnexpectedEof.into()))?
+                as u64;
+
+            if run == 0 || start + run > total {
+                return Err(invalid("invalid RLE run length"));
+            }
+
+            if !value.voxel_eq(&T::default()) {
+                for index in start..start + run {
+                    batch.just_set(decode_child_index_path(index as u32), value);
+                }
+            }
+
+            start += run;
+        }
+
+        if !reader.is_empty() {
+            return Err(invalid("trailing bytes after the last RLE run"));
+        }
+
+        let mut chunk = Self::with_position(chunk_size, max_depth, 0, 0, 0);
+        chunk.data.apply_batch(interner, &batch);
+
+        Ok(chunk)
+    }
+
+    /// Copies the set voxels of the inclusive `min..=max` box, see `VoxTree::extract_region`.
+    pub fn extract_region(&self, interner: &VoxInterner<T>, min: IVec3, max: IVec3) -> Batch<T> {
+        self.data.extract_region(interner, min, max)
+    }
+
+    /// Casts a ray given in voxel units of the chunk, see `VoxTree::raycast`.
+    pub fn raycast(
+        &self,
+        interner: &VoxInterner<T>,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+    ) -> Option<RayHit<T>> {
+        self.data.raycast(interner, origin, dir, max_dist)
+    }
+
+    /// Triangle count the naive mesher would emit at `lod`, found by walking the DAG without
+    /// building any geometry. The greedy mesher merges faces, so this is its upper bound.
+    pub fn estimate_triangles(&self, interner: &VoxInterner<T>, lod: Lod) -> u64 {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::estimate_triangles");
+
+        // the naive mesher emits a single box for a uniform chunk
+        if self.data.is_leaf() {
+            return 6 * 2;
+        }
+
+        count_exposed_faces(interner, &self.data.get_root_id(), self.max_depth(lod)) * 2
+    }
+
+    /// Greedy meshes the chunk at the finest LOD, up to `max_lod`, whose triangle estimate fits
+    /// in `max_triangles`. Falls back to `max_lod` when no level fits, so the cost stays bounded.
+    pub fn generate_mesh_capped(
+        &self,
+        interner: &VoxInterner<T>,
+        max_triangles: u64,
+        max_lod: Lod,
+    ) -> (MeshData, Lod) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxChunk::generate_mesh_capped");
+
+        let max_lod = max_lod.lod().min(self.max_depth(Lod::new(0)).max());
+
+        let lod = (0..=max_lod)
+            .map(Lod::new)
+            .find(|lod| self.estimate_triangles(interner, *lod) <= max_triangles)
+            .unwrap_or(Lod::new(max_lod));
+
+        let mut mesh_data = MeshData::default();
+        self.generate_greedy_mesh_arrays(interner, &mut mesh_data, Vec3::ZERO, lod);
+
+        (mesh_data, lod)
+    }
 }
 
 impl<T: VoxelTrait> VoxOpsRead<T> for VoxChunk<T> {
@@ -68,6 +418,10 @@ impl<T: VoxelTrait> VoxOpsRead<T> for VoxChunk<T> {
 impl<T: VoxelTrait> VoxOpsWrite<T> for VoxChunk<T> {
     #[inline(always)]
     fn set(&mut self, interner: &mut VoxInterner<T>, position: IVec3, voxel: T) -> bool {
+        if !self.owners.is_empty() && voxel.voxel_eq(&T::default()) {
+            self.owners.remove(&position);
+        }
+
         self.data.set(interner, position, voxel)
     }
 }
@@ -75,11 +429,16 @@ impl<T: VoxelTrait> VoxOpsWrite<T> for VoxChunk<T> {
 impl<T: VoxelTrait> VoxOpsBulkWrite<T> for VoxChunk<T> {
     #[inline(always)]
     fn fill(&mut self, interner: &mut VoxInterner<T>, value: T) {
+        if value.voxel_eq(&T::default()) {
+            self.owners.clear();
+        }
+
         self.data.fill(interner, value)
     }
 
     #[inline(always)]
     fn clear(&mut self, interner: &mut VoxInterner<T>) {
+        self.owners.clear();
         self.data.clear(interner)
     }
 }
@@ -92,7 +451,10 @@ impl<T: VoxelTrait> VoxOpsBatch<T> for VoxChunk<T> {
 
     #[inline(always)]
     fn apply_batch(&mut self, interner: &mut VoxInterner<T>, batch: &Batch<T>) -> bool {
-        self.data.apply_batch(interner, batch)
+        let changed = self.data.apply_batch(interner, batch);
+        self.retain_occupied_owners(interner);
+
+        changed
     }
 }
 
@@ -144,12 +506,12 @@ impl<T: VoxelTrait> VoxOpsChunkConfig for VoxChunk<T> {
     }
 
     #[inline(always)]
-    fn chunk_size(&self) -> f32 {
+    fn chunk_size(&self) -> Vec3 {
         self.chunk_size
     }
 
     #[inline(always)]
-    fn voxel_size(&self, lod: Lod) -> f32 {
+    fn voxel_size(&self, lod: Lod) -> Vec3 {
         self.chunk_size / self.data.voxels_per_axis(lod) as f32
     }
 }
@@ -162,18 +524,17 @@ impl<T: VoxelTrait> VoxOpsSpatial3D for VoxChunk<T> {
 
     #[inline(always)]
     fn world_position_3d(&self) -> Vec3 {
-        self.position.as_vec3() * Vec3::splat(self.chunk_size)
+        self.position.as_vec3() * self.chunk_size
     }
 
     #[inline(always)]
     fn world_center_position_3d(&self) -> Vec3 {
-        let half_size = self.chunk_size / 2.0;
-        self.world_position_3d() + Vec3::splat(half_size)
+        self.world_position_3d() + self.chunk_size / 2.0
     }
 
     #[inline(always)]
     fn world_size_3d(&self) -> Vec3 {
-        Vec3::splat(self.chunk_size)
+        self.chunk_size
     }
 }
 
@@ -236,9 +597,7 @@ impl<T: VoxelTrait> VoxOpsMesh<T> for VoxChunk<T> {
 
         let max_depth = self.max_depth(lod);
         let voxels_per_axis = self.voxels_per_axis(lod);
-        let voxel_size = self.voxel_size(lod);
-
-        let voxel_size_vec3 = Vec3::splat(voxel_size);
+        let voxel_size_vec3 = self.voxel_size(lod);
         let shift_y = 1 << (2 * max_depth.as_usize());
         let shift_z = 1 << max_depth.as_usize();
 
@@ -279,22 +638,22 @@ impl<T: VoxelTrait> VoxOpsMesh<T> for VoxChunk<T> {
                 for x in 0..voxels_per_axis {
                     let index = base_index_z + x as usize;
 
-                    if unsafe { *data.get_unchecked(index) } == T::default() {
+                    if unsafe { data.get_unchecked(index) }.voxel_eq(&T::default()) {
                         continue;
                     }
 
                     let has_top = y + 1 >= voxels_per_axis
-                        || unsafe { *data.get_unchecked(index + shift_y) } == T::default();
-                    let has_bottom =
-                        y == 0 || unsafe { *data.get_unchecked(index - shift_y) } == T::default();
+                        || unsafe { data.get_unchecked(index + shift_y) }.voxel_eq(&T::default());
+                    let has_bottom = y == 0
+                        || unsafe { data.get_unchecked(index - shift_y) }.voxel_eq(&T::default());
                     let has_front = z + 1 >= voxels_per_axis
-                        || unsafe { *data.get_unchecked(index + shift_z) } == T::default();
-                    let has_back =
-                        z == 0 || unsafe { *data.get_unchecked(index - shift_z) } == T::default();
+                        || unsafe { data.get_unchecked(index + shift_z) }.voxel_eq(&T::default());
+                    let has_back = z == 0
+                        || unsafe { data.get_unchecked(index - shift_z) }.voxel_eq(&T::default());
                     let has_right = x + 1 >= voxels_per_axis
-                        || unsafe { *data.get_unchecked(index + 1) } == T::default();
+                        || unsafe { data.get_unchecked(index + 1) }.voxel_eq(&T::default());
                     let has_left =
-                        x == 0 || unsafe { *data.get_unchecked(index - 1) } == T::default();
+                        x == 0 || unsafe { data.get_unchecked(index - 1) }.voxel_eq(&T::default());
 
                     if !(has_top || has_bottom || has_left || has_right || has_back || has_front) {
                         continue;
@@ -372,12 +731,44 @@ impl<T: VoxelTrait> VoxOpsMesh<T> for VoxChunk<T> {
             max_depth,
             offset,
             voxel_size,
+            false,
+            mesh::SweepAxis::X,
             #[cfg(feature = "trace_greedy_timings")]
             &mut timings,
         );
     }
 }
 
+// Appends the runs of the node covering `8^depth` voxels in Morton order, merging them into
+// the last run while the value stays the same
+#[cfg(feature = "vtm")]
+fn collect_rle_runs<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    depth: u8,
+    runs: &mut Vec<(T, u32)>,
+) {
+    if !node_id.is_empty() && node_id.is_branch() {
+        for child_id in interner.get_children_ref(&node_id) {
+            collect_rle_runs(interner, *child_id, depth - 1, runs);
+        }
+
+        return;
+    }
+
+    let value = if node_id.is_empty() {
+        T::default()
+    } else {
+        *interner.get_value(&node_id)
+    };
+    let count = 1u32 << (3 * depth as u32);
+
+    match runs.last_mut() {
+        Some((last, run)) if last.voxel_eq(&value) => *run += count,
+        _ => runs.push((value, count)),
+    }
+}
+
 #[cfg(feature = "vtm")]
 pub fn serialize_chunk<T: VoxelTrait>(
     chunk: &VoxChunk<T>,
@@ -402,39 +793,644 @@ pub fn serialize_chunk<T: VoxelTrait>(
     let new_id_bytes = encode_varint(new_id as usize);
 
     writer.write_all(&new_id_bytes).unwrap();
+
+    write_owners(&chunk.owners, &mut writer).unwrap();
+}
+
+/// Writes the owner count, then the position and owner of every entry as varints.
+#[cfg(feature = "vtm")]
+pub(crate) fn write_owners<W: Write>(
+    owners: &FxHashMap<IVec3, u32>,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    // sorted, so the same chunk always serializes to the same bytes
+    let mut owners = owners.iter().collect::<Vec<_>>();
+    owners.sort_by_key(|(position, _)| (position.y, position.z, position.x));
+
+    writer.write_all(&encode_varint_u32(owners.len() as u32))?;
+    for (position, owner) in owners {
+        for coord in position.to_array() {
+            writer.write_all(&encode_varint_u32(coord as u32))?;
+        }
+        writer.write_all(&encode_varint_u32(*owner))?;
+    }
+
+    Ok(())
 }
 
+/// Reads owners written by [`write_owners`].
 #[cfg(feature = "vtm")]
-pub fn deserialize_chunk<T: VoxelTrait>(
+pub(crate) fn read_owners<R: Read>(reader: &mut R) -> Result<FxHashMap<IVec3, u32>, VtmError> {
+    let eof = || VtmError::Io(std::io::ErrorKind::UnexpectedEof.into());
+
+    let owners_len = decode_varint_u32_from_reader(reader).ok_or_else(eof)?;
+
+    let mut owners = FxHashMap::default();
+    for _ in 0..owners_len {
+        let x = decode_varint_u32_from_reader(reader).ok_or_else(eof)? as i32;
+        let y = decode_varint_u32_from_reader(reader).ok_or_else(eof)? as i32;
+        let z = decode_varint_u32_from_reader(reader).ok_or_else(eof)? as i32;
+        let owner = decode_varint_u32_from_reader(reader).ok_or_else(eof)?;
+
+        owners.insert(IVec3::new(x, y, z), owner);
+    }
+
+    Ok(owners)
+}
+
+/// Serialized node ids mapped to the interned leaves and their values.
+#[cfg(feature = "vtm")]
+pub(crate) type LeafPatterns<T> = FxHashMap<u32, (BlockId, T)>;
+
+/// Serialized node ids mapped to the interned branches, their serialized children and
+/// LOD values.
+#[cfg(feature = "vtm")]
+pub(crate) type BranchPatterns<T> = FxHashMap<u32, (BlockId, [u32; 8], T)>;
+
+/// Reads a chunk record written by [`serialize_chunk`], laid out as in VTM `version`.
+#[cfg(feature = "vtm")]
+pub fn deserialize_chunk<T: VoxelTrait, R: Read>(
     interner: &mut VoxInterner<T>,
-    leaf_patterns: &FxHashMap<u32, (BlockId, T)>,
-    patterns: &FxHashMap<u32, (BlockId, [u32; 8], T)>,
-    reader: &mut BufReader<&[u8]>,
-    chunk_size: f32,
+    leaf_patterns: &LeafPatterns<T>,
+    patterns: &BranchPatterns<T>,
+    reader: &mut R,
+    chunk_size: Vec3,
     max_depth: MaxDepth,
-) -> VoxChunk<T> {
+    version: u16,
+) -> Result<VoxChunk<T>, VtmError> {
     #[cfg(feature = "tracy")]
     let _span = tracy_client::span!("deserialize_chunk");
 
+    let eof = || VtmError::Io(std::io::ErrorKind::UnexpectedEof.into());
+
     let mut magic = [0; VTC_MAGIC.len()];
-    reader.read_exact(&mut magic).unwrap();
-    assert_eq!(magic, VTC_MAGIC);
+    reader.read_exact(&mut magic)?;
+    if magic != VTC_MAGIC {
+        return Err(VtmError::InvalidMagic);
+    }
 
-    // println!("Magic: {:?}", std::str::from_utf8(&magic).unwrap());
+    let x = reader.read_i32::<BigEndian>()?;
+    let y = reader.read_i32::<BigEndian>()?;
+    let z = reader.read_i32::<BigEndian>()?;
 
-    let x = reader.read_i32::<BigEndian>().unwrap();
-    let y = reader.read_i32::<BigEndian>().unwrap();
-    let z = reader.read_i32::<BigEndian>().unwrap();
+    let root_id = decode_varint_u32_from_reader(reader).ok_or_else(eof)?;
+    let block_id = patterns
+        .get(&root_id)
+        .map(|(block_id, _, _)| *block_id)
+        .or_else(|| leaf_patterns.get(&root_id).map(|(block_id, _)| *block_id))
+        .ok_or(VtmError::InvalidNode(root_id))?;
 
+    // older records end with the root
+    let owners = if version >= VTM_VERSION_OWNERS {
+        read_owners(reader)?
+    } else {
+        FxHashMap::default()
+    };
+
+    // the record is fully read, so a malformed one never holds a reference
     let mut chunk = VoxChunk::with_position(chunk_size, max_depth, x, y, z);
+    chunk.data.set_root_id(interner, block_id);
+    chunk.owners = owners;
 
-    let root_id = decode_varint_u32_from_reader(reader).unwrap();
-    if let Some((block_id, _, _)) = patterns.get(&root_id) {
-        chunk.data.set_root_id(interner, *block_id);
-    } else {
-        let (block_id, _) = leaf_patterns.get(&root_id).unwrap();
-        chunk.data.set_root_id(interner, *block_id);
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TraversalDepth, utils::mesh::CoordinateSystem};
+
+    #[test]
+    fn test_debug_tint_by_depth() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        // a full 2x2x2 block collapses into a single leaf one level above max depth
+        for y in 0..2 {
+            for z in 0..2 {
+                for x in 0..2 {
+                    chunk.set(&mut interner, IVec3::new(x, y, z), 1);
+                }
+            }
+        }
+        // a lone voxel stays a leaf at max depth
+        chunk.set(&mut interner, IVec3::new(6, 6, 6), 1);
+
+        let mut mesh_data = MeshData::default();
+        chunk.generate_debug_mesh_arrays(
+            &interner,
+            &mut mesh_data,
+            Vec3::ZERO,
+            Lod::new(0),
+            DebugTint::ByDepth,
+        );
+
+        assert_eq!(mesh_data.vertices.len(), 2 * 6 * 4);
+        assert_eq!(mesh_data.colors.len(), mesh_data.vertices.len());
+
+        let block_color = mesh_data.colors[mesh_data
+            .vertices
+            .iter()
+            .position(|v| v.max_element() <= 2.0)
+            .unwrap()];
+        let voxel_color = mesh_data.colors[mesh_data
+            .vertices
+            .iter()
+            .position(|v| v.min_element() >= 6.0)
+            .unwrap()];
+
+        assert_ne!(block_color, voxel_color);
+        assert_eq!(Some(block_color), DebugTint::ByDepth.color(2, IVec3::ZERO));
+        assert_eq!(Some(voxel_color), DebugTint::ByDepth.color(3, IVec3::ZERO));
+    }
+
+    #[test]
+    fn test_debug_tint_none_writes_no_colors() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+        chunk.set(&mut interner, IVec3::new(1, 2, 3), 1);
+
+        let mut mesh_data = MeshData::default();
+        chunk.generate_debug_mesh_arrays(
+            &interner,
+            &mut mesh_data,
+            Vec3::ZERO,
+            Lod::new(0),
+            DebugTint::None,
+        );
+
+        assert_eq!(mesh_data.vertices.len(), 6 * 4);
+        assert!(mesh_data.colors.is_empty());
+    }
+
+    #[test]
+    fn test_debug_tint_by_chunk_neighbors_differ() {
+        let a = DebugTint::ByChunk.color(0, IVec3::new(0, 0, 0));
+        let b = DebugTint::ByChunk.color(0, IVec3::new(1, 0, 0));
+        let c = DebugTint::ByChunk.color(5, IVec3::new(0, 0, 0));
+
+        assert_ne!(a, b);
+        assert_eq!(a, c);
     }
 
-    chunk
+    fn assert_cube_faces_outward(mesh_data: &MeshData, center: Vec3) {
+        assert!(!mesh_data.indices.is_empty());
+
+        for triangle in mesh_data.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let (v0, v1, v2) = (
+                mesh_data.vertices[a],
+                mesh_data.vertices[b],
+                mesh_data.vertices[c],
+            );
+            let normal = mesh_data.normals[a];
+            let centroid = (v0 + v1 + v2) / 3.0;
+
+            // normal points away from the cube
+            assert!(normal.dot(centroid - center) > 0.0);
+            // winding agrees with the normal, which makes the triangle front-facing
+            // for both counter-clockwise right-handed and clockwise left-handed conventions
+            assert!((v1 - v0).cross(v2 - v0).dot(normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_coordinate_system_cube() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        for y in 2..4 {
+            for z in 2..4 {
+                for x in 2..4 {
+                    chunk.set(&mut interner, IVec3::new(x, y, z), 1);
+                }
+            }
+        }
+
+        let center = Vec3::splat(3.0);
+
+        for coordinate_system in [
+            CoordinateSystem::RightHandedYUp,
+            CoordinateSystem::LeftHandedYUp,
+        ] {
+            let mut naive = MeshData::with_coordinate_system(coordinate_system);
+            chunk.generate_naive_mesh_arrays(&interner, &mut naive, Vec3::ZERO, Lod::new(0));
+
+            let mut greedy = MeshData::with_coordinate_system(coordinate_system);
+            chunk.generate_greedy_mesh_arrays(&interner, &mut greedy, Vec3::ZERO, Lod::new(0));
+
+            assert_eq!(greedy.indices.len(), 6 * 6);
+
+            for mesh_data in [&naive, &greedy] {
+                assert_cube_faces_outward(mesh_data, coordinate_system.convert(center));
+            }
+        }
+
+        // the left-handed mesh is the right-handed one mirrored along `Z`
+        let mut right = MeshData::default();
+        chunk.generate_greedy_mesh_arrays(&interner, &mut right, Vec3::ZERO, Lod::new(0));
+        let mut left = MeshData::with_coordinate_system(CoordinateSystem::LeftHandedYUp);
+        chunk.generate_greedy_mesh_arrays(&interner, &mut left, Vec3::ZERO, Lod::new(0));
+
+        for (r, l) in right.vertices.iter().zip(&left.vertices) {
+            assert_eq!(*l, Vec3::new(r.x, r.y, -r.z));
+        }
+    }
+
+    #[test]
+    fn test_octree_wireframe_boxes_match_branches() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        // a collapsed 4^3 octant next to scattered voxels, two of them in the same octant
+        chunk
+            .data
+            .fill_region(&mut interner, IVec3::ZERO, IVec3::splat(3), 1);
+        for position in [
+            IVec3::new(7, 0, 0),
+            IVec3::new(6, 1, 0),
+            IVec3::new(5, 6, 2),
+        ] {
+            chunk.set(&mut interner, position, 2);
+        }
+
+        let mut branches = 0;
+        for depth in 0..MAX_DEPTH.max() {
+            let cells = 1 << depth;
+            let cell_size = 8 >> depth;
+            for z in 0..cells {
+                for y in 0..cells {
+                    for x in 0..cells {
+                        let position = IVec3::new(x, y, z) * cell_size;
+                        let depth = TraversalDepth::new(depth, MAX_DEPTH.max());
+                        if let Some((_, false)) = chunk.data.node_at(&interner, position, depth) {
+                            branches += 1;
+                        }
+                    }
+                }
+            }
+        }
+        // the root, the two octants holding voxels and the two 2^3 cells under them
+        assert_eq!(branches, 5);
+
+        let mesh_data = chunk.generate_octree_wireframe(&interner, Lod::new(0));
+
+        assert_eq!(mesh_data.topology, MeshTopology::LineList);
+        assert_eq!(mesh_data.vertices.len(), branches * 8);
+        assert_eq!(mesh_data.indices.len(), branches * 12 * 2);
+        assert_eq!(mesh_data.colors.len(), mesh_data.vertices.len());
+        assert!(mesh_data.normals.is_empty());
+
+        // the root box spans the whole chunk
+        assert!(mesh_data.vertices.contains(&Vec3::splat(8.0)));
+
+        // one level coarser the 2^3 cells are leaves, only the root and octants are left
+        let mesh_data = chunk.generate_octree_wireframe(&interner, Lod::new(1));
+        assert_eq!(mesh_data.vertices.len(), 3 * 8);
+
+        chunk.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    // Number of vertices, edges and triangles, failing unless every edge is shared by exactly
+    // two triangles running along it in opposite directions
+    fn closed_surface_counts(mesh_data: &MeshData) -> (usize, usize, usize) {
+        let mut directed = FxHashMap::default();
+        for triangle in mesh_data.indices.chunks_exact(3) {
+            for i in 0..3 {
+                let edge = (triangle[i], triangle[(i + 1) % 3]);
+                assert!(
+                    directed.insert(edge, ()).is_none(),
+                    "Edge {edge:?} is used twice in the same direction"
+                );
+            }
+        }
+        for (a, b) in directed.keys() {
+            assert!(directed.contains_key(&(*b, *a)), "Edge {a}-{b} is open");
+        }
+
+        (
+            mesh_data.vertices.len(),
+            directed.len() / 2,
+            mesh_data.indices.len() / 3,
+        )
+    }
+
+    #[test]
+    fn test_marching_cubes_sphere_is_watertight() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(4);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(16.0), MAX_DEPTH, 0, 0, 0);
+
+        let mut batch = chunk.create_batch();
+        batch.stamp_sphere(IVec3::splat(8), 5, 1);
+        chunk.apply_batch(&mut interner, &batch);
+
+        let mesh_data = chunk.generate_marching_cubes(&interner, 1, Lod::new(0));
+        assert!(!mesh_data.indices.is_empty());
+        assert_eq!(mesh_data.normals.len(), mesh_data.vertices.len());
+
+        // a closed surface without holes
+        let (vertices, edges, triangles) = closed_surface_counts(&mesh_data);
+        assert_eq!(vertices + triangles, edges + 2);
+
+        // normals point away from the center and are not only axis aligned
+        let center = Vec3::splat(8.5);
+        for (vertex, normal) in mesh_data.vertices.iter().zip(&mesh_data.normals) {
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+            assert!(normal.dot(*vertex - center) > 0.0);
+        }
+        assert!(
+            mesh_data
+                .normals
+                .iter()
+                .any(|normal| normal.abs().min_element() > 0.1)
+        );
+
+        // a sphere cut by the chunk border is closed along the border
+        chunk.clear(&mut interner);
+        let mut batch = chunk.create_batch();
+        batch.stamp_sphere(IVec3::new(1, 8, 8), 5, 1);
+        chunk.apply_batch(&mut interner, &batch);
+
+        let mesh_data = chunk.generate_marching_cubes(&interner, 1, Lod::new(0));
+        let (vertices, edges, triangles) = closed_surface_counts(&mesh_data);
+        assert_eq!(vertices + triangles, edges + 2);
+
+        // values below the iso level are outside
+        let mesh_data = chunk.generate_marching_cubes(&interner, 2, Lod::new(0));
+        assert!(mesh_data.indices.is_empty());
+
+        chunk.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_estimate_triangles() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        assert_eq!(chunk.estimate_triangles(&interner, Lod::new(0)), 0);
+
+        // an L-shaped wall, a full octant and a few scattered voxels, touching the chunk edges
+        for y in 0..5 {
+            for x in 0..8 {
+                chunk.set(&mut interner, IVec3::new(x, y, 7), 1);
+            }
+            chunk.set(&mut interner, IVec3::new(0, y, 3), 2);
+        }
+        for y in 4..8 {
+            for z in 0..4 {
+                for x in 4..8 {
+                    chunk.set(&mut interner, IVec3::new(x, y, z), 3);
+                }
+            }
+        }
+        chunk.set(&mut interner, IVec3::new(2, 6, 5), 4);
+        chunk.set(&mut interner, IVec3::new(3, 6, 5), 4);
+        chunk.set(&mut interner, IVec3::new(6, 0, 1), 5);
+
+        for lod in [Lod::new(0), Lod::new(1), Lod::new(2)] {
+            let estimate = chunk.estimate_triangles(&interner, lod);
+
+            let mut naive = MeshData::default();
+            chunk.generate_naive_mesh_arrays(&interner, &mut naive, Vec3::ZERO, lod);
+            assert_eq!(estimate, naive.indices.len() as u64 / 3);
+
+            let mut greedy = MeshData::default();
+            chunk.generate_greedy_mesh_arrays(&interner, &mut greedy, Vec3::ZERO, lod);
+            assert!(estimate >= greedy.indices.len() as u64 / 3);
+        }
+
+        chunk.fill(&mut interner, 1);
+        assert_eq!(chunk.estimate_triangles(&interner, Lod::new(0)), 12);
+    }
+
+    #[test]
+    fn test_sample_majority() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::ZERO, Lod::new(1)),
+            None
+        );
+
+        // 5/8 of the first cell is stone, 2/8 dirt
+        for (i, value) in [1, 1, 1, 2, 1, 2, 1].into_iter().enumerate() {
+            let position = IVec3::new(i as i32 & 1, (i as i32 >> 1) & 1, (i as i32 >> 2) & 1);
+            chunk.set(&mut interner, position, value);
+        }
+        // 3/8 of the next cell along x is set, so it is mostly empty
+        for z in 0..2 {
+            chunk.set(&mut interner, IVec3::new(2, 0, z), 3);
+        }
+        chunk.set(&mut interner, IVec3::new(3, 1, 1), 3);
+        // half of the next cell along y is set, split evenly between two values
+        for x in 0..2 {
+            chunk.set(&mut interner, IVec3::new(x, 2, 0), 5);
+            chunk.set(&mut interner, IVec3::new(x, 2, 1), 4);
+        }
+        // a full octant collapses into a leaf above max depth
+        for y in 4..8 {
+            for z in 4..8 {
+                for x in 4..8 {
+                    chunk.set(&mut interner, IVec3::new(x, y, z), 6);
+                }
+            }
+        }
+
+        let lod = Lod::new(1);
+        assert_eq!(chunk.sample_majority(&interner, IVec3::ZERO, lod), Some(1));
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::new(1, 0, 0), lod),
+            None
+        );
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::new(0, 1, 0), lod),
+            Some(4)
+        );
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::new(3, 3, 2), lod),
+            Some(6)
+        );
+
+        // a larger cell, holding the first three cells above
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::ZERO, Lod::new(2)),
+            None
+        );
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::ONE, Lod::new(2)),
+            Some(6)
+        );
+        // the whole chunk is a single cell, all stone but four voxels
+        chunk.fill(&mut interner, 1);
+        for x in 0..4 {
+            chunk.set(&mut interner, IVec3::new(x, 0, 0), 2);
+        }
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::ZERO, Lod::new(3)),
+            Some(1)
+        );
+        assert_eq!(
+            chunk.sample_majority(&interner, IVec3::ZERO, Lod::new(0)),
+            Some(2)
+        );
+
+        chunk.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_generate_mesh_capped() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(4);
+        const MAX_TRIANGLES: u64 = 1000;
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        // a 3D checkerboard, every voxel shows all of its faces at LOD 0
+        let mut dense = VoxChunk::with_position(Vec3::splat(16.0), MAX_DEPTH, 0, 0, 0);
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    if (x + y + z) % 2 == 0 {
+                        dense.set(&mut interner, IVec3::new(x, y, z), 1);
+                    }
+                }
+            }
+        }
+        assert!(dense.estimate_triangles(&interner, Lod::new(0)) > MAX_TRIANGLES);
+
+        let (mesh, lod) = dense.generate_mesh_capped(&interner, MAX_TRIANGLES, Lod::new(3));
+        assert!(lod.lod() > 0);
+        assert!(dense.estimate_triangles(&interner, lod) <= MAX_TRIANGLES);
+        assert!(!mesh.indices.is_empty());
+        assert!(mesh.indices.len() as u64 / 3 <= MAX_TRIANGLES);
+
+        // nothing fits, the coarsest allowed level is used
+        let (_, lod) = dense.generate_mesh_capped(&interner, 0, Lod::new(1));
+        assert_eq!(lod, Lod::new(1));
+
+        let mut sparse = VoxChunk::with_position(Vec3::splat(16.0), MAX_DEPTH, 0, 0, 0);
+        sparse.set(&mut interner, IVec3::new(3, 4, 5), 1);
+
+        let (mesh, lod) = sparse.generate_mesh_capped(&interner, MAX_TRIANGLES, Lod::new(3));
+        assert_eq!(lod, Lod::new(0));
+        assert_eq!(mesh.indices.len(), 12 * 3);
+
+        dense.clear(&mut interner);
+        sparse.clear(&mut interner);
+    }
+
+    #[test]
+    fn test_owners_follow_voxels() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+
+        for x in 0..4 {
+            chunk.set(&mut interner, IVec3::new(x, 0, 0), 1);
+        }
+
+        assert!(!chunk.set_owner(&interner, IVec3::new(5, 0, 0), 1));
+        for x in 0..4 {
+            assert!(chunk.set_owner(&interner, IVec3::new(x, 0, 0), 10 + x as u32));
+        }
+
+        // overwriting the material keeps the owner, clearing the voxel drops it
+        chunk.set(&mut interner, IVec3::new(0, 0, 0), 2);
+        assert_eq!(chunk.owner(IVec3::new(0, 0, 0)), Some(10));
+        chunk.set(&mut interner, IVec3::new(1, 0, 0), 0);
+        assert_eq!(chunk.owner(IVec3::new(1, 0, 0)), None);
+
+        let mut batch = chunk.create_batch();
+        batch.just_set(IVec3::new(2, 0, 0), 0);
+        chunk.apply_batch(&mut interner, &batch);
+        assert_eq!(chunk.owner(IVec3::new(2, 0, 0)), None);
+        assert_eq!(chunk.owner(IVec3::new(3, 0, 0)), Some(13));
+        assert_eq!(chunk.owners().len(), 2);
+
+        assert_eq!(chunk.clear_owner(IVec3::new(3, 0, 0)), Some(13));
+        assert_eq!(chunk.get(&interner, IVec3::new(3, 0, 0)), Some(1));
+
+        chunk.clear(&mut interner);
+        assert!(chunk.owners().is_empty());
+    }
+
+    #[cfg(feature = "vtm")]
+    #[test]
+    fn test_set_root_id_drops_owners() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 0, 0, 0);
+        let mut other = VoxChunk::with_position(Vec3::splat(8.0), MAX_DEPTH, 1, 0, 0);
+
+        chunk.set(&mut interner, IVec3::new(1, 2, 3), 1);
+        assert!(chunk.set_owner(&interner, IVec3::new(1, 2, 3), 7));
+        other.set(&mut interner, IVec3::new(1, 2, 3), 2);
+
+        // the owner was given to the old content, the new one has a voxel at the same spot
+        chunk.set_root_id(&mut interner, other.get_root_id());
+        assert_eq!(chunk.get(&interner, IVec3::new(1, 2, 3)), Some(2));
+        assert!(chunk.owners().is_empty());
+    }
+
+    #[cfg(feature = "vtm")]
+    #[test]
+    fn test_rle_round_trip() {
+        use crate::utils::shapes::generate_terrain_batch;
+
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(5);
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut chunk = VoxChunk::with_position(Vec3::ONE, MAX_DEPTH, 0, 0, 0);
+
+        let mut batch = chunk.create_batch();
+        generate_terrain_batch(&mut batch, 1.0 / 32.0, 12.0, Vec3::ZERO, true);
+        chunk.apply_batch(&mut interner, &batch);
+        assert!(!chunk.is_empty());
+
+        let rle = chunk.to_rle(&interner);
+        let dense = to_vec(&interner, &chunk.get_root_id(), MAX_DEPTH);
+        assert!(rle.len() < dense.len() * std::mem::size_of::<i32>());
+
+        let mut decoded = VoxChunk::from_rle(&rle, &mut interner, Vec3::ONE, MAX_DEPTH).unwrap();
+        assert_eq!(decoded.get_root_id(), chunk.get_root_id());
+        assert_eq!(decoded.to_rle(&interner), rle);
+
+        // an empty chunk is a single run
+        let mut empty = VoxChunk::<i32>::with_position(Vec3::ONE, MAX_DEPTH, 0, 0, 0);
+        assert_eq!(empty.to_rle(&interner), [0, 0, 0, 0, 0x80, 0x80, 0x02]);
+
+        // cut short, too long and trailing bytes
+        assert!(
+            VoxChunk::from_rle(&rle[..rle.len() - 1], &mut interner, Vec3::ONE, MAX_DEPTH).is_err()
+        );
+        let too_long = [0, 0, 0, 1, 0x81, 0x80, 0x02];
+        assert!(VoxChunk::from_rle(&too_long, &mut interner, Vec3::ONE, MAX_DEPTH).is_err());
+        let mut trailing = rle.clone();
+        trailing.push(0);
+        assert!(VoxChunk::from_rle(&trailing, &mut interner, Vec3::ONE, MAX_DEPTH).is_err());
+
+        chunk.clear(&mut interner);
+        decoded.clear(&mut interner);
+        empty.clear(&mut interner);
+        assert!(interner.patterns_empty());
+    }
 }
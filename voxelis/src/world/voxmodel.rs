@@ -1,40 +1,401 @@
 use std::{
     collections::HashMap,
-    io::{BufReader, Write},
+    io::{BufReader, Read, Write},
+    ops::Range,
     sync::Arc,
 };
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use glam::{IVec3, UVec3};
+use glam::{I64Vec3, IVec3, U64Vec3, UVec2, UVec3, Vec3, Vec4};
 use parking_lot::RwLock;
+use rayon::prelude::*;
 
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 #[cfg(feature = "memory_stats")]
 use crate::interner::InternerStats;
 
+#[cfg(feature = "trace_greedy_timings")]
+use crate::utils::mesh::GreedyTimings;
+
 use crate::{
-    BlockId, Lod, MaxDepth, VoxInterner, VoxelTrait,
-    interner::EMPTY_CHILD,
+    Batch, BlockId, Lod, MaxDepth, Orientation, TraversalDepth, VoxInterner, VoxelTrait,
+    interner::{EMPTY_CHILD, MAX_ALLOWED_DEPTH},
+    io::VtmError,
+    io::export::ByteSize,
     io::varint::{decode_varint_u32_from_reader, encode_varint_u32},
-    spatial::{VoxOpsChunkConfig, VoxOpsChunkLocalContainer, VoxOpsConfig, VoxOpsSpatial3D},
+    spatial::{
+        VoxOpsBatch, VoxOpsChunkConfig, VoxOpsChunkLocalContainer, VoxOpsConfig, VoxOpsDirty,
+        VoxOpsRead, VoxOpsSpatial3D, VoxOpsState,
+    },
+    utils::common::{
+        chunk_to_world_voxel, count_exposed_faces_per_axis, count_occupied_voxels,
+        count_touching_faces, decode_child_index_path, get_at_depth, to_vec, world_voxel_to_chunk,
+    },
+    utils::mesh::{
+        ExternalPlane, MergeMode, MeshData, OccupancyDataBuilder, SweepAxis,
+        generate_external_occupancy_mask, generate_greedy_mesh_arrays, generate_occupancy_masks,
+        greedy_mesh_dense,
+    },
     world::{
-        VoxChunk,
-        voxchunk::{deserialize_chunk, serialize_chunk},
+        Palette, VoxChunk,
+        voxchunk::{BranchPatterns, LeafPatterns, deserialize_chunk, serialize_chunk},
+        voxworld::ChunkRayIter,
     },
 };
 
+/// Offsets of the six chunks sharing a face with a chunk, in the order
+/// [`VoxModel::chunk_neighbors`] returns them.
+pub const CHUNK_FACE_NEIGHBORS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Offsets of the 26 chunks sharing a face, an edge or a corner with a chunk, in the order
+/// [`VoxModel::chunk_neighbors_26`] returns them: sorted by `z`, then `y`, then `x`.
+pub const CHUNK_NEIGHBORS_26: [IVec3; 26] = {
+    let mut offsets = [IVec3::ZERO; 26];
+
+    let mut index = 0;
+    let mut i = 0;
+    while i < 27 {
+        // the chunk itself
+        if i != 13 {
+            offsets[index] = IVec3::new(i % 3 - 1, i / 3 % 3 - 1, i / 9 - 1);
+            index += 1;
+        }
+        i += 1;
+    }
+
+    offsets
+};
+
 pub struct VoxModel<T: VoxelTrait> {
     pub max_depth: MaxDepth,
-    pub chunk_world_size: f32,
+    pub chunk_world_size: Vec3,
     pub world_bounds: IVec3,
+    /// Chunks keyed by chunk position, in chunks rather than voxels. World-space voxel
+    /// positions can exceed the `i32` range, see [`world_voxel_to_chunk`].
     pub chunks: HashMap<IVec3, VoxChunk<T>>,
     pub interner: Arc<RwLock<VoxInterner<T>>>,
+    /// Colors of the voxel values, stored in VTM files and used by
+    /// [`VoxModel::generate_unified_mesh`] for vertex colors.
+    pub palette: Palette<T>,
+    /// Chunk that mesh vertex positions are computed relative to, see [`VoxModel::set_origin`].
+    origin: IVec3,
+}
+
+/// Chunks sharing identical content, see [`VoxModel::deduplicate_chunks`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Shared root and the positions of every chunk using it, for roots used by more than
+    /// one chunk. A renderer can mesh each group once and instance it.
+    pub groups: Vec<(BlockId, Vec<IVec3>)>,
+    /// Number of distinct non-empty roots.
+    pub unique_chunks: usize,
+    /// Number of non-empty chunks that repeat the content of another chunk.
+    pub duplicate_chunks: usize,
+    /// Nodes the duplicates would need without sharing.
+    pub nodes_saved: u64,
+}
+
+/// Occupancy of a region, see [`VoxModel::classify_region`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionClass {
+    /// No voxel in the region is set.
+    Empty,
+    /// Every voxel in the region is set.
+    Solid,
+    /// The region holds both set and empty voxels.
+    Mixed,
+}
+
+impl RegionClass {
+    fn merge(self, other: Self) -> Self {
+        if self == other { self } else { Self::Mixed }
+    }
+}
+
+// Classifies the part of the octree node at `node_min` with `side` voxels per axis that lies
+// inside the inclusive `min..=max` box. Empty and leaf nodes answer for their whole cube.
+fn classify_node<T: VoxelTrait>(
+    interner: &VoxInterner<T>,
+    node_id: BlockId,
+    node_min: IVec3,
+    side: i32,
+    (min, max): (IVec3, IVec3),
+    visited: &mut usize,
+) -> RegionClass {
+    *visited += 1;
+
+    if node_id.is_empty() {
+        return RegionClass::Empty;
+    }
+
+    if node_id.is_leaf() {
+        return RegionClass::Solid;
+    }
+
+    let half = side / 2;
+    let children = interner.get_children_ref(&node_id);
+
+    let mut class = None;
+
+    for (index, child_id) in children.iter().enumerate() {
+        let child_min = node_min
+            + IVec3::new(
+                (index & 1) as i32,
+                ((index >> 1) & 1) as i32,
+                ((index >> 2) & 1) as i32,
+            ) * half;
+        let child_max = child_min + IVec3::splat(half - 1);
+
+        if child_max.cmplt(min).any() || child_min.cmpgt(max).any() {
+            continue;
+        }
+
+        let child_class = classify_node(interner, *child_id, child_min, half, (min, max), visited);
+        let merged = class.map_or(child_class, |class: RegionClass| class.merge(child_class));
+        if merged == RegionClass::Mixed {
+            return merged;
+        }
+
+        class = Some(merged);
+    }
+
+    class.unwrap_or(RegionClass::Empty)
+}
+
+/// Invalid settings rejected by [`VoxModelBuilder::build`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// A required setting was never given, named by its setter.
+    Missing(&'static str),
+    /// Depth the interner can't address, it has to stay below [`MAX_ALLOWED_DEPTH`].
+    MaxDepthTooLarge(u8),
+    /// Every axis of a chunk has to be positive and finite.
+    InvalidChunkSize(Vec3),
+    /// Negative number of chunks along an axis.
+    InvalidDimensions(IVec3),
+    /// The budget can't hold the nodes the model needs at the very least, see
+    /// [`VoxModelBuilder::build`].
+    BudgetTooSmall { budget: usize, required: usize },
+    /// The budget holds more nodes than a [`BlockId`] can address.
+    BudgetTooLarge(usize),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(setting) => write!(f, "missing model setting: {setting}"),
+            Self::MaxDepthTooLarge(max_depth) => write!(
+                f,
+                "max depth {max_depth} exceeds the limit of {}",
+                MAX_ALLOWED_DEPTH - 1
+            ),
+            Self::InvalidChunkSize(size) => write!(f, "invalid chunk size: {size}"),
+            Self::InvalidDimensions(dimensions) => {
+                write!(f, "invalid model dimensions: {dimensions}")
+            }
+            Self::BudgetTooSmall { budget, required } => write!(
+                f,
+                "memory budget of {} is too small, at least {} is needed",
+                ByteSize(*budget),
+                ByteSize(*required)
+            ),
+            Self::BudgetTooLarge(budget) => {
+                write!(f, "memory budget of {} is too large", ByteSize(*budget))
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Most voxels the dense grid of [`VoxModel::generate_unified_mesh`] may hold.
+pub const MAX_UNIFIED_MESH_VOXELS: u64 = 1 << 28;
+
+/// The non-empty chunks of a model span a grid of more than [`MAX_UNIFIED_MESH_VOXELS`]
+/// voxels, rejected by [`VoxModel::generate_unified_mesh`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnifiedMeshTooLarge {
+    /// Voxels of the grid along each axis.
+    pub size: U64Vec3,
+}
+
+impl std::fmt::Display for UnifiedMeshTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unified mesh grid of {}x{}x{} voxels exceeds the limit of {MAX_UNIFIED_MESH_VOXELS}",
+            self.size.x, self.size.y, self.size.z
+        )
+    }
+}
+
+impl std::error::Error for UnifiedMeshTooLarge {}
+
+/// Validating builder for [`VoxModel`], an alternative to the positional constructors.
+///
+/// # Examples
+///
+/// ```rust
+/// use glam::{IVec3, Vec3};
+/// use voxelis::world::{BuildError, VoxModel, VoxModelBuilder};
+///
+/// let model: VoxModel<i32> = VoxModelBuilder::new()
+///     .max_depth(4)
+///     .chunk_world_size(Vec3::splat(2.0))
+///     .dimensions(IVec3::new(4, 2, 4))
+///     .memory_budget(1024 * 1024)
+///     .build()
+///     .unwrap();
+/// assert_eq!(model.chunks.len(), 32);
+///
+/// let result = VoxModelBuilder::new()
+///     .max_depth(4)
+///     .chunk_world_size(Vec3::new(1.0, 0.0, 1.0))
+///     .memory_budget(1024 * 1024)
+///     .build::<i32>();
+/// assert!(matches!(result, Err(BuildError::InvalidChunkSize(_))));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VoxModelBuilder {
+    max_depth: Option<u8>,
+    chunk_world_size: Option<Vec3>,
+    dimensions: IVec3,
+    memory_budget: Option<usize>,
+    max_memory_budget: Option<usize>,
+}
+
+impl VoxModelBuilder {
+    /// Creates a builder for a model without chunks, see [`VoxModelBuilder::dimensions`].
+    /// The max depth, chunk size and memory budget have to be set before building.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the depth of every chunk octree, a chunk has `2^max_depth` voxels per axis.
+    pub fn max_depth(mut self, max_depth: u8) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets the size of a chunk in world units.
+    pub fn chunk_world_size(mut self, chunk_world_size: Vec3) -> Self {
+        self.chunk_world_size = Some(chunk_world_size);
+        self
+    }
+
+    /// Sets the number of chunks created up front along each axis, like
+    /// [`VoxModel::with_dimensions`]. Defaults to none.
+    pub fn dimensions(mut self, dimensions: IVec3) -> Self {
+        self.dimensions = dimensions;
+        self
+    }
+
+    /// Sets the memory budget of the interner holding the nodes of every chunk.
+    pub fn memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
+
+    /// Lets the interner grow from the memory budget up to `max_memory_budget` instead of
+    /// running out, see [`VoxInterner::with_growable_budget`].
+    pub fn max_memory_budget(mut self, max_memory_budget: usize) -> Self {
+        self.max_memory_budget = Some(max_memory_budget);
+        self
+    }
+
+    /// Validates the settings and creates the model.
+    ///
+    /// The budget is only checked against a lower bound: every chunk of the dimensions
+    /// holding a single voxel, i.e. one node per level of its octree. Real content usually
+    /// needs far more, so passing this check doesn't guarantee the budget suffices.
+    pub fn build<T: VoxelTrait>(&self) -> Result<VoxModel<T>, BuildError> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModelBuilder::build");
+
+        let max_depth = self.max_depth.ok_or(BuildError::Missing("max_depth"))?;
+        let chunk_world_size = self
+            .chunk_world_size
+            .ok_or(BuildError::Missing("chunk_world_size"))?;
+        let memory_budget = self
+            .memory_budget
+            .ok_or(BuildError::Missing("memory_budget"))?;
+
+        if max_depth as usize >= MAX_ALLOWED_DEPTH {
+            return Err(BuildError::MaxDepthTooLarge(max_depth));
+        }
+
+        if !chunk_world_size.is_finite() || chunk_world_size.cmple(Vec3::ZERO).any() {
+            return Err(BuildError::InvalidChunkSize(chunk_world_size));
+        }
+
+        if self.dimensions.cmplt(IVec3::ZERO).any() {
+            return Err(BuildError::InvalidDimensions(self.dimensions));
+        }
+
+        let node_size = VoxInterner::<T>::node_size();
+
+        // the interner keeps the shared empty branch in its first node
+        let chunk_count = self.dimensions.as_i64vec3().element_product() as usize;
+        let required_nodes = chunk_count
+            .saturating_mul(max_depth as usize + 1)
+            .saturating_add(1);
+        let required = required_nodes.saturating_mul(node_size);
+
+        let max_memory_budget = self.max_memory_budget.unwrap_or(memory_budget);
+
+        if max_memory_budget < memory_budget {
+            return Err(BuildError::BudgetTooSmall {
+                budget: max_memory_budget,
+                required: memory_budget,
+            });
+        }
+
+        if max_memory_budget / node_size >= u32::MAX as usize {
+            return Err(BuildError::BudgetTooLarge(max_memory_budget));
+        }
+
+        if memory_budget < node_size {
+            return Err(BuildError::BudgetTooSmall {
+                budget: memory_budget,
+                required: node_size,
+            });
+        }
+
+        // a growable interner only has to fit it once grown
+        if max_memory_budget < required {
+            return Err(BuildError::BudgetTooSmall {
+                budget: max_memory_budget,
+                required,
+            });
+        }
+
+        let interner = match self.max_memory_budget {
+            Some(max_memory_budget) => {
+                VoxInterner::with_growable_budget(memory_budget, max_memory_budget)
+            }
+            None => VoxInterner::with_memory_budget(memory_budget),
+        };
+
+        Ok(VoxModel::with_interner(
+            MaxDepth::new(max_depth),
+            chunk_world_size,
+            self.dimensions,
+            interner,
+        ))
+    }
 }
 
 fn initialize_chunks<T: VoxelTrait>(
     max_depth: MaxDepth,
-    chunk_world_size: f32,
+    chunk_world_size: Vec3,
     bounds: IVec3,
 ) -> HashMap<IVec3, VoxChunk<T>> {
     #[cfg(feature = "tracy")]
@@ -59,41 +420,33 @@ fn initialize_chunks<T: VoxelTrait>(
 }
 
 impl<T: VoxelTrait> VoxModel<T> {
-    pub fn empty(max_depth: MaxDepth, chunk_world_size: f32, memory_budget: usize) -> Self {
+    pub fn empty(max_depth: MaxDepth, chunk_world_size: Vec3, memory_budget: usize) -> Self {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("VoxModel::empty");
 
-        let interner = Arc::new(RwLock::new(VoxInterner::with_memory_budget(memory_budget)));
-
-        Self {
+        Self::with_interner(
             max_depth,
             chunk_world_size,
-            world_bounds: IVec3::ZERO,
-            chunks: HashMap::default(),
-            interner,
-        }
+            IVec3::ZERO,
+            VoxInterner::with_memory_budget(memory_budget),
+        )
     }
 
-    pub fn new(max_depth: MaxDepth, chunk_world_size: f32, memory_budget: usize) -> Self {
+    pub fn new(max_depth: MaxDepth, chunk_world_size: Vec3, memory_budget: usize) -> Self {
         #[cfg(feature = "tracy")]
         let _span = tracy_client::span!("VoxModel::new");
 
-        let interner = Arc::new(RwLock::new(VoxInterner::with_memory_budget(memory_budget)));
-        let world_bounds = IVec3::new(32, 32, 32);
-        let chunks = initialize_chunks(max_depth, chunk_world_size, world_bounds);
-
-        Self {
+        Self::with_interner(
             max_depth,
             chunk_world_size,
-            world_bounds,
-            chunks,
-            interner,
-        }
+            IVec3::new(32, 32, 32),
+            VoxInterner::with_memory_budget(memory_budget),
+        )
     }
 
     pub fn with_dimensions(
         max_depth: MaxDepth,
-        chunk_world_size: f32,
+        chunk_world_size: Vec3,
         world_bounds: IVec3,
         memory_budget: usize,
     ) -> Self {
@@ -103,7 +456,26 @@ impl<T: VoxelTrait> VoxModel<T> {
         println!(
             "Creating model with bounds {world_bounds:?}, chunk: {chunk_world_size}m depth: {max_depth}"
         );
-        let interner = Arc::new(RwLock::new(VoxInterner::with_memory_budget(memory_budget)));
+        Self::with_interner(
+            max_depth,
+            chunk_world_size,
+            world_bounds,
+            VoxInterner::with_memory_budget(memory_budget),
+        )
+    }
+
+    /// Creates a model with chunks for `world_bounds`, like [`VoxModel::with_dimensions`],
+    /// that stores its nodes in `interner`, e.g. one created with
+    /// [`VoxInterner::with_growable_budget`].
+    pub fn with_interner(
+        max_depth: MaxDepth,
+        chunk_world_size: Vec3,
+        world_bounds: IVec3,
+        interner: VoxInterner<T>,
+    ) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::with_interner");
+
         let chunks = initialize_chunks(max_depth, chunk_world_size, world_bounds);
 
         Self {
@@ -111,7 +483,9 @@ impl<T: VoxelTrait> VoxModel<T> {
             chunk_world_size,
             world_bounds,
             chunks,
-            interner,
+            interner: Arc::new(RwLock::new(interner)),
+            palette: Palette::new(),
+            origin: IVec3::ZERO,
         }
     }
 
@@ -134,6 +508,64 @@ impl<T: VoxelTrait> VoxModel<T> {
         })
     }
 
+    /// Reserves room for at least `additional` more chunks, so bulk inserts, like voxelization,
+    /// do not rehash the chunk map along the way.
+    pub fn reserve_chunks(&mut self, additional: usize) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::reserve_chunks");
+
+        self.chunks.reserve(additional);
+    }
+
+    /// Releases chunk map capacity that is no longer needed, e.g. after loading.
+    pub fn shrink_to_fit(&mut self) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::shrink_to_fit");
+
+        self.chunks.shrink_to_fit();
+    }
+
+    /// Returns the positions of chunks changed since the last call and clears their dirty
+    /// flags, so only those chunks need to be remeshed.
+    ///
+    /// Positions are sorted by `y`, then `z`, then `x`.
+    pub fn take_dirty_chunks(&mut self) -> Vec<IVec3> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::take_dirty_chunks");
+
+        let mut positions = self
+            .chunks
+            .iter_mut()
+            .filter(|(_, chunk)| chunk.is_dirty())
+            .map(|(position, chunk)| {
+                chunk.clear_dirty();
+                *position
+            })
+            .collect::<Vec<_>>();
+
+        positions.sort_by_key(|position| (position.y, position.z, position.x));
+
+        positions
+    }
+
+    /// Removes the chunks left without voxels, e.g. after carving, and returns how many were
+    /// removed. An empty chunk holds no nodes, so there are no roots to release.
+    ///
+    /// Empty chunks still marked dirty are kept, so [`VoxModel::take_dirty_chunks`] still
+    /// reports them and their old meshes can be dropped. Prune after taking the dirty chunks
+    /// to remove them too. The world bounds are left as they are.
+    pub fn prune_empty_chunks(&mut self) -> usize {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::prune_empty_chunks");
+
+        let chunk_count = self.chunks.len();
+
+        self.chunks
+            .retain(|_, chunk| !chunk.is_empty() || chunk.is_dirty());
+
+        chunk_count - self.chunks.len()
+    }
+
     pub fn get_interner(&self) -> Arc<RwLock<VoxInterner<T>>> {
         self.interner.clone()
     }
@@ -156,6 +588,16 @@ impl<T: VoxelTrait> VoxModel<T> {
         self.chunks = initialize_chunks(self.max_depth, self.chunk_world_size, self.world_bounds);
     }
 
+    /// Rebases mesh vertex positions onto `origin_chunk`, for floating-origin worlds. Only the
+    /// offsets passed to the meshers change, voxel data and chunk keys stay as they are.
+    pub fn set_origin(&mut self, origin_chunk: IVec3) {
+        self.origin = origin_chunk;
+    }
+
+    pub fn origin(&self) -> IVec3 {
+        self.origin
+    }
+
     pub fn get_bounds_size(&self) -> usize {
         self.world_bounds.x as usize * self.world_bounds.y as usize * self.world_bounds.z as usize
     }
@@ -169,291 +611,2662 @@ impl<T: VoxelTrait> VoxModel<T> {
             && position.z < self.world_bounds.z
     }
 
-    #[cfg(feature = "memory_stats")]
-    pub fn interner_stats(&self) -> InternerStats {
-        self.interner.read().stats()
+    /// One line summary for logging: chunks, occupied voxels, depth, chunk size and interner usage.
+    pub fn summary(&self) -> String {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::summary");
+
+        let interner = self.interner.read();
+
+        let non_empty_chunks = self
+            .chunks
+            .values()
+            .filter(|chunk| !chunk.is_empty())
+            .count();
+
+        let occupied_voxels = self.total_voxel_count(&interner);
+
+        let interner_nodes = interner.leaf_patterns().len() + interner.branch_patterns().len();
+        let interner_bytes = interner_nodes * VoxInterner::<T>::node_size();
+
+        let size = self.chunk_world_size;
+        let chunk_size = if size.x == size.y && size.y == size.z {
+            format!("{}", size.x)
+        } else {
+            format!("{}x{}x{}", size.x, size.y, size.z)
+        };
+
+        format!(
+            "chunks: {} (non-empty: {non_empty_chunks}), voxels: {occupied_voxels}, depth: {}, chunk size: {chunk_size}m, interner: {}",
+            self.chunks.len(),
+            self.max_depth,
+            ByteSize(interner_bytes),
+        )
     }
 
-    pub fn serialize(&self, data: &mut Vec<u8>) {
+    /// Number of set voxels across all chunks, at full resolution.
+    pub fn total_voxel_count(&self, interner: &VoxInterner<T>) -> u64 {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxModel::serialize");
+        let _span = tracy_client::span!("VoxModel::total_voxel_count");
 
-        const BUFFER_SIZE: usize = 256;
+        self.chunks
+            .values()
+            .map(|chunk| chunk.voxel_count(interner))
+            .sum()
+    }
 
-        let interner = self.interner.read();
+    pub fn occupied_counts_parallel(&self) -> FxHashMap<IVec3, u64>
+    where
+        T: Send + Sync,
+    {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::occupied_counts_parallel");
 
-        let leaf_patterns = interner.leaf_patterns();
-        let branch_patterns = interner.branch_patterns();
+        let interner = self.interner.read();
+        let interner = &*interner;
+        let max_depth = self.max_depth;
+
+        self.chunks
+            .par_iter()
+            .map(|(position, chunk)| {
+                let count = count_occupied_voxels(interner, &chunk.get_root_id(), max_depth);
+                (*position, count)
+            })
+            .collect()
+    }
 
-        let mut id_map: FxHashMap<u32, u32> = FxHashMap::default();
-        id_map.insert(0, 0);
+    /// Total area of voxel faces that touch empty space, in world units. Faces on chunk seams
+    /// count only where the neighbouring chunk is empty on the other side.
+    pub fn surface_area(&self, interner: &VoxInterner<T>, lod: Lod) -> f64 {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::surface_area");
 
-        let mut leaf_patterns = leaf_patterns.values().map(|id| *id).collect::<Vec<_>>();
-        let mut branch_patterns = branch_patterns.values().copied().collect::<Vec<_>>();
+        const NEIGHBOURS: [IVec3; 3] = [IVec3::X, IVec3::Y, IVec3::Z];
 
-        let mut next_id = 1;
+        let max_depth = self.max_depth(lod);
 
-        leaf_patterns.sort_by_key(|id| id.index());
-        branch_patterns.sort_by_key(|id| id.index());
+        let faces = self
+            .chunks
+            .iter()
+            .map(|(position, chunk)| {
+                let root_id = chunk.get_root_id();
+                if root_id.is_empty() {
+                    return [0; 3];
+                }
 
-        leaf_patterns.iter().for_each(|id| {
-            id_map.insert(id.index(), next_id);
-            next_id += 1;
-        });
+                let mut faces = count_exposed_faces_per_axis(interner, &root_id, max_depth);
 
-        branch_patterns.iter().for_each(|id| {
-            let index = id.index();
-            if index == 0 {
-                return;
-            }
+                for (axis, offset) in NEIGHBOURS.iter().enumerate() {
+                    let Some(neighbour) = self.chunks.get(&(*position + *offset)) else {
+                        continue;
+                    };
+
+                    let hidden = count_touching_faces(
+                        interner,
+                        &root_id,
+                        &neighbour.get_root_id(),
+                        axis,
+                        max_depth,
+                    );
+                    faces[axis] -= 2 * hidden;
+                }
 
-            id_map.insert(id.index(), next_id);
-            next_id += 1;
-        });
+                faces
+            })
+            .fold([0; 3], |total, faces| {
+                std::array::from_fn(|axis| total[axis] + faces[axis])
+            });
 
-        let leaf_size = leaf_patterns.len();
-        assert!(leaf_size <= u32::MAX as usize);
-        let branch_size = branch_patterns.len();
-        assert!(branch_size <= u32::MAX as usize);
-        let size = leaf_size + branch_size;
-        assert!(size <= u32::MAX as usize);
+        let voxel_size = self.voxel_size(lod).as_dvec3();
+        // faces perpendicular to an axis span the other two
+        let face_area = [
+            voxel_size.y * voxel_size.z,
+            voxel_size.x * voxel_size.z,
+            voxel_size.x * voxel_size.y,
+        ];
+
+        (0..3)
+            .map(|axis| faces[axis] as f64 * face_area[axis])
+            .sum()
+    }
 
-        let leaf_size = leaf_size as u32;
-        let branch_size = branch_size as u32;
+    /// Value of the voxel at a world voxel position, sampled at `lod`. Positions are in voxels
+    /// of that level, so at coarser levels one position covers several finer voxels.
+    pub fn get_world_voxel(
+        &self,
+        interner: &VoxInterner<T>,
+        position: IVec3,
+        lod: Lod,
+    ) -> Option<T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::get_world_voxel");
 
-        let mut writer = std::io::BufWriter::new(data);
+        let (chunk_position, local_position) =
+            world_voxel_to_chunk(position.as_i64vec3(), self.voxels_per_axis(lod));
 
-        writer.write_u32::<BigEndian>(leaf_size).unwrap();
-        for id in leaf_patterns.iter() {
-            let new_id = *id_map.get(&id.index()).unwrap();
-            // println!(" leaf id: {} -> {new_id}", id.index());
-            let new_id_bytes = encode_varint_u32(new_id);
-            // writer.write_u32::<BigEndian>(new_id).unwrap();
-            writer.write_all(&new_id_bytes).unwrap();
-            let value = interner.get_value(id);
-            value.write_as_be(&mut writer).unwrap();
-        }
+        let chunk = self.chunks.get(&chunk_position)?;
 
-        writer.write_u32::<BigEndian>(branch_size - 1).unwrap();
-        for id in branch_patterns.iter() {
-            if id.index() == 0 {
-                continue;
-            }
+        get_at_depth(
+            interner,
+            chunk.get_root_id(),
+            &local_position,
+            &TraversalDepth::new(0, self.max_depth(lod).max()),
+        )
+    }
 
-            let new_id = *id_map.get(&id.index()).unwrap();
-            // println!("branch id: {} -> {new_id}", id.index());
-            let new_id_bytes = encode_varint_u32(new_id);
-            // writer.write_u32::<BigEndian>(new_id).unwrap();
-            writer.write_all(&new_id_bytes).unwrap();
-            writer.write_u8(id.mask()).unwrap();
-            let branch = interner.get_children_ref(id);
-            for child in branch.iter() {
-                if child.is_empty() {
-                    // println!(" empty child");
-                    continue;
-                }
-                let new_id = *id_map.get(&child.index()).unwrap();
-                // println!("  child id: {} -> {new_id}", child.index());
-                let new_id_bytes = encode_varint_u32(new_id);
-                // writer.write_u32::<BigEndian>(new_id).unwrap();
-                writer.write_all(&new_id_bytes).unwrap();
-            }
-            let branch_lod_value = interner.get_value(id);
-            branch_lod_value.write_as_be(&mut writer).unwrap();
-        }
+    /// World voxel position at `lod` containing a mesh-space position, e.g. a picked vertex.
+    /// Vertices lie on voxel corners, a corner maps to the voxel it is the minimum corner of,
+    /// so nudge the position against the face normal first to pick the solid voxel.
+    /// Takes [`VoxModel::set_origin`] into account. Inverse of [`VoxModel::voxel_to_world_pos`].
+    pub fn world_pos_to_voxel(&self, world_pos: Vec3, lod: Lod) -> IVec3 {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::world_pos_to_voxel");
 
-        let chunks_data: Vec<Vec<u8>> = self
-            .chunks
-            .iter() // .par_iter() needs Send + Sync for VoxelTrait
-            .map(|(_, chunk)| {
-                let mut buffer = Vec::with_capacity(BUFFER_SIZE);
-                serialize_chunk(chunk, &id_map, &mut buffer);
-                buffer
-            })
-            .collect();
+        // absorbs rounding in vertex positions that should land exactly on a corner
+        const EPSILON: f32 = 1e-4;
 
-        let actual_chunks_len = self.chunks.len();
-        writer
-            .write_u32::<BigEndian>(actual_chunks_len as u32)
-            .unwrap();
+        let voxel = (world_pos / self.voxel_size(lod) + Vec3::splat(EPSILON)).floor();
 
-        for chunk_data in chunks_data.iter() {
-            writer.write_all(chunk_data).unwrap();
-        }
+        voxel.as_ivec3() + self.origin * self.voxels_per_axis(lod) as i32
     }
 
-    pub fn deserialize(&mut self, data: &[u8]) {
+    /// Mesh-space position of the minimum corner of a world voxel at `lod`, matching the
+    /// vertices the meshers emit for it.
+    pub fn voxel_to_world_pos(&self, voxel: IVec3, lod: Lod) -> Vec3 {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxModel::deserialize");
+        let _span = tracy_client::span!("VoxModel::voxel_to_world_pos");
 
-        println!("Deserializing chunks...");
+        // subtract in integers, like chunk_mesh_offset
+        let voxel = voxel - self.origin * self.voxels_per_axis(lod) as i32;
 
-        let now = std::time::Instant::now();
+        voxel.as_vec3() * self.voxel_size(lod)
+    }
 
-        let mut reader = BufReader::new(data);
+    /// Groups non-empty chunks by root. Interning gives identical content the same root, so
+    /// chunks in a group are identical and already share all of their nodes.
+    pub fn deduplicate_chunks(&self, interner: &VoxInterner<T>) -> DedupReport {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::deduplicate_chunks");
 
-        let leaf_size = reader.read_u32::<BigEndian>().unwrap();
-        // let mut leaf_patterns: HashMap<u32, (BlockId, i32)> =
-        //     HashMap<K, V, FxBuildHasher>(leaf_size as usize);
-        let mut leaf_patterns: FxHashMap<u32, (BlockId, T)> = FxHashMap::default();
+        let mut roots: FxHashMap<BlockId, Vec<IVec3>> = FxHashMap::default();
+        for (position, chunk) in self.chunks.iter() {
+            let root_id = chunk.get_root_id();
+            if !root_id.is_empty() {
+                roots.entry(root_id).or_default().push(*position);
+            }
+        }
 
-        let mut interner = self.interner.write();
+        let mut report = DedupReport {
+            unique_chunks: roots.len(),
+            ..Default::default()
+        };
 
-        for _ in 0..leaf_size {
-            let id = decode_varint_u32_from_reader(&mut reader).unwrap();
-            let value = T::read_from_be(&mut reader).unwrap();
+        for (root_id, mut positions) in roots {
+            if positions.len() < 2 {
+                continue;
+            }
 
-            let block_id = interner.deserialize_leaf(id, value);
-            leaf_patterns.insert(id, (block_id, value));
+            positions.sort_by_key(|position| (position.y, position.z, position.x));
 
-            println!(" leaf id: {block_id:?} -> {value}");
+            let duplicates = positions.len() - 1;
+            report.duplicate_chunks += duplicates;
+            report.nodes_saved += duplicates as u64 * interner.count_nodes(root_id) as u64;
+            report.groups.push((root_id, positions));
         }
 
-        let branch_size = reader.read_u32::<BigEndian>().unwrap();
-        let mut branch_patterns: FxHashMap<u32, (BlockId, [u32; 8], T)> =
-        // FxHashMap::with_capacity(branch_size as usize);
-            FxHashMap::default();
+        report
+            .groups
+            .sort_by_key(|(_, positions)| (positions[0].y, positions[0].z, positions[0].x));
 
-        branch_patterns.insert(0, (BlockId::EMPTY, [0u32; 8], T::default()));
+        report
+    }
 
-        for _ in 0..branch_size {
-            let id = decode_varint_u32_from_reader(&mut reader).unwrap();
-            assert_ne!(id, 0);
+    /// Tells whether the world voxels in the inclusive `world_min..=world_max` box are all empty,
+    /// all set, or both. Uniform nodes covering part of the box answer for it without descending,
+    /// and the walk stops at the first sign of a mixed region. Missing chunks count as empty.
+    pub fn classify_region(
+        &self,
+        interner: &VoxInterner<T>,
+        world_min: IVec3,
+        world_max: IVec3,
+    ) -> RegionClass {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::classify_region");
 
-            let mask = reader.read_u8().unwrap();
-            // println!("id: {} mask: {:08b}", id, mask);
-            let mut types: u8 = 0;
-            let mut children = [0u32; 8];
-            for child_id in 0..8 {
-                if mask & (1 << child_id) == 0 {
-                    // println!(" skipping child {child_id}");
-                    continue;
-                }
-                // println!(" reading child {child_id}");
-                children[child_id] = decode_varint_u32_from_reader(&mut reader).unwrap();
-                if leaf_patterns.contains_key(&children[child_id]) {
-                    types |= 1 << child_id;
+        self.classify_region_counted(interner, world_min, world_max, &mut 0)
+    }
+
+    fn classify_region_counted(
+        &self,
+        interner: &VoxInterner<T>,
+        world_min: IVec3,
+        world_max: IVec3,
+        visited: &mut usize,
+    ) -> RegionClass {
+        assert!(
+            world_min.cmple(world_max).all(),
+            "Region min {world_min} is past max {world_max}"
+        );
+
+        let voxels_per_axis = self.voxels_per_axis(Lod::new(0));
+        let side = voxels_per_axis as i32;
+
+        let (min_chunk, _) = world_voxel_to_chunk(world_min.as_i64vec3(), voxels_per_axis);
+        let (max_chunk, _) = world_voxel_to_chunk(world_max.as_i64vec3(), voxels_per_axis);
+
+        let mut class = None;
+
+        for y in min_chunk.y..=max_chunk.y {
+            for z in min_chunk.z..=max_chunk.z {
+                for x in min_chunk.x..=max_chunk.x {
+                    let chunk_position = IVec3::new(x, y, z);
+                    let chunk_min = chunk_position * side;
+
+                    let chunk_class = match self.chunks.get(&chunk_position) {
+                        Some(chunk) => classify_node(
+                            interner,
+                            chunk.get_root_id(),
+                            IVec3::ZERO,
+                            side,
+                            (
+                                (world_min - chunk_min).max(IVec3::ZERO),
+                                (world_max - chunk_min).min(IVec3::splat(side - 1)),
+                            ),
+                            visited,
+                        ),
+                        None => RegionClass::Empty,
+                    };
+
+                    let merged =
+                        class.map_or(chunk_class, |class: RegionClass| class.merge(chunk_class));
+                    if merged == RegionClass::Mixed {
+                        return merged;
+                    }
+
+                    class = Some(merged);
                 }
             }
-            let lod_value = T::read_from_be(&mut reader).unwrap();
+        }
 
-            let block_id = interner.preallocate_branch_id(id, types, mask);
+        class.unwrap_or(RegionClass::Empty)
+    }
 
-            branch_patterns.insert(id, (block_id, children, lod_value));
-            // println!(
-            //     " branch: mask: {mask:08b} types: {types:08b} id: {id:08X} [{block_id:?}] -> {children:08X?}"
-            // );
-            assert_ne!(mask, 0);
-        }
+    /// Samples the `(2 * radius + 1)^3` cube of world voxels around `center`, across chunk
+    /// borders, indexed as `(y * side + z) * side + x` with the center in the middle.
+    /// Voxels in missing chunks are `None`, like empty ones.
+    pub fn extract_window(
+        &self,
+        interner: &VoxInterner<T>,
+        center: IVec3,
+        radius: i32,
+    ) -> Vec<Option<T>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::extract_window");
 
-        branch_patterns
-            .iter()
-            .for_each(|(id, (block_id, children, lod_value))| {
-                if *id == 0 {
-                    return;
-                }
+        assert!(radius >= 0, "Window radius must not be negative");
 
-                let types = block_id.types();
-                let mask = block_id.mask();
+        let lod = Lod::new(0);
+        let side = 2 * radius + 1;
 
-                let mut branch = EMPTY_CHILD;
-                for child_idx in 0..8 {
-                    if mask & (1 << child_idx) == 0 {
-                        continue;
-                    }
+        let mut window = Vec::with_capacity((side * side * side) as usize);
 
-                    let child_id = children[child_idx];
-                    if types & (1 << child_idx) != 0 {
-                        let (leaf_id, _) = leaf_patterns.get(&child_id).unwrap();
-                        branch[child_idx] = *leaf_id;
-                    } else {
-                        branch[child_idx] = branch_patterns.get(&child_id).unwrap().0;
-                    }
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                for x in -radius..=radius {
+                    let position = center + IVec3::new(x, y, z);
+                    window.push(self.get_world_voxel(interner, position, lod));
                 }
+            }
+        }
 
-                // println!("branch: {block_id:?} -> {branch:?}");
-                interner.deserialize_branch(*block_id, branch, types, mask, *lod_value);
-            });
+        window
+    }
 
-        // drop(interner);
+    /// Copies the world voxels in the inclusive `min_world..=max_world` box into a new model,
+    /// e.g. to drop the empty margin of a scan or to split a large model into tiles.
+    ///
+    /// Only chunks overlapping the box are visited, each through
+    /// [`crate::spatial::VoxTree::extract_region`] clipped to the box. Chunk positions are rebased so the
+    /// chunk holding `min_world` becomes the origin, voxels keep their offset inside their
+    /// chunk, and the dimensions shrink to the chunks spanned by the box. Chunks left empty
+    /// by the crop are not created. The new model gets its own interner with the budget of
+    /// `interner`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_world` is past `max_world` on any axis.
+    pub fn crop(&self, interner: &VoxInterner<T>, min_world: IVec3, max_world: IVec3) -> Self {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::crop");
 
-        let mut branch_ids = branch_patterns
-            .iter()
-            .map(|(_, (block_id, _, _))| *block_id)
-            .collect::<Vec<_>>();
-        branch_ids.sort_by_key(|id| id.index());
+        assert!(
+            min_world.cmple(max_world).all(),
+            "Crop min {min_world} is past max {max_world}"
+        );
 
-        // for branch_id in branch_ids.iter() {
-        //     println!("Branch id: {branch_id:?}");
-        //     interner.dump_node(*branch_id, 0, "  ");
-        // }
+        let voxels_per_axis = self.voxels_per_axis(Lod::new(0));
+        let side = voxels_per_axis as i64;
 
-        let actual_chunks_len = reader.read_u32::<BigEndian>().unwrap();
+        let (min_chunk, _) = world_voxel_to_chunk(min_world.as_i64vec3(), voxels_per_axis);
+        let (max_chunk, _) = world_voxel_to_chunk(max_world.as_i64vec3(), voxels_per_axis);
 
-        for _ in 0..actual_chunks_len {
-            let chunk = deserialize_chunk(
-                &mut interner,
-                &leaf_patterns,
-                &branch_patterns,
-                &mut reader,
-                self.chunk_world_size,
-                self.max_depth,
+        let mut cropped = Self::with_interner(
+            self.max_depth,
+            self.chunk_world_size,
+            IVec3::ZERO,
+            VoxInterner::with_growable_budget(
+                interner.memory_budget(),
+                interner.max_pool_capacity() * VoxInterner::<T>::node_size(),
+            ),
+        );
+
+        let cropped_interner = cropped.get_interner();
+        let mut cropped_interner = cropped_interner.write();
+
+        for (position, chunk) in &self.chunks {
+            if position.cmplt(min_chunk).any() || position.cmpgt(max_chunk).any() {
+                continue;
+            }
+
+            // the chunk overlaps the box, so both clamped corners fit back into i32
+            let chunk_min = chunk_to_world_voxel(*position, voxels_per_axis);
+            let batch = chunk.extract_region(
+                interner,
+                (min_world.as_i64vec3() - chunk_min)
+                    .max(I64Vec3::ZERO)
+                    .as_ivec3(),
+                (max_world.as_i64vec3() - chunk_min)
+                    .min(I64Vec3::splat(side - 1))
+                    .as_ivec3(),
             );
 
-            self.chunks.insert(chunk.position_3d(), chunk);
+            if batch.has_patches() {
+                cropped
+                    .get_or_create_chunk(*position - min_chunk)
+                    .apply_batch(&mut cropped_interner, &batch);
+            }
         }
 
-        let elapsed = now.elapsed();
-        println!("Deserializing chunks took {elapsed:?}");
+        drop(cropped_interner);
+
+        cropped.world_bounds = max_chunk - min_chunk + IVec3::ONE;
+        cropped.palette = self.palette.clone();
+
+        cropped
     }
-}
 
-impl<T: VoxelTrait> VoxOpsConfig for VoxModel<T> {
-    fn max_depth(&self, lod: Lod) -> MaxDepth {
-        self.max_depth.for_lod(lod)
+    /// Chunk positions a ray passes through, in order, up to `max_dist` world units from
+    /// `origin`. The ray is given in mesh space, so [`VoxModel::set_origin`] is taken into
+    /// account. Chunks are returned whether or not they are loaded, for predictive streaming.
+    pub fn chunks_along_ray(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+    ) -> impl Iterator<Item = IVec3> + use<T> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::chunks_along_ray");
+
+        ChunkRayIter::new(origin, dir, max_dist, self.chunk_world_size, self.origin)
     }
 
-    fn voxels_per_axis(&self, lod: Lod) -> u32 {
-        1 << self.max_depth.for_lod(lod).max()
+    /// Returns the positions of the loaded chunks whose bounds intersect the view frustum.
+    ///
+    /// Each plane is `(normal, d)` with the normal pointing into the frustum, a point `p` is
+    /// inside when `normal.dot(p) + d >= 0`. Planes are given in mesh space, like the chunk
+    /// meshes, so [`VoxModel::set_origin`] is taken into account. A chunk is culled only if its
+    /// bounds lie fully outside one plane, so a few chunks near the frustum corners are kept
+    /// although they are not visible. Doesn't look at the voxels, empty chunks are returned as
+    /// well.
+    ///
+    /// Positions are sorted by `y`, then `z`, then `x`.
+    pub fn chunks_in_frustum(&self, planes: &[Vec4; 6]) -> Vec<IVec3> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::chunks_in_frustum");
+
+        let mut positions = self
+            .chunks
+            .keys()
+            .filter(|position| {
+                let min = self.chunk_mesh_offset(**position);
+                let max = min + self.chunk_world_size;
+
+                planes.iter().all(|plane| {
+                    let normal = plane.truncate();
+                    // corner of the bounds furthest along the plane normal
+                    let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+                    normal.dot(corner) + plane.w >= 0.0
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        positions.sort_by_key(|position| (position.y, position.z, position.x));
+
+        positions
     }
-}
 
-impl<T: VoxelTrait> VoxOpsChunkConfig for VoxModel<T> {
-    fn chunk_dimensions(&self) -> UVec3 {
-        self.world_bounds.as_uvec3() + UVec3::ONE
+    /// Returns the chunks sharing a face with the chunk at `position`, in the order of
+    /// [`CHUNK_FACE_NEIGHBORS`], `None` where no chunk is loaded.
+    ///
+    /// The chunk at `position` itself doesn't need to be loaded.
+    pub fn chunk_neighbors(&self, position: IVec3) -> [Option<&VoxChunk<T>>; 6] {
+        CHUNK_FACE_NEIGHBORS.map(|offset| self.chunks.get(&(position + offset)))
     }
 
-    fn chunk_size(&self) -> f32 {
-        self.chunk_world_size
+    /// Returns the [`VoxChunk::content_hash`] of every chunk by position, so a client can diff
+    /// it against the map of a server and request only the chunks that differ. Both maps
+    /// have to come from models sharing an interner for equal hashes to mean equal content.
+    pub fn chunk_hashes(&self) -> FxHashMap<IVec3, u64> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::chunk_hashes");
+
+        self.chunks
+            .iter()
+            .map(|(position, chunk)| (*position, chunk.content_hash()))
+            .collect()
     }
 
-    fn voxel_size(&self, lod: Lod) -> f32 {
-        1.0 / self.voxels_per_axis(lod) as f32 * self.chunk_world_size
+    /// Returns the chunks sharing a face, an edge or a corner with the chunk at `position`, in
+    /// the order of [`CHUNK_NEIGHBORS_26`], `None` where no chunk is loaded.
+    pub fn chunk_neighbors_26(&self, position: IVec3) -> [Option<&VoxChunk<T>>; 26] {
+        CHUNK_NEIGHBORS_26.map(|offset| self.chunks.get(&(position + offset)))
     }
-}
 
-impl<T: VoxelTrait> VoxOpsChunkLocalContainer<T> for VoxModel<T> {
-    fn has_local_chunk(&self, position: UVec3) -> bool {
+    /// Greedy meshes the whole model as a single grid, so flat surfaces spanning several chunks
+    /// become one quad. Trades the per-chunk meshes for fewer draw calls, any edit requires
+    /// meshing the model again. With a non-empty palette vertices get the palette colors,
+    /// white for values without an entry. The grid spans the box of all non-empty chunks,
+    /// models where it would exceed `MAX_UNIFIED_MESH_VOXELS` are rejected.
+    pub fn generate_unified_mesh(&self, lod: Lod) -> Result<MeshData, UnifiedMeshTooLarge> {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxModel::has_local_chunk");
+        let _span = tracy_client::span!("VoxModel::generate_unified_mesh");
 
-        let position = position.as_ivec3();
-        self.chunks.contains_key(&position)
+        let interner = self.interner.read();
+
+        let mut mesh_data = MeshData::default();
+
+        let Some((min_chunk, max_chunk)) = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| !chunk.is_empty_at_lod(&interner, lod))
+            .map(|(position, _)| (*position, *position))
+            .reduce(|(min, max), (position, _)| (min.min(position), max.max(position)))
+        else {
+            return Ok(mesh_data);
+        };
+
+        let voxels_per_axis = self.voxels_per_axis(lod) as usize;
+
+        // chunk positions span up to 2^32 per axis, a chunk at most 2^7 voxels, so only the
+        // product can overflow
+        let size = (max_chunk.as_i64vec3() - min_chunk.as_i64vec3() + I64Vec3::ONE).as_u64vec3()
+            * voxels_per_axis as u64;
+        let voxel_count = size
+            .x
+            .checked_mul(size.y)
+            .and_then(|count| count.checked_mul(size.z))
+            .filter(|count| *count <= MAX_UNIFIED_MESH_VOXELS)
+            .ok_or(UnifiedMeshTooLarge { size })?;
+
+        let mut voxels = vec![T::default(); voxel_count as usize];
+
+        // both grids are laid out y, z, x, so every row of a chunk is a single copy
+        let (size_x, size_z) = (size.x as usize, size.z as usize);
+        let max_depth = self.max_depth(lod);
+
+        for (position, chunk) in self.chunks.iter() {
+            if chunk.is_empty_at_lod(&interner, lod) {
+                continue;
+            }
+
+            let chunk_voxels = to_vec(&interner, &chunk.get_root_id(), max_depth);
+            // fits, the grid holding the chunk passed the size check
+            let offset = (*position - min_chunk).as_uvec3() * voxels_per_axis as u32;
+            let (offset_x, offset_y, offset_z) =
+                (offset.x as usize, offset.y as usize, offset.z as usize);
+
+            for (row_index, row) in chunk_voxels.chunks_exact(voxels_per_axis).enumerate() {
+                let z = row_index % voxels_per_axis;
+                let y = row_index / voxels_per_axis;
+                let start = ((offset_y + y) * size_z + offset_z + z) * size_x + offset_x;
+
+                voxels[start..start + voxels_per_axis].copy_from_slice(row);
+            }
+        }
+
+        // looked up by value, palettes may hold negative or sparse values
+        let color = |value: T| self.palette.color(value).unwrap_or([1.0; 4]);
+
+        greedy_mesh_dense(
+            &voxels,
+            size.as_uvec3(),
+            std::slice::from_mut(&mut mesh_data),
+            |_| 0,
+            self.chunk_mesh_offset(min_chunk),
+            self.voxel_size(lod),
+            MergeMode::PerValue,
+            (!self.palette.is_empty()).then_some(&color),
+        );
+
+        Ok(mesh_data)
     }
 
-    fn local_chunk(&self, position: UVec3) -> Option<&VoxChunk<T>> {
+    /// Greedy meshes the chunk at `position` into `mesh_data`, skipping boundary faces covered
+    /// by the touching voxels of the six adjacent chunks, so neighboring chunk meshes don't
+    /// leave internal walls on their shared planes.
+    ///
+    /// Missing neighbors leave their side open. Does nothing if there is no chunk at `position`.
+    pub fn generate_chunk_mesh_with_neighbors(
+        &self,
+        interner: &VoxInterner<T>,
+        position: IVec3,
+        lod: Lod,
+        mesh_data: &mut MeshData,
+    ) {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxModel::chunk");
+        let _span = tracy_client::span!("VoxModel::generate_chunk_mesh_with_neighbors");
 
-        let position = position.as_ivec3();
-        self.chunks.get(&position)
+        self.generate_chunk_mesh_with_neighbors_at(
+            interner,
+            position,
+            lod,
+            self.chunk_mesh_offset(position),
+            mesh_data,
+        );
     }
 
-    fn local_chunk_mut(&mut self, position: UVec3) -> Option<&mut VoxChunk<T>> {
+    /// Greedy meshes every non-empty chunk in parallel, like
+    /// [`VoxModel::generate_chunk_mesh_with_neighbors`], one chunk per rayon task.
+    ///
+    /// Returns the position of each chunk with the offset to place its mesh at and the mesh
+    /// itself, with vertices relative to that offset, sorted by position. All tasks share
+    /// `interner`, so a single read guard is held for the whole call.
+    pub fn generate_all_meshes_parallel(
+        &self,
+        interner: &VoxInterner<T>,
+        lod: Lod,
+    ) -> Vec<(IVec3, Vec3, MeshData)>
+    where
+        T: Send + Sync,
+    {
         #[cfg(feature = "tracy")]
-        let _span = tracy_client::span!("VoxModel::chunk_mut");
+        let _span = tracy_client::span!("VoxModel::generate_all_meshes_parallel");
 
-        let position = position.as_ivec3();
-        self.chunks.get_mut(&position)
+        let mut meshes = self
+            .chunks
+            .par_iter()
+            .filter(|(_, chunk)| !chunk.is_empty_at_lod(interner, lod))
+            .map(|(position, _)| {
+                let mut mesh_data = MeshData::default();
+                self.generate_chunk_mesh_with_neighbors_at(
+                    interner,
+                    *position,
+                    lod,
+                    Vec3::ZERO,
+                    &mut mesh_data,
+                );
+
+                (*position, self.chunk_mesh_offset(*position), mesh_data)
+            })
+            .collect::<Vec<_>>();
+
+        meshes.sort_unstable_by_key(|(position, _, _)| (position.y, position.z, position.x));
+
+        meshes
+    }
+
+    fn generate_chunk_mesh_with_neighbors_at(
+        &self,
+        interner: &VoxInterner<T>,
+        position: IVec3,
+        lod: Lod,
+        offset: Vec3,
+        mesh_data: &mut MeshData,
+    ) {
+        // external plane the touching face of each neighbor fills, in the order of
+        // `CHUNK_FACE_NEIGHBORS`
+        const EXTERNAL_PLANES: [ExternalPlane; 6] = [
+            ExternalPlane::YZPos,
+            ExternalPlane::YZNeg,
+            ExternalPlane::XZPos,
+            ExternalPlane::XZNeg,
+            ExternalPlane::XYPos,
+            ExternalPlane::XYNeg,
+        ];
+
+        let Some(chunk) = self.chunks.get(&position) else {
+            return;
+        };
+
+        if chunk.is_empty_at_lod(interner, lod) {
+            return;
+        }
+
+        let max_depth = self.max_depth(lod);
+
+        let mut builder = OccupancyDataBuilder::default();
+
+        for (neighbor, external_plane) in self
+            .chunk_neighbors(position)
+            .into_iter()
+            .zip(EXTERNAL_PLANES)
+        {
+            if let Some(neighbor) = neighbor
+                && !neighbor.is_empty_at_lod(interner, lod)
+            {
+                generate_external_occupancy_mask(
+                    interner,
+                    &mut builder,
+                    &neighbor.get_root_id(),
+                    max_depth,
+                    external_plane,
+                    UVec2::ZERO,
+                );
+            }
+        }
+
+        #[cfg(feature = "trace_greedy_timings")]
+        let mut timings = GreedyTimings::default();
+
+        generate_occupancy_masks(
+            interner,
+            &mut builder,
+            &chunk.get_root_id(),
+            max_depth,
+            UVec3::ZERO,
+            #[cfg(feature = "trace_greedy_timings")]
+            &mut timings,
+        );
+
+        generate_greedy_mesh_arrays(
+            &builder.build(),
+            mesh_data,
+            max_depth,
+            offset,
+            self.voxel_size(lod),
+            false,
+            SweepAxis::X,
+            #[cfg(feature = "trace_greedy_timings")]
+            &mut timings,
+        );
+    }
+
+    /// Removes every voxel whose 6 neighbors are all occupied, leaving a hollow shell.
+    /// Neighbors across chunk borders are looked up in the adjacent chunk, voxels on
+    /// the model border are always kept.
+    pub fn extract_shell(&mut self, interner: &mut VoxInterner<T>) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::extract_shell");
+
+        const NEIGHBORS: [IVec3; 6] = [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ];
+
+        let max_depth = self.max_depth;
+        let voxels_per_axis = 1i32 << max_depth.max();
+        let shift_y = (voxels_per_axis * voxels_per_axis) as usize;
+        let shift_z = voxels_per_axis as usize;
+
+        let default_t = T::default();
+
+        // all batches are collected before applying, so removals don't affect neighbors
+        let mut batches = Vec::new();
+
+        for (chunk_position, chunk) in self.chunks.iter() {
+            if chunk.is_empty() {
+                continue;
+            }
+
+            let data = to_vec(interner, &chunk.get_root_id(), max_depth);
+
+            let is_occupied = |position: IVec3| -> bool {
+                if position.cmpge(IVec3::ZERO).all()
+                    && position.cmplt(IVec3::splat(voxels_per_axis)).all()
+                {
+                    let index = position.y as usize * shift_y
+                        + position.z as usize * shift_z
+                        + position.x as usize;
+                    data[index] != default_t
+                } else {
+                    let neighbor_position =
+                        *chunk_position + position.div_euclid(IVec3::splat(voxels_per_axis));
+                    let local_position = position.rem_euclid(IVec3::splat(voxels_per_axis));
+
+                    self.chunks
+                        .get(&neighbor_position)
+                        .is_some_and(|neighbor| neighbor.get(interner, local_position).is_some())
+                }
+            };
+
+            let mut batch = chunk.create_batch();
+
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        let position = IVec3::new(x, y, z);
+
+                        if is_occupied(position)
+                            && NEIGHBORS
+                                .iter()
+                                .all(|offset| is_occupied(position + *offset))
+                        {
+                            batch.just_set(position, default_t);
+                        }
+                    }
+                }
+            }
+
+            if batch.has_patches() {
+                batches.push((*chunk_position, batch));
+            }
+        }
+
+        for (chunk_position, batch) in batches {
+            let chunk = self.chunks.get_mut(&chunk_position).unwrap();
+            chunk.apply_batch(interner, &batch);
+        }
+    }
+
+    /// Stamps a prefab batch at a world voxel position, rotated inside the batch cube.
+    /// The prefab may span several chunks, missing chunks are created on demand.
+    pub fn stamp(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        world_position: IVec3,
+        batch: &Batch<T>,
+        orientation: Orientation,
+    ) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::stamp");
+
+        let lod = Lod::new(0);
+        let batch_voxels_per_axis = batch.voxels_per_axis(lod) as i32;
+        let chunk_voxels_per_axis = self.voxels_per_axis(lod);
+        let max_depth = self.max_depth;
+
+        let mut batches: FxHashMap<IVec3, Batch<T>> = FxHashMap::default();
+
+        let mut stamp_voxel = |position: IVec3, value: T| {
+            let world = world_position.as_i64vec3()
+                + orientation
+                    .rotate_in_cube(position, batch_voxels_per_axis)
+                    .as_i64vec3();
+            let (chunk_position, local_position) =
+                world_voxel_to_chunk(world, chunk_voxels_per_axis);
+
+            batches
+                .entry(chunk_position)
+                .or_insert_with(|| Batch::new(max_depth))
+                .just_set(local_position, value);
+        };
+
+        if let Some(fill_value) = batch.to_fill() {
+            // a filled batch covers its whole cube, patches override the fill
+            for y in 0..batch_voxels_per_axis {
+                for z in 0..batch_voxels_per_axis {
+                    for x in 0..batch_voxels_per_axis {
+                        stamp_voxel(IVec3::new(x, y, z), fill_value);
+                    }
+                }
+            }
+        }
+
+        for (path_index, (set_mask, clear_mask)) in batch.masks().iter().enumerate() {
+            let mut mask_bits = *set_mask | *clear_mask;
+            while mask_bits != 0 {
+                let idx = mask_bits.trailing_zeros() as usize;
+                mask_bits &= !(1 << idx);
+
+                let position = decode_child_index_path(((path_index << 3) | idx) as u32);
+                let value = if *set_mask & (1 << idx) != 0 {
+                    batch.values()[path_index][idx]
+                } else {
+                    T::default()
+                };
+
+                stamp_voxel(position, value);
+            }
+        }
+
+        for (chunk_position, batch) in batches {
+            let chunk = self.get_or_create_chunk(chunk_position);
+            chunk.apply_batch(interner, &batch);
+        }
+    }
+
+    /// Applies edits given in world voxel coordinates, creating missing chunks on demand.
+    pub fn apply_world_edits(&mut self, interner: &mut VoxInterner<T>, edits: &[(IVec3, T)]) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::apply_world_edits");
+
+        let batches = Batch::group_world_edits(self.voxels_per_axis(Lod::new(0)), edits);
+
+        for (chunk_position, batch) in batches {
+            self.get_or_create_chunk(chunk_position)
+                .apply_batch(interner, &batch);
+        }
+    }
+
+    #[cfg(feature = "memory_stats")]
+    pub fn interner_stats(&self) -> InternerStats {
+        self.interner.read().stats()
+    }
+
+    /// Serializes the model into `data` and returns the byte range of every chunk record.
+    pub fn serialize(&self, data: &mut Vec<u8>) -> Vec<(IVec3, Range<usize>)> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::serialize");
+
+        const BUFFER_SIZE: usize = 256;
+
+        let interner = self.interner.read();
+
+        let leaf_patterns = interner.leaf_patterns();
+        let branch_patterns = interner.branch_patterns();
+
+        let mut id_map: FxHashMap<u32, u32> = FxHashMap::default();
+        id_map.insert(0, 0);
+
+        let mut leaf_patterns = leaf_patterns.values().map(|id| *id).collect::<Vec<_>>();
+        let mut branch_patterns = branch_patterns.values().copied().collect::<Vec<_>>();
+
+        let mut next_id = 1;
+
+        leaf_patterns.sort_by_key(|id| id.index());
+        branch_patterns.sort_by_key(|id| id.index());
+
+        leaf_patterns.iter().for_each(|id| {
+            id_map.insert(id.index(), next_id);
+            next_id += 1;
+        });
+
+        branch_patterns.iter().for_each(|id| {
+            let index = id.index();
+            if index == 0 {
+                return;
+            }
+
+            id_map.insert(id.index(), next_id);
+            next_id += 1;
+        });
+
+        let leaf_size = leaf_patterns.len();
+        assert!(leaf_size <= u32::MAX as usize);
+        let branch_size = branch_patterns.len();
+        assert!(branch_size <= u32::MAX as usize);
+        let size = leaf_size + branch_size;
+        assert!(size <= u32::MAX as usize);
+
+        let leaf_size = leaf_size as u32;
+        let branch_size = branch_size as u32;
+
+        let mut writer = std::io::BufWriter::new(data);
+
+        writer.write_u32::<BigEndian>(leaf_size).unwrap();
+        for id in leaf_patterns.iter() {
+            let new_id = *id_map.get(&id.index()).unwrap();
+            // println!(" leaf id: {} -> {new_id}", id.index());
+            let new_id_bytes = encode_varint_u32(new_id);
+            // writer.write_u32::<BigEndian>(new_id).unwrap();
+            writer.write_all(&new_id_bytes).unwrap();
+            let value = interner.get_value(id);
+            value.write_as_be(&mut writer).unwrap();
+        }
+
+        writer.write_u32::<BigEndian>(branch_size - 1).unwrap();
+        for id in branch_patterns.iter() {
+            if id.index() == 0 {
+                continue;
+            }
+
+            let new_id = *id_map.get(&id.index()).unwrap();
+            // println!("branch id: {} -> {new_id}", id.index());
+            let new_id_bytes = encode_varint_u32(new_id);
+            // writer.write_u32::<BigEndian>(new_id).unwrap();
+            writer.write_all(&new_id_bytes).unwrap();
+            writer.write_u8(id.mask()).unwrap();
+            let branch = interner.get_children_ref(id);
+            for child in branch.iter() {
+                if child.is_empty() {
+                    // println!(" empty child");
+                    continue;
+                }
+                let new_id = *id_map.get(&child.index()).unwrap();
+                // println!("  child id: {} -> {new_id}", child.index());
+                let new_id_bytes = encode_varint_u32(new_id);
+                // writer.write_u32::<BigEndian>(new_id).unwrap();
+                writer.write_all(&new_id_bytes).unwrap();
+            }
+            let branch_lod_value = interner.get_value(id);
+            branch_lod_value.write_as_be(&mut writer).unwrap();
+        }
+
+        let chunks_data: Vec<(IVec3, Vec<u8>)> = self
+            .chunks
+            .iter() // .par_iter() needs Send + Sync for VoxelTrait
+            .map(|(position, chunk)| {
+                let mut buffer = Vec::with_capacity(BUFFER_SIZE);
+                serialize_chunk(chunk, &id_map, &mut buffer);
+                (*position, buffer)
+            })
+            .collect();
+
+        let actual_chunks_len = self.chunks.len();
+        writer
+            .write_u32::<BigEndian>(actual_chunks_len as u32)
+            .unwrap();
+        writer.flush().unwrap();
+
+        let mut offset = writer.get_ref().len();
+        let mut chunk_ranges = Vec::with_capacity(chunks_data.len());
+
+        for (position, chunk_data) in chunks_data.iter() {
+            writer.write_all(chunk_data).unwrap();
+
+            chunk_ranges.push((*position, offset..offset + chunk_data.len()));
+            offset += chunk_data.len();
+        }
+
+        chunk_ranges
+    }
+
+    /// Reads node data written by [`VoxModel::serialize`], laid out as in VTM `version`, into
+    /// the model.
+    pub fn deserialize(&mut self, data: &[u8], version: u16) -> Result<(), VtmError> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::deserialize");
+
+        println!("Deserializing chunks...");
+
+        let now = std::time::Instant::now();
+
+        let mut reader = BufReader::new(data);
+
+        let mut interner = self.interner.write();
+
+        let (leaf_patterns, branch_patterns) = deserialize_patterns(&mut interner, &mut reader)?;
+
+        let actual_chunks_len = reader.read_u32::<BigEndian>()?;
+
+        for _ in 0..actual_chunks_len {
+            let chunk = deserialize_chunk(
+                &mut interner,
+                &leaf_patterns,
+                &branch_patterns,
+                &mut reader,
+                self.chunk_world_size,
+                self.max_depth,
+                version,
+            )?;
+
+            self.chunks.insert(chunk.position_3d(), chunk);
+        }
+
+        let elapsed = now.elapsed();
+        println!("Deserializing chunks took {elapsed:?}");
+
+        Ok(())
+    }
+}
+
+/// Reads the leaf and branch tables written by [`VoxModel::serialize`] into `interner`,
+/// leaving `reader` at the chunk count.
+///
+/// Returns the serialized ids mapped to the interned nodes, as used by [`deserialize_chunk`].
+pub(crate) fn deserialize_patterns<T: VoxelTrait, R: Read>(
+    interner: &mut VoxInterner<T>,
+    reader: &mut R,
+) -> Result<(LeafPatterns<T>, BranchPatterns<T>), VtmError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("deserialize_patterns");
+
+    let eof = || VtmError::Io(std::io::ErrorKind::UnexpectedEof.into());
+
+    let leaf_size = reader.read_u32::<BigEndian>()?;
+    let mut leaves = Vec::with_capacity(leaf_size as usize);
+
+    for _ in 0..leaf_size {
+        let id = decode_varint_u32_from_reader(reader).ok_or_else(eof)?;
+        let value = T::read_from_be(reader)?;
+
+        leaves.push((id, value));
+    }
+
+    let leaf_ids = leaves.iter().map(|(id, _)| *id).collect::<FxHashSet<_>>();
+
+    let branch_size = reader.read_u32::<BigEndian>()?;
+    let mut branches = Vec::with_capacity(branch_size as usize);
+
+    for _ in 0..branch_size {
+        let id = decode_varint_u32_from_reader(reader).ok_or_else(eof)?;
+        let mask = reader.read_u8()?;
+        if id == 0 || mask == 0 {
+            return Err(VtmError::InvalidNode(id));
+        }
+
+        let mut types: u8 = 0;
+        let mut children = [0u32; 8];
+        for child_id in 0..8 {
+            if mask & (1 << child_id) == 0 {
+                continue;
+            }
+            children[child_id] = decode_varint_u32_from_reader(reader).ok_or_else(eof)?;
+            if leaf_ids.contains(&children[child_id]) {
+                types |= 1 << child_id;
+            }
+        }
+        let lod_value = T::read_from_be(reader)?;
+
+        branches.push((id, types, mask, children, lod_value));
+    }
+
+    // every child has to be a known node, checked before anything is interned, so a
+    // malformed table leaves no unreferenced nodes behind
+    let branch_ids = branches
+        .iter()
+        .map(|(id, _, _, _, _)| *id)
+        .collect::<FxHashSet<_>>();
+
+    for (_, types, mask, children, _) in branches.iter() {
+        for child_idx in 0..8 {
+            if mask & (1 << child_idx) == 0 || types & (1 << child_idx) != 0 {
+                continue;
+            }
+
+            // id 0 stands for the empty node
+            let child_id = children[child_idx];
+            if child_id != 0 && !branch_ids.contains(&child_id) {
+                return Err(VtmError::InvalidNode(child_id));
+            }
+        }
+    }
+
+    // nodes are interned in file order and have to land on the ids they were written with
+    let ids = leaves
+        .iter()
+        .map(|(id, _)| *id)
+        .chain(branches.iter().map(|(id, _, _, _, _)| *id));
+
+    if let Some((id, _)) = ids
+        .zip(interner.upcoming_indices())
+        .find(|(id, index)| id != index)
+    {
+        return Err(VtmError::InvalidNode(id));
+    }
+
+    let mut leaf_patterns: LeafPatterns<T> = FxHashMap::default();
+
+    for (id, value) in leaves {
+        let block_id = interner.deserialize_leaf(id, value);
+        leaf_patterns.insert(id, (block_id, value));
+    }
+
+    let mut branch_patterns: BranchPatterns<T> = FxHashMap::default();
+
+    branch_patterns.insert(0, (BlockId::EMPTY, [0u32; 8], T::default()));
+
+    for (id, types, mask, children, lod_value) in branches {
+        let block_id = interner.preallocate_branch_id(id, types, mask);
+
+        branch_patterns.insert(id, (block_id, children, lod_value));
+    }
+
+    // resolve the children only after every branch id is known
+    let mut branches = Vec::with_capacity(branch_patterns.len());
+    for (id, (block_id, children, lod_value)) in branch_patterns.iter() {
+        if *id == 0 {
+            continue;
+        }
+
+        let types = block_id.types();
+        let mask = block_id.mask();
+
+        let mut branch = EMPTY_CHILD;
+        for child_idx in 0..8 {
+            if mask & (1 << child_idx) == 0 {
+                continue;
+            }
+
+            let child_id = children[child_idx];
+            branch[child_idx] = if types & (1 << child_idx) != 0 {
+                leaf_patterns[&child_id].0
+            } else {
+                branch_patterns[&child_id].0
+            };
+        }
+
+        branches.push((*block_id, branch, *lod_value));
+    }
+
+    for (block_id, branch, lod_value) in branches {
+        interner.deserialize_branch(
+            block_id,
+            branch,
+            block_id.types(),
+            block_id.mask(),
+            lod_value,
+        );
+    }
+
+    Ok((leaf_patterns, branch_patterns))
+}
+
+impl<T: VoxelTrait> VoxOpsConfig for VoxModel<T> {
+    fn max_depth(&self, lod: Lod) -> MaxDepth {
+        self.max_depth.for_lod(lod)
+    }
+
+    fn voxels_per_axis(&self, lod: Lod) -> u32 {
+        1 << self.max_depth.for_lod(lod).max()
+    }
+}
+
+impl<T: VoxelTrait> VoxOpsChunkConfig for VoxModel<T> {
+    fn chunk_dimensions(&self) -> UVec3 {
+        self.world_bounds.as_uvec3() + UVec3::ONE
+    }
+
+    fn chunk_size(&self) -> Vec3 {
+        self.chunk_world_size
+    }
+
+    fn voxel_size(&self, lod: Lod) -> Vec3 {
+        1.0 / self.voxels_per_axis(lod) as f32 * self.chunk_world_size
+    }
+
+    fn chunk_mesh_offset(&self, chunk_position: IVec3) -> Vec3 {
+        // subtract in integers, so far away chunks don't lose precision before the cast, and
+        // in i64, so chunks on opposite ends of the i32 range don't overflow
+        (chunk_position.as_i64vec3() - self.origin.as_i64vec3()).as_vec3() * self.chunk_world_size
+    }
+}
+
+impl<T: VoxelTrait> VoxOpsChunkLocalContainer<T> for VoxModel<T> {
+    fn has_local_chunk(&self, position: UVec3) -> bool {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::has_local_chunk");
+
+        let position = position.as_ivec3();
+        self.chunks.contains_key(&position)
+    }
+
+    fn local_chunk(&self, position: UVec3) -> Option<&VoxChunk<T>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::chunk");
+
+        let position = position.as_ivec3();
+        self.chunks.get(&position)
+    }
+
+    fn local_chunk_mut(&mut self, position: UVec3) -> Option<&mut VoxChunk<T>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxModel::chunk_mut");
+
+        let position = position.as_ivec3();
+        self.chunks.get_mut(&position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        spatial::{VoxOpsBulkWrite, VoxOpsMesh, VoxOpsWrite},
+        utils::{
+            mesh::{NormalMode, generate_greedy_mesh_arrays_stride},
+            shapes::generate_terrain_batch,
+        },
+        world::PaletteEntry,
+    };
+
+    use super::*;
+
+    fn build_terrain_model() -> VoxModel<i32> {
+        let max_depth = MaxDepth::new(4);
+        let mut model = VoxModel::with_dimensions(
+            max_depth,
+            Vec3::splat(1.0),
+            IVec3::new(2, 1, 2),
+            1024 * 1024,
+        );
+
+        let voxels_per_axis = model.voxels_per_axis(Lod::new(0)) as f32;
+        let voxel_size = model.voxel_size(Lod::new(0));
+
+        let mut interner = model.interner.write();
+
+        for (position, chunk) in model.chunks.iter_mut() {
+            let mut batch = chunk.create_batch();
+            let offset = position.as_vec3() * voxels_per_axis * voxel_size;
+            generate_terrain_batch(&mut batch, voxel_size.x, 25.0, offset, false);
+            chunk.apply_batch(&mut interner, &batch);
+        }
+
+        drop(interner);
+
+        model
+    }
+
+    #[test]
+    fn test_occupied_counts_parallel_matches_brute_force() {
+        let model = build_terrain_model();
+
+        let counts = model.occupied_counts_parallel();
+        assert_eq!(counts.len(), model.chunks.len());
+
+        let interner = model.interner.read();
+        let voxels_per_axis = model.voxels_per_axis(Lod::new(0)) as i32;
+
+        let mut total = 0;
+
+        for (position, chunk) in model.chunks.iter() {
+            let mut expected = 0u64;
+            for y in 0..voxels_per_axis {
+                for z in 0..voxels_per_axis {
+                    for x in 0..voxels_per_axis {
+                        if chunk.get(&interner, IVec3::new(x, y, z)).is_some() {
+                            expected += 1;
+                        }
+                    }
+                }
+            }
+
+            assert_eq!(counts[position], expected, "chunk {position:?}");
+            total += expected;
+        }
+
+        assert!(total > 0);
+    }
+
+    #[test]
+    fn test_extract_shell_hollows_solid_cube() {
+        let max_depth = MaxDepth::new(4);
+        let mut model =
+            VoxModel::<i32>::with_dimensions(max_depth, Vec3::splat(1.0), IVec3::ONE, 1024 * 1024);
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        let chunk = model.chunks.get_mut(&IVec3::ZERO).unwrap();
+        for y in 2..10 {
+            for z in 2..10 {
+                for x in 2..10 {
+                    chunk.set(&mut interner, IVec3::new(x, y, z), 1);
+                }
+            }
+        }
+
+        model.extract_shell(&mut interner);
+
+        let chunk = &model.chunks[&IVec3::ZERO];
+        for y in 2..10 {
+            for z in 2..10 {
+                for x in 2..10 {
+                    let on_shell = [x, y, z].iter().any(|v| *v == 2 || *v == 9);
+                    let value = chunk.get(&interner, IVec3::new(x, y, z));
+
+                    if on_shell {
+                        assert_eq!(value, Some(1), "shell voxel {x},{y},{z}");
+                    } else {
+                        assert_eq!(value, None, "interior voxel {x},{y},{z}");
+                    }
+                }
+            }
+        }
+
+        assert_eq!(
+            count_occupied_voxels(&interner, &chunk.get_root_id(), max_depth),
+            8 * 8 * 8 - 6 * 6 * 6
+        );
+    }
+
+    #[test]
+    fn test_extract_shell_across_chunks() {
+        let max_depth = MaxDepth::new(3);
+        let mut model = VoxModel::<i32>::with_dimensions(
+            max_depth,
+            Vec3::splat(1.0),
+            IVec3::new(2, 1, 1),
+            1024 * 1024,
+        );
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        for chunk in model.chunks.values_mut() {
+            chunk.fill(&mut interner, 1);
+        }
+
+        model.extract_shell(&mut interner);
+
+        let left = &model.chunks[&IVec3::new(0, 0, 0)];
+        let right = &model.chunks[&IVec3::new(1, 0, 0)];
+
+        // voxels touching the shared border are interior now
+        assert_eq!(left.get(&interner, IVec3::new(7, 4, 4)), None);
+        assert_eq!(right.get(&interner, IVec3::new(0, 4, 4)), None);
+
+        // outer faces stay
+        assert_eq!(left.get(&interner, IVec3::new(0, 4, 4)), Some(1));
+        assert_eq!(right.get(&interner, IVec3::new(7, 4, 4)), Some(1));
+        assert_eq!(left.get(&interner, IVec3::new(7, 0, 4)), Some(1));
+    }
+
+    #[test]
+    fn test_builder_validates_settings() {
+        let builder = VoxModelBuilder::new()
+            .max_depth(4)
+            .chunk_world_size(Vec3::new(2.0, 1.0, 2.0))
+            .dimensions(IVec3::new(3, 1, 2))
+            .memory_budget(1024 * 1024);
+
+        let model = builder.build::<i32>().unwrap();
+        assert_eq!(model.max_depth.max(), 4);
+        assert_eq!(model.chunk_world_size, Vec3::new(2.0, 1.0, 2.0));
+        assert_eq!(model.world_bounds, IVec3::new(3, 1, 2));
+        assert_eq!(model.chunks.len(), 6);
+
+        let error = |builder: VoxModelBuilder| builder.build::<i32>().err().unwrap();
+
+        assert_eq!(
+            error(VoxModelBuilder::new().max_depth(4).memory_budget(1024)),
+            BuildError::Missing("chunk_world_size")
+        );
+        assert_eq!(
+            error(builder.clone().max_depth(MAX_ALLOWED_DEPTH as u8)),
+            BuildError::MaxDepthTooLarge(MAX_ALLOWED_DEPTH as u8)
+        );
+        for size in [Vec3::new(1.0, 0.0, 1.0), Vec3::splat(-1.0), Vec3::NAN] {
+            assert!(matches!(
+                error(builder.clone().chunk_world_size(size)),
+                BuildError::InvalidChunkSize(_)
+            ));
+        }
+        assert_eq!(
+            error(builder.clone().dimensions(IVec3::new(1, -1, 1))),
+            BuildError::InvalidDimensions(IVec3::new(1, -1, 1))
+        );
+
+        // a chunk with a single voxel already needs one node per level
+        let node_size = VoxInterner::<i32>::node_size();
+        let dimensions = IVec3::new(64, 8, 64);
+        let required = (64 * 8 * 64 * 5 + 1) * node_size;
+        assert_eq!(
+            error(builder.clone().dimensions(dimensions)),
+            BuildError::BudgetTooSmall {
+                budget: 1024 * 1024,
+                required
+            }
+        );
+        let grown = builder
+            .clone()
+            .dimensions(dimensions)
+            .max_memory_budget(required)
+            .build::<i32>()
+            .unwrap();
+        assert_eq!(grown.chunks.len(), 64 * 8 * 64);
+
+        assert_eq!(
+            error(builder.clone().max_memory_budget(1024)),
+            BuildError::BudgetTooSmall {
+                budget: 1024,
+                required: 1024 * 1024
+            }
+        );
+        assert!(matches!(
+            error(builder.clone().memory_budget(usize::MAX)),
+            BuildError::BudgetTooLarge(usize::MAX)
+        ));
+        assert!(
+            error(builder.memory_budget(1))
+                .to_string()
+                .contains("too small")
+        );
+    }
+
+    #[test]
+    fn test_apply_world_edits() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+
+        let edits = [
+            (IVec3::new(7, 0, 0), 1),
+            (IVec3::new(8, 0, 0), 2),
+            (IVec3::new(-1, -1, -1), 3),
+            (IVec3::new(15, 7, 0), 4),
+            (IVec3::new(7, 0, 0), 5),
+        ];
+
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+            model.apply_world_edits(&mut interner, &edits);
+        }
+
+        assert_eq!(model.chunks.len(), 3);
+
+        let interner = model.interner.read();
+        let get = |chunk: IVec3, position: IVec3| model.chunks[&chunk].get(&interner, position);
+
+        // the later edit of the same voxel wins
+        assert_eq!(get(IVec3::ZERO, IVec3::new(7, 0, 0)), Some(5));
+        assert_eq!(get(IVec3::new(1, 0, 0), IVec3::new(0, 0, 0)), Some(2));
+        assert_eq!(get(IVec3::new(1, 0, 0), IVec3::new(7, 7, 0)), Some(4));
+        assert_eq!(get(IVec3::splat(-1), IVec3::splat(7)), Some(3));
+    }
+
+    #[test]
+    fn test_prune_empty_chunks() {
+        let mut model = VoxModel::<i32>::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::ONE,
+            IVec3::new(2, 1, 1),
+            1024 * 1024,
+        );
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        let chunk = model.get_or_create_chunk(IVec3::X);
+        chunk.fill(&mut interner, 5);
+        model
+            .get_or_create_chunk(IVec3::Z)
+            .set(&mut interner, IVec3::ONE, 2);
+        model.take_dirty_chunks();
+
+        // the untouched chunk of the dimensions goes, the filled ones stay
+        assert_eq!(model.prune_empty_chunks(), 1);
+        assert_eq!(model.chunks.len(), 2);
+
+        // cleared, but its mesh has not been dropped yet
+        model.get_or_create_chunk(IVec3::X).clear(&mut interner);
+        assert_eq!(model.prune_empty_chunks(), 0);
+        assert!(model.chunks.contains_key(&IVec3::X));
+
+        assert_eq!(model.take_dirty_chunks(), vec![IVec3::X]);
+        assert_eq!(model.prune_empty_chunks(), 1);
+        assert_eq!(
+            model.chunks.keys().copied().collect::<Vec<_>>(),
+            vec![IVec3::Z]
+        );
+        assert_eq!(model.prune_empty_chunks(), 0);
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_take_dirty_chunks() {
+        let mut model = build_terrain_model();
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        assert!(!model.take_dirty_chunks().is_empty());
+        assert!(model.take_dirty_chunks().is_empty());
+
+        let chunk = model.chunks.get_mut(&IVec3::new(1, 0, 0)).unwrap();
+        assert!(chunk.set(&mut interner, IVec3::new(3, 15, 3), 7));
+        assert_eq!(model.take_dirty_chunks(), vec![IVec3::new(1, 0, 0)]);
+
+        model.apply_world_edits(&mut interner, &[(IVec3::new(2, 15, 17), 7)]);
+        assert_eq!(model.take_dirty_chunks(), vec![IVec3::new(0, 0, 1)]);
+        assert!(model.take_dirty_chunks().is_empty());
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+
+        {
+            let interner = model.get_interner();
+            let mut interner = interner.write();
+
+            let edits = (0..8)
+                .map(|i| (IVec3::new(i, 0, 0), 1))
+                .chain([(IVec3::new(8, 0, 0), 2)])
+                .collect::<Vec<_>>();
+            model.apply_world_edits(&mut interner, &edits);
+            model.get_or_create_chunk(IVec3::new(0, 1, 0));
+        }
+
+        // leaves 1 and 2, the empty branch and 3 deduplicated branch levels per non-empty chunk
+        let node_size = VoxInterner::<i32>::node_size();
+        let interner_bytes = ByteSize((2 + 1 + 3 + 3) * node_size);
+
+        assert_eq!(
+            model.summary(),
+            format!(
+                "chunks: 3 (non-empty: 2), voxels: 9, depth: 3, chunk size: 8m, interner: {interner_bytes}"
+            )
+        );
+
+        let interner = model.interner.read();
+        assert_eq!(model.total_voxel_count(&interner), 9);
+        assert_eq!(model.chunks[&IVec3::ZERO].voxel_count(&interner), 8);
+        assert_eq!(model.chunks[&IVec3::X].voxel_count(&interner), 1);
+    }
+
+    #[test]
+    fn test_surface_area() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        model.apply_world_edits(&mut interner, &[(IVec3::new(3, 3, 3), 1)]);
+        assert_eq!(model.surface_area(&interner, Lod::new(0)), 6.0);
+
+        // a pair inside one chunk
+        model.apply_world_edits(&mut interner, &[(IVec3::new(4, 3, 3), 2)]);
+        assert_eq!(model.surface_area(&interner, Lod::new(0)), 10.0);
+
+        // a pair across the seam between two chunks, along every axis
+        for offset in [IVec3::X, IVec3::Y, IVec3::Z] {
+            for chunk in model.chunks.values_mut() {
+                chunk.clear(&mut interner);
+            }
+            model.chunks.clear();
+
+            let first = IVec3::splat(7);
+            model.apply_world_edits(&mut interner, &[(first, 1), (first + offset, 1)]);
+            assert_eq!(model.chunks.len(), 2);
+            assert_eq!(model.surface_area(&interner, Lod::new(0)), 10.0);
+        }
+
+        // faces scale with the voxel size
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(4.0), 1024 * 1024);
+        model.apply_world_edits(&mut interner, &[(IVec3::ZERO, 1)]);
+        assert_eq!(model.surface_area(&interner, Lod::new(0)), 6.0 * 0.25);
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_sparse_chunk_is_skipped_at_coarse_lod() {
+        let mut model = VoxModel::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::splat(8.0),
+            IVec3::new(2, 1, 1),
+            1024 * 1024,
+        );
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // a single voxel is outvoted by its empty siblings once the octree is cut above it
+        model.apply_world_edits(&mut interner, &[(IVec3::new(3, 3, 3), 1)]);
+
+        let chunk = &model.chunks[&IVec3::ZERO];
+        assert!(!chunk.is_empty());
+        assert!(!chunk.is_empty_at_lod(&interner, Lod::new(0)));
+        assert!(chunk.is_empty_at_lod(&interner, Lod::new(1)));
+        assert!(chunk.is_empty_at_lod(&interner, Lod::new(2)));
+
+        let mut fine = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(0),
+            &mut fine,
+            false,
+            NormalMode::Flat,
+        );
+        assert!(!fine.vertices.is_empty());
+
+        let mut coarse = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(1),
+            &mut coarse,
+            false,
+            NormalMode::Flat,
+        );
+        assert!(coarse.vertices.is_empty());
+        assert!(coarse.indices.is_empty());
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_unified_mesh_merges_across_chunks() {
+        let mut model = VoxModel::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::splat(8.0),
+            IVec3::new(2, 1, 2),
+            1024 * 1024,
+        );
+        let interner = model.get_interner();
+
+        // a flat floor covering the bottom layer of all four chunks
+        let floor = (0..16)
+            .flat_map(|z| (0..16).map(move |x| (IVec3::new(x, 0, z), 1)))
+            .collect::<Vec<_>>();
+        model.apply_world_edits(&mut interner.write(), &floor);
+
+        let up_quads = |mesh: &MeshData| mesh.normals.iter().filter(|n| **n == Vec3::Y).count() / 4;
+
+        let mut per_chunk = 0;
+        for chunk in model.chunks.values() {
+            let mut mesh = MeshData::default();
+            chunk.generate_greedy_mesh_arrays(&interner.read(), &mut mesh, Vec3::ZERO, Lod::new(0));
+            per_chunk += up_quads(&mesh);
+        }
+        assert_eq!(per_chunk, 4);
+
+        let unified = model.generate_unified_mesh(Lod::new(0)).unwrap();
+        assert_eq!(up_quads(&unified), 1);
+        // top, bottom and four sides
+        assert_eq!(unified.indices.len(), 6 * 6);
+
+        let min = unified.vertices.iter().fold(Vec3::MAX, |a, v| a.min(*v));
+        let max = unified.vertices.iter().fold(Vec3::MIN, |a, v| a.max(*v));
+        assert_eq!(min, Vec3::ZERO);
+        assert_eq!(max, Vec3::new(16.0, 1.0, 16.0));
+
+        assert_eq!(
+            model.get_world_voxel(&interner.read(), IVec3::new(12, 0, 3), Lod::new(0)),
+            Some(1)
+        );
+        assert_eq!(
+            model.get_world_voxel(&interner.read(), IVec3::new(12, 1, 3), Lod::new(0)),
+            None
+        );
+
+        let mut interner = interner.write();
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_unified_mesh_palette_negative_value() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+        let interner = model.get_interner();
+
+        // three single voxels apart, the last one without an entry
+        model.apply_world_edits(
+            &mut interner.write(),
+            &[
+                (IVec3::ZERO, -1),
+                (IVec3::new(2, 0, 0), 2),
+                (IVec3::new(4, 0, 0), 3),
+            ],
+        );
+        model
+            .palette
+            .insert(-1, PaletteEntry::new("void", [1.0, 0.0, 0.0, 1.0]));
+        model
+            .palette
+            .insert(2, PaletteEntry::new("water", [0.0, 0.0, 1.0, 1.0]));
+
+        let unified = model.generate_unified_mesh(Lod::new(0)).unwrap();
+        assert_eq!(unified.colors.len(), unified.vertices.len());
+        assert_eq!(unified.vertices.len(), 3 * 6 * 4);
+
+        for (vertex, color) in unified.vertices.iter().zip(&unified.colors) {
+            let expected = match vertex.x {
+                x if x <= 1.0 => [1.0, 0.0, 0.0, 1.0],
+                x if x <= 3.0 => [0.0, 0.0, 1.0, 1.0],
+                _ => [1.0; 4],
+            };
+            assert_eq!(*color, expected, "Wrong color at {vertex}");
+        }
+
+        let mut interner = interner.write();
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_unified_mesh_rejects_huge_grid() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+
+        // two voxels in chunks far apart span a grid of 2^26 x 8 x 8 voxels
+        model.apply_world_edits(
+            &mut model.get_interner().write(),
+            &[(IVec3::ZERO, 1), (IVec3::new(1 << 26, 0, 0) - IVec3::X, 1)],
+        );
+
+        assert_eq!(
+            model.generate_unified_mesh(Lod::new(0)).err(),
+            Some(UnifiedMeshTooLarge {
+                size: U64Vec3::new(1 << 26, 8, 8)
+            })
+        );
+
+        // the opposite ends of the chunk range don't overflow either
+        model.apply_world_edits(
+            &mut model.get_interner().write(),
+            &[(IVec3::MIN, 1), (IVec3::MAX, 1)],
+        );
+        assert!(model.generate_unified_mesh(Lod::new(0)).is_err());
+    }
+
+    #[test]
+    fn test_chunk_neighbors() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(2), Vec3::ONE, 1024 * 1024);
+        for i in 0..8 {
+            model.get_or_create_chunk(IVec3::new(i & 1, (i >> 1) & 1, i >> 2));
+        }
+
+        let present = |neighbors: &[Option<&VoxChunk<i32>>]| {
+            neighbors
+                .iter()
+                .flatten()
+                .map(|chunk| chunk.position_3d())
+                .collect::<Vec<_>>()
+        };
+
+        // a corner of the 2x2x2 model touches the other seven chunks, three of them by a face
+        assert_eq!(
+            present(&model.chunk_neighbors(IVec3::ZERO)),
+            vec![IVec3::X, IVec3::Y, IVec3::Z]
+        );
+        assert_eq!(
+            present(&model.chunk_neighbors_26(IVec3::ZERO)),
+            vec![
+                IVec3::new(1, 0, 0),
+                IVec3::new(0, 1, 0),
+                IVec3::new(1, 1, 0),
+                IVec3::new(0, 0, 1),
+                IVec3::new(1, 0, 1),
+                IVec3::new(0, 1, 1),
+                IVec3::new(1, 1, 1),
+            ]
+        );
+        assert_eq!(
+            present(&model.chunk_neighbors(IVec3::ONE)),
+            vec![
+                IVec3::new(0, 1, 1),
+                IVec3::new(1, 0, 1),
+                IVec3::new(1, 1, 0)
+            ]
+        );
+
+        // offsets are in the documented order and each way round
+        assert!(
+            CHUNK_NEIGHBORS_26
+                .windows(2)
+                .all(|pair| (pair[0].z, pair[0].y, pair[0].x) < (pair[1].z, pair[1].y, pair[1].x))
+        );
+        assert!(
+            CHUNK_NEIGHBORS_26
+                .iter()
+                .all(|offset| *offset != IVec3::ZERO
+                    && offset.abs().max_element() == 1
+                    && CHUNK_NEIGHBORS_26.contains(&-*offset))
+        );
+        assert!(
+            CHUNK_FACE_NEIGHBORS
+                .iter()
+                .all(|offset| CHUNK_NEIGHBORS_26.contains(offset))
+        );
+
+        // outside the model, only the chunk inside is found
+        let outside = model.chunk_neighbors_26(IVec3::splat(-1));
+        assert_eq!(present(&outside), vec![IVec3::ZERO]);
+        assert!(
+            model
+                .chunk_neighbors(IVec3::splat(5))
+                .iter()
+                .all(Option::is_none)
+        );
+    }
+
+    #[test]
+    fn test_chunk_hashes() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::ONE, 1024 * 1024);
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // the same voxels, set in a different order
+        for i in 0..8 {
+            let position = IVec3::new(i, 7 - i, i / 2);
+            model
+                .get_or_create_chunk(IVec3::ZERO)
+                .set(&mut interner, position, 3);
+            model.get_or_create_chunk(IVec3::X).set(
+                &mut interner,
+                IVec3::new(7 - i, i, (7 - i) / 2),
+                3,
+            );
+        }
+        model.get_or_create_chunk(IVec3::Y);
+        model.get_or_create_chunk(IVec3::Z);
+
+        let hashes = model.chunk_hashes();
+        assert_eq!(hashes.len(), 4);
+        assert_eq!(hashes[&IVec3::ZERO], hashes[&IVec3::X]);
+        assert_eq!(hashes[&IVec3::Y], hashes[&IVec3::Z]);
+        assert_ne!(hashes[&IVec3::ZERO], hashes[&IVec3::Y]);
+
+        // an edit only changes the hash of its chunk
+        model
+            .get_or_create_chunk(IVec3::X)
+            .set(&mut interner, IVec3::ZERO, 1);
+        let edited = model.chunk_hashes();
+        let changed = hashes
+            .iter()
+            .filter(|(position, hash)| edited[*position] != **hash)
+            .map(|(position, _)| *position)
+            .collect::<Vec<_>>();
+        assert_eq!(changed, vec![IVec3::X]);
+
+        // only the content counts, not the position
+        let mut other = VoxChunk::<i32>::with_position(Vec3::ONE, MaxDepth::new(3), 5, 5, 5);
+        assert_eq!(other.content_hash(), hashes[&IVec3::Y]);
+        for i in 0..8 {
+            other.set(&mut interner, IVec3::new(i, 7 - i, i / 2), 3);
+        }
+        assert_eq!(other.content_hash(), hashes[&IVec3::ZERO]);
+
+        other.clear(&mut interner);
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_chunk_mesh_with_neighbors_skips_shared_plane() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(1.0), 1024 * 1024);
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        model
+            .get_or_create_chunk(IVec3::ZERO)
+            .fill(&mut interner, 1);
+        model.get_or_create_chunk(IVec3::X).fill(&mut interner, 2);
+
+        let faces = |mesh: &MeshData, normal: Vec3| {
+            mesh.normals.iter().filter(|n| **n == normal).count() / 4
+        };
+
+        let mut isolated = MeshData::default();
+        model.chunks[&IVec3::ZERO].generate_greedy_mesh_arrays(
+            &interner,
+            &mut isolated,
+            Vec3::ZERO,
+            Lod::new(0),
+        );
+        assert_eq!(faces(&isolated, Vec3::X), 1);
+
+        let mut left = MeshData::default();
+        model.generate_chunk_mesh_with_neighbors(&interner, IVec3::ZERO, Lod::new(0), &mut left);
+        let mut right = MeshData::default();
+        model.generate_chunk_mesh_with_neighbors(&interner, IVec3::X, Lod::new(0), &mut right);
+
+        assert_eq!(faces(&left, Vec3::X), 0);
+        assert_eq!(faces(&right, Vec3::NEG_X), 0);
+        // the remaining five sides of each chunk
+        assert_eq!(left.indices.len(), 5 * 6);
+        assert_eq!(right.indices.len(), 5 * 6);
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_generate_all_meshes_parallel_matches_per_chunk() {
+        let mut model = build_terrain_model();
+        // an empty chunk is skipped
+        model.get_or_create_chunk(IVec3::new(0, 3, 0));
+        model.set_origin(IVec3::new(1, 0, 0));
+
+        let interner = model.get_interner();
+        let interner = interner.read();
+
+        for lod in [Lod::new(0), Lod::new(1)] {
+            let meshes = model.generate_all_meshes_parallel(&interner, lod);
+            assert_eq!(
+                meshes
+                    .iter()
+                    .map(|(position, _, _)| *position)
+                    .collect::<Vec<_>>(),
+                [
+                    IVec3::new(0, 0, 0),
+                    IVec3::new(1, 0, 0),
+                    IVec3::new(0, 0, 1),
+                    IVec3::new(1, 0, 1)
+                ]
+            );
+
+            for (position, offset, mesh) in meshes.iter() {
+                assert!(!mesh.indices.is_empty());
+                assert_eq!(*offset, model.chunk_mesh_offset(*position));
+
+                let mut expected = MeshData::default();
+                model.generate_chunk_mesh_with_neighbors(&interner, *position, lod, &mut expected);
+
+                assert_eq!(mesh.indices, expected.indices);
+                assert_eq!(mesh.normals, expected.normals);
+                for (vertex, expected) in mesh.vertices.iter().zip(&expected.vertices) {
+                    assert!((*vertex + *offset).abs_diff_eq(*expected, 1e-5));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks_along_ray() {
+        // marches in tiny steps and records every chunk entered
+        fn reference(origin: Vec3, dir: Vec3, max_dist: f32, chunk_size: f32) -> Vec<IVec3> {
+            let dir = dir.normalize();
+            let steps = (max_dist / 1e-3) as usize;
+
+            let mut chunks = Vec::<IVec3>::new();
+            for i in 0..=steps {
+                let point = origin + dir * (i as f32 * 1e-3);
+                let chunk = (point / chunk_size).floor().as_ivec3();
+                if chunks.last() != Some(&chunk) {
+                    chunks.push(chunk);
+                }
+            }
+
+            chunks
+        }
+
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(2.0), 1024 * 1024);
+
+        let chunks = model
+            .chunks_along_ray(Vec3::new(1.0, 1.0, 1.0), Vec3::X, 6.5)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            [0, 1, 2, 3].map(|x| IVec3::new(x, 0, 0)).to_vec(),
+            "axis-aligned"
+        );
+        assert_eq!(
+            model
+                .chunks_along_ray(Vec3::new(1.0, 1.0, 1.0), Vec3::NEG_Z, 6.5)
+                .collect::<Vec<_>>(),
+            reference(Vec3::new(1.0, 1.0, 1.0), Vec3::NEG_Z, 6.5, 2.0)
+        );
+
+        let origin = Vec3::new(0.3, -1.7, 5.1);
+        let dir = Vec3::new(1.0, 0.55, -0.35);
+        let chunks = model
+            .chunks_along_ray(origin, dir, 15.0)
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, reference(origin, dir, 15.0, 2.0), "diagonal");
+        assert!(chunks.len() > 8);
+
+        // chunk positions follow the mesh origin
+        model.set_origin(IVec3::new(10, 0, -4));
+        let shifted = model
+            .chunks_along_ray(origin, dir, 15.0)
+            .map(|chunk| chunk - IVec3::new(10, 0, -4))
+            .collect::<Vec<_>>();
+        assert_eq!(shifted, chunks);
+
+        assert_eq!(model.chunks_along_ray(origin, dir, -1.0).count(), 0);
+        assert_eq!(model.chunks_along_ray(origin, Vec3::ZERO, 10.0).count(), 1);
+    }
+
+    #[test]
+    fn test_reserve_chunks() {
+        // counts how often the chunk map grows while inserting `count` chunks
+        fn reallocations(model: &mut VoxModel<i32>, count: i32) -> usize {
+            let mut capacity = model.chunks.capacity();
+            let mut reallocations = 0;
+
+            for x in 0..count {
+                model.get_or_create_chunk(IVec3::new(x, 0, 0));
+                if model.chunks.capacity() != capacity {
+                    capacity = model.chunks.capacity();
+                    reallocations += 1;
+                }
+            }
+
+            reallocations
+        }
+
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(1.0), 1024 * 1024);
+        assert!(reallocations(&mut model, 1000) > 0);
+
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(1.0), 1024 * 1024);
+        model.reserve_chunks(1000);
+        assert_eq!(reallocations(&mut model, 1000), 0);
+
+        model.chunks.retain(|position, _| position.x < 10);
+        model.shrink_to_fit();
+        assert!(model.chunks.capacity() < 1000);
+        assert_eq!(model.chunks.len(), 10);
+    }
+
+    #[test]
+    fn test_extract_window_across_chunks() {
+        let mut model = VoxModel::<i32>::empty(MaxDepth::new(3), Vec3::splat(8.0), 1024 * 1024);
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // x = 7 is the last voxel of chunk 0, x = 8 the first of chunk 1
+        model.apply_world_edits(
+            &mut interner,
+            &[
+                (IVec3::new(6, 2, 2), 1),
+                (IVec3::new(7, 3, 2), 2),
+                (IVec3::new(8, 2, 2), 3),
+                (IVec3::new(9, 2, 1), 4),
+            ],
+        );
+        assert_eq!(model.chunks.len(), 2);
+
+        let center = IVec3::new(7, 2, 2);
+        let window = model.extract_window(&interner, center, 2);
+        assert_eq!(window.len(), 5 * 5 * 5);
+
+        let at = |offset: IVec3| {
+            let index = offset + IVec3::splat(2);
+            window[((index.y * 5 + index.z) * 5 + index.x) as usize]
+        };
+
+        assert_eq!(at(IVec3::new(-1, 0, 0)), Some(1));
+        assert_eq!(at(IVec3::new(0, 1, 0)), Some(2));
+        assert_eq!(at(IVec3::new(1, 0, 0)), Some(3));
+        assert_eq!(at(IVec3::new(2, 0, -1)), Some(4));
+        assert_eq!(at(IVec3::ZERO), None);
+        assert_eq!(window.iter().flatten().count(), 4);
+
+        // reaches into chunks that do not exist
+        let window = model.extract_window(&interner, IVec3::ZERO, 1);
+        assert_eq!(window.len(), 27);
+        assert!(window.iter().all(Option::is_none));
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_deduplicate_chunks() {
+        let mut model = VoxModel::<i32>::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::splat(8.0),
+            IVec3::new(3, 1, 1),
+            1024 * 1024,
+        );
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // the same pillar built separately in the first two chunks, something else in the third
+        for x in [0, 8] {
+            for y in 0..8 {
+                model.apply_world_edits(&mut interner, &[(IVec3::new(x + 2, y, 3), 1)]);
+            }
+        }
+        model.apply_world_edits(&mut interner, &[(IVec3::new(17, 0, 0), 2)]);
+
+        let first = model.chunks[&IVec3::ZERO].get_root_id();
+        assert_eq!(first, model.chunks[&IVec3::X].get_root_id());
+
+        let report = model.deduplicate_chunks(&interner);
+        assert_eq!(report.unique_chunks, 2);
+        assert_eq!(report.duplicate_chunks, 1);
+        assert_eq!(report.groups, vec![(first, vec![IVec3::ZERO, IVec3::X])]);
+        assert_eq!(report.nodes_saved, interner.count_nodes(first) as u64);
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_crop_terrain() {
+        let model = build_terrain_model();
+        let interner = model.interner.read();
+        let lod = Lod::new(0);
+
+        // exactly the chunk at (1, 0, 1)
+        let tile = model.crop(&interner, IVec3::new(16, 0, 16), IVec3::new(31, 15, 31));
+        assert_eq!(tile.chunks.len(), 1);
+        assert_eq!(tile.world_bounds, IVec3::ONE);
+
+        let tile_interner = tile.interner.read();
+        assert_eq!(
+            to_vec(
+                &tile_interner,
+                &tile.chunks[&IVec3::ZERO].get_root_id(),
+                model.max_depth
+            ),
+            to_vec(
+                &interner,
+                &model.chunks[&IVec3::new(1, 0, 1)].get_root_id(),
+                model.max_depth
+            )
+        );
+        drop(tile_interner);
+
+        // part of two chunks, rebased by one chunk along x
+        let (min, max) = (IVec3::new(20, 0, 3), IVec3::new(31, 10, 20));
+        let cropped = model.crop(&interner, min, max);
+        assert_eq!(cropped.world_bounds, IVec3::new(1, 1, 2));
+
+        let cropped_interner = cropped.interner.read();
+        for y in 0..16 {
+            for z in 0..32 {
+                for x in 0..16 {
+                    let position = IVec3::new(x, y, z);
+                    let source = position + IVec3::new(16, 0, 0);
+
+                    let expected = if source.cmpge(min).all() && source.cmple(max).all() {
+                        model.get_world_voxel(&interner, source, lod)
+                    } else {
+                        None
+                    };
+                    assert_eq!(
+                        cropped.get_world_voxel(&cropped_interner, position, lod),
+                        expected,
+                        "{position}"
+                    );
+                }
+            }
+        }
+        assert!(cropped.total_voxel_count(&cropped_interner) > 0);
+    }
+
+    #[test]
+    fn test_crop_at_i32_limits() {
+        let model = build_terrain_model();
+        let interner = model.interner.read();
+
+        let cropped = model.crop(&interner, IVec3::MIN, IVec3::MAX);
+        let cropped_interner = cropped.interner.read();
+
+        assert_eq!(cropped.world_bounds, IVec3::splat(1 << 28));
+        assert_eq!(
+            cropped.total_voxel_count(&cropped_interner),
+            model.total_voxel_count(&interner)
+        );
+    }
+
+    #[test]
+    fn test_chunk_mesh_offset_at_i32_limits() {
+        let mut model = build_terrain_model();
+
+        model.set_origin(IVec3::MIN);
+        assert_eq!(
+            model.chunk_mesh_offset(IVec3::MAX),
+            Vec3::splat(u32::MAX as f32) * model.chunk_world_size
+        );
+    }
+
+    #[test]
+    fn test_classify_region() {
+        const MAX_DEPTH: MaxDepth = MaxDepth::new(6);
+
+        let mut model = VoxModel::<i32>::empty(MAX_DEPTH, Vec3::splat(64.0), 1024 * 1024);
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // the lower half of chunk 0 filled voxel by voxel, it collapses into a single octant leaf
+        let mut batch = model.get_or_create_chunk(IVec3::ZERO).create_batch();
+        for y in 0..32 {
+            for z in 0..64 {
+                for x in 0..64 {
+                    batch.set(&mut interner, IVec3::new(x, y, z), 1);
+                }
+            }
+        }
+        model
+            .get_or_create_chunk(IVec3::ZERO)
+            .apply_batch(&mut interner, &batch);
+        // and chunk 1 fully solid
+        model.get_or_create_chunk(IVec3::X).fill(&mut interner, 2);
+
+        let mut visited = 0;
+        let class = model.classify_region_counted(
+            &interner,
+            IVec3::new(5, 3, 7),
+            IVec3::new(20, 30, 40),
+            &mut visited,
+        );
+        assert_eq!(class, RegionClass::Solid);
+        assert!(
+            visited <= MAX_DEPTH.max() as usize,
+            "visited {visited} nodes"
+        );
+
+        // across the seam into the filled chunk
+        assert_eq!(
+            model.classify_region(&interner, IVec3::new(60, 0, 0), IVec3::new(100, 31, 63)),
+            RegionClass::Solid
+        );
+        assert_eq!(
+            model.classify_region(&interner, IVec3::new(0, 32, 0), IVec3::new(63, 63, 63)),
+            RegionClass::Empty
+        );
+        assert_eq!(
+            model.classify_region(&interner, IVec3::new(10, 31, 10), IVec3::new(10, 32, 10)),
+            RegionClass::Mixed
+        );
+        // chunks that do not exist are empty
+        assert_eq!(
+            model.classify_region(&interner, IVec3::new(0, 64, 0), IVec3::new(200, 300, 10)),
+            RegionClass::Empty
+        );
+        assert_eq!(
+            model.classify_region(&interner, IVec3::new(-4, 0, 0), IVec3::new(4, 4, 4)),
+            RegionClass::Mixed
+        );
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_set_origin_shifts_mesh_vertices() {
+        let mut model = build_terrain_model();
+        let interner = model.get_interner();
+
+        let roots = model
+            .chunks
+            .iter()
+            .map(|(position, chunk)| (*position, chunk.get_root_id()))
+            .collect::<FxHashMap<_, _>>();
+
+        let mut before = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner.read(),
+            Lod::new(0),
+            &mut before,
+            false,
+            NormalMode::Flat,
+        );
+
+        let origin = IVec3::new(1, 0, 2);
+        model.set_origin(origin);
+        assert_eq!(model.origin(), origin);
+        assert_eq!(model.chunk_mesh_offset(origin), Vec3::ZERO);
+
+        let mut after = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner.read(),
+            Lod::new(0),
+            &mut after,
+            false,
+            NormalMode::Flat,
+        );
+
+        assert!(!before.vertices.is_empty());
+        assert_eq!(before.vertices.len(), after.vertices.len());
+        assert_eq!(before.indices, after.indices);
+
+        let shift = origin.as_vec3() * model.chunk_world_size;
+        for (before, after) in before.vertices.iter().zip(&after.vertices) {
+            assert!((*before - shift).abs_diff_eq(*after, 1e-5));
+        }
+
+        for (position, chunk) in model.chunks.iter() {
+            assert_eq!(roots[position], chunk.get_root_id());
+        }
+    }
+
+    #[test]
+    fn test_stamp_rotated_l_shape() {
+        let max_depth = MaxDepth::new(3);
+        let mut model = VoxModel::<i32>::with_dimensions(
+            max_depth,
+            Vec3::splat(1.0),
+            IVec3::new(2, 1, 1),
+            1024 * 1024,
+        );
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // L-shaped prefab in a 4x4x4 batch: a long arm along X and a short one along Y
+        let mut prefab = Batch::new(MaxDepth::new(2));
+        prefab.just_set(IVec3::new(0, 0, 0), 1);
+        prefab.just_set(IVec3::new(1, 0, 0), 2);
+        prefab.just_set(IVec3::new(2, 0, 0), 2);
+        prefab.just_set(IVec3::new(0, 1, 0), 3);
+
+        // a quarter turn around Z maps X to Y and Y to -X
+        let orientation = Orientation::from_quarter_turns(0, 0, 1);
+        let world_position = IVec3::new(5, 0, 2);
+
+        model.stamp(&mut interner, world_position, &prefab, orientation);
+
+        let expected = [
+            (IVec3::new(8, 0, 2), 1),
+            (IVec3::new(8, 1, 2), 2),
+            (IVec3::new(8, 2, 2), 2),
+            (IVec3::new(7, 0, 2), 3),
+        ];
+
+        let get_world = |position: IVec3| {
+            let chunk = &model.chunks[&position.div_euclid(IVec3::splat(8))];
+            chunk.get(&interner, position.rem_euclid(IVec3::splat(8)))
+        };
+
+        for (position, value) in expected {
+            assert_eq!(get_world(position), Some(value), "{position:?}");
+        }
+
+        let total: u64 = model
+            .chunks
+            .values()
+            .map(|chunk| count_occupied_voxels(&interner, &chunk.get_root_id(), max_depth))
+            .sum();
+        assert_eq!(total, expected.len() as u64);
+    }
+
+    #[test]
+    fn test_world_pos_to_voxel_from_mesh_vertex() {
+        let mut model = VoxModel::<i32>::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::splat(2.0),
+            IVec3::new(2, 1, 1),
+            1024 * 1024,
+        );
+        model.set_origin(IVec3::new(1, 0, -1));
+
+        let voxel = IVec3::new(13, 5, 6);
+        let (chunk_position, local_position) =
+            world_voxel_to_chunk(voxel.as_i64vec3(), model.voxels_per_axis(Lod::new(0)));
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        model
+            .get_or_create_chunk(chunk_position)
+            .set(&mut interner, local_position, 1);
+
+        let mut mesh_data = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(0),
+            &mut mesh_data,
+            false,
+            NormalMode::Flat,
+        );
+
+        // the lowest vertex of a single voxel cube is its minimum corner
+        let corner = mesh_data
+            .vertices
+            .iter()
+            .copied()
+            .reduce(Vec3::min)
+            .unwrap();
+        assert!(corner.abs_diff_eq(model.voxel_to_world_pos(voxel, Lod::new(0)), 1e-5));
+        assert_eq!(model.world_pos_to_voxel(corner, Lod::new(0)), voxel);
+
+        // a point inside the voxel maps to it as well
+        let center = corner + model.voxel_size(Lod::new(0)) * 0.5;
+        assert_eq!(model.world_pos_to_voxel(center, Lod::new(0)), voxel);
+
+        for lod in [Lod::new(0), Lod::new(1), Lod::new(2)] {
+            for voxel in [IVec3::ZERO, IVec3::new(-9, 3, 17), IVec3::new(4, -1, -6)] {
+                let world_pos = model.voxel_to_world_pos(voxel, lod);
+                assert_eq!(model.world_pos_to_voxel(world_pos, lod), voxel);
+            }
+        }
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_chunks_in_frustum() {
+        let mut model = VoxModel::<i32>::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::splat(2.0),
+            IVec3::new(1, 1, 5),
+            1024 * 1024,
+        );
+
+        // camera at (1, 1, 3) looking down +z, a 90 degree frustum from z = 3 to 7.5
+        let planes = [
+            Vec4::new(0.0, 0.0, 1.0, -3.0),
+            Vec4::new(0.0, 0.0, -1.0, 7.5),
+            Vec4::new(1.0, 0.0, 1.0, -4.0),
+            Vec4::new(-1.0, 0.0, 1.0, -2.0),
+            Vec4::new(0.0, 1.0, 1.0, -4.0),
+            Vec4::new(0.0, -1.0, 1.0, -2.0),
+        ];
+
+        // chunk 0 is fully behind the camera, chunk 4 beyond the far plane, chunks 1 and 3
+        // cross the near and far plane
+        assert_eq!(
+            model.chunks_in_frustum(&planes),
+            vec![
+                IVec3::new(0, 0, 1),
+                IVec3::new(0, 0, 2),
+                IVec3::new(0, 0, 3)
+            ]
+        );
+
+        // the origin moves the meshes one chunk back
+        model.set_origin(IVec3::new(0, 0, 1));
+        assert_eq!(
+            model.chunks_in_frustum(&planes),
+            vec![
+                IVec3::new(0, 0, 2),
+                IVec3::new(0, 0, 3),
+                IVec3::new(0, 0, 4)
+            ]
+        );
+
+        // a frustum looking the other way sees nothing
+        let behind = planes.map(|plane| Vec4::new(plane.x, plane.y, -plane.z, plane.w));
+        assert!(model.chunks_in_frustum(&behind).is_empty());
+    }
+
+    #[test]
+    fn test_smooth_normals_on_sphere() {
+        let mut model = VoxModel::<i32>::with_dimensions(
+            MaxDepth::new(4),
+            Vec3::splat(16.0),
+            IVec3::ONE,
+            1024 * 1024,
+        );
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        let chunk = model.get_or_create_chunk(IVec3::ZERO);
+        let mut batch = chunk.create_batch();
+        batch.stamp_sphere(IVec3::splat(8), 5, 1);
+        chunk.apply_batch(&mut interner, &batch);
+
+        let mut flat = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(0),
+            &mut flat,
+            false,
+            NormalMode::Flat,
+        );
+
+        let mut smooth = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(0),
+            &mut smooth,
+            false,
+            NormalMode::Smooth,
+        );
+
+        assert_eq!(flat.vertices, smooth.vertices);
+        assert!(
+            smooth
+                .normals
+                .iter()
+                .all(|normal| (normal.length() - 1.0).abs() < 1e-5)
+        );
+
+        // the corner furthest along the diagonal joins faces of all three axes
+        let corner = (0..flat.vertices.len())
+            .max_by(|&a, &b| {
+                flat.vertices[a]
+                    .element_sum()
+                    .total_cmp(&flat.vertices[b].element_sum())
+            })
+            .unwrap();
+        assert_eq!(flat.normals[corner].abs().element_sum(), 1.0);
+        assert_ne!(smooth.normals[corner], flat.normals[corner]);
+        assert!(smooth.normals[corner].cmpgt(Vec3::ZERO).all());
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_greedy_mesh_ao_splits_quads() {
+        let mut model = VoxModel::<i32>::with_dimensions(
+            MaxDepth::new(3),
+            Vec3::splat(8.0),
+            IVec3::new(1, 1, 1),
+            1024 * 1024,
+        );
+
+        let interner = model.get_interner();
+        let mut interner = interner.write();
+
+        // a strip of four voxels, with a voxel above and behind its first one
+        let chunk = model.get_or_create_chunk(IVec3::ZERO);
+        for x in 0..4 {
+            chunk.set(&mut interner, IVec3::new(x, 0, 0), 1);
+        }
+        chunk.set(&mut interner, IVec3::new(0, 1, 1), 1);
+
+        let strip_top = |mesh_data: &MeshData| {
+            let min_y = mesh_data
+                .vertices
+                .iter()
+                .map(|v| v.y)
+                .fold(f32::MAX, f32::min);
+            let top_y = min_y + model.voxel_size(Lod::new(0)).y;
+
+            (0..mesh_data.vertices.len())
+                .step_by(4)
+                .filter(|&i| {
+                    mesh_data.normals[i] == Vec3::Y
+                        && mesh_data.vertices[i..i + 4]
+                            .iter()
+                            .all(|v| (v.y - top_y).abs() < 1e-5)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut flat = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(0),
+            &mut flat,
+            false,
+            NormalMode::Flat,
+        );
+        assert!(flat.ao.is_empty());
+        assert_eq!(strip_top(&flat).len(), 1);
+
+        let mut shaded = MeshData::default();
+        generate_greedy_mesh_arrays_stride(
+            &model,
+            &interner,
+            Lod::new(0),
+            &mut shaded,
+            true,
+            NormalMode::Flat,
+        );
+        assert_eq!(shaded.ao.len(), shaded.vertices.len());
+        assert!(shaded.indices.len() > flat.indices.len());
+
+        // the first two faces get occluded corners, so the strip splits into three quads
+        let quads = strip_top(&shaded);
+        assert_eq!(quads.len(), 3);
+
+        let occluded = quads
+            .iter()
+            .filter(|&&i| shaded.ao[i..i + 4].iter().any(|&ao| ao < 1.0))
+            .count();
+        assert_eq!(occluded, 2);
+
+        // only the corners on the edge touching the voxel behind are darkened
+        for i in quads.iter().flat_map(|&i| i..i + 4) {
+            let vertex = shaded.vertices[i];
+            let touching = vertex.z == 1.0 && vertex.x <= 1.0;
+            assert_eq!(shaded.ao[i] < 1.0, touching, "{vertex}");
+        }
+
+        for chunk in model.chunks.values_mut() {
+            chunk.clear(&mut interner);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_patterns_rejects_unknown_child() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let mut data = Vec::new();
+
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&encode_varint_u32(1));
+        data.extend_from_slice(&5i32.to_be_bytes());
+
+        // a valid branch over the leaf, then one pointing at a node missing from the file
+        data.extend_from_slice(&2u32.to_be_bytes());
+        for (id, child) in [(2, 1), (3, 9)] {
+            data.extend_from_slice(&encode_varint_u32(id));
+            data.push(0b0000_0001);
+            data.extend_from_slice(&encode_varint_u32(child));
+            data.extend_from_slice(&5i32.to_be_bytes());
+        }
+
+        assert!(matches!(
+            deserialize_patterns(&mut interner, &mut data.as_slice()),
+            Err(VtmError::InvalidNode(9))
+        ));
+        assert_eq!(interner.live_node_count(), 1);
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_deserialize_patterns_rejects_out_of_order_ids() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+
+        let mut data = Vec::new();
+
+        // the leaf and the branch swapped their ids
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&encode_varint_u32(2));
+        data.extend_from_slice(&5i32.to_be_bytes());
+
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&encode_varint_u32(1));
+        data.push(0b0000_0001);
+        data.extend_from_slice(&encode_varint_u32(2));
+        data.extend_from_slice(&5i32.to_be_bytes());
+
+        assert!(matches!(
+            deserialize_patterns(&mut interner, &mut data.as_slice()),
+            Err(VtmError::InvalidNode(2))
+        ));
+        assert_eq!(interner.live_node_count(), 1);
+        assert!(interner.patterns_empty());
     }
 }
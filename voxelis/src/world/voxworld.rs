@@ -1,14 +1,48 @@
-use glam::IVec3;
+#[cfg(feature = "vtm")]
+use std::path::Path;
 
-use crate::VoxelTrait;
+use glam::{IVec3, Vec3};
+use parking_lot::RwLock;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[cfg(feature = "vtm")]
+use crate::io::{
+    VtmError,
+    region::{chunk_to_region, read_region, region_file_name, write_region},
+};
+use crate::{
+    Lod, VoxInterner, VoxelTrait,
+    spatial::{VoxOpsBulkWrite, VoxOpsChunkConfig, VoxOpsConfig, VoxOpsSpatial3D},
+};
 
 use super::VoxChunk;
 
+/// First solid voxel hit by a ray, as returned by [`VoxWorld::raycast`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldRayHit<T: VoxelTrait> {
+    /// Position of the chunk holding the voxel.
+    pub chunk: IVec3,
+    /// Position of the voxel in the world, counted in voxels from the world origin.
+    pub position: IVec3,
+    /// Value of the voxel that was hit.
+    pub value: T,
+    /// Normal of the face the ray entered the voxel through, `IVec3::ZERO` if the ray
+    /// started inside it.
+    pub normal: IVec3,
+    /// Distance from the ray origin to the hit, in world units.
+    pub t: f32,
+}
+
 #[derive(Default)]
 pub struct VoxWorld<T: VoxelTrait> {
     pub chunks_size: IVec3,
     pub chunks_len: usize,
     pub chunks: Vec<VoxChunk<T>>,
+    // Center and radius of the last region passed to `update_loaded_region`
+    loaded_region: Option<(IVec3, i32)>,
+    // Index of every chunk in `chunks` by its position, used by `raycast`. Entries are
+    // checked against the chunk they point at and the index is rebuilt when one is stale
+    chunk_index: RwLock<FxHashMap<IVec3, usize>>,
 }
 
 impl<T: VoxelTrait> VoxWorld<T> {
@@ -24,6 +58,8 @@ impl<T: VoxelTrait> VoxWorld<T> {
             chunks_size,
             chunks_len,
             chunks,
+            loaded_region: None,
+            chunk_index: RwLock::default(),
         }
     }
 
@@ -38,6 +74,8 @@ impl<T: VoxelTrait> VoxWorld<T> {
             chunks_size: size,
             chunks_len,
             chunks,
+            loaded_region: None,
+            chunk_index: RwLock::default(),
         }
     }
 
@@ -46,6 +84,8 @@ impl<T: VoxelTrait> VoxWorld<T> {
         let _span = tracy_client::span!("VoxWorld::clear");
 
         self.chunks.clear();
+        self.loaded_region = None;
+        self.chunk_index.get_mut().clear();
     }
 
     pub fn resize(&mut self, size: IVec3) {
@@ -55,5 +95,639 @@ impl<T: VoxelTrait> VoxWorld<T> {
         self.chunks_size = size;
         self.chunks_len = size.x as usize * size.y as usize * size.z as usize;
         self.chunks = Vec::with_capacity(self.chunks_len);
+        self.loaded_region = None;
+        self.chunk_index.get_mut().clear();
+    }
+
+    /// Keeps the chunks within `radius` chunks of `center` loaded, e.g. around the camera.
+    ///
+    /// Diffs the cube around `center` against the one from the previous call. `load` is
+    /// called once for every coordinate that entered it, and may return `None` for empty
+    /// space. Chunks outside of the cube are passed to `unload`, e.g. to save them, and are
+    /// then removed and their roots released in `interner`.
+    pub fn update_loaded_region(
+        &mut self,
+        interner: &mut VoxInterner<T>,
+        center: IVec3,
+        radius: i32,
+        mut load: impl FnMut(&mut VoxInterner<T>, IVec3) -> Option<VoxChunk<T>>,
+        mut unload: impl FnMut(&VoxInterner<T>, IVec3, &VoxChunk<T>),
+    ) {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxWorld::update_loaded_region");
+
+        assert!(radius >= 0, "Radius must not be negative");
+
+        let in_region = |position: IVec3, center: IVec3, radius: i32| {
+            (position - center).abs().max_element() <= radius
+        };
+
+        let mut chunk_idx = 0;
+        while chunk_idx < self.chunks.len() {
+            let position = self.chunks[chunk_idx].position_3d();
+
+            if in_region(position, center, radius) {
+                chunk_idx += 1;
+                continue;
+            }
+
+            let mut chunk = self.chunks.swap_remove(chunk_idx);
+            unload(interner, position, &chunk);
+            chunk.clear(interner);
+        }
+
+        let loaded = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.position_3d())
+            .collect::<FxHashSet<_>>();
+        let previous = self.loaded_region.replace((center, radius));
+
+        for z in -radius..=radius {
+            for y in -radius..=radius {
+                for x in -radius..=radius {
+                    let position = center + IVec3::new(x, y, z);
+
+                    let entered = previous.is_none_or(|(previous_center, previous_radius)| {
+                        !in_region(position, previous_center, previous_radius)
+                    });
+
+                    if !entered || loaded.contains(&position) {
+                        continue;
+                    }
+
+                    if let Some(chunk) = load(interner, position) {
+                        debug_assert_eq!(
+                            chunk.position_3d(),
+                            position,
+                            "Loaded chunk has a different position"
+                        );
+
+                        self.chunks.push(chunk);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Casts a ray in world units and returns the first non-empty voxel it hits within
+    /// `max_dist`, e.g. to pick the voxel under the cursor.
+    ///
+    /// Walks the chunk grid along the ray and casts it through the tree of every loaded chunk
+    /// it passes, chunks that aren't loaded are skipped. All chunks are expected to have the
+    /// same size, `dir` does not need to be normalized.
+    ///
+    /// Chunks are looked up through an index by position kept across calls. It is rebuilt, at
+    /// most once per call, when it points at a chunk with another position or misses one.
+    pub fn raycast(
+        &self,
+        interner: &VoxInterner<T>,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+    ) -> Option<WorldRayHit<T>> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxWorld::raycast");
+
+        let chunk_size = self.chunks.first()?.world_size_3d();
+        debug_assert!(
+            self.chunks
+                .iter()
+                .all(|chunk| chunk.world_size_3d() == chunk_size),
+            "Chunks have different sizes"
+        );
+
+        let dir = dir.normalize_or_zero();
+
+        let mut rebuilt = false;
+        let mut find_chunk = |position: IVec3| loop {
+            let index = self.chunk_index.read().get(&position).copied();
+            let chunk = index
+                .and_then(|index| self.chunks.get(index))
+                .filter(|chunk| chunk.position_3d() == position);
+
+            if chunk.is_some() || rebuilt {
+                return chunk;
+            }
+
+            // `chunks` changed since the index was built
+            *self.chunk_index.write() = self
+                .chunks
+                .iter()
+                .enumerate()
+                .map(|(index, chunk)| (chunk.position_3d(), index))
+                .collect();
+            rebuilt = true;
+        };
+
+        for position in ChunkRayIter::new(origin, dir, max_dist, chunk_size, IVec3::ZERO) {
+            let Some(chunk) = find_chunk(position) else {
+                continue;
+            };
+
+            // the tree measures distances in its voxels, which may not be cubes in the world
+            let voxel_size = chunk.voxel_size(Lod::new(0));
+            let local_dir = dir / voxel_size;
+            let scale = local_dir.length();
+            let local_origin = (origin - chunk.world_position_3d()) / voxel_size;
+
+            let Some(hit) = chunk.raycast(interner, local_origin, local_dir, max_dist * scale)
+            else {
+                continue;
+            };
+
+            let voxels_per_axis = chunk.voxels_per_axis(Lod::new(0)) as i32;
+
+            return Some(WorldRayHit {
+                chunk: position,
+                position: position * voxels_per_axis + hit.position,
+                value: hit.value,
+                normal: hit.normal,
+                t: if scale > 0.0 { hit.t / scale } else { 0.0 },
+            });
+        }
+
+        None
+    }
+}
+
+// Grid DDA over chunk cells, distances are measured in chunks
+pub(crate) struct ChunkRayIter {
+    next: Option<IVec3>,
+    step: IVec3,
+    t_max: Vec3,
+    t_delta: Vec3,
+    max_dist: f32,
+}
+
+impl ChunkRayIter {
+    // Walks cells of `cell_size` world units, distances stay in world units.
+    pub(crate) fn new(
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+        cell_size: Vec3,
+        offset: IVec3,
+    ) -> Self {
+        let origin = origin / cell_size;
+        let dir = dir.normalize_or_zero() / cell_size;
+        let cell = origin.floor();
+
+        let step = IVec3::new(
+            dir.x.signum() as i32 * (dir.x != 0.0) as i32,
+            dir.y.signum() as i32 * (dir.y != 0.0) as i32,
+            dir.z.signum() as i32 * (dir.z != 0.0) as i32,
+        );
+
+        let mut t_max = Vec3::INFINITY;
+        let mut t_delta = Vec3::INFINITY;
+
+        for axis in 0..3 {
+            if step[axis] > 0 {
+                t_max[axis] = (cell[axis] + 1.0 - origin[axis]) / dir[axis];
+            } else if step[axis] < 0 {
+                t_max[axis] = (origin[axis] - cell[axis]) / -dir[axis];
+            } else {
+                continue;
+            }
+            t_delta[axis] = 1.0 / dir[axis].abs();
+        }
+
+        Self {
+            next: (max_dist >= 0.0 && origin.is_finite()).then(|| cell.as_ivec3() + offset),
+            step,
+            t_max,
+            t_delta,
+            max_dist,
+        }
+    }
+}
+
+impl Iterator for ChunkRayIter {
+    type Item = IVec3;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+
+        let axis = if self.t_max.x <= self.t_max.y && self.t_max.x <= self.t_max.z {
+            0
+        } else if self.t_max.y <= self.t_max.z {
+            1
+        } else {
+            2
+        };
+
+        self.next = if self.t_max[axis] <= self.max_dist {
+            let mut next = current;
+            next[axis] += self.step[axis];
+            self.t_max[axis] += self.t_delta[axis];
+            Some(next)
+        } else {
+            None
+        };
+
+        Some(current)
+    }
+}
+
+#[cfg(feature = "vtm")]
+impl<T: VoxelTrait> VoxWorld<T> {
+    /// Groups chunks into regions of `region_chunk_size` chunks per axis and writes one
+    /// self-contained file per region into `dir`. Returns the number of region files written.
+    pub fn save_regions<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        region_chunk_size: u32,
+        interner: &VoxInterner<T>,
+    ) -> Result<usize, VtmError> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxWorld::save_regions");
+
+        assert!(region_chunk_size > 0, "Region must hold at least one chunk");
+
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut regions: FxHashMap<IVec3, Vec<&VoxChunk<T>>> = FxHashMap::default();
+        for chunk in self.chunks.iter() {
+            regions
+                .entry(chunk_to_region(chunk.position_3d(), region_chunk_size))
+                .or_default()
+                .push(chunk);
+        }
+
+        for (region_coord, chunks) in regions.iter() {
+            let file = std::fs::File::create(dir.join(region_file_name(*region_coord)))?;
+            let mut writer = std::io::BufWriter::new(file);
+
+            write_region(&mut writer, *region_coord, chunks, interner)?;
+        }
+
+        Ok(regions.len())
+    }
+
+    /// Reads a single region file from `dir`, replacing chunks at the same positions.
+    /// Other region files are not touched, and a region that was never saved loads as
+    /// nothing. Returns the number of chunks loaded.
+    pub fn load_region<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        region_coord: IVec3,
+        interner: &mut VoxInterner<T>,
+    ) -> Result<usize, VtmError> {
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("VoxWorld::load_region");
+
+        let path = dir.as_ref().join(region_file_name(region_coord));
+
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let mut reader = std::io::BufReader::new(file);
+
+        let (_, chunks) = read_region(&mut reader, interner)?;
+        let loaded = chunks.len();
+
+        for chunk in chunks {
+            let position = chunk.position_3d();
+
+            match self
+                .chunks
+                .iter_mut()
+                .find(|existing| existing.position_3d() == position)
+            {
+                Some(existing) => {
+                    existing.clear(interner);
+                    *existing = chunk;
+                }
+                None => self.chunks.push(chunk),
+            }
+        }
+
+        Ok(loaded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "vtm")]
+    use crate::utils::common::to_vec;
+    use crate::{
+        MaxDepth,
+        spatial::{VoxOpsRead, VoxOpsWrite},
+    };
+
+    use super::*;
+
+    const MAX_DEPTH: MaxDepth = MaxDepth::new(3);
+
+    #[cfg(feature = "vtm")]
+    fn build_world(interner: &mut VoxInterner<i32>) -> VoxWorld<i32> {
+        let mut world = VoxWorld::new();
+
+        let positions = [
+            IVec3::new(0, 0, 0),
+            IVec3::new(1, 0, 1),
+            IVec3::new(2, 0, 0),
+            IVec3::new(-1, 0, 0),
+            IVec3::new(3, 1, 1),
+        ];
+
+        for (i, position) in positions.into_iter().enumerate() {
+            let mut chunk = VoxChunk::with_position(
+                glam::Vec3::splat(8.0),
+                MAX_DEPTH,
+                position.x,
+                position.y,
+                position.z,
+            );
+
+            // the last chunk is left empty
+            if i + 1 < positions.len() {
+                for y in 0..=i as i32 {
+                    for x in 0..8 {
+                        chunk.set(interner, IVec3::new(x, y, (x + y) % 8), i as i32 + 1);
+                    }
+                }
+                chunk.set(interner, IVec3::new(7, 7, 7), 9);
+                chunk.set_owner(interner, IVec3::new(7, 7, 7), 100 + i as u32);
+            }
+
+            world.chunks.push(chunk);
+        }
+
+        world
+    }
+
+    #[cfg(feature = "vtm")]
+    fn voxels(world: &VoxWorld<i32>, interner: &VoxInterner<i32>) -> FxHashMap<IVec3, Vec<i32>> {
+        world
+            .chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.position_3d(),
+                    to_vec(interner, &chunk.get_root_id(), MAX_DEPTH),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_update_loaded_region() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut world = VoxWorld::new();
+
+        // every chunk holds its own coordinates, below y = 0 is empty space
+        let load = |interner: &mut VoxInterner<i32>, position: IVec3| {
+            if position.y < 0 {
+                return None;
+            }
+
+            let mut chunk = VoxChunk::with_position(
+                glam::Vec3::splat(8.0),
+                MAX_DEPTH,
+                position.x,
+                position.y,
+                position.z,
+            );
+            chunk.set(interner, IVec3::ZERO, position.x + 10);
+            chunk.set(interner, IVec3::ONE, position.z + 10);
+            Some(chunk)
+        };
+
+        let mut loaded = Vec::new();
+        let mut unloaded = Vec::new();
+
+        world.update_loaded_region(
+            &mut interner,
+            IVec3::ZERO,
+            1,
+            |interner, position| {
+                loaded.push(position);
+                load(interner, position)
+            },
+            |_, position, _| unloaded.push(position),
+        );
+        assert_eq!(loaded.len(), 27);
+        assert!(unloaded.is_empty());
+        assert_eq!(world.chunks.len(), 18);
+
+        // move one chunk along x, only the entered and left faces change
+        loaded.clear();
+        world.update_loaded_region(
+            &mut interner,
+            IVec3::X,
+            1,
+            |interner, position| {
+                loaded.push(position);
+                load(interner, position)
+            },
+            |interner, position, chunk| {
+                assert_eq!(chunk.get(interner, IVec3::ZERO), Some(position.x + 10));
+                unloaded.push(position);
+            },
+        );
+        assert_eq!(loaded.len(), 9);
+        assert!(loaded.iter().all(|position| position.x == 2));
+        assert_eq!(unloaded.len(), 6);
+        assert!(unloaded.iter().all(|position| position.x == -1));
+        assert_eq!(world.chunks.len(), 18);
+
+        for chunk in world.chunks.iter() {
+            let position = chunk.position_3d();
+            assert!((position - IVec3::X).abs().max_element() <= 1);
+            assert_eq!(chunk.get(&interner, IVec3::ONE), Some(position.z + 10));
+        }
+
+        // the same region again is a no-op
+        loaded.clear();
+        unloaded.clear();
+        world.update_loaded_region(
+            &mut interner,
+            IVec3::X,
+            1,
+            |interner, position| {
+                loaded.push(position);
+                load(interner, position)
+            },
+            |_, position, _| unloaded.push(position),
+        );
+        assert!(loaded.is_empty());
+        assert!(unloaded.is_empty());
+
+        // chunks that left the region are released
+        world.update_loaded_region(
+            &mut interner,
+            IVec3::new(100, 0, 0),
+            0,
+            |_, _| None,
+            |_, _, _| {},
+        );
+        assert!(world.chunks.is_empty());
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    fn test_raycast_across_chunks() {
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut world = VoxWorld::new();
+
+        // 16 world units per chunk, so every voxel is 2 units wide
+        let chunk_at = |x: i32| VoxChunk::with_position(Vec3::splat(16.0), MAX_DEPTH, x, 0, 0);
+
+        // the first chunk is loaded but empty, the third one isn't loaded
+        world.chunks.push(chunk_at(0));
+        let mut chunk = chunk_at(1);
+        chunk.set(&mut interner, IVec3::new(2, 3, 4), 5);
+        world.chunks.push(chunk);
+        let mut chunk = chunk_at(3);
+        chunk.set(&mut interner, IVec3::new(0, 3, 4), 7);
+        world.chunks.push(chunk);
+
+        let origin = Vec3::new(1.0, 7.0, 9.0);
+        let hit = world
+            .raycast(&interner, origin, Vec3::X * 3.0, 100.0)
+            .unwrap();
+        assert_eq!(hit.chunk, IVec3::X);
+        assert_eq!(hit.position, IVec3::new(10, 3, 4));
+        assert_eq!(hit.value, 5);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.t - 19.0).abs() < 1e-4);
+
+        assert_eq!(world.raycast(&interner, origin, Vec3::X, 18.0), None);
+
+        // past the hit voxel the ray crosses the unloaded chunk before the last one
+        let origin = Vec3::new(30.0, 7.0, 9.0);
+        let hit = world.raycast(&interner, origin, Vec3::X, 100.0).unwrap();
+        assert_eq!(hit.chunk, IVec3::new(3, 0, 0));
+        assert_eq!(hit.position, IVec3::new(24, 3, 4));
+        assert!((hit.t - 18.0).abs() < 1e-4);
+
+        // a ray starting inside a set voxel hits it right away
+        let hit = world
+            .raycast(&interner, Vec3::new(21.0, 7.0, 9.0), Vec3::Y, 10.0)
+            .unwrap();
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.t, 0.0);
+
+        assert_eq!(world.raycast(&interner, origin, Vec3::NEG_Y, 100.0), None);
+
+        // a chunk loaded after the first raycast is found too
+        let mut chunk = chunk_at(2);
+        chunk.set(&mut interner, IVec3::new(1, 3, 4), 9);
+        world.chunks.push(chunk);
+
+        let hit = world.raycast(&interner, origin, Vec3::X, 100.0).unwrap();
+        assert_eq!(hit.chunk, IVec3::new(2, 0, 0));
+        assert_eq!(hit.value, 9);
+
+        // and still found once moved within `chunks`
+        let last = world.chunks.len() - 1;
+        world.chunks.swap(0, last);
+
+        let hit = world.raycast(&interner, origin, Vec3::X, 100.0).unwrap();
+        assert_eq!(hit.chunk, IVec3::new(2, 0, 0));
+        assert_eq!(hit.value, 9);
+
+        // or replaced by a chunk at another position, keeping the number of chunks
+        let mut chunk = std::mem::replace(&mut world.chunks[0], chunk_at(4));
+        chunk.clear(&mut interner);
+
+        let hit = world.raycast(&interner, origin, Vec3::X, 100.0).unwrap();
+        assert_eq!(hit.chunk, IVec3::new(3, 0, 0));
+        assert_eq!(hit.value, 7);
+
+        for chunk in world.chunks.iter_mut() {
+            chunk.clear(&mut interner);
+        }
+        assert!(interner.patterns_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "vtm")]
+    fn test_save_and_load_regions() {
+        let dir = std::env::temp_dir().join(format!("voxelis_regions_{}", std::process::id()));
+
+        let mut interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let world = build_world(&mut interner);
+        let expected = voxels(&world, &interner);
+
+        assert_eq!(world.save_regions(&dir, 2, &interner).unwrap(), 3);
+
+        // reload everything into a fresh interner
+        let mut loaded_interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut loaded = VoxWorld::new();
+        let mut total = 0;
+        for region_coord in [
+            IVec3::new(0, 0, 0),
+            IVec3::new(1, 0, 0),
+            IVec3::new(-1, 0, 0),
+        ] {
+            total += loaded
+                .load_region(&dir, region_coord, &mut loaded_interner)
+                .unwrap();
+        }
+        assert_eq!(total, world.chunks.len());
+        assert_eq!(voxels(&loaded, &loaded_interner), expected);
+
+        // owners are saved along with the voxels
+        for chunk in loaded.chunks.iter() {
+            let original = world
+                .chunks
+                .iter()
+                .find(|original| original.position_3d() == chunk.position_3d())
+                .unwrap();
+            assert_eq!(chunk.owners(), original.owners());
+        }
+
+        // loading a region again replaces its chunks
+        loaded
+            .load_region(&dir, IVec3::ZERO, &mut loaded_interner)
+            .unwrap();
+        assert_eq!(loaded.chunks.len(), world.chunks.len());
+        assert_eq!(voxels(&loaded, &loaded_interner), expected);
+
+        for chunk in loaded.chunks.iter_mut() {
+            chunk.clear(&mut loaded_interner);
+        }
+        assert!(loaded_interner.patterns_empty());
+
+        // a broken neighbour region must not be read when loading another one
+        std::fs::write(
+            dir.join(region_file_name(IVec3::new(1, 0, 0))),
+            b"not a voxelis region file",
+        )
+        .unwrap();
+
+        let mut partial_interner = VoxInterner::<i32>::with_memory_budget(1024 * 1024);
+        let mut partial = VoxWorld::new();
+        assert_eq!(
+            partial
+                .load_region(&dir, IVec3::ZERO, &mut partial_interner)
+                .unwrap(),
+            2
+        );
+        assert!(matches!(
+            partial.load_region(&dir, IVec3::new(1, 0, 0), &mut partial_interner),
+            Err(VtmError::InvalidMagic)
+        ));
+        assert_eq!(
+            partial
+                .load_region(&dir, IVec3::new(5, 5, 5), &mut partial_interner)
+                .unwrap(),
+            0
+        );
+
+        let partial_voxels = voxels(&partial, &partial_interner);
+        assert_eq!(partial_voxels.len(), 2);
+        for (position, data) in partial_voxels.iter() {
+            assert_eq!(chunk_to_region(*position, 2), IVec3::ZERO);
+            assert_eq!(&expected[position], data);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -2,7 +2,10 @@ use std::path::Path;
 
 use voxelis::{
     Lod,
-    io::{export::export_model_to_obj, import::import_model_from_vtm},
+    io::{
+        export::{ObjExportOptions, export_model_to_obj_with_options},
+        import::import_model_from_vtm,
+    },
     world::VoxModel,
 };
 
@@ -15,7 +18,7 @@ fn main() {
 
     if std::env::args().len() < 3 {
         eprintln!(
-            "Usage: {} <input.vtm> <output.obj>",
+            "Usage: {} <input.vtm> <output.obj> [--weld]",
             std::env::args().next().unwrap()
         );
         std::process::exit(1);
@@ -23,12 +26,19 @@ fn main() {
 
     let input = std::env::args().nth(1).unwrap();
     let output = std::env::args().nth(2).unwrap();
+    let weld_vertices = std::env::args().skip(3).any(|arg| arg == "--weld");
 
     let input = Path::new(&input);
     let output = Path::new(&output);
 
     let name = output.file_stem().unwrap().to_str().unwrap().to_string();
 
-    let model: VoxModel<i32> = import_model_from_vtm(&input, 1024 * 1024 * 1024, None);
-    export_model_to_obj(name, &output, &model, Lod::new(0));
+    let model: VoxModel<i32> = import_model_from_vtm(&input, 1024 * 1024 * 1024, None).unwrap();
+    export_model_to_obj_with_options(
+        name,
+        &output,
+        &model,
+        Lod::new(0),
+        ObjExportOptions { weld_vertices },
+    );
 }
@@ -11,6 +11,7 @@ use bevy::pbr::{
     ScreenSpaceAmbientOcclusion, ScreenSpaceAmbientOcclusionQualityLevel, VolumetricFog,
 };
 use bevy::render::camera::TemporalJitter;
+use bevy::render::mesh::VertexAttributeValues;
 use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*, window::PresentMode};
 use bevy_egui::EguiPlugin;
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
@@ -21,7 +22,7 @@ use bevy_screen_diagnostics::{
 use voxelis::{
     Lod,
     io::import::import_model_from_vtm,
-    utils::mesh::{MeshData, generate_greedy_mesh_arrays_stride},
+    utils::mesh::{MeshData, NormalMode, generate_greedy_mesh_arrays_stride},
     world::VoxModel,
 };
 
@@ -30,18 +31,24 @@ struct GamePlugin;
 #[derive(Component)]
 struct Chunk;
 
+// Normals of the mode not shown, swapped with the mesh attribute when toggled
+#[derive(Component)]
+struct HiddenNormals(VertexAttributeValues);
+
 #[derive(Resource)]
 pub struct ModelResource(pub VoxModel<i32>);
 
 #[derive(Resource)]
 pub struct ModelSettings {
     pub lod: Lod,
+    pub ao: bool,
+    pub normal_mode: NormalMode,
 }
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup);
-        app.add_systems(Update, toggle_wireframe);
+        app.add_systems(Update, (toggle_wireframe, toggle_normals));
         #[cfg(feature = "tracy")]
         app.add_systems(Last, tracy_mark_frame);
     }
@@ -110,7 +117,7 @@ fn setup(
         .insert(TemporalAntiAliasing::default());
 
     commands.spawn((
-        Text::new("Press space to toggle wireframes"),
+        Text::new("Press space to toggle wireframes, N to toggle smooth normals"),
         Node {
             position_type: PositionType::Absolute,
             top: Val::Px(12.0),
@@ -135,19 +142,50 @@ fn setup(
 
     let mut mesh_data = MeshData::default();
 
-    generate_greedy_mesh_arrays_stride(model, &interner, model_settings.lod, &mut mesh_data);
+    // meshed flat, smoothing after keeps both sets of normals so toggling never re-meshes
+    generate_greedy_mesh_arrays_stride(
+        model,
+        &interner,
+        model_settings.lod,
+        &mut mesh_data,
+        model_settings.ao,
+        NormalMode::Flat,
+    );
+
+    let total_vertices = mesh_data.vertex_count();
+    let total_triangles = mesh_data.triangle_count();
+    let total_bytes = mesh_data.estimated_gpu_bytes();
 
-    let total_vertices = mesh_data.vertices.len();
-    let total_indices = mesh_data.indices.len();
-    let total_normals = mesh_data.normals.len();
+    let flat_normals = mesh_data.normals.clone();
+    mesh_data.smooth_normals();
+    let smooth_normals = std::mem::take(&mut mesh_data.normals);
+
+    let (shown_normals, hidden_normals) = match model_settings.normal_mode {
+        NormalMode::Flat => (flat_normals, smooth_normals),
+        NormalMode::Smooth => (smooth_normals, flat_normals),
+    };
 
     let mesh = Mesh::new(
         bevy::render::mesh::PrimitiveTopology::TriangleList,
-        bevy::render::render_asset::RenderAssetUsages::RENDER_WORLD,
+        // kept in the main world too, so the normals can be swapped after the upload
+        bevy::render::render_asset::RenderAssetUsages::MAIN_WORLD
+            | bevy::render::render_asset::RenderAssetUsages::RENDER_WORLD,
     )
     .with_inserted_indices(bevy::render::mesh::Indices::U32(mesh_data.indices))
     .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, mesh_data.vertices)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, mesh_data.normals);
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, shown_normals);
+
+    // vertex colors multiply the base color, so they darken occluded corners
+    let mesh = if mesh_data.ao.is_empty() {
+        mesh
+    } else {
+        let colors = mesh_data
+            .ao
+            .iter()
+            .map(|&ao| [ao, ao, ao, 1.0])
+            .collect::<Vec<_>>();
+        mesh.with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+    };
 
     let mesh = meshes.add(mesh);
 
@@ -159,7 +197,11 @@ fn setup(
     });
 
     commands
-        .spawn((Mesh3d(mesh), MeshMaterial3d(mesh_material.clone())))
+        .spawn((
+            Mesh3d(mesh),
+            MeshMaterial3d(mesh_material.clone()),
+            HiddenNormals(hidden_normals.into()),
+        ))
         .insert(Chunk);
 
     println!(
@@ -167,12 +209,12 @@ fn setup(
         humanize_bytes::humanize_quantity!(total_vertices),
     );
     println!(
-        " Normals: {}",
-        humanize_bytes::humanize_quantity!(total_normals),
+        " Triangles: {}",
+        humanize_bytes::humanize_quantity!(total_triangles),
     );
     println!(
-        " Indices: {}",
-        humanize_bytes::humanize_quantity!(total_indices),
+        " GPU memory: {}",
+        humanize_bytes::humanize_bytes_decimal!(total_bytes),
     );
 
     println!("Generating meshes took {:?}", now.elapsed());
@@ -199,6 +241,34 @@ fn toggle_wireframe(
     }
 }
 
+fn toggle_normals(
+    mut model_settings: ResMut<ModelSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunks: Query<(&Mesh3d, &mut HiddenNormals), With<Chunk>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyN) {
+        return;
+    }
+
+    model_settings.normal_mode = match model_settings.normal_mode {
+        NormalMode::Flat => NormalMode::Smooth,
+        NormalMode::Smooth => NormalMode::Flat,
+    };
+
+    // only the normals differ between the modes, the vertices stay the same
+    for (mesh3d, mut hidden_normals) in &mut chunks {
+        let Some(mesh) = meshes.get_mut(&mesh3d.0) else {
+            continue;
+        };
+
+        if let Some(shown_normals) = mesh.remove_attribute(Mesh::ATTRIBUTE_NORMAL) {
+            let normals = std::mem::replace(&mut hidden_normals.0, shown_normals);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        }
+    }
+}
+
 fn main() {
     #[cfg(feature = "tracy")]
     tracy_client::Client::start();
@@ -207,7 +277,7 @@ fn main() {
     let _span = tracy_client::span!("vtm-viewer");
 
     if std::env::args().len() < 2 {
-        println!("Usage: vtm-viewer <vtm-file> <chunk size in m> <lod-level (0-7)>");
+        println!("Usage: vtm-viewer <vtm-file> <chunk size in m> <lod-level (0-7)> <ao (0-1)>");
         std::process::exit(1);
     }
 
@@ -229,8 +299,21 @@ fn main() {
     };
     println!("Using LOD level {lod}");
 
+    let ao = if let Some(ao) = std::env::args().nth(4) {
+        let ao: u8 = ao.parse().unwrap();
+        ao != 0
+    } else {
+        false
+    };
+    println!("Using ambient occlusion: {ao}");
+
     println!("Opening VTM model {}", input.display());
-    let model = import_model_from_vtm(&input, 1024 * 1024 * 1024 * 4, Some(chunk_world_size));
+    let model = import_model_from_vtm(
+        &input,
+        1024 * 1024 * 1024 * 4,
+        Some(Vec3::splat(chunk_world_size)),
+    )
+    .unwrap();
 
     #[cfg(feature = "memory_stats")]
     {
@@ -269,7 +352,11 @@ fn main() {
             alpha: 1.0,
         })))
         .insert_resource(ModelResource(model))
-        .insert_resource(ModelSettings { lod })
+        .insert_resource(ModelSettings {
+            lod,
+            ao,
+            normal_mode: NormalMode::Flat,
+        })
         .run();
 
     println!("Exiting...");
@@ -1,10 +1,13 @@
 use std::path::Path;
 
+use glam::Vec3;
 use voxelis::{
-    MaxDepth,
     io::{Obj, export::export_model_to_vtm},
+    world::VoxModelBuilder,
+};
+use voxelis_voxelize::{
+    Voxelizer, estimate_memory_budget, max_memory_budget, terminal_progress_bar,
 };
-use voxelis_voxelize::Voxelizer;
 
 fn main() {
     #[cfg(feature = "tracy")]
@@ -26,25 +29,47 @@ fn main() {
     let input = std::env::args().nth(3).unwrap();
     let output = std::env::args().nth(4).unwrap();
 
-    let max_depth: usize = max_depth.parse().unwrap();
-    let chunk_size: f32 = chunk_size.parse().unwrap();
-
-    let max_depth = MaxDepth::new(max_depth as u8);
-    let memory_budget = 1024 * 1024 * 1024 * 16;
-
-    println!("Max octree depth: {max_depth}");
-    println!("Voxels per axis: {}", 1 << max_depth.max());
-    println!("Chunk size: {chunk_size}m");
-    println!("Memory budget: {memory_budget} bytes");
+    let Ok(max_depth) = max_depth.parse::<u8>() else {
+        eprintln!("Invalid max depth: {max_depth}");
+        std::process::exit(1);
+    };
+    let Ok(chunk_size) = chunk_size.parse::<f32>() else {
+        eprintln!("Invalid chunk size: {chunk_size}");
+        std::process::exit(1);
+    };
 
     let input = Path::new(&input);
     let output = Path::new(&output);
 
     let name = output.file_stem().unwrap().to_str().unwrap().to_string();
 
-    let obj = Obj::parse(&input);
+    let obj = Obj::parse(&input).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {err}", input.display());
+        std::process::exit(1);
+    });
+
+    let voxel_size = f64::from(chunk_size) / 2f64.powi(i32::from(max_depth));
+    let memory_budget = estimate_memory_budget(&obj, voxel_size);
+
+    // an underestimated budget slows a long voxelization down instead of aborting it
+    let model = VoxModelBuilder::new()
+        .max_depth(max_depth)
+        .chunk_world_size(Vec3::splat(chunk_size))
+        .memory_budget(memory_budget)
+        .max_memory_budget(max_memory_budget(memory_budget))
+        .build()
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(1);
+        });
+
+    println!("Max octree depth: {max_depth}");
+    println!("Voxels per axis: {}", 1 << max_depth);
+    println!("Chunk size: {chunk_size}m");
+    println!("Memory budget: {memory_budget} bytes");
 
-    let mut voxelizer = Voxelizer::empty(max_depth, chunk_size, obj, memory_budget);
+    let mut voxelizer = Voxelizer::with_model(model, obj);
+    voxelizer.set_progress_callback(terminal_progress_bar());
     voxelizer.voxelize();
 
     export_model_to_vtm(name, &output, &voxelizer.model);